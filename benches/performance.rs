@@ -52,7 +52,7 @@ fn bench_config_operations(c: &mut Criterion) {
     
     // Benchmark default config creation
     group.bench_function("config_default", |b| {
-        b.iter(|| AppConfig::default())
+        b.iter(AppConfig::default)
     });
     
     // Benchmark TOML parsing
@@ -171,7 +171,7 @@ fn bench_concurrent_operations(c: &mut Criterion) {
             }).collect();
             
             for handle in handles {
-                black_box(handle.join().unwrap());
+                let _ = black_box(handle.join().unwrap());
             }
         })
     });
@@ -0,0 +1,220 @@
+//! Copies of files read from a remote backend, kept in the share's state directory so a file
+//! everyone downloads is fetched from the backend once. The copies read least recently go first
+//! when the cache grows past its size.
+
+use filetime::FileTime;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, warn};
+
+use super::{FileReader, StoredObject};
+use crate::utils::hash::hash_bytes;
+
+/// Directory of the cache within the share's state directory
+pub const CACHE_DIR: &str = "backend-cache";
+
+struct CachedCopy {
+    key: String,
+    size: u64,
+}
+
+#[derive(Default)]
+struct Copies {
+    /// Least recently read first
+    order: VecDeque<CachedCopy>,
+    total: u64,
+}
+
+/// Local copies of backend files, up to `max_size` bytes
+pub struct ReadCache {
+    directory: PathBuf,
+    max_size: u64,
+    copies: Mutex<Copies>,
+}
+
+impl ReadCache {
+    /// Open the cache in `directory`, picking up the copies an earlier run left there
+    pub fn open(directory: PathBuf, max_size: u64) -> Self {
+        let mut found: Vec<_> = std::fs::read_dir(&directory)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let key = entry.file_name().into_string().ok()?;
+                // Copies a crash left half written
+                if key.ends_with(".part") {
+                    let _ = std::fs::remove_file(entry.path());
+                    return None;
+                }
+                Some((FileTime::from_last_modification_time(&metadata), CachedCopy { key, size: metadata.len() }))
+            })
+            .collect();
+        found.sort_by_key(|(read, _)| *read);
+
+        let mut copies = Copies::default();
+        for (_, copy) in found {
+            copies.total += copy.size;
+            copies.order.push_back(copy);
+        }
+        let cache = Self { directory, max_size, copies: Mutex::new(copies) };
+        cache.evict(&mut cache.copies.lock().unwrap());
+        cache
+    }
+
+    /// The cached copy of `object`, if there is one for its current version
+    pub async fn copy(&self, object: &StoredObject) -> Option<tokio::fs::File> {
+        let key = cache_key(object);
+        {
+            let mut copies = self.copies.lock().unwrap();
+            let index = copies.order.iter().position(|copy| copy.key == key)?;
+            let copy = copies.order.remove(index)?;
+            copies.order.push_back(copy);
+        }
+        let path = self.directory.join(&key);
+        match tokio::fs::File::open(&path).await {
+            Ok(copy) => {
+                // The modification time remembers the last read across restarts
+                if let Err(e) = filetime::set_file_mtime(&path, FileTime::now()) {
+                    debug!("Failed to touch cached copy {:?}: {}", path, e);
+                }
+                Some(copy)
+            }
+            Err(e) => {
+                warn!("Failed to read cached copy of {}: {}", object.name, e);
+                self.forget(&key);
+                None
+            }
+        }
+    }
+
+    /// Whether a copy of `object` would fit at all
+    pub fn fits(&self, object: &StoredObject) -> bool {
+        object.size <= self.max_size
+    }
+
+    /// Keep a copy of `object` read from `reader`, making room for it, and open the copy.
+    /// None when the copy could not be made.
+    pub async fn fill(&self, object: &StoredObject, mut reader: FileReader) -> Option<tokio::fs::File> {
+        let key = cache_key(object);
+        let path = self.directory.join(&key);
+        let part = self.directory.join(format!("{}.part", key));
+        let written = async {
+            tokio::fs::create_dir_all(&self.directory).await?;
+            let mut copy = tokio::fs::File::create(&part).await?;
+            let size = tokio::io::copy(&mut reader, &mut copy).await?;
+            copy.flush().await?;
+            tokio::fs::rename(&part, &path).await?;
+            Ok::<_, std::io::Error>(size)
+        }
+        .await;
+        let size = match written {
+            Ok(size) if size <= self.max_size => size,
+            Ok(_) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                return None;
+            }
+            Err(e) => {
+                warn!("Failed to cache a copy of {}: {}", object.name, e);
+                let _ = tokio::fs::remove_file(&part).await;
+                return None;
+            }
+        };
+
+        let copy = tokio::fs::File::open(&path).await.ok()?;
+
+        let mut copies = self.copies.lock().unwrap();
+        if let Some(index) = copies.order.iter().position(|copy| copy.key == key) {
+            let copy = copies.order.remove(index).unwrap();
+            copies.total -= copy.size;
+        }
+        copies.order.push_back(CachedCopy { key, size });
+        copies.total += size;
+        self.evict(&mut copies);
+        Some(copy)
+    }
+
+    fn forget(&self, key: &str) {
+        let mut copies = self.copies.lock().unwrap();
+        if let Some(index) = copies.order.iter().position(|copy| copy.key == key) {
+            let copy = copies.order.remove(index).unwrap();
+            copies.total -= copy.size;
+        }
+    }
+
+    /// Remove the copies read least recently until the rest fit
+    fn evict(&self, copies: &mut Copies) {
+        while copies.total > self.max_size {
+            let Some(copy) = copies.order.pop_front() else {
+                break;
+            };
+            copies.total -= copy.size;
+            let path = self.directory.join(&copy.key);
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Failed to remove cached copy {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+/// Name of the copy of one version of a file, so a file replaced in the backend is read again
+fn cache_key(object: &StoredObject) -> String {
+    let version = format!("{}\0{}\0{}", object.name, object.size, object.modified.to_rfc3339());
+    hash_bytes(version.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tempfile::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    fn object(name: &str, size: u64) -> StoredObject {
+        StoredObject { name: name.to_string(), size, modified: Utc::now() }
+    }
+
+    async fn contents(copy: Option<tokio::fs::File>) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        copy?.read_to_end(&mut data).await.unwrap();
+        Some(data)
+    }
+
+    async fn put(cache: &ReadCache, object: &StoredObject, data: &'static [u8]) -> Option<Vec<u8>> {
+        contents(cache.fill(object, Box::pin(data)).await).await
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_read_copies_go_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = ReadCache::open(temp_dir.path().join(CACHE_DIR), 10);
+        let (a, b, c) = (object("a.jpg", 4), object("b.jpg", 4), object("c.jpg", 4));
+
+        assert!(cache.copy(&a).await.is_none());
+        assert_eq!(put(&cache, &a, b"aaaa").await.unwrap(), b"aaaa");
+        put(&cache, &b, b"bbbb").await;
+        assert_eq!(contents(cache.copy(&a).await).await.unwrap(), b"aaaa");
+
+        // b was read least recently, so it makes room for c
+        put(&cache, &c, b"cccc").await;
+        assert!(cache.copy(&b).await.is_none());
+        assert_eq!(contents(cache.copy(&a).await).await.unwrap(), b"aaaa");
+        assert_eq!(contents(cache.copy(&c).await).await.unwrap(), b"cccc");
+
+        // A new version of a file is not served from the old copy
+        let newer = StoredObject { size: 5, ..a.clone() };
+        assert!(cache.copy(&newer).await.is_none());
+
+        // Files larger than the whole cache are not kept, even when listed smaller
+        let big = object("big.mov", 11);
+        assert!(!cache.fits(&big));
+        assert!(put(&cache, &object("big.mov", 4), &[0; 11]).await.is_none());
+        assert_eq!(std::fs::read_dir(temp_dir.path().join(CACHE_DIR)).unwrap().count(), 2);
+
+        // Copies survive a restart
+        let reopened = ReadCache::open(temp_dir.path().join(CACHE_DIR), 10);
+        assert_eq!(contents(reopened.copy(&c).await).await.unwrap(), b"cccc");
+    }
+}
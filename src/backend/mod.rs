@@ -0,0 +1,328 @@
+//! Storage backends that received files are kept in: another directory like a mounted NAS, an
+//! S3 bucket, or an SMB share RustDrop mounts itself.
+//!
+//! The share stays the working directory every feature reads from. A backend gets a copy of
+//! every upload, and the files it holds are listed and downloaded alongside the share's, so with
+//! `keep_local = false` the share only holds files until the backend has them. Files read from
+//! a remote backend are cached in the share's state directory.
+
+pub mod cache;
+pub mod s3;
+pub mod smb;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use humansize::{format_size, BINARY};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::{info, warn};
+
+use crate::backend::cache::{ReadCache, CACHE_DIR};
+use crate::core::config::{StorageBackend, StorageConfig};
+use crate::core::metadata::STATE_DIR;
+use crate::core::models::FileInfo;
+use crate::utils::file::{file_id, list_share, resolve_relative_path};
+
+/// How often the listing of the backend is fetched again, for files others put there
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A file held by a backend, named by its `/` separated path like files of the share
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub name: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+/// Contents of a stored file, read as they are needed so large files never sit in memory
+pub type FileReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Somewhere files can be kept
+pub trait Storage: Send + Sync {
+    /// Where the files go, for the logs
+    fn describe(&self) -> String;
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<StoredObject>>>;
+
+    fn open<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<FileReader>>;
+
+    /// Store `data` as `name`, replacing a file of that name
+    fn write<'a>(&'a self, name: &'a str, data: Vec<u8>) -> BoxFuture<'a, Result<()>>;
+
+    fn delete<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Files in a directory of the local file system
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        resolve_relative_path(&self.root, name).ok_or_else(|| anyhow!("Invalid file name {:?}", name))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<StoredObject>>> {
+        let root = self.root.clone();
+        Box::pin(async move {
+            let files = tokio::task::spawn_blocking(move || list_share(&root)).await??;
+            Ok(files
+                .into_iter()
+                .map(|file| StoredObject { name: file.name, size: file.size, modified: file.modified })
+                .collect())
+        })
+    }
+
+    fn open<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<FileReader>> {
+        Box::pin(async move {
+            let path = self.path(name)?;
+            let file = tokio::fs::File::open(&path).await.with_context(|| format!("Failed to read {:?}", path))?;
+            Ok(Box::pin(file) as FileReader)
+        })
+    }
+
+    fn write<'a>(&'a self, name: &'a str, data: Vec<u8>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = self.path(name)?;
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, data).await.with_context(|| format!("Failed to write {:?}", path))
+        })
+    }
+
+    fn delete<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = self.path(name)?;
+            tokio::fs::remove_file(&path).await.with_context(|| format!("Failed to delete {:?}", path))
+        })
+    }
+}
+
+/// The backend of a server, with the listing it had when last asked
+pub struct Backend {
+    storage: Box<dyn Storage>,
+    keep_local: bool,
+    objects: RwLock<Vec<StoredObject>>,
+    cache: Option<ReadCache>,
+}
+
+impl Backend {
+    pub fn new(storage: Box<dyn Storage>, keep_local: bool) -> Self {
+        Self {
+            storage,
+            keep_local,
+            objects: RwLock::default(),
+            cache: None,
+        }
+    }
+
+    /// Serve repeated reads from local copies in `cache`
+    pub fn with_cache(self, cache: ReadCache) -> Self {
+        Self { cache: Some(cache), ..self }
+    }
+
+    /// The backend `config` asks for, if any, mounting SMB shares as needed. Remote backends
+    /// get a read cache in the state directory of `share`.
+    pub async fn open(config: &StorageConfig, share: &Path) -> Result<Option<Self>> {
+        let storage: Box<dyn Storage> = match config.backend {
+            StorageBackend::Local => match &config.path {
+                Some(path) => Box::new(LocalStorage::new(path.clone())),
+                None => return Ok(None),
+            },
+            StorageBackend::S3 => {
+                let s3 = config.s3.as_ref().context("The s3 storage backend needs a [storage.s3] section")?;
+                Box::new(s3::S3Storage::new(s3)?)
+            }
+            StorageBackend::Smb => {
+                let smb = config.smb.as_ref().context("The smb storage backend needs a [storage.smb] section")?;
+                Box::new(LocalStorage::new(smb::mount(smb).await?))
+            }
+        };
+
+        let mut backend = Self::new(storage, config.keep_local);
+        if config.backend != StorageBackend::Local && config.cache_size > 0 {
+            let directory = share.join(STATE_DIR).join(CACHE_DIR);
+            backend = backend.with_cache(ReadCache::open(directory, config.cache_size));
+        }
+        backend.refresh().await?;
+        info!("Storing received files in {}", backend.describe());
+        Ok(Some(backend))
+    }
+
+    pub fn describe(&self) -> String {
+        self.storage.describe()
+    }
+
+    /// Fetch the listing of the backend again
+    pub async fn refresh(&self) -> Result<()> {
+        let objects = self.storage.list().await?;
+        *self.objects.write().unwrap() = objects;
+        Ok(())
+    }
+
+    /// Add the files only the backend has to a listing of the share
+    pub fn merge(&self, share: &Path, files: &mut Vec<FileInfo>) {
+        let objects = self.objects.read().unwrap();
+        let missing: Vec<FileInfo> = objects
+            .iter()
+            .filter(|object| !files.iter().any(|file| file.name == object.name))
+            .map(|object| file_info(share, object))
+            .collect();
+        if !missing.is_empty() {
+            files.extend(missing);
+            files.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+    }
+
+    /// Copy a file just received into the share to the backend, removing it from the share
+    /// afterwards unless `keep_local` is set
+    pub async fn store(&self, file: &FileInfo) -> Result<()> {
+        let data = tokio::fs::read(&file.path)
+            .await
+            .with_context(|| format!("Failed to read {:?}", file.path))?;
+        self.storage.write(&file.name, data).await?;
+
+        {
+            let mut objects = self.objects.write().unwrap();
+            objects.retain(|object| object.name != file.name);
+            objects.push(StoredObject { name: file.name.clone(), size: file.size, modified: file.modified });
+        }
+
+        if !self.keep_local {
+            if let Err(e) = tokio::fs::remove_file(&file.path).await {
+                warn!("Failed to remove {:?} after storing it: {}", file.path, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Open a file of the backend, or the cached copy while it holds the same version. Files the
+    /// cache has room for are copied there before they are read.
+    pub async fn open_file(&self, name: &str) -> Result<FileReader> {
+        let object = self.objects.read().unwrap().iter().find(|object| object.name == name).cloned();
+        let (Some(cache), Some(object)) = (&self.cache, object) else {
+            return self.storage.open(name).await;
+        };
+        if let Some(copy) = cache.copy(&object).await {
+            return Ok(Box::pin(copy));
+        }
+        if cache.fits(&object) {
+            let reader = self.storage.open(name).await?;
+            if let Some(copy) = cache.fill(&object, reader).await {
+                return Ok(Box::pin(copy));
+            }
+        }
+        self.storage.open(name).await
+    }
+
+    /// Read a whole file of the backend, like [`Backend::open_file`]
+    pub async fn read(&self, name: &str) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open_file(name).await?.read_to_end(&mut data).await?;
+        Ok(data)
+    }
+}
+
+/// Fetch the backend's listing now and then, for as long as the server runs
+pub async fn keep_refreshed(backend: Arc<Backend>) {
+    loop {
+        tokio::time::sleep(REFRESH_INTERVAL).await;
+        if let Err(e) = backend.refresh().await {
+            warn!("Failed to list {}: {:#}", backend.describe(), e);
+        }
+    }
+}
+
+/// A file of the backend as if it were in the share, with the id it has there
+fn file_info(share: &Path, object: &StoredObject) -> FileInfo {
+    let path = object.name.split('/').fold(share.to_path_buf(), |path, component| path.join(component));
+    FileInfo {
+        id: file_id(&path),
+        name: object.name.clone(),
+        size: object.size,
+        size_human: format_size(object.size, BINARY),
+        modified: object.modified,
+        mime_type: mime_guess::from_path(&object.name).first_or_octet_stream().to_string(),
+        path,
+        tags: Vec::new(),
+        pinned: false,
+        sender: None,
+        published: false,
+        mode: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::file::get_share_file_info;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_store_moves_files_to_the_backend() {
+        let share = TempDir::new().unwrap();
+        let nas = TempDir::new().unwrap();
+        let backend = Backend::new(Box::new(LocalStorage::new(nas.path().to_path_buf())), false);
+
+        let path = share.path().join("photos").join("a.jpg");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"jpeg").unwrap();
+        let file = get_share_file_info(share.path(), &path).unwrap();
+        backend.store(&file).await.unwrap();
+
+        assert!(!path.exists());
+        assert_eq!(std::fs::read(nas.path().join("photos").join("a.jpg")).unwrap(), b"jpeg");
+        assert_eq!(backend.read("photos/a.jpg").await.unwrap(), b"jpeg");
+
+        // Listed with the id it had in the share
+        let mut files = Vec::new();
+        backend.merge(share.path(), &mut files);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].id, file.id);
+        assert_eq!(files[0].mime_type, "image/jpeg");
+
+        std::fs::write(nas.path().join("b.txt"), b"put there by someone else").unwrap();
+        backend.refresh().await.unwrap();
+        let mut files = Vec::new();
+        backend.merge(share.path(), &mut files);
+        assert_eq!(files.iter().map(|file| file.name.as_str()).collect::<Vec<_>>(), ["b.txt", "photos/a.jpg"]);
+    }
+
+    #[tokio::test]
+    async fn test_reads_go_through_the_cache() {
+        let share = TempDir::new().unwrap();
+        let nas = TempDir::new().unwrap();
+        std::fs::write(nas.path().join("popular.mp4"), b"video").unwrap();
+        let cache = ReadCache::open(share.path().join(STATE_DIR).join(CACHE_DIR), 1024);
+        let backend = Backend::new(Box::new(LocalStorage::new(nas.path().to_path_buf())), true).with_cache(cache);
+        backend.refresh().await.unwrap();
+
+        assert_eq!(backend.read("popular.mp4").await.unwrap(), b"video");
+        // Served from the copy, the backend is not asked again
+        std::fs::remove_file(nas.path().join("popular.mp4")).unwrap();
+        assert_eq!(backend.read("popular.mp4").await.unwrap(), b"video");
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_rejects_escaping_names() {
+        let nas = TempDir::new().unwrap();
+        let storage = LocalStorage::new(nas.path().to_path_buf());
+        assert!(storage.write("../outside.txt", b"no".to_vec()).await.is_err());
+        assert!(storage.open("/etc/passwd").await.is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Mounting SMB shares with the tools the operating system brings, so the `smb` backend can use
+//! them like any directory: GVfs (`gio mount`) on Linux, `mount_smbfs` on macOS, and plain UNC
+//! paths on Windows. None of them needs administrator rights.
+
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::core::config::SmbConfig;
+
+/// Where a `smb://server/share/path` URL points
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmbLocation {
+    pub server: String,
+    pub share: String,
+    /// Directories inside the share
+    pub path: Vec<String>,
+}
+
+impl SmbLocation {
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("smb://")
+            .with_context(|| format!("SMB share {:?} should look like smb://server/share", url))?;
+        let mut components = rest.split('/').filter(|component| !component.is_empty());
+        let server = components.next().unwrap_or_default();
+        // A user name belongs in `username`, not in the URL
+        let server = server.rsplit('@').next().unwrap_or(server);
+        let Some(share) = components.next() else {
+            bail!("SMB share {:?} names no share on {:?}", url, server);
+        };
+        let path: Vec<String> = components.map(str::to_string).collect();
+        if path.iter().any(|component| component == "..") {
+            bail!("SMB share {:?} leaves the share", url);
+        }
+        Ok(Self {
+            server: server.to_string(),
+            share: share.to_string(),
+            path,
+        })
+    }
+}
+
+/// Mount the share of `config` unless it already is, returning the directory it is reachable at
+pub async fn mount(config: &SmbConfig) -> Result<PathBuf> {
+    let location = SmbLocation::parse(&config.url)?;
+    let root = mount_share(&location, config).await?;
+    let directory = location.path.iter().fold(root, |path, component| path.join(component));
+    info!("SMB share {} is mounted at {:?}", config.url, directory);
+    Ok(directory)
+}
+
+#[cfg(target_os = "linux")]
+async fn mount_share(location: &SmbLocation, config: &SmbConfig) -> Result<PathBuf> {
+    use std::process::Stdio;
+    use tokio::io::AsyncWriteExt;
+
+    let uid = rustix::process::getuid().as_raw();
+    let root = PathBuf::from(format!(
+        "/run/user/{}/gvfs/smb-share:server={},share={}",
+        uid,
+        location.server.to_lowercase(),
+        location.share.to_lowercase()
+    ));
+    if root.exists() {
+        return Ok(root);
+    }
+
+    let mut child = tokio::process::Command::new("gio")
+        .args(["mount", &format!("smb://{}/{}", location.server, location.share)])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run gio to mount the SMB share (is GVfs installed?)")?;
+    // gio asks for user, domain and password when the share wants them
+    if let Some(mut stdin) = child.stdin.take() {
+        let answers = format!(
+            "{}\nWORKGROUP\n{}\n",
+            config.username.as_deref().unwrap_or("guest"),
+            config.password.as_deref().unwrap_or("")
+        );
+        stdin.write_all(answers.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !root.exists() {
+        bail!("Failed to mount {}: {}", config.url, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(root)
+}
+
+#[cfg(target_os = "macos")]
+async fn mount_share(location: &SmbLocation, config: &SmbConfig) -> Result<PathBuf> {
+    use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+    use std::os::unix::fs::MetadataExt;
+
+    let root = std::env::temp_dir().join(format!("rustdrop-smb-{}-{}", location.server, location.share));
+    std::fs::create_dir_all(&root)?;
+    // A mount point lies on another device than the directory holding it
+    let mounted = match (std::fs::metadata(&root), root.parent().map(std::fs::metadata)) {
+        (Ok(root), Some(Ok(parent))) => root.dev() != parent.dev(),
+        _ => false,
+    };
+    if mounted {
+        return Ok(root);
+    }
+
+    let credentials = match (&config.username, &config.password) {
+        (Some(user), Some(password)) => format!(
+            "{}:{}@",
+            utf8_percent_encode(user, NON_ALPHANUMERIC),
+            utf8_percent_encode(password, NON_ALPHANUMERIC)
+        ),
+        (Some(user), None) => format!("{}@", utf8_percent_encode(user, NON_ALPHANUMERIC)),
+        _ => "guest@".to_string(),
+    };
+    let output = tokio::process::Command::new("mount_smbfs")
+        .arg("-N")
+        .arg(format!("//{}{}/{}", credentials, location.server, location.share))
+        .arg(&root)
+        .output()
+        .await
+        .context("Failed to run mount_smbfs")?;
+    if !output.status.success() {
+        bail!("Failed to mount {}: {}", config.url, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(root)
+}
+
+#[cfg(windows)]
+async fn mount_share(location: &SmbLocation, config: &SmbConfig) -> Result<PathBuf> {
+    let root = PathBuf::from(format!(r"\\{}\{}", location.server, location.share));
+    if let Some(user) = &config.username {
+        // Connecting again with the same credentials fails harmlessly, so the result only matters
+        // when the share stays out of reach
+        let _ = tokio::process::Command::new("net")
+            .arg("use")
+            .arg(&root)
+            .arg(config.password.as_deref().unwrap_or("*"))
+            .arg(format!("/user:{}", user))
+            .output()
+            .await;
+    }
+    if !root.exists() {
+        bail!("SMB share {} is not reachable", config.url);
+    }
+    Ok(root)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+async fn mount_share(_location: &SmbLocation, config: &SmbConfig) -> Result<PathBuf> {
+    bail!("Mounting SMB shares like {} is not supported on this platform, mount it and use the local backend", config.url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_locations() {
+        let location = SmbLocation::parse("smb://ann@nas.local/Photos/2024/inbox/").unwrap();
+        assert_eq!(location.server, "nas.local");
+        assert_eq!(location.share, "Photos");
+        assert_eq!(location.path, ["2024", "inbox"]);
+
+        assert!(SmbLocation::parse("smb://nas").is_err());
+        assert!(SmbLocation::parse("//nas/photos").is_err());
+        assert!(SmbLocation::parse("smb://nas/photos/../admin").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::{bail, Result};
+use clap::Args;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::client::fanout::{collect_from_peers, resolve_peers};
+use crate::core::collect::parse_pattern;
+
+#[derive(Args, Debug)]
+pub struct CollectArgs {
+    /// Devices to collect from: `all` for every device on the network, device names, or host:port addresses
+    #[arg(long, required = true, value_delimiter = ',')]
+    from: Vec<String>,
+
+    /// Directory to save into, with one subdirectory per device
+    #[arg(long)]
+    into: PathBuf,
+
+    /// Files to ask for, e.g. "*.jpg" or "trip/**/*"
+    #[arg(long, default_value = "*")]
+    pattern: String,
+
+    /// Name shown to the owners when they are asked for approval (defaults to this computer's name)
+    #[arg(long = "as")]
+    requester: Option<String>,
+
+    /// Seconds to wait for each owner to answer
+    #[arg(long, default_value_t = 300)]
+    timeout: u64,
+}
+
+impl CollectArgs {
+    pub async fn run(&self) -> Result<()> {
+        if parse_pattern(&self.pattern).is_none() {
+            bail!("Invalid pattern: {}", self.pattern);
+        }
+        let requester = match &self.requester {
+            Some(name) => name.clone(),
+            None => hostname::get()?.to_string_lossy().to_string(),
+        };
+
+        println!("Looking for devices...");
+        let clients = resolve_peers(&self.from).await?;
+        println!(
+            "Asking {} devices for {:?}, their owners need to approve in the RustDrop web page",
+            clients.len(),
+            self.pattern
+        );
+
+        let outcomes = collect_from_peers(
+            clients,
+            &requester,
+            &self.pattern,
+            &self.into,
+            Duration::from_secs(self.timeout),
+        )
+        .await;
+
+        let mut collected = 0;
+        for outcome in &outcomes {
+            match &outcome.result {
+                Ok(files) => {
+                    collected += files.len();
+                    println!("  ✓ {} ({} files)", outcome.peer, files.len());
+                }
+                Err(e) => println!("  ✗ {}: {:#}", outcome.peer, e),
+            }
+        }
+        println!("Collected {} files into {:?}", collected, self.into);
+        Ok(())
+    }
+}
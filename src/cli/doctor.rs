@@ -0,0 +1,63 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use std::path::Path;
+
+use crate::core::config::AppConfig;
+use crate::core::identity::identity_dir;
+use crate::core::share::check_share;
+use crate::utils::network::is_port_available;
+
+#[derive(Args, Debug)]
+pub struct DoctorArgs {}
+
+impl DoctorArgs {
+    /// Check the setup without starting the server, reporting every problem found
+    pub async fn run(&self, config: &AppConfig) -> Result<()> {
+        let directory = match &config.files.directory {
+            Some(directory) => directory.clone(),
+            None => std::env::current_dir().context("Failed to get current directory")?,
+        };
+
+        let mut failures = 0;
+        let mut report = |ok: bool, what: String| {
+            println!("{} {}", if ok { "✅" } else { "❌" }, what);
+            if !ok {
+                failures += 1;
+            }
+        };
+
+        match check_share(&directory) {
+            Ok(()) => report(true, format!("Shared directory {:?} is readable and writable", directory)),
+            Err(e) => report(false, format!("Shared directory: {}", e)),
+        }
+
+        let port = config.server.port;
+        if is_port_available(port) {
+            report(true, format!("Port {} is free", port));
+        } else {
+            // Not fatal, the server picks another port
+            println!("⚠️  Port {} is in use, another port will be chosen", port);
+        }
+
+        match identity_dir() {
+            Some(dir) => match check_identity_dir(&dir) {
+                Ok(()) => report(true, format!("Device identity is kept in {:?}", dir)),
+                Err(e) => report(false, format!("Device identity cannot be saved in {:?}: {}", dir, e)),
+            },
+            None => report(false, "No config directory to keep the device identity in".to_string()),
+        }
+
+        if failures > 0 {
+            bail!("{} problem(s) found", failures);
+        }
+        println!("Everything looks good");
+        Ok(())
+    }
+}
+
+fn check_identity_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(".write-test");
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
+}
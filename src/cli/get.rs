@@ -0,0 +1,85 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use humansize::{format_size, BINARY};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cli::send::speed_and_eta;
+use crate::client::download::{download_resumable, part_path};
+use crate::client::fanout::{resolve_peers, ALL_PEERS};
+use crate::utils::throughput::Throughput;
+
+/// How often the progress line is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Args, Debug)]
+pub struct GetArgs {
+    /// Device to download from: a device name or host:port address
+    from: String,
+
+    /// File to download, as named in the device's share (e.g. `docs/report.pdf`)
+    file: String,
+
+    /// Where to save it (defaults to the file's name in the current directory)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl GetArgs {
+    pub async fn run(&self) -> Result<()> {
+        if self.from.trim().eq_ignore_ascii_case(ALL_PEERS) {
+            bail!("`get` downloads from one device, name it or give its address");
+        }
+        let clients = resolve_peers(std::slice::from_ref(&self.from)).await?;
+        let [client] = clients.as_slice() else {
+            bail!("{} matches {} devices, give its address instead", self.from, clients.len());
+        };
+
+        let name = self.file.trim_matches('/');
+        let file = client
+            .list_files()
+            .await?
+            .into_iter()
+            .find(|file| file.name == name)
+            .with_context(|| format!("{} has no file {:?}", client.base_url(), name))?;
+        let target = match &self.output {
+            Some(output) if output.is_dir() => output.join(base_name(name)),
+            Some(output) => output.clone(),
+            None => PathBuf::from(base_name(name)),
+        };
+        if target.exists() {
+            bail!("{:?} already exists", target);
+        }
+
+        if part_path(&target).exists() {
+            println!("Continuing the earlier download of {}", name);
+        }
+        // Progress comes with every network read, the line is redrawn a few times a second
+        let throughput = Mutex::new((Throughput::default(), None::<Instant>));
+        download_resumable(client, &file.id.to_string(), &target, |received, size| {
+            let (throughput, shown) = &mut *throughput.lock().unwrap();
+            if shown.is_some_and(|shown| shown.elapsed() < REDRAW_INTERVAL) && received < size {
+                return;
+            }
+            *shown = Some(Instant::now());
+            throughput.record(received);
+            print!(
+                "\r{} of {}{}   ",
+                format_size(received, BINARY),
+                format_size(size, BINARY),
+                speed_and_eta(throughput, size)
+            );
+            std::io::stdout().flush().ok();
+        })
+        .await?;
+        println!();
+        println!("✓ Saved {:?}, checksum verified", target);
+        Ok(())
+    }
+}
+
+fn base_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
@@ -0,0 +1,84 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use clap::Args;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::client::PeerClient;
+use crate::core::instances::InstanceRegistry;
+use crate::core::models::DeviceInfo;
+use crate::utils::network::is_port_available;
+
+/// How long the previous instance gets to let go of the port
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Args, Debug)]
+pub struct ListInstancesArgs {}
+
+impl ListInstancesArgs {
+    pub async fn run(&self) -> Result<()> {
+        let instances = InstanceRegistry::open_default().list();
+        if instances.is_empty() {
+            println!("No RustDrop instances are running");
+            return Ok(());
+        }
+
+        for instance in instances {
+            let uptime = (Utc::now() - instance.started).num_minutes();
+            println!("🌐 {}  {}", instance.url, instance.name);
+            println!(
+                "   serving {:?}, pid {}, up {}h {:02}m",
+                instance.directory,
+                instance.pid,
+                uptime / 60,
+                uptime % 60
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Look for another RustDrop already listening on `port`. Without `takeover` its URL is
+/// printed and true returned, so the caller stops instead of moving to another port and
+/// breaking bookmarks and QR codes; with it the other instance is asked to shut down.
+pub async fn check_existing_instance(registry: &InstanceRegistry, port: u16, takeover: bool) -> Result<bool> {
+    if is_port_available(port) {
+        return Ok(false);
+    }
+    let client = PeerClient::new(&format!("127.0.0.1:{}", port))?;
+    if !client.is_rustdrop().await {
+        return Ok(false);
+    }
+
+    let record = registry.get(port);
+    if !takeover {
+        let url = record
+            .as_ref()
+            .map(|record| record.url.clone())
+            .unwrap_or_else(|| DeviceInfo::new(port).url());
+        println!("🌐 RustDrop is already running at {}", url);
+        if let Some(record) = &record {
+            println!("   serving {:?}, pid {}", record.directory, record.pid);
+        }
+        println!("   Use that one, pass --port to run another instance, or --takeover to replace it.");
+        return Ok(true);
+    }
+
+    let Some(token) = record.and_then(|record| record.control_token) else {
+        bail!(
+            "The RustDrop on port {} was not started by this user or is too old to hand over its port; stop it first",
+            port
+        );
+    };
+    client.request_shutdown(&token).await?;
+
+    let deadline = Instant::now() + TAKEOVER_TIMEOUT;
+    while !is_port_available(port) || registry.get(port).is_some() {
+        if Instant::now() > deadline {
+            bail!("The RustDrop on port {} did not shut down in time", port);
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    info!("Took over port {} from the previous instance", port);
+    Ok(false)
+}
@@ -0,0 +1,31 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::core::config::AppConfig;
+use crate::utils::file::{list_share_with, ListOptions};
+use crate::utils::hash::sha256_manifest;
+
+#[derive(Args, Debug)]
+pub struct ManifestArgs {
+    /// Directory to list (defaults to the configured share, then the current directory)
+    dir: Option<PathBuf>,
+}
+
+impl ManifestArgs {
+    /// Print a `SHA256SUMS` manifest of the directory, checkable there with `sha256sum -c`
+    pub async fn run(&self, config: &AppConfig) -> Result<()> {
+        let directory = match self.dir.clone().or_else(|| config.files.directory.clone()) {
+            Some(directory) => directory,
+            None => std::env::current_dir().context("Failed to get current directory")?,
+        };
+
+        let files = list_share_with(&directory, &ListOptions::from_config(&config.files))
+            .with_context(|| format!("Failed to list {:?}", directory))?;
+        let manifest = tokio::task::spawn_blocking(move || sha256_manifest(&files)).await??;
+
+        std::io::stdout().write_all(manifest.as_bytes())?;
+        Ok(())
+    }
+}
@@ -1,16 +1,32 @@
+mod collect;
+mod doctor;
+mod get;
+mod instances;
+mod manifest;
+mod pair;
+mod send;
+mod state;
+mod sync;
+
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::client::agent::set_user_agent;
 use crate::core::app::App;
-use crate::core::config::AppConfig;
-use crate::utils::network::get_available_port_or_default;
+use crate::core::config::{AppConfig, SnapshotMode};
+use crate::core::instances::{instance_label, remembered_port, InstanceRegistry};
+use crate::core::share::prepare_share;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Port to listen on (will find next available port if this one is in use)
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Port to listen on (will find next available port if this one is in use). Defaults to the
+    /// port the directory was last served on.
     #[arg(short, long)]
     port: Option<u16>,
 
@@ -30,11 +46,57 @@ pub struct Cli {
     #[arg(short, long)]
     open: bool,
 
+    /// Create the directory to serve files from if it does not exist
+    #[arg(long)]
+    create_dir: bool,
+
+    /// Make new clients enter an access code shown in this terminal
+    #[arg(long)]
+    require_code: bool,
+
+    /// Serve HTTPS with a self-signed certificate, which mobile browsers need for features
+    /// such as clipboard access (needs the `tls` feature)
+    #[arg(long)]
+    tls: bool,
+
+    /// Ask a RustDrop already running on the port to shut down and take its place
+    #[arg(long)]
+    takeover: bool,
+
+    /// Serve the directory read-only, as it was at startup: `index` lists the files found then
+    /// and stops serving those that change, `copy` serves copies made at startup
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "index")]
+    snapshot: Option<SnapshotMode>,
+
     /// Generate example configuration file
     #[arg(long)]
     generate_config: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Two-way sync a local directory with another RustDrop device
+    Sync(sync::SyncArgs),
+    /// Send files to several devices at once
+    Send(send::SendArgs),
+    /// Download matching files from several devices, with their owners' approval
+    Collect(collect::CollectArgs),
+    /// Download one file from another device, resuming where an interrupted download stopped
+    Get(get::GetArgs),
+    /// Enter the access code shown on another device so it accepts this one
+    Pair(pair::PairArgs),
+    /// Check the shared directory, port and device identity without starting the server
+    Doctor(doctor::DoctorArgs),
+    /// Print a SHA256SUMS manifest of a directory, by default the share
+    Manifest(manifest::ManifestArgs),
+    /// Write the configuration, device identity, pairings and history to one file
+    ExportState(state::ExportStateArgs),
+    /// Restore a file written by `export-state`, e.g. on a new machine
+    ImportState(state::ImportStateArgs),
+    /// List the RustDrop instances running on this host
+    ListInstances(instances::ListInstancesArgs),
+}
+
 impl Cli {
     pub async fn run(&self) -> Result<()> {
         // Generate config file if requested
@@ -66,6 +128,33 @@ impl Cli {
         if self.open {
             config.ui.open_browser = true;
         }
+        if self.require_code {
+            config.access.require_code = true;
+        }
+        if self.tls {
+            config.server.tls.enabled = true;
+        }
+        if let Some(snapshot) = self.snapshot {
+            config.files.snapshot = snapshot;
+        }
+        if let Some(user_agent) = &config.client.user_agent {
+            set_user_agent(user_agent.clone());
+        }
+
+        if let Some(command) = &self.command {
+            return match command {
+                Command::Sync(args) => args.run(&config).await,
+                Command::Send(args) => args.run().await,
+                Command::Collect(args) => args.run().await,
+                Command::Get(args) => args.run().await,
+                Command::Pair(args) => args.run().await,
+                Command::Doctor(args) => args.run(&config).await,
+                Command::Manifest(args) => args.run(&config).await,
+                Command::ExportState(args) => args.run(&config).await,
+                Command::ImportState(args) => args.run(&config).await,
+                Command::ListInstances(args) => args.run().await,
+            };
+        }
 
         // Determine the directory to serve files from
         let directory = config.files.directory.clone().unwrap_or_else(|| {
@@ -73,19 +162,40 @@ impl Cli {
             info!("No directory specified, using current directory: {:?}", current_dir);
             current_dir
         });
+        prepare_share(&directory, self.create_dir)?;
+
+        // Unless a port was given, reuse the one this share had last time so its URLs stay valid
+        let range = config.server.port_range();
+        let preferred = match remembered_port(&directory) {
+            Some(port) if self.port.is_none() && range.as_ref().is_none_or(|range| range.contains(&port)) => port,
+            _ => config.server.port,
+        };
 
-        // Find an available port
-        let available_port = get_available_port_or_default(config.server.port);
+        // Find a port that is free and not claimed by another instance on this host
+        let registry = InstanceRegistry::open_default();
+        if instances::check_existing_instance(&registry, preferred, self.takeover).await? {
+            return Ok(());
+        }
+        let others = registry.list();
+        let claim = registry.claim(preferred, range)?;
         
         // Create and run the application
-        let app = App::new(
-            available_port,
-            directory,
+        let mut app = App::new(
+            claim.port(),
+            directory.clone(),
             config.discovery.enabled,
             config.ui.qr_code,
             config.ui.open_browser,
             config.server.max_file_size,
-        );
+        )
+        .with_config(config);
+        if !others.is_empty() {
+            app = app.with_instance_label(&instance_label(&directory));
+        }
+        app.bind().await?;
+        if let Err(e) = claim.publish(&directory, app.device_info(), app.control()) {
+            warn!("Failed to register instance: {}", e);
+        }
         
         app.run().await
     }
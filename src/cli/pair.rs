@@ -0,0 +1,26 @@
+use anyhow::{bail, Result};
+use clap::Args;
+
+use crate::client::fanout::resolve_peers;
+
+#[derive(Args, Debug)]
+pub struct PairArgs {
+    /// Device to pair with: a device name or host:port address
+    peer: String,
+
+    /// Access code shown in the peer's terminal
+    code: String,
+}
+
+impl PairArgs {
+    pub async fn run(&self) -> Result<()> {
+        let peers = resolve_peers(std::slice::from_ref(&self.peer)).await?;
+        let [client] = peers.as_slice() else {
+            bail!("{:?} matches several devices, pick one by address", self.peer);
+        };
+
+        client.pair(&self.code).await?;
+        println!("✅ Paired with {}", client.base_url());
+        Ok(())
+    }
+}
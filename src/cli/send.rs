@@ -0,0 +1,252 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use clap::Args;
+use humansize::{format_size, BINARY};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::client::fanout::{resolve_each, resolve_peers, send_to_peers, OutgoingFile, PeerOutcome, ALL_PEERS};
+use crate::client::outgoing::OutgoingQueue;
+use crate::client::PeerClient;
+use crate::core::alerts::desktop_notification;
+use crate::utils::file::{attributes_of, get_file_info};
+use crate::utils::throughput::{format_eta, Throughput};
+
+/// How long the daemon waits between attempts to reach queued recipients
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Queued sends are given up after this long
+const MAX_QUEUE_AGE: chrono::Duration = chrono::Duration::days(7);
+
+#[derive(Args, Debug)]
+pub struct SendArgs {
+    /// Files to send
+    #[arg(required_unless_present = "daemon")]
+    files: Vec<PathBuf>,
+
+    /// Recipients: `all` for every device on the network, device names, or host:port addresses
+    #[arg(long, required_unless_present = "daemon", value_delimiter = ',')]
+    to: Vec<String>,
+
+    /// Directory on the recipients to store the files in (defaults to their usual upload location)
+    #[arg(long)]
+    dir: Option<String>,
+
+    /// Send extended attributes (Finder tags, quarantine flags) along; recipients only keep
+    /// them if they set `files.preserve_xattrs`
+    #[arg(long)]
+    xattrs: bool,
+
+    /// Keep the files for recipients that cannot be reached and deliver them in the background
+    /// once they can
+    #[arg(long, conflicts_with = "daemon")]
+    queue: bool,
+
+    /// Deliver the sends queued with `--queue`, retrying until every one went out
+    #[arg(long)]
+    daemon: bool,
+}
+
+impl SendArgs {
+    pub async fn run(&self) -> Result<()> {
+        if self.daemon {
+            return run_daemon().await;
+        }
+        if self.queue && self.to.iter().any(|selector| selector.trim().eq_ignore_ascii_case(ALL_PEERS)) {
+            bail!("--queue needs recipients by name or address, `all` cannot be waited for");
+        }
+        let files = self.read_files().await?;
+
+        println!("Looking for devices...");
+        let mut clients = Vec::new();
+        let mut recipients = Vec::new();
+        let mut unreachable = Vec::new();
+        if self.queue {
+            for (selector, resolved) in resolve_each(&self.to).await {
+                match resolved {
+                    Ok(found) => {
+                        recipients.extend(found.iter().map(|_| selector.clone()));
+                        clients.extend(found);
+                    }
+                    Err(e) => {
+                        println!("  ✗ {}: {:#}", selector, e);
+                        unreachable.push(selector);
+                    }
+                }
+            }
+        } else {
+            clients = resolve_peers(&self.to).await?;
+        }
+
+        let outcomes = if clients.is_empty() {
+            Vec::new()
+        } else {
+            send_with_progress(clients, files).await?
+        };
+        let mut failures = 0;
+        for (index, outcome) in outcomes.iter().enumerate() {
+            match &outcome.result {
+                Ok(count) => println!("  ✓ {} ({} files)", outcome.peer, count),
+                Err(e) => {
+                    println!("  ✗ {}: {:#}", outcome.peer, e);
+                    match recipients.get(index) {
+                        Some(selector) if self.queue => {
+                            if !unreachable.contains(selector) {
+                                unreachable.push(selector.clone());
+                            }
+                        }
+                        _ => failures += 1,
+                    }
+                }
+            }
+        }
+
+        if !unreachable.is_empty() {
+            self.queue_for(&unreachable).await?;
+        }
+        if failures > 0 {
+            bail!("Sending failed for {} of {} devices", failures, outcomes.len());
+        }
+        Ok(())
+    }
+
+    async fn read_files(&self) -> Result<Vec<OutgoingFile>> {
+        let mut files = Vec::new();
+        for path in &self.files {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .with_context(|| format!("Not a file: {:?}", path))?;
+            let data = tokio::fs::read(path)
+                .await
+                .with_context(|| format!("Failed to read {:?}", path))?;
+            let attributes = attributes_of(&get_file_info(path)?, self.xattrs);
+            let target = match &self.dir {
+                Some(dir) => format!("{}/{}", dir.trim_end_matches('/'), name),
+                None => name.to_string(),
+            };
+            files.push(OutgoingFile { path: target, data, attributes });
+        }
+        Ok(files)
+    }
+
+    /// Keep the files for recipients that could not be reached and make sure a daemon delivers them
+    async fn queue_for(&self, recipients: &[String]) -> Result<()> {
+        let queue = OutgoingQueue::open_default().context("No config directory to keep queued files in")?;
+        // Read again, the first copy went to the devices that were reached
+        let files = self.read_files().await?;
+        for recipient in recipients {
+            queue.add(recipient, &files)?;
+            println!("📮 Queued for {}, it gets the files once it can be reached", recipient);
+        }
+
+        if !queue.daemon_running() {
+            start_daemon()?;
+        }
+        Ok(())
+    }
+}
+
+/// Send to every client, showing the overall progress
+async fn send_with_progress(clients: Vec<PeerClient>, files: Vec<OutgoingFile>) -> Result<Vec<PeerOutcome>> {
+    let (progress, transfer) = send_to_peers(clients, files);
+    println!(
+        "Sending {} to {} devices",
+        format_size(progress.per_peer, BINARY),
+        progress.peers()
+    );
+
+    let transfer = tokio::spawn(transfer);
+    let mut ticker = tokio::time::interval(Duration::from_millis(500));
+    let mut throughput = Throughput::default();
+    while !transfer.is_finished() {
+        ticker.tick().await;
+        throughput.record(progress.sent());
+        print!(
+            "\r{} of {} sent{}, {}/{} devices done   ",
+            format_size(progress.sent(), BINARY),
+            format_size(progress.total(), BINARY),
+            speed_and_eta(&throughput, progress.total()),
+            progress.finished(),
+            progress.peers()
+        );
+        std::io::stdout().flush().ok();
+    }
+    println!();
+
+    Ok(transfer.await?)
+}
+
+/// `, 12 MiB/s, 3 min left` once the speed is known
+pub(crate) fn speed_and_eta(throughput: &Throughput, size: u64) -> String {
+    let Some(speed) = throughput.speed() else {
+        return String::new();
+    };
+    let eta = throughput
+        .eta(size)
+        .map(|eta| format!(", {} left", format_eta(eta)))
+        .unwrap_or_default();
+    format!(", {}/s{}", format_size(speed, BINARY), eta)
+}
+
+/// Run `rustdrop send --daemon` in the background, outliving this command
+fn start_daemon() -> Result<()> {
+    let program = std::env::current_exe().context("Failed to find the rustdrop executable")?;
+    let mut command = std::process::Command::new(program);
+    command
+        .args(["send", "--daemon"])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    // Out of the terminal's process group, so Ctrl+C in the terminal leaves it running
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    command.spawn().context("Failed to start delivering in the background")?;
+    println!("   Delivering in the background, you will be notified");
+    Ok(())
+}
+
+/// Deliver queued sends until none are left
+async fn run_daemon() -> Result<()> {
+    let queue = OutgoingQueue::open_default().context("No config directory with queued files")?;
+    let Some(_claim) = queue.claim_daemon()? else {
+        println!("Queued sends are already being delivered");
+        return Ok(());
+    };
+
+    loop {
+        let sends = queue.list();
+        if sends.is_empty() {
+            return Ok(());
+        }
+        println!("{} sends waiting", sends.len());
+
+        for mut send in sends {
+            if Utc::now() - send.created > MAX_QUEUE_AGE {
+                let reason = send.last_error.as_deref().unwrap_or("it could not be reached");
+                println!("  ✗ Gave up on {}: {}", send.peer, reason);
+                desktop_notification(
+                    "RustDrop: sending gave up",
+                    &format!("{} files never reached {}", send.files.len(), send.peer),
+                );
+                queue.remove(&send)?;
+                continue;
+            }
+
+            match queue.deliver(&send).await {
+                Ok(count) => {
+                    println!("  ✓ {} ({} files, {})", send.peer, count, format_size(send.size(&queue), BINARY));
+                    desktop_notification("RustDrop: files delivered", &format!("{} files reached {}", count, send.peer));
+                    queue.remove(&send)?;
+                }
+                Err(e) => {
+                    send.attempts += 1;
+                    send.last_error = Some(format!("{:#}", e));
+                    queue.update(&send)?;
+                }
+            }
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::core::bundle::{StateBundle, StateLocations};
+use crate::core::config::AppConfig;
+use crate::core::identity::identity_dir;
+
+#[derive(Args, Debug)]
+pub struct ExportStateArgs {
+    /// File to write the bundle to
+    file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportStateArgs {
+    /// Bundle written by `rustdrop export-state`
+    file: PathBuf,
+
+    /// Replace existing identity and state files that differ from the bundle
+    #[arg(long)]
+    force: bool,
+}
+
+/// The configuration file in the current directory, the identity directory and the share
+fn locations(config: &AppConfig) -> Result<StateLocations> {
+    let share = match &config.files.directory {
+        Some(directory) => directory.clone(),
+        None => std::env::current_dir().context("Failed to get current directory")?,
+    };
+    Ok(StateLocations {
+        config_file: PathBuf::from("rustdrop.toml"),
+        identity_dir: identity_dir(),
+        share,
+    })
+}
+
+impl ExportStateArgs {
+    /// Bundle the configuration, device identity and share state into one file
+    pub async fn run(&self, config: &AppConfig) -> Result<()> {
+        let bundle = StateBundle::collect(&locations(config)?)?;
+        let content = serde_json::to_vec_pretty(&bundle)?;
+
+        // The bundle holds the client token, keep it away from other users
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&self.file)
+            .and_then(|mut file| file.write_all(&content))
+            .with_context(|| format!("Failed to write {:?}", self.file))?;
+
+        println!("📦 Exported {} files to {}", bundle.files.len(), self.file.display());
+        println!("   It contains this device's identity; keep it private and delete it once imported.");
+        Ok(())
+    }
+}
+
+impl ImportStateArgs {
+    /// Restore a bundle on this machine, so peers recognise it as the exporting device
+    pub async fn run(&self, config: &AppConfig) -> Result<()> {
+        let content =
+            std::fs::read_to_string(&self.file).with_context(|| format!("Failed to read {:?}", self.file))?;
+        let bundle = StateBundle::from_json(&content)?;
+        let locations = locations(config)?;
+
+        let conflicts = bundle.conflicts(&locations)?;
+        if !conflicts.is_empty() && !self.force {
+            for path in &conflicts {
+                eprintln!("   {}", path.display());
+            }
+            bail!("{} existing files differ from the bundle, pass --force to replace them", conflicts.len());
+        }
+
+        let written = bundle.restore(&locations)?;
+        println!(
+            "✅ Imported {} files exported on {}",
+            written.len(),
+            bundle.created.format("%Y-%m-%d %H:%M UTC")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use std::path::PathBuf;
+
+use crate::client::PeerClient;
+use crate::core::config::{AppConfig, ConflictPolicy};
+use crate::sync::watch::watch_and_push;
+use crate::sync::{sync_directory, SyncOptions};
+use crate::utils::file::RestoreOptions;
+
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    /// Peer to sync with (host, host:port or http:// URL)
+    peer: String,
+
+    /// Local directory to sync (defaults to the served directory)
+    #[arg(short, long)]
+    directory: Option<PathBuf>,
+
+    /// How to settle files changed on both sides (defaults to sync.conflict_policy)
+    #[arg(long, value_enum)]
+    conflict: Option<ConflictPolicy>,
+
+    /// Only print what would be done
+    #[arg(long, conflicts_with = "watch")]
+    dry_run: bool,
+
+    /// Keep running and push local changes to the peer as they happen
+    #[arg(long)]
+    watch: bool,
+
+    /// Transfer file contents over the peer's QUIC transport when it offers one
+    #[cfg(feature = "quic")]
+    #[arg(long)]
+    quic: bool,
+}
+
+impl SyncArgs {
+    pub async fn run(&self, config: &AppConfig) -> Result<()> {
+        let directory = match self.directory.clone().or_else(|| config.files.directory.clone()) {
+            Some(directory) => directory,
+            None => std::env::current_dir().context("Failed to get current directory")?,
+        };
+        let options = SyncOptions {
+            conflict_policy: self.conflict.unwrap_or(config.sync.conflict_policy),
+            dry_run: self.dry_run,
+            restore: RestoreOptions::from_config(&config.files),
+            ..Default::default()
+        };
+        let client = PeerClient::new(&self.peer)?;
+        #[cfg(feature = "quic")]
+        let client = {
+            let mut client = client;
+            if self.quic && !client.use_quic().await? {
+                println!("{} has no QUIC transport, using HTTP", client.base_url());
+            }
+            client
+        };
+
+        println!("Syncing {:?} with {}", directory, client.base_url());
+        if self.watch {
+            return watch_and_push(&client, &directory, options).await;
+        }
+
+        let actions = sync_directory(&client, &directory, options).await?;
+
+        if actions.is_empty() {
+            println!("Already in sync");
+        }
+        for action in &actions {
+            println!("{}{}", if self.dry_run { "would " } else { "" }, action);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+//! How this device introduces itself on calls to peers: a `User-Agent` naming RustDrop, its
+//! version and the device, and the same details in `X-RustDrop-Client` for peers to read.
+
+use std::sync::OnceLock;
+
+use crate::core::clients::PeerAgent;
+
+/// `client.user_agent`, set once the configuration is loaded
+static USER_AGENT: OnceLock<String> = OnceLock::new();
+
+/// Use `user_agent` on every call to a peer from now on, in place of the default
+pub fn set_user_agent(user_agent: String) {
+    let _ = USER_AGENT.set(user_agent);
+}
+
+/// `User-Agent` of calls made as the device `device`
+pub fn user_agent(device: &str) -> String {
+    USER_AGENT
+        .get()
+        .cloned()
+        .unwrap_or_else(|| default_user_agent(&PeerAgent::this_device(device)))
+}
+
+fn default_user_agent(agent: &PeerAgent) -> String {
+    format!("RustDrop/{} ({}; device {})", agent.version, agent.os, agent.device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_user_agent() {
+        let agent = PeerAgent { version: "0.1.0".to_string(), device: "abc".to_string(), os: "linux".to_string() };
+        assert_eq!(default_user_agent(&agent), "RustDrop/0.1.0 (linux; device abc)");
+    }
+}
@@ -0,0 +1,99 @@
+//! Carrying the files of the outboxes to their peers
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use crate::client::PeerClient;
+use crate::core::config::CourierPeer;
+use crate::core::courier::{settled, Courier};
+use crate::core::history::NetworkUsage;
+use crate::core::models::FileInfo;
+use crate::core::pause::TransferPause;
+use crate::utils::file::{attributes_of, sanitize_path_component, INBOX_DIR};
+
+/// Deliver the outboxes of `courier` for as long as it lives: whenever a file is queued, and
+/// every `retry_secs` for peers that could not be reached. Files land in the peer's
+/// `inbox/<device_name>/`, keeping the folders they had in the outbox. What was delivered is
+/// counted in `network`.
+pub async fn run_courier(
+    courier: Arc<Courier>,
+    pause: Arc<TransferPause>,
+    network: Arc<NetworkUsage>,
+    device_name: String,
+) {
+    let inbox = format!("{}/{}", INBOX_DIR, sanitize_path_component(&device_name));
+    loop {
+        for peer in courier.peers() {
+            let now = Utc::now();
+            let files: Vec<FileInfo> = courier.pending(peer).into_iter().filter(|file| settled(file, now)).collect();
+            if !files.is_empty() {
+                pause.wait_until_resumed().await;
+                deliver(&courier, peer, files, &inbox, &pause, &network).await;
+            }
+        }
+        courier.wait().await;
+    }
+}
+
+async fn deliver(
+    courier: &Courier,
+    peer: &CourierPeer,
+    files: Vec<FileInfo>,
+    inbox: &str,
+    pause: &Arc<TransferPause>,
+    network: &NetworkUsage,
+) {
+    let client = match PeerClient::new(&peer.address) {
+        Ok(client) => client.with_pause(pause.clone()),
+        Err(e) => {
+            warn!("Cannot deliver to {}: {:#}", peer.name, e);
+            courier.failed(peer, format!("{:#}", e));
+            return;
+        }
+    };
+    if !client.is_rustdrop().await {
+        debug!("{} is not reachable, {} files keep waiting", peer.name, files.len());
+        courier.unreachable(peer);
+        return;
+    }
+
+    let outbox = courier.outbox(peer);
+    for file in files {
+        if let Err(e) = send(&client, &file, inbox).await {
+            warn!("Failed to deliver {} to {}: {:#}", file.name, peer.name, e);
+            courier.failed(peer, format!("{:#}", e));
+            return;
+        }
+        info!("Delivered {} to {}", file.name, peer.name);
+        network.record(&peer.name, Some(&peer.address), 0, file.size);
+        courier.delivered(peer);
+        if let Err(e) = tokio::fs::remove_file(&file.path).await {
+            warn!("Failed to remove {:?} from the outbox: {}", file.path, e);
+        }
+        remove_empty_parents(&file.path, &outbox).await;
+    }
+}
+
+async fn send(client: &PeerClient, file: &FileInfo, inbox: &str) -> Result<()> {
+    let data = tokio::fs::read(&file.path)
+        .await
+        .with_context(|| format!("Failed to read {:?}", file.path))?;
+    client
+        .upload(&format!("{}/{}", inbox, file.name), data, attributes_of(file, false))
+        .await?;
+    Ok(())
+}
+
+/// Drop the folders of a delivered file that are empty now, up to the outbox itself
+async fn remove_empty_parents(path: &Path, outbox: &Path) {
+    let mut directory = path.parent();
+    while let Some(current) = directory.filter(|current| *current != outbox && current.starts_with(outbox)) {
+        if tokio::fs::remove_dir(current).await.is_err() {
+            break;
+        }
+        directory = current.parent();
+    }
+}
@@ -0,0 +1,262 @@
+//! Downloads that survive interruptions, for `rustdrop get`.
+//!
+//! Data goes to `<target>.part`, and `<target>.part.json` records which file of which peer it
+//! belongs to and how much of it is safely on disk. An interrupted download continues from there
+//! with a range request, and the finished file only takes the target's name once its SHA-256
+//! matches the peer's.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tracing::warn;
+
+use crate::client::{check_status, PeerClient};
+use crate::utils::file::set_modified;
+use crate::utils::hash::hash_file;
+use crate::web::handlers::api::MODIFIED_HEADER;
+
+const DOWNLOAD_RETRIES: u32 = 5;
+
+/// Progress is saved whenever this much more arrived
+const PROGRESS_INTERVAL: u64 = 8 * 1024 * 1024;
+
+/// What a `.part` file holds, saved next to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialDownload {
+    pub peer: String,
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    pub sha256: String,
+    /// Bytes of the part file known to be on disk
+    pub received: u64,
+}
+
+impl PartialDownload {
+    /// Whether both describe the same version of the same file
+    fn same_file(&self, other: &PartialDownload) -> bool {
+        self.peer == other.peer
+            && self.id == other.id
+            && self.size == other.size
+            && self.modified == other.modified
+            && self.sha256 == other.sha256
+    }
+}
+
+/// Where the data of a download to `target` goes until it is complete
+pub fn part_path(target: &Path) -> PathBuf {
+    let mut path = target.as_os_str().to_owned();
+    path.push(".part");
+    PathBuf::from(path)
+}
+
+fn progress_path(target: &Path) -> PathBuf {
+    let mut path = target.as_os_str().to_owned();
+    path.push(".part.json");
+    PathBuf::from(path)
+}
+
+fn load_progress(target: &Path) -> Option<PartialDownload> {
+    let content = std::fs::read_to_string(progress_path(target)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn save_progress(target: &Path, progress: &PartialDownload) -> Result<()> {
+    let path = progress_path(target);
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(progress)?)
+        .and_then(|_| std::fs::rename(&tmp_path, &path))
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Why streaming the file stopped early
+enum Interrupted {
+    /// The connection failed, trying again continues where it stopped
+    Network(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+/// Download file `id` of the peer to `target`, continuing an earlier attempt if one left its
+/// `.part` file behind. `on_progress` gets the bytes received so far and the size.
+pub async fn download_resumable(
+    client: &PeerClient,
+    id: &str,
+    target: &Path,
+    on_progress: impl Fn(u64, u64),
+) -> Result<()> {
+    let checksum = client.checksum(id).await?;
+    let mut progress = PartialDownload {
+        peer: client.base_url().to_string(),
+        id: id.to_string(),
+        name: checksum.name,
+        size: checksum.size,
+        modified: checksum.modified,
+        sha256: checksum.sha256,
+        received: 0,
+    };
+
+    let part = part_path(target);
+    let part_len = std::fs::metadata(&part).map(|metadata| metadata.len()).unwrap_or(0);
+    match load_progress(target) {
+        Some(saved) if saved.same_file(&progress) => progress.received = saved.received.min(part_len),
+        Some(_) => warn!("{:?} belongs to another version of the file, starting over", part),
+        None => {}
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&part)
+        .await
+        .with_context(|| format!("Failed to open {:?}", part))?;
+    // Anything after the saved progress may not have reached the disk intact
+    file.set_len(progress.received).await?;
+    save_progress(target, &progress)?;
+
+    let mut failures = 0;
+    while progress.received < progress.size {
+        on_progress(progress.received, progress.size);
+        match stream(client, &mut progress, &mut file, target, &on_progress).await {
+            Ok(()) => {}
+            Err(Interrupted::Fatal(e)) => return Err(e),
+            Err(Interrupted::Network(e)) => {
+                failures += 1;
+                if failures > DOWNLOAD_RETRIES {
+                    return Err(e.context(format!("Download interrupted, run again to continue from {:?}", part)));
+                }
+                warn!("Download of {} interrupted, retrying: {:#}", progress.name, e);
+                tokio::time::sleep(Duration::from_secs(failures as u64)).await;
+            }
+        }
+    }
+    file.sync_all().await?;
+    drop(file);
+    on_progress(progress.received, progress.size);
+
+    let hashed = part.clone();
+    let sha256 = tokio::task::spawn_blocking(move || hash_file(&hashed)).await??;
+    if sha256 != progress.sha256 {
+        let _ = std::fs::remove_file(&part);
+        let _ = std::fs::remove_file(progress_path(target));
+        bail!(
+            "Checksum mismatch for {}: expected {}, got {}. The download was discarded, run again to start over",
+            progress.name,
+            progress.sha256,
+            sha256
+        );
+    }
+
+    std::fs::rename(&part, target).with_context(|| format!("Failed to move the download to {:?}", target))?;
+    set_modified(target, progress.modified)?;
+    let _ = std::fs::remove_file(progress_path(target));
+    Ok(())
+}
+
+/// Append the rest of the file to `file`, saving progress along the way
+async fn stream(
+    client: &PeerClient,
+    progress: &mut PartialDownload,
+    file: &mut tokio::fs::File,
+    target: &Path,
+    on_progress: &impl Fn(u64, u64),
+) -> Result<(), Interrupted> {
+    let fatal = |e: anyhow::Error| Interrupted::Fatal(e);
+    let network = |e: reqwest::Error| Interrupted::Network(e.into());
+
+    let mut request = client.http.get(format!("{}/api/files/{}", client.base_url, progress.id));
+    if progress.received > 0 {
+        request = request.header(RANGE, format!("bytes={}-", progress.received));
+    }
+    let mut response = request.send().await.map_err(network)?;
+    if response.status().is_server_error() {
+        return Err(Interrupted::Network(anyhow!("HTTP {}", response.status())));
+    }
+    check_status(&response, "download file").map_err(fatal)?;
+
+    let modified = response
+        .headers()
+        .get(MODIFIED_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+        .map(|modified| modified.to_utc());
+    if modified.is_some_and(|modified| modified != progress.modified) {
+        return Err(fatal(anyhow!("{} changed on the peer while downloading, run again to start over", progress.name)));
+    }
+    if progress.received > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+        // The whole file is coming, so start the part file over
+        progress.received = 0;
+        file.set_len(0).await.map_err(|e| fatal(e.into()))?;
+    }
+    file.seek(std::io::SeekFrom::Start(progress.received)).await.map_err(|e| fatal(e.into()))?;
+
+    let mut saved = progress.received;
+    let result = loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(network(e)),
+        };
+        if let Err(e) = file.write_all(&chunk).await {
+            break Err(fatal(e.into()));
+        }
+        progress.received += chunk.len() as u64;
+        on_progress(progress.received, progress.size);
+        if progress.received - saved >= PROGRESS_INTERVAL {
+            if let Err(e) = checkpoint(file, target, progress).await {
+                break Err(fatal(e));
+            }
+            saved = progress.received;
+        }
+    };
+
+    checkpoint(file, target, progress).await.map_err(fatal)?;
+    if result.is_ok() && progress.received < progress.size {
+        return Err(Interrupted::Network(anyhow!("connection closed early")));
+    }
+    result
+}
+
+/// Get what was written onto the disk, then record how far it goes
+async fn checkpoint(file: &mut tokio::fs::File, target: &Path, progress: &PartialDownload) -> Result<()> {
+    file.flush().await?;
+    file.sync_data().await?;
+    save_progress(target, progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_progress_is_kept_next_to_the_part_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("movie.mkv");
+        assert_eq!(part_path(&target), temp_dir.path().join("movie.mkv.part"));
+        assert!(load_progress(&target).is_none());
+
+        let progress = PartialDownload {
+            peer: "http://10.0.0.2:8080".to_string(),
+            id: "4f0c".to_string(),
+            name: "movie.mkv".to_string(),
+            size: 1000,
+            modified: Utc::now(),
+            sha256: "ab".repeat(32),
+            received: 400,
+        };
+        save_progress(&target, &progress).unwrap();
+        let saved = load_progress(&target).unwrap();
+        assert_eq!(saved, progress);
+
+        let newer = PartialDownload { modified: progress.modified + chrono::Duration::seconds(1), ..progress.clone() };
+        assert!(saved.same_file(&PartialDownload { received: 0, ..progress }));
+        assert!(!saved.same_file(&newer));
+    }
+}
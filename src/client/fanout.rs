@@ -0,0 +1,382 @@
+//! Transfers involving several peers at once
+
+use anyhow::{bail, Context, Result};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tracing::{error, warn};
+
+use crate::client::PeerClient;
+use crate::core::collect::CollectStatus;
+use crate::core::identity::device_id;
+use crate::core::models::{DeviceInfo, FileAttributes, FileInfo};
+use crate::discovery::ServiceDiscovery;
+use crate::utils::file::{resolve_relative_path, sanitize_path_component, set_modified};
+
+/// How often to check whether a peer's owner answered a collection request
+const COLLECT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Target selector meaning every discovered device
+pub const ALL_PEERS: &str = "all";
+
+/// Whether resolving `selectors` needs an mDNS lookup, i.e. anything besides explicit `host:port` or URLs
+pub fn needs_discovery(selectors: &[String]) -> bool {
+    selectors.iter().any(|selector| !selector.contains(':'))
+}
+
+/// Turn `--to`/`--from` values into peer addresses.
+///
+/// `all` stands for every discovered device except this one (`own_id`). Other values pick a
+/// discovered device by name (case-insensitive) or id, or are taken as an address if none matches
+/// and they look like one.
+pub fn select_peers(selectors: &[String], discovered: &[DeviceInfo], own_id: &str) -> Result<Vec<String>> {
+    let mut peers: Vec<String> = Vec::new();
+    let mut add = |address: String| {
+        if !peers.contains(&address) {
+            peers.push(address);
+        }
+    };
+
+    for selector in selectors.iter().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if selector.eq_ignore_ascii_case(ALL_PEERS) {
+            discovered
+                .iter()
+                .filter(|device| device.id != own_id)
+                .for_each(|device| add(device_address(device)));
+            continue;
+        }
+
+        let matched: Vec<&DeviceInfo> = discovered
+            .iter()
+            .filter(|device| device.id == selector || device.name.eq_ignore_ascii_case(selector))
+            .collect();
+
+        if !matched.is_empty() {
+            matched.into_iter().for_each(|device| add(device_address(device)));
+        } else if selector.contains(':') || selector.contains('.') {
+            add(selector.to_string());
+        } else {
+            bail!("No device named {:?} found on the network", selector);
+        }
+    }
+
+    Ok(peers)
+}
+
+/// Resolve `--to`/`--from` values to peer clients, browsing the network only when needed
+pub async fn resolve_peers(selectors: &[String]) -> Result<Vec<PeerClient>> {
+    let discovered = if needs_discovery(selectors) {
+        ServiceDiscovery::discover().await?
+    } else {
+        Vec::new()
+    };
+
+    let peers = select_peers(selectors, &discovered, &device_id())?;
+    if peers.is_empty() {
+        bail!("No devices found");
+    }
+    peers.iter().map(|peer| PeerClient::new(peer)).collect()
+}
+
+/// Like [`resolve_peers`] for every selector on its own, so devices that cannot be found do not
+/// keep the others from being sent to
+pub async fn resolve_each(selectors: &[String]) -> Vec<(String, Result<Vec<PeerClient>>)> {
+    let discovered = if needs_discovery(selectors) {
+        match ServiceDiscovery::discover().await {
+            Ok(discovered) => discovered,
+            Err(e) => {
+                warn!("Failed to look for devices: {:#}", e);
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let own_id = device_id();
+    selectors
+        .iter()
+        .map(|selector| {
+            let clients = select_peers(std::slice::from_ref(selector), &discovered, &own_id).and_then(|peers| {
+                if peers.is_empty() {
+                    bail!("No devices found");
+                }
+                peers.iter().map(|peer| PeerClient::new(peer)).collect()
+            });
+            (selector.clone(), clients)
+        })
+        .collect()
+}
+
+fn device_address(device: &DeviceInfo) -> String {
+    if device.ip.contains(':') {
+        format!("[{}]:{}", device.ip, device.port)
+    } else {
+        format!("{}:{}", device.ip, device.port)
+    }
+}
+
+/// Bytes stored by every peer of a multi-peer send, for progress reports while it runs
+pub struct SendProgress {
+    /// Bytes every peer has to receive
+    pub per_peer: u64,
+    sent: Vec<AtomicU64>,
+    finished: AtomicUsize,
+}
+
+impl SendProgress {
+    fn new(peers: usize, per_peer: u64) -> Self {
+        Self {
+            per_peer,
+            sent: (0..peers).map(|_| AtomicU64::new(0)).collect(),
+            finished: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn peers(&self) -> usize {
+        self.sent.len()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.per_peer * self.peers() as u64
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.iter().map(|sent| sent.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Peers that are done, successfully or not
+    pub fn finished(&self) -> usize {
+        self.finished.load(Ordering::Relaxed)
+    }
+}
+
+/// A file to send, with its path relative to the peers' shares
+pub struct OutgoingFile {
+    pub path: String,
+    pub data: Vec<u8>,
+    /// Modification time and permissions for the peers to give their copies
+    pub attributes: FileAttributes,
+}
+
+/// Outcome of sending to one peer
+pub struct PeerOutcome {
+    pub peer: String,
+    /// Number of files stored, or why the send failed
+    pub result: Result<usize>,
+}
+
+/// Send the same files to several peers concurrently. Each peer gets several files at once when
+/// its link is slow to answer, see `ChunkPacer::streams`.
+///
+/// Progress can be watched through the returned `SendProgress` while the future runs.
+pub fn send_to_peers(
+    clients: Vec<PeerClient>,
+    files: Vec<OutgoingFile>,
+) -> (Arc<SendProgress>, impl std::future::Future<Output = Vec<PeerOutcome>>) {
+    let per_peer = files.iter().map(|file| file.data.len() as u64).sum();
+    let progress = Arc::new(SendProgress::new(clients.len(), per_peer));
+    let files = Arc::new(files);
+
+    let task_progress = progress.clone();
+    let transfer = async move {
+        let mut tasks = JoinSet::new();
+        for (index, client) in clients.into_iter().enumerate() {
+            let files = files.clone();
+            let progress = task_progress.clone();
+
+            tasks.spawn(async move {
+                let result = send_files(&client, &files, &progress.sent[index]).await;
+
+                progress.finished.fetch_add(1, Ordering::Relaxed);
+                (index, PeerOutcome { peer: client.base_url().to_string(), result })
+            });
+        }
+
+        let mut outcomes = Vec::new();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok(outcome) => outcomes.push(outcome),
+                Err(e) => error!("Send task failed: {}", e),
+            }
+        }
+        // Report in the order the peers were given
+        outcomes.sort_by_key(|(index, _)| *index);
+        outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+    };
+
+    (progress, transfer)
+}
+
+/// Upload `files` to one peer, as many at once as its pacer suggests, adding what the peer
+/// stored to `sent`
+async fn send_files(client: &PeerClient, files: &[OutgoingFile], sent: &AtomicU64) -> Result<usize> {
+    let mut pending = files.iter();
+    let mut in_flight = FuturesUnordered::new();
+    loop {
+        // Asked again whenever an upload finishes, the link may have changed meanwhile
+        while in_flight.len() < client.upload_streams() {
+            let Some(file) = pending.next() else { break };
+            in_flight.push(upload_counted(client, file, sent));
+        }
+        match in_flight.next().await {
+            Some(result) => {
+                result?;
+            }
+            None => return Ok(files.len()),
+        }
+    }
+}
+
+/// Upload one file, keeping `sent` up to date as the peer stores it
+async fn upload_counted(client: &PeerClient, file: &OutgoingFile, sent: &AtomicU64) -> Result<FileInfo> {
+    let stored = AtomicU64::new(0);
+    client
+        .upload_with_progress(&file.path, file.data.clone(), file.attributes.clone(), |offset| {
+            let previous = stored.swap(offset, Ordering::Relaxed);
+            if offset >= previous {
+                sent.fetch_add(offset - previous, Ordering::Relaxed);
+            } else {
+                sent.fetch_sub(previous - offset, Ordering::Relaxed);
+            }
+        })
+        .await
+}
+
+/// Outcome of collecting from one peer
+pub struct CollectOutcome {
+    pub peer: String,
+    /// Files saved locally, or why nothing was collected
+    pub result: Result<Vec<PathBuf>>,
+}
+
+/// Ask several peers for the files matching `pattern` and download what each owner approves.
+///
+/// Every peer's files land in `into/<device name>/`, keeping their paths within the share.
+/// Peers whose owner does not answer within `timeout` are given up on.
+pub async fn collect_from_peers(
+    clients: Vec<PeerClient>,
+    requester: &str,
+    pattern: &str,
+    into: &Path,
+    timeout: Duration,
+) -> Vec<CollectOutcome> {
+    let mut tasks = JoinSet::new();
+    for (index, client) in clients.into_iter().enumerate() {
+        let requester = requester.to_string();
+        let pattern = pattern.to_string();
+        let into = into.to_path_buf();
+
+        tasks.spawn(async move {
+            let result = collect_from_peer(&client, &requester, &pattern, &into, timeout).await;
+            (index, CollectOutcome { peer: client.base_url().to_string(), result })
+        });
+    }
+
+    let mut outcomes = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(outcome) => outcomes.push(outcome),
+            Err(e) => error!("Collect task failed: {}", e),
+        }
+    }
+    outcomes.sort_by_key(|(index, _)| *index);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+async fn collect_from_peer(
+    client: &PeerClient,
+    requester: &str,
+    pattern: &str,
+    into: &Path,
+    timeout: Duration,
+) -> Result<Vec<PathBuf>> {
+    let device = client.device_info().await?;
+    let mut request = client.request_collection(requester, pattern).await?;
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    while request.status == CollectStatus::Pending {
+        if tokio::time::Instant::now() >= deadline {
+            bail!("{} did not answer the request in time", device.name);
+        }
+        tokio::time::sleep(COLLECT_POLL_INTERVAL).await;
+        request = client.collection(&request.id).await?;
+    }
+    if request.status == CollectStatus::Declined {
+        bail!("{} declined the request", device.name);
+    }
+
+    let target = into.join(sanitize_path_component(&device.name));
+    let mut saved = Vec::new();
+    for file in &request.files {
+        // The peer chooses these names, so keep them inside the target directory
+        let Some(path) = resolve_relative_path(&target, &file.name) else {
+            warn!("Skipping {:?} from {}: invalid path", file.name, device.name);
+            continue;
+        };
+
+        let download = client.download(&file.id.to_string()).await?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, download.data)
+            .await
+            .with_context(|| format!("Failed to save {:?}", path))?;
+        if let Some(modified) = download.modified {
+            if let Err(e) = set_modified(&path, modified) {
+                warn!("Failed to set modification time of {:?}: {}", path, e);
+            }
+        }
+        saved.push(path);
+    }
+
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str, name: &str, ip: &str) -> DeviceInfo {
+        DeviceInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            ip: ip.to_string(),
+            port: 8080,
+            os: "linux".to_string(),
+            tls: false,
+        }
+    }
+
+    #[test]
+    fn test_select_peers() {
+        let discovered = vec![
+            device("me", "Desk", "192.168.1.2"),
+            device("a", "Laptop", "192.168.1.3"),
+            device("b", "Phone", "192.168.1.4"),
+            // mDNS reports devices once per address family and interface
+            device("b", "Phone", "192.168.1.4"),
+        ];
+        let select = |selectors: &[&str]| {
+            let selectors: Vec<String> = selectors.iter().map(|s| s.to_string()).collect();
+            select_peers(&selectors, &discovered, "me")
+        };
+
+        assert_eq!(select(&["all"]).unwrap(), vec!["192.168.1.3:8080", "192.168.1.4:8080"]);
+        assert_eq!(
+            select(&["phone", "10.0.0.9:9000"]).unwrap(),
+            vec!["192.168.1.4:8080", "10.0.0.9:9000"]
+        );
+        assert_eq!(select(&["nas.local"]).unwrap(), vec!["nas.local"]);
+        assert!(select(&["Tablet"]).is_err());
+    }
+
+    #[test]
+    fn test_needs_discovery() {
+        assert!(!needs_discovery(&["10.0.0.9:8080".to_string(), "http://nas".to_string()]));
+        assert!(needs_discovery(&["all".to_string()]));
+    }
+}
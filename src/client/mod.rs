@@ -0,0 +1,569 @@
+//! HTTP client for talking to another RustDrop instance
+
+pub mod agent;
+pub mod courier;
+pub mod download;
+pub mod fanout;
+pub mod outgoing;
+pub mod pacing;
+pub mod proxy;
+pub mod queue;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::StatusCode;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{instrument, warn};
+
+use self::agent::user_agent;
+use self::pacing::ChunkPacer;
+use self::proxy::with_proxies;
+use crate::core::collect::CollectRequest;
+use crate::core::capabilities::{Capabilities, TransferStrategy, UploadMethod};
+use crate::core::clients::PeerAgent;
+use crate::core::identity::{client_token, device_id};
+use crate::core::models::{DeviceInfo, FileAttributes, FileInfo};
+use crate::core::pause::TransferPause;
+use crate::core::uploads::UploadSession;
+use crate::web::handlers::api::MODIFIED_HEADER;
+use crate::web::handlers::instance::CONTROL_HEADER;
+use crate::web::handlers::manifest::FileChecksum;
+use crate::utils::sparse::next_run;
+use crate::web::handlers::uploads::{UploadProgress, UPLOAD_OFFSET_HEADER, UPLOAD_ZEROES_HEADER};
+use crate::web::session::{CLIENT_HEADER, DEVICE_HEADER};
+
+const UPLOAD_RETRIES: u32 = 5;
+
+/// How long to wait for a paused peer whose `Retry-After` is not a number of seconds
+const PAUSED_PEER_RETRY: Duration = Duration::from_secs(5);
+
+/// Contents of a downloaded file
+#[derive(Debug)]
+pub struct Download {
+    pub data: Vec<u8>,
+    /// Modification time of the file on the peer, if it told us
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Client for the REST API of a remote RustDrop device
+#[derive(Clone)]
+pub struct PeerClient {
+    base_url: String,
+    #[cfg_attr(not(feature = "quic"), allow(dead_code))]
+    token: String,
+    http: reqwest::Client,
+    /// Holds uploads between chunks while paused
+    pause: Option<Arc<TransferPause>>,
+    /// Chunk sizes learned from earlier uploads, shared by clones so every file sent to the
+    /// peer benefits
+    pacer: Arc<Mutex<ChunkPacer>>,
+    /// What the peer accepts, asked once and shared by clones
+    capabilities: Arc<tokio::sync::OnceCell<Capabilities>>,
+    /// Carries downloads and uploads once `use_quic` succeeded
+    #[cfg(feature = "quic")]
+    quic: Option<std::sync::Arc<crate::quic::QuicClient>>,
+}
+
+impl PeerClient {
+    /// Create a client for a peer given as `host:port` or a full `http(s)://` URL
+    pub fn new(peer: &str) -> Result<Self> {
+        Self::with_token(peer, &client_token())
+    }
+
+    /// Create a client that identifies itself to the peer with `token`
+    pub fn with_token(peer: &str, token: &str) -> Result<Self> {
+        let base_url = normalize_peer_url(peer)?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            DEVICE_HEADER,
+            HeaderValue::from_str(token).context("Invalid client token")?,
+        );
+        let device = device_id();
+        headers.insert(
+            CLIENT_HEADER,
+            HeaderValue::from_str(&PeerAgent::this_device(&device).to_string()).context("Invalid device id")?,
+        );
+        let http = with_proxies(reqwest::Client::builder())
+            .user_agent(user_agent(&device))
+            .default_headers(headers)
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        Ok(Self {
+            base_url,
+            token: token.to_string(),
+            http,
+            pause: None,
+            pacer: Arc::default(),
+            capabilities: Arc::default(),
+            #[cfg(feature = "quic")]
+            quic: None,
+        })
+    }
+
+    /// Hold uploads whenever `pause` is set
+    pub fn with_pause(mut self, pause: Arc<TransferPause>) -> Self {
+        self.pause = Some(pause);
+        self
+    }
+
+    /// How many files to upload to the peer at once, judging from the uploads so far
+    pub fn upload_streams(&self) -> usize {
+        self.pacer.lock().unwrap().streams()
+    }
+
+    async fn wait_if_paused(&self) {
+        if let Some(pause) = &self.pause {
+            pause.wait_until_resumed().await;
+        }
+    }
+
+    /// What the peer accepts. Peers from before `/api/capabilities` get what they accepted then.
+    pub async fn capabilities(&self) -> Result<Capabilities> {
+        let capabilities = self
+            .capabilities
+            .get_or_try_init(|| async {
+                let response = self
+                    .http
+                    .get(format!("{}/api/capabilities", self.base_url))
+                    .send()
+                    .await
+                    .with_context(|| format!("Failed to reach {}", self.base_url))?;
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Ok(Capabilities::legacy());
+                }
+                check_status(&response, "describe what it accepts")?;
+                Ok::<_, anyhow::Error>(response.json().await?)
+            })
+            .await?;
+        Ok(capabilities.clone())
+    }
+
+    /// How to push files to the peer, or why that cannot work
+    pub async fn strategy(&self) -> Result<TransferStrategy> {
+        Ok(TransferStrategy::negotiate(&self.capabilities().await?)?)
+    }
+
+    /// Move downloads and uploads onto the peer's QUIC transport, returning false if it has none
+    #[cfg(feature = "quic")]
+    pub async fn use_quic(&mut self) -> Result<bool> {
+        let response = self
+            .http
+            .get(format!("{}/api/quic", self.base_url))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.base_url))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        check_status(&response, "describe its QUIC transport")?;
+        let info: crate::core::models::QuicInfo = response.json().await?;
+
+        let url = reqwest::Url::parse(&self.base_url)?;
+        let host = url.host_str().context("Peer address has no host")?;
+        let addr = tokio::net::lookup_host((host, info.port))
+            .await?
+            .next()
+            .with_context(|| format!("Failed to resolve {}", host))?;
+
+        let client = crate::quic::QuicClient::connect(addr, &info.fingerprint, &self.token).await?;
+        self.quic = Some(std::sync::Arc::new(client));
+        Ok(true)
+    }
+
+    /// Enter the access code shown on the peer, so it lets this device in from now on
+    pub async fn pair(&self, code: &str) -> Result<()> {
+        let response = self
+            .http
+            .post(format!("{}/api/access", self.base_url))
+            .json(&json!({ "code": code }))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.base_url))?;
+
+        if response.status() == StatusCode::FORBIDDEN {
+            bail!("The peer rejected the code, it may have expired");
+        }
+        check_status(&response, "accept the access code")
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Every file in the peer's share
+    #[instrument(skip(self), fields(peer = %self.base_url))]
+    pub async fn list_files(&self) -> Result<Vec<FileInfo>> {
+        let response = self
+            .http
+            .get(format!("{}/api/files", self.base_url))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.base_url))?;
+
+        check_status(&response, "list files")?;
+        Ok(response.json().await?)
+    }
+
+    #[instrument(skip(self), fields(peer = %self.base_url))]
+    pub async fn download(&self, id: &str) -> Result<Download> {
+        #[cfg(feature = "quic")]
+        if let Some(quic) = &self.quic {
+            return quic.download(id).await;
+        }
+
+        let response = self
+            .http
+            .get(format!("{}/api/files/{}", self.base_url, id))
+            .send()
+            .await?;
+
+        check_status(&response, "download file")?;
+        let modified = response
+            .headers()
+            .get(MODIFIED_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .map(|modified| modified.to_utc());
+        let data = response.bytes().await?.to_vec();
+        Ok(Download { data, modified })
+    }
+
+    /// The attributes the peer keeps for a file, including extended attributes if the peer
+    /// preserves them
+    pub async fn attributes(&self, id: &str) -> Result<FileAttributes> {
+        let response = self
+            .http
+            .get(format!("{}/api/files/{}/attributes", self.base_url, id))
+            .send()
+            .await?;
+
+        check_status(&response, "get file attributes")?;
+        Ok(response.json().await?)
+    }
+
+    /// SHA-256 of a file on the peer, with the size and modification time it was taken at
+    pub async fn checksum(&self, id: &str) -> Result<FileChecksum> {
+        self.capabilities().await?.hash_algorithm()?;
+        let response = self
+            .http
+            .get(format!("{}/api/files/{}/checksum", self.base_url, id))
+            .send()
+            .await?;
+
+        check_status(&response, "hash file")?;
+        Ok(response.json().await?)
+    }
+
+    /// Upload a file to `path` (relative to the peer's share), replacing any existing file.
+    /// The peer gives the stored file the given `attributes`.
+    ///
+    /// The data is sent in chunks through a resumable upload session, so a dropped connection
+    /// only costs the chunk in flight. Chunks grow on fast links and shrink on slow or lossy ones.
+    /// Long runs of zeroes, like the holes of disk images, are only announced rather than sent.
+    /// Peers without upload sessions get the file in one request, see [`TransferStrategy`].
+    /// Files larger than the peer accepts are refused before anything is sent.
+    pub async fn upload(&self, path: &str, data: Vec<u8>, attributes: FileAttributes) -> Result<FileInfo> {
+        self.upload_with_progress(path, data, attributes, |_| {}).await
+    }
+
+    /// Like `upload`, calling `on_progress` with the number of bytes the peer has stored so far
+    #[instrument(name = "upload", skip(self, data, attributes, on_progress), fields(peer = %self.base_url, size = data.len()))]
+    pub async fn upload_with_progress(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        attributes: FileAttributes,
+        on_progress: impl Fn(u64),
+    ) -> Result<FileInfo> {
+        let strategy = self.strategy().await?;
+        strategy.check_size(path, data.len() as u64)?;
+
+        #[cfg(feature = "quic")]
+        if let Some(quic) = &self.quic {
+            self.wait_if_paused().await;
+            let size = data.len() as u64;
+            let file = quic.upload(path, data, attributes).await?;
+            on_progress(size);
+            return Ok(file);
+        }
+
+        if strategy.method == UploadMethod::Multipart {
+            self.wait_if_paused().await;
+            let file = self.upload_multipart(path, data, &attributes).await?;
+            on_progress(file.size);
+            return Ok(file);
+        }
+
+        let (dir, name) = split_path(path);
+        let mut request = serde_json::to_value(&attributes)?;
+        request["name"] = json!(name);
+        request["size"] = json!(data.len());
+        request["dir"] = json!(dir);
+        let started = Instant::now();
+        let response = self
+            .http
+            .post(format!("{}/api/uploads", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+        check_status(&response, "start upload")?;
+        self.pacer.lock().unwrap().round_trip(started.elapsed());
+        let session: UploadSession = response.json().await?;
+        // Sending a file means the peer's copy of that name should become this one
+        let url = format!("{}/api/uploads/{}?conflict=replace", self.base_url, session.id);
+
+        let mut offset = session.offset;
+        let mut failures = 0;
+        // Also cleared if the peer predates zero chunks and ignores them
+        let mut sparse = strategy.sparse;
+        loop {
+            self.wait_if_paused().await;
+            let chunk_size = self.pacer.lock().unwrap().chunk_size();
+            let (end, zeroes) = if sparse && (offset as usize) < data.len() {
+                next_run(&data, offset as usize, chunk_size)
+            } else {
+                ((offset as usize + chunk_size).min(data.len()), false)
+            };
+            let request = self.http.patch(&url).header(UPLOAD_OFFSET_HEADER, offset);
+            let request = if zeroes {
+                request.header(UPLOAD_ZEROES_HEADER, end as u64 - offset)
+            } else {
+                request.body(data[offset as usize..end].to_vec())
+            };
+            let started = Instant::now();
+            let result = request.send().await;
+
+            // 409 means the peer is at a different offset, its answer says where
+            let response = match result {
+                Ok(response) if response.status().is_success() || response.status() == StatusCode::CONFLICT => response,
+                // The peer paused its transfers and says when to ask again, which is not a failure
+                Ok(response) if response.status() == StatusCode::SERVICE_UNAVAILABLE
+                    && response.headers().contains_key(reqwest::header::RETRY_AFTER) =>
+                {
+                    tokio::time::sleep(retry_after(&response)).await;
+                    continue;
+                }
+                Ok(response) if response.status().is_client_error() => {
+                    bail!("Peer failed to upload file: HTTP {}", response.status());
+                }
+                failed => {
+                    self.pacer.lock().unwrap().failed();
+                    failures += 1;
+                    if failures > UPLOAD_RETRIES {
+                        return Err(match failed {
+                            Ok(response) => anyhow!("Peer failed to upload file: HTTP {}", response.status()),
+                            Err(e) => e.into(),
+                        });
+                    }
+                    warn!("Upload of {} interrupted, retrying", path);
+                    tokio::time::sleep(Duration::from_secs(failures as u64)).await;
+                    // Ask where the peer got to, some of the chunk may have arrived
+                    let response = self.http.get(&url).send().await?;
+                    check_status(&response, "resume upload")?;
+                    offset = response.json::<UploadSession>().await?.offset;
+                    continue;
+                }
+            };
+
+            let stored = response.status().is_success();
+            let progress: UploadProgress = response.json().await?;
+            // A conflict only says where the peer got to, the chunk was not stored
+            if stored {
+                let mut pacer = self.pacer.lock().unwrap();
+                if zeroes {
+                    pacer.round_trip(started.elapsed());
+                } else {
+                    pacer.sent(end - offset as usize, started.elapsed());
+                }
+            }
+            on_progress(progress.offset);
+            if let Some(file) = progress.file {
+                return Ok(file);
+            }
+            if zeroes && progress.offset == offset {
+                sparse = false;
+            }
+            offset = progress.offset;
+        }
+    }
+
+    /// Send a whole file in one multipart request, replacing any file of the same name. Of the
+    /// attributes, only the modification time gets through.
+    async fn upload_multipart(&self, path: &str, data: Vec<u8>, attributes: &FileAttributes) -> Result<FileInfo> {
+        let (dir, name) = split_path(path);
+        let form = reqwest::multipart::Form::new()
+            .part("file", reqwest::multipart::Part::bytes(data).file_name(name.to_string()));
+        let mut request = self
+            .http
+            .post(format!("{}/api/files", self.base_url))
+            .query(&[("conflict", "replace")])
+            .multipart(form);
+        if let Some(dir) = dir {
+            request = request.query(&[("dir", dir)]);
+        }
+        if let Some(modified) = attributes.modified {
+            request = request.header(MODIFIED_HEADER, modified.to_rfc3339());
+        }
+
+        let response = request.send().await?;
+        check_status(&response, "upload file")?;
+        Ok(response.json().await?)
+    }
+
+    /// Whether a RustDrop server answers at the peer's address
+    pub async fn is_rustdrop(&self) -> bool {
+        let response = self
+            .http
+            .get(format!("{}/api/health", self.base_url))
+            .timeout(Duration::from_secs(2))
+            .send()
+            .await;
+        let Ok(health) = response.and_then(|response| response.error_for_status()) else {
+            return false;
+        };
+        health
+            .json::<serde_json::Value>()
+            .await
+            .is_ok_and(|health| health["service"] == "rustdrop")
+    }
+
+    /// Ask an instance on this host to shut down, proving it was started by the same user
+    /// with the control token it published in the instance registry
+    pub async fn request_shutdown(&self, control_token: &str) -> Result<()> {
+        let response = self
+            .http
+            .post(format!("{}/api/instance/shutdown", self.base_url))
+            .header(CONTROL_HEADER, control_token)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.base_url))?;
+
+        check_status(&response, "shut down")
+    }
+
+    pub async fn device_info(&self) -> Result<DeviceInfo> {
+        let response = self
+            .http
+            .get(format!("{}/api/device", self.base_url))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.base_url))?;
+
+        check_status(&response, "describe itself")?;
+        Ok(response.json().await?)
+    }
+
+    /// Ask the peer's owner for the files matching `pattern`
+    pub async fn request_collection(&self, requester: &str, pattern: &str) -> Result<CollectRequest> {
+        let response = self
+            .http
+            .post(format!("{}/api/collect", self.base_url))
+            .json(&json!({ "requester": requester, "pattern": pattern }))
+            .send()
+            .await?;
+
+        check_status(&response, "accept the collection request")?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn collection(&self, id: &str) -> Result<CollectRequest> {
+        let response = self
+            .http
+            .get(format!("{}/api/collect/{}", self.base_url, id))
+            .send()
+            .await?;
+
+        check_status(&response, "report the collection request")?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        self.bulk(json!([{ "op": "delete", "id": id }])).await
+    }
+
+    /// Move a file to `to` (relative to the peer's share)
+    pub async fn rename(&self, id: &str, to: &str) -> Result<()> {
+        self.bulk(json!([{ "op": "move", "id": id, "to": to }])).await
+    }
+
+    async fn bulk(&self, operations: serde_json::Value) -> Result<()> {
+        let response = self
+            .http
+            .post(format!("{}/api/files/bulk", self.base_url))
+            .json(&json!({ "operations": operations }))
+            .send()
+            .await?;
+
+        check_status(&response, "update files")
+    }
+}
+
+/// Seconds to wait according to a response's `Retry-After` header
+fn retry_after(response: &reqwest::Response) -> Duration {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(PAUSED_PEER_RETRY)
+}
+
+/// Directory (if any) and name of a path relative to a share
+fn split_path(path: &str) -> (Option<&str>, &str) {
+    match path.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, path),
+    }
+}
+
+fn check_status(response: &reqwest::Response, action: &str) -> Result<()> {
+    if response.status() == StatusCode::UNAUTHORIZED {
+        bail!(
+            "Peer failed to {}: it requires an access code, run `rustdrop pair {} <code>` first",
+            action,
+            response.url().authority()
+        );
+    }
+    if !response.status().is_success() {
+        bail!("Peer failed to {}: HTTP {}", action, response.status());
+    }
+    Ok(())
+}
+
+/// Turn `host`, `host:port` or a URL into a base URL without trailing slash
+pub fn normalize_peer_url(peer: &str) -> Result<String> {
+    let peer = peer.trim().trim_end_matches('/');
+    if peer.is_empty() {
+        bail!("Peer address is empty");
+    }
+
+    let url = if peer.starts_with("http://") || peer.starts_with("https://") {
+        peer.to_string()
+    } else if peer.contains(':') {
+        format!("http://{}", peer)
+    } else {
+        format!("http://{}:8080", peer)
+    };
+
+    reqwest::Url::parse(&url).with_context(|| format!("Invalid peer address: {}", peer))?;
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_peer_url() {
+        assert_eq!(normalize_peer_url("192.168.1.5").unwrap(), "http://192.168.1.5:8080");
+        assert_eq!(normalize_peer_url("laptop.local:9000").unwrap(), "http://laptop.local:9000");
+        assert_eq!(normalize_peer_url("https://nas:8443/").unwrap(), "https://nas:8443");
+        assert!(normalize_peer_url("  ").is_err());
+    }
+}
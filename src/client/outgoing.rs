@@ -0,0 +1,212 @@
+//! Sends waiting for their recipient to come back, queued by `rustdrop send --queue` and
+//! delivered by `rustdrop send --daemon`.
+//!
+//! Each send keeps a copy of its files in a directory of its own under the identity directory,
+//! so the originals can change or go away in the meantime.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::client::fanout::{resolve_peers, OutgoingFile};
+use crate::core::identity::identity_dir;
+use crate::core::instances::process_running;
+use crate::core::models::FileAttributes;
+
+const OUTGOING_DIR: &str = "outgoing";
+const SEND_FILE: &str = "send.json";
+const DAEMON_FILE: &str = "daemon.pid";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedFile {
+    /// Path on the recipient, relative to its share
+    pub path: String,
+    pub attributes: FileAttributes,
+}
+
+/// Files waiting for one recipient
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedSend {
+    pub id: String,
+    /// Recipient as given to `--to`, looked up again on every attempt
+    pub peer: String,
+    pub files: Vec<QueuedFile>,
+    pub created: DateTime<Utc>,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl QueuedSend {
+    pub fn size(&self, queue: &OutgoingQueue) -> u64 {
+        (0..self.files.len())
+            .filter_map(|index| std::fs::metadata(queue.data_path(&self.id, index)).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+}
+
+/// The sends of this user waiting for delivery
+pub struct OutgoingQueue {
+    dir: PathBuf,
+}
+
+impl OutgoingQueue {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The queue in the identity directory, None on systems without one
+    pub fn open_default() -> Option<Self> {
+        identity_dir().map(|dir| Self::new(dir.join(OUTGOING_DIR)))
+    }
+
+    fn data_path(&self, id: &str, index: usize) -> PathBuf {
+        self.dir.join(id).join(index.to_string())
+    }
+
+    /// Queue `files` for `peer`
+    pub fn add(&self, peer: &str, files: &[OutgoingFile]) -> Result<QueuedSend> {
+        let send = QueuedSend {
+            id: Uuid::new_v4().to_string(),
+            peer: peer.to_string(),
+            files: files
+                .iter()
+                .map(|file| QueuedFile { path: file.path.clone(), attributes: file.attributes.clone() })
+                .collect(),
+            created: Utc::now(),
+            attempts: 0,
+            last_error: None,
+        };
+        let dir = self.dir.join(&send.id);
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        for (index, file) in files.iter().enumerate() {
+            let path = self.data_path(&send.id, index);
+            std::fs::write(&path, &file.data).with_context(|| format!("Failed to write {:?}", path))?;
+        }
+        // Written last, so a send interrupted while being queued is never picked up
+        self.update(&send)?;
+        Ok(send)
+    }
+
+    /// Every queued send, oldest first
+    pub fn list(&self) -> Vec<QueuedSend> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut sends: Vec<QueuedSend> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| std::fs::read_to_string(entry.path().join(SEND_FILE)).ok())
+            .filter_map(|content| serde_json::from_str(&content).ok())
+            .collect();
+        sends.sort_by_key(|send| send.created);
+        sends
+    }
+
+    /// Record how the latest attempt went
+    pub fn update(&self, send: &QueuedSend) -> Result<()> {
+        let path = self.dir.join(&send.id).join(SEND_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(send)?)
+            .and_then(|_| std::fs::rename(&tmp_path, &path))
+            .with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    pub fn remove(&self, send: &QueuedSend) -> Result<()> {
+        let dir = self.dir.join(&send.id);
+        std::fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {:?}", dir))
+    }
+
+    /// Try to deliver a send to every device its recipient stands for, returning the number of
+    /// files each received
+    pub async fn deliver(&self, send: &QueuedSend) -> Result<usize> {
+        let clients = resolve_peers(std::slice::from_ref(&send.peer)).await?;
+        for client in &clients {
+            if !client.is_rustdrop().await {
+                bail!("{} is not reachable", client.base_url());
+            }
+        }
+        for client in &clients {
+            for (index, file) in send.files.iter().enumerate() {
+                let path = self.data_path(&send.id, index);
+                let data = tokio::fs::read(&path)
+                    .await
+                    .with_context(|| format!("Failed to read {:?}", path))?;
+                client.upload(&file.path, data, file.attributes.clone()).await?;
+            }
+        }
+        Ok(send.files.len())
+    }
+
+    /// Become the one process delivering this queue, None if another one already is
+    pub fn claim_daemon(&self) -> Result<Option<DaemonClaim>> {
+        let path = self.dir.join(DAEMON_FILE);
+        let running = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok())
+            .is_some_and(|pid| pid != std::process::id() && process_running(pid));
+        if running {
+            return Ok(None);
+        }
+        std::fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {:?}", self.dir))?;
+        std::fs::write(&path, std::process::id().to_string()).with_context(|| format!("Failed to write {:?}", path))?;
+        Ok(Some(DaemonClaim { path }))
+    }
+
+    /// Whether a daemon is delivering this queue
+    pub fn daemon_running(&self) -> bool {
+        std::fs::read_to_string(self.dir.join(DAEMON_FILE))
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok())
+            .is_some_and(process_running)
+    }
+}
+
+/// Held by the daemon delivering a queue, released when it exits
+pub struct DaemonClaim {
+    path: PathBuf,
+}
+
+impl Drop for DaemonClaim {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sends_wait_in_the_queue() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue = OutgoingQueue::new(temp_dir.path().join("outgoing"));
+        assert!(queue.list().is_empty());
+
+        let file = |path: &str, data: &str| OutgoingFile {
+            path: path.to_string(),
+            data: data.as_bytes().to_vec(),
+            attributes: FileAttributes::default(),
+        };
+        let first = queue.add("laptop", &[file("notes.md", "notes"), file("docs/plan.pdf", "plan")]).unwrap();
+        let second = queue.add("10.0.0.2:8080", &[file("photo.jpg", "jpeg")]).unwrap();
+        assert_eq!(queue.list(), vec![first.clone(), second.clone()]);
+        assert_eq!(first.size(&queue), 9);
+
+        let mut failed = first.clone();
+        failed.attempts = 1;
+        failed.last_error = Some("laptop is not reachable".to_string());
+        queue.update(&failed).unwrap();
+        queue.remove(&second).unwrap();
+        assert_eq!(queue.list(), vec![failed]);
+
+        let claim = queue.claim_daemon().unwrap().unwrap();
+        assert!(queue.daemon_running());
+        drop(claim);
+        assert!(!queue.daemon_running());
+    }
+}
@@ -0,0 +1,160 @@
+//! Sizing upload chunks, and choosing how many files go out at once, from the throughput and
+//! round-trip time measured during a transfer. Gigabit Ethernet ends up with large chunks that
+//! keep per-request overhead low, congested Wi-Fi with small ones that cost little to retry.
+
+use std::time::Duration;
+
+pub const MIN_CHUNK_SIZE: usize = 256 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Chunks start small and grow once the link proved fast
+const INITIAL_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How long one chunk should take: long enough that the round trip between chunks hardly
+/// matters, short enough that a dropped chunk is cheap to send again
+const TARGET_CHUNK_TIME: Duration = Duration::from_secs(2);
+
+/// Weight of the newest sample in the throughput average
+const THROUGHPUT_WEIGHT: f64 = 0.3;
+
+pub const MAX_STREAMS: usize = 4;
+
+/// Chunks that have to succeed in a row after a failure before files go out in parallel again
+const CALM_CHUNKS: u32 = 4;
+
+/// What the transfers to one peer have measured so far
+#[derive(Debug, Clone)]
+pub struct ChunkPacer {
+    chunk_size: usize,
+    /// Bytes per second, averaged over the recent chunks
+    throughput: Option<f64>,
+    /// Shortest round trip seen
+    rtt: Option<Duration>,
+    /// Chunks sent without failure since the last one failed
+    calm: u32,
+}
+
+impl Default for ChunkPacer {
+    fn default() -> Self {
+        Self {
+            chunk_size: INITIAL_CHUNK_SIZE,
+            throughput: None,
+            rtt: None,
+            calm: CALM_CHUNKS,
+        }
+    }
+}
+
+impl ChunkPacer {
+    /// Bytes to send in the next chunk
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn throughput(&self) -> Option<f64> {
+        self.throughput
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Note how long a request without a body took
+    pub fn round_trip(&mut self, elapsed: Duration) {
+        self.rtt = Some(self.rtt.map_or(elapsed, |rtt| rtt.min(elapsed)));
+    }
+
+    /// Note that a chunk of `bytes` was stored by the peer `elapsed` after it was sent
+    pub fn sent(&mut self, bytes: usize, elapsed: Duration) {
+        self.calm = self.calm.saturating_add(1);
+        // Mostly latency, says nothing about the bandwidth
+        let transfer_time = elapsed.saturating_sub(self.rtt.unwrap_or_default());
+        if bytes < MIN_CHUNK_SIZE || transfer_time.is_zero() {
+            self.round_trip(elapsed);
+            return;
+        }
+
+        let sample = bytes as f64 / transfer_time.as_secs_f64();
+        let throughput = match self.throughput {
+            Some(average) => average + THROUGHPUT_WEIGHT * (sample - average),
+            None => sample,
+        };
+        self.throughput = Some(throughput);
+
+        let target = (throughput * TARGET_CHUNK_TIME.as_secs_f64()) as usize;
+        // At most doubling per chunk, so one lucky sample does not make the next chunk huge
+        self.chunk_size = target.min(self.chunk_size * 2).clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
+    }
+
+    /// Note that a chunk did not make it, halving the next one
+    pub fn failed(&mut self) {
+        self.calm = 0;
+        self.chunk_size = (self.chunk_size / 2).max(MIN_CHUNK_SIZE);
+    }
+
+    /// How many files to upload at once. A single upload waits a round trip between chunks,
+    /// which on a slow-answering link leaves it idle much of the time; parallel uploads fill
+    /// those gaps. After failures, which on Wi-Fi mostly mean congestion, files go one by one.
+    pub fn streams(&self) -> usize {
+        let (Some(rtt), Some(throughput)) = (self.rtt, self.throughput) else {
+            return 1;
+        };
+        if self.calm < CALM_CHUNKS {
+            return 1;
+        }
+        let chunk_time = self.chunk_size as f64 / throughput;
+        let idle = rtt.as_secs_f64() / (chunk_time + rtt.as_secs_f64());
+        // Idle a tenth of the time or less is not worth another stream
+        if idle <= 0.1 {
+            return 1;
+        }
+        ((1.0 / (1.0 - idle)).ceil() as usize).clamp(1, MAX_STREAMS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: f64 = 1024.0 * 1024.0;
+
+    /// Feed chunks over a link of `throughput` bytes per second and `rtt`
+    fn run(pacer: &mut ChunkPacer, throughput: f64, rtt: Duration, chunks: usize) {
+        pacer.round_trip(rtt);
+        for _ in 0..chunks {
+            let size = pacer.chunk_size();
+            pacer.sent(size, rtt + Duration::from_secs_f64(size as f64 / throughput));
+        }
+    }
+
+    #[test]
+    fn test_fast_links_get_large_chunks() {
+        let mut pacer = ChunkPacer::default();
+        run(&mut pacer, 110.0 * MB, Duration::from_micros(500), 10);
+        assert_eq!(pacer.chunk_size(), MAX_CHUNK_SIZE);
+        assert_eq!(pacer.streams(), 1);
+    }
+
+    #[test]
+    fn test_slow_links_get_small_chunks() {
+        let mut pacer = ChunkPacer::default();
+        run(&mut pacer, 400.0 * 1024.0, Duration::from_millis(20), 10);
+        let size = pacer.chunk_size();
+        assert!((MIN_CHUNK_SIZE..=1024 * 1024).contains(&size), "{}", size);
+
+        pacer.failed();
+        assert_eq!(pacer.chunk_size(), (size / 2).max(MIN_CHUNK_SIZE));
+    }
+
+    #[test]
+    fn test_distant_links_send_in_parallel_until_failures() {
+        let mut pacer = ChunkPacer::default();
+        run(&mut pacer, 20.0 * MB, Duration::from_secs(1), 10);
+        assert!(pacer.streams() > 1);
+
+        pacer.failed();
+        assert_eq!(pacer.streams(), 1);
+        run(&mut pacer, 20.0 * MB, Duration::from_secs(1), CALM_CHUNKS as usize);
+        assert!(pacer.streams() > 1);
+    }
+}
@@ -0,0 +1,85 @@
+//! Proxies for calls to other devices. Behind a corporate proxy, `HTTP_PROXY`, `HTTPS_PROXY`
+//! and `ALL_PROXY` (or their lower case forms) carry calls to peers out of the network, while
+//! peers on the local network, which the proxy cannot reach, are always called directly, along
+//! with whatever `NO_PROXY` lists.
+
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+use tracing::warn;
+
+/// Hosts never reached through a proxy: this host, mDNS names, private, shared and link-local
+/// addresses
+const LAN_HOSTS: &str = "localhost,.local,127.0.0.0/8,10.0.0.0/8,172.16.0.0/12,192.168.0.0/16,\
+                         100.64.0.0/10,169.254.0.0/16,::1,fc00::/7,fe80::/10";
+
+/// Use the proxies of the environment in `builder`, in place of those reqwest picks by itself
+pub fn with_proxies(builder: ClientBuilder) -> ClientBuilder {
+    with_proxies_from(builder, |name| std::env::var(name).ok())
+}
+
+fn with_proxies_from(builder: ClientBuilder, var: impl Fn(&str) -> Option<String>) -> ClientBuilder {
+    let lookup = |name: &str| {
+        var(name)
+            .or_else(|| var(&name.to_lowercase()))
+            .filter(|value| !value.trim().is_empty())
+    };
+    let no_proxy = match lookup("NO_PROXY") {
+        Some(list) => format!("{},{}", LAN_HOSTS, list),
+        None => LAN_HOSTS.to_string(),
+    };
+
+    // The first matching proxy is used, so the scheme-specific ones go before ALL_PROXY
+    let mut builder = builder.no_proxy();
+    for name in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY"] {
+        let Some(url) = lookup(name) else {
+            continue;
+        };
+        let proxy = match name {
+            "HTTP_PROXY" => Proxy::http(url),
+            "HTTPS_PROXY" => Proxy::https(url),
+            _ => Proxy::all(url),
+        };
+        match proxy {
+            Ok(proxy) => builder = builder.proxy(proxy.no_proxy(NoProxy::from_string(&no_proxy))),
+            Err(e) => warn!("Ignoring {}: {}", name, e),
+        }
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answers every request with `body`, standing in for both a proxy and a peer
+    async fn answer(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_lan_peers_skip_the_proxy() {
+        let proxy = answer("proxied").await;
+        let peer = answer("direct").await;
+        let env = HashMap::from([("http_proxy", format!("http://{}", proxy)), ("NO_PROXY", "nas.example.com".to_string())]);
+        let client = with_proxies_from(reqwest::Client::builder(), |name| env.get(name).cloned())
+            .build()
+            .unwrap();
+
+        let direct = client.get(format!("http://{}/api/files", peer)).send().await.unwrap();
+        assert_eq!(direct.text().await.unwrap(), "direct");
+        let proxied = client.get("http://peer.example.com/api/files").send().await.unwrap();
+        assert_eq!(proxied.text().await.unwrap(), "proxied");
+    }
+}
@@ -0,0 +1,64 @@
+//! Sending the transfers of the server's queue to other devices
+
+use anyhow::{anyhow, Context, Result};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::client::PeerClient;
+use crate::core::events::{EventBus, ServerEvent};
+use crate::core::history::NetworkUsage;
+use crate::core::pause::TransferPause;
+use crate::core::queue::{QueuedTransfer, TransferQueue, TransferStatus};
+use crate::utils::file::{attributes_of, get_file_info};
+
+/// Send queued transfers one after another, the most urgent first, for as long as the queue
+/// lives. Nothing new starts while `pause` is set, and the transfer in progress halts between chunks.
+/// Failed transfers are announced on `events`, and what was sent is counted in `network`.
+/// Cancelling the queue drops the transfer in progress right away.
+pub async fn run_queue(
+    queue: Arc<TransferQueue>,
+    pause: Arc<TransferPause>,
+    events: Arc<EventBus>,
+    network: Arc<NetworkUsage>,
+) {
+    loop {
+        pause.wait_until_resumed().await;
+        let transfer = queue.next().await;
+        info!("Sending {} to {}", transfer.name, transfer.peer);
+
+        let result = tokio::select! {
+            result = send(&queue, &transfer, &pause) => result,
+            _ = queue.cancelled() => Err(anyhow!("cancelled")),
+        };
+        if queue.is_cancelled(&transfer.id) {
+            info!("Cancelled sending {} to {}", transfer.name, transfer.peer);
+            continue;
+        }
+        match &result {
+            Ok(size) => {
+                info!("Sent {} to {}", transfer.name, transfer.peer);
+                network.record(&transfer.peer, None, 0, *size);
+            }
+            Err(e) => warn!("Failed to send {} to {}: {:#}", transfer.name, transfer.peer, e),
+        }
+        queue.finish(&transfer.id, result.map(|_| ()).map_err(|e| format!("{:#}", e)));
+        if let Some(failed) = queue.get(&transfer.id).filter(|transfer| transfer.status == TransferStatus::Failed) {
+            events.publish(ServerEvent::TransferFailed(failed));
+        }
+    }
+}
+
+/// Returns the number of bytes sent
+async fn send(queue: &TransferQueue, transfer: &QueuedTransfer, pause: &Arc<TransferPause>) -> Result<u64> {
+    let client = PeerClient::new(&transfer.peer)?.with_pause(pause.clone());
+    let data = tokio::fs::read(&transfer.source)
+        .await
+        .with_context(|| format!("Failed to read {:?}", transfer.source))?;
+    let attributes = attributes_of(&get_file_info(&transfer.source)?, false);
+    let size = data.len() as u64;
+
+    client
+        .upload_with_progress(&transfer.name, data, attributes, |sent| queue.progress(&transfer.id, sent))
+        .await?;
+    Ok(size)
+}
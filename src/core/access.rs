@@ -0,0 +1,408 @@
+use chrono::{DateTime, Duration, Utc};
+use ring::hmac;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::core::config::AccessConfig;
+use crate::core::error::{AppError, AppResult};
+use crate::core::identity::load_or_create_key;
+use crate::core::metadata::STATE_DIR;
+
+const APPROVED_FILE: &str = "approved-clients.json";
+
+/// Random key PIN fingerprints are made with, kept with the identity rather than the share
+pub const PIN_KEY_FILE: &str = "pin-key";
+
+/// Wrong guesses allowed before a code is thrown away, or an address is refused
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+
+/// Wrong PINs, from any number of addresses, before the PIN stops being accepted for
+/// [`PIN_LOCKOUT_SECS`]. Well above what one address may try, so one guesser cannot lock out
+/// everyone.
+const MAX_FAILED_PINS: u32 = 4 * MAX_FAILED_ATTEMPTS;
+
+/// How long a PIN guessed wrong too often is refused, from everyone
+const PIN_LOCKOUT_SECS: i64 = 60;
+
+/// How long no new code is created after one was voided, so guessing cannot go on with fresh codes
+const CODE_COOLDOWN_SECS: i64 = 60;
+
+/// Window in which one address may guess wrong [`MAX_FAILED_ATTEMPTS`] times before it is refused
+/// until the window ends
+const ADDRESS_WINDOW_SECS: i64 = 600;
+
+struct AccessCode {
+    code: String,
+    expires: DateTime<Utc>,
+    failures: u32,
+}
+
+/// Wrong guesses from one address since `since`
+struct Guesses {
+    failures: u32,
+    since: DateTime<Utc>,
+}
+
+struct AccessState {
+    /// Client ids that entered a valid code, with the fingerprint of the PIN when that was it
+    approved: BTreeMap<String, Option<String>>,
+    code: Option<AccessCode>,
+    /// No new code is created before this, after the last one was voided
+    cooldown_until: Option<DateTime<Utc>>,
+    /// Keyed by client address, as client ids are free; requests without one share a key
+    guesses: HashMap<Option<IpAddr>, Guesses>,
+    /// Wrong PINs entered since the last right one or lockout
+    pin_failures: u32,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+/// Pairing of new clients through short-lived codes shown on the host, like pairing a TV app.
+///
+/// A client that entered a valid code stays approved across restarts. With a fixed PIN set,
+/// clients enter the PIN instead, and stay approved only as long as that PIN is configured.
+pub struct AccessControl {
+    enabled: bool,
+    pin: Option<String>,
+    pin_key: hmac::Key,
+    lifetime: Duration,
+    path: PathBuf,
+    state: Mutex<AccessState>,
+}
+
+impl AccessControl {
+    pub fn load(directory: &Path, config: &AccessConfig) -> Self {
+        let path = directory.join(STATE_DIR).join(APPROVED_FILE);
+
+        // Older versions stored a plain list of clients, all approved by codes
+        let approved = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .or_else(|_| {
+                    serde_json::from_str::<BTreeSet<String>>(&content)
+                        .map(|clients| clients.into_iter().map(|client| (client, None)).collect())
+                })
+                .unwrap_or_else(|e| {
+                    warn!("Ignoring unreadable approved clients file {:?}: {}", path, e);
+                    BTreeMap::new()
+                }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        Self {
+            enabled: config.require_code,
+            pin: None,
+            pin_key: hmac::Key::new(hmac::HMAC_SHA256, Uuid::new_v4().as_bytes()),
+            lifetime: Duration::seconds(config.code_lifetime_secs as i64),
+            path,
+            state: Mutex::new(AccessState {
+                approved,
+                code: None,
+                cooldown_until: None,
+                guesses: HashMap::new(),
+                pin_failures: 0,
+                locked_until: None,
+            }),
+        }
+    }
+
+    /// Ask new clients for `pin` instead of short-lived codes. Approvals remember the PIN by a
+    /// fingerprint keyed with the key kept in `key_directory`.
+    pub fn with_pin(mut self, pin: Option<&str>, key_directory: &Path) -> Self {
+        if let Some(pin) = pin {
+            let path = key_directory.join(PIN_KEY_FILE);
+            match load_or_create_key(&path, 32) {
+                Ok(key) => self.pin_key = hmac::Key::new(hmac::HMAC_SHA256, &key),
+                Err(e) => warn!("Failed to persist PIN key at {:?}, PIN approvals end at restart: {}", path, e),
+            }
+            self.enabled = true;
+            self.pin = Some(pin.to_string());
+        }
+        self
+    }
+
+    /// Whether clients enter a fixed PIN rather than a code from the host terminal
+    pub fn uses_pin(&self) -> bool {
+        self.pin.is_some()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether a client may use the server; always true when codes are not required
+    pub fn is_approved(&self, client_id: &str) -> bool {
+        !self.enabled || self.is_paired(client_id)
+    }
+
+    /// Whether a client entered a valid code at some point, or the PIN that is still configured,
+    /// whether or not codes are required
+    pub fn is_paired(&self, client_id: &str) -> bool {
+        match self.state.lock().unwrap().approved.get(client_id) {
+            Some(Some(fingerprint)) => self.pin.as_deref().map(|pin| self.pin_fingerprint(pin)).as_ref() == Some(fingerprint),
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    /// Withdraw the approval of a client, e.g. because the host revoked it
+    pub fn forget(&self, client_id: &str) -> AppResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.approved.remove(client_id).is_some() {
+            self.save(&state.approved)?;
+        }
+        Ok(())
+    }
+
+    /// The code new clients should enter, and whether it was just created and needs announcing.
+    /// A PIN never needs announcing, it is shown with the QR code. None while no new code may be
+    /// created because the last one was guessed at too often.
+    pub fn current_code(&self, now: DateTime<Utc>) -> Option<(String, bool)> {
+        if let Some(pin) = &self.pin {
+            return Some((pin.clone(), false));
+        }
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(code) = state.code.as_ref().filter(|code| code.expires > now) {
+            return Some((code.code.clone(), false));
+        }
+        if state.cooldown_until.is_some_and(|until| until > now) {
+            return None;
+        }
+
+        let code = format!("{:06}", Uuid::new_v4().as_u128() % 1_000_000);
+        state.code = Some(AccessCode {
+            code: code.clone(),
+            expires: now + self.lifetime,
+            failures: 0,
+        });
+        Some((code, true))
+    }
+
+    /// Approve `client_id`, connecting from `ip`, if `code` is the current code. Too many wrong
+    /// guesses void the code, or lock the PIN for a while.
+    pub fn verify(&self, client_id: &str, ip: Option<IpAddr>, code: &str, now: DateTime<Utc>) -> AppResult<bool> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.is_locked_out(ip, now) {
+            return Ok(false);
+        }
+
+        if let Some(pin) = &self.pin {
+            if state.locked_until.is_some_and(|until| until > now) {
+                return Ok(false);
+            }
+            let fingerprint = self.pin_fingerprint(pin);
+            if !constant_time_eq(fingerprint.as_bytes(), self.pin_fingerprint(code.trim()).as_bytes()) {
+                state.record_failure(ip, now);
+                state.pin_failures += 1;
+                if state.pin_failures >= MAX_FAILED_PINS {
+                    warn!("Too many wrong PINs, refusing PINs for {} seconds", PIN_LOCKOUT_SECS);
+                    state.pin_failures = 0;
+                    state.locked_until = Some(now + Duration::seconds(PIN_LOCKOUT_SECS));
+                }
+                return Ok(false);
+            }
+            state.pin_failures = 0;
+            state.approved.insert(client_id.to_string(), Some(fingerprint));
+            self.save(&state.approved)?;
+            return Ok(true);
+        }
+
+        let Some(current) = state.code.as_mut().filter(|current| current.expires > now) else {
+            return Ok(false);
+        };
+
+        if current.code != code.trim() {
+            current.failures += 1;
+            if current.failures >= MAX_FAILED_ATTEMPTS {
+                warn!("Too many wrong access codes, no new code for {} seconds", CODE_COOLDOWN_SECS);
+                state.code = None;
+                state.cooldown_until = Some(now + Duration::seconds(CODE_COOLDOWN_SECS));
+            }
+            state.record_failure(ip, now);
+            return Ok(false);
+        }
+
+        state.approved.insert(client_id.to_string(), None);
+        self.save(&state.approved)?;
+        Ok(true)
+    }
+
+    fn save(&self, approved: &BTreeMap<String, Option<String>>) -> AppResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(approved)
+            .map_err(|e| AppError::File(format!("Failed to serialize approved clients: {}", e)))?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// What approvals by a PIN remember of it, so they lapse when the PIN changes. Keyed, as a
+    /// plain hash of a few digits is reversed by trying them all; without the key, which is not
+    /// stored with the approvals, the fingerprint tells nothing about the PIN.
+    fn pin_fingerprint(&self, pin: &str) -> String {
+        hmac::sign(&self.pin_key, pin.as_bytes())
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Compare without returning early, so timing does not tell how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+impl AccessState {
+    /// Whether `ip` guessed wrong too often lately
+    fn is_locked_out(&self, ip: Option<IpAddr>, now: DateTime<Utc>) -> bool {
+        self.guesses.get(&ip).is_some_and(|guesses| {
+            guesses.failures >= MAX_FAILED_ATTEMPTS && guesses.since + Duration::seconds(ADDRESS_WINDOW_SECS) > now
+        })
+    }
+
+    fn record_failure(&mut self, ip: Option<IpAddr>, now: DateTime<Utc>) {
+        let window = Duration::seconds(ADDRESS_WINDOW_SECS);
+        self.guesses.retain(|_, guesses| guesses.since + window > now);
+        let guesses = self.guesses.entry(ip).or_insert(Guesses { failures: 0, since: now });
+        guesses.failures += 1;
+        if guesses.failures == MAX_FAILED_ATTEMPTS {
+            let ip = ip.map_or_else(|| "an unknown address".to_string(), |ip| ip.to_string());
+            warn!("Too many wrong guesses from {}, refusing it for {} seconds", ip, ADDRESS_WINDOW_SECS);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config() -> AccessConfig {
+        AccessConfig {
+            require_code: true,
+            code_lifetime_secs: 60,
+        }
+    }
+
+    #[test]
+    fn test_disabled_allows_everyone() {
+        let temp_dir = TempDir::new().unwrap();
+        let access = AccessControl::load(temp_dir.path(), &AccessConfig::default());
+        assert!(access.is_approved("anyone"));
+    }
+
+    #[test]
+    fn test_code_approves_client() {
+        let temp_dir = TempDir::new().unwrap();
+        let access = AccessControl::load(temp_dir.path(), &config());
+        let now = Utc::now();
+
+        assert!(!access.is_approved("phone"));
+        let (code, fresh) = access.current_code(now).unwrap();
+        assert!(fresh);
+        assert_eq!(code.len(), 6);
+        assert_eq!(access.current_code(now), Some((code.clone(), false)));
+
+        assert!(access.verify("phone", None, &code, now).unwrap());
+        assert!(access.is_approved("phone"));
+        assert!(!access.is_approved("laptop"));
+
+        // Approval survives a restart
+        let reloaded = AccessControl::load(temp_dir.path(), &config());
+        assert!(reloaded.is_approved("phone"));
+
+        reloaded.forget("phone").unwrap();
+        assert!(!reloaded.is_approved("phone"));
+    }
+
+    #[test]
+    fn test_codes_expire_and_stop_guessing() {
+        let temp_dir = TempDir::new().unwrap();
+        let access = AccessControl::load(temp_dir.path(), &config());
+        let now = Utc::now();
+
+        let (code, _) = access.current_code(now).unwrap();
+        assert!(!access.verify("phone", None, &code, now + Duration::seconds(61)).unwrap());
+
+        let (code, fresh) = access.current_code(now + Duration::seconds(61)).unwrap();
+        assert!(fresh);
+        let wrong = if code == "000000" { "111111" } else { "000000" };
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            assert!(!access.verify("phone", None, wrong, now + Duration::seconds(62)).unwrap());
+        }
+        // The code was voided by the wrong guesses, and no new one is created for a while
+        assert!(!access.verify("phone", None, &code, now + Duration::seconds(62)).unwrap());
+        assert_eq!(access.current_code(now + Duration::seconds(63)), None);
+        let later = now + Duration::seconds(63 + CODE_COOLDOWN_SECS);
+        let (code, fresh) = access.current_code(later).unwrap();
+        assert!(fresh);
+
+        // The guessing address stays refused, others are not
+        assert!(!access.verify("phone", None, &code, later).unwrap());
+        let other = Some(IpAddr::from([192, 168, 1, 20]));
+        assert!(access.verify("laptop", other, &code, later).unwrap());
+    }
+
+    #[test]
+    fn test_pin_approves_client() {
+        let temp_dir = TempDir::new().unwrap();
+        let access = AccessControl::load(temp_dir.path(), &AccessConfig::default()).with_pin(Some("1234"), temp_dir.path());
+        let now = Utc::now();
+
+        assert!(access.is_enabled());
+        assert!(!access.is_approved("phone"));
+        assert_eq!(access.current_code(now), Some(("1234".to_string(), false)));
+        assert!(access.verify("phone", None, " 1234 ", now).unwrap());
+        assert!(access.is_approved("phone"));
+
+        // One guessing address only locks out itself
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            assert!(!access.verify("laptop", None, "0000", now).unwrap());
+        }
+        assert!(!access.verify("laptop", None, "1234", now).unwrap());
+        let tablet = Some(IpAddr::from([192, 168, 1, 20]));
+        assert!(access.verify("tablet", tablet, "1234", now).unwrap());
+
+        // Guessing from many addresses locks the PIN for everyone for a while
+        for guesser in 0..MAX_FAILED_PINS {
+            let ip = Some(IpAddr::from([10, 0, 0, guesser as u8]));
+            assert!(!access.verify("guesser", ip, "0000", now).unwrap());
+        }
+        assert!(!access.verify("laptop", tablet, "1234", now + Duration::seconds(1)).unwrap());
+        assert!(access.verify("laptop", tablet, "1234", now + Duration::seconds(PIN_LOCKOUT_SECS + 1)).unwrap());
+
+        // Approvals by the PIN lapse when it changes, those by codes do not
+        let mut state = access.state.lock().unwrap();
+        state.approved.insert("desktop".to_string(), None);
+        access.save(&state.approved).unwrap();
+        drop(state);
+        let same = AccessControl::load(temp_dir.path(), &AccessConfig::default()).with_pin(Some("1234"), temp_dir.path());
+        assert!(same.is_approved("phone"));
+        assert!(temp_dir.path().join(PIN_KEY_FILE).exists());
+        let changed = AccessControl::load(temp_dir.path(), &AccessConfig::default()).with_pin(Some("5678"), temp_dir.path());
+        assert!(!changed.is_approved("phone"));
+        assert!(!changed.is_approved("laptop"));
+        assert!(changed.is_approved("desktop"));
+        let unset = AccessControl::load(temp_dir.path(), &config());
+        assert!(!unset.is_approved("phone"));
+        assert!(unset.is_approved("desktop"));
+    }
+
+    #[test]
+    fn test_loads_plain_list_of_approved_clients() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join(STATE_DIR)).unwrap();
+        std::fs::write(temp_dir.path().join(STATE_DIR).join(APPROVED_FILE), r#"["phone"]"#).unwrap();
+        let access = AccessControl::load(temp_dir.path(), &config());
+        assert!(access.is_approved("phone"));
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::info;
+
+/// How often the local address is looked up again
+pub const ADDRESS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The address this device is reachable at on the local network.
+///
+/// It changes when the device roams to another Wi-Fi network or its DHCP lease is renewed with
+/// a different address; whoever advertises the device subscribes to hear about that.
+pub struct LocalAddress {
+    ip: watch::Sender<String>,
+}
+
+impl LocalAddress {
+    pub fn new(ip: String) -> Self {
+        Self {
+            ip: watch::Sender::new(ip),
+        }
+    }
+
+    pub fn current(&self) -> String {
+        self.ip.borrow().clone()
+    }
+
+    /// Record the address, returning whether it changed
+    pub fn set(&self, ip: String) -> bool {
+        self.ip.send_if_modified(|current| {
+            if *current == ip {
+                return false;
+            }
+            *current = ip;
+            true
+        })
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<String> {
+        self.ip.subscribe()
+    }
+
+    /// Look the address up every `interval`, forever. While the device has no address at all
+    /// the last one is kept, so it comes back unchanged when the network does.
+    pub async fn watch(&self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Ok(ip) = local_ip_address::local_ip() {
+                if self.set(ip.to_string()) {
+                    info!("Local address changed to {}", ip);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribers_see_changes() {
+        let address = LocalAddress::new("192.168.1.7".to_string());
+        let mut changes = address.subscribe();
+
+        assert!(!address.set("192.168.1.7".to_string()));
+        assert!(!changes.has_changed().unwrap());
+
+        assert!(address.set("10.0.0.12".to_string()));
+        changes.changed().await.unwrap();
+        assert_eq!(*changes.borrow_and_update(), "10.0.0.12");
+        assert_eq!(address.current(), "10.0.0.12");
+    }
+}
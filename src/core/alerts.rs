@@ -0,0 +1,199 @@
+//! Alerts about clients the host did not expect, for hosts on shared networks.
+//!
+//! A request raises an alert when it comes from outside the expected networks, or from a
+//! device that never entered an access code. Alerts go to the terminal, optionally with a
+//! desktop notification and to a webhook, at most once per client every few minutes.
+
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::core::config::AlertsConfig;
+
+/// Time before the same client raises another alert
+const ALERT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertReason {
+    /// The client's address is outside the expected networks
+    UnexpectedNetwork,
+    /// The client never entered an access code
+    UnpairedDevice,
+}
+
+impl AlertReason {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::UnexpectedNetwork => "a client from outside the expected networks",
+            Self::UnpairedDevice => "a device that never entered an access code",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub reason: AlertReason,
+    pub client: String,
+    pub ip: Option<IpAddr>,
+    pub path: String,
+    pub user_agent: Option<String>,
+    pub time: DateTime<Utc>,
+}
+
+pub struct Alerts {
+    config: AlertsConfig,
+    networks: Vec<IpNet>,
+    /// When each client last raised an alert
+    recent: Mutex<HashMap<String, Instant>>,
+    http: reqwest::Client,
+}
+
+impl Alerts {
+    pub fn new(config: &AlertsConfig) -> Self {
+        let networks = config
+            .expected_networks
+            .iter()
+            .filter_map(|entry| {
+                let entry = entry.trim();
+                let network = entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from));
+                if network.is_err() {
+                    warn!("Ignoring invalid expected network {:?}", entry);
+                }
+                network.ok()
+            })
+            .collect();
+
+        Self {
+            config: config.clone(),
+            networks,
+            recent: Mutex::default(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Why a request from `ip` by a client that is `paired` or not deserves an alert, if it does
+    pub fn reason(&self, ip: Option<IpAddr>, paired: bool) -> Option<AlertReason> {
+        // The host itself is always expected
+        let expected = ip.is_none_or(|ip| {
+            ip.is_loopback() || self.networks.is_empty() || self.networks.iter().any(|network| network.contains(&ip))
+        });
+        if !expected {
+            return Some(AlertReason::UnexpectedNetwork);
+        }
+        if self.config.unpaired && !paired && !ip.is_some_and(|ip| ip.is_loopback()) {
+            return Some(AlertReason::UnpairedDevice);
+        }
+        None
+    }
+
+    /// Whether `client` may raise an alert now, noting that it did
+    fn due(&self, client: &str, now: Instant) -> bool {
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|_, last| now.duration_since(*last) < ALERT_INTERVAL);
+        if recent.contains_key(client) {
+            return false;
+        }
+        recent.insert(client.to_string(), now);
+        true
+    }
+
+    /// Tell the host about `alert`, unless its client raised one a moment ago
+    pub fn raise(&self, alert: Alert) {
+        if !self.due(&alert.client, Instant::now()) {
+            return;
+        }
+
+        let from = alert.ip.map_or_else(|| "an unknown address".to_string(), |ip| ip.to_string());
+        let bell = if self.config.bell { "\x07" } else { "" };
+        println!();
+        println!("{}🚨 Unexpected access by {} at {}", bell, alert.reason.message(), from);
+        println!("   {} {}", alert.path, alert.user_agent.as_deref().unwrap_or(""));
+        println!();
+        warn!("Alert: {:?} from {} requesting {}", alert.reason, from, alert.path);
+
+        if self.config.notify {
+            desktop_notification("RustDrop: unexpected access", &format!("{} at {}", alert.reason.message(), from));
+        }
+        if let Some(webhook) = self.config.webhook.clone() {
+            let request = self.http.post(&webhook).json(&alert).timeout(Duration::from_secs(10));
+            tokio::spawn(async move {
+                if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+                    warn!("Failed to send alert to {}: {}", webhook, e);
+                }
+            });
+        }
+    }
+}
+
+/// Show a notification on the desktop of this machine, where the platform has a way to
+#[cfg(target_os = "linux")]
+pub fn desktop_notification(title: &str, body: &str) {
+    if let Err(e) = std::process::Command::new("notify-send").args([title, body]).spawn() {
+        debug!("Failed to show desktop notification: {}", e);
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn desktop_notification(title: &str, body: &str) {
+    let script = format!("display notification {:?} with title {:?}", body, title);
+    if let Err(e) = std::process::Command::new("osascript").args(["-e", &script]).spawn() {
+        debug!("Failed to show desktop notification: {}", e);
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn desktop_notification(_title: &str, _body: &str) {
+    debug!("Desktop notifications are not supported on this platform");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alerts(expected_networks: &[&str], unpaired: bool) -> Alerts {
+        Alerts::new(&AlertsConfig {
+            expected_networks: expected_networks.iter().map(|entry| entry.to_string()).collect(),
+            unpaired,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_reasons() {
+        let home = "192.168.1.20".parse().ok();
+        let cafe = "10.0.0.7".parse().ok();
+        let host = "127.0.0.1".parse().ok();
+
+        let everywhere = alerts(&[], false);
+        assert_eq!(everywhere.reason(cafe, false), None);
+
+        let networks = alerts(&["192.168.1.0/24", "not a network"], false);
+        assert_eq!(networks.reason(home, false), None);
+        assert_eq!(networks.reason(cafe, true), Some(AlertReason::UnexpectedNetwork));
+        assert_eq!(networks.reason(host, false), None);
+
+        let pairing = alerts(&[], true);
+        assert_eq!(pairing.reason(home, false), Some(AlertReason::UnpairedDevice));
+        assert_eq!(pairing.reason(home, true), None);
+        assert_eq!(pairing.reason(host, false), None);
+    }
+
+    #[test]
+    fn test_one_alert_per_client_and_interval() {
+        let alerts = alerts(&[], true);
+        let start = Instant::now();
+
+        assert!(alerts.due("phone", start));
+        assert!(!alerts.due("phone", start + Duration::from_secs(1)));
+        assert!(alerts.due("laptop", start + Duration::from_secs(1)));
+        assert!(alerts.due("phone", start + ALERT_INTERVAL));
+    }
+}
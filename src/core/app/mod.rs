@@ -1,11 +1,20 @@
 use anyhow::Result;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::TcpListener;
 use tokio::signal;
 use tracing::{info, error, warn};
 
+use crate::core::address::{LocalAddress, ADDRESS_POLL_INTERVAL};
+use crate::core::config::AppConfig;
+use crate::core::identity::device_id;
+use crate::core::instances::{remember_port, InstanceControl};
+use crate::core::jump::JumpCodes;
 use crate::core::models::DeviceInfo;
-use crate::discovery::ServiceDiscovery;
+use crate::core::schedule::{Schedule, SCHEDULE_CHECK_INTERVAL};
+use crate::discovery::{ServiceDiscovery, HEALTH_CHECK_INTERVAL};
+use crate::utils::bigtext::render_big;
 use crate::utils::qrcode::generate_qr_code;
 use crate::web::server::WebServer;
 
@@ -17,6 +26,9 @@ pub struct App {
     open_browser: bool,
     max_file_size: u64,
     device_info: DeviceInfo,
+    config: AppConfig,
+    control: Arc<InstanceControl>,
+    listener: Option<TcpListener>,
 }
 
 impl App {
@@ -28,7 +40,8 @@ impl App {
         open_browser: bool,
         max_file_size: u64,
     ) -> Self {
-        let device_info = DeviceInfo::new(port);
+        let mut device_info = DeviceInfo::new(port);
+        device_info.id = device_id();
         
         Self {
             port,
@@ -38,49 +51,161 @@ impl App {
             open_browser,
             max_file_size,
             device_info,
+            config: AppConfig::default(),
+            control: Arc::default(),
+            listener: None,
         }
     }
     
-    pub async fn run(&self) -> Result<()> {
-        // Print application information
-        info!("Serving files from: {:?}", self.directory);
-        info!("Web interface available at: {}", self.device_info.url());
+    /// Pass the loaded configuration through to the web server
+    pub fn with_config(mut self, config: AppConfig) -> Self {
+        self.device_info.tls = config.server.tls.enabled && cfg!(feature = "tls");
+        self.config = config;
+        self
+    }
+    
+    /// Tell this instance apart from others running on the same host, in the name it announces
+    pub fn with_instance_label(mut self, label: &str) -> Self {
+        self.device_info.name = format!("{} ({})", self.device_info.name, label);
+        self
+    }
+    
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.device_info
+    }
+    
+    /// Lets another instance ask this one to shut down
+    pub fn control(&self) -> &InstanceControl {
+        &self.control
+    }
+    
+    /// Print where the web interface is, with a QR code if enabled. The QR code leads to the
+    /// welcome page, which helps when the device scanning it cannot connect; so does the short
+    /// `/j/<code>` address printed large below it, for devices without a camera.
+    fn show_url(&self, device_info: &DeviceInfo, welcome_code: Option<&str>) {
+        info!("Web interface available at: {}", device_info.url());
         
-        // Display QR code if enabled
         if self.enable_qr {
-            match generate_qr_code(&self.device_info.url()) {
+            match generate_qr_code(&format!("{}/welcome", device_info.url())) {
                 Ok(qr_code) => println!("{}", qr_code),
                 Err(e) => error!("Failed to generate QR code: {}", e),
             }
+            
+            if let Some(code) = welcome_code {
+                println!("Or type {}/j/{}", device_info.url(), code);
+                let host = render_big(&format!("{}:{}", device_info.ip, device_info.port));
+                if let (Some(host), Some(path)) = (host, render_big(&format!("/j/{}", code))) {
+                    println!("\n{}\n{}", host, path);
+                }
+            }
+        }
+        
+        // Whoever can read the PIN off this screen can connect
+        if let Some(pin) = self.config.security.pin() {
+            println!("PIN: {}", pin);
+            if let Some(big) = render_big(pin) {
+                println!("\n{}", big);
+            }
+        }
+    }
+    
+    /// Bind the server socket and take the port from it, so everything announcing this
+    /// instance describes the address actually listened on. `run` binds if this was not called.
+    pub async fn bind(&mut self) -> Result<()> {
+        let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], self.port))).await?;
+        self.port = listener.local_addr()?.port();
+        self.device_info.port = self.port;
+        self.listener = Some(listener);
+        Ok(())
+    }
+    
+    pub async fn run(mut self) -> Result<()> {
+        let listener = match self.listener.take() {
+            Some(listener) => listener,
+            None => {
+                self.bind().await?;
+                self.listener.take().expect("listener was just bound")
+            }
+        };
+        let addr = listener.local_addr()?;
+        let mut device_info = self.device_info.clone();
+        if let Err(e) = remember_port(&self.directory, self.port) {
+            warn!("Failed to remember the port: {}", e);
         }
         
+        // Print application information
+        info!("Serving files from: {:?}", self.directory);
+        let welcome_code = JumpCodes::load(&self.directory)
+            .code_for("/welcome")
+            .map_err(|e| warn!("Failed to create a short code for the welcome page: {}", e))
+            .ok();
+        self.show_url(&device_info, welcome_code.as_deref());
+        
+        // Outside the scheduled hours the server is not announced
+        let schedule = Schedule::parse(&self.config.server.schedule);
+        let mut available = schedule.is_open();
+        if !schedule.is_always_open() {
+            info!("Available {}{}", schedule, if available { "" } else { ", closed for now" });
+        }
+        let mut schedule_check = tokio::time::interval(SCHEDULE_CHECK_INTERVAL);
+        let mut mdns_check = tokio::time::interval_at(
+            tokio::time::Instant::now() + HEALTH_CHECK_INTERVAL,
+            HEALTH_CHECK_INTERVAL,
+        );
+        
         // Start mDNS service discovery if enabled
         let mut discovery = if self.enable_mdns {
-            let mut service = ServiceDiscovery::new(self.device_info.clone());
-            match service.register().await {
-                Ok(_) => {
-                    info!("mDNS service registered successfully");
-                    Some(service)
-                },
-                Err(e) => {
-                    error!("Failed to register mDNS service: {}", e);
-                    None
+            let mut service = ServiceDiscovery::new(device_info.clone());
+            if !available {
+                // Registered once the schedule opens
+                Some(service)
+            } else {
+                match service.register().await {
+                    Ok(_) => {
+                        info!("mDNS service registered successfully");
+                        Some(service)
+                    },
+                    Err(e) => {
+                        error!("Failed to register mDNS service: {}", e);
+                        None
+                    }
                 }
             }
         } else {
             None
         };
         
+        // Announce the virtual printer if enabled
+        #[cfg(feature = "ipp")]
+        let printer = if self.enable_mdns && self.config.printer.enabled {
+            let name = crate::ipp::printer_name(self.config.printer.name.as_deref(), &device_info);
+            crate::ipp::announce(&device_info, &name)
+                .map_err(|e| error!("Failed to announce the printer: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        
         // Open browser if requested
         if self.open_browser {
-            if let Err(e) = open::that(&self.device_info.url()) {
+            if let Err(e) = open::that(device_info.url()) {
                 error!("Failed to open browser: {}", e);
             }
         }
         
+        // Follow the address as the host roams between networks
+        let address = Arc::new(LocalAddress::new(device_info.ip.clone()));
+        let watcher = tokio::spawn({
+            let address = address.clone();
+            async move { address.watch(ADDRESS_POLL_INTERVAL).await }
+        });
+        let mut address_changes = address.subscribe();
+        
         // Start the web server
-        let addr = SocketAddr::from(([0, 0, 0, 0], self.port));
-        let server = WebServer::new(addr, self.directory.clone(), self.device_info.clone(), self.max_file_size);
+        let server = WebServer::new(addr, self.directory.clone(), device_info.clone(), self.max_file_size)
+            .with_config(self.config.clone())
+            .with_control(self.control.clone())
+            .with_address(address);
         
         // Setup graceful shutdown
         let shutdown_signal = async {
@@ -91,19 +216,69 @@ impl App {
         };
         
         // Run the server with graceful shutdown
-        tokio::select! {
-            result = server.run() => {
-                if let Err(e) = result {
-                    error!("Server error: {}", e);
+        let server = server.serve(listener);
+        tokio::pin!(server);
+        tokio::pin!(shutdown_signal);
+        loop {
+            tokio::select! {
+                result = &mut server => {
+                    if let Err(e) = result {
+                        error!("Server error: {}", e);
+                    }
+                    break;
+                }
+                _ = &mut shutdown_signal => {
+                    info!("Shutdown signal received");
+                    break;
+                }
+                _ = self.control.shutdown_requested() => {
+                    info!("Handing the port over to the new instance");
+                    break;
+                }
+                _ = schedule_check.tick() => {
+                    let open = schedule.is_open();
+                    if open == available {
+                        continue;
+                    }
+                    available = open;
+                    info!("{} for the scheduled hours {}", if open { "Opening" } else { "Closing" }, schedule);
+                    if let Some(ref mut discovery) = discovery {
+                        let result = if open {
+                            discovery.register().await.map(|_| ())
+                        } else {
+                            discovery.unregister().await
+                        };
+                        if let Err(e) = result {
+                            error!("Failed to update the mDNS announcement: {}", e);
+                        }
+                    }
+                }
+                _ = mdns_check.tick(), if available => {
+                    if let Some(ref mut discovery) = discovery {
+                        if let Err(e) = discovery.ensure_registered().await {
+                            error!("Failed to check the mDNS announcement: {}", e);
+                        }
+                    }
+                }
+                Ok(()) = address_changes.changed() => {
+                    device_info.ip = address_changes.borrow_and_update().clone();
+                    self.show_url(&device_info, welcome_code.as_deref());
+                    if let Some(ref mut discovery) = discovery {
+                        if let Err(e) = discovery.update(device_info.clone()).await {
+                            error!("Failed to announce the new address over mDNS: {}", e);
+                        }
+                    }
                 }
-            }
-            _ = shutdown_signal => {
-                info!("Shutdown signal received");
             }
         }
+        watcher.abort();
         
         // Graceful cleanup
         info!("Cleaning up services...");
+        #[cfg(feature = "ipp")]
+        if let Some(printer) = printer {
+            printer.withdraw();
+        }
         
         // Unregister mDNS service if it was started
         if let Some(ref mut discovery) = discovery {
@@ -122,3 +297,19 @@ impl App {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_device_info_has_bound_port() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut app = App::new(0, temp_dir.path().to_path_buf(), false, false, false, 1024);
+        app.bind().await.unwrap();
+
+        let bound = app.listener.as_ref().unwrap().local_addr().unwrap().port();
+        assert_ne!(bound, 0);
+        assert_eq!(app.device_info().port, bound);
+    }
+}
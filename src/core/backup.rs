@@ -0,0 +1,189 @@
+//! Photo backup from phones: the camera roll items each device already backed up, and the
+//! hashes of everything backed up, so a photo is stored once however often and from however
+//! many devices it is sent.
+//!
+//! Devices name their items with keys of their own, like name, size and date, which lets them
+//! find new items without reading every photo again. Items deleted from the share stay backed up
+//! as far as the devices are concerned, so the host can weed out photos without phones sending
+//! them again.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::warn;
+
+use crate::core::error::{AppError, AppResult};
+use crate::core::metadata::STATE_DIR;
+
+const BACKUP_FILE: &str = "backup.json";
+
+/// Longest item key accepted
+pub const MAX_KEY_LENGTH: usize = 512;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DeviceBackup {
+    /// Content hash of every item backed up, by the device's key
+    #[serde(default)]
+    items: BTreeMap<String, String>,
+    #[serde(default)]
+    bytes: u64,
+    #[serde(default)]
+    last_backup: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Backups {
+    /// Backup state by client id
+    #[serde(default)]
+    devices: BTreeMap<String, DeviceBackup>,
+    /// Name in the share of the file first backed up with each content hash
+    #[serde(default)]
+    hashes: BTreeMap<String, String>,
+}
+
+/// What a device has backed up so far
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupSummary {
+    pub items: usize,
+    /// Bytes the device sent, not counting items that were backed up already
+    pub bytes: u64,
+    pub last_backup: Option<DateTime<Utc>>,
+}
+
+/// An item a device may back up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupItem {
+    pub key: String,
+    /// SHA-256 of the contents, when the device could compute it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// The backup state of every device, saved alongside the share
+pub struct BackupStore {
+    path: PathBuf,
+    data: RwLock<Backups>,
+}
+
+impl BackupStore {
+    /// Load the backup state of a shared directory, starting empty if none exists yet
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(STATE_DIR).join(BACKUP_FILE);
+
+        let data = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable backup state {:?}: {}", path, e);
+                Backups::default()
+            }),
+            Err(_) => Backups::default(),
+        };
+
+        Self {
+            path,
+            data: RwLock::new(data),
+        }
+    }
+
+    pub fn summary(&self, device: &str) -> BackupSummary {
+        let data = self.data.read().unwrap();
+        let backup = data.devices.get(device).cloned().unwrap_or_default();
+        BackupSummary {
+            items: backup.items.len(),
+            bytes: backup.bytes,
+            last_backup: backup.last_backup,
+        }
+    }
+
+    /// Keys of the items a device still has to send. Items whose contents another device, or
+    /// the same one under another key, backed up already count as backed up from now on.
+    pub fn missing(&self, device: &str, items: &[BackupItem]) -> AppResult<Vec<String>> {
+        let mut data = self.data.write().unwrap();
+        let Backups { devices, hashes } = &mut *data;
+        let backup = devices.entry(device.to_string()).or_default();
+
+        let mut missing = Vec::new();
+        let mut changed = false;
+        for item in items {
+            if backup.items.contains_key(&item.key) {
+                continue;
+            }
+            match item.hash.as_deref().map(str::to_ascii_lowercase) {
+                Some(hash) if hashes.contains_key(&hash) => {
+                    backup.items.insert(item.key.clone(), hash);
+                    changed = true;
+                }
+                _ => missing.push(item.key.clone()),
+            }
+        }
+
+        if changed {
+            backup.last_backup = Some(Utc::now());
+            self.save(&data)?;
+        }
+        Ok(missing)
+    }
+
+    /// Name in the share of the file backed up with contents of this hash
+    pub fn stored(&self, hash: &str) -> Option<String> {
+        self.data.read().unwrap().hashes.get(hash).cloned()
+    }
+
+    /// Note that a device backed up an item, sending `size` bytes, stored as `name` unless it
+    /// was backed up already
+    pub fn record(&self, device: &str, key: &str, hash: &str, name: &str, size: u64) -> AppResult<()> {
+        let mut data = self.data.write().unwrap();
+        data.hashes.entry(hash.to_string()).or_insert_with(|| name.to_string());
+        let backup = data.devices.entry(device.to_string()).or_default();
+        backup.items.insert(key.to_string(), hash.to_string());
+        backup.bytes += size;
+        backup.last_backup = Some(Utc::now());
+        self.save(&data)
+    }
+
+    fn save(&self, data: &Backups) -> AppResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string(data)
+            .map_err(|e| AppError::File(format!("Failed to serialize backup state: {}", e)))?;
+
+        // Write to a temporary file first so a crash never leaves half-written state
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn item(key: &str, hash: Option<&str>) -> BackupItem {
+        BackupItem { key: key.to_string(), hash: hash.map(str::to_string) }
+    }
+
+    #[test]
+    fn test_items_are_backed_up_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = BackupStore::load(temp_dir.path());
+
+        let items = [item("IMG_1.jpg:100", Some("aa")), item("IMG_2.jpg:200", None)];
+        assert_eq!(store.missing("phone", &items).unwrap(), ["IMG_1.jpg:100", "IMG_2.jpg:200"]);
+        store.record("phone", "IMG_1.jpg:100", "aa", "Backup/phone/IMG_1.jpg", 100).unwrap();
+        assert_eq!(store.missing("phone", &items).unwrap(), ["IMG_2.jpg:200"]);
+        assert_eq!(store.stored("aa").as_deref(), Some("Backup/phone/IMG_1.jpg"));
+
+        // The tablet has the same photo under another key, and does not send it again
+        let store = BackupStore::load(temp_dir.path());
+        assert_eq!(store.missing("tablet", &[item("0001", Some("AA"))]).unwrap(), Vec::<String>::new());
+        assert_eq!(store.summary("tablet").items, 1);
+        assert_eq!(store.summary("tablet").bytes, 0);
+        assert_eq!(store.summary("phone").bytes, 100);
+        assert_eq!(store.summary("laptop").last_backup, None);
+    }
+}
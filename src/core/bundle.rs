@@ -0,0 +1,283 @@
+//! A single-file copy of everything that makes an installation itself: the configuration, the
+//! device identity (id, client token, QUIC certificate) and the share's state (history, tags,
+//! approved and revoked clients, share links, sync journals). Importing it on a new machine
+//! keeps pairings and history, because peers keep recognising the same device.
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use crate::backend::cache::CACHE_DIR;
+use crate::core::metadata::STATE_DIR;
+
+const BUNDLE_VERSION: u32 = 1;
+
+/// Entries of the state directory that are caches or in-flight data, not worth carrying over
+const SKIPPED_STATE: &[&str] = &["uploads", "trash", "thumbnails", CACHE_DIR];
+
+/// Where a bundled file belongs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleScope {
+    /// The `rustdrop.toml` configuration file
+    Config,
+    /// The per-user identity directory
+    Identity,
+    /// The share's `.rustdrop` state directory
+    Share,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledFile {
+    pub scope: BundleScope,
+    /// Path inside the scope, with `/` separators
+    pub path: String,
+    /// Base64 content
+    pub data: String,
+}
+
+/// Where the state is read from on export and written to on import
+#[derive(Debug, Clone)]
+pub struct StateLocations {
+    pub config_file: PathBuf,
+    pub identity_dir: Option<PathBuf>,
+    pub share: PathBuf,
+}
+
+impl StateLocations {
+    fn target(&self, file: &BundledFile) -> Result<PathBuf> {
+        let relative = safe_relative_path(&file.path)?;
+        Ok(match file.scope {
+            BundleScope::Config => self.config_file.clone(),
+            BundleScope::Identity => self
+                .identity_dir
+                .as_ref()
+                .context("No config directory to restore the device identity to")?
+                .join(relative),
+            BundleScope::Share => self.share.join(STATE_DIR).join(relative),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateBundle {
+    pub version: u32,
+    pub created: DateTime<Utc>,
+    pub files: Vec<BundledFile>,
+}
+
+impl StateBundle {
+    /// Read every state file that exists at `locations`
+    pub fn collect(locations: &StateLocations) -> Result<Self> {
+        let mut files = Vec::new();
+
+        if locations.config_file.is_file() {
+            files.push(bundled(BundleScope::Config, "rustdrop.toml", &locations.config_file)?);
+        }
+        if let Some(dir) = &locations.identity_dir {
+            collect_dir(BundleScope::Identity, dir, dir, &[], &mut files)?;
+        }
+        let state_dir = locations.share.join(STATE_DIR);
+        collect_dir(BundleScope::Share, &state_dir, &state_dir, SKIPPED_STATE, &mut files)?;
+
+        Ok(Self {
+            version: BUNDLE_VERSION,
+            created: Utc::now(),
+            files,
+        })
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        let bundle: Self = serde_json::from_str(content).context("Not a RustDrop state bundle")?;
+        if bundle.version > BUNDLE_VERSION {
+            bail!(
+                "The bundle was written by a newer RustDrop (format {}, this one reads up to {})",
+                bundle.version,
+                BUNDLE_VERSION
+            );
+        }
+        Ok(bundle)
+    }
+
+    /// Existing files at `locations` the import would replace with different content
+    pub fn conflicts(&self, locations: &StateLocations) -> Result<Vec<PathBuf>> {
+        let mut conflicts = Vec::new();
+        for file in &self.files {
+            let target = locations.target(file)?;
+            if let Ok(existing) = std::fs::read(&target) {
+                if existing != decode(file)? {
+                    conflicts.push(target);
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Write the bundled files to `locations`, returning the paths written
+    pub fn restore(&self, locations: &StateLocations) -> Result<Vec<PathBuf>> {
+        // Check every entry before writing anything so a bad bundle leaves no half import
+        let entries = self
+            .files
+            .iter()
+            .map(|file| Ok((locations.target(file)?, decode(file)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut written = Vec::new();
+        for (target, data) in entries {
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+            }
+            // Keys and tokens are among the files, so none is readable by other users
+            let tmp_path = target.with_extension("import-tmp");
+            // The mode only applies to new files, not to one a failed import left behind
+            let _ = std::fs::remove_file(&tmp_path);
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options
+                .open(&tmp_path)
+                .and_then(|mut file| file.write_all(&data))
+                .with_context(|| format!("Failed to write {:?}", target))?;
+            std::fs::rename(&tmp_path, &target).with_context(|| format!("Failed to write {:?}", target))?;
+            written.push(target);
+        }
+        Ok(written)
+    }
+}
+
+fn bundled(scope: BundleScope, path: &str, source: &Path) -> Result<BundledFile> {
+    let data = std::fs::read(source).with_context(|| format!("Failed to read {:?}", source))?;
+    Ok(BundledFile {
+        scope,
+        path: path.to_string(),
+        data: STANDARD.encode(data),
+    })
+}
+
+fn decode(file: &BundledFile) -> Result<Vec<u8>> {
+    STANDARD
+        .decode(&file.data)
+        .with_context(|| format!("Corrupt content for {}", file.path))
+}
+
+fn collect_dir(
+    scope: BundleScope,
+    root: &Path,
+    dir: &Path,
+    skipped: &[&str],
+    files: &mut Vec<BundledFile>,
+) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", dir)),
+    };
+
+    let mut entries = entries.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if dir == root && skipped.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_dir(scope, root, &path, skipped, files)?;
+        } else if file_type.is_file() {
+            let relative = path.strip_prefix(root).expect("entries are under the root");
+            let relative: Vec<String> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            files.push(bundled(scope, &relative.join("/"), &path)?);
+        }
+    }
+    Ok(())
+}
+
+/// A bundled path, refusing anything that would land outside its scope
+fn safe_relative_path(path: &str) -> Result<PathBuf> {
+    let relative = PathBuf::from(path);
+    if path.is_empty() || !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        bail!("Refusing to import {:?}: not a plain relative path", path);
+    }
+    Ok(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn locations(root: &Path) -> StateLocations {
+        StateLocations {
+            config_file: root.join("rustdrop.toml"),
+            identity_dir: Some(root.join("identity")),
+            share: root.join("share"),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let old = TempDir::new().unwrap();
+        let old_locations = locations(old.path());
+        std::fs::write(&old_locations.config_file, "[server]\nport = 9000\n").unwrap();
+        std::fs::create_dir_all(old_locations.identity_dir.as_ref().unwrap()).unwrap();
+        std::fs::write(old_locations.identity_dir.as_ref().unwrap().join("device-id"), "id").unwrap();
+        let state_dir = old_locations.share.join(STATE_DIR);
+        std::fs::create_dir_all(state_dir.join("sync")).unwrap();
+        std::fs::create_dir_all(state_dir.join("thumbnails")).unwrap();
+        std::fs::write(state_dir.join("history.jsonl"), "{}\n").unwrap();
+        std::fs::write(state_dir.join("sync").join("peer.json"), "{}").unwrap();
+        std::fs::write(state_dir.join("thumbnails").join("a.jpg"), "cache").unwrap();
+
+        let bundle = StateBundle::collect(&old_locations).unwrap();
+        assert_eq!(bundle.files.len(), 4);
+        let json = serde_json::to_string(&bundle).unwrap();
+
+        let new = TempDir::new().unwrap();
+        let new_locations = locations(new.path());
+        let bundle = StateBundle::from_json(&json).unwrap();
+        assert!(bundle.conflicts(&new_locations).unwrap().is_empty());
+        bundle.restore(&new_locations).unwrap();
+
+        let state_dir = new_locations.share.join(STATE_DIR);
+        assert_eq!(std::fs::read_to_string(&new_locations.config_file).unwrap(), "[server]\nport = 9000\n");
+        assert_eq!(std::fs::read_to_string(new.path().join("identity").join("device-id")).unwrap(), "id");
+        assert_eq!(std::fs::read_to_string(state_dir.join("sync").join("peer.json")).unwrap(), "{}");
+        assert!(!state_dir.join("thumbnails").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let identity = std::fs::metadata(new.path().join("identity").join("device-id")).unwrap();
+            assert_eq!(identity.permissions().mode() & 0o777, 0o600);
+        }
+
+        std::fs::write(new.path().join("identity").join("device-id"), "other").unwrap();
+        assert_eq!(
+            bundle.conflicts(&new_locations).unwrap(),
+            vec![new.path().join("identity").join("device-id")]
+        );
+    }
+
+    #[test]
+    fn test_rejects_escaping_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let bundle = StateBundle {
+            version: BUNDLE_VERSION,
+            created: Utc::now(),
+            files: vec![BundledFile {
+                scope: BundleScope::Share,
+                path: "../../outside".to_string(),
+                data: STANDARD.encode("x"),
+            }],
+        };
+
+        assert!(bundle.restore(&locations(temp_dir.path())).is_err());
+        assert!(!temp_dir.path().join("outside").exists());
+    }
+}
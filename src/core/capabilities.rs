@@ -0,0 +1,191 @@
+//! What a device can take in, served at `/api/capabilities`, so a sender picks a way of pushing
+//! files the receiver understands before sending anything, and can say plainly why a transfer
+//! cannot work instead of failing halfway through.
+
+use humansize::{format_size, BINARY};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Ways of uploading a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadMethod {
+    /// Chunks through an upload session that survives dropped connections (`/api/uploads`)
+    Resumable,
+    /// Runs of zeroes announced instead of sent, on top of resumable uploads
+    Sparse,
+    /// The tus 1.0.0 protocol on `/api/uploads`
+    Tus,
+    /// The whole file in one `multipart/form-data` request (`POST /api/files`)
+    Multipart,
+    /// Something a newer version offers
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Sha256,
+    #[serde(other)]
+    Unknown,
+}
+
+/// How transfers to the device can be encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encryption {
+    /// The web server speaks HTTPS
+    Tls,
+    /// The QUIC transport is on, see `/api/quic`
+    Quic,
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a device accepts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Largest single file taken in
+    pub max_file_size: u64,
+    /// Algorithms `/api/files/:id/checksum` can hash with
+    pub hash_algorithms: Vec<HashAlgorithm>,
+    /// Empty while the device takes no uploads, e.g. serving a read-only snapshot
+    pub uploads: Vec<UploadMethod>,
+    pub encryption: Vec<Encryption>,
+}
+
+impl Capabilities {
+    /// What devices from before `/api/capabilities` accept
+    pub fn legacy() -> Self {
+        Self {
+            max_file_size: u64::MAX,
+            hash_algorithms: vec![HashAlgorithm::Sha256],
+            uploads: vec![UploadMethod::Resumable, UploadMethod::Sparse],
+            encryption: Vec::new(),
+        }
+    }
+
+    /// The algorithm to compare contents with, or why there is none
+    pub fn hash_algorithm(&self) -> Result<HashAlgorithm, CapabilityError> {
+        self.hash_algorithms
+            .iter()
+            .copied()
+            .find(|hash| *hash == HashAlgorithm::Sha256)
+            .ok_or(CapabilityError::NoHashAlgorithm)
+    }
+}
+
+/// Why files cannot be pushed to a peer
+#[derive(Error, Debug, PartialEq)]
+pub enum CapabilityError {
+    #[error("The peer does not accept uploads right now, it may be serving a read-only snapshot")]
+    ReadOnly,
+    #[error("The peer only accepts uploads this version cannot send ({offered}), update RustDrop on one of the devices")]
+    NoUploadMethod { offered: String },
+    #[error("{name} is {}, the peer only accepts files up to {}", format_size(*size, BINARY), format_size(*max, BINARY))]
+    TooLarge { name: String, size: u64, max: u64 },
+    #[error("The peer cannot hash files with an algorithm this version knows, update RustDrop on one of the devices")]
+    NoHashAlgorithm,
+}
+
+/// How to push files to one peer, agreed from its capabilities
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferStrategy {
+    /// `Resumable` or `Multipart`
+    pub method: UploadMethod,
+    /// Announce runs of zeroes instead of sending them
+    pub sparse: bool,
+    /// Algorithm to compare contents with, if the peer hashes with one this version knows
+    pub hash: Option<HashAlgorithm>,
+    /// Whether the peer runs the QUIC transport
+    pub quic: bool,
+    pub max_file_size: u64,
+}
+
+impl TransferStrategy {
+    /// The best way to push files to a peer with `peer` capabilities
+    pub fn negotiate(peer: &Capabilities) -> Result<Self, CapabilityError> {
+        if peer.uploads.is_empty() {
+            return Err(CapabilityError::ReadOnly);
+        }
+        let method = [UploadMethod::Resumable, UploadMethod::Multipart]
+            .into_iter()
+            .find(|method| peer.uploads.contains(method))
+            .ok_or_else(|| CapabilityError::NoUploadMethod { offered: describe(&peer.uploads) })?;
+
+        Ok(Self {
+            method,
+            sparse: method == UploadMethod::Resumable && peer.uploads.contains(&UploadMethod::Sparse),
+            hash: peer.hash_algorithm().ok(),
+            quic: peer.encryption.contains(&Encryption::Quic),
+            max_file_size: peer.max_file_size,
+        })
+    }
+
+    /// Refuse a file the peer would turn away, before sending any of it
+    pub fn check_size(&self, name: &str, size: u64) -> Result<(), CapabilityError> {
+        if size > self.max_file_size {
+            return Err(CapabilityError::TooLarge { name: name.to_string(), size, max: self.max_file_size });
+        }
+        Ok(())
+    }
+}
+
+/// Upload methods as they appear in `/api/capabilities`
+fn describe(methods: &[UploadMethod]) -> String {
+    methods
+        .iter()
+        .map(|method| match method {
+            UploadMethod::Unknown => "a newer one".to_string(),
+            method => serde_json::to_value(method).ok().and_then(|value| value.as_str().map(str::to_string)).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let current = Capabilities {
+            max_file_size: 100,
+            hash_algorithms: vec![HashAlgorithm::Sha256],
+            uploads: vec![UploadMethod::Resumable, UploadMethod::Sparse, UploadMethod::Tus, UploadMethod::Multipart],
+            encryption: vec![Encryption::Quic],
+        };
+        let strategy = TransferStrategy::negotiate(&current).unwrap();
+        assert_eq!(strategy.method, UploadMethod::Resumable);
+        assert!(strategy.sparse && strategy.quic);
+        assert_eq!(strategy.hash, Some(HashAlgorithm::Sha256));
+        assert!(strategy.check_size("a.mov", 100).is_ok());
+        assert!(matches!(strategy.check_size("a.mov", 101), Err(CapabilityError::TooLarge { .. })));
+
+        // Peers that only take single requests still get files, without holes skipped
+        let plain = Capabilities { uploads: vec![UploadMethod::Multipart, UploadMethod::Sparse], ..current.clone() };
+        let strategy = TransferStrategy::negotiate(&plain).unwrap();
+        assert_eq!((strategy.method, strategy.sparse), (UploadMethod::Multipart, false));
+
+        let read_only = Capabilities { uploads: Vec::new(), ..current.clone() };
+        assert_eq!(TransferStrategy::negotiate(&read_only).unwrap_err(), CapabilityError::ReadOnly);
+
+        // What a newer version might offer, and this one does not understand
+        let newer: Capabilities = serde_json::from_value(serde_json::json!({
+            "max_file_size": 100,
+            "hash_algorithms": ["blake3"],
+            "uploads": ["tus", "webtransport"],
+            "encryption": [],
+        }))
+        .unwrap();
+        assert_eq!(newer.uploads, [UploadMethod::Tus, UploadMethod::Unknown]);
+        assert_eq!(
+            TransferStrategy::negotiate(&newer).unwrap_err().to_string(),
+            "The peer only accepts uploads this version cannot send (tus, a newer one), update RustDrop on one of the devices"
+        );
+        let hashless = Capabilities { hash_algorithms: newer.hash_algorithms.clone(), ..current };
+        assert_eq!(TransferStrategy::negotiate(&hashless).unwrap().hash, None);
+        assert_eq!(hashless.hash_algorithm(), Err(CapabilityError::NoHashAlgorithm));
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+/// Where a certificate is kept: the certificate, its private key and the names it was made for
+pub struct CertificateFiles {
+    pub cert: &'static str,
+    pub key: &'static str,
+    pub names: &'static str,
+}
+
+/// Self-signed certificate, for the QUIC device identity and the HTTPS web interface
+pub struct SelfSignedCertificate {
+    pub cert: CertificateDer<'static>,
+    key: PrivatePkcs8KeyDer<'static>,
+}
+
+impl SelfSignedCertificate {
+    /// Fresh certificate for `names`, not stored anywhere
+    pub fn generate(names: Vec<String>) -> Result<Self> {
+        let certified = rcgen::generate_simple_self_signed(names).context("Failed to generate certificate")?;
+
+        Ok(Self {
+            cert: certified.cert.der().clone(),
+            key: PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()),
+        })
+    }
+
+    /// Certificate kept in `directory`, so the same one is presented every time. It is created
+    /// on first use and again whenever `names` changed, e.g. because the address did.
+    pub fn load_or_create(directory: &Path, files: &CertificateFiles, names: Vec<String>) -> Result<Self> {
+        let cert_path = directory.join(files.cert);
+        let key_path = directory.join(files.key);
+        let names_path = directory.join(files.names);
+
+        let stored_names = std::fs::read_to_string(&names_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Vec<String>>(&content).ok());
+        if stored_names.as_ref() == Some(&names) {
+            if let (Ok(cert), Ok(key)) = (std::fs::read(&cert_path), std::fs::read(&key_path)) {
+                return Ok(Self {
+                    cert: CertificateDer::from(cert),
+                    key: PrivatePkcs8KeyDer::from(key),
+                });
+            }
+        }
+
+        let certificate = Self::generate(names.clone())?;
+        std::fs::create_dir_all(directory)?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&key_path)
+            .and_then(|mut file| file.write_all(certificate.key.secret_pkcs8_der()))
+            .with_context(|| format!("Failed to save {:?}", key_path))?;
+        std::fs::write(&cert_path, certificate.cert.as_ref())
+            .with_context(|| format!("Failed to save {:?}", cert_path))?;
+        std::fs::write(&names_path, serde_json::to_string(&names)?)
+            .with_context(|| format!("Failed to save {:?}", names_path))?;
+        Ok(certificate)
+    }
+
+    pub fn private_key(&self) -> PrivateKeyDer<'static> {
+        PrivateKeyDer::Pkcs8(self.key.clone_key())
+    }
+
+    pub fn fingerprint(&self) -> String {
+        certificate_fingerprint(&self.cert)
+    }
+}
+
+/// SHA-256 of a DER certificate as lowercase hex, which peers pin instead of trusting a CA
+pub fn certificate_fingerprint(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const FILES: CertificateFiles = CertificateFiles {
+        cert: "test-cert.der",
+        key: "test-key.der",
+        names: "test-cert-names.json",
+    };
+
+    #[test]
+    fn test_certificate_is_reused() {
+        let temp_dir = TempDir::new().unwrap();
+        let names = vec!["device-a".to_string()];
+
+        let first = SelfSignedCertificate::load_or_create(temp_dir.path(), &FILES, names.clone()).unwrap();
+        let second = SelfSignedCertificate::load_or_create(temp_dir.path(), &FILES, names.clone()).unwrap();
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        assert_eq!(first.fingerprint().len(), 64);
+
+        let other = SelfSignedCertificate::generate(names).unwrap();
+        assert_ne!(first.fingerprint(), other.fingerprint());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(temp_dir.path().join(FILES.key)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_certificate_follows_names() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let first = SelfSignedCertificate::load_or_create(temp_dir.path(), &FILES, vec!["192.168.1.7".to_string()]).unwrap();
+        let moved = SelfSignedCertificate::load_or_create(temp_dir.path(), &FILES, vec!["192.168.1.8".to_string()]).unwrap();
+        assert_ne!(first.fingerprint(), moved.fingerprint());
+    }
+}
@@ -0,0 +1,357 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::core::error::{AppError, AppResult};
+use crate::core::metadata::STATE_DIR;
+
+const REVOKED_FILE: &str = "revoked-clients.json";
+
+/// How long a client may stay silent before its session is forgotten
+const SESSION_IDLE_HOURS: i64 = 24;
+
+/// Longest value of a field of `X-RustDrop-Client` that is believed
+const MAX_AGENT_FIELD_LENGTH: usize = 64;
+
+/// Name other clients may see for the client `id`: a hash of its cookie or token, which cannot
+/// be used in place of either
+pub fn client_handle(id: &str) -> String {
+    Sha256::new()
+        .chain_update(b"rustdrop client ")
+        .chain_update(id.as_bytes())
+        .finalize()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// What kind of program a client is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientKind {
+    Browser,
+    /// Another RustDrop device, or its command line
+    Peer,
+    /// Scripts, tus clients, companion apps and anything else
+    Other,
+}
+
+impl ClientKind {
+    fn of(user_agent: Option<&str>, peer: Option<&PeerAgent>) -> Self {
+        if peer.is_some() {
+            Self::Peer
+        } else if user_agent.is_some_and(|agent| agent.starts_with("Mozilla/")) {
+            Self::Browser
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// How a RustDrop peer introduces itself in `X-RustDrop-Client`:
+/// `rustdrop/<version>; device=<device id>; os=<os>`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeerAgent {
+    pub version: String,
+    pub device: String,
+    pub os: String,
+}
+
+impl PeerAgent {
+    /// This build running as the device `device`
+    pub fn this_device(device: &str) -> Self {
+        Self {
+            version: crate::VERSION.to_string(),
+            device: device.to_string(),
+            os: std::env::consts::OS.to_string(),
+        }
+    }
+
+    /// Read a header value, `None` unless it names RustDrop and a device. Fields are kept to
+    /// short runs of visible ASCII, so they are safe to log.
+    pub fn parse(value: &str) -> Option<Self> {
+        let valid = |field: &str| {
+            !field.is_empty() && field.len() <= MAX_AGENT_FIELD_LENGTH && field.chars().all(|c| c.is_ascii_graphic())
+        };
+        let mut parts = value.split(';').map(str::trim);
+        let version = parts.next()?.strip_prefix("rustdrop/").filter(|version| valid(version))?;
+        let (mut device, mut os) = (None, None);
+        for part in parts {
+            match part.split_once('=') {
+                Some(("device", value)) if valid(value) => device = Some(value),
+                Some(("os", value)) if valid(value) => os = Some(value),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            version: version.to_string(),
+            device: device?.to_string(),
+            os: os.unwrap_or("unknown").to_string(),
+        })
+    }
+}
+
+impl fmt::Display for PeerAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rustdrop/{}; device={}; os={}", self.version, self.device, self.os)
+    }
+}
+
+/// A browser or peer that has talked to this server since it started
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientSession {
+    /// Handle of the client, see [`client_handle`]
+    pub id: String,
+    /// Cookie or token of the client, never served
+    #[serde(skip)]
+    token: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub kind: ClientKind,
+    /// What the client said about itself if it is a RustDrop peer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer: Option<PeerAgent>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// Request bodies received from the client
+    pub bytes_received: u64,
+    /// Response bodies sent to the client
+    pub bytes_sent: u64,
+}
+
+/// Active client sessions, and the ids the host revoked.
+///
+/// Sessions are only kept in memory, and forgotten after [`SESSION_IDLE_HOURS`] without a
+/// request; revocations are saved so a revoked cookie or token stays useless after a restart.
+pub struct ClientRegistry {
+    path: PathBuf,
+    sessions: Mutex<HashMap<String, ClientSession>>,
+    revoked: Mutex<BTreeSet<String>>,
+    /// Clients whose fingerprint the host terminal already showed
+    fingerprinted: Mutex<HashSet<String>>,
+}
+
+impl ClientRegistry {
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(STATE_DIR).join(REVOKED_FILE);
+
+        let revoked = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable revoked clients file {:?}: {}", path, e);
+                BTreeSet::new()
+            }),
+            Err(_) => BTreeSet::new(),
+        };
+
+        Self {
+            path,
+            sessions: Mutex::new(HashMap::new()),
+            revoked: Mutex::new(revoked),
+            fingerprinted: Mutex::default(),
+        }
+    }
+
+    /// Note a request from `id`, refreshing its address, user agent and peer details. Returns
+    /// whether the client is new.
+    pub fn touch(
+        &self,
+        id: &str,
+        ip: Option<String>,
+        user_agent: Option<String>,
+        peer: Option<PeerAgent>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let is_new = !sessions.contains_key(id);
+        if is_new {
+            let idle = chrono::Duration::hours(SESSION_IDLE_HOURS);
+            let mut fingerprinted = self.fingerprinted.lock().unwrap();
+            sessions.retain(|token, session| {
+                let active = now - session.last_seen <= idle;
+                if !active {
+                    fingerprinted.remove(token);
+                }
+                active
+            });
+        }
+        let session = sessions.entry(id.to_string()).or_insert_with(|| ClientSession {
+            id: client_handle(id),
+            token: id.to_string(),
+            ip: None,
+            user_agent: None,
+            kind: ClientKind::Other,
+            peer: None,
+            first_seen: now,
+            last_seen: now,
+            bytes_received: 0,
+            bytes_sent: 0,
+        });
+
+        session.last_seen = now;
+        if ip.is_some() {
+            session.ip = ip;
+        }
+        if user_agent.is_some() {
+            session.user_agent = user_agent;
+        }
+        if peer.is_some() {
+            session.peer = peer;
+        }
+        session.kind = ClientKind::of(session.user_agent.as_deref(), session.peer.as_ref());
+        is_new
+    }
+
+    pub fn add_traffic(&self, id: &str, received: u64, sent: u64) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(id) {
+            session.bytes_received += received;
+            session.bytes_sent += sent;
+        }
+    }
+
+    /// Sessions, most recently active first
+    pub fn list(&self) -> Vec<ClientSession> {
+        let mut sessions: Vec<ClientSession> = self.sessions.lock().unwrap().values().cloned().collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen));
+        sessions
+    }
+
+    /// Whether `id` asks for its fingerprint for the first time since the server started
+    pub fn first_fingerprint(&self, id: &str) -> bool {
+        self.fingerprinted.lock().unwrap().insert(id.to_string())
+    }
+
+    pub fn is_revoked(&self, id: &str) -> bool {
+        self.revoked.lock().unwrap().contains(id)
+    }
+
+    /// Drop the session with the handle `handle` and refuse its cookie or token from now on.
+    /// Returns the cookie or token, or `None` if no such session exists.
+    pub fn revoke(&self, handle: &str) -> AppResult<Option<String>> {
+        let token = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let Some(token) = sessions.values().find(|session| session.id == handle).map(|session| session.token.clone())
+            else {
+                return Ok(None);
+            };
+            sessions.remove(&token);
+            token
+        };
+
+        let mut revoked = self.revoked.lock().unwrap();
+        revoked.insert(token.clone());
+        self.save(&revoked)?;
+        Ok(Some(token))
+    }
+
+    fn save(&self, revoked: &BTreeSet<String>) -> AppResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(revoked)
+            .map_err(|e| AppError::File(format!("Failed to serialize revoked clients: {}", e)))?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sessions_track_traffic() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = ClientRegistry::load(temp_dir.path());
+        let start = Utc::now();
+
+        assert!(registry.touch("phone", Some("192.168.1.5".to_string()), Some("Safari".to_string()), None, start));
+        registry.touch("laptop", Some("192.168.1.6".to_string()), None, None, start);
+        assert!(!registry.touch("phone", None, None, None, start + chrono::Duration::seconds(5)));
+        registry.add_traffic("phone", 100, 2000);
+        registry.add_traffic("phone", 50, 0);
+
+        let sessions = registry.list();
+        assert_eq!(sessions.len(), 2);
+        let phone = &sessions[0];
+        assert_eq!(phone.id, client_handle("phone"));
+        // Only the handle is served, never the cookie itself
+        assert!(!serde_json::to_string(phone).unwrap().contains("\"phone\""));
+        assert_eq!(phone.ip.as_deref(), Some("192.168.1.5"));
+        assert_eq!(phone.user_agent.as_deref(), Some("Safari"));
+        assert_eq!(phone.first_seen, start);
+        assert_eq!((phone.bytes_received, phone.bytes_sent), (150, 2000));
+    }
+
+    #[test]
+    fn test_idle_sessions_are_forgotten() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = ClientRegistry::load(temp_dir.path());
+        let start = Utc::now();
+
+        registry.touch("phone", None, None, None, start);
+        registry.touch("laptop", None, None, None, start + chrono::Duration::hours(SESSION_IDLE_HOURS));
+        assert_eq!(registry.list().len(), 2);
+        registry.touch("tablet", None, None, None, start + chrono::Duration::hours(SESSION_IDLE_HOURS + 1));
+        let tokens: Vec<_> = registry.list().into_iter().map(|session| session.token).collect();
+        assert_eq!(tokens, ["tablet", "laptop"]);
+    }
+
+    #[test]
+    fn test_revocation_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = ClientRegistry::load(temp_dir.path());
+        registry.touch("phone", None, None, None, Utc::now());
+
+        assert_eq!(registry.revoke("unknown").unwrap(), None);
+        assert_eq!(registry.revoke("phone").unwrap(), None);
+        assert_eq!(registry.revoke(&client_handle("phone")).unwrap().as_deref(), Some("phone"));
+        assert!(registry.is_revoked("phone"));
+        assert!(registry.list().is_empty());
+
+        let reloaded = ClientRegistry::load(temp_dir.path());
+        assert!(reloaded.is_revoked("phone"));
+    }
+
+    #[test]
+    fn test_peer_agent() {
+        let agent = PeerAgent::this_device("5f0c6f1e-4a5e-4cde-9d7b-2b2a3f0e9c11");
+        assert_eq!(PeerAgent::parse(&agent.to_string()), Some(agent.clone()));
+
+        let parsed = PeerAgent::parse("rustdrop/0.1.0; device=abc").unwrap();
+        assert_eq!((parsed.version.as_str(), parsed.os.as_str()), ("0.1.0", "unknown"));
+        assert_eq!(PeerAgent::parse("curl/8.0; device=abc"), None);
+        assert_eq!(PeerAgent::parse("rustdrop/0.1.0; os=linux"), None);
+        assert_eq!(PeerAgent::parse(&format!("rustdrop/0.1.0; device={}", "x".repeat(65))), None);
+
+        let temp_dir = TempDir::new().unwrap();
+        let registry = ClientRegistry::load(temp_dir.path());
+        let safari = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) Safari/604.1";
+        registry.touch("phone", None, Some(safari.to_string()), None, Utc::now());
+        registry.touch("laptop", None, Some("RustDrop/0.1.0".to_string()), Some(agent), Utc::now());
+        registry.touch("script", None, Some("curl/8.0".to_string()), None, Utc::now());
+        let kinds: HashMap<_, _> = registry.list().into_iter().map(|session| (session.token, session.kind)).collect();
+        assert_eq!(kinds["phone"], ClientKind::Browser);
+        assert_eq!(kinds["laptop"], ClientKind::Peer);
+        assert_eq!(kinds["script"], ClientKind::Other);
+    }
+
+    #[test]
+    fn test_fingerprint_announced_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = ClientRegistry::load(temp_dir.path());
+
+        assert!(registry.first_fingerprint("phone"));
+        assert!(!registry.first_fingerprint("phone"));
+        assert!(registry.first_fingerprint("laptop"));
+    }
+}
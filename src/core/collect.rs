@@ -0,0 +1,168 @@
+use chrono::{DateTime, Duration, Utc};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::core::models::FileInfo;
+
+/// Requests nobody answered are forgotten after this long
+const REQUEST_MAX_AGE_HOURS: i64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectStatus {
+    Pending,
+    Approved,
+    Declined,
+}
+
+/// Another device asking to download the files matching a pattern
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectRequest {
+    pub id: String,
+    /// Name the collecting device introduced itself with
+    pub requester: String,
+    pub pattern: String,
+    pub status: CollectStatus,
+    pub created: DateTime<Utc>,
+    /// Files the owner agreed to hand over, filled in on approval
+    #[serde(default)]
+    pub files: Vec<FileInfo>,
+    /// Client that made the request, which may not answer it itself
+    #[serde(skip)]
+    pub client_id: String,
+}
+
+/// Open collection requests, kept in memory until answered and fetched or expired
+#[derive(Default)]
+pub struct CollectRequests {
+    requests: Mutex<HashMap<String, CollectRequest>>,
+}
+
+impl CollectRequests {
+    pub fn create(&self, requester: &str, pattern: &str, client_id: &str) -> CollectRequest {
+        let request = CollectRequest {
+            id: Uuid::new_v4().to_string(),
+            requester: requester.to_string(),
+            pattern: pattern.to_string(),
+            status: CollectStatus::Pending,
+            created: Utc::now(),
+            files: Vec::new(),
+            client_id: client_id.to_string(),
+        };
+
+        let mut requests = self.requests.lock().unwrap();
+        let cutoff = Utc::now() - Duration::hours(REQUEST_MAX_AGE_HOURS);
+        requests.retain(|_, existing| existing.created > cutoff);
+        requests.insert(request.id.clone(), request.clone());
+        request
+    }
+
+    pub fn get(&self, id: &str) -> Option<CollectRequest> {
+        self.requests.lock().unwrap().get(id).cloned()
+    }
+
+    /// Requests still waiting for an answer, oldest first
+    pub fn pending(&self) -> Vec<CollectRequest> {
+        let mut pending: Vec<CollectRequest> = self
+            .requests
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|request| request.status == CollectStatus::Pending)
+            .cloned()
+            .collect();
+        pending.sort_by_key(|request| request.created);
+        pending
+    }
+
+    /// Record the owner's answer, handing over `files` if approved.
+    /// Returns `None` if the request does not exist or was already answered.
+    pub fn decide(&self, id: &str, approve: bool, files: Vec<FileInfo>) -> Option<CollectRequest> {
+        let mut requests = self.requests.lock().unwrap();
+        let request = requests
+            .get_mut(id)
+            .filter(|request| request.status == CollectStatus::Pending)?;
+
+        if approve {
+            request.status = CollectStatus::Approved;
+            request.files = files;
+        } else {
+            request.status = CollectStatus::Declined;
+        }
+        Some(request.clone())
+    }
+}
+
+/// Parse a collection pattern like `*.jpg` or `photos/**/*.heic`
+pub fn parse_pattern(pattern: &str) -> Option<Pattern> {
+    Pattern::new(pattern.trim()).ok()
+}
+
+/// Files matching `pattern`: patterns with a `/` are matched against the path in the share,
+/// others against the file name only
+pub fn matching_files(files: Vec<FileInfo>, pattern: &Pattern) -> Vec<FileInfo> {
+    let options = MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    let match_path = pattern.as_str().contains('/');
+
+    files
+        .into_iter()
+        .filter(|file| {
+            let target = if match_path {
+                file.name.as_str()
+            } else {
+                file.name.rsplit('/').next().unwrap_or(&file.name)
+            };
+            pattern.matches_with(target, options)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str) -> FileInfo {
+        FileInfo {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_matching_files() {
+        let files = vec![file("IMG_1.JPG"), file("trip/beach.jpg"), file("notes.txt")];
+        let names = |pattern: &str| -> Vec<String> {
+            matching_files(files.clone(), &parse_pattern(pattern).unwrap())
+                .into_iter()
+                .map(|f| f.name)
+                .collect()
+        };
+
+        assert_eq!(names("*.jpg"), vec!["IMG_1.JPG", "trip/beach.jpg"]);
+        assert_eq!(names("trip/*"), vec!["trip/beach.jpg"]);
+        assert_eq!(names("*"), vec!["IMG_1.JPG", "trip/beach.jpg", "notes.txt"]);
+        assert!(parse_pattern("[").is_none());
+    }
+
+    #[test]
+    fn test_requests_are_answered_once() {
+        let requests = CollectRequests::default();
+        let request = requests.create("Laptop", "*.jpg", "client");
+        assert_eq!(requests.pending().len(), 1);
+
+        let approved = requests.decide(&request.id, true, vec![file("a.jpg")]).unwrap();
+        assert_eq!(approved.status, CollectStatus::Approved);
+        assert_eq!(approved.files.len(), 1);
+
+        assert!(requests.decide(&request.id, false, Vec::new()).is_none());
+        assert!(requests.pending().is_empty());
+        assert_eq!(requests.get(&request.id).unwrap().status, CollectStatus::Approved);
+    }
+}
@@ -1,23 +1,102 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use anyhow::Result;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub files: FilesConfig,
+    /// Limits of each share, by the name of its directory
+    #[serde(default)]
+    pub shares: BTreeMap<String, ShareConfig>,
+    #[serde(default)]
+    pub storage: StorageConfig,
     pub discovery: DiscoveryConfig,
     pub ui: UiConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub client: ClientConfig,
+    #[serde(default)]
+    pub quic: QuicConfig,
+    #[serde(default)]
+    pub access: AccessConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub printer: PrinterConfig,
+    #[serde(default)]
+    pub email: EmailConfig,
+    #[serde(default)]
+    pub ocr: OcrConfig,
+    #[serde(default)]
+    pub transcode: TranscodeConfig,
+    #[serde(default)]
+    pub backup: BackupConfig,
+    #[serde(default)]
+    pub courier: CourierConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Programs run on every upload they are interested in
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Ports (first and last) to pick from when `port` is taken, instead of searching widely
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port_range: Option<[u16; 2]>,
     #[serde(default = "default_host")]
     pub host: String,
     #[serde(default = "default_max_file_size")]
     pub max_file_size: u64,
+    /// Reverse proxies (addresses or CIDR ranges) whose `X-Forwarded-*` headers are believed
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Seconds a client has to send the headers of a request
+    #[serde(default = "default_header_timeout")]
+    pub header_timeout_secs: u64,
+    /// Seconds a connection may neither send nor receive anything before it is closed, 0 to never
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout_secs: u64,
+    /// Connections served at once; further clients wait until one closes
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+    /// Daily windows in local time (`"08:00-22:00"`) when clients are served and the server is
+    /// announced; empty for always
+    #[serde(default)]
+    pub schedule: Vec<String>,
+    #[serde(default)]
+    pub tls: TlsConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Serve HTTPS with a self-signed certificate generated on first run (needs the `tls` feature)
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Settings of one share, under `[shares.<directory name>]`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareConfig {
+    /// Most bytes the share may hold; uploads that would go over it are refused
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// Delete the files received longest ago to make room under `max_size` instead
+    #[serde(default)]
+    pub evict_oldest: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +104,114 @@ pub struct FilesConfig {
     pub directory: Option<PathBuf>,
     #[serde(default = "default_file_expiry")]
     pub expiry_hours: Option<u64>,
+    /// Place uploads from named senders into `inbox/<sender>/`
+    #[serde(default)]
+    pub per_sender_subdirs: bool,
+    /// File uploads into `YYYY/MM/` subfolders
+    #[serde(default)]
+    pub organize_by_date: DateOrganization,
+    /// Serve whatever symlinks point to. When off, only links into the share itself or into
+    /// `allowed_symlink_targets` are followed.
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Directories outside the share that symlinks may deliberately point into
+    #[serde(default)]
+    pub allowed_symlink_targets: Vec<PathBuf>,
+    /// List dotfiles and system files such as `Thumbs.db` and `.DS_Store`
+    #[serde(default)]
+    pub show_hidden: bool,
+    /// Give files received from other devices the permission bits they had there (Unix only)
+    #[serde(default)]
+    pub preserve_permissions: bool,
+    /// Carry extended attributes (Finder tags, quarantine flags) between devices that both enable this
+    #[serde(default)]
+    pub preserve_xattrs: bool,
+    /// Serve the share as it was at startup, read-only
+    #[serde(default)]
+    pub snapshot: SnapshotMode,
+}
+
+/// Whether the share is served as a frozen view taken at startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotMode {
+    /// Serve the share as it is
+    #[default]
+    Off,
+    /// List the files found at startup; files changed since are no longer served
+    Index,
+    /// Copy the files to a temporary directory at startup and serve the copies
+    Copy,
+}
+
+/// Which date decides the `YYYY/MM/` folder of an upload
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateOrganization {
+    /// Keep uploads where they are
+    #[default]
+    Off,
+    /// The time the upload arrived
+    Upload,
+    /// The EXIF capture date of photos, falling back to the upload time
+    Exif,
+}
+
+/// Network storage that received files are kept in, besides or instead of the share
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: StorageBackend,
+    /// Keep received files in the share once they are stored in the backend
+    #[serde(default = "default_true")]
+    pub keep_local: bool,
+    /// Bytes of files read from an S3 or SMB backend kept in the share's state directory, so
+    /// popular downloads are fetched once (0 turns the cache off)
+    #[serde(default = "default_cache_size")]
+    pub cache_size: u64,
+    /// Directory files are copied to with the `local` backend, like a mounted NAS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3: Option<S3Config>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smb: Option<SmbConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// The share only, or the directory in `path` when set
+    #[default]
+    Local,
+    /// An S3 bucket, on AWS or a compatible server like MinIO
+    S3,
+    /// A Windows or Samba file share, mounted when the server starts
+    Smb,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Config {
+    /// Server URL, like `https://s3.eu-central-1.amazonaws.com` or `http://nas:9000`
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Key prefix the files are stored under, like `rustdrop/`
+    #[serde(default)]
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmbConfig {
+    /// Share to mount, like `smb://nas/photos` or `smb://nas/photos/inbox`
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,35 +226,451 @@ pub struct UiConfig {
     pub qr_code: bool,
     #[serde(default = "default_false")]
     pub open_browser: bool,
+    /// Name of the Wi-Fi network devices must join, shown on the `/welcome` page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_name: Option<String>,
+}
+
+/// How this device introduces itself when it calls other devices
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    /// `User-Agent` of calls to peers, for proxies that filter on it; `RustDrop/<version>
+    /// (<os>; device <id>)` when unset. Peers still learn the version from `X-RustDrop-Client`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// How `rustdrop sync` settles files changed on both sides
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// The most recently modified version wins
+    #[default]
+    Newest,
+    /// Keep both, renaming the local version to a conflicted copy
+    KeepBoth,
+    /// Ask on the terminal for every conflict
+    Prompt,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuicConfig {
+    /// Also accept transfers from other instances over QUIC, on the server port over UDP
+    /// (needs the `quic` feature)
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrinterConfig {
+    /// Offer a network printer that saves what is printed to it as PDFs in the share, open to
+    /// anyone on the network (needs the `ipp` feature)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Name the printer is shown under, "RustDrop on <device>" by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    /// Accept mail over SMTP and save its attachments in the share (needs the `email` feature)
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_email_port")]
+    pub port: u16,
+    /// Only accept mail to this address; any recipient when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Only accept mail from these addresses, or from anyone when empty
+    #[serde(default)]
+    pub allowed_senders: Vec<String>,
+    /// Largest message accepted, attachments included
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: u64,
+}
+
+/// Text recognition in uploaded PDFs and images, for search (needs the `ocr` feature)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Tesseract languages to recognize, like `eng` or `deu+eng`
+    #[serde(default = "default_ocr_languages")]
+    pub languages: String,
+    /// Seconds a document may take before recognition is given up
+    #[serde(default = "default_ocr_timeout")]
+    pub timeout_secs: u64,
+}
+
+/// Copies of videos every browser can play (needs the `transcode` feature)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TranscodeConfig {
+    /// Convert uploaded videos some browsers cannot play right away, instead of when one fails
+    #[serde(default)]
+    pub background: bool,
+}
+
+/// Photo backup from phones at `/backup`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    /// Folder of the share backups go to, with a subfolder per device and month
+    #[serde(default = "default_backup_directory")]
+    pub directory: PathBuf,
+}
+
+/// Devices files are carried to through an outbox whenever they can be reached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CourierConfig {
+    #[serde(default)]
+    pub peers: Vec<CourierPeer>,
+    /// Seconds between attempts to reach peers with files waiting
+    #[serde(default = "default_courier_retry")]
+    pub retry_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CourierPeer {
+    /// Name of the peer's outbox, `outbox/<name>/`
+    pub name: String,
+    /// `host:port` or URL of the peer
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessConfig {
+    /// Make new clients enter a code shown in the host terminal before they can see anything
+    #[serde(default)]
+    pub require_code: bool,
+    /// How long each access code stays valid
+    #[serde(default = "default_code_lifetime")]
+    pub code_lifetime_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    /// PIN new clients have to enter before they can use the web UI or API, printed next to the
+    /// QR code so only people who can see the host connect. Takes the place of `access` codes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+}
+
+impl SecurityConfig {
+    /// The configured PIN, unless it is blank
+    pub fn pin(&self) -> Option<&str> {
+        self.pin.as_deref().map(str::trim).filter(|pin| !pin.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertsConfig {
+    /// Networks (addresses or CIDR ranges) clients should come from; API requests from
+    /// anywhere else raise an alert. Empty to expect clients from anywhere.
+    #[serde(default)]
+    pub expected_networks: Vec<String>,
+    /// Raise an alert when a device that never entered an access code uses the API
+    #[serde(default)]
+    pub unpaired: bool,
+    /// Ring the terminal bell with every alert
+    #[serde(default = "default_true")]
+    pub bell: bool,
+    /// Also show alerts as desktop notifications
+    #[serde(default)]
+    pub notify: bool,
+    /// URL every alert is POSTed to as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+}
+
+/// Messages pushed to the host's phone or chat when files arrive or transfers fail
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    /// Notify about uploads, several arriving together in one message
+    #[serde(default = "default_true")]
+    pub uploads: bool,
+    /// Notify about uploads failing for lack of space and transfers to other devices failing
+    #[serde(default = "default_true")]
+    pub failures: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ntfy: Option<NtfyConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub telegram: Option<TelegramConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<MatrixConfig>,
+    /// URL every notification is POSTed to as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NtfyConfig {
+    /// Topic URL, like `https://ntfy.sh/my-rustdrop`
+    pub url: String,
+    /// Access token for protected topics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramConfig {
+    /// Token of the bot sending the messages, from @BotFather
+    pub bot_token: String,
+    /// Chat the bot writes to
+    pub chat_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixConfig {
+    /// Base URL of the homeserver, like `https://matrix.org`
+    pub homeserver: String,
+    /// Access token of the account posting the messages
+    pub access_token: String,
+    /// Room to post in, like `!abcdef:matrix.org`
+    pub room_id: String,
+}
+
+/// An external program processing uploads, like an OCR tool or a transcoder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginConfig {
+    /// Name in the logs, and the sender of the files the plugin produces
+    pub name: String,
+    /// Program and arguments; `{file}` is replaced by the path of the upload, which is appended
+    /// when no argument mentions it
+    pub command: Vec<String>,
+    /// MIME types the plugin is run for, like `application/pdf` or `image/*`; all when empty
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    /// Seconds the program may run before it is killed
+    #[serde(default = "default_plugin_timeout")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// OTLP/gRPC endpoint of an OpenTelemetry collector to send traces to, like
+    /// `http://localhost:4317` (needs the `otlp` feature)
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` the traces are reported under
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Also write the log to this file, for installs running as a daemon
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Filter for the log file, like `info` or `rustdrop=debug`
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    /// When to start a new log file, which gets the date appended to its name
+    #[serde(default)]
+    pub rotate: LogRotation,
+    /// Rotated log files to keep, all of them when unset
+    #[serde(default)]
+    pub max_files: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    #[default]
+    Daily,
 }
 
 // Default value functions
 fn default_port() -> u16 { 8080 }
 fn default_host() -> String { "0.0.0.0".to_string() }
 fn default_max_file_size() -> u64 { 1024 * 1024 * 1024 } // 1GB
+fn default_header_timeout() -> u64 { 10 }
+fn default_idle_timeout() -> u64 { 60 }
+fn default_max_connections() -> usize { 64 }
 fn default_file_expiry() -> Option<u64> { None }
+fn default_code_lifetime() -> u64 { 120 }
+fn default_service_name() -> String { "rustdrop".to_string() }
+fn default_log_level() -> String { "info".to_string() }
+fn default_s3_region() -> String { "us-east-1".to_string() }
+fn default_email_port() -> u16 { 2525 }
+fn default_max_message_size() -> u64 { 50 * 1024 * 1024 }
+fn default_plugin_timeout() -> u64 { 60 }
+fn default_backup_directory() -> PathBuf { PathBuf::from("Backup") }
+fn default_courier_retry() -> u64 { 60 }
+fn default_ocr_languages() -> String { "eng".to_string() }
+fn default_ocr_timeout() -> u64 { 300 }
 fn default_true() -> bool { true }
+fn default_cache_size() -> u64 { 1024 * 1024 * 1024 }
 fn default_false() -> bool { false }
 
-impl Default for AppConfig {
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: default_port(),
+            port_range: None,
+            host: default_host(),
+            max_file_size: default_max_file_size(),
+            trusted_proxies: Vec::new(),
+            header_timeout_secs: default_header_timeout(),
+            idle_timeout_secs: default_idle_timeout(),
+            max_connections: default_max_connections(),
+            schedule: Vec::new(),
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The configured port range, if any
+    pub fn port_range(&self) -> Option<RangeInclusive<u16>> {
+        self.port_range.map(|[first, last]| first..=last)
+    }
+}
+
+impl Default for FilesConfig {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            expiry_hours: default_file_expiry(),
+            per_sender_subdirs: false,
+            organize_by_date: DateOrganization::Off,
+            follow_symlinks: false,
+            allowed_symlink_targets: Vec::new(),
+            show_hidden: false,
+            preserve_permissions: false,
+            preserve_xattrs: false,
+            snapshot: SnapshotMode::Off,
+        }
+    }
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_true(),
+        }
+    }
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            qr_code: default_true(),
+            open_browser: default_false(),
+            network_name: None,
+        }
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            expected_networks: Vec::new(),
+            unpaired: false,
+            bell: default_true(),
+            notify: false,
+            webhook: None,
+        }
+    }
+}
+
+impl Default for StorageConfig {
     fn default() -> Self {
         Self {
-            server: ServerConfig {
-                port: default_port(),
-                host: default_host(),
-                max_file_size: default_max_file_size(),
-            },
-            files: FilesConfig {
-                directory: None,
-                expiry_hours: default_file_expiry(),
-            },
-            discovery: DiscoveryConfig {
-                enabled: default_true(),
-            },
-            ui: UiConfig {
-                qr_code: default_true(),
-                open_browser: default_false(),
-            },
+            backend: StorageBackend::default(),
+            keep_local: default_true(),
+            cache_size: default_cache_size(),
+            path: None,
+            s3: None,
+            smb: None,
+        }
+    }
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            uploads: default_true(),
+            failures: default_true(),
+            ntfy: None,
+            telegram: None,
+            matrix: None,
+            webhook: None,
+        }
+    }
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_email_port(),
+            address: None,
+            allowed_senders: Vec::new(),
+            max_message_size: default_max_message_size(),
+        }
+    }
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            languages: default_ocr_languages(),
+            timeout_secs: default_ocr_timeout(),
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            directory: default_backup_directory(),
+        }
+    }
+}
+
+impl Default for CourierConfig {
+    fn default() -> Self {
+        Self {
+            peers: Vec::new(),
+            retry_secs: default_courier_retry(),
+        }
+    }
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            require_code: false,
+            code_lifetime_secs: default_code_lifetime(),
+        }
+    }
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: default_service_name(),
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            level: default_log_level(),
+            rotate: LogRotation::default(),
+            max_files: None,
         }
     }
 }
@@ -128,6 +731,10 @@ mod tests {
         assert!(!config.ui.open_browser);
         assert!(config.files.directory.is_none());
         assert!(config.files.expiry_hours.is_none());
+        assert!(!config.files.per_sender_subdirs);
+        assert_eq!(config.sync.conflict_policy, ConflictPolicy::Newest);
+        assert!(config.telemetry.otlp_endpoint.is_none());
+        assert_eq!(config.telemetry.service_name, "rustdrop");
     }
 
     #[test]
@@ -147,11 +754,18 @@ mod tests {
         let toml_content = r#"
             [server]
             port = 9090
+            port_range = [9090, 9099]
             host = "127.0.0.1"
             max_file_size = 500000000
 
             [files]
             expiry_hours = 24
+            per_sender_subdirs = true
+            organize_by_date = "exif"
+
+            [shares.Photos]
+            max_size = 8000000000
+            evict_oldest = true
 
             [discovery]
             enabled = false
@@ -159,17 +773,32 @@ mod tests {
             [ui]
             qr_code = false
             open_browser = true
+
+            [sync]
+            conflict_policy = "keep-both"
+
+            [logging]
+            file = "/var/log/rustdrop/rustdrop.log"
+            rotate = "hourly"
         "#;
 
         let config = AppConfig::from_toml(toml_content).unwrap();
         
         assert_eq!(config.server.port, 9090);
+        assert_eq!(config.server.port_range(), Some(9090..=9099));
         assert_eq!(config.server.host, "127.0.0.1");
         assert_eq!(config.server.max_file_size, 500000000);
         assert_eq!(config.files.expiry_hours, Some(24));
+        assert_eq!(config.shares["Photos"], ShareConfig { max_size: Some(8000000000), evict_oldest: true });
+        assert!(config.files.per_sender_subdirs);
+        assert_eq!(config.files.organize_by_date, DateOrganization::Exif);
         assert!(!config.discovery.enabled);
         assert!(!config.ui.qr_code);
         assert!(config.ui.open_browser);
+        assert_eq!(config.sync.conflict_policy, ConflictPolicy::KeepBoth);
+        assert_eq!(config.logging.file, Some(PathBuf::from("/var/log/rustdrop/rustdrop.log")));
+        assert_eq!(config.logging.rotate, LogRotation::Hourly);
+        assert_eq!(config.logging.level, "info");
     }
 
     #[test]
@@ -0,0 +1,210 @@
+//! Store-and-forward delivery to other devices. Every peer under `[courier]` has an outbox,
+//! `outbox/<name>/` in the share, and files put there are carried to the peer's
+//! `inbox/<this device>/` whenever it can be reached, then removed from the outbox.
+//!
+//! Outboxes are plain folders: files can be queued with `POST /api/outbox/<peer>` or dropped in
+//! with a file manager, and whatever is waiting survives restarts.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::core::config::{CourierConfig, CourierPeer};
+use crate::core::models::FileInfo;
+use crate::utils::file::{is_hidden, list_share, sanitize_path_component, set_modified};
+use crate::utils::filename::unused_path;
+
+/// Directory of the share holding the outboxes
+pub const OUTBOX_DIR: &str = "outbox";
+
+/// Files changed more recently than this may still be being copied into an outbox
+const SETTLE_TIME: chrono::Duration = chrono::Duration::seconds(5);
+
+#[derive(Debug, Clone, Default)]
+struct Delivery {
+    reachable: Option<bool>,
+    last_attempt: Option<DateTime<Utc>>,
+    last_delivery: Option<DateTime<Utc>>,
+    delivered: u64,
+    error: Option<String>,
+}
+
+/// How deliveries to a peer are going
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxStatus {
+    pub name: String,
+    pub address: String,
+    /// Files waiting in the outbox
+    pub pending: usize,
+    pub pending_bytes: u64,
+    /// Whether the peer could be reached on the last attempt, None before the first
+    pub reachable: Option<bool>,
+    pub last_attempt: Option<DateTime<Utc>>,
+    pub last_delivery: Option<DateTime<Utc>>,
+    /// Files delivered since the server started
+    pub delivered: u64,
+    /// Why the last delivery failed, cleared once one succeeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The outboxes of a share and how delivering them goes
+pub struct Courier {
+    share: PathBuf,
+    peers: Vec<CourierPeer>,
+    retry: Duration,
+    deliveries: Mutex<HashMap<String, Delivery>>,
+    wake: Notify,
+}
+
+impl Courier {
+    pub fn new(share: &Path, config: &CourierConfig) -> Self {
+        Self {
+            share: share.to_path_buf(),
+            peers: config.peers.clone(),
+            retry: Duration::from_secs(config.retry_secs.max(1)),
+            deliveries: Mutex::default(),
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn peers(&self) -> &[CourierPeer] {
+        &self.peers
+    }
+
+    /// The configured peer of this name, ignoring case
+    pub fn peer(&self, name: &str) -> Option<&CourierPeer> {
+        self.peers.iter().find(|peer| peer.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn outbox(&self, peer: &CourierPeer) -> PathBuf {
+        self.share.join(OUTBOX_DIR).join(sanitize_path_component(&peer.name))
+    }
+
+    /// Files waiting for a peer, named relative to its outbox
+    pub fn pending(&self, peer: &CourierPeer) -> Vec<FileInfo> {
+        let mut files = list_share(&self.outbox(peer)).unwrap_or_default();
+        files.retain(|file| !is_hidden(&file.name));
+        files
+    }
+
+    /// Put a file of the share into a peer's outbox and have the courier look at it right away.
+    /// The outbox gets a hard link where the file system allows, a copy otherwise.
+    pub async fn enqueue(&self, peer: &CourierPeer, file: &FileInfo) -> std::io::Result<PathBuf> {
+        let outbox = self.outbox(peer);
+        tokio::fs::create_dir_all(&outbox).await?;
+        let name = file.name.rsplit('/').next().unwrap_or(&file.name);
+        let target = unused_path(outbox.join(name));
+        if tokio::fs::hard_link(&file.path, &target).await.is_err() {
+            tokio::fs::copy(&file.path, &target).await?;
+            // Keeps the original time for the peer, and the copy counts as settled
+            set_modified(&target, file.modified)?;
+        }
+        self.wake.notify_one();
+        Ok(target)
+    }
+
+    pub fn statuses(&self) -> Vec<OutboxStatus> {
+        let deliveries = self.deliveries.lock().unwrap();
+        self.peers
+            .iter()
+            .map(|peer| {
+                let pending = self.pending(peer);
+                let delivery = deliveries.get(&peer.name).cloned().unwrap_or_default();
+                OutboxStatus {
+                    name: peer.name.clone(),
+                    address: peer.address.clone(),
+                    pending: pending.len(),
+                    pending_bytes: pending.iter().map(|file| file.size).sum(),
+                    reachable: delivery.reachable,
+                    last_attempt: delivery.last_attempt,
+                    last_delivery: delivery.last_delivery,
+                    delivered: delivery.delivered,
+                    error: delivery.error,
+                }
+            })
+            .collect()
+    }
+
+    pub fn unreachable(&self, peer: &CourierPeer) {
+        self.update(peer, |delivery| delivery.reachable = Some(false));
+    }
+
+    pub fn failed(&self, peer: &CourierPeer, error: String) {
+        self.update(peer, |delivery| {
+            delivery.reachable = Some(true);
+            delivery.error = Some(error);
+        });
+    }
+
+    pub fn delivered(&self, peer: &CourierPeer) {
+        self.update(peer, |delivery| {
+            delivery.reachable = Some(true);
+            delivery.last_delivery = delivery.last_attempt;
+            delivery.delivered += 1;
+            delivery.error = None;
+        });
+    }
+
+    fn update(&self, peer: &CourierPeer, change: impl FnOnce(&mut Delivery)) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let delivery = deliveries.entry(peer.name.clone()).or_default();
+        delivery.last_attempt = Some(Utc::now());
+        change(delivery);
+    }
+
+    /// Wait until a file is queued or it is time to try unreachable peers again
+    pub async fn wait(&self) {
+        let _ = tokio::time::timeout(self.retry, self.wake.notified()).await;
+    }
+}
+
+/// Whether a file in an outbox was left alone long enough to be complete
+pub fn settled(file: &FileInfo, now: DateTime<Utc>) -> bool {
+    now - file.modified >= SETTLE_TIME
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::file::get_share_file_info;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_files_wait_in_the_outbox() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CourierConfig {
+            peers: vec![CourierPeer { name: "Laptop".to_string(), address: "10.0.0.2:8080".to_string() }],
+            retry_secs: 60,
+        };
+        let courier = Courier::new(temp_dir.path(), &config);
+        let peer = courier.peer("laptop ").unwrap().clone();
+        assert!(courier.peer("phone").is_none());
+
+        std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        std::fs::write(temp_dir.path().join("docs/report.pdf"), "report").unwrap();
+        let file = get_share_file_info(temp_dir.path(), &temp_dir.path().join("docs/report.pdf")).unwrap();
+        courier.enqueue(&peer, &file).await.unwrap();
+        let second = courier.enqueue(&peer, &file).await.unwrap();
+        assert_eq!(second, temp_dir.path().join("outbox/Laptop/report (2).pdf"));
+
+        let pending = courier.pending(&peer);
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].name, "report (2).pdf");
+        assert!(!settled(&pending[0], pending[0].modified));
+        assert!(settled(&pending[0], pending[0].modified + SETTLE_TIME));
+
+        courier.failed(&peer, "disk full".to_string());
+        courier.delivered(&peer);
+        let [status] = courier.statuses().try_into().unwrap();
+        assert_eq!(status.pending, 2);
+        assert_eq!(status.pending_bytes, 12);
+        assert_eq!(status.reachable, Some(true));
+        assert_eq!(status.delivered, 1);
+        assert_eq!(status.error, None);
+    }
+}
@@ -0,0 +1,96 @@
+//! Live notifications about what happens on the server, for clients that would otherwise poll
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::core::collect::CollectRequest;
+use crate::core::history::HistoryEntry;
+use crate::core::queue::QueuedTransfer;
+use crate::core::relay::RelayOffer;
+use crate::core::storage::StorageReport;
+
+/// Events a slow subscriber may fall behind by before it misses some
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// A file was uploaded, downloaded, deleted or moved
+    Activity(HistoryEntry),
+    /// Another device asks for files and waits for an answer
+    CollectRequested(CollectRequest),
+    /// A file waits to be relayed to whoever downloads it
+    RelayOffered(RelayOffer),
+    /// Uploads started failing because of the share's file system
+    StorageProblem(StorageReport),
+    /// A queued transfer to another device failed
+    TransferFailed(QueuedTransfer),
+    /// The host revoked a client
+    ClientRevoked { id: String },
+    /// The host paused or resumed all transfers
+    TransfersPaused { paused: bool },
+    /// The host moved to another network, so it is reachable at a new address
+    AddressChanged { ip: String, url: String },
+}
+
+impl ServerEvent {
+    /// Name of the event, as in its `type` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Activity(_) => "activity",
+            Self::CollectRequested(_) => "collect_requested",
+            Self::RelayOffered(_) => "relay_offered",
+            Self::StorageProblem(_) => "storage_problem",
+            Self::TransferFailed(_) => "transfer_failed",
+            Self::ClientRevoked { .. } => "client_revoked",
+            Self::TransfersPaused { .. } => "transfers_paused",
+            Self::AddressChanged { .. } => "address_changed",
+        }
+    }
+}
+
+/// The one broadcast channel every live transport subscribes to
+pub struct EventBus {
+    sender: broadcast::Sender<ServerEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+}
+
+impl EventBus {
+    /// Send an event to everyone listening right now; nobody listening is fine
+    pub fn publish(&self, event: ServerEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::history::HistoryAction;
+
+    #[tokio::test]
+    async fn test_subscribers_get_events_published_after_subscribing() {
+        let bus = EventBus::default();
+        bus.publish(ServerEvent::ClientRevoked { id: "before".to_string() });
+
+        let mut receiver = bus.subscribe();
+        bus.publish(ServerEvent::Activity(HistoryEntry::new(HistoryAction::Upload, "a.txt")));
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.name(), "activity");
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "activity");
+        assert_eq!(json["file"], "a.txt");
+        assert_eq!(json["action"], "upload");
+    }
+}
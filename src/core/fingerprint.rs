@@ -0,0 +1,99 @@
+//! Short emoji and word sequences identifying a client's session with this host.
+//!
+//! The host prints the sequence when a browser first asks for it, and the page shows the same
+//! one, so the person holding the phone can check they reached the right machine: a look-alike
+//! server on the network cannot make this host's terminal show anything.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Symbols in a fingerprint, 5 bits each
+const SYMBOLS: usize = 4;
+
+const ALPHABET: [(&str, &str); 32] = [
+    ("🐶", "dog"),
+    ("🐱", "cat"),
+    ("🦊", "fox"),
+    ("🐻", "bear"),
+    ("🐼", "panda"),
+    ("🐨", "koala"),
+    ("🐯", "tiger"),
+    ("🦁", "lion"),
+    ("🐮", "cow"),
+    ("🐷", "pig"),
+    ("🐸", "frog"),
+    ("🐙", "octopus"),
+    ("🐢", "turtle"),
+    ("🦋", "butterfly"),
+    ("🐝", "bee"),
+    ("🐬", "dolphin"),
+    ("🌵", "cactus"),
+    ("🌻", "sunflower"),
+    ("🍄", "mushroom"),
+    ("🌙", "moon"),
+    ("⭐", "star"),
+    ("🔥", "fire"),
+    ("🌈", "rainbow"),
+    ("⛄", "snowman"),
+    ("🍎", "apple"),
+    ("🍋", "lemon"),
+    ("🍉", "melon"),
+    ("🍒", "cherry"),
+    ("🚀", "rocket"),
+    ("⚓", "anchor"),
+    ("🎈", "balloon"),
+    ("🔑", "key"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fingerprint {
+    pub emoji: Vec<&'static str>,
+    pub words: Vec<&'static str>,
+}
+
+impl Fingerprint {
+    /// The fingerprint of `client_id`'s session with the host `device_id`
+    pub fn of_session(device_id: &str, client_id: &str) -> Self {
+        let digest = Sha256::new()
+            .chain_update(b"rustdrop-session-fingerprint\0")
+            .chain_update(device_id.as_bytes())
+            .chain_update(b"\0")
+            .chain_update(client_id.as_bytes())
+            .finalize();
+        let bits = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+
+        let (emoji, words) = (0..SYMBOLS)
+            .map(|index| ALPHABET[(bits >> (27 - 5 * index)) as usize & 31])
+            .unzip();
+        Self { emoji, words }
+    }
+}
+
+impl std::fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pairs: Vec<String> = self
+            .emoji
+            .iter()
+            .zip(&self.words)
+            .map(|(emoji, word)| format!("{} {}", emoji, word))
+            .collect();
+        write!(f, "{}", pairs.join("  "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_depends_on_host_and_client() {
+        let fingerprint = Fingerprint::of_session("host", "client");
+        assert_eq!(fingerprint.emoji.len(), SYMBOLS);
+        assert_eq!(fingerprint.words.len(), SYMBOLS);
+        assert_eq!(Fingerprint::of_session("host", "client"), fingerprint);
+
+        assert_ne!(Fingerprint::of_session("other host", "client"), fingerprint);
+        assert_ne!(Fingerprint::of_session("host", "other client"), fingerprint);
+        assert_eq!(fingerprint.to_string().split("  ").count(), SYMBOLS);
+    }
+}
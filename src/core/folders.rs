@@ -0,0 +1,130 @@
+//! Sizes of folders in the share, worked out in the background so that a large `DCIM` does not
+//! hold up the request asking about it. Results are kept until files change through RustDrop,
+//! or for a few minutes in case they change behind its back.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::utils::usage::folder_size;
+
+/// How long a computed size is trusted when nothing was uploaded, deleted or moved
+const MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Where the size of a folder stands
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum FolderSizeStatus {
+    Computing,
+    Done { bytes: u64, files: u64, computed_at: DateTime<Utc> },
+    Failed { error: String },
+}
+
+struct Entry {
+    status: FolderSizeStatus,
+    started: Instant,
+}
+
+/// Folder sizes computed so far and those being computed
+#[derive(Default)]
+pub struct FolderSizes {
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+    /// Bumped by [`FolderSizes::invalidate`], so walks that began before a change are discarded
+    generation: AtomicU64,
+}
+
+impl FolderSizes {
+    /// The size of a folder if known, starting to compute it otherwise or when the last result
+    /// is stale. A failure is reported once, the next call tries again.
+    pub fn get_or_start(self: &Arc<Self>, directory: PathBuf) -> FolderSizeStatus {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get(&directory) {
+            match entry.status {
+                FolderSizeStatus::Computing => return entry.status.clone(),
+                FolderSizeStatus::Done { .. } if entry.started.elapsed() < MAX_AGE => return entry.status.clone(),
+                FolderSizeStatus::Failed { .. } => return entries.remove(&directory).unwrap().status,
+                FolderSizeStatus::Done { .. } => {}
+            }
+        }
+
+        entries.insert(directory.clone(), Entry { status: FolderSizeStatus::Computing, started: Instant::now() });
+        let generation = self.generation.load(Ordering::SeqCst);
+        let sizes = self.clone();
+        tokio::spawn(async move {
+            let path = directory.clone();
+            let status = match tokio::task::spawn_blocking(move || folder_size(&path)).await {
+                Ok(Ok((bytes, files))) => FolderSizeStatus::Done { bytes, files, computed_at: Utc::now() },
+                Ok(Err(e)) => FolderSizeStatus::Failed { error: e.to_string() },
+                Err(e) => FolderSizeStatus::Failed { error: e.to_string() },
+            };
+            if let FolderSizeStatus::Failed { error } = &status {
+                warn!("Failed to compute the size of {:?}: {}", directory, error);
+            }
+            sizes.finish(directory, generation, status);
+        });
+        FolderSizeStatus::Computing
+    }
+
+    fn finish(&self, directory: PathBuf, generation: u64, status: FolderSizeStatus) {
+        let mut entries = self.entries.lock().unwrap();
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        if let Some(entry) = entries.get_mut(&directory) {
+            entry.status = status;
+        }
+    }
+
+    /// Forget every size, for when files were added, removed or moved
+    pub fn invalidate(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn wait_for_size(sizes: &Arc<FolderSizes>, directory: PathBuf) -> FolderSizeStatus {
+        for _ in 0..100 {
+            let status = sizes.get_or_start(directory.clone());
+            if status != FolderSizeStatus::Computing {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("size of {:?} never computed", directory);
+    }
+
+    #[tokio::test]
+    async fn test_sizes_are_cached_until_invalidated() {
+        let temp_dir = TempDir::new().unwrap();
+        let dcim = temp_dir.path().join("DCIM");
+        std::fs::create_dir(&dcim).unwrap();
+        std::fs::write(dcim.join("a.jpg"), "12345").unwrap();
+        let sizes = Arc::new(FolderSizes::default());
+
+        assert_eq!(sizes.get_or_start(dcim.clone()), FolderSizeStatus::Computing);
+        let FolderSizeStatus::Done { bytes, files, .. } = wait_for_size(&sizes, dcim.clone()).await else {
+            panic!("size not computed");
+        };
+        assert_eq!((bytes, files), (5, 1));
+
+        // Cached until told otherwise
+        std::fs::write(dcim.join("b.jpg"), "12345").unwrap();
+        assert!(matches!(sizes.get_or_start(dcim.clone()), FolderSizeStatus::Done { bytes: 5, .. }));
+        sizes.invalidate();
+        assert!(matches!(wait_for_size(&sizes, dcim.clone()).await, FolderSizeStatus::Done { bytes: 10, .. }));
+
+        let missing = wait_for_size(&sizes, temp_dir.path().join("missing")).await;
+        assert!(matches!(missing, FolderSizeStatus::Failed { .. }));
+    }
+}
@@ -0,0 +1,442 @@
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::core::clients::client_handle;
+use crate::core::error::{AppError, AppResult};
+use crate::core::metadata::STATE_DIR;
+
+const HISTORY_FILE: &str = "history.jsonl";
+const NETWORK_FILE: &str = "network-usage.json";
+
+/// Days of network usage kept, enough for a year of monthly billing periods
+pub const NETWORK_RETENTION_DAYS: u32 = 400;
+
+/// Network usage is saved at most this often, and when the server stops
+const NETWORK_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Peers counted separately per day; traffic of any further peer is added to [`OTHER_PEERS`]
+const NETWORK_PEERS_PER_DAY: usize = 100;
+
+/// Peer that traffic without an established client session, or beyond the daily limit, is counted under
+pub const OTHER_PEERS: &str = "other";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryAction {
+    Upload,
+    Delete,
+    Move,
+    Download,
+}
+
+impl HistoryAction {
+    /// Whether a file went to or came from another device
+    pub fn is_transfer(self) -> bool {
+        matches!(self, HistoryAction::Upload | HistoryAction::Download)
+    }
+}
+
+/// One thing that happened to a file in the share
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub action: HistoryAction,
+    /// File name after the action
+    pub file: String,
+    #[serde(default)]
+    pub size: u64,
+    /// Display name chosen by the person who sent the file, or who downloaded it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    /// Handle of the client that did it, never its cookie or token, see [`client_handle`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Previous name of a moved file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_name: Option<String>,
+    /// Id of the HTTP request that did it, to find it in the logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(action: HistoryAction, file: &str) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            action,
+            file: file.to_string(),
+            size: 0,
+            sender: None,
+            client_id: None,
+            previous_name: None,
+            request_id: None,
+        }
+    }
+}
+
+/// Append-only activity log of the share, one JSON object per line
+pub struct HistoryLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl HistoryLog {
+    pub fn open(directory: &Path) -> Self {
+        Self {
+            path: directory.join(STATE_DIR).join(HISTORY_FILE),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn record(&self, entry: &HistoryEntry) -> AppResult<()> {
+        let _guard = self.lock.lock().unwrap();
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_string(entry)
+            .map_err(|e| AppError::File(format!("Failed to serialize history: {}", e)))?;
+        line.push('\n');
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Most recent entries first, at most `limit` of them
+    pub fn recent(&self, limit: usize) -> Vec<HistoryEntry> {
+        self.recent_matching(limit, |_| true)
+    }
+
+    /// Most recent entries `filter` accepts, at most `limit` of them
+    pub fn recent_matching(&self, limit: usize, filter: impl Fn(&HistoryEntry) -> bool) -> Vec<HistoryEntry> {
+        let _guard = self.lock.lock().unwrap();
+
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(_) => return Vec::new(),
+        };
+
+        content
+            .lines()
+            .rev()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str::<HistoryEntry>(line) {
+                Ok(mut entry) => {
+                    // Entries from before handles hold the client's cookie or token itself
+                    if let Some(id) = entry.client_id.as_mut().filter(|id| Uuid::parse_str(id).is_ok()) {
+                        *id = client_handle(id);
+                    }
+                    Some(entry)
+                }
+                Err(e) => {
+                    warn!("Skipping unreadable history entry: {}", e);
+                    None
+                }
+            })
+            .filter(|entry| filter(entry))
+            .take(limit)
+            .collect()
+    }
+}
+
+/// Bytes moved to and from one peer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerTraffic {
+    /// Last address the peer was seen at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub received: u64,
+    #[serde(default)]
+    pub sent: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeerUsage {
+    /// Handle of a browser or device (see [`client_handle`]), or the name or address of a peer
+    /// this server sends to
+    pub peer: String,
+    #[serde(flatten)]
+    pub traffic: PeerTraffic,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DayUsage {
+    pub date: NaiveDate,
+    pub received: u64,
+    pub sent: u64,
+    /// Busiest peers first
+    pub peers: Vec<PeerUsage>,
+}
+
+/// Network usage of the last days, for `GET /api/usage/network`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NetworkReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub received: u64,
+    pub sent: u64,
+    /// Totals of the whole period, busiest peers first
+    pub peers: Vec<PeerUsage>,
+    /// Days with any traffic, most recent first
+    pub days: Vec<DayUsage>,
+}
+
+/// Bytes the server moved per peer and local day, kept next to the history so users on metered
+/// connections can follow them. Counts are saved every few seconds rather than on every request.
+pub struct NetworkUsage {
+    path: PathBuf,
+    days: Mutex<BTreeMap<NaiveDate, BTreeMap<String, PeerTraffic>>>,
+    saved: Mutex<Option<Instant>>,
+}
+
+impl NetworkUsage {
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(STATE_DIR).join(NETWORK_FILE);
+
+        let mut days: BTreeMap<NaiveDate, BTreeMap<String, PeerTraffic>> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable network usage file {:?}: {}", path, e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        // Counts from before handles are kept under the client's cookie or token itself
+        for peers in days.values_mut() {
+            let old: Vec<String> = peers.keys().filter(|peer| Uuid::parse_str(peer).is_ok()).cloned().collect();
+            for id in old {
+                let traffic = peers.remove(&id).unwrap_or_default();
+                let merged = peers.entry(client_handle(&id)).or_default();
+                merged.received += traffic.received;
+                merged.sent += traffic.sent;
+                merged.address = merged.address.take().or(traffic.address);
+            }
+        }
+
+        Self {
+            path,
+            days: Mutex::new(days),
+            saved: Mutex::new(None),
+        }
+    }
+
+    /// Add traffic with `peer` to today's count
+    pub fn record(&self, peer: &str, address: Option<&str>, received: u64, sent: u64) {
+        self.record_on(Local::now().date_naive(), peer, address, received, sent);
+    }
+
+    fn record_on(&self, date: NaiveDate, peer: &str, address: Option<&str>, received: u64, sent: u64) {
+        if received == 0 && sent == 0 {
+            return;
+        }
+
+        {
+            let mut days = self.days.lock().unwrap();
+            let peers = days.entry(date).or_default();
+            let peer = if peers.contains_key(peer) || peers.len() < NETWORK_PEERS_PER_DAY {
+                peer
+            } else {
+                OTHER_PEERS
+            };
+            let traffic = peers.entry(peer.to_string()).or_default();
+            traffic.received += received;
+            traffic.sent += sent;
+            if address.is_some() && peer != OTHER_PEERS {
+                traffic.address = address.map(str::to_string);
+            }
+            let oldest = date - chrono::Duration::days(i64::from(NETWORK_RETENTION_DAYS));
+            days.retain(|day, _| *day > oldest);
+        }
+
+        let due = self
+            .saved
+            .lock()
+            .unwrap()
+            .is_none_or(|saved| saved.elapsed() >= NETWORK_SAVE_INTERVAL);
+        if due {
+            if let Err(e) = self.flush() {
+                warn!("Failed to save network usage: {}", e);
+            }
+        }
+    }
+
+    /// Save the counts now
+    pub fn flush(&self) -> AppResult<()> {
+        let days = self.days.lock().unwrap();
+        *self.saved.lock().unwrap() = Some(Instant::now());
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(&*days)
+            .map_err(|e| AppError::File(format!("Failed to serialize network usage: {}", e)))?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// Usage of the `days` days up to today
+    pub fn report(&self, days: u32) -> NetworkReport {
+        self.report_until(Local::now().date_naive(), days)
+    }
+
+    fn report_until(&self, to: NaiveDate, days: u32) -> NetworkReport {
+        let from = to - chrono::Duration::days(i64::from(days.clamp(1, NETWORK_RETENTION_DAYS)) - 1);
+        let mut report = NetworkReport { from, to, received: 0, sent: 0, peers: Vec::new(), days: Vec::new() };
+        let mut totals: HashMap<String, PeerTraffic> = HashMap::new();
+
+        let recorded = self.days.lock().unwrap();
+        for (date, peers) in recorded.range(from..=to).rev() {
+            let mut day = DayUsage { date: *date, received: 0, sent: 0, peers: Vec::new() };
+            for (peer, traffic) in peers {
+                day.received += traffic.received;
+                day.sent += traffic.sent;
+                let total = totals.entry(peer.clone()).or_default();
+                total.received += traffic.received;
+                total.sent += traffic.sent;
+                // Days run newest first, so the first address seen is the latest
+                if total.address.is_none() {
+                    total.address = traffic.address.clone();
+                }
+                day.peers.push(PeerUsage { peer: peer.clone(), traffic: traffic.clone() });
+            }
+            sort_busiest_first(&mut day.peers);
+            report.received += day.received;
+            report.sent += day.sent;
+            report.days.push(day);
+        }
+
+        report.peers = totals.into_iter().map(|(peer, traffic)| PeerUsage { peer, traffic }).collect();
+        sort_busiest_first(&mut report.peers);
+        report
+    }
+}
+
+fn sort_busiest_first(peers: &mut [PeerUsage]) {
+    peers.sort_by(|a, b| {
+        let total = |usage: &PeerUsage| usage.traffic.received + usage.traffic.sent;
+        total(b).cmp(&total(a)).then_with(|| a.peer.cmp(&b.peer))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_and_read_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = HistoryLog::open(temp_dir.path());
+
+        assert!(log.recent(10).is_empty());
+
+        let mut upload = HistoryEntry::new(HistoryAction::Upload, "photo.jpg");
+        upload.sender = Some("Mum".to_string());
+        upload.size = 42;
+        log.record(&upload).unwrap();
+        log.record(&HistoryEntry::new(HistoryAction::Delete, "old.txt")).unwrap();
+
+        let entries = log.recent(10);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].action, HistoryAction::Delete);
+        assert_eq!(entries[1].file, "photo.jpg");
+        assert_eq!(entries[1].sender.as_deref(), Some("Mum"));
+
+        assert_eq!(log.recent(1).len(), 1);
+
+        // Cookies logged by older versions are only ever read back as handles
+        let mut old = HistoryEntry::new(HistoryAction::Download, "photo.jpg");
+        old.client_id = Some("0b7a4a9e-3f7e-4f57-9d3c-6d1b8c2f5a10".to_string());
+        log.record(&old).unwrap();
+        assert_eq!(log.recent(1)[0].client_id, Some(client_handle("0b7a4a9e-3f7e-4f57-9d3c-6d1b8c2f5a10")));
+
+        let transfers = log.recent_matching(10, |entry| entry.action.is_transfer());
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[1].file, "photo.jpg");
+    }
+
+    #[test]
+    fn test_corrupt_lines_are_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = HistoryLog::open(temp_dir.path());
+        log.record(&HistoryEntry::new(HistoryAction::Upload, "a.txt")).unwrap();
+
+        let path = temp_dir.path().join(STATE_DIR).join(HISTORY_FILE);
+        let mut content = std::fs::read_to_string(&path).unwrap();
+        content.push_str("{not json\n");
+        std::fs::write(&path, content).unwrap();
+
+        let entries = log.recent(10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].file, "a.txt");
+    }
+
+    #[test]
+    fn test_network_usage_per_peer_and_day() {
+        let temp_dir = TempDir::new().unwrap();
+        let usage = NetworkUsage::load(temp_dir.path());
+        let day = |day: u32| NaiveDate::from_ymd_opt(2026, 3, day).unwrap();
+
+        usage.record_on(day(1), "phone", Some("10.0.0.5"), 100, 10);
+        usage.record_on(day(2), "phone", Some("10.0.0.7"), 50, 0);
+        usage.record_on(day(2), "laptop", None, 0, 500);
+        usage.record_on(day(2), "idle", None, 0, 0);
+        usage.flush().unwrap();
+
+        let report = NetworkUsage::load(temp_dir.path()).report_until(day(2), 30);
+        assert_eq!(report.from, day(2) - chrono::Duration::days(29));
+        assert_eq!((report.received, report.sent), (150, 510));
+        assert_eq!(report.days.len(), 2);
+        assert_eq!(report.days[0].date, day(2));
+        assert_eq!(report.days[0].peers[0].peer, "laptop");
+        assert_eq!(report.peers[1].peer, "phone");
+        assert_eq!(report.peers[1].traffic.address.as_deref(), Some("10.0.0.7"));
+        assert_eq!((report.peers[1].traffic.received, report.peers[1].traffic.sent), (150, 10));
+
+        let today = usage.report_until(day(2), 1);
+        assert_eq!(today.days.len(), 1);
+        assert_eq!(today.received, 50);
+
+        // Counts saved under a client's cookie by older versions are served under its handle
+        usage.record_on(day(2), "0b7a4a9e-3f7e-4f57-9d3c-6d1b8c2f5a10", None, 7, 0);
+        usage.flush().unwrap();
+        let report = NetworkUsage::load(temp_dir.path()).report_until(day(2), 1);
+        assert!(report.peers.iter().any(|usage| usage.peer == client_handle("0b7a4a9e-3f7e-4f57-9d3c-6d1b8c2f5a10")));
+    }
+
+    #[test]
+    fn test_network_usage_caps_peers_per_day() {
+        let temp_dir = TempDir::new().unwrap();
+        let usage = NetworkUsage::load(temp_dir.path());
+        let day = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+
+        for peer in 0..NETWORK_PEERS_PER_DAY + 20 {
+            usage.record_on(day, &format!("peer-{}", peer), Some("10.0.0.5"), 1, 0);
+        }
+        usage.record_on(day, "peer-0", None, 1, 0);
+
+        let report = usage.report_until(day, 1);
+        assert_eq!(report.received, NETWORK_PEERS_PER_DAY as u64 + 21);
+        assert_eq!(report.days[0].peers.len(), NETWORK_PEERS_PER_DAY + 1);
+        let other = report.peers.iter().find(|usage| usage.peer == OTHER_PEERS).unwrap();
+        assert_eq!(other.traffic.received, 20);
+        assert_eq!(other.traffic.address, None);
+        assert!(report.peers.iter().any(|usage| usage.peer == "peer-0" && usage.traffic.received == 2));
+    }
+}
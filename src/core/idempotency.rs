@@ -0,0 +1,135 @@
+//! Outcomes of requests sent with an `Idempotency-Key`, so a client retrying after a lost
+//! response gets the original answer instead of doing the work twice
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// How long a key is remembered
+const KEY_MAX_AGE_HOURS: i64 = 24;
+
+const MAX_KEY_LENGTH: usize = 255;
+
+struct Entry {
+    created: DateTime<Utc>,
+    /// Response of the completed request, `None` while it is still running
+    response: Option<serde_json::Value>,
+}
+
+/// What became of the first request that used a key
+pub enum KeyState<'a> {
+    /// The key is new; run the request and complete it with its response
+    New(PendingKey<'a>),
+    /// A request with the key is still running
+    Running,
+    /// A request with the key already succeeded with this response
+    Completed(serde_json::Value),
+}
+
+/// Keys of recent requests, per client and kept in memory only
+#[derive(Default)]
+pub struct IdempotencyKeys {
+    entries: Mutex<HashMap<(String, String), Entry>>,
+}
+
+impl IdempotencyKeys {
+    /// Claim `key` for a request from `client`
+    pub fn begin(&self, client: &str, key: &str) -> KeyState<'_> {
+        let mut entries = self.entries.lock().unwrap();
+        let cutoff = Utc::now() - Duration::hours(KEY_MAX_AGE_HOURS);
+        entries.retain(|_, entry| entry.created > cutoff);
+
+        let id = (client.to_string(), key.to_string());
+        match entries.get(&id) {
+            Some(Entry { response: Some(response), .. }) => KeyState::Completed(response.clone()),
+            Some(_) => KeyState::Running,
+            None => {
+                entries.insert(
+                    id.clone(),
+                    Entry {
+                        created: Utc::now(),
+                        response: None,
+                    },
+                );
+                KeyState::New(PendingKey {
+                    keys: self,
+                    id,
+                    completed: false,
+                })
+            }
+        }
+    }
+}
+
+/// A claimed key whose request is running. Dropping it without completing it, because the
+/// request failed or the client went away, frees the key for a retry.
+pub struct PendingKey<'a> {
+    keys: &'a IdempotencyKeys,
+    id: (String, String),
+    completed: bool,
+}
+
+impl PendingKey<'_> {
+    /// Remember the successful response for retries
+    pub fn complete(mut self, response: serde_json::Value) {
+        if let Some(entry) = self.keys.entries.lock().unwrap().get_mut(&self.id) {
+            entry.response = Some(response);
+        }
+        self.completed = true;
+    }
+}
+
+impl Drop for PendingKey<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.keys.entries.lock().unwrap().remove(&self.id);
+        }
+    }
+}
+
+/// Keys are opaque printable ASCII strings, typically UUIDs
+pub fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.chars().all(|c| c.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_completed_key_replays_response() {
+        let keys = IdempotencyKeys::default();
+
+        let KeyState::New(pending) = keys.begin("phone", "upload-1") else {
+            panic!("a new key should be claimed");
+        };
+        assert!(matches!(keys.begin("phone", "upload-1"), KeyState::Running));
+        // Keys belong to a client
+        assert!(matches!(keys.begin("laptop", "upload-1"), KeyState::New(_)));
+
+        pending.complete(json!({ "name": "a.txt" }));
+        let KeyState::Completed(response) = keys.begin("phone", "upload-1") else {
+            panic!("the response should be replayed");
+        };
+        assert_eq!(response["name"], "a.txt");
+    }
+
+    #[test]
+    fn test_failed_request_frees_key() {
+        let keys = IdempotencyKeys::default();
+        let KeyState::New(pending) = keys.begin("phone", "upload-1") else {
+            panic!("a new key should be claimed");
+        };
+        drop(pending);
+        assert!(matches!(keys.begin("phone", "upload-1"), KeyState::New(_)));
+    }
+
+    #[test]
+    fn test_valid_key() {
+        assert!(valid_key("8d3c1f0e-8f2b-4a57-9d1e-3f1c2b7a9e10"));
+        assert!(!valid_key(""));
+        assert!(!valid_key("with space"));
+        assert!(!valid_key(&"k".repeat(256)));
+    }
+}
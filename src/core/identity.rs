@@ -0,0 +1,129 @@
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use uuid::Uuid;
+
+const DEVICE_ID_FILE: &str = "device-id";
+const CLIENT_TOKEN_FILE: &str = "client-token";
+
+/// Identity of this installation, kept in the user's config directory so it survives
+/// restarts and network changes
+pub fn device_id() -> String {
+    let Some(path) = device_id_path() else {
+        warn!("No config directory available, using a temporary device id");
+        return Uuid::new_v4().to_string();
+    };
+
+    load_or_create_device_id(&path).unwrap_or_else(|e| {
+        warn!("Failed to persist device id at {:?}: {}", path, e);
+        Uuid::new_v4().to_string()
+    })
+}
+
+/// Secret identifying this installation when it acts as a client of other devices.
+///
+/// Unlike the device id, which is announced over mDNS, the token is never shown to anyone, so
+/// peers can rely on it the way they rely on a browser's session cookie.
+pub fn client_token() -> String {
+    let Some(path) = identity_dir().map(|dir| dir.join(CLIENT_TOKEN_FILE)) else {
+        warn!("No config directory available, using a temporary client token");
+        return Uuid::new_v4().to_string();
+    };
+
+    load_or_create_device_id(&path).unwrap_or_else(|e| {
+        warn!("Failed to persist client token at {:?}: {}", path, e);
+        Uuid::new_v4().to_string()
+    })
+}
+
+/// Per-user directory holding the device id and other identity material
+pub fn identity_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rustdrop"))
+}
+
+fn device_id_path() -> Option<PathBuf> {
+    identity_dir().map(|dir| dir.join(DEVICE_ID_FILE))
+}
+
+/// Read the id (device id or client token) stored at `path`, generating and saving a new one
+/// if it is missing or invalid
+pub fn load_or_create_device_id(path: &Path) -> std::io::Result<String> {
+    if let Ok(content) = std::fs::read_to_string(path) {
+        if let Ok(id) = Uuid::parse_str(content.trim()) {
+            return Ok(id.to_string());
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let id = Uuid::new_v4().to_string();
+    std::fs::write(path, &id)?;
+    Ok(id)
+}
+
+/// Read the random key of `len` bytes stored at `path`, generating and saving a new one, readable
+/// by its owner only, if it is missing or damaged
+pub fn load_or_create_key(path: &Path, len: usize) -> std::io::Result<Vec<u8>> {
+    if let Ok(key) = std::fs::read(path) {
+        if key.len() == len {
+            return Ok(key);
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut key = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| std::io::Error::other("no random numbers available"))?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(&key)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_device_id_is_stable() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("rustdrop").join(DEVICE_ID_FILE);
+
+        let first = load_or_create_device_id(&path).unwrap();
+        assert_eq!(load_or_create_device_id(&path).unwrap(), first);
+
+        // A damaged file is replaced rather than trusted
+        std::fs::write(&path, "garbage").unwrap();
+        let replaced = load_or_create_device_id(&path).unwrap();
+        assert_ne!(replaced, first);
+        assert!(Uuid::parse_str(&replaced).is_ok());
+    }
+
+    #[test]
+    fn test_key_is_stable_and_private() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("rustdrop").join("test-key");
+
+        let first = load_or_create_key(&path, 32).unwrap();
+        assert_eq!(first.len(), 32);
+        assert_eq!(load_or_create_key(&path, 32).unwrap(), first);
+        assert_ne!(load_or_create_key(&path, 16).unwrap(), first);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
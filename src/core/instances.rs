@@ -0,0 +1,408 @@
+//! Registry of the RustDrop instances running on this host, so several of them (serving
+//! different directories) can run side by side: each claims its port in the registry before
+//! binding it, announces a distinct name, and `rustdrop list-instances` shows them all.
+//!
+//! Every instance owns one `<port>.json` file in the registry directory, created exclusively
+//! so two instances starting at the same moment cannot both take the same port, and removed
+//! when the instance exits. Files left behind by a crash are recognised by their port being
+//! free and their process being gone, and are cleaned up by the next instance looking at the
+//! registry.
+//!
+//! The port a share was last served on is kept in its state directory and preferred on the
+//! next launch, so bookmarks and printed QR codes keep working across restarts.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::core::metadata::STATE_DIR;
+use crate::core::models::DeviceInfo;
+use crate::utils::network::is_port_available;
+
+/// Time an instance has between claiming its port and binding it, where its process cannot
+/// be checked
+const STARTUP_GRACE: Duration = Duration::from_secs(30);
+
+/// Ports tried after the preferred one, in order, unless a port range is configured
+const FALLBACK_PORTS: [RangeInclusive<u16>; 2] = [8000..=8999, 9000..=9999];
+
+/// File in the share's state directory remembering the port it was last served on
+const PORT_FILE: &str = "port.json";
+
+/// What an instance tells the others about itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceRecord {
+    pub pid: u32,
+    pub port: u16,
+    pub directory: PathBuf,
+    pub name: String,
+    pub url: String,
+    pub started: DateTime<Utc>,
+    /// Secret another instance of the same user presents to take over the port
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_token: Option<String>,
+}
+
+/// Lets another instance started by the same user ask this one to shut down
+pub struct InstanceControl {
+    token: String,
+    shutdown: Notify,
+}
+
+impl Default for InstanceControl {
+    fn default() -> Self {
+        Self {
+            token: Uuid::new_v4().to_string(),
+            shutdown: Notify::new(),
+        }
+    }
+}
+
+impl InstanceControl {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn request_shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    /// Resolves once a shutdown was requested
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await;
+    }
+}
+
+pub struct InstanceRegistry {
+    dir: PathBuf,
+}
+
+impl InstanceRegistry {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The registry shared by every instance of this user
+    pub fn open_default() -> Self {
+        let dir = dirs::data_local_dir()
+            .map(|dir| dir.join("rustdrop").join("instances"))
+            .unwrap_or_else(|| std::env::temp_dir().join("rustdrop-instances"));
+        Self::new(dir)
+    }
+
+    /// The running instance that claimed `port`
+    pub fn get(&self, port: u16) -> Option<InstanceRecord> {
+        self.list().into_iter().find(|record| record.port == port)
+    }
+
+    /// Running instances by port, removing the records of those that are gone
+    pub fn list(&self) -> Vec<InstanceRecord> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+
+        let mut instances = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(port) = record_port(&path) else {
+                continue;
+            };
+            if is_stale(&path, port) {
+                debug!("Removing record of stopped instance on port {}", port);
+                let _ = std::fs::remove_file(&path);
+                continue;
+            }
+            // A claim whose record is not written yet has nothing to show
+            if let Some(record) = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok())
+            {
+                instances.push(record);
+            }
+        }
+        instances.sort_by_key(|record: &InstanceRecord| record.port);
+        instances
+    }
+
+    /// Claim `preferred`, or the first port of `range` (the fallback ports without one) that
+    /// is free and not claimed by another instance. The claim lasts until the returned value
+    /// is dropped.
+    pub fn claim(&self, preferred: u16, range: Option<RangeInclusive<u16>>) -> Result<PortClaim> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create instance registry {:?}", self.dir))?;
+
+        let fallback: Vec<RangeInclusive<u16>> = match range {
+            Some(range) => vec![range],
+            None => FALLBACK_PORTS.to_vec(),
+        };
+        let candidates = std::iter::once(preferred).chain(fallback.into_iter().flatten());
+        for port in candidates {
+            if !is_port_available(port) {
+                continue;
+            }
+            if let Some(claim) = self.try_claim(port)? {
+                if port != preferred {
+                    warn!("Port {} is not available, using port {}", preferred, port);
+                }
+                return Ok(claim);
+            }
+        }
+        bail!("No free port found for another instance")
+    }
+
+    fn try_claim(&self, port: u16) -> Result<Option<PortClaim>> {
+        let path = self.dir.join(format!("{}.json", port));
+        for _ in 0..2 {
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            match options.open(&path) {
+                Ok(mut file) => {
+                    // The owner is known right away, in case it dies before publishing
+                    let owner = serde_json::json!({ "pid": std::process::id() });
+                    file.write_all(owner.to_string().as_bytes())
+                        .with_context(|| format!("Failed to write {:?}", path))?;
+                    return Ok(Some(PortClaim { port, path }));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if !is_stale(&path, port) {
+                        return Ok(None);
+                    }
+                    let _ = std::fs::remove_file(&path);
+                }
+                Err(e) => return Err(e).with_context(|| format!("Failed to write {:?}", path)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A port reserved for this instance in the registry
+#[derive(Debug)]
+pub struct PortClaim {
+    port: u16,
+    path: PathBuf,
+}
+
+impl PortClaim {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Make this instance show up in `rustdrop list-instances`
+    pub fn publish(&self, directory: &Path, device_info: &DeviceInfo, control: &InstanceControl) -> Result<()> {
+        let record = InstanceRecord {
+            pid: std::process::id(),
+            port: self.port,
+            directory: directory.to_path_buf(),
+            name: device_info.name.clone(),
+            url: device_info.url(),
+            started: Utc::now(),
+            control_token: Some(control.token().to_string()),
+        };
+        let content = serde_json::to_string_pretty(&record)?;
+
+        // The control token shuts the instance down, keep it away from other users
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .with_context(|| format!("Failed to write {:?}", self.path))
+    }
+}
+
+impl Drop for PortClaim {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn record_port(path: &Path) -> Option<u16> {
+    if path.extension()? != "json" {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+#[derive(Deserialize)]
+struct ClaimOwner {
+    pid: u32,
+}
+
+/// Nothing listens on the port any more, and the process that claimed it is gone or has had
+/// plenty of time to bind it
+fn is_stale(path: &Path, port: u16) -> bool {
+    if !is_port_available(port) {
+        return false;
+    }
+    let owner = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<ClaimOwner>(&content).ok());
+    if owner.is_some_and(|owner| !process_running(owner.pid)) {
+        return true;
+    }
+    let age = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or_default();
+    age > STARTUP_GRACE
+}
+
+/// Whether the process with this id is still alive
+#[cfg(unix)]
+pub fn process_running(pid: u32) -> bool {
+    let Some(pid) = i32::try_from(pid).ok().and_then(rustix::process::Pid::from_raw) else {
+        return false;
+    };
+    // Permission denied still means the process exists
+    !matches!(rustix::process::test_kill_process(pid), Err(rustix::io::Errno::SRCH))
+}
+
+/// Without a way to ask, the startup grace decides
+#[cfg(not(unix))]
+pub fn process_running(_pid: u32) -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize)]
+struct LastPort {
+    port: u16,
+}
+
+/// The port `directory` was served on last time, so its URLs and QR codes can stay the same
+pub fn remembered_port(directory: &Path) -> Option<u16> {
+    let content = std::fs::read_to_string(directory.join(STATE_DIR).join(PORT_FILE)).ok()?;
+    serde_json::from_str::<LastPort>(&content).ok().map(|last| last.port)
+}
+
+/// Record the port `directory` is being served on
+pub fn remember_port(directory: &Path, port: u16) -> Result<()> {
+    let state_dir = directory.join(STATE_DIR);
+    std::fs::create_dir_all(&state_dir)
+        .with_context(|| format!("Failed to create {:?}", state_dir))?;
+    let path = state_dir.join(PORT_FILE);
+    std::fs::write(&path, serde_json::to_string(&LastPort { port })?)
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Label telling instances on the same host apart: the name of the directory they serve
+pub fn instance_label(directory: &Path) -> String {
+    directory
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| directory.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use tempfile::TempDir;
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn test_claims_are_exclusive() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = InstanceRegistry::new(temp_dir.path().to_path_buf());
+        let port = free_port();
+
+        let first = registry.claim(port, None).unwrap();
+        assert_eq!(first.port(), port);
+        let second = registry.claim(port, None).unwrap();
+        assert_ne!(second.port(), port);
+
+        drop(first);
+        assert_eq!(registry.claim(port, None).unwrap().port(), port);
+    }
+
+    #[test]
+    fn test_list_published_instances() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = InstanceRegistry::new(temp_dir.path().to_path_buf());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let claim = PortClaim {
+            port,
+            path: temp_dir.path().join(format!("{}.json", port)),
+        };
+        let control = InstanceControl::default();
+        claim
+            .publish(Path::new("/srv/photos"), &DeviceInfo::new(port), &control)
+            .unwrap();
+        let instances = registry.list();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].directory, PathBuf::from("/srv/photos"));
+        assert_eq!(instances[0].pid, std::process::id());
+        assert_eq!(registry.get(port).unwrap().control_token.as_deref(), Some(control.token()));
+
+        drop(claim);
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn test_stale_records_are_removed() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = InstanceRegistry::new(temp_dir.path().to_path_buf());
+        let port = free_port();
+        let path = temp_dir.path().join(format!("{}.json", port));
+        std::fs::write(&path, "{}").unwrap();
+        let old = SystemTime::now() - STARTUP_GRACE * 2;
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(old).unwrap();
+
+        assert!(registry.list().is_empty());
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_claim_of_dead_process_is_stale() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = InstanceRegistry::new(temp_dir.path().to_path_buf());
+        let port = free_port();
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        std::fs::write(temp_dir.path().join(format!("{}.json", port)), format!("{{\"pid\": {}}}", child.id())).unwrap();
+
+        assert_eq!(registry.claim(port, None).unwrap().port(), port);
+    }
+
+    #[test]
+    fn test_claims_stay_in_port_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let registry = InstanceRegistry::new(temp_dir.path().to_path_buf());
+        let port = free_port();
+
+        let _first = registry.claim(port, Some(port..=port)).unwrap();
+        assert!(registry.claim(port, Some(port..=port)).is_err());
+    }
+
+    #[test]
+    fn test_remember_port() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(remembered_port(temp_dir.path()), None);
+
+        remember_port(temp_dir.path(), 8085).unwrap();
+        assert_eq!(remembered_port(temp_dir.path()), Some(8085));
+    }
+
+    #[test]
+    fn test_instance_label() {
+        assert_eq!(instance_label(Path::new("/home/ana/Photos")), "Photos");
+    }
+}
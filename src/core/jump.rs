@@ -0,0 +1,132 @@
+//! Short codes standing for longer paths on this server, so an address like
+//! `192.168.1.7:8080/j/7f3` can be typed on a device that cannot scan the QR code.
+//!
+//! Codes are a few hex digits, growing longer only once the short ones are crowded, and are
+//! kept in the share's state directory so a printed or written down code keeps working.
+
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::core::error::{AppError, AppResult};
+use crate::core::metadata::STATE_DIR;
+
+const JUMP_FILE: &str = "jump-codes.json";
+
+/// Hex digits of the shortest codes
+const MIN_CODE_LEN: usize = 3;
+
+/// Random codes tried at one length before moving to a longer one
+const ATTEMPTS_PER_LENGTH: usize = 8;
+
+pub struct JumpCodes {
+    path: PathBuf,
+    codes: Mutex<BTreeMap<String, String>>,
+}
+
+impl JumpCodes {
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(STATE_DIR).join(JUMP_FILE);
+
+        let codes = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable jump codes file {:?}: {}", path, e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        Self {
+            path,
+            codes: Mutex::new(codes),
+        }
+    }
+
+    /// The code jumping to `target`, a path on this server, creating one if there is none yet
+    pub fn code_for(&self, target: &str) -> AppResult<String> {
+        if !is_local_path(target) {
+            return Err(AppError::File(format!("{} is not a path on this server", target)));
+        }
+
+        let mut codes = self.codes.lock().unwrap();
+        if let Some((code, _)) = codes.iter().find(|(_, existing)| *existing == target) {
+            return Ok(code.clone());
+        }
+
+        let random = SystemRandom::new();
+        let code = (MIN_CODE_LEN..)
+            .flat_map(|len| std::iter::repeat_n(len, ATTEMPTS_PER_LENGTH))
+            .map(|len| random_code(&random, len))
+            .find(|code| code.as_ref().map_or(true, |code| !codes.contains_key(code)))
+            .expect("codes grow until one is free")?;
+        codes.insert(code.clone(), target.to_string());
+        self.save(&codes)?;
+        Ok(code)
+    }
+
+    /// Where `code` jumps to
+    pub fn target(&self, code: &str) -> Option<String> {
+        self.codes.lock().unwrap().get(&code.to_ascii_lowercase()).cloned()
+    }
+
+    fn save(&self, codes: &BTreeMap<String, String>) -> AppResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(codes)
+            .map_err(|e| AppError::File(format!("Failed to serialize jump codes: {}", e)))?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// A path on this server, never another site a code could redirect to
+pub fn is_local_path(target: &str) -> bool {
+    target.starts_with('/') && !target.starts_with("//") && !target.contains('\\')
+}
+
+fn random_code(random: &SystemRandom, len: usize) -> AppResult<String> {
+    let mut bytes = vec![0u8; len.div_ceil(2)];
+    random
+        .fill(&mut bytes)
+        .map_err(|_| AppError::Unknown("No randomness available".to_string()))?;
+    let mut code: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    code.truncate(len);
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_codes_are_short_stable_and_persisted() {
+        let temp_dir = TempDir::new().unwrap();
+        let codes = JumpCodes::load(temp_dir.path());
+
+        let code = codes.code_for("/welcome").unwrap();
+        assert_eq!(code.len(), MIN_CODE_LEN);
+        assert_eq!(codes.code_for("/welcome").unwrap(), code);
+        assert_ne!(codes.code_for("/tail/abc").unwrap(), code);
+
+        let reloaded = JumpCodes::load(temp_dir.path());
+        assert_eq!(reloaded.target(&code.to_uppercase()).as_deref(), Some("/welcome"));
+        assert_eq!(reloaded.target("zzz"), None);
+    }
+
+    #[test]
+    fn test_only_local_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        let codes = JumpCodes::load(temp_dir.path());
+
+        assert!(codes.code_for("https://example.com").is_err());
+        assert!(codes.code_for("//example.com/").is_err());
+        assert!(codes.code_for("/\\example.com").is_err());
+    }
+}
@@ -0,0 +1,321 @@
+//! Share links whose content is encrypted with a passphrase.
+//!
+//! The key is derived from the passphrase with PBKDF2-HMAC-SHA256, so the browser can derive the
+//! same key with WebCrypto. Files are sent as a random nonce prefix followed by AES-256-GCM
+//! records of [`RECORD_SIZE`] bytes. Each record's nonce holds its number and whether it is the
+//! last one, so records cannot be reordered and a cut off download is noticed.
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::core::error::{AppError, AppResult};
+use crate::core::metadata::STATE_DIR;
+
+const LINKS_FILE: &str = "share-links.json";
+
+/// PBKDF2 rounds for new links, following the OWASP recommendation for HMAC-SHA256
+pub const KDF_ITERATIONS: u32 = 600_000;
+
+/// Plaintext bytes per encrypted record; every record but the last is this long
+pub const RECORD_SIZE: usize = 64 * 1024;
+
+/// Random bytes starting the encrypted stream, shared by the nonces of all its records
+pub const NONCE_PREFIX_LEN: usize = 7;
+
+/// Bytes the authentication tag adds to every record
+pub const TAG_LEN: usize = 16;
+
+/// A link handing out one file of the share, encrypted with a key derived from a passphrase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareLink {
+    pub token: String,
+    /// Path of the file in the share
+    pub file: String,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    /// PBKDF2 salt, base64
+    pub salt: String,
+    pub iterations: u32,
+    /// The derived AES key, base64. The passphrase itself is never stored.
+    key: String,
+}
+
+impl ShareLink {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Start encrypting a download of the linked file
+    pub fn sealer(&self) -> AppResult<RecordSealer> {
+        let key = STANDARD
+            .decode(&self.key)
+            .map_err(|e| AppError::File(format!("Invalid key of share link {}: {}", self.token, e)))?;
+        RecordSealer::new(&key)
+    }
+}
+
+/// The derived key of `passphrase`, as the browser derives it with WebCrypto
+pub fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    let iterations = NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN);
+    ring::pbkdf2::derive(ring::pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+    key
+}
+
+/// The nonce of record `counter` of a stream
+pub fn record_nonce(prefix: &[u8; NONCE_PREFIX_LEN], counter: u32, last: bool) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&counter.to_be_bytes());
+    nonce[NONCE_LEN - 1] = u8::from(last);
+    nonce
+}
+
+/// Length of the encrypted stream of a `size` byte file
+pub fn sealed_len(size: u64) -> u64 {
+    NONCE_PREFIX_LEN as u64 + size + record_count(size) * TAG_LEN as u64
+}
+
+/// Records a `size` byte file is split into; an empty file still gets one (empty) record
+pub fn record_count(size: u64) -> u64 {
+    size.div_ceil(RECORD_SIZE as u64).max(1)
+}
+
+/// Encrypts the records of one download, with a fresh nonce prefix so that no two downloads
+/// reuse a nonce even if the file changed in between
+pub struct RecordSealer {
+    key: LessSafeKey,
+    prefix: [u8; NONCE_PREFIX_LEN],
+    counter: u32,
+}
+
+impl RecordSealer {
+    fn new(key: &[u8]) -> AppResult<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, key)
+            .map_err(|_| AppError::File("Invalid share link key".to_string()))?;
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        SystemRandom::new()
+            .fill(&mut prefix)
+            .map_err(|_| AppError::Unknown("No randomness available".to_string()))?;
+
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            prefix,
+            counter: 0,
+        })
+    }
+
+    /// Sent ahead of the records
+    pub fn prefix(&self) -> [u8; NONCE_PREFIX_LEN] {
+        self.prefix
+    }
+
+    /// Encrypt the next record in place, appending its tag
+    pub fn seal(&mut self, mut record: Vec<u8>, last: bool) -> AppResult<Vec<u8>> {
+        let nonce = Nonce::assume_unique_for_key(record_nonce(&self.prefix, self.counter, last));
+        self.key
+            .seal_in_place_append_tag(nonce, Aad::empty(), &mut record)
+            .map_err(|_| AppError::File("Failed to encrypt record".to_string()))?;
+        self.counter += 1;
+        Ok(record)
+    }
+}
+
+/// Share links the host created, saved in the state directory
+pub struct ShareLinks {
+    path: PathBuf,
+    links: Mutex<BTreeMap<String, ShareLink>>,
+}
+
+impl ShareLinks {
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(STATE_DIR).join(LINKS_FILE);
+
+        let links = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable share links file {:?}: {}", path, e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        Self {
+            path,
+            links: Mutex::new(links),
+        }
+    }
+
+    /// Create a link to `file`, dropping links that expired in the meantime
+    pub fn create(
+        &self,
+        file: &str,
+        passphrase: &str,
+        lifetime: Option<Duration>,
+        iterations: u32,
+    ) -> AppResult<ShareLink> {
+        let random = SystemRandom::new();
+        let mut token = [0u8; 16];
+        let mut salt = [0u8; 16];
+        random
+            .fill(&mut token)
+            .and_then(|_| random.fill(&mut salt))
+            .map_err(|_| AppError::Unknown("No randomness available".to_string()))?;
+
+        let now = Utc::now();
+        let link = ShareLink {
+            token: URL_SAFE_NO_PAD.encode(token),
+            file: file.to_string(),
+            created: now,
+            expires: lifetime.map(|lifetime| now + lifetime),
+            salt: STANDARD.encode(salt),
+            iterations,
+            key: STANDARD.encode(derive_key(passphrase, &salt, iterations)),
+        };
+
+        let mut links = self.links.lock().unwrap();
+        links.retain(|_, existing| !existing.is_expired(now));
+        links.insert(link.token.clone(), link.clone());
+        self.save(&links)?;
+        Ok(link)
+    }
+
+    /// The link with `token`, unless it expired
+    pub fn get(&self, token: &str) -> Option<ShareLink> {
+        self.links
+            .lock()
+            .unwrap()
+            .get(token)
+            .filter(|link| !link.is_expired(Utc::now()))
+            .cloned()
+    }
+
+    /// Links that have not expired, newest first
+    pub fn list(&self) -> Vec<ShareLink> {
+        let now = Utc::now();
+        let mut links: Vec<ShareLink> = self
+            .links
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|link| !link.is_expired(now))
+            .cloned()
+            .collect();
+        links.sort_by_key(|link| std::cmp::Reverse(link.created));
+        links
+    }
+
+    /// Delete a link, returning false if it did not exist
+    pub fn revoke(&self, token: &str) -> AppResult<bool> {
+        let mut links = self.links.lock().unwrap();
+        if links.remove(token).is_none() {
+            return Ok(false);
+        }
+        self.save(&links)?;
+        Ok(true)
+    }
+
+    fn save(&self, links: &BTreeMap<String, ShareLink>) -> AppResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(links)
+            .map_err(|e| AppError::File(format!("Failed to serialize share links: {}", e)))?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Decrypt a stream the way the browser does
+    fn open_stream(key: &[u8; 32], stream: &[u8]) -> Option<Vec<u8>> {
+        let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).ok()?);
+        let prefix: [u8; NONCE_PREFIX_LEN] = stream.get(..NONCE_PREFIX_LEN)?.try_into().ok()?;
+        let records: Vec<&[u8]> = stream[NONCE_PREFIX_LEN..].chunks(RECORD_SIZE + TAG_LEN).collect();
+
+        let mut plain = Vec::new();
+        for (counter, record) in records.iter().enumerate() {
+            let nonce = record_nonce(&prefix, counter as u32, counter + 1 == records.len());
+            let mut record = record.to_vec();
+            let opened = key
+                .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut record)
+                .ok()?;
+            plain.extend_from_slice(opened);
+        }
+        Some(plain)
+    }
+
+    fn seal_all(link: &ShareLink, data: &[u8]) -> Vec<u8> {
+        let mut sealer = link.sealer().unwrap();
+        let mut stream = sealer.prefix().to_vec();
+        let records = record_count(data.len() as u64) as usize;
+        for index in 0..records {
+            let record = data[index * RECORD_SIZE..((index + 1) * RECORD_SIZE).min(data.len())].to_vec();
+            stream.extend(sealer.seal(record, index + 1 == records).unwrap());
+        }
+        stream
+    }
+
+    #[test]
+    fn test_stream_opens_only_with_passphrase() {
+        let temp_dir = TempDir::new().unwrap();
+        let links = ShareLinks::load(temp_dir.path());
+        let link = links.create("secret.txt", "correct horse", None, 1000).unwrap();
+        let salt = STANDARD.decode(&link.salt).unwrap();
+
+        let data: Vec<u8> = (0..2 * RECORD_SIZE + 10).map(|i| i as u8).collect();
+        let stream = seal_all(&link, &data);
+        assert_eq!(stream.len() as u64, sealed_len(data.len() as u64));
+
+        let key = derive_key("correct horse", &salt, link.iterations);
+        assert_eq!(open_stream(&key, &stream), Some(data));
+        assert_eq!(open_stream(&derive_key("wrong", &salt, link.iterations), &stream), None);
+
+        // Dropping the final record makes the one before it fail as a last record
+        let truncated = &stream[..NONCE_PREFIX_LEN + 2 * (RECORD_SIZE + TAG_LEN)];
+        assert_eq!(open_stream(&key, truncated), None);
+    }
+
+    #[test]
+    fn test_empty_file_has_one_record() {
+        let temp_dir = TempDir::new().unwrap();
+        let link = ShareLinks::load(temp_dir.path()).create("empty", "pass", None, 1000).unwrap();
+        let stream = seal_all(&link, &[]);
+        assert_eq!(stream.len() as u64, sealed_len(0));
+
+        let key = derive_key("pass", &STANDARD.decode(&link.salt).unwrap(), link.iterations);
+        assert_eq!(open_stream(&key, &stream), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_links_persist_and_expire() {
+        let temp_dir = TempDir::new().unwrap();
+        let links = ShareLinks::load(temp_dir.path());
+        let kept = links.create("a.txt", "pass", None, 1000).unwrap();
+        let expired = links.create("b.txt", "pass", Some(Duration::seconds(-1)), 1000).unwrap();
+
+        let reloaded = ShareLinks::load(temp_dir.path());
+        assert_eq!(reloaded.get(&kept.token).unwrap().file, "a.txt");
+        assert!(reloaded.get(&expired.token).is_none());
+        assert_eq!(reloaded.list().len(), 1);
+
+        assert!(reloaded.revoke(&kept.token).unwrap());
+        assert!(!reloaded.revoke(&kept.token).unwrap());
+        assert!(ShareLinks::load(temp_dir.path()).get(&kept.token).is_none());
+    }
+}
@@ -0,0 +1,349 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::warn;
+
+use crate::core::error::{AppError, AppResult};
+use crate::core::models::FileInfo;
+
+/// Hidden directory inside the shared folder where RustDrop keeps its state
+pub const STATE_DIR: &str = ".rustdrop";
+
+const METADATA_FILE: &str = "metadata.json";
+const MAX_TAG_LENGTH: usize = 32;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Metadata {
+    /// Tags keyed by file name
+    #[serde(default)]
+    tags: BTreeMap<String, BTreeSet<String>>,
+    /// Pinned file names keyed by client ID
+    #[serde(default)]
+    pins: BTreeMap<String, BTreeSet<String>>,
+    /// Sender display names keyed by file name
+    #[serde(default)]
+    senders: BTreeMap<String, String>,
+    /// File names shown in the public gallery
+    #[serde(default)]
+    published: BTreeSet<String>,
+}
+
+/// Per-file metadata (tags, pins, ...) stored alongside the share without touching the files themselves
+pub struct MetadataStore {
+    path: PathBuf,
+    data: RwLock<Metadata>,
+}
+
+impl MetadataStore {
+    /// Load the metadata for a shared directory, starting empty if none exists yet
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(STATE_DIR).join(METADATA_FILE);
+
+        let data = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable metadata file {:?}: {}", path, e);
+                Metadata::default()
+            }),
+            Err(_) => Metadata::default(),
+        };
+
+        Self {
+            path,
+            data: RwLock::new(data),
+        }
+    }
+
+    /// Tags assigned to a file, in sorted order
+    pub fn tags(&self, name: &str) -> Vec<String> {
+        let data = self.data.read().unwrap();
+        data.tags
+            .get(name)
+            .map(|tags| tags.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Replace the tags of a file. Tags must already be normalized with [`normalize_tag`].
+    pub fn set_tags(&self, name: &str, tags: &[String]) -> AppResult<Vec<String>> {
+        let mut data = self.data.write().unwrap();
+
+        let tags: BTreeSet<String> = tags.iter().cloned().collect();
+        if tags.is_empty() {
+            data.tags.remove(name);
+        } else {
+            data.tags.insert(name.to_string(), tags);
+        }
+
+        self.save(&data)?;
+        Ok(data.tags.get(name).map(|t| t.iter().cloned().collect()).unwrap_or_default())
+    }
+
+    /// Names of the files pinned by a client
+    pub fn pinned(&self, client_id: &str) -> BTreeSet<String> {
+        let data = self.data.read().unwrap();
+        data.pins.get(client_id).cloned().unwrap_or_default()
+    }
+
+    /// Pin or unpin a file for a single client
+    pub fn set_pinned(&self, client_id: &str, name: &str, pinned: bool) -> AppResult<()> {
+        let mut data = self.data.write().unwrap();
+
+        let pins = data.pins.entry(client_id.to_string()).or_default();
+        let changed = if pinned {
+            pins.insert(name.to_string())
+        } else {
+            pins.remove(name)
+        };
+
+        if pins.is_empty() {
+            data.pins.remove(client_id);
+        }
+
+        if changed {
+            self.save(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Remember who uploaded a file, forgetting any previous sender when `None`
+    pub fn set_sender(&self, name: &str, sender: Option<&str>) -> AppResult<()> {
+        let mut data = self.data.write().unwrap();
+
+        let changed = match sender {
+            Some(sender) => data.senders.insert(name.to_string(), sender.to_string()).as_deref() != Some(sender),
+            None => data.senders.remove(name).is_some(),
+        };
+
+        if changed {
+            self.save(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Names of the files shown in the public gallery
+    pub fn published(&self) -> BTreeSet<String> {
+        self.data.read().unwrap().published.clone()
+    }
+
+    /// Add a file to or take it out of the public gallery
+    pub fn set_published(&self, name: &str, published: bool) -> AppResult<()> {
+        let mut data = self.data.write().unwrap();
+
+        let changed = if published {
+            data.published.insert(name.to_string())
+        } else {
+            data.published.remove(name)
+        };
+
+        if changed {
+            self.save(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Carry tags, pins and sender over to a file's new name after it was moved
+    pub fn rename(&self, old_name: &str, new_name: &str) -> AppResult<()> {
+        let mut data = self.data.write().unwrap();
+
+        if let Some(tags) = data.tags.remove(old_name) {
+            data.tags.insert(new_name.to_string(), tags);
+        }
+        if let Some(sender) = data.senders.remove(old_name) {
+            data.senders.insert(new_name.to_string(), sender);
+        }
+        if data.published.remove(old_name) {
+            data.published.insert(new_name.to_string());
+        }
+        for pins in data.pins.values_mut() {
+            if pins.remove(old_name) {
+                pins.insert(new_name.to_string());
+            }
+        }
+
+        self.save(&data)
+    }
+
+    /// Drop all metadata of a deleted file
+    pub fn forget(&self, name: &str) -> AppResult<()> {
+        let mut data = self.data.write().unwrap();
+
+        data.tags.remove(name);
+        data.senders.remove(name);
+        data.published.remove(name);
+        for pins in data.pins.values_mut() {
+            pins.remove(name);
+        }
+        data.pins.retain(|_, pins| !pins.is_empty());
+
+        self.save(&data)
+    }
+
+    /// Fill in the stored metadata on a freshly read `FileInfo`
+    pub fn annotate(&self, file: &mut FileInfo) {
+        file.tags = self.tags(&file.name);
+        let data = self.data.read().unwrap();
+        file.sender = data.senders.get(&file.name).cloned();
+        file.published = data.published.contains(&file.name);
+    }
+
+    fn save(&self, data: &Metadata) -> AppResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(data)
+            .map_err(|e| AppError::File(format!("Failed to serialize metadata: {}", e)))?;
+
+        // Write to a temporary file first so a crash never leaves half-written metadata
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Normalize a user supplied tag (trimmed, lowercase), returning `None` if it is not a valid tag
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+        return None;
+    }
+
+    if !tag.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return None;
+    }
+
+    Some(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_empty_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MetadataStore::load(temp_dir.path());
+
+        assert!(store.tags("missing.txt").is_empty());
+        // Nothing should be written until metadata actually changes
+        assert!(!temp_dir.path().join(STATE_DIR).exists());
+    }
+
+    #[test]
+    fn test_set_tags_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MetadataStore::load(temp_dir.path());
+
+        let tags = store
+            .set_tags("receipt.pdf", &["receipts".to_string(), "2024".to_string()])
+            .unwrap();
+        assert_eq!(tags, vec!["2024", "receipts"]);
+
+        // Reloading from disk should give the same tags
+        let reloaded = MetadataStore::load(temp_dir.path());
+        assert_eq!(reloaded.tags("receipt.pdf"), vec!["2024", "receipts"]);
+    }
+
+    #[test]
+    fn test_clearing_tags_removes_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MetadataStore::load(temp_dir.path());
+
+        store.set_tags("photo.jpg", &["holiday".to_string()]).unwrap();
+        store.set_tags("photo.jpg", &[]).unwrap();
+
+        assert!(store.tags("photo.jpg").is_empty());
+    }
+
+    #[test]
+    fn test_pins_are_per_client() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MetadataStore::load(temp_dir.path());
+
+        store.set_pinned("phone", "a.txt", true).unwrap();
+        store.set_pinned("laptop", "b.txt", true).unwrap();
+
+        assert!(store.pinned("phone").contains("a.txt"));
+        assert!(!store.pinned("phone").contains("b.txt"));
+        assert!(store.pinned("laptop").contains("b.txt"));
+
+        // Pins survive a reload
+        let reloaded = MetadataStore::load(temp_dir.path());
+        assert!(reloaded.pinned("phone").contains("a.txt"));
+
+        store.set_pinned("phone", "a.txt", false).unwrap();
+        assert!(store.pinned("phone").is_empty());
+    }
+
+    #[test]
+    fn test_rename_and_forget() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MetadataStore::load(temp_dir.path());
+
+        store.set_tags("old.txt", &["work".to_string()]).unwrap();
+        store.set_pinned("phone", "old.txt", true).unwrap();
+        store.set_sender("old.txt", Some("Alex")).unwrap();
+        store.set_published("old.txt", true).unwrap();
+
+        store.rename("old.txt", "archive/new.txt").unwrap();
+        assert!(store.tags("old.txt").is_empty());
+        assert_eq!(store.tags("archive/new.txt"), vec!["work"]);
+        assert!(store.pinned("phone").contains("archive/new.txt"));
+
+        let mut file = FileInfo { name: "archive/new.txt".to_string(), ..Default::default() };
+        store.annotate(&mut file);
+        assert_eq!(file.sender.as_deref(), Some("Alex"));
+        assert!(file.published);
+
+        store.forget("archive/new.txt").unwrap();
+        assert!(store.tags("archive/new.txt").is_empty());
+        assert!(store.pinned("phone").is_empty());
+        assert!(store.published().is_empty());
+
+        store.annotate(&mut file);
+        assert_eq!(file.sender, None);
+    }
+
+    #[test]
+    fn test_sender_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MetadataStore::load(temp_dir.path());
+
+        store.set_sender("photo.jpg", Some("Grandma's phone")).unwrap();
+
+        let reloaded = MetadataStore::load(temp_dir.path());
+        let mut file = FileInfo { name: "photo.jpg".to_string(), ..Default::default() };
+        reloaded.annotate(&mut file);
+        assert_eq!(file.sender.as_deref(), Some("Grandma's phone"));
+
+        // Re-uploading anonymously clears the old sender
+        store.set_sender("photo.jpg", None).unwrap();
+        store.annotate(&mut file);
+        assert_eq!(file.sender, None);
+    }
+
+    #[test]
+    fn test_corrupt_metadata_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_dir = temp_dir.path().join(STATE_DIR);
+        std::fs::create_dir_all(&state_dir).unwrap();
+        std::fs::write(state_dir.join(METADATA_FILE), "not json").unwrap();
+
+        let store = MetadataStore::load(temp_dir.path());
+        assert!(store.tags("anything").is_empty());
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag("  Receipts "), Some("receipts".to_string()));
+        assert_eq!(normalize_tag("tax-2024"), Some("tax-2024".to_string()));
+        assert_eq!(normalize_tag(""), None);
+        assert_eq!(normalize_tag("   "), None);
+        assert_eq!(normalize_tag("two words"), None);
+        assert_eq!(normalize_tag("a,b"), None);
+        assert_eq!(normalize_tag(&"x".repeat(MAX_TAG_LENGTH + 1)), None);
+    }
+}
@@ -1,4 +1,37 @@
+pub mod access;
+pub mod address;
+pub mod alerts;
 pub mod app;
+pub mod backup;
+pub mod bundle;
+pub mod capabilities;
+#[cfg(any(feature = "quic", feature = "tls"))]
+pub mod certificate;
+pub mod clients;
+pub mod collect;
 pub mod config;
+pub mod courier;
 pub mod error;
+pub mod events;
+pub mod fingerprint;
+pub mod folders;
+pub mod history;
+pub mod idempotency;
+pub mod identity;
+pub mod instances;
+pub mod jump;
+pub mod links;
+pub mod metadata;
 pub mod models;
+pub mod notifications;
+pub mod pause;
+pub mod queue;
+pub mod quota;
+pub mod relay;
+pub mod schedule;
+pub mod search;
+pub mod share;
+pub mod snapshot;
+pub mod storage;
+pub mod transcode;
+pub mod uploads;
@@ -1,9 +1,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct FileInfo {
     pub id: Uuid,
     pub name: String,
@@ -12,6 +13,162 @@ pub struct FileInfo {
     pub size_human: String,
     pub modified: DateTime<Utc>,
     pub mime_type: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Whether the requesting client pinned this file
+    #[serde(default)]
+    pub pinned: bool,
+    /// Display name of whoever uploaded the file, if they set one
+    #[serde(default)]
+    pub sender: Option<String>,
+    /// Whether the file is shown in the public gallery
+    #[serde(default)]
+    pub published: bool,
+    /// Unix permission bits, so peers can keep scripts executable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+/// Broad kind of a file, derived from its MIME type, for filtering listings
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+    Image,
+    Video,
+    Audio,
+    Document,
+    Archive,
+    Other,
+}
+
+const ARCHIVE_TYPES: &[&str] = &[
+    "application/gzip",
+    "application/vnd.rar",
+    "application/x-7z-compressed",
+    "application/x-apple-diskimage",
+    "application/x-bzip2",
+    "application/x-iso9660-image",
+    "application/x-rar-compressed",
+    "application/x-tar",
+    "application/x-xz",
+    "application/zip",
+    "application/zstd",
+];
+
+const DOCUMENT_TYPES: &[&str] = &[
+    "application/epub+zip",
+    "application/msword",
+    "application/pdf",
+    "application/rtf",
+    "application/vnd.ms-excel",
+    "application/vnd.ms-powerpoint",
+];
+
+/// Prefixes of the OpenDocument and Office Open XML formats
+const DOCUMENT_PREFIXES: &[&str] = &["application/vnd.oasis.opendocument.", "application/vnd.openxmlformats-officedocument."];
+
+impl FileType {
+    pub fn of_mime(mime_type: &str) -> Self {
+        let mime_type = mime_type.split(';').next().unwrap_or_default().trim();
+        match mime_type.split('/').next().unwrap_or_default() {
+            "image" => FileType::Image,
+            "video" => FileType::Video,
+            "audio" => FileType::Audio,
+            "text" => FileType::Document,
+            _ if DOCUMENT_TYPES.contains(&mime_type) => FileType::Document,
+            _ if DOCUMENT_PREFIXES.iter().any(|prefix| mime_type.starts_with(prefix)) => FileType::Document,
+            _ if ARCHIVE_TYPES.contains(&mime_type) => FileType::Archive,
+            _ => FileType::Other,
+        }
+    }
+}
+
+impl FileInfo {
+    pub fn file_type(&self) -> FileType {
+        FileType::of_mime(&self.mime_type)
+    }
+}
+
+/// File system attributes sent along with file contents between devices
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct FileAttributes {
+    /// Modification time of the original file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+    /// Unix permission bits of the original file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Extended attributes of the original file, like macOS Finder tags
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty", with = "base64_values")]
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl FileAttributes {
+    pub fn of(file: &FileInfo) -> Self {
+        Self {
+            modified: Some(file.modified),
+            mode: file.mode,
+            xattrs: BTreeMap::new(),
+        }
+    }
+}
+
+/// What to do when an upload has the name of a file already in the share
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// Refuse with 409 and a description of both files, unless their content is the same
+    #[default]
+    Ask,
+    Replace,
+    /// Store the upload as `name (2).ext`
+    KeepBoth,
+    /// Leave the existing file alone and drop the upload
+    Skip,
+}
+
+/// One of the two files of an upload conflict
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConflictingFile {
+    pub name: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+    pub sha256: String,
+}
+
+/// The file in the share and the upload that would replace it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadConflict {
+    pub existing: ConflictingFile,
+    pub incoming: ConflictingFile,
+}
+
+/// (De)serializes binary map values as base64 strings
+pub mod base64_values {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer>(values: &BTreeMap<String, Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(values.iter().map(|(name, value)| (name, STANDARD.encode(value))))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Vec<u8>>, D::Error> {
+        BTreeMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(name, value)| Ok((name, STANDARD.decode(value).map_err(D::Error::custom)?)))
+            .collect()
+    }
+}
+
+/// Where and how to reach a device's QUIC transport
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct QuicInfo {
+    /// UDP port of the listener
+    pub port: u16,
+    /// SHA-256 of the device certificate the listener presents
+    pub fingerprint: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,6 +178,9 @@ pub struct DeviceInfo {
     pub ip: String,
     pub port: u16,
     pub os: String,
+    /// Served over HTTPS
+    #[serde(default)]
+    pub tls: bool,
 }
 
 impl DeviceInfo {
@@ -42,11 +202,13 @@ impl DeviceInfo {
             ip,
             port,
             os,
+            tls: false,
         }
     }
     
     pub fn url(&self) -> String {
-        format!("http://{}:{}", self.ip, self.port)
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}:{}", scheme, self.ip, self.port)
     }
 }
 
@@ -66,6 +228,7 @@ mod tests {
             size_human: "1.0 KiB".to_string(),
             modified: Utc::now(),
             mime_type: "text/plain".to_string(),
+            ..Default::default()
         };
 
         assert_eq!(file_info.name, "test.txt");
@@ -85,6 +248,7 @@ mod tests {
             size_human: "2.0 KiB".to_string(),
             modified: Utc::now(),
             mime_type: "application/json".to_string(),
+            ..Default::default()
         };
 
         // Test JSON serialization
@@ -100,6 +264,19 @@ mod tests {
         assert_eq!(deserialized.size, file_info.size);
     }
 
+    #[test]
+    fn test_xattrs_serialized_as_base64() {
+        let attributes = FileAttributes {
+            xattrs: BTreeMap::from([("com.apple.FinderInfo".to_string(), vec![0, 1, 255])]),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(&attributes).unwrap();
+        assert_eq!(json, serde_json::json!({ "xattrs": { "com.apple.FinderInfo": "AAH/" } }));
+        assert_eq!(serde_json::from_value::<FileAttributes>(json).unwrap(), attributes);
+        assert_eq!(serde_json::to_value(FileAttributes::default()).unwrap(), serde_json::json!({}));
+    }
+
     #[test]
     fn test_device_info_creation() {
         let port = 8080;
@@ -132,6 +309,9 @@ mod tests {
         // Verify URL format
         let expected_url = format!("http://{}:{}", device_info.ip, device_info.port);
         assert_eq!(url, expected_url);
+
+        let secure = DeviceInfo { tls: true, ..device_info };
+        assert_eq!(secure.url(), format!("https://{}:{}", secure.ip, secure.port));
     }
 
     #[test]
@@ -176,6 +356,7 @@ mod tests {
             size_human: "512 B".to_string(),
             modified: Utc::now(),
             mime_type: "text/plain".to_string(),
+            ..Default::default()
         };
 
         let cloned = original.clone();
@@ -234,10 +415,26 @@ mod tests {
                 size_human: "100 B".to_string(),
                 modified: Utc::now(),
                 mime_type: "application/octet-stream".to_string(),
+                ..Default::default()
             };
 
             assert_eq!(file_info.name, expected_name);
             assert_eq!(file_info.path, path);
         }
     }
+
+    #[test]
+    fn test_file_type_of_mime() {
+        assert_eq!(FileType::of_mime("image/jpeg"), FileType::Image);
+        assert_eq!(FileType::of_mime("video/mp4"), FileType::Video);
+        assert_eq!(FileType::of_mime("audio/mpeg"), FileType::Audio);
+        assert_eq!(FileType::of_mime("application/pdf"), FileType::Document);
+        assert_eq!(FileType::of_mime("text/plain; charset=utf-8"), FileType::Document);
+        assert_eq!(
+            FileType::of_mime("application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+            FileType::Document
+        );
+        assert_eq!(FileType::of_mime("application/zip"), FileType::Archive);
+        assert_eq!(FileType::of_mime("application/octet-stream"), FileType::Other);
+    }
 }
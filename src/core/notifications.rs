@@ -0,0 +1,351 @@
+//! Messages pushed to the host through ntfy, Telegram, Matrix or a webhook when files arrive or
+//! transfers fail, so nobody has to keep the page open to know something came in.
+//!
+//! Notifications follow the event bus. Uploads arriving within a few seconds of each other are
+//! announced together, so dropping a folder of photos sends one message instead of dozens.
+
+use humansize::{format_size, BINARY};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::core::config::NotificationsConfig;
+use crate::core::events::ServerEvent;
+use crate::core::history::{HistoryAction, HistoryEntry};
+
+/// How long to wait for more uploads before announcing the ones that arrived
+const UPLOAD_BATCH_WINDOW: Duration = Duration::from_secs(10);
+
+/// Files listed by name in one message
+const MAX_LISTED_FILES: usize = 10;
+
+const TELEGRAM_API: &str = "https://api.telegram.org";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+}
+
+/// A service notifications can be delivered to
+pub trait Notifier: Send + Sync {
+    /// Name of the service, for the logs
+    fn name(&self) -> &'static str;
+
+    /// The request delivering `notification`
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> reqwest::RequestBuilder;
+}
+
+struct Ntfy {
+    url: String,
+    token: Option<String>,
+}
+
+impl Notifier for Ntfy {
+    fn name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> reqwest::RequestBuilder {
+        // The title goes in the query, headers could not carry a non-ASCII device name
+        let request = http
+            .post(&self.url)
+            .query(&[("title", notification.title.as_str()), ("tags", "inbox_tray")])
+            .body(notification.message.clone());
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+struct Telegram {
+    bot_token: String,
+    chat_id: String,
+}
+
+impl Notifier for Telegram {
+    fn name(&self) -> &'static str {
+        "Telegram"
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> reqwest::RequestBuilder {
+        http.post(format!("{}/bot{}/sendMessage", TELEGRAM_API, self.bot_token))
+            .json(&serde_json::json!({
+                "chat_id": self.chat_id,
+                "text": format!("{}\n{}", notification.title, notification.message),
+            }))
+    }
+}
+
+struct Matrix {
+    homeserver: String,
+    access_token: String,
+    room_id: String,
+}
+
+impl Notifier for Matrix {
+    fn name(&self) -> &'static str {
+        "Matrix"
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> reqwest::RequestBuilder {
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+            self.homeserver.trim_end_matches('/'),
+            utf8_percent_encode(&self.room_id, NON_ALPHANUMERIC),
+            Uuid::new_v4()
+        );
+        // Notices, as bots send them, do not trigger other bots
+        http.put(url).bearer_auth(&self.access_token).json(&serde_json::json!({
+            "msgtype": "m.notice",
+            "body": format!("{}\n{}", notification.title, notification.message),
+        }))
+    }
+}
+
+struct Webhook {
+    url: String,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> reqwest::RequestBuilder {
+        http.post(&self.url).json(notification)
+    }
+}
+
+/// The notifiers configured under `[notifications]`, fed from the event bus
+pub struct Notifications {
+    config: NotificationsConfig,
+    notifiers: Vec<Box<dyn Notifier>>,
+    /// Name of this device, so several hosts can share a topic or chat
+    device: String,
+    http: reqwest::Client,
+}
+
+impl Notifications {
+    pub fn new(config: &NotificationsConfig, device: &str) -> Self {
+        let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+        if let Some(ntfy) = &config.ntfy {
+            notifiers.push(Box::new(Ntfy { url: ntfy.url.clone(), token: ntfy.token.clone() }));
+        }
+        if let Some(telegram) = &config.telegram {
+            notifiers.push(Box::new(Telegram {
+                bot_token: telegram.bot_token.clone(),
+                chat_id: telegram.chat_id.clone(),
+            }));
+        }
+        if let Some(matrix) = &config.matrix {
+            notifiers.push(Box::new(Matrix {
+                homeserver: matrix.homeserver.clone(),
+                access_token: matrix.access_token.clone(),
+                room_id: matrix.room_id.clone(),
+            }));
+        }
+        if let Some(url) = &config.webhook {
+            notifiers.push(Box::new(Webhook { url: url.clone() }));
+        }
+
+        Self {
+            config: config.clone(),
+            notifiers,
+            device: device.to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Whether there is anywhere to send notifications to
+    pub fn is_empty(&self) -> bool {
+        self.notifiers.is_empty()
+    }
+
+    /// Send notifications for the events on `events` until the bus closes
+    pub async fn run(self, mut events: broadcast::Receiver<ServerEvent>) {
+        let mut uploads: Vec<HistoryEntry> = Vec::new();
+        let mut deadline: Option<Instant> = None;
+
+        loop {
+            let received = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, events.recv()).await.ok(),
+                None => Some(events.recv().await),
+            };
+            // Announce a batch when its window is over, even while events keep coming
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                self.send(arrivals(&self.device, &uploads));
+                uploads.clear();
+                deadline = None;
+            }
+            let Some(received) = received else {
+                continue;
+            };
+
+            match received {
+                Ok(ServerEvent::Activity(entry)) if self.config.uploads && entry.action == HistoryAction::Upload => {
+                    deadline.get_or_insert_with(|| Instant::now() + UPLOAD_BATCH_WINDOW);
+                    uploads.push(entry);
+                }
+                Ok(event) if self.config.failures => {
+                    if let Some(notification) = failure(&self.device, &event) {
+                        self.send(notification);
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => debug!("Notifications missed {} events", missed),
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        if !uploads.is_empty() {
+            self.deliver(&arrivals(&self.device, &uploads)).await;
+        }
+    }
+
+    /// Send `notification` in the background
+    fn send(&self, notification: Notification) {
+        tokio::spawn(deliver_all(self.requests(&notification)));
+    }
+
+    /// Send `notification` to every notifier and wait until they answered
+    pub async fn deliver(&self, notification: &Notification) {
+        deliver_all(self.requests(notification)).await;
+    }
+
+    fn requests(&self, notification: &Notification) -> Vec<(&'static str, reqwest::RequestBuilder)> {
+        self.notifiers
+            .iter()
+            .map(|notifier| (notifier.name(), notifier.request(&self.http, notification)))
+            .collect()
+    }
+}
+
+async fn deliver_all(requests: Vec<(&'static str, reqwest::RequestBuilder)>) {
+    for (name, request) in requests {
+        let result = request
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            warn!("Failed to send notification to {}: {}", name, e);
+        }
+    }
+}
+
+/// One message for uploads that arrived together
+fn arrivals(device: &str, uploads: &[HistoryEntry]) -> Notification {
+    let size: u64 = uploads.iter().map(|entry| entry.size).sum();
+    let title = match uploads.len() {
+        1 => format!("New file on {}", device),
+        count => format!("{} new files on {} ({})", count, device, format_size(size, BINARY)),
+    };
+
+    let mut lines: Vec<String> = uploads
+        .iter()
+        .take(MAX_LISTED_FILES)
+        .map(|entry| {
+            let mut line = format!("{} ({})", entry.file, format_size(entry.size, BINARY));
+            if let Some(sender) = &entry.sender {
+                line.push_str(&format!(" from {}", sender));
+            }
+            line
+        })
+        .collect();
+    if uploads.len() > MAX_LISTED_FILES {
+        lines.push(format!("and {} more", uploads.len() - MAX_LISTED_FILES));
+    }
+
+    Notification { title, message: lines.join("\n") }
+}
+
+/// A message about something that failed, if the event is about a failure
+fn failure(device: &str, event: &ServerEvent) -> Option<Notification> {
+    match event {
+        ServerEvent::StorageProblem(report) => Some(Notification {
+            title: format!("Uploads failing on {}", device),
+            message: format!("{}: {}", report.problem.message(), report.detail),
+        }),
+        ServerEvent::TransferFailed(transfer) => Some(Notification {
+            title: format!("Transfer failed on {}", device),
+            message: format!(
+                "Could not send {} to {}: {}",
+                transfer.name,
+                transfer.peer,
+                transfer.error.as_deref().unwrap_or("unknown error")
+            ),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::NtfyConfig;
+    use crate::core::queue::{QueuedTransfer, TransferPriority};
+    use std::path::PathBuf;
+    use wiremock::matchers::{body_string, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn upload(file: &str, size: u64, sender: Option<&str>) -> HistoryEntry {
+        let mut entry = HistoryEntry::new(HistoryAction::Upload, file);
+        entry.size = size;
+        entry.sender = sender.map(str::to_string);
+        entry
+    }
+
+    #[test]
+    fn test_arrivals_are_summed_up() {
+        let one = arrivals("laptop", &[upload("a.jpg", 2048, Some("Ann"))]);
+        assert_eq!(one.title, "New file on laptop");
+        assert_eq!(one.message, "a.jpg (2 KiB) from Ann");
+
+        let uploads: Vec<HistoryEntry> = (0..12).map(|index| upload(&format!("{}.jpg", index), 1024, None)).collect();
+        let many = arrivals("laptop", &uploads);
+        assert_eq!(many.title, "12 new files on laptop (12 KiB)");
+        assert_eq!(many.message.lines().count(), MAX_LISTED_FILES + 1);
+        assert_eq!(many.message.lines().last(), Some("and 2 more"));
+    }
+
+    #[test]
+    fn test_failures() {
+        let mut transfer = QueuedTransfer::new("id", "a.txt", 1, PathBuf::from("a.txt"), "10.0.0.2:8080", TransferPriority::Normal);
+        transfer.error = Some("connection refused".to_string());
+        let notification = failure("laptop", &ServerEvent::TransferFailed(transfer)).unwrap();
+        assert_eq!(notification.title, "Transfer failed on laptop");
+        assert_eq!(notification.message, "Could not send a.txt to 10.0.0.2:8080: connection refused");
+
+        assert_eq!(failure("laptop", &ServerEvent::TransfersPaused { paused: true }), None);
+    }
+
+    #[tokio::test]
+    async fn test_ntfy_delivery() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/rustdrop"))
+            .and(query_param("title", "New file on Küche"))
+            .and(header("authorization", "Bearer secret"))
+            .and(body_string("a.jpg (2 KiB) from Ann"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = NotificationsConfig {
+            ntfy: Some(NtfyConfig { url: format!("{}/rustdrop", server.uri()), token: Some("secret".to_string()) }),
+            ..Default::default()
+        };
+        let notifications = Notifications::new(&config, "Küche");
+        assert!(!notifications.is_empty());
+        notifications.deliver(&arrivals("Küche", &[upload("a.jpg", 2048, Some("Ann"))])).await;
+    }
+}
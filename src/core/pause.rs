@@ -0,0 +1,60 @@
+use tokio::sync::watch;
+
+/// Switch suspending transfers, e.g. while the network is needed for a video call.
+///
+/// Sending from the queue stops between chunks, and peers uploading here in chunks are asked
+/// to wait. Nothing is cancelled, so every transfer continues where it was on resume.
+pub struct TransferPause {
+    paused: watch::Sender<bool>,
+}
+
+impl Default for TransferPause {
+    fn default() -> Self {
+        Self {
+            paused: watch::Sender::new(false),
+        }
+    }
+}
+
+impl TransferPause {
+    /// Pause or resume, returning whether that changed anything
+    pub fn set(&self, paused: bool) -> bool {
+        self.paused.send_replace(paused) != paused
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Return once transfers are not paused
+    pub async fn wait_until_resumed(&self) {
+        let mut paused = self.paused.subscribe();
+        // The sender lives as long as `self`, so this cannot fail
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_wait_until_resumed() {
+        let pause = Arc::new(TransferPause::default());
+        pause.wait_until_resumed().await;
+
+        assert!(pause.set(true));
+        assert!(!pause.set(true));
+        let waiting = tokio::spawn({
+            let pause = pause.clone();
+            async move { pause.wait_until_resumed().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        assert!(pause.set(false));
+        tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+    }
+}
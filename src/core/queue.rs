@@ -0,0 +1,357 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use thiserror::Error;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::utils::throughput::Throughput;
+
+/// Finished transfers kept for the list, the oldest are dropped first
+const MAX_FINISHED: usize = 100;
+
+/// Transfers of higher priority are sent first, equal ones in the order they were queued
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    Queued,
+    Sending,
+    Sent,
+    Failed,
+    Cancelled,
+}
+
+impl TransferStatus {
+    /// Whether the transfer is over, one way or another
+    pub fn is_finished(self) -> bool {
+        matches!(self, TransferStatus::Sent | TransferStatus::Failed | TransferStatus::Cancelled)
+    }
+}
+
+/// A file of the share waiting to be sent to another device, or being or having been sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedTransfer {
+    pub id: String,
+    pub file_id: String,
+    /// Name the file is stored under on the peer
+    pub name: String,
+    pub size: u64,
+    /// Address of the receiving device
+    pub peer: String,
+    pub priority: TransferPriority,
+    pub status: TransferStatus,
+    /// Bytes the peer has stored so far
+    pub sent: u64,
+    /// Bytes per second over the last seconds, while sending
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<u64>,
+    /// Seconds left at that speed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<u64>,
+    /// Speed about once a second, oldest first, for drawing a graph
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub speed_history: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created: DateTime<Utc>,
+    /// Where the file is in the share
+    #[serde(skip)]
+    pub source: PathBuf,
+    #[serde(skip)]
+    throughput: Throughput,
+}
+
+impl QueuedTransfer {
+    pub fn new(file_id: &str, name: &str, size: u64, source: PathBuf, peer: &str, priority: TransferPriority) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            file_id: file_id.to_string(),
+            name: name.to_string(),
+            size,
+            peer: peer.to_string(),
+            priority,
+            status: TransferStatus::Queued,
+            sent: 0,
+            speed: None,
+            eta_secs: None,
+            speed_history: Vec::new(),
+            error: None,
+            created: Utc::now(),
+            source,
+            throughput: Throughput::default(),
+        }
+    }
+
+    fn measure(&mut self, sent: u64) {
+        self.sent = sent;
+        self.throughput.record(sent);
+        self.speed = self.throughput.speed();
+        self.eta_secs = self.throughput.eta(self.size).map(|eta| eta.as_secs());
+        self.speed_history = self.throughput.history();
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QueueError {
+    #[error("Transfer not found")]
+    NotFound,
+
+    #[error("Transfer is no longer queued")]
+    NotQueued,
+}
+
+/// Transfers to other devices, sent one at a time so an urgent file does not have to share
+/// the link with everything queued before it. Kept in memory only.
+#[derive(Default)]
+pub struct TransferQueue {
+    transfers: Mutex<Vec<QueuedTransfer>>,
+    wake: Notify,
+    /// Tells the worker to drop the transfer it is sending
+    cancel: Notify,
+    worker_started: AtomicBool,
+}
+
+impl TransferQueue {
+    pub fn push(&self, transfer: QueuedTransfer) -> QueuedTransfer {
+        self.transfers.lock().unwrap().push(transfer.clone());
+        self.wake.notify_one();
+        transfer
+    }
+
+    /// All transfers in the order they are sent: the one in progress, then the queue, then
+    /// finished ones, most recent first
+    pub fn list(&self) -> Vec<QueuedTransfer> {
+        let mut transfers = self.transfers.lock().unwrap().clone();
+        transfers.sort_by(|a, b| {
+            let rank = |transfer: &QueuedTransfer| match transfer.status {
+                TransferStatus::Sending => 0,
+                TransferStatus::Queued => 1,
+                TransferStatus::Sent | TransferStatus::Failed | TransferStatus::Cancelled => 2,
+            };
+            rank(a).cmp(&rank(b)).then_with(|| match a.status {
+                TransferStatus::Queued => b.priority.cmp(&a.priority).then(a.created.cmp(&b.created)),
+                _ => b.created.cmp(&a.created),
+            })
+        });
+        transfers
+    }
+
+    pub fn get(&self, id: &str) -> Option<QueuedTransfer> {
+        self.transfers.lock().unwrap().iter().find(|transfer| transfer.id == id).cloned()
+    }
+
+    /// Move a transfer that is still waiting ahead of or behind others
+    pub fn set_priority(&self, id: &str, priority: TransferPriority) -> Result<QueuedTransfer, QueueError> {
+        let mut transfers = self.transfers.lock().unwrap();
+        let transfer = transfers
+            .iter_mut()
+            .find(|transfer| transfer.id == id)
+            .ok_or(QueueError::NotFound)?;
+        if transfer.status != TransferStatus::Queued {
+            return Err(QueueError::NotQueued);
+        }
+        transfer.priority = priority;
+        Ok(transfer.clone())
+    }
+
+    /// Mark the most urgent waiting transfer as sending and return it
+    pub fn start_next(&self) -> Option<QueuedTransfer> {
+        let mut transfers = self.transfers.lock().unwrap();
+        let transfer = transfers
+            .iter_mut()
+            .filter(|transfer| transfer.status == TransferStatus::Queued)
+            .min_by(|a, b| b.priority.cmp(&a.priority).then(a.created.cmp(&b.created)))?;
+        transfer.status = TransferStatus::Sending;
+        transfer.measure(0);
+        Some(transfer.clone())
+    }
+
+    /// Wait until a transfer is queued and start it
+    pub async fn next(&self) -> QueuedTransfer {
+        loop {
+            if let Some(transfer) = self.start_next() {
+                return transfer;
+            }
+            self.wake.notified().await;
+        }
+    }
+
+    pub fn progress(&self, id: &str, sent: u64) {
+        if let Some(transfer) = self.transfers.lock().unwrap().iter_mut().find(|transfer| transfer.id == id) {
+            transfer.measure(sent);
+        }
+    }
+
+    pub fn finish(&self, id: &str, result: Result<(), String>) {
+        let mut transfers = self.transfers.lock().unwrap();
+        // A cancelled transfer stays cancelled, however the attempt to send it ended
+        if let Some(transfer) = transfers
+            .iter_mut()
+            .find(|transfer| transfer.id == id && transfer.status != TransferStatus::Cancelled)
+        {
+            // The graph stays, to show how the transfer went
+            transfer.speed = None;
+            transfer.eta_secs = None;
+            match result {
+                Ok(()) => {
+                    transfer.status = TransferStatus::Sent;
+                    transfer.sent = transfer.size;
+                }
+                Err(error) => {
+                    transfer.status = TransferStatus::Failed;
+                    transfer.error = Some(error);
+                }
+            }
+        }
+
+        let mut excess = transfers
+            .iter()
+            .filter(|transfer| transfer.status.is_finished())
+            .count()
+            .saturating_sub(MAX_FINISHED);
+        transfers.retain(|transfer| {
+            if excess > 0 && transfer.status.is_finished() {
+                excess -= 1;
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Cancel every transfer that is waiting or being sent, returning how many there were.
+    /// The one being sent stops at once, the peer drops what it received after a while.
+    pub fn cancel_all(&self) -> usize {
+        let mut transfers = self.transfers.lock().unwrap();
+        let mut cancelled = 0;
+        for transfer in transfers.iter_mut().filter(|transfer| !transfer.status.is_finished()) {
+            transfer.status = TransferStatus::Cancelled;
+            transfer.speed = None;
+            transfer.eta_secs = None;
+            cancelled += 1;
+        }
+        self.cancel.notify_waiters();
+        cancelled
+    }
+
+    /// Resolves when [`TransferQueue::cancel_all`] is called
+    pub async fn cancelled(&self) {
+        self.cancel.notified().await;
+    }
+
+    /// Whether the transfer was cancelled, for a worker that may have missed the notification
+    pub fn is_cancelled(&self, id: &str) -> bool {
+        self.get(id).is_some_and(|transfer| transfer.status == TransferStatus::Cancelled)
+    }
+
+    /// Remove sent, failed and cancelled transfers from the list, returning how many
+    pub fn clear_finished(&self) -> usize {
+        let mut transfers = self.transfers.lock().unwrap();
+        let before = transfers.len();
+        transfers.retain(|transfer| !transfer.status.is_finished());
+        before - transfers.len()
+    }
+
+    /// True the first time it is called, for whoever should start the sending task
+    pub fn claim_worker(&self) -> bool {
+        !self.worker_started.swap(true, Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(name: &str, priority: TransferPriority) -> QueuedTransfer {
+        QueuedTransfer::new("id", name, 10, PathBuf::from(name), "10.0.0.2:8080", priority)
+    }
+
+    #[test]
+    fn test_urgent_transfer_jumps_ahead() {
+        let queue = TransferQueue::default();
+        let video = queue.push(transfer("video.mp4", TransferPriority::Normal));
+        let photo = queue.push(transfer("photo.jpg", TransferPriority::Normal));
+        let document = queue.push(transfer("contract.pdf", TransferPriority::Low));
+
+        queue.set_priority(&document.id, TransferPriority::High).unwrap();
+        let names: Vec<String> = queue.list().into_iter().map(|transfer| transfer.name).collect();
+        assert_eq!(names, vec!["contract.pdf", "video.mp4", "photo.jpg"]);
+
+        assert_eq!(queue.start_next().unwrap().id, document.id);
+        assert_eq!(
+            queue.set_priority(&document.id, TransferPriority::Low).unwrap_err(),
+            QueueError::NotQueued
+        );
+        queue.finish(&document.id, Ok(()));
+        assert_eq!(queue.start_next().unwrap().id, video.id);
+        queue.finish(&video.id, Err("peer went away".to_string()));
+        assert_eq!(queue.start_next().unwrap().id, photo.id);
+        assert!(queue.start_next().is_none());
+
+        let video = queue.get(&video.id).unwrap();
+        assert_eq!(video.status, TransferStatus::Failed);
+        assert_eq!(video.error.as_deref(), Some("peer went away"));
+        assert_eq!(queue.set_priority("missing", TransferPriority::High).unwrap_err(), QueueError::NotFound);
+    }
+
+    #[test]
+    fn test_sending_transfers_report_speed() {
+        let queue = TransferQueue::default();
+        let transfer = queue.push(transfer("video.mp4", TransferPriority::Normal));
+        queue.start_next();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        queue.progress(&transfer.id, 5);
+
+        let sending = queue.get(&transfer.id).unwrap();
+        assert!(sending.speed.is_some_and(|speed| speed > 0));
+        assert!(sending.eta_secs.is_some());
+        assert_eq!(sending.speed_history.len(), 1);
+
+        queue.finish(&transfer.id, Ok(()));
+        let sent = queue.get(&transfer.id).unwrap();
+        assert_eq!((sent.speed, sent.eta_secs), (None, None));
+        assert_eq!(sent.speed_history.len(), 1);
+    }
+
+    #[test]
+    fn test_cancel_all_and_clear_finished() {
+        let queue = TransferQueue::default();
+        let sent = queue.push(transfer("a.jpg", TransferPriority::Normal));
+        queue.start_next();
+        queue.finish(&sent.id, Ok(()));
+        let sending = queue.push(transfer("b.jpg", TransferPriority::Normal));
+        queue.start_next();
+        queue.push(transfer("c.jpg", TransferPriority::Normal));
+
+        assert_eq!(queue.cancel_all(), 2);
+        assert!(queue.is_cancelled(&sending.id));
+        assert!(queue.start_next().is_none());
+        // The worker reporting how the cancelled transfer ended changes nothing
+        queue.finish(&sending.id, Err("connection closed".to_string()));
+        assert_eq!(queue.get(&sending.id).unwrap().status, TransferStatus::Cancelled);
+
+        assert_eq!(queue.clear_finished(), 3);
+        assert!(queue.list().is_empty());
+    }
+
+    #[test]
+    fn test_finished_transfers_are_capped() {
+        let queue = TransferQueue::default();
+        for _ in 0..MAX_FINISHED + 5 {
+            let transfer = queue.push(transfer("a.txt", TransferPriority::Normal));
+            queue.start_next();
+            queue.finish(&transfer.id, Ok(()));
+        }
+        assert_eq!(queue.list().len(), MAX_FINISHED);
+    }
+}
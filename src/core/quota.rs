@@ -0,0 +1,127 @@
+//! A cap on how much a share may hold, so a drop box left running on a small SD card never
+//! fills the disk. With `evict_oldest`, the files received longest ago make way for new ones,
+//! but only for an upload that has arrived in full: one refused or abandoned costs nothing.
+
+use humansize::{format_size, BINARY};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::SystemTime;
+use thiserror::Error;
+
+use crate::core::config::ShareConfig;
+use crate::core::instances::instance_label;
+use crate::core::models::FileInfo;
+
+/// Why an upload cannot be taken in
+#[derive(Error, Debug, PartialEq)]
+pub enum QuotaError {
+    #[error("The share is limited to {}, {} more does not fit", format_size(*max_size, BINARY), format_size(*needed, BINARY))]
+    Full { max_size: u64, needed: u64 },
+}
+
+/// The `shares.<name>.max_size` limit and what to do when it is reached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub max_size: u64,
+    pub evict_oldest: bool,
+}
+
+impl Quota {
+    /// The limit configured for the share in `directory`, if any
+    pub fn for_share(shares: &BTreeMap<String, ShareConfig>, directory: &Path) -> Option<Self> {
+        let share = shares.get(&instance_label(directory))?;
+        share.max_size.map(|max_size| Self { max_size, evict_oldest: share.evict_oldest })
+    }
+
+    /// The files to remove, oldest received first, so that `incoming` more bytes fit next to
+    /// `files`. Files at `replacing` are about to be overwritten, so they neither count nor are
+    /// picked.
+    pub fn make_room<'a>(
+        &self,
+        files: &'a [FileInfo],
+        incoming: u64,
+        replacing: &[&Path],
+    ) -> Result<Vec<&'a FileInfo>, QuotaError> {
+        let kept = |file: &&FileInfo| !replacing.contains(&file.path.as_path());
+        let used: u64 = files.iter().filter(kept).map(|file| file.size).sum();
+        let mut excess = (used + incoming).saturating_sub(self.max_size);
+        if excess == 0 {
+            return Ok(Vec::new());
+        }
+        let full = QuotaError::Full { max_size: self.max_size, needed: excess };
+        if !self.evict_oldest {
+            return Err(full);
+        }
+
+        let mut candidates: Vec<_> = files
+            .iter()
+            .filter(kept)
+            .map(|file| (received(file), file))
+            .collect();
+        candidates.sort_by_key(|(received, _)| *received);
+
+        let mut evicted = Vec::new();
+        for (_, file) in candidates {
+            if excess == 0 {
+                return Ok(evicted);
+            }
+            excess = excess.saturating_sub(file.size);
+            evicted.push(file);
+        }
+        if excess == 0 { Ok(evicted) } else { Err(full) }
+    }
+}
+
+/// When a file arrived in the share: its creation time where the file system keeps one, as
+/// uploads may carry the modification time they had on the sender's device
+fn received(file: &FileInfo) -> SystemTime {
+    std::fs::metadata(&file.path)
+        .and_then(|metadata| metadata.created())
+        .unwrap_or_else(|_| file.modified.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::file::get_file_info;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_make_room() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut files = Vec::new();
+        for name in ["old.jpg", "middle.jpg", "new.jpg"] {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, "0123456789").unwrap();
+            files.push(get_file_info(&path).unwrap());
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        let quota = Quota { max_size: 35, evict_oldest: true };
+        assert!(quota.make_room(&files, 5, &[]).unwrap().is_empty());
+        let evicted = quota.make_room(&files, 15, &[]).unwrap();
+        assert_eq!(evicted.iter().map(|file| file.name.as_str()).collect::<Vec<_>>(), ["old.jpg"]);
+        // A file being replaced makes room by itself, and is not evicted on top of that
+        let replacing = [files[0].path.as_path()];
+        assert!(quota.make_room(&files, 15, &replacing).unwrap().is_empty());
+        let evicted = quota.make_room(&files, 25, &replacing).unwrap();
+        assert_eq!(evicted.iter().map(|file| file.name.as_str()).collect::<Vec<_>>(), ["middle.jpg"]);
+        assert_eq!(quota.make_room(&files, 40, &[]).unwrap_err(), QuotaError::Full { max_size: 35, needed: 35 });
+
+        let strict = Quota { evict_oldest: false, ..quota };
+        assert_eq!(strict.make_room(&files, 15, &[]).unwrap_err(), QuotaError::Full { max_size: 35, needed: 10 });
+    }
+
+    #[test]
+    fn test_quota_per_share() {
+        let shares = BTreeMap::from([(
+            "Photos".to_string(),
+            ShareConfig { max_size: Some(100), evict_oldest: true },
+        )]);
+        assert_eq!(
+            Quota::for_share(&shares, Path::new("/home/me/Photos")),
+            Some(Quota { max_size: 100, evict_oldest: true })
+        );
+        assert_eq!(Quota::for_share(&shares, Path::new("/home/me/Documents")), None);
+    }
+}
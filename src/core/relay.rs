@@ -0,0 +1,224 @@
+//! Transfers passed straight from an uploading client to a waiting downloader, without ever
+//! touching the host's disk: offered files, and named pipes of unknown length
+
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Chunks in flight between uploader and downloader; the uploader waits while they are full
+const RELAY_BUFFER_CHUNKS: usize = 16;
+
+/// Offers nobody picked up or started uploading, and pipes only one side connected to, are
+/// forgotten after this long
+const OFFER_MAX_AGE_HOURS: i64 = 1;
+
+/// Longest name of a pipe
+const MAX_PIPE_NAME_LENGTH: usize = 64;
+
+/// A piece of the relayed file, or the error that cut the upload short
+pub type RelayChunk = Result<Bytes, std::io::Error>;
+
+/// A file a client wants to hand to whichever device downloads it
+#[derive(Debug, Clone, Serialize)]
+pub struct RelayOffer {
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+    pub sender: Option<String>,
+    pub created: DateTime<Utc>,
+    /// Whether a device is downloading it already
+    pub claimed: bool,
+}
+
+struct Relay {
+    offer: RelayOffer,
+    upload: Option<mpsc::Sender<RelayChunk>>,
+    download: Option<mpsc::Receiver<RelayChunk>>,
+}
+
+/// A named pipe waiting for its other end
+struct Pipe {
+    created: DateTime<Utc>,
+    producer: Option<mpsc::Sender<RelayChunk>>,
+    consumer: Option<mpsc::Receiver<RelayChunk>>,
+}
+
+impl Pipe {
+    fn new() -> Self {
+        let (producer, consumer) = mpsc::channel(RELAY_BUFFER_CHUNKS);
+        Self {
+            created: Utc::now(),
+            producer: Some(producer),
+            consumer: Some(consumer),
+        }
+    }
+}
+
+/// Relay transfers and pipes in progress, kept in memory only
+#[derive(Default)]
+pub struct RelayHub {
+    relays: Mutex<HashMap<String, Relay>>,
+    pipes: Mutex<HashMap<String, Pipe>>,
+}
+
+impl RelayHub {
+    pub fn offer(&self, name: &str, size: u64, sender: Option<String>) -> RelayOffer {
+        let offer = RelayOffer {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            size,
+            sender,
+            created: Utc::now(),
+            claimed: false,
+        };
+        let (upload, download) = mpsc::channel(RELAY_BUFFER_CHUNKS);
+
+        let mut relays = self.relays.lock().unwrap();
+        let cutoff = Utc::now() - Duration::hours(OFFER_MAX_AGE_HOURS);
+        // Dropping an unstarted relay ends the download of anyone who claimed it
+        relays.retain(|_, relay| relay.offer.created > cutoff || relay.upload.is_none());
+        relays.insert(
+            offer.id.clone(),
+            Relay {
+                offer: offer.clone(),
+                upload: Some(upload),
+                download: Some(download),
+            },
+        );
+        offer
+    }
+
+    /// Offers no device has claimed yet, oldest first
+    pub fn waiting(&self) -> Vec<RelayOffer> {
+        let mut offers: Vec<RelayOffer> = self
+            .relays
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|relay| !relay.offer.claimed)
+            .map(|relay| relay.offer.clone())
+            .collect();
+        offers.sort_by_key(|offer| offer.created);
+        offers
+    }
+
+    pub fn get(&self, id: &str) -> Option<RelayOffer> {
+        self.relays.lock().unwrap().get(id).map(|relay| relay.offer.clone())
+    }
+
+    /// Take the receiving end of an offer; only one device can download it
+    pub fn claim(&self, id: &str) -> Option<(RelayOffer, mpsc::Receiver<RelayChunk>)> {
+        let mut relays = self.relays.lock().unwrap();
+        let relay = relays.get_mut(id)?;
+        let download = relay.download.take()?;
+        relay.offer.claimed = true;
+        Some((relay.offer.clone(), download))
+    }
+
+    /// Take the sending end of an offer; only one upload can feed it
+    pub fn start_upload(&self, id: &str) -> Option<(RelayOffer, mpsc::Sender<RelayChunk>)> {
+        let mut relays = self.relays.lock().unwrap();
+        let relay = relays.get_mut(id)?;
+        let upload = relay.upload.take()?;
+        Some((relay.offer.clone(), upload))
+    }
+
+    /// Forget a relay whose upload ended
+    pub fn finish(&self, id: &str) {
+        self.relays.lock().unwrap().remove(id);
+    }
+
+    /// Attach the producer of the pipe `name`, or `None` if it already has one
+    pub fn pipe_producer(&self, name: &str) -> Option<mpsc::Sender<RelayChunk>> {
+        self.attach(name, |pipe| pipe.producer.take())
+    }
+
+    /// Attach the consumer of the pipe `name`, or `None` if it already has one
+    pub fn pipe_consumer(&self, name: &str) -> Option<mpsc::Receiver<RelayChunk>> {
+        self.attach(name, |pipe| pipe.consumer.take())
+    }
+
+    /// Take one end of a pipe, creating the pipe if this is the first end to arrive. Once both
+    /// ends are taken the name is free for the next pipe.
+    fn attach<T>(&self, name: &str, take: impl FnOnce(&mut Pipe) -> Option<T>) -> Option<T> {
+        let mut pipes = self.pipes.lock().unwrap();
+        let cutoff = Utc::now() - Duration::hours(OFFER_MAX_AGE_HOURS);
+        pipes.retain(|_, pipe| pipe.created > cutoff);
+
+        let pipe = pipes.entry(name.to_string()).or_insert_with(Pipe::new);
+        let end = take(pipe)?;
+        if pipe.producer.is_none() && pipe.consumer.is_none() {
+            pipes.remove(name);
+        }
+        Some(end)
+    }
+}
+
+/// Pipe names are short and URL friendly: letters, digits, `-`, `_` and `.`
+pub fn valid_pipe_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_PIPE_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_relay_passes_chunks_once() {
+        let hub = RelayHub::default();
+        let offer = hub.offer("video.mp4", 6, Some("Phone".to_string()));
+        assert_eq!(hub.waiting().len(), 1);
+
+        let (_, mut download) = hub.claim(&offer.id).unwrap();
+        assert!(hub.claim(&offer.id).is_none());
+        assert!(hub.waiting().is_empty());
+        assert!(hub.get(&offer.id).unwrap().claimed);
+
+        let (_, upload) = hub.start_upload(&offer.id).unwrap();
+        assert!(hub.start_upload(&offer.id).is_none());
+        upload.send(Ok(Bytes::from_static(b"abc"))).await.unwrap();
+        upload.send(Ok(Bytes::from_static(b"def"))).await.unwrap();
+        drop(upload);
+        hub.finish(&offer.id);
+
+        assert_eq!(download.recv().await.unwrap().unwrap(), "abc");
+        assert_eq!(download.recv().await.unwrap().unwrap(), "def");
+        assert!(download.recv().await.is_none());
+        assert!(hub.get(&offer.id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pipe_ends_meet_in_any_order() {
+        let hub = RelayHub::default();
+
+        let mut consumer = hub.pipe_consumer("backup").unwrap();
+        assert!(hub.pipe_consumer("backup").is_none());
+        let producer = hub.pipe_producer("backup").unwrap();
+        producer.send(Ok(Bytes::from_static(b"tar"))).await.unwrap();
+        drop(producer);
+        assert_eq!(consumer.recv().await.unwrap().unwrap(), "tar");
+        assert!(consumer.recv().await.is_none());
+
+        // Both ends were taken, so the name starts a new pipe
+        let producer = hub.pipe_producer("backup").unwrap();
+        assert!(hub.pipe_producer("backup").is_none());
+        let mut consumer = hub.pipe_consumer("backup").unwrap();
+        producer.send(Ok(Bytes::from_static(b"again"))).await.unwrap();
+        assert_eq!(consumer.recv().await.unwrap().unwrap(), "again");
+    }
+
+    #[test]
+    fn test_valid_pipe_name() {
+        assert!(valid_pipe_name("nightly-backup_2.tar"));
+        assert!(!valid_pipe_name(""));
+        assert!(!valid_pipe_name("a/b"));
+        assert!(!valid_pipe_name("with space"));
+        assert!(!valid_pipe_name(&"x".repeat(65)));
+    }
+}
@@ -0,0 +1,175 @@
+//! Daily hours during which the server is available, e.g. a homework drop box that only takes
+//! files in the afternoon. Outside them, clients get a page saying when to come back and the
+//! server stops announcing itself over mDNS.
+
+use chrono::{Local, NaiveTime, Timelike};
+use std::time::Duration;
+use tracing::warn;
+
+/// How often the server checks whether it should open or close
+pub const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Window {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Window {
+    fn parse(entry: &str) -> Option<Self> {
+        let (start, end) = entry.split_once(['-', '–'])?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+        Some(Self { start, end })
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            // Runs past midnight, or all day when start and end are the same
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// When the server is available each day, in local time
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    windows: Vec<Window>,
+    /// Whether any windows were configured, valid or not
+    configured: bool,
+}
+
+impl Schedule {
+    /// Windows like `08:00-22:00`; one may run past midnight (`22:00-06:00`). Invalid entries
+    /// are skipped with a warning. Without any windows the server is always available, but when
+    /// none of the configured ones is valid it is never, rather than opening a share meant to be
+    /// limited all day because of a typo.
+    pub fn parse(entries: &[String]) -> Self {
+        let windows = entries
+            .iter()
+            .filter_map(|entry| {
+                let window = Window::parse(entry);
+                if window.is_none() {
+                    warn!("Ignoring invalid schedule window {:?}, expected e.g. \"08:00-22:00\"", entry);
+                }
+                window
+            })
+            .collect::<Vec<_>>();
+        let configured = !entries.is_empty();
+        if configured && windows.is_empty() {
+            warn!("No valid schedule window, the server stays closed until the schedule is fixed");
+        }
+        Self { windows, configured }
+    }
+
+    /// Whether the server is available all the time
+    pub fn is_always_open(&self) -> bool {
+        !self.configured
+    }
+
+    pub fn is_open_at(&self, time: NaiveTime) -> bool {
+        self.is_always_open() || self.windows.iter().any(|window| window.contains(time))
+    }
+
+    /// Whether the server is available right now
+    pub fn is_open(&self) -> bool {
+        self.is_open_at(Local::now().time())
+    }
+
+    /// Time from `time` until the next window opens, if the server is closed then. A day when
+    /// no window is valid.
+    pub fn until_open(&self, time: NaiveTime) -> Option<Duration> {
+        if self.is_open_at(time) {
+            return None;
+        }
+        if self.windows.is_empty() {
+            return Some(Duration::from_secs(SECONDS_PER_DAY.into()));
+        }
+        self.windows
+            .iter()
+            .map(|window| {
+                let seconds = (window.start.num_seconds_from_midnight() + SECONDS_PER_DAY
+                    - time.num_seconds_from_midnight())
+                    % SECONDS_PER_DAY;
+                Duration::from_secs(seconds.into())
+            })
+            .min()
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_always_open() {
+            return write!(f, "always");
+        }
+        if self.windows.is_empty() {
+            return write!(f, "never");
+        }
+        let windows: Vec<String> = self
+            .windows
+            .iter()
+            .map(|window| format!("{}–{}", window.start.format("%H:%M"), window.end.format("%H:%M")))
+            .collect();
+        write!(f, "{}", windows.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn schedule(entries: &[&str]) -> Schedule {
+        Schedule::parse(&entries.iter().map(|entry| entry.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_daytime_window() {
+        let schedule = schedule(&["08:00-22:00"]);
+        assert!(!schedule.is_open_at(at(7, 59)));
+        assert!(schedule.is_open_at(at(8, 0)));
+        assert!(schedule.is_open_at(at(21, 59)));
+        assert!(!schedule.is_open_at(at(22, 0)));
+
+        assert_eq!(schedule.until_open(at(12, 0)), None);
+        assert_eq!(schedule.until_open(at(7, 30)), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(schedule.until_open(at(23, 0)), Some(Duration::from_secs(9 * 60 * 60)));
+        assert_eq!(schedule.to_string(), "08:00–22:00");
+    }
+
+    #[test]
+    fn test_window_past_midnight_and_several_windows() {
+        let schedule = schedule(&["22:00 – 02:00", "12:00-13:00", "lunch"]);
+        assert!(schedule.is_open_at(at(23, 0)));
+        assert!(schedule.is_open_at(at(1, 0)));
+        assert!(schedule.is_open_at(at(12, 30)));
+        assert!(!schedule.is_open_at(at(3, 0)));
+        assert_eq!(schedule.until_open(at(3, 0)), Some(Duration::from_secs(9 * 60 * 60)));
+        assert_eq!(schedule.to_string(), "22:00–02:00, 12:00–13:00");
+    }
+
+    #[test]
+    fn test_no_windows_is_always_open() {
+        let schedule = schedule(&[]);
+        assert!(schedule.is_always_open());
+        assert!(schedule.is_open_at(at(3, 0)));
+        assert_eq!(schedule.until_open(at(3, 0)), None);
+    }
+
+    #[test]
+    fn test_only_invalid_windows_is_always_closed() {
+        let schedule = schedule(&["9-17", "lunch"]);
+        assert!(!schedule.is_always_open());
+        assert!(!schedule.is_open_at(at(12, 0)));
+        assert!(!schedule.is_open_at(at(3, 0)));
+        assert_eq!(schedule.until_open(at(12, 0)), Some(Duration::from_secs(24 * 60 * 60)));
+        assert_eq!(schedule.to_string(), "never");
+    }
+}
@@ -0,0 +1,205 @@
+//! Text extracted from the files of the share, so `/api/search` finds documents by what they
+//! say and not just by their name. Plugins, like the OCR of the `ocr` feature, fill it in.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::warn;
+
+use crate::core::error::{AppError, AppResult};
+use crate::core::metadata::STATE_DIR;
+
+const TEXT_FILE: &str = "text.json";
+
+/// Text kept of a single file; enough for hundreds of pages
+pub const MAX_TEXT_LENGTH: usize = 512 * 1024;
+
+/// Characters shown on either side of a match
+const SNIPPET_CONTEXT: usize = 60;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Texts {
+    /// Extracted text keyed by file name
+    #[serde(default)]
+    files: BTreeMap<String, String>,
+}
+
+/// The text of files that have any, stored alongside the share like their metadata
+pub struct TextIndex {
+    path: PathBuf,
+    data: RwLock<Texts>,
+}
+
+impl TextIndex {
+    /// Load the index of a shared directory, starting empty if none exists yet
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(STATE_DIR).join(TEXT_FILE);
+
+        let data = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable text index {:?}: {}", path, e);
+                Texts::default()
+            }),
+            Err(_) => Texts::default(),
+        };
+
+        Self {
+            path,
+            data: RwLock::new(data),
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Option<String> {
+        self.data.read().unwrap().files.get(name).cloned()
+    }
+
+    /// Store the text of a file, cut off after [`MAX_TEXT_LENGTH`] bytes
+    pub fn set(&self, name: &str, text: &str) -> AppResult<()> {
+        let mut end = text.len().min(MAX_TEXT_LENGTH);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let text = text[..end].trim();
+
+        let mut data = self.data.write().unwrap();
+        if text.is_empty() {
+            data.files.remove(name);
+        } else {
+            data.files.insert(name.to_string(), text.to_string());
+        }
+        self.save(&data)
+    }
+
+    /// Carry the text over to a file's new name after it was moved
+    pub fn rename(&self, old_name: &str, new_name: &str) -> AppResult<()> {
+        let mut data = self.data.write().unwrap();
+        match data.files.remove(old_name) {
+            Some(text) => data.files.insert(new_name.to_string(), text),
+            None => return Ok(()),
+        };
+        self.save(&data)
+    }
+
+    /// Drop the text of a deleted file
+    pub fn forget(&self, name: &str) -> AppResult<()> {
+        let mut data = self.data.write().unwrap();
+        if data.files.remove(name).is_none() {
+            return Ok(());
+        }
+        self.save(&data)
+    }
+
+    /// How a file matches `query`: None if it does not, otherwise the part of its text
+    /// matching, if the name alone did not
+    pub fn search(&self, query: &Query, name: &str) -> Option<Option<String>> {
+        let data = self.data.read().unwrap();
+        let text = data.files.get(name);
+        let name = name.to_lowercase();
+        let lowercase_text = text.map(|text| text.to_lowercase());
+
+        let mut snippet = None;
+        for term in &query.terms {
+            if name.contains(term.as_str()) {
+                continue;
+            }
+            let (text, lowercase_text) = text.zip(lowercase_text.as_deref())?;
+            let start = lowercase_text.find(term.as_str())?;
+            if snippet.is_none() {
+                snippet = Some(excerpt(text, lowercase_text, start, term.len()));
+            }
+        }
+        Some(snippet)
+    }
+
+    fn save(&self, data: &Texts) -> AppResult<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string(data)
+            .map_err(|e| AppError::File(format!("Failed to serialize text index: {}", e)))?;
+
+        // Write to a temporary file first so a crash never leaves a half-written index
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Words that all have to appear in a file's name or text, ignoring case
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    terms: Vec<String>,
+}
+
+impl Query {
+    /// None for a query without any words
+    pub fn parse(query: &str) -> Option<Self> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        (!terms.is_empty()).then_some(Self { terms })
+    }
+}
+
+/// The text around a match, found at `start` of the lowercase text, on a single line
+fn excerpt(text: &str, lowercase_text: &str, start: usize, length: usize) -> String {
+    // Lowercasing can change the length of some characters, then the positions do not carry over
+    let text = if text.len() == lowercase_text.len() { text } else { lowercase_text };
+
+    let before = text[..start].char_indices().rev().nth(SNIPPET_CONTEXT - 1).map_or(0, |(index, _)| index);
+    let after = text[start + length..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map_or(text.len(), |(index, _)| start + length + index);
+
+    let mut snippet = text[before..after].split_whitespace().collect::<Vec<_>>().join(" ");
+    if before > 0 {
+        snippet.insert(0, '…');
+    }
+    if after < text.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_search_names_and_text() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = TextIndex::load(temp_dir.path());
+        index.set("scans/Invoice.pdf", "ACME Corp\nInvoice 2024-117\nTotal due: 1,200.00 EUR").unwrap();
+
+        let query = Query::parse("invoice").unwrap();
+        assert_eq!(index.search(&query, "scans/Invoice.pdf"), Some(None));
+        let query = Query::parse("  acme  TOTAL ").unwrap();
+        assert_eq!(
+            index.search(&query, "scans/Invoice.pdf"),
+            Some(Some("ACME Corp Invoice 2024-117 Total due: 1,200.00 EUR".to_string()))
+        );
+        assert_eq!(index.search(&Query::parse("receipt").unwrap(), "scans/Invoice.pdf"), None);
+        assert_eq!(index.search(&Query::parse("acme").unwrap(), "photo.jpg"), None);
+        assert_eq!(Query::parse("   "), None);
+
+        // Survives a restart and follows the file around
+        let index = TextIndex::load(temp_dir.path());
+        index.rename("scans/Invoice.pdf", "Invoice.pdf").unwrap();
+        assert!(index.text("Invoice.pdf").unwrap().starts_with("ACME"));
+        index.forget("Invoice.pdf").unwrap();
+        assert_eq!(index.text("Invoice.pdf"), None);
+    }
+
+    #[test]
+    fn test_excerpts_are_cut_around_the_match() {
+        let text = format!("{} needle {}", "a ".repeat(100), "b ".repeat(100));
+        let snippet = excerpt(&text, &text, text.find("needle").unwrap(), "needle".len());
+        assert!(snippet.starts_with("…a a"));
+        assert!(snippet.contains("needle"));
+        assert!(snippet.ends_with("b b…"));
+        assert!(snippet.chars().count() < 2 * SNIPPET_CONTEXT + 10);
+    }
+}
@@ -0,0 +1,81 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Why a directory cannot be served
+#[derive(Error, Debug)]
+pub enum ShareProblem {
+    #[error("{0:?} does not exist (pass --create-dir to create it)")]
+    Missing(PathBuf),
+
+    #[error("{0:?} is not a directory")]
+    NotADirectory(PathBuf),
+
+    #[error("{0:?} cannot be read: {1}")]
+    NotReadable(PathBuf, io::Error),
+
+    #[error("{0:?} is not writable, so uploads would fail: {1}")]
+    NotWritable(PathBuf, io::Error),
+}
+
+/// Make sure `directory` can be served: it exists, can be listed and accepts new files.
+///
+/// With `create`, a missing directory is created first instead of being reported.
+pub fn prepare_share(directory: &Path, create: bool) -> Result<(), ShareProblem> {
+    if create && !directory.exists() {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| ShareProblem::NotWritable(directory.to_path_buf(), e))?;
+    }
+    check_share(directory)
+}
+
+/// Check that `directory` exists, can be listed and accepts new files, without changing anything
+pub fn check_share(directory: &Path) -> Result<(), ShareProblem> {
+    let path = directory.to_path_buf();
+    match std::fs::metadata(directory) {
+        Ok(metadata) if !metadata.is_dir() => return Err(ShareProblem::NotADirectory(path)),
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(ShareProblem::Missing(path)),
+        Err(e) => return Err(ShareProblem::NotReadable(path, e)),
+    }
+
+    std::fs::read_dir(directory).map_err(|e| ShareProblem::NotReadable(path.clone(), e))?;
+
+    // Uploads create files in the share, so try exactly that
+    let probe = directory.join(format!(".rustdrop-write-test-{}", Uuid::new_v4()));
+    std::fs::write(&probe, b"").map_err(|e| ShareProblem::NotWritable(path, e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_share() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(check_share(temp_dir.path()).is_ok());
+        // The write probe is cleaned up
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+        let missing = temp_dir.path().join("missing");
+        assert!(matches!(check_share(&missing), Err(ShareProblem::Missing(_))));
+
+        let file = temp_dir.path().join("file.txt");
+        std::fs::write(&file, "x").unwrap();
+        assert!(matches!(check_share(&file), Err(ShareProblem::NotADirectory(_))));
+    }
+
+    #[test]
+    fn test_prepare_share_creates_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("new/share");
+
+        assert!(prepare_share(&missing, false).is_err());
+        assert!(prepare_share(&missing, true).is_ok());
+        assert!(missing.is_dir());
+    }
+}
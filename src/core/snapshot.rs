@@ -0,0 +1,155 @@
+//! A frozen view of the share, taken once at startup with `--snapshot`, so listings and
+//! downloads stay consistent while other programs keep changing the folder.
+//!
+//! An index snapshot only remembers what the files looked like and stops serving the ones that
+//! changed since. A copy snapshot copies every file to a temporary directory first, which costs
+//! the time and space of the copy but keeps every file available.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::core::config::SnapshotMode;
+use crate::core::models::FileInfo;
+use crate::utils::file::set_modified;
+
+/// What `GET /api/snapshot` reports
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SnapshotInfo {
+    pub mode: SnapshotMode,
+    pub taken: DateTime<Utc>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+pub struct Snapshot {
+    mode: SnapshotMode,
+    taken: DateTime<Utc>,
+    files: Vec<FileInfo>,
+    /// Files only the storage backend had
+    remote: HashSet<Uuid>,
+    /// Holds the copies of a copy snapshot, removed with the snapshot
+    copies: Option<PathBuf>,
+}
+
+impl Snapshot {
+    /// Freeze `files`, the share as listed right now. Copies go to a new directory under the
+    /// system's temporary directory; files only a storage backend has are not copied.
+    pub fn take(files: Vec<FileInfo>, mode: SnapshotMode) -> Result<Self> {
+        let taken = Utc::now();
+        let remote = files.iter().filter(|file| !file.path.exists()).map(|file| file.id).collect();
+        if mode != SnapshotMode::Copy {
+            return Ok(Self { mode, taken, files, remote, copies: None });
+        }
+
+        let copies = std::env::temp_dir().join(format!("rustdrop-snapshot-{}", Uuid::new_v4()));
+        let mut snapshot = Self { mode, taken, files: Vec::new(), remote, copies: Some(copies.clone()) };
+        for mut file in files {
+            if file.path.exists() {
+                let copy = copies.join(&file.name);
+                copy_file(&file.path, &copy, file.modified)
+                    .with_context(|| format!("Failed to copy {} into the snapshot", file.name))?;
+                file.path = copy;
+            }
+            snapshot.files.push(file);
+        }
+        Ok(snapshot)
+    }
+
+    pub fn mode(&self) -> SnapshotMode {
+        self.mode
+    }
+
+    pub fn files(&self) -> &[FileInfo] {
+        &self.files
+    }
+
+    /// Whether `file` of the snapshot can still be served as it was listed. Copies always can,
+    /// originals only while nobody changed them.
+    pub fn is_current(&self, file: &FileInfo) -> bool {
+        // The storage backend is not watched
+        if self.copies.is_some() || self.remote.contains(&file.id) {
+            return true;
+        }
+        let Ok(metadata) = std::fs::metadata(&file.path) else {
+            return false;
+        };
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        metadata.len() == file.size && modified == Some(file.modified)
+    }
+
+    pub fn info(&self) -> SnapshotInfo {
+        SnapshotInfo {
+            mode: self.mode,
+            taken: self.taken,
+            files: self.files.len(),
+            bytes: self.files.iter().map(|file| file.size).sum(),
+        }
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Some(copies) = &self.copies {
+            if let Err(e) = std::fs::remove_dir_all(copies) {
+                warn!("Failed to remove the snapshot copies in {:?}: {}", copies, e);
+            }
+        }
+    }
+}
+
+fn copy_file(from: &Path, to: &Path, modified: DateTime<Utc>) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(from, to)?;
+    set_modified(to, modified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::file::list_share;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_index_snapshot_drops_changed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "first").unwrap();
+        std::fs::write(temp_dir.path().join("b.txt"), "second").unwrap();
+        let snapshot = Snapshot::take(list_share(temp_dir.path()).unwrap(), SnapshotMode::Index).unwrap();
+
+        std::fs::write(temp_dir.path().join("a.txt"), "changed").unwrap();
+        std::fs::write(temp_dir.path().join("c.txt"), "new").unwrap();
+        assert_eq!(snapshot.files().len(), 2);
+        let current: Vec<&str> = snapshot
+            .files()
+            .iter()
+            .filter(|file| snapshot.is_current(file))
+            .map(|file| file.name.as_str())
+            .collect();
+        assert_eq!(current, vec!["b.txt"]);
+    }
+
+    #[test]
+    fn test_copy_snapshot_keeps_the_old_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        std::fs::write(temp_dir.path().join("docs/plan.md"), "draft").unwrap();
+        let snapshot = Snapshot::take(list_share(temp_dir.path()).unwrap(), SnapshotMode::Copy).unwrap();
+
+        std::fs::write(temp_dir.path().join("docs/plan.md"), "final version").unwrap();
+        let [file] = snapshot.files() else { panic!("expected one file") };
+        assert!(snapshot.is_current(file));
+        assert_eq!(std::fs::read_to_string(&file.path).unwrap(), "draft");
+        assert_eq!(snapshot.info().bytes, 5);
+
+        let copies = snapshot.copies.clone().unwrap();
+        drop(snapshot);
+        assert!(!copies.exists());
+    }
+}
@@ -0,0 +1,98 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+
+/// Conditions of the share's file system that make uploads fail until someone steps in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageProblem {
+    DiskFull,
+    NotWritable,
+}
+
+impl StorageProblem {
+    /// Recognize the errors an upload cannot recover from by retrying
+    pub fn of(e: &std::io::Error) -> Option<Self> {
+        match e.kind() {
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded => Some(Self::DiskFull),
+            ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => Some(Self::NotWritable),
+            _ => None,
+        }
+    }
+
+    /// Explanation for the person uploading
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::DiskFull => "The receiving device is out of disk space",
+            Self::NotWritable => "The receiving device cannot write to its shared folder",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageReport {
+    pub problem: StorageProblem,
+    pub since: DateTime<Utc>,
+    /// The underlying error, for the host
+    pub detail: String,
+}
+
+/// The last storage problem an upload ran into, cleared by the next successful upload
+#[derive(Default)]
+pub struct StorageHealth {
+    current: Mutex<Option<StorageReport>>,
+}
+
+impl StorageHealth {
+    /// Remember a problem, returning true if it is new and worth announcing
+    pub fn report(&self, problem: StorageProblem, detail: String) -> bool {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_some_and(|report| report.problem == problem) {
+            return false;
+        }
+
+        *current = Some(StorageReport {
+            problem,
+            since: Utc::now(),
+            detail,
+        });
+        true
+    }
+
+    pub fn clear(&self) {
+        self.current.lock().unwrap().take();
+    }
+
+    pub fn current(&self) -> Option<StorageReport> {
+        self.current.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Error;
+
+    #[test]
+    fn test_problem_of_error() {
+        assert_eq!(StorageProblem::of(&Error::from(ErrorKind::StorageFull)), Some(StorageProblem::DiskFull));
+        assert_eq!(
+            StorageProblem::of(&Error::from(ErrorKind::PermissionDenied)),
+            Some(StorageProblem::NotWritable)
+        );
+        assert_eq!(StorageProblem::of(&Error::from(ErrorKind::NotFound)), None);
+    }
+
+    #[test]
+    fn test_report_once_until_cleared() {
+        let health = StorageHealth::default();
+        assert!(health.report(StorageProblem::DiskFull, "no space".to_string()));
+        assert!(!health.report(StorageProblem::DiskFull, "no space".to_string()));
+        assert_eq!(health.current().unwrap().problem, StorageProblem::DiskFull);
+
+        health.clear();
+        assert!(health.current().is_none());
+        assert!(health.report(StorageProblem::DiskFull, "no space".to_string()));
+    }
+}
@@ -0,0 +1,295 @@
+//! Copies of videos in H.264 and AAC, which every browser plays, for videos recorded in codecs
+//! some browsers cannot decode, like HEVC from phones, AV1, or AC-3 sound in MKV files.
+//!
+//! ffmpeg makes them with the `transcode` feature: on demand when a browser fails to play a
+//! video, or in the background after uploads with `transcode.background`. Copies are kept in the
+//! share's state directory, named after the version of the video they were made from.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{watch, Semaphore};
+use tracing::{info, warn};
+
+use crate::core::metadata::STATE_DIR;
+use crate::core::models::FileInfo;
+
+/// Whether this build can transcode at all
+pub const AVAILABLE: bool = cfg!(feature = "transcode");
+
+const TRANSCODE_DIR: &str = "transcoded";
+
+/// Videos converted at once; each keeps every core busy already
+const MAX_RUNNING: usize = 1;
+
+/// Where a copy of a video stands
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TranscodeStatus {
+    /// Waiting or converting, with the fraction done from 0 to 1 once ffmpeg reported any
+    Running { progress: Option<f64> },
+    /// The copy is ready, `size` bytes large
+    Done { size: u64 },
+    Failed { error: String },
+}
+
+/// The copies of a share and the conversions under way
+pub struct Transcoder {
+    directory: PathBuf,
+    /// Conversions started, by the path of their copy
+    jobs: Mutex<HashMap<PathBuf, watch::Receiver<TranscodeStatus>>>,
+    running: Semaphore,
+}
+
+impl Transcoder {
+    pub fn new(share: &Path) -> Self {
+        Self {
+            directory: share.join(STATE_DIR).join(TRANSCODE_DIR),
+            jobs: Mutex::default(),
+            running: Semaphore::new(MAX_RUNNING),
+        }
+    }
+
+    /// Where the copy of this version of a video goes
+    pub fn output_path(&self, file: &FileInfo) -> PathBuf {
+        self.directory.join(format!("{}-{}.mp4", file.id, file.modified.timestamp()))
+    }
+
+    /// How the copy of a video is coming along, None if nobody asked for one
+    pub fn status(&self, file: &FileInfo) -> Option<TranscodeStatus> {
+        let output = self.output_path(file);
+        if let Some(job) = self.jobs.lock().unwrap().get(&output) {
+            return Some(job.borrow().clone());
+        }
+        let size = std::fs::metadata(&output).ok()?.len();
+        Some(TranscodeStatus::Done { size })
+    }
+
+    /// Start converting a video unless its copy exists or is being made; failed conversions are
+    /// tried again
+    pub fn start(self: &Arc<Self>, file: &FileInfo) -> TranscodeStatus {
+        self.job(file).borrow().clone()
+    }
+
+    /// Convert a video unless its copy exists, and wait until it does
+    pub async fn finish(self: &Arc<Self>, file: &FileInfo) -> TranscodeStatus {
+        let mut job = self.job(file);
+        let status = job
+            .wait_for(|status| !matches!(status, TranscodeStatus::Running { .. }))
+            .await
+            .map(|status| status.clone());
+        status.unwrap_or_else(|_| TranscodeStatus::Failed { error: "Conversion was abandoned".to_string() })
+    }
+
+    fn job(self: &Arc<Self>, file: &FileInfo) -> watch::Receiver<TranscodeStatus> {
+        let output = self.output_path(file);
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get(&output) {
+            if !matches!(*job.borrow(), TranscodeStatus::Failed { .. }) {
+                return job.clone();
+            }
+        }
+        if let Ok(metadata) = std::fs::metadata(&output) {
+            return watch::channel(TranscodeStatus::Done { size: metadata.len() }).1;
+        }
+
+        let (status, job) = watch::channel(TranscodeStatus::Running { progress: None });
+        jobs.insert(output.clone(), job.clone());
+        let transcoder = self.clone();
+        let file = file.clone();
+        tokio::spawn(async move {
+            let result = transcoder.convert(&file, &output, &status).await;
+            match result {
+                Ok(size) => {
+                    info!("Converted {} for browsers that cannot play it", file.name);
+                    status.send_replace(TranscodeStatus::Done { size });
+                    // The copy itself says it is done from now on
+                    transcoder.jobs.lock().unwrap().remove(&output);
+                }
+                Err(e) => {
+                    warn!("Failed to convert {}: {:#}", file.name, e);
+                    status.send_replace(TranscodeStatus::Failed { error: format!("{:#}", e) });
+                }
+            }
+        });
+        job
+    }
+
+    async fn convert(&self, file: &FileInfo, output: &Path, status: &watch::Sender<TranscodeStatus>) -> Result<u64> {
+        let _permit = self.running.acquire().await?;
+        tokio::fs::create_dir_all(&self.directory).await?;
+        self.remove_stale_copies(file).await;
+
+        // Written under another name first, so an interrupted conversion is never taken for a copy
+        let partial = output.with_extension("partial.mp4");
+        let converted = ffmpeg::convert(&file.path, &partial, |progress| {
+            status.send_replace(TranscodeStatus::Running { progress: Some(progress) });
+        })
+        .await;
+        if let Err(e) = converted {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e);
+        }
+        tokio::fs::rename(&partial, output).await?;
+        Ok(tokio::fs::metadata(output).await?.len())
+    }
+
+    /// Drop copies made from older versions of the same video
+    async fn remove_stale_copies(&self, file: &FileInfo) {
+        let prefix = format!("{}-", file.id);
+        let Ok(mut entries) = tokio::fs::read_dir(&self.directory).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = tokio::fs::remove_file(entry.path()).await;
+            }
+        }
+    }
+}
+
+/// Whether every browser can play a video with these streams, as listed by
+/// `ffprobe -show_entries stream=codec_name,codec_type -of csv=p=0`
+pub fn playable(streams: &str) -> bool {
+    streams.lines().filter_map(|line| line.split_once(',')).all(|(codec, kind)| match kind.trim() {
+        "video" => codec == "h264",
+        "audio" => matches!(codec, "aac" | "mp3"),
+        _ => true,
+    })
+}
+
+#[cfg(feature = "transcode")]
+mod ffmpeg {
+    use anyhow::{bail, Context, Result};
+    use std::path::Path;
+    use std::process::Stdio;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    /// Convert `input` to H.264 and AAC in an MP4 that starts playing before it is downloaded,
+    /// reporting the fraction done along the way
+    pub async fn convert(input: &Path, output: &Path, progress: impl Fn(f64)) -> Result<()> {
+        let duration = duration(input).await;
+        let mut child = tokio::process::Command::new("ffmpeg")
+            .args(["-hide_banner", "-nostdin", "-loglevel", "error", "-y", "-i"])
+            .arg(input)
+            .args(["-map", "0:v:0", "-map", "0:a:0?"])
+            .args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-pix_fmt", "yuv420p"])
+            .args(["-c:a", "aac", "-b:a", "160k", "-movflags", "+faststart"])
+            .args(["-progress", "pipe:1", "-nostats"])
+            .arg(output)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to run ffmpeg (is it installed?)")?;
+
+        let stdout = child.stdout.take().context("ffmpeg has no output")?;
+        let mut stderr = child.stderr.take().context("ffmpeg has no error output")?;
+        let report = async {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let position = line.strip_prefix("out_time_us=").and_then(|value| value.parse::<f64>().ok());
+                if let (Some(position), Some(duration)) = (position, duration) {
+                    progress((position / 1_000_000.0 / duration).clamp(0.0, 1.0));
+                }
+            }
+        };
+        let mut errors = String::new();
+        let (_, _, status) = tokio::join!(report, stderr.read_to_string(&mut errors), child.wait());
+
+        if !status?.success() {
+            bail!("ffmpeg failed: {}", errors.trim().lines().last().unwrap_or("unknown error"));
+        }
+        Ok(())
+    }
+
+    /// Whether every browser can play `path` as it is
+    pub async fn playable(path: &Path) -> Result<bool> {
+        let streams = probe(path, "stream=codec_name,codec_type").await?;
+        Ok(super::playable(&streams))
+    }
+
+    /// Length of a video in seconds
+    async fn duration(path: &Path) -> Option<f64> {
+        let duration = probe(path, "format=duration").await.ok()?;
+        duration.trim().parse().ok().filter(|duration: &f64| *duration > 0.0)
+    }
+
+    async fn probe(path: &Path, entries: &str) -> Result<String> {
+        let output = tokio::process::Command::new("ffprobe")
+            .args(["-v", "error", "-show_entries", entries, "-of", "csv=p=0"])
+            .arg(path)
+            .kill_on_drop(true)
+            .output()
+            .await
+            .context("Failed to run ffprobe (is ffmpeg installed?)")?;
+        if !output.status.success() {
+            bail!("ffprobe failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[cfg(not(feature = "transcode"))]
+mod ffmpeg {
+    use anyhow::{bail, Result};
+    use std::path::Path;
+
+    pub async fn convert(_input: &Path, _output: &Path, _progress: impl Fn(f64)) -> Result<()> {
+        bail!("Transcoding is not enabled in this build")
+    }
+
+    pub async fn playable(_path: &Path) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Make the copy of an upload in the background when some browsers could not play it
+pub async fn prepare(transcoder: &Arc<Transcoder>, file: &FileInfo) {
+    if !file.mime_type.starts_with("video/") {
+        return;
+    }
+    match ffmpeg::playable(&file.path).await {
+        Ok(true) => {}
+        Ok(false) => {
+            transcoder.finish(file).await;
+        }
+        Err(e) => warn!("Failed to inspect {}: {:#}", file.name, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::file::get_share_file_info;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_playable_streams() {
+        assert!(playable("h264,video\naac,audio\n"));
+        assert!(playable("h264,video\n"));
+        assert!(playable("h264,video\nmov_text,subtitle\n"));
+        assert!(!playable("hevc,video\naac,audio\n"));
+        assert!(!playable("h264,video\nac3,audio\n"));
+        assert!(!playable("av1,video\nopus,audio\n"));
+    }
+
+    #[tokio::test]
+    async fn test_finished_copies_are_found() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("clip.mkv");
+        std::fs::write(&path, b"matroska").unwrap();
+        let file = get_share_file_info(temp_dir.path(), &path).unwrap();
+        let transcoder = Arc::new(Transcoder::new(temp_dir.path()));
+        assert_eq!(transcoder.status(&file), None);
+
+        let output = transcoder.output_path(&file);
+        std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+        std::fs::write(&output, b"mp4 copy").unwrap();
+        assert_eq!(transcoder.status(&file), Some(TranscodeStatus::Done { size: 8 }));
+        assert_eq!(transcoder.start(&file), TranscodeStatus::Done { size: 8 });
+    }
+}
@@ -0,0 +1,436 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::core::metadata::STATE_DIR;
+use crate::core::models::FileAttributes;
+
+const UPLOADS_DIR: &str = "uploads";
+
+/// An upload that can be continued chunk by chunk, possibly over several connections.
+///
+/// Sessions belong to a client identity rather than a network address, so a device that
+/// changes IP mid-transfer picks up where it left off.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub id: String,
+    /// Client that started the upload; only it may see or continue the session
+    pub owner: String,
+    pub name: String,
+    /// Target directory relative to the share, automatic placement when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    pub size: u64,
+    /// Attributes the file had on the sending device, applied once the upload is complete
+    #[serde(flatten)]
+    pub attributes: FileAttributes,
+    /// Bytes received so far
+    pub offset: u64,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Batch the upload belongs to; it stays here once complete until the whole batch is committed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<String>,
+}
+
+impl UploadSession {
+    pub fn is_complete(&self) -> bool {
+        self.offset >= self.size
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum UploadError {
+    #[error("Upload session not found")]
+    NotFound,
+
+    #[error("Chunk starts at {given} but the upload is at {expected}")]
+    OffsetMismatch { given: u64, expected: u64 },
+
+    #[error("Chunk goes past the declared upload size")]
+    TooLarge,
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Corrupt upload session: {0}")]
+    Corrupt(String),
+}
+
+/// Unfinished uploads of a share, kept in `.rustdrop/uploads` as `<id>.json` plus `<id>.part`
+pub struct UploadStore {
+    directory: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl UploadStore {
+    pub fn open(share: &Path) -> Self {
+        Self {
+            directory: share.join(STATE_DIR).join(UPLOADS_DIR),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn create(
+        &self,
+        owner: &str,
+        name: &str,
+        size: u64,
+        dir: Option<String>,
+        sender: Option<String>,
+        attributes: FileAttributes,
+    ) -> Result<UploadSession, UploadError> {
+        let _guard = self.lock.lock().unwrap();
+        std::fs::create_dir_all(&self.directory)?;
+
+        let now = Utc::now();
+        let session = UploadSession {
+            id: Uuid::new_v4().to_string(),
+            owner: owner.to_string(),
+            name: name.to_string(),
+            dir,
+            sender,
+            size,
+            attributes,
+            offset: 0,
+            created: now,
+            updated: now,
+            batch: None,
+        };
+
+        std::fs::File::create(self.part_path(&session.id))?;
+        self.save(&session)?;
+        Ok(session)
+    }
+
+    /// Session `id` if it exists and belongs to `owner`
+    pub fn get(&self, id: &str, owner: &str) -> Result<UploadSession, UploadError> {
+        let _guard = self.lock.lock().unwrap();
+        self.load_owned(id, owner)
+    }
+
+    /// Make the fresh session `id` part of `batch`, a UUID chosen by the client
+    pub fn join_batch(&self, id: &str, owner: &str, batch: &str) -> Result<UploadSession, UploadError> {
+        let _guard = self.lock.lock().unwrap();
+        Self::check_id(batch)?;
+        let mut session = self.load_owned(id, owner)?;
+        session.batch = Some(batch.to_string());
+        self.save(&session)?;
+        Ok(session)
+    }
+
+    /// Uploads of `owner` in `batch`, oldest first
+    pub fn batch(&self, owner: &str, batch: &str) -> Result<Vec<UploadSession>, UploadError> {
+        Ok(self
+            .list(owner)?
+            .into_iter()
+            .filter(|session| session.batch.as_deref() == Some(batch))
+            .collect())
+    }
+
+    /// Unfinished uploads of `owner`, oldest first
+    pub fn list(&self, owner: &str) -> Result<Vec<UploadSession>, UploadError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut sessions: Vec<UploadSession> = self
+            .all()?
+            .into_iter()
+            .filter(|session| session.owner == owner)
+            .collect();
+        sessions.sort_by_key(|session| session.created);
+        Ok(sessions)
+    }
+
+    /// Write a chunk starting at `offset`, which must be where the upload currently stands
+    pub fn append(
+        &self,
+        id: &str,
+        owner: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<UploadSession, UploadError> {
+        self.extend(id, owner, offset, data.len() as u64, |file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(data)
+        })
+    }
+
+    /// Add `length` zero bytes at `offset` without writing them, which leaves a hole in the
+    /// partial file on file systems supporting sparse files
+    pub fn append_zeroes(
+        &self,
+        id: &str,
+        owner: &str,
+        offset: u64,
+        length: u64,
+    ) -> Result<UploadSession, UploadError> {
+        self.extend(id, owner, offset, length, |file| file.set_len(offset + length))
+    }
+
+    fn extend(
+        &self,
+        id: &str,
+        owner: &str,
+        offset: u64,
+        length: u64,
+        write: impl FnOnce(&mut std::fs::File) -> std::io::Result<()>,
+    ) -> Result<UploadSession, UploadError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut session = self.load_owned(id, owner)?;
+
+        if offset != session.offset {
+            return Err(UploadError::OffsetMismatch {
+                given: offset,
+                expected: session.offset,
+            });
+        }
+        if offset + length > session.size {
+            return Err(UploadError::TooLarge);
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(self.part_path(id))?;
+        // Drop anything past the recorded offset left behind by an interrupted write
+        file.set_len(offset)?;
+        write(&mut file)?;
+        file.sync_data()?;
+
+        session.offset += length;
+        session.updated = Utc::now();
+        self.save(&session)?;
+        Ok(session)
+    }
+
+    /// Where a file is put aside while a batch replacing it is committed
+    pub fn replaced_path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{}.replaced", id))
+    }
+
+    /// File holding the bytes received so far
+    pub fn part_path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{}.part", id))
+    }
+
+    /// Forget a session, deleting its partial data if still there
+    pub fn remove(&self, id: &str) -> Result<(), UploadError> {
+        let _guard = self.lock.lock().unwrap();
+        Self::check_id(id)?;
+        self.delete_files(id)
+    }
+
+    /// Abandon the session `id` of `owner`, deleting the data received so far
+    pub fn cancel(&self, id: &str, owner: &str) -> Result<UploadSession, UploadError> {
+        let _guard = self.lock.lock().unwrap();
+        let session = self.load_owned(id, owner)?;
+        self.delete_files(id)?;
+        Ok(session)
+    }
+
+    fn delete_files(&self, id: &str) -> Result<(), UploadError> {
+        for path in [self.session_path(id), self.part_path(id)] {
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove sessions that have not received data for `max_age`, returning how many were dropped
+    pub fn expire(&self, max_age: Duration, now: DateTime<Utc>) -> Result<usize, UploadError> {
+        let stale: Vec<String> = {
+            let _guard = self.lock.lock().unwrap();
+            self.all()?
+                .into_iter()
+                .filter(|session| now - session.updated > max_age)
+                .map(|session| session.id)
+                .collect()
+        };
+
+        for id in &stale {
+            self.remove(id)?;
+        }
+        Ok(stale.len())
+    }
+
+    fn all(&self) -> Result<Vec<UploadSession>, UploadError> {
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut sessions = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path)?;
+            sessions.push(
+                serde_json::from_str(&content)
+                    .map_err(|e| UploadError::Corrupt(format!("{:?}: {}", path, e)))?,
+            );
+        }
+        Ok(sessions)
+    }
+
+    fn load_owned(&self, id: &str, owner: &str) -> Result<UploadSession, UploadError> {
+        Self::check_id(id)?;
+
+        let content = match std::fs::read_to_string(self.session_path(id)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(UploadError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        let session: UploadSession = serde_json::from_str(&content)
+            .map_err(|e| UploadError::Corrupt(format!("{}: {}", id, e)))?;
+
+        // Other clients' sessions are reported as missing so ids cannot be probed
+        if session.owner != owner {
+            return Err(UploadError::NotFound);
+        }
+        Ok(session)
+    }
+
+    fn save(&self, session: &UploadSession) -> Result<(), UploadError> {
+        let content = serde_json::to_string_pretty(session)
+            .map_err(|e| UploadError::Corrupt(e.to_string()))?;
+
+        let path = self.session_path(&session.id);
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    fn session_path(&self, id: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", id))
+    }
+
+    /// Session ids end up in file names, so only accept well-formed UUIDs
+    fn check_id(id: &str) -> Result<(), UploadError> {
+        Uuid::parse_str(id).map(|_| ()).map_err(|_| UploadError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_append_in_chunks() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = UploadStore::open(temp_dir.path());
+
+        let session = store.create("phone", "video.mp4", 10, None, None, FileAttributes::default()).unwrap();
+        store.append(&session.id, "phone", 0, b"hello").unwrap();
+
+        // A retried chunk that already arrived is rejected with the real position
+        match store.append(&session.id, "phone", 0, b"hello") {
+            Err(UploadError::OffsetMismatch { expected, .. }) => assert_eq!(expected, 5),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(
+            store.append(&session.id, "phone", 5, b"too much data"),
+            Err(UploadError::TooLarge)
+        ));
+
+        let session = store.append(&session.id, "phone", 5, b"world").unwrap();
+        assert!(session.is_complete());
+        assert_eq!(std::fs::read(store.part_path(&session.id)).unwrap(), b"helloworld");
+    }
+
+    #[test]
+    fn test_append_zeroes_leaves_a_hole() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = UploadStore::open(temp_dir.path());
+
+        let session = store.create("laptop", "disk.img", 12, None, None, FileAttributes::default()).unwrap();
+        store.append(&session.id, "laptop", 0, b"boot").unwrap();
+        store.append_zeroes(&session.id, "laptop", 4, 6).unwrap();
+        assert!(matches!(
+            store.append_zeroes(&session.id, "laptop", 10, 3),
+            Err(UploadError::TooLarge)
+        ));
+
+        let session = store.append(&session.id, "laptop", 10, b"ok").unwrap();
+        assert!(session.is_complete());
+        assert_eq!(std::fs::read(store.part_path(&session.id)).unwrap(), b"boot\0\0\0\0\0\0ok");
+    }
+
+    #[test]
+    fn test_sessions_are_private_to_their_owner() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = UploadStore::open(temp_dir.path());
+
+        let session = store.create("phone", "a.jpg", 3, None, None, FileAttributes::default()).unwrap();
+        store.create("laptop", "b.jpg", 3, None, None, FileAttributes::default()).unwrap();
+
+        assert!(matches!(store.get(&session.id, "laptop"), Err(UploadError::NotFound)));
+        assert!(matches!(
+            store.append(&session.id, "laptop", 0, b"abc"),
+            Err(UploadError::NotFound)
+        ));
+        assert!(matches!(store.get("../../etc/passwd", "phone"), Err(UploadError::NotFound)));
+
+        let listed = store.list("phone").unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "a.jpg");
+    }
+
+    #[test]
+    fn test_expire_and_remove() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = UploadStore::open(temp_dir.path());
+
+        let session = store.create("phone", "a.jpg", 3, None, None, FileAttributes::default()).unwrap();
+        assert_eq!(store.expire(Duration::days(1), Utc::now()).unwrap(), 0);
+        assert_eq!(store.expire(Duration::days(1), Utc::now() + Duration::days(2)).unwrap(), 1);
+
+        assert!(matches!(store.get(&session.id, "phone"), Err(UploadError::NotFound)));
+        assert!(!store.part_path(&session.id).exists());
+        store.remove(&session.id).unwrap();
+    }
+
+    #[test]
+    fn test_batches() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = UploadStore::open(temp_dir.path());
+        let batch = Uuid::new_v4().to_string();
+
+        let first = store.create("phone", "a.jpg", 3, None, None, FileAttributes::default()).unwrap();
+        store.create("phone", "b.jpg", 3, None, None, FileAttributes::default()).unwrap();
+        let second = store.create("phone", "c.jpg", 3, None, None, FileAttributes::default()).unwrap();
+        store.join_batch(&first.id, "phone", &batch).unwrap();
+        store.join_batch(&second.id, "phone", &batch).unwrap();
+        assert!(matches!(store.join_batch(&first.id, "phone", "album"), Err(UploadError::NotFound)));
+
+        let names: Vec<String> = store.batch("phone", &batch).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["a.jpg", "c.jpg"]);
+        assert!(store.batch("laptop", &batch).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = UploadStore::open(temp_dir.path());
+
+        let session = store.create("phone", "a.jpg", 3, None, None, FileAttributes::default()).unwrap();
+        store.append(&session.id, "phone", 0, b"ab").unwrap();
+        assert!(matches!(store.cancel(&session.id, "laptop"), Err(UploadError::NotFound)));
+
+        store.cancel(&session.id, "phone").unwrap();
+        assert!(!store.part_path(&session.id).exists());
+        assert!(store.list("phone").unwrap().is_empty());
+        assert!(matches!(store.cancel(&session.id, "phone"), Err(UploadError::NotFound)));
+    }
+}
@@ -0,0 +1,182 @@
+//! Crash reports. A panic writes the version, platform, backtrace and the last lines logged
+//! to a file in the data directory and prints its path, so a crash can be reported with more
+//! than "it quit".
+
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Log lines kept for the report
+const RECENT_LINES: usize = 200;
+
+/// The last lines logged, fed by a `fmt` layer writing to it
+#[derive(Clone, Default)]
+pub struct RecentLog {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl RecentLog {
+    pub fn lines(&self) -> Vec<String> {
+        // The panic may have happened while logging; a busy buffer is left out of the report
+        match self.lines.try_lock() {
+            Ok(lines) => lines.iter().cloned().collect(),
+            Err(std::sync::TryLockError::Poisoned(lines)) => lines.into_inner().iter().cloned().collect(),
+            Err(std::sync::TryLockError::WouldBlock) => Vec::new(),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for RecentLog {
+    type Writer = RecentLogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RecentLogWriter(self.clone())
+    }
+}
+
+/// Writer for one log event
+pub struct RecentLogWriter(RecentLog);
+
+impl Write for RecentLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut lines = self.0.lines.lock().unwrap_or_else(|e| e.into_inner());
+        for line in String::from_utf8_lossy(buf).lines() {
+            if lines.len() == RECENT_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Directory crash reports are written to
+pub fn crash_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .map(|dir| dir.join("rustdrop").join("crashes"))
+        .unwrap_or_else(|| std::env::temp_dir().join("rustdrop-crashes"))
+}
+
+/// Write a crash report on panic, after the default message
+pub fn install_panic_hook(recent: RecentLog) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+
+        let report = crash_report(info, &Backtrace::force_capture(), &recent.lines());
+        match write_report(&crash_dir(), &report) {
+            Ok(path) => eprintln!(
+                "💥 RustDrop crashed. A report was written to {}; please attach it when reporting the problem.",
+                path.display()
+            ),
+            Err(e) => eprintln!("💥 RustDrop crashed and the crash report could not be written: {}", e),
+        }
+    }));
+}
+
+fn crash_report(info: &PanicHookInfo<'_>, backtrace: &Backtrace, recent: &[String]) -> String {
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "(no message)".to_string());
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let thread = std::thread::current().name().unwrap_or("<unnamed>").to_string();
+
+    format_report(&message, &location, &thread, &backtrace.to_string(), recent)
+}
+
+fn format_report(message: &str, location: &str, thread: &str, backtrace: &str, recent: &[String]) -> String {
+    let mut report = format!(
+        "RustDrop crash report\n\
+         Time: {}\n\
+         Version: {}\n\
+         OS: {} ({}, {})\n\
+         Thread: {}\n\
+         Panic: {}\n\
+         Location: {}\n\n\
+         Backtrace:\n{}\n\n\
+         Recent log:\n",
+        Utc::now().to_rfc3339(),
+        crate::VERSION,
+        std::env::consts::OS,
+        std::env::consts::FAMILY,
+        std::env::consts::ARCH,
+        thread,
+        message,
+        location,
+        backtrace.trim_end(),
+    );
+    if recent.is_empty() {
+        report.push_str("(empty)\n");
+    }
+    for line in recent {
+        report.push_str(line);
+        report.push('\n');
+    }
+    report
+}
+
+fn write_report(dir: &Path, report: &str) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("crash-{}.txt", Utc::now().format("%Y%m%dT%H%M%S%.3fZ")));
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_recent_log_keeps_last_lines() {
+        let recent = RecentLog::default();
+        let layer = tracing_subscriber::fmt::layer().with_ansi(false).with_writer(recent.clone());
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..RECENT_LINES + 5 {
+                tracing::info!("line {}", i);
+            }
+        });
+
+        let lines = recent.lines();
+        assert_eq!(lines.len(), RECENT_LINES);
+        assert!(lines[0].ends_with("line 5"));
+        assert!(lines[RECENT_LINES - 1].ends_with(&format!("line {}", RECENT_LINES + 4)));
+    }
+
+    #[test]
+    fn test_write_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let report = format_report(
+            "index out of bounds",
+            "src/main.rs:1:1",
+            "main",
+            "0: rustdrop::main",
+            &["INFO started".to_string()],
+        );
+
+        let path = write_report(&temp_dir.path().join("crashes"), &report).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains(&format!("Version: {}", crate::VERSION)));
+        assert!(written.contains("Panic: index out of bounds"));
+        assert!(written.contains("0: rustdrop::main"));
+        assert!(written.ends_with("INFO started\n"));
+    }
+}
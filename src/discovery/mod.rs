@@ -1,20 +1,31 @@
 use anyhow::Result;
-use mdns_sd::{ServiceDaemon, ServiceInfo, ServiceEvent};
+use mdns_sd::{DaemonStatus, ServiceDaemon, ServiceInfo, ServiceEvent};
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::str::FromStr;
 use std::time::Duration;
-use tracing::info;
+use tracing::{info, instrument, warn};
 use tokio;
 
 use crate::core::models::DeviceInfo;
 
 const SERVICE_TYPE: &str = "_rustdrop._tcp.local.";
 
+/// How often the registered service is looked up to make sure it still answers
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a health check waits for the service to answer
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Checks in a row the service must miss before it counts as gone, so a single lost packet does
+/// not cause a registration
+const HEALTH_CHECK_MISSES: u32 = 2;
+
 pub struct ServiceDiscovery {
     device_info: DeviceInfo,
     daemon: Option<ServiceDaemon>,
     service_fullname: Option<String>,
+    missed_checks: u32,
 }
 
 impl ServiceDiscovery {
@@ -23,9 +34,11 @@ impl ServiceDiscovery {
             device_info,
             daemon: None,
             service_fullname: None,
+            missed_checks: 0,
         }
     }
     
+    #[instrument(name = "register", skip(self))]
     pub async fn register(&mut self) -> Result<&mut Self> {
         // Create a new mDNS daemon
         let daemon = ServiceDaemon::new()?;
@@ -35,13 +48,17 @@ impl ServiceDiscovery {
         properties.insert("name".to_string(), self.device_info.name.clone());
         properties.insert("os".to_string(), self.device_info.os.clone());
         properties.insert("id".to_string(), self.device_info.id.clone());
+        if self.device_info.tls {
+            properties.insert("tls".to_string(), "1".to_string());
+        }
         
         // Create service info
         let host_ipv4 = IpAddr::from_str(&self.device_info.ip)?;
         let service_info = ServiceInfo::new(
             SERVICE_TYPE,
             &self.device_info.name,
-            &format!("rustdrop-{}", self.device_info.id),
+            // Instances on the same host share the device id, the port keeps their host names apart
+            &format!("rustdrop-{}-{}", self.device_info.id, self.device_info.port),
             host_ipv4,
             self.device_info.port,
             Some(properties),
@@ -77,10 +94,69 @@ impl ServiceDiscovery {
         // Clear references to ensure proper cleanup
         self.daemon = None;
         self.service_fullname = None;
+        self.missed_checks = 0;
+        
+        Ok(())
+    }
+    
+    /// Look the service up like other devices do and register it again when it stopped
+    /// answering, which some mDNS daemons do after network changes. Tells whether it had to.
+    pub async fn ensure_registered(&mut self) -> Result<bool> {
+        let (Some(daemon), Some(fullname)) = (&self.daemon, &self.service_fullname) else {
+            return Ok(false);
+        };
+        let (daemon, fullname) = (daemon.clone(), fullname.clone());
+        if tokio::task::spawn_blocking(move || Self::answers(&daemon, &fullname)).await?? {
+            self.missed_checks = 0;
+            return Ok(false);
+        }
+        self.missed_checks += 1;
+        if self.missed_checks < HEALTH_CHECK_MISSES {
+            return Ok(false);
+        }
         
+        warn!(
+            "mDNS service {} silently disappeared, registering it again",
+            self.service_fullname.as_deref().unwrap_or(SERVICE_TYPE)
+        );
+        self.unregister().await?;
+        self.register().await?;
+        Ok(true)
+    }
+    
+    /// Whether `registered` still runs and the service it announces as `fullname` answers a
+    /// query from a daemon of its own
+    fn answers(registered: &ServiceDaemon, fullname: &str) -> Result<bool> {
+        let running = registered.status()?.recv_timeout(HEALTH_CHECK_TIMEOUT);
+        if !matches!(running, Ok(DaemonStatus::Running)) {
+            return Ok(false);
+        }
+        let daemon = ServiceDaemon::new()?;
+        let receiver = daemon.browse(SERVICE_TYPE)?;
+        let start_time = std::time::Instant::now();
+        let mut found = false;
+        while !found && start_time.elapsed() < HEALTH_CHECK_TIMEOUT {
+            if let Ok(ServiceEvent::ServiceResolved(info)) = receiver.recv_timeout(HEALTH_CHECK_TIMEOUT) {
+                found = info.get_fullname() == fullname;
+            }
+        }
+        let _ = daemon.shutdown();
+        Ok(found)
+    }
+    
+    /// Announce the device again, e.g. at the address it moved to. While it is not announced,
+    /// the new details are kept for the next `register`.
+    pub async fn update(&mut self, device_info: DeviceInfo) -> Result<()> {
+        let registered = self.daemon.is_some();
+        self.unregister().await?;
+        self.device_info = device_info;
+        if registered {
+            self.register().await?;
+        }
         Ok(())
     }
     
+    #[instrument(name = "discover")]
     pub async fn discover() -> Result<Vec<DeviceInfo>> {
         let daemon = ServiceDaemon::new()?;
         let receiver = daemon.browse(SERVICE_TYPE)?;
@@ -92,14 +168,9 @@ impl ServiceDiscovery {
         let start_time = std::time::Instant::now();
         
         while start_time.elapsed() < timeout {
-            if let Ok(event) = receiver.recv_timeout(timeout) {
-                match event {
-                    ServiceEvent::ServiceResolved(info) => {
-                        if let Some(device) = Self::service_to_device(&info) {
-                            devices.push(device);
-                        }
-                    },
-                    _ => {}
+            if let Ok(ServiceEvent::ServiceResolved(info)) = receiver.recv_timeout(timeout) {
+                if let Some(device) = Self::service_to_device(&info) {
+                    devices.push(device);
                 }
             }
         }
@@ -124,6 +195,7 @@ impl ServiceDiscovery {
         let mut id = None;
         let mut name = None;
         let mut os = None;
+        let mut tls = false;
         
         for property in properties.iter() {
             let prop_str = property.to_string();
@@ -133,6 +205,8 @@ impl ServiceDiscovery {
                 name = Some(value);
             } else if let Some(value) = extract_value(&prop_str, "os") {
                 os = Some(value);
+            } else if let Some(value) = extract_value(&prop_str, "tls") {
+                tls = value == "1";
             }
         }
         
@@ -156,6 +230,7 @@ impl ServiceDiscovery {
             ip,
             port,
             os,
+            tls,
         })
     }
 }
@@ -0,0 +1,478 @@
+//! Just enough MIME (RFC 2045-2047, 2231) to find the attachments of an email
+
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+
+/// Mail clients do not always pad the last line of base64
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Nesting of multipart bodies followed before giving up
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// File name given by the sender, if any
+    pub name: Option<String>,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Message {
+    /// Display name of the `From` header, or its address when there is none
+    pub sender: Option<String>,
+    pub subject: Option<String>,
+    pub attachments: Vec<Attachment>,
+}
+
+impl Message {
+    pub fn parse(raw: &[u8]) -> Self {
+        let (head, body) = split_head(raw);
+        let headers = Headers::parse(head);
+
+        let mut attachments = Vec::new();
+        collect_attachments(&headers, body, 0, &mut attachments);
+
+        Self {
+            sender: headers.get("from").and_then(mailbox_name),
+            subject: headers.get("subject").map(decode_words),
+            attachments,
+        }
+    }
+}
+
+struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    fn parse(head: &[u8]) -> Self {
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in String::from_utf8_lossy(head).lines() {
+            if line.starts_with([' ', '\t']) {
+                // Folded continuation of the header before
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+        Self(headers)
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// A structured header like `Content-Type: text/plain; charset=utf-8`
+struct Field {
+    value: String,
+    params: Vec<(String, String)>,
+}
+
+impl Field {
+    fn parse(raw: &str) -> Self {
+        let mut segments = split_unquoted(raw, ';').into_iter();
+        let value = segments.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let params = segments
+            .filter_map(|segment| {
+                let (key, value) = segment.split_once('=')?;
+                Some((key.trim().to_ascii_lowercase(), unquote(value.trim())))
+            })
+            .collect();
+        Self { value, params }
+    }
+
+    fn raw_param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// A parameter in any of its encodings: RFC 2231 (`name*`, `name*0*`, ...), or plain
+    /// with RFC 2047 encoded words as many clients send file names
+    fn param(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.raw_param(&format!("{}*", name)) {
+            return Some(decode_extended(&[(value, true)]));
+        }
+
+        let mut sections = Vec::new();
+        for index in 0.. {
+            if let Some(value) = self.raw_param(&format!("{}*{}*", name, index)) {
+                sections.push((value, true));
+            } else if let Some(value) = self.raw_param(&format!("{}*{}", name, index)) {
+                sections.push((value, false));
+            } else {
+                break;
+            }
+        }
+        if !sections.is_empty() {
+            return Some(decode_extended(&sections));
+        }
+
+        self.raw_param(name).map(decode_words)
+    }
+}
+
+fn collect_attachments(headers: &Headers, body: &[u8], depth: usize, attachments: &mut Vec<Attachment>) {
+    let content_type = Field::parse(headers.get("content-type").unwrap_or("text/plain"));
+
+    if content_type.value.starts_with("multipart/") {
+        let Some(boundary) = content_type.raw_param("boundary") else {
+            return;
+        };
+        if depth >= MAX_DEPTH {
+            return;
+        }
+        for part in split_multipart(body, boundary) {
+            let (head, body) = split_head(part);
+            collect_attachments(&Headers::parse(head), body, depth + 1, attachments);
+        }
+        return;
+    }
+
+    // Anything named is a file, including photos that phones attach "inline"
+    let disposition = headers.get("content-disposition").map(Field::parse);
+    let name = disposition
+        .as_ref()
+        .and_then(|disposition| disposition.param("filename"))
+        .or_else(|| content_type.param("name"));
+    let is_attachment = disposition.is_some_and(|disposition| disposition.value == "attachment");
+    if name.is_none() && !is_attachment {
+        return;
+    }
+
+    let encoding = headers.get("content-transfer-encoding").unwrap_or("7bit").trim().to_ascii_lowercase();
+    let data = match encoding.as_str() {
+        "base64" => {
+            let text: Vec<u8> = body.iter().copied().filter(|byte| !byte.is_ascii_whitespace()).collect();
+            match BASE64.decode(text) {
+                Ok(data) => data,
+                Err(_) => return,
+            }
+        }
+        "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.to_vec(),
+    };
+
+    attachments.push(Attachment {
+        name: name.filter(|name| !name.trim().is_empty()),
+        content_type: content_type.value,
+        data,
+    });
+}
+
+/// Headers and body of a message or part, split at the first empty line
+fn split_head(data: &[u8]) -> (&[u8], &[u8]) {
+    let mut position = 0;
+    while position < data.len() {
+        let end = line_end(data, position);
+        if trim_newline(&data[position..end]).is_empty() {
+            return (&data[..position], &data[end..]);
+        }
+        position = end;
+    }
+    (data, &[])
+}
+
+/// The parts of a multipart body, without the preamble and epilogue
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut position = 0;
+
+    while position < body.len() {
+        let end = line_end(body, position);
+        let line = trim_newline(&body[position..end]);
+        if let Some(rest) = line.strip_prefix(delimiter.as_bytes()) {
+            let rest = rest.trim_ascii_end();
+            if rest.is_empty() || rest == b"--" {
+                // The line break before a delimiter belongs to the delimiter
+                if let Some(start) = start {
+                    parts.push(trim_newline(&body[start..position]));
+                }
+                if rest == b"--" {
+                    return parts;
+                }
+                start = Some(end);
+            }
+        }
+        position = end;
+    }
+
+    // A message cut short still yields the parts it has
+    if let Some(start) = start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+/// Position just after the line starting at `start`
+fn line_end(data: &[u8], start: usize) -> usize {
+    data[start..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(data.len(), |index| start + index + 1)
+}
+
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Split on `separator` where it is not inside double quotes
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                segments.push(&text[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    segments.push(&text[start..]);
+    segments
+}
+
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"').map(|inner| inner.strip_suffix('"').unwrap_or(inner)) else {
+        return value.to_string();
+    };
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// RFC 2231 parameter sections: `charset'language'percent%20encoded`, where only the first
+/// section names the charset and plain sections are taken as they are
+fn decode_extended(sections: &[(&str, bool)]) -> String {
+    let mut charset = "utf-8";
+    let mut bytes = Vec::new();
+    for (index, (value, encoded)) in sections.iter().enumerate() {
+        if !encoded {
+            bytes.extend_from_slice(value.as_bytes());
+            continue;
+        }
+        let mut value = *value;
+        if index == 0 {
+            if let Some((prefix, rest)) = value.split_once('\'').and_then(|(set, rest)| {
+                rest.split_once('\'').map(|(_, rest)| (set, rest))
+            }) {
+                charset = prefix;
+                value = rest;
+            }
+        }
+        bytes.extend(percent_encoding::percent_decode_str(value));
+    }
+    decode_charset(charset, &bytes)
+}
+
+/// Replace RFC 2047 encoded words (`=?utf-8?B?...?=`) with their text
+fn decode_words(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let Some(word) = encoded_word(&rest[start..]) else {
+            decoded.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            after_word = false;
+            continue;
+        };
+        // Whitespace between two encoded words is not part of the text
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            decoded.push_str(between);
+        }
+        decoded.push_str(&word.0);
+        rest = &rest[start + word.1..];
+        after_word = true;
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The text of the encoded word `text` starts with, and the length of the word
+fn encoded_word(text: &str) -> Option<(String, usize)> {
+    let inner = text.strip_prefix("=?")?;
+    let mut fields = inner.splitn(3, '?');
+    let charset = fields.next()?;
+    let encoding = fields.next()?;
+    let remainder = fields.next()?;
+    let end = remainder.find("?=")?;
+    let payload = &remainder[..end];
+    if payload.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let bytes = match encoding.to_ascii_uppercase().as_str() {
+        "B" => BASE64.decode(payload).ok()?,
+        "Q" => decode_quoted_printable(payload.as_bytes(), true),
+        _ => return None,
+    };
+    let length = "=?".len() + charset.len() + 1 + encoding.len() + 1 + end + "?=".len();
+    // Charsets may carry a language, as in `utf-8*en`
+    let charset = charset.split('*').next().unwrap_or(charset);
+    Some((decode_charset(charset, &bytes), length))
+}
+
+/// Quoted-printable data; in encoded words, `_` stands for a space
+fn decode_quoted_printable(data: &[u8], word: bool) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut index = 0;
+    while index < data.len() {
+        match data[index] {
+            b'=' => {
+                let rest = &data[index + 1..];
+                if let Some(after) = rest.strip_prefix(b"\r\n").or_else(|| rest.strip_prefix(b"\n")) {
+                    // Soft line break
+                    index = data.len() - after.len();
+                    continue;
+                }
+                let hex = rest.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        index += 3;
+                    }
+                    None => {
+                        decoded.push(b'=');
+                        index += 1;
+                    }
+                }
+            }
+            b'_' if word => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    decoded
+}
+
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    match charset.to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "latin1" | "windows-1252" => bytes.iter().map(|&byte| char::from(byte)).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// `"Name" <address>` gives the name, a bare address the address
+fn mailbox_name(from: &str) -> Option<String> {
+    let from = decode_words(from);
+    let name = match from.split_once('<') {
+        Some((name, address)) => {
+            let name = unquote(name.trim());
+            if name.is_empty() {
+                address.trim_end_matches('>').trim().to_string()
+            } else {
+                name
+            }
+        }
+        None => from.trim().to_string(),
+    };
+    Some(name).filter(|name| !name.is_empty())
+}
+
+/// The address of a `<address>` path in an SMTP command, lowercased for comparison
+pub fn path_address(path: &str) -> String {
+    let path = path.trim();
+    let address = match (path.find('<'), path.find('>')) {
+        (Some(start), Some(end)) if start < end => &path[start + 1..end],
+        _ => path.split_whitespace().next().unwrap_or_default(),
+    };
+    address.to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart_attachments() {
+        let message = b"From: \"Ann Example\" <ann@example.com>\r\n\
+Subject: =?utf-8?Q?Caf=C3=A9?= =?utf-8?B?IHBob3Rvcw==?=\r\n\
+Content-Type: multipart/mixed;\r\n\tboundary=\"outer\"\r\n\
+\r\n\
+This is a multi-part message.\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+See attached.\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: image/jpeg; name=\"photo.jpg\"\r\n\
+Content-Disposition: inline\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+aGVsbG8g\r\n\
+d29ybGQ\r\n\
+--outer\r\n\
+Content-Type: text/plain\r\n\
+Content-Disposition: attachment; filename*=utf-8''r%C3%A9sum%C3%A9.txt\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+caf=C3=A9 =\r\n\
+au lait\r\n\
+--outer--\r\n\
+epilogue\r\n";
+
+        let message = Message::parse(message);
+        assert_eq!(message.sender.as_deref(), Some("Ann Example"));
+        assert_eq!(message.subject.as_deref(), Some("Café photos"));
+        assert_eq!(message.attachments.len(), 2);
+        assert_eq!(message.attachments[0].name.as_deref(), Some("photo.jpg"));
+        assert_eq!(message.attachments[0].content_type, "image/jpeg");
+        assert_eq!(message.attachments[0].data, b"hello world");
+        assert_eq!(message.attachments[1].name.as_deref(), Some("résumé.txt"));
+        assert_eq!(message.attachments[1].data, "café au lait".as_bytes());
+    }
+
+    #[test]
+    fn test_file_name_encodings() {
+        let field = Field::parse("attachment; filename*0*=utf-8''%E2%82%AC; filename*1=\" 10.pdf\"");
+        assert_eq!(field.param("filename").as_deref(), Some("€ 10.pdf"));
+
+        let field = Field::parse("attachment; filename=\"=?iso-8859-1?Q?gr=FC=DFe?=.txt\"");
+        assert_eq!(field.param("filename").as_deref(), Some("grüße.txt"));
+
+        let field = Field::parse("attachment; filename=\"semi;colon \\\"quoted\\\".txt\"");
+        assert_eq!(field.param("filename").as_deref(), Some("semi;colon \"quoted\".txt"));
+    }
+
+    #[test]
+    fn test_plain_message_has_no_attachments() {
+        let message = Message::parse(b"From: bob@example.com\nSubject: hi\n\nJust text\n");
+        assert_eq!(message.sender.as_deref(), Some("bob@example.com"));
+        assert!(message.attachments.is_empty());
+        assert_eq!(path_address(" <Bob@Example.com> SIZE=10"), "bob@example.com");
+        assert_eq!(path_address("<>"), "");
+    }
+}
@@ -0,0 +1,277 @@
+//! An email gateway: a minimal SMTP listener that saves the attachments of incoming mail in the
+//! share, so files can be sent from any mail client or scanner with "scan to email".
+//!
+//! The listener speaks just enough SMTP for a client or relay to hand over a message: no
+//! authentication, TLS or forwarding. Mail clients cannot enter access codes, which is why the
+//! gateway has to be enabled in the configuration, and `email.address` and
+//! `email.allowed_senders` narrow down who it takes mail from.
+
+pub mod mime;
+
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info, warn};
+
+use crate::core::config::EmailConfig;
+use crate::utils::file::{get_share_file_info, sanitize_path_component};
+use crate::utils::filename::{host_name, long_path, unused_path};
+use crate::utils::organize::upload_subdirectory;
+use crate::web::handlers::api::record_upload;
+use crate::web::session::normalize_sender;
+use crate::web::state::AppState;
+use mime::{path_address, Attachment, Message};
+
+/// Longest command line accepted; RFC 5321 allows 512 bytes, with room for extensions
+const MAX_LINE_LENGTH: usize = 4096;
+
+/// Idle time after which a session is dropped
+const SESSION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// SMTP listener saving email attachments to the share of an `AppState`
+pub struct EmailGateway {
+    listener: TcpListener,
+    state: AppState,
+}
+
+impl EmailGateway {
+    pub async fn bind(addr: SocketAddr, state: AppState) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind SMTP listener on {}", addr))?;
+        Ok(Self { listener, state })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept connections until the task is aborted
+    pub async fn run(self) {
+        info!("Email gateway listening on {:?}", self.listener.local_addr());
+
+        loop {
+            let (stream, peer) = match self.listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to accept SMTP connection: {}", e);
+                    continue;
+                }
+            };
+            let state = self.state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_session(stream, &state).await {
+                    debug!("SMTP session with {} ended: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+/// Where a session is in the SMTP exchange
+#[derive(Default)]
+struct Envelope {
+    from: Option<String>,
+    recipients: usize,
+}
+
+async fn handle_session(stream: TcpStream, state: &AppState) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let config = &state.config.email;
+    let host = state.device_info.name.clone();
+
+    if !state.schedule.is_open() {
+        // Senders queue the message and try again later
+        writer.write_all(b"421 4.3.2 Closed at this hour, try again later\r\n").await?;
+        return Ok(());
+    }
+    writer.write_all(format!("220 {} RustDrop ESMTP\r\n", host).as_bytes()).await?;
+
+    let mut envelope = Envelope::default();
+    loop {
+        let Some(line) = read_line(&mut reader).await? else {
+            return Ok(());
+        };
+        let (verb, argument) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+        let reply = match verb.to_ascii_uppercase().as_str() {
+            "EHLO" => format!(
+                "250-{} greets {}\r\n250-SIZE {}\r\n250-8BITMIME\r\n250 OK",
+                host,
+                argument.trim(),
+                config.max_message_size
+            ),
+            "HELO" => format!("250 {}", host),
+            "MAIL" => match argument.split_once(':') {
+                Some((keyword, path)) if keyword.eq_ignore_ascii_case("FROM") => {
+                    let address = path_address(path);
+                    if sender_allowed(config, &address) {
+                        envelope = Envelope { from: Some(address), recipients: 0 };
+                        "250 2.1.0 OK".to_string()
+                    } else {
+                        warn!("Refusing email from {:?}", address);
+                        "550 5.7.1 Sender not allowed".to_string()
+                    }
+                }
+                _ => "501 5.5.4 Syntax: MAIL FROM:<address>".to_string(),
+            },
+            "RCPT" => match argument.split_once(':') {
+                _ if envelope.from.is_none() => "503 5.5.1 MAIL first".to_string(),
+                Some((keyword, path)) if keyword.eq_ignore_ascii_case("TO") => {
+                    if recipient_accepted(config, &path_address(path)) {
+                        envelope.recipients += 1;
+                        "250 2.1.5 OK".to_string()
+                    } else {
+                        "550 5.1.1 No such mailbox".to_string()
+                    }
+                }
+                _ => "501 5.5.4 Syntax: RCPT TO:<address>".to_string(),
+            },
+            "DATA" if envelope.recipients == 0 => "503 5.5.1 RCPT first".to_string(),
+            "DATA" => {
+                writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").await?;
+                let reply = match read_data(&mut reader, config.max_message_size).await? {
+                    Some(data) => receive_message(state, &data).await,
+                    None => "552 5.3.4 Message too large".to_string(),
+                };
+                envelope = Envelope::default();
+                reply
+            }
+            "RSET" => {
+                envelope = Envelope::default();
+                "250 2.0.0 OK".to_string()
+            }
+            "NOOP" => "250 2.0.0 OK".to_string(),
+            "QUIT" => {
+                writer.write_all(b"221 2.0.0 Bye\r\n").await?;
+                return Ok(());
+            }
+            _ => "502 5.5.2 Command not implemented".to_string(),
+        };
+        writer.write_all(format!("{}\r\n", reply).as_bytes()).await?;
+    }
+}
+
+fn sender_allowed(config: &EmailConfig, address: &str) -> bool {
+    config.allowed_senders.is_empty()
+        || config.allowed_senders.iter().any(|allowed| allowed.eq_ignore_ascii_case(address))
+}
+
+fn recipient_accepted(config: &EmailConfig, address: &str) -> bool {
+    config.address.as_deref().is_none_or(|accepted| accepted.eq_ignore_ascii_case(address))
+}
+
+/// A command line without its line break, or None once the client hung up
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<String>> {
+    let mut line = Vec::new();
+    let mut reader = reader.take(MAX_LINE_LENGTH as u64);
+    let read = tokio::time::timeout(SESSION_TIMEOUT, reader.read_until(b'\n', &mut line))
+        .await
+        .context("Session timed out")??;
+    if read == 0 {
+        return Ok(None);
+    }
+    let line = String::from_utf8_lossy(&line);
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// The message after DATA up to the lone dot, or None when it is larger than `limit`
+async fn read_data<R: AsyncBufRead + Unpin>(reader: &mut R, limit: u64) -> Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    let mut too_large = false;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        // Bounded, so a message without line breaks cannot exhaust memory
+        let mut reader = (&mut *reader).take(limit.saturating_add(3));
+        let read = tokio::time::timeout(SESSION_TIMEOUT, reader.read_until(b'\n', &mut line))
+            .await
+            .context("Session timed out")??;
+        if read == 0 {
+            anyhow::bail!("Connection closed during DATA");
+        }
+        if line == b".\r\n" || line == b".\n" {
+            return Ok((!too_large).then_some(data));
+        }
+        if too_large {
+            continue;
+        }
+        // Lines starting with a dot had another one put in front
+        let line = line.strip_prefix(b".").unwrap_or(&line);
+        data.extend_from_slice(line);
+        if data.len() as u64 > limit {
+            too_large = true;
+            data = Vec::new();
+        }
+    }
+}
+
+/// Save the attachments of a message, returning the reply to the client
+async fn receive_message(state: &AppState, data: &[u8]) -> String {
+    let message = Message::parse(data);
+    if message.attachments.is_empty() {
+        // Bounced, so the sender learns nothing arrived
+        info!("Rejected email from {:?} without attachments", message.sender);
+        return "554 5.6.0 No attachments found".to_string();
+    }
+
+    let incoming = message.attachments.iter().map(|attachment| attachment.data.len() as u64).sum();
+    if let Err(e) = state.make_room(incoming, &[]) {
+        info!("Rejected email from {:?}: {}", message.sender, e);
+        return "452 4.3.1 Insufficient system storage".to_string();
+    }
+
+    let sender = message.sender.as_deref().and_then(normalize_sender);
+    let mut saved = 0;
+    for (index, attachment) in message.attachments.iter().enumerate() {
+        match store_attachment(state, attachment, index, sender.as_deref()).await {
+            Ok(path) => match get_share_file_info(&state.directory, &path) {
+                Ok(mut file_info) => {
+                    info!("Email attachment saved as {}", file_info.name);
+                    record_upload(state, &mut file_info, sender.clone(), None);
+                    saved += 1;
+                }
+                Err(e) => warn!("Failed to read back email attachment {:?}: {}", path, e),
+            },
+            Err(e) => error!("Failed to store email attachment: {}", e),
+        }
+    }
+    state.storage.clear();
+
+    if saved == 0 {
+        "451 4.3.0 Failed to store the attachments".to_string()
+    } else {
+        format!("250 2.0.0 Saved {} of {} attachments", saved, message.attachments.len())
+    }
+}
+
+async fn store_attachment(
+    state: &AppState,
+    attachment: &Attachment,
+    index: usize,
+    sender: Option<&str>,
+) -> std::io::Result<PathBuf> {
+    let name = match &attachment.name {
+        Some(name) => sanitize_path_component(name),
+        None => {
+            let extension = mime_guess::get_mime_extensions_str(&attachment.content_type)
+                .and_then(|extensions| extensions.first())
+                .unwrap_or(&"bin");
+            format!("attachment-{}.{}", index + 1, extension)
+        }
+    };
+
+    let directory = state.directory.join(upload_subdirectory(
+        &state.config.files,
+        sender,
+        &attachment.data,
+        chrono::Utc::now(),
+    ));
+    tokio::fs::create_dir_all(&directory).await?;
+    let path = long_path(unused_path(directory.join(host_name(&name))));
+    tokio::fs::write(&path, &attachment.data).await?;
+    Ok(path)
+}
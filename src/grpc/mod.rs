@@ -0,0 +1,181 @@
+//! gRPC control API for native companion apps, served on the web server port next to the REST
+//! API and guarded by the same access codes.
+//!
+//! The schema is `proto/rustdrop/v1/rustdrop.proto`. Its messages and the service glue are
+//! written out by hand in [`proto`] and here rather than generated, so building needs no
+//! `protoc`; they must be kept in step with the `.proto` file.
+
+pub mod proto;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Router,
+};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{empty_body, http, Body, BoxFuture, Context, Future, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, ServerStreamingService, UnaryService};
+use tonic::Status;
+
+use crate::web::handlers::api::{list_files, ListFilesQuery};
+use crate::web::handlers::uploads::list_uploads;
+use crate::web::session::ClientId;
+use crate::web::state::AppState;
+use proto::*;
+
+pub const SERVICE_NAME: &str = "rustdrop.v1.RustDrop";
+
+/// Paths of the service's methods start with this
+const PATH_PREFIX: &str = "/rustdrop.v1.RustDrop/";
+
+/// Routes serving the control API, to be merged into the web server's router
+pub fn router(state: AppState) -> Router {
+    Router::new().route_service(&format!("{}*method", PATH_PREFIX), ControlServer { state })
+}
+
+/// The `rustdrop.v1.RustDrop` service
+#[derive(Clone)]
+pub struct ControlServer {
+    state: AppState,
+}
+
+impl NamedService for ControlServer {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<B> Service<http::Request<B>> for ControlServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let state = self.state.clone();
+        match request.uri().path().strip_prefix(PATH_PREFIX) {
+            Some("GetDevice") => unary(request, move |_: tonic::Request<GetDeviceRequest>| async move {
+                Ok(Device::from(&state.current_device_info()))
+            }),
+            Some("ListFiles") => unary(request, move |request: tonic::Request<ListFilesRequest>| async move {
+                let client = request.extensions().get::<ClientId>().cloned();
+                let request = request.into_inner();
+                let query = ListFilesQuery {
+                    tag: request.tag,
+                    hidden: request.hidden,
+                    ..ListFilesQuery::default()
+                };
+                let files = list_files(State(state), client, Query(query))
+                    .await
+                    .map_err(status_of)?
+                    .0;
+                Ok(ListFilesResponse {
+                    files: files.into_iter().map(File::from).collect(),
+                })
+            }),
+            Some("ListTransfers") => unary(request, move |request: tonic::Request<ListTransfersRequest>| async move {
+                let client = request
+                    .extensions()
+                    .get::<ClientId>()
+                    .cloned()
+                    .ok_or_else(|| Status::unauthenticated("no client id"))?;
+                let relays = state.relays.waiting();
+                let uploads = list_uploads(State(state), client)
+                    .await
+                    .map_err(status_of)?
+                    .0;
+                Ok(ListTransfersResponse {
+                    uploads: uploads.into_iter().map(Upload::from).collect(),
+                    relays: relays.into_iter().map(Relay::from).collect(),
+                })
+            }),
+            Some("WatchEvents") => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.server_streaming(WatchEvents(state), request).await)
+            }),
+            _ => Box::pin(async {
+                let mut response = http::Response::new(empty_body());
+                let headers = response.headers_mut();
+                headers.insert(Status::GRPC_STATUS, (tonic::Code::Unimplemented as i32).into());
+                headers.insert(http::header::CONTENT_TYPE, tonic::metadata::GRPC_CONTENT_TYPE);
+                Ok(response)
+            }),
+        }
+    }
+}
+
+/// Answer a unary call with `handler`
+fn unary<B, Req, Res, F, Fut>(request: http::Request<B>, handler: F) -> BoxFuture<http::Response<BoxBody>, Infallible>
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+    Req: prost::Message + Default + Send + 'static,
+    Res: prost::Message + Send + 'static,
+    F: FnOnce(tonic::Request<Req>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Res, Status>> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = Grpc::new(ProstCodec::default());
+        Ok(grpc.unary(Unary(Some(handler)), request).await)
+    })
+}
+
+struct Unary<F>(Option<F>);
+
+impl<Req, Res, F, Fut> UnaryService<Req> for Unary<F>
+where
+    F: FnOnce(tonic::Request<Req>) -> Fut,
+    Fut: Future<Output = Result<Res, Status>> + Send + 'static,
+{
+    type Response = Res;
+    type Future = BoxFuture<tonic::Response<Res>, Status>;
+
+    fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+        let handler = self.0.take().expect("a unary call is answered once");
+        let response = handler(request);
+        Box::pin(async move { response.await.map(tonic::Response::new) })
+    }
+}
+
+struct WatchEvents(AppState);
+
+impl ServerStreamingService<WatchEventsRequest> for WatchEvents {
+    type Response = Event;
+    type ResponseStream = BoxStream<'static, Result<Event, Status>>;
+    type Future = BoxFuture<tonic::Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, _request: tonic::Request<WatchEventsRequest>) -> Self::Future {
+        let receiver = self.0.events.subscribe();
+        let events = stream::unfold(receiver, |mut receiver| async move {
+            let event = match receiver.recv().await {
+                Ok(event) => Event::from(event),
+                Err(RecvError::Lagged(missed)) => Event {
+                    kind: Some(event::Kind::Lagged(Lagged { missed })),
+                },
+                Err(RecvError::Closed) => return None,
+            };
+            Some((Ok(event), receiver))
+        });
+        Box::pin(async move { Ok(tonic::Response::new(events.boxed())) })
+    }
+}
+
+/// The gRPC status closest to a REST handler's failure
+fn status_of(status: StatusCode) -> Status {
+    let message = status.canonical_reason().unwrap_or("request failed");
+    match status {
+        StatusCode::BAD_REQUEST => Status::invalid_argument(message),
+        StatusCode::NOT_FOUND => Status::not_found(message),
+        StatusCode::FORBIDDEN => Status::permission_denied(message),
+        _ => Status::internal(message),
+    }
+}
@@ -0,0 +1,342 @@
+//! Messages of `proto/rustdrop/v1/rustdrop.proto`, and how the server's types map onto them
+
+use chrono::{DateTime, Utc};
+
+use crate::core::events::ServerEvent;
+use crate::core::models::{DeviceInfo, FileInfo};
+use crate::core::relay::RelayOffer;
+use crate::core::storage;
+use crate::core::uploads::UploadSession;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetDeviceRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Device {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub ip: String,
+    #[prost(uint32, tag = "4")]
+    pub port: u32,
+    #[prost(string, tag = "5")]
+    pub os: String,
+    #[prost(string, tag = "6")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListFilesRequest {
+    #[prost(string, optional, tag = "1")]
+    pub tag: Option<String>,
+    #[prost(bool, optional, tag = "2")]
+    pub hidden: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct File {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+    #[prost(int64, tag = "4")]
+    pub modified_unix_ms: i64,
+    #[prost(string, tag = "5")]
+    pub mime_type: String,
+    #[prost(string, repeated, tag = "6")]
+    pub tags: Vec<String>,
+    #[prost(bool, tag = "7")]
+    pub pinned: bool,
+    #[prost(string, optional, tag = "8")]
+    pub sender: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListFilesResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub files: Vec<File>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListTransfersRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Upload {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+    #[prost(uint64, tag = "4")]
+    pub offset: u64,
+    #[prost(int64, tag = "5")]
+    pub updated_unix_ms: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Relay {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+    #[prost(string, optional, tag = "4")]
+    pub sender: Option<String>,
+    #[prost(int64, tag = "5")]
+    pub created_unix_ms: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListTransfersResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub uploads: Vec<Upload>,
+    #[prost(message, repeated, tag = "2")]
+    pub relays: Vec<Relay>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WatchEventsRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Event {
+    #[prost(oneof = "event::Kind", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9")]
+    pub kind: Option<event::Kind>,
+}
+
+pub mod event {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Kind {
+        #[prost(message, tag = "1")]
+        Activity(super::Activity),
+        #[prost(message, tag = "2")]
+        CollectRequested(super::CollectRequested),
+        #[prost(message, tag = "3")]
+        RelayOffered(super::Relay),
+        #[prost(message, tag = "4")]
+        StorageProblem(super::StorageProblem),
+        #[prost(message, tag = "5")]
+        ClientRevoked(super::ClientRevoked),
+        #[prost(message, tag = "6")]
+        Lagged(super::Lagged),
+        #[prost(message, tag = "7")]
+        TransfersPaused(super::TransfersPaused),
+        #[prost(message, tag = "8")]
+        AddressChanged(super::AddressChanged),
+        #[prost(message, tag = "9")]
+        TransferFailed(super::TransferFailed),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Activity {
+    #[prost(string, tag = "1")]
+    pub action: String,
+    #[prost(string, tag = "2")]
+    pub file: String,
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+    #[prost(string, optional, tag = "4")]
+    pub sender: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub previous_name: Option<String>,
+    #[prost(int64, tag = "6")]
+    pub time_unix_ms: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CollectRequested {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub requester: String,
+    #[prost(string, tag = "3")]
+    pub pattern: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StorageProblem {
+    #[prost(string, tag = "1")]
+    pub problem: String,
+    #[prost(string, tag = "2")]
+    pub detail: String,
+    #[prost(int64, tag = "3")]
+    pub since_unix_ms: i64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ClientRevoked {
+    #[prost(string, tag = "1")]
+    pub id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TransfersPaused {
+    #[prost(bool, tag = "1")]
+    pub paused: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AddressChanged {
+    #[prost(string, tag = "1")]
+    pub ip: String,
+    #[prost(string, tag = "2")]
+    pub url: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TransferFailed {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, tag = "3")]
+    pub peer: String,
+    #[prost(string, tag = "4")]
+    pub error: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Lagged {
+    #[prost(uint64, tag = "1")]
+    pub missed: u64,
+}
+
+fn unix_ms(time: DateTime<Utc>) -> i64 {
+    time.timestamp_millis()
+}
+
+/// The name a value has in the REST API's JSON, like `disk_full`
+fn json_name(value: &impl serde::Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+impl From<&DeviceInfo> for Device {
+    fn from(device: &DeviceInfo) -> Self {
+        Self {
+            id: device.id.clone(),
+            name: device.name.clone(),
+            ip: device.ip.clone(),
+            port: device.port.into(),
+            os: device.os.clone(),
+            version: crate::VERSION.to_string(),
+        }
+    }
+}
+
+impl From<FileInfo> for File {
+    fn from(file: FileInfo) -> Self {
+        Self {
+            id: file.id.to_string(),
+            name: file.name,
+            size: file.size,
+            modified_unix_ms: unix_ms(file.modified),
+            mime_type: file.mime_type,
+            tags: file.tags,
+            pinned: file.pinned,
+            sender: file.sender,
+        }
+    }
+}
+
+impl From<UploadSession> for Upload {
+    fn from(session: UploadSession) -> Self {
+        Self {
+            id: session.id,
+            name: session.name,
+            size: session.size,
+            offset: session.offset,
+            updated_unix_ms: unix_ms(session.updated),
+        }
+    }
+}
+
+impl From<RelayOffer> for Relay {
+    fn from(offer: RelayOffer) -> Self {
+        Self {
+            id: offer.id,
+            name: offer.name,
+            size: offer.size,
+            sender: offer.sender,
+            created_unix_ms: unix_ms(offer.created),
+        }
+    }
+}
+
+impl From<ServerEvent> for Event {
+    fn from(event: ServerEvent) -> Self {
+        let kind = match event {
+            ServerEvent::Activity(entry) => event::Kind::Activity(Activity {
+                action: json_name(&entry.action),
+                file: entry.file,
+                size: entry.size,
+                sender: entry.sender,
+                previous_name: entry.previous_name,
+                time_unix_ms: unix_ms(entry.timestamp),
+            }),
+            ServerEvent::CollectRequested(request) => event::Kind::CollectRequested(CollectRequested {
+                id: request.id,
+                requester: request.requester,
+                pattern: request.pattern,
+            }),
+            ServerEvent::RelayOffered(offer) => event::Kind::RelayOffered(offer.into()),
+            ServerEvent::StorageProblem(storage::StorageReport { problem, since, detail }) => {
+                event::Kind::StorageProblem(StorageProblem {
+                    problem: json_name(&problem),
+                    detail,
+                    since_unix_ms: unix_ms(since),
+                })
+            }
+            ServerEvent::TransferFailed(transfer) => event::Kind::TransferFailed(TransferFailed {
+                id: transfer.id,
+                name: transfer.name,
+                peer: transfer.peer,
+                error: transfer.error.unwrap_or_default(),
+            }),
+            ServerEvent::ClientRevoked { id } => event::Kind::ClientRevoked(ClientRevoked { id }),
+            ServerEvent::TransfersPaused { paused } => event::Kind::TransfersPaused(TransfersPaused { paused }),
+            ServerEvent::AddressChanged { ip, url } => event::Kind::AddressChanged(AddressChanged { ip, url }),
+        };
+        Self { kind: Some(kind) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::history::{HistoryAction, HistoryEntry};
+    use prost::Message;
+
+    #[test]
+    fn test_events_keep_their_rest_names() {
+        let mut entry = HistoryEntry::new(HistoryAction::Move, "b.txt");
+        entry.previous_name = Some("a.txt".to_string());
+        let event = Event::from(ServerEvent::Activity(entry));
+
+        let decoded = Event::decode(event.encode_to_vec().as_slice()).unwrap();
+        let Some(event::Kind::Activity(activity)) = decoded.kind else {
+            panic!("expected an activity, got {:?}", decoded.kind);
+        };
+        assert_eq!(activity.action, "move");
+        assert_eq!(activity.file, "b.txt");
+        assert_eq!(activity.previous_name.as_deref(), Some("a.txt"));
+
+        let report = storage::StorageReport {
+            problem: storage::StorageProblem::DiskFull,
+            since: Utc::now(),
+            detail: "No space left on device".to_string(),
+        };
+        let Some(event::Kind::StorageProblem(problem)) = Event::from(ServerEvent::StorageProblem(report)).kind else {
+            panic!("expected a storage problem");
+        };
+        assert_eq!(problem.problem, "disk_full");
+    }
+}
@@ -0,0 +1,401 @@
+//! A virtual printer: phones and laptops "print" to it over IPP Everywhere and the documents
+//! land in the share as PDFs, without anyone opening a browser.
+//!
+//! The printer is announced over mDNS as `_ipp._tcp` and served on the web server port at
+//! [`PRINTER_PATH`]. It accepts PDF documents only and finishes every job as it arrives, so
+//! there is no queue to manage. Printing systems cannot enter access codes, which is why the
+//! printer has to be enabled in the configuration.
+
+pub mod message;
+
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use crate::core::models::DeviceInfo;
+use crate::utils::file::{get_share_file_info, sanitize_path_component};
+use crate::utils::filename::unused_path;
+use crate::web::handlers::access::PRINTER_PATH;
+use crate::web::handlers::api::record_upload;
+use crate::web::session::normalize_sender;
+use crate::web::state::AppState;
+use message::{group, Attribute, Request, Response as IppResponse, Value};
+
+/// Announced for IPP Everywhere printers
+const SERVICE_TYPE: &str = "_print._sub._ipp._tcp.local.";
+
+/// Folder of the share that printed documents go into
+const PRINTED_DIR: &str = "Printed";
+
+const PDF: &str = "application/pdf";
+
+mod operation {
+    pub const PRINT_JOB: u16 = 0x0002;
+    pub const VALIDATE_JOB: u16 = 0x0004;
+    pub const CANCEL_JOB: u16 = 0x0008;
+    pub const GET_JOB_ATTRIBUTES: u16 = 0x0009;
+    pub const GET_JOBS: u16 = 0x000A;
+    pub const GET_PRINTER_ATTRIBUTES: u16 = 0x000B;
+}
+
+mod status {
+    pub const OK: u16 = 0x0000;
+    pub const BAD_REQUEST: u16 = 0x0400;
+    pub const NOT_POSSIBLE: u16 = 0x0404;
+    pub const NOT_FOUND: u16 = 0x0406;
+    pub const DOCUMENT_FORMAT_NOT_SUPPORTED: u16 = 0x040A;
+    pub const INTERNAL_ERROR: u16 = 0x0500;
+    pub const OPERATION_NOT_SUPPORTED: u16 = 0x0501;
+    pub const VERSION_NOT_SUPPORTED: u16 = 0x0503;
+    pub const NOT_ACCEPTING_JOBS: u16 = 0x0506;
+}
+
+/// Job states of RFC 8011
+const JOB_COMPLETED: i32 = 9;
+const PRINTER_IDLE: i32 = 3;
+
+#[derive(Clone)]
+struct Printer {
+    state: AppState,
+    /// Id of the last job
+    jobs: Arc<AtomicI32>,
+}
+
+/// Routes serving the printer, to be merged into the web server's router
+pub fn router(state: AppState) -> Router {
+    Router::new().route(PRINTER_PATH, post(handle_request)).with_state(Printer {
+        state,
+        jobs: Arc::default(),
+    })
+}
+
+/// Name the printer is shown under
+pub fn printer_name(configured: Option<&str>, device_info: &DeviceInfo) -> String {
+    configured.map_or_else(|| format!("RustDrop on {}", device_info.name), str::to_string)
+}
+
+async fn handle_request(State(printer): State<Printer>, headers: HeaderMap, body: Bytes) -> Response {
+    let response = match Request::parse(&body) {
+        Some(request) if !matches!(request.version.0, 1 | 2) => {
+            IppResponse::new(status::VERSION_NOT_SUPPORTED, request.request_id)
+        }
+        Some(request) => printer.handle(&request, &printer_uri(&printer.state, &headers)).await,
+        None => IppResponse::new(status::BAD_REQUEST, 0),
+    };
+
+    let mut http_headers = HeaderMap::new();
+    http_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/ipp"));
+    (http_headers, response.encode()).into_response()
+}
+
+/// `ipp://` address of the printer as the client reached it
+fn printer_uri(state: &AppState, headers: &HeaderMap) -> String {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            let device_info = state.current_device_info();
+            format!("{}:{}", device_info.ip, device_info.port)
+        });
+    format!("ipp://{}{}", host, PRINTER_PATH)
+}
+
+impl Printer {
+    async fn handle(&self, request: &Request, uri: &str) -> IppResponse {
+        let id = request.request_id;
+        match request.operation {
+            operation::GET_PRINTER_ATTRIBUTES => {
+                IppResponse::new(status::OK, id).with_group(group::PRINTER, self.printer_attributes(uri))
+            }
+            operation::VALIDATE_JOB => match document_format(request) {
+                Some(_) => IppResponse::new(status::OK, id),
+                None => IppResponse::new(status::DOCUMENT_FORMAT_NOT_SUPPORTED, id),
+            },
+            operation::PRINT_JOB => self.print(request, uri).await,
+            operation::GET_JOB_ATTRIBUTES => {
+                let job = request.operation_attribute("job-id").and_then(Value::as_integer);
+                match job {
+                    Some(job) if job > 0 && job <= self.jobs.load(Ordering::SeqCst) => {
+                        IppResponse::new(status::OK, id).with_group(group::JOB, job_attributes(uri, job))
+                    }
+                    _ => IppResponse::new(status::NOT_FOUND, id),
+                }
+            }
+            // Jobs are done as soon as they arrive, so none are waiting or can be cancelled
+            operation::GET_JOBS => IppResponse::new(status::OK, id),
+            operation::CANCEL_JOB => IppResponse::new(status::NOT_POSSIBLE, id),
+            _ => IppResponse::new(status::OPERATION_NOT_SUPPORTED, id),
+        }
+    }
+
+    /// Store the document of a Print-Job request in the share
+    async fn print(&self, request: &Request, uri: &str) -> IppResponse {
+        let id = request.request_id;
+        if document_format(request).is_none() || !request.document.starts_with(b"%PDF") {
+            warn!("Rejected a print job that is not a PDF document");
+            return IppResponse::new(status::DOCUMENT_FORMAT_NOT_SUPPORTED, id);
+        }
+
+        let job = self.jobs.fetch_add(1, Ordering::SeqCst) + 1;
+        let job_name = request.operation_attribute("job-name").and_then(Value::as_str);
+        let sender = request
+            .operation_attribute("requesting-user-name")
+            .and_then(Value::as_str)
+            .and_then(normalize_sender);
+
+        let state = &self.state;
+        if let Err(e) = state.make_room(request.document.len() as u64, &[]) {
+            warn!("Rejected a print job: {}", e);
+            return IppResponse::new(status::NOT_ACCEPTING_JOBS, id);
+        }
+        let path = match store_document(&state.directory, job_name, &request.document).await {
+            Ok(path) => path,
+            Err(e) => {
+                error!("Failed to store printed document: {}", e);
+                return IppResponse::new(status::INTERNAL_ERROR, id);
+            }
+        };
+        match get_share_file_info(&state.directory, &path) {
+            Ok(mut file_info) => {
+                info!("Printed document saved as {}", file_info.name);
+                record_upload(state, &mut file_info, sender, None);
+            }
+            Err(e) => warn!("Failed to read back printed document {:?}: {}", path, e),
+        }
+
+        IppResponse::new(status::OK, id).with_group(group::JOB, job_attributes(uri, job))
+    }
+
+    fn printer_attributes(&self, uri: &str) -> Vec<Attribute> {
+        let name = printer_name(self.state.config.printer.name.as_deref(), &self.state.device_info);
+        let keyword = |value: &str| Value::Keyword(value.to_string());
+        let operations = [
+            operation::PRINT_JOB,
+            operation::VALIDATE_JOB,
+            operation::CANCEL_JOB,
+            operation::GET_JOB_ATTRIBUTES,
+            operation::GET_JOBS,
+            operation::GET_PRINTER_ATTRIBUTES,
+        ];
+
+        vec![
+            Attribute::new("printer-uri-supported", Value::Uri(uri.to_string())),
+            Attribute::new("uri-security-supported", keyword("none")),
+            Attribute::new("uri-authentication-supported", keyword("none")),
+            Attribute::new("printer-name", Value::Name(name.clone())),
+            Attribute::new("printer-info", Value::Text(name)),
+            Attribute::new("printer-make-and-model", Value::Text("RustDrop".to_string())),
+            Attribute::new("printer-state", Value::Enum(PRINTER_IDLE)),
+            Attribute::new("printer-state-reasons", keyword("none")),
+            Attribute::new("printer-is-accepting-jobs", Value::Boolean(true)),
+            Attribute::new("queued-job-count", Value::Integer(0)),
+            Attribute::new("printer-up-time", Value::Integer(chrono::Utc::now().timestamp() as i32)),
+            Attribute::with_values("ipp-versions-supported", vec![keyword("1.1"), keyword("2.0")]),
+            Attribute::with_values(
+                "operations-supported",
+                operations.iter().map(|&operation| Value::Enum(operation.into())).collect(),
+            ),
+            Attribute::new("charset-configured", Value::Charset("utf-8".to_string())),
+            Attribute::new("charset-supported", Value::Charset("utf-8".to_string())),
+            Attribute::new("natural-language-configured", Value::Language("en".to_string())),
+            Attribute::new("generated-natural-language-supported", Value::Language("en".to_string())),
+            Attribute::new("document-format-default", Value::MimeType(PDF.to_string())),
+            Attribute::new("document-format-supported", Value::MimeType(PDF.to_string())),
+            Attribute::new("pdl-override-supported", keyword("not-attempted")),
+            Attribute::new("compression-supported", keyword("none")),
+            Attribute::new("color-supported", Value::Boolean(true)),
+            Attribute::new("sides-supported", keyword("one-sided")),
+            Attribute::new("media-default", keyword("iso_a4_210x297mm")),
+            Attribute::with_values(
+                "media-supported",
+                vec![keyword("iso_a4_210x297mm"), keyword("na_letter_8.5x11in")],
+            ),
+        ]
+    }
+}
+
+/// The document format of a request if the printer takes it; PDF, or a format to detect
+fn document_format(request: &Request) -> Option<&str> {
+    match request.operation_attribute("document-format").and_then(Value::as_str) {
+        None => Some(PDF),
+        Some(format @ (PDF | "application/octet-stream")) => Some(format),
+        Some(_) => None,
+    }
+}
+
+fn job_attributes(uri: &str, job: i32) -> Vec<Attribute> {
+    vec![
+        Attribute::new("job-uri", Value::Uri(format!("{}/{}", uri, job))),
+        Attribute::new("job-id", Value::Integer(job)),
+        Attribute::new("job-state", Value::Enum(JOB_COMPLETED)),
+        Attribute::new("job-state-reasons", Value::Keyword("job-completed-successfully".to_string())),
+    ]
+}
+
+/// Write a printed document into the share under its job name, never replacing another file
+async fn store_document(share: &std::path::Path, job_name: Option<&str>, document: &[u8]) -> std::io::Result<PathBuf> {
+    let directory = share.join(PRINTED_DIR);
+    tokio::fs::create_dir_all(&directory).await?;
+
+    let stem = job_name
+        .map(|name| name.strip_suffix(".pdf").unwrap_or(name))
+        .map(sanitize_path_component)
+        .unwrap_or_else(|| format!("Printed {}", chrono::Local::now().format("%Y-%m-%d %H.%M.%S")));
+    let path = unused_path(directory.join(format!("{}.pdf", stem)));
+    tokio::fs::write(&path, document).await?;
+    Ok(path)
+}
+
+/// The printer's mDNS announcement, withdrawn by [`PrinterAnnouncement::withdraw`]
+pub struct PrinterAnnouncement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+/// Announce the printer on the local network
+pub fn announce(device_info: &DeviceInfo, name: &str) -> Result<PrinterAnnouncement> {
+    let properties: HashMap<String, String> = [
+        ("txtvers", "1"),
+        ("qtotal", "1"),
+        ("rp", PRINTER_PATH.trim_start_matches('/')),
+        ("ty", name),
+        ("product", "(RustDrop)"),
+        ("pdl", PDF),
+        ("Color", "T"),
+        ("Duplex", "F"),
+        ("note", "Saves documents to the shared folder"),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect();
+
+    let daemon = ServiceDaemon::new()?;
+    let service = ServiceInfo::new(
+        SERVICE_TYPE,
+        name,
+        &format!("rustdrop-printer-{}-{}", device_info.id, device_info.port),
+        device_info.ip.as_str(),
+        device_info.port,
+        Some(properties),
+    )?
+    // Follows the host between networks without being announced again
+    .enable_addr_auto();
+    let fullname = service.get_fullname().to_string();
+    daemon.register(service)?;
+
+    info!("Announced printer {:?}", name);
+    Ok(PrinterAnnouncement { daemon, fullname })
+}
+
+impl PrinterAnnouncement {
+    pub fn withdraw(self) {
+        if let Err(e) = self.daemon.unregister(&self.fullname) {
+            tracing::debug!("Failed to withdraw printer announcement: {}", e);
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Group;
+    use tempfile::TempDir;
+
+    fn request(operation: u16, attributes: Vec<Attribute>, document: &[u8]) -> Request {
+        let mut all = vec![
+            Attribute::new("attributes-charset", Value::Charset("utf-8".to_string())),
+            Attribute::new("attributes-natural-language", Value::Language("en".to_string())),
+            Attribute::new("printer-uri", Value::Uri("ipp://localhost/ipp/print".to_string())),
+        ];
+        all.extend(attributes);
+        Request {
+            version: (2, 0),
+            operation,
+            request_id: 1,
+            groups: vec![Group { tag: group::OPERATION, attributes: all }],
+            document: document.to_vec(),
+        }
+    }
+
+    fn printer(temp_dir: &TempDir) -> Printer {
+        Printer {
+            state: AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080)),
+            jobs: Arc::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_print_job_lands_in_share() {
+        let temp_dir = TempDir::new().unwrap();
+        let printer = printer(&temp_dir);
+        let job = request(
+            operation::PRINT_JOB,
+            vec![
+                Attribute::new("job-name", Value::Name("Boarding pass.pdf".to_string())),
+                Attribute::new("requesting-user-name", Value::Name("alex".to_string())),
+                Attribute::new("document-format", Value::MimeType(PDF.to_string())),
+            ],
+            b"%PDF-1.7 boarding pass",
+        );
+
+        let response = printer.handle(&job, "ipp://host/ipp/print").await;
+        assert_eq!(response.status, status::OK);
+        let response = printer.handle(&job, "ipp://host/ipp/print").await;
+        assert_eq!(response.status, status::OK);
+
+        let printed = temp_dir.path().join(PRINTED_DIR);
+        assert_eq!(std::fs::read(printed.join("Boarding pass.pdf")).unwrap(), b"%PDF-1.7 boarding pass");
+        assert!(printed.join("Boarding pass (2).pdf").exists());
+        assert_eq!(printer.state.history.recent(1)[0].sender.as_deref(), Some("alex"));
+
+        let status = printer
+            .handle(
+                &request(operation::GET_JOB_ATTRIBUTES, vec![Attribute::new("job-id", Value::Integer(2))], b""),
+                "ipp://host/ipp/print",
+            )
+            .await;
+        assert_eq!(status.status, status::OK);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_other_formats_and_operations() {
+        let temp_dir = TempDir::new().unwrap();
+        let printer = printer(&temp_dir);
+
+        let jpeg = request(
+            operation::PRINT_JOB,
+            vec![Attribute::new("document-format", Value::MimeType("image/jpeg".to_string()))],
+            b"\xff\xd8\xff",
+        );
+        let response = printer.handle(&jpeg, "ipp://host/ipp/print").await;
+        assert_eq!(response.status, status::DOCUMENT_FORMAT_NOT_SUPPORTED);
+
+        let not_pdf = request(operation::PRINT_JOB, Vec::new(), b"plain text");
+        let response = printer.handle(&not_pdf, "ipp://host/ipp/print").await;
+        assert_eq!(response.status, status::DOCUMENT_FORMAT_NOT_SUPPORTED);
+        assert!(!temp_dir.path().join(PRINTED_DIR).exists());
+
+        let response = printer.handle(&request(0x0005, Vec::new(), b""), "ipp://host/ipp/print").await;
+        assert_eq!(response.status, status::OPERATION_NOT_SUPPORTED);
+
+        let response = printer
+            .handle(&request(operation::GET_PRINTER_ATTRIBUTES, Vec::new(), b""), "ipp://host/ipp/print")
+            .await;
+        assert_eq!(response.status, status::OK);
+        assert_eq!(response.groups[1].tag, group::PRINTER);
+    }
+}
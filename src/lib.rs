@@ -3,11 +3,26 @@
 //! This crate provides a simple and secure way to share files across devices
 //! on a local network using a web interface.
 
+pub mod backend;
 pub mod core;
 pub mod utils;
 pub mod web;
 pub mod discovery;
 pub mod cli;
+pub mod client;
+pub mod crash;
+pub mod logging;
+pub mod plugins;
+pub mod sync;
+pub mod telemetry;
+#[cfg(feature = "quic")]
+pub mod quic;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "ipp")]
+pub mod ipp;
+#[cfg(feature = "email")]
+pub mod email;
 
 // Re-export commonly used types for convenience
 pub use core::{
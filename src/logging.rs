@@ -0,0 +1,84 @@
+//! Writing the log to a file, configured in `[logging]`, so daemon installs keep their logs
+//! without a service manager collecting standard output
+
+use anyhow::{Context, Result};
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::core::config::{LogRotation, LoggingConfig};
+
+/// Tracing layer writing to the log file
+pub type FileLayer<S> = Box<dyn Layer<S> + Send + Sync>;
+
+/// A layer writing to `logging.file` if one is set, and the guard that flushes it when dropped
+pub fn file_layer<S>(config: &LoggingConfig) -> Result<Option<(FileLayer<S>, WorkerGuard)>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let Some(path) = &config.file else {
+        return Ok(None);
+    };
+
+    let name = path
+        .file_name()
+        .with_context(|| format!("Log file {} has no file name", path.display()))?;
+    let directory = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(".".as_ref());
+    let filter = EnvFilter::try_new(&config.level).with_context(|| format!("Invalid log level {:?}", config.level))?;
+
+    let mut appender = RollingFileAppender::builder()
+        .rotation(rotation(config.rotate))
+        .filename_prefix(name.to_string_lossy());
+    if let Some(max_files) = config.max_files {
+        appender = appender.max_log_files(max_files);
+    }
+    let appender = appender
+        .build(directory)
+        .with_context(|| format!("Failed to open log file {}", path.display()))?;
+
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(writer)
+        .with_filter(filter);
+    Ok(Some((Box::new(layer), guard)))
+}
+
+fn rotation(rotate: LogRotation) -> Rotation {
+    match rotate {
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_file_layer_writes_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = LoggingConfig {
+            file: Some(temp_dir.path().join("rustdrop.log")),
+            rotate: LogRotation::Never,
+            ..Default::default()
+        };
+
+        let (layer, guard) = file_layer(&config).unwrap().unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("stored upload");
+            tracing::debug!("below the level");
+        });
+        drop(guard);
+
+        let log = std::fs::read_to_string(temp_dir.path().join("rustdrop.log")).unwrap();
+        assert!(log.contains("stored upload"));
+        assert!(!log.contains("below the level"));
+    }
+}
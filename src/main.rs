@@ -1,27 +1,52 @@
-mod cli;
-mod core;
-mod discovery;
-mod utils;
-mod web;
-
 use anyhow::Result;
 use clap::Parser;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
-use cli::Cli;
+use rustdrop::cli::Cli;
+use rustdrop::AppConfig;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging with custom filter to reduce mDNS noise during shutdown
-    let env_filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| {
+    let env_filter = || {
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| {
             // Filter out harmless mDNS errors during shutdown
             EnvFilter::new("info,mdns_sd::service_daemon=off")
-        });
+        })
+    };
     
+    // Logging and telemetry settings only come from the configuration file and environment
+    let config = AppConfig::load().unwrap_or_default();
+    let (file_layer, _log_guard) = match rustdrop::logging::file_layer(&config.logging) {
+        Ok(file) => file.unzip(),
+        Err(e) => {
+            eprintln!("⚠️  Not writing a log file: {:#}", e);
+            (None, None)
+        }
+    };
+    let (telemetry_layer, _telemetry) = match rustdrop::telemetry::init(&config.telemetry) {
+        Ok(telemetry) => telemetry.unzip(),
+        Err(e) => {
+            eprintln!("⚠️  Not exporting traces: {}", e);
+            (None, None)
+        }
+    };
+
+    // The last log lines go into crash reports
+    let recent_log = rustdrop::crash::RecentLog::default();
+    rustdrop::crash::install_panic_hook(recent_log.clone());
+
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter)
+        .with(telemetry_layer.map(|layer| layer.with_filter(env_filter())))
+        .with(file_layer)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(recent_log)
+                .with_filter(env_filter()),
+        )
+        // Standard output is left to commands like `rustdrop manifest` whose output gets piped
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_filter(env_filter()))
         .init();
 
     let cli = Cli::parse();
@@ -0,0 +1,237 @@
+//! Plugins that are external programs, configured under `[[plugins]]`.
+//!
+//! The program runs with the upload's path as an argument, inside an empty working directory
+//! that only it uses, and with an environment holding nothing but `PATH` and a description of
+//! the upload. Whatever it leaves in the working directory is added to the share, and a JSON
+//! object like `{"tags": ["invoice"], "text": "..."}` on its standard output tags the upload and
+//! makes it searchable by its text.
+
+use anyhow::{bail, Context, Result};
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{Plugin, PluginOutput};
+use crate::core::config::PluginConfig;
+use crate::core::models::FileInfo;
+
+/// Placeholder in the arguments for the path of the upload
+const FILE_PLACEHOLDER: &str = "{file}";
+
+/// Output kept of each stream; programs writing more still run to the end
+const MAX_OUTPUT: usize = 64 * 1024;
+
+/// What a program may print on its standard output
+#[derive(Debug, Default, Deserialize)]
+struct Report {
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+/// An external program run for each upload it accepts
+pub struct CommandPlugin {
+    config: PluginConfig,
+}
+
+impl CommandPlugin {
+    pub fn new(config: PluginConfig) -> Self {
+        Self { config }
+    }
+
+    /// Arguments with the placeholder replaced, or the path appended when there is none
+    fn arguments(&self, path: &Path) -> Vec<String> {
+        let path = path.to_string_lossy();
+        let mut arguments: Vec<String> = self.config.command.iter().skip(1).cloned().collect();
+        if arguments.iter().any(|argument| argument.contains(FILE_PLACEHOLDER)) {
+            for argument in &mut arguments {
+                *argument = argument.replace(FILE_PLACEHOLDER, &path);
+            }
+        } else {
+            arguments.push(path.into_owned());
+        }
+        arguments
+    }
+
+    async fn run(&self, file: &FileInfo, workdir: &Path) -> Result<PluginOutput> {
+        let Some(program) = self.config.command.first() else {
+            bail!("Plugin {} has no command", self.config.name);
+        };
+
+        let mut command = tokio::process::Command::new(program);
+        command
+            .args(self.arguments(&file.path))
+            .current_dir(workdir)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            // Tools keeping caches or settings in their home directory find an empty one
+            .env("HOME", workdir)
+            .env("RUSTDROP_FILE_NAME", &file.name)
+            .env("RUSTDROP_MIME_TYPE", &file.mime_type)
+            .env("RUSTDROP_SIZE", file.size.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(sender) = &file.sender {
+            command.env("RUSTDROP_SENDER", sender);
+        }
+        // Its own process group, so whatever it starts is killed along with it
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to run {:?}", program))?;
+        #[cfg(unix)]
+        let mut group = ProcessGroup(child.id());
+
+        let stdout = child.stdout.take().map(read_capped);
+        let stderr = child.stderr.take().map(read_capped);
+        let (stdout, stderr, status) = tokio::join!(
+            async move { Some(stdout?.await) },
+            async move { Some(stderr?.await) },
+            child.wait()
+        );
+        let status = status?;
+        #[cfg(unix)]
+        {
+            group.0 = None;
+        }
+
+        if !status.success() {
+            let stderr = stderr.unwrap_or_default();
+            let stderr = String::from_utf8_lossy(&stderr);
+            bail!("{:?} {}: {}", program, status, stderr.trim().lines().last().unwrap_or(""));
+        }
+
+        let stdout = stdout.unwrap_or_default();
+        let stdout = String::from_utf8_lossy(&stdout);
+        let report = if stdout.trim_start().starts_with('{') {
+            serde_json::from_str(stdout.trim()).context("Unreadable JSON on standard output")?
+        } else {
+            Report::default()
+        };
+        Ok(PluginOutput { tags: report.tags, text: report.text })
+    }
+}
+
+impl Plugin for CommandPlugin {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn accepts(&self, file: &FileInfo) -> bool {
+        self.config.mime_types.is_empty()
+            || self.config.mime_types.iter().any(|pattern| match pattern.strip_suffix("/*") {
+                Some(kind) => file.mime_type.split('/').next() == Some(kind),
+                None => pattern.eq_ignore_ascii_case(&file.mime_type),
+            })
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.config.timeout_secs)
+    }
+
+    fn process<'a>(&'a self, file: &'a FileInfo, workdir: &'a Path) -> BoxFuture<'a, Result<PluginOutput>> {
+        Box::pin(self.run(file, workdir))
+    }
+}
+
+/// Kills the process group of a program that did not finish, when it timed out
+#[cfg(unix)]
+struct ProcessGroup(Option<u32>);
+
+#[cfg(unix)]
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        use rustix::process::{kill_process_group, Pid, Signal};
+
+        if let Some(pid) = self.0.and_then(|id| Pid::from_raw(id as i32)) {
+            let _ = kill_process_group(pid, Signal::KILL);
+        }
+    }
+}
+
+/// The start of everything `reader` gives until it ends
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut buffer = [0u8; 8192];
+    while let Ok(read) = reader.read(&mut buffer).await {
+        if read == 0 {
+            break;
+        }
+        let keep = read.min(MAX_OUTPUT - output.len());
+        output.extend_from_slice(&buffer[..keep]);
+    }
+    output
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::core::config::PluginConfig;
+    use std::path::PathBuf;
+
+    fn plugin(command: &[&str], mime_types: &[&str]) -> CommandPlugin {
+        CommandPlugin::new(PluginConfig {
+            name: "test".to_string(),
+            command: command.iter().map(|part| part.to_string()).collect(),
+            mime_types: mime_types.iter().map(|kind| kind.to_string()).collect(),
+            timeout_secs: 5,
+        })
+    }
+
+    fn upload(mime_type: &str) -> FileInfo {
+        FileInfo {
+            id: uuid::Uuid::new_v4(),
+            name: "scan.pdf".to_string(),
+            path: PathBuf::from("/share/scan.pdf"),
+            size: 3,
+            size_human: "3 B".to_string(),
+            modified: chrono::Utc::now(),
+            mime_type: mime_type.to_string(),
+            tags: Vec::new(),
+            pinned: false,
+            sender: None,
+            published: false,
+            mode: None,
+        }
+    }
+
+    #[test]
+    fn test_arguments_and_types() {
+        let ocr = plugin(&["ocr", "--in={file}", "--lang", "eng"], &["application/pdf", "image/*"]);
+        assert_eq!(ocr.arguments(Path::new("/share/scan.pdf")), ["--in=/share/scan.pdf", "--lang", "eng"]);
+        assert!(ocr.accepts(&upload("application/pdf")));
+        assert!(ocr.accepts(&upload("image/png")));
+        assert!(!ocr.accepts(&upload("video/mp4")));
+
+        let index = plugin(&["index"], &[]);
+        assert_eq!(index.arguments(Path::new("/share/scan.pdf")), ["/share/scan.pdf"]);
+        assert!(index.accepts(&upload("video/mp4")));
+    }
+
+    #[tokio::test]
+    async fn test_runs_in_a_clean_environment() {
+        let workdir = tempfile::TempDir::new().unwrap();
+        let script = r#"test -z "$CARGO_MANIFEST_DIR" && test "$HOME" = "$PWD" && echo "$RUSTDROP_FILE_NAME" > name.txt && echo '{"tags": ["scanned"], "text": "Total due"}'"#;
+        let output = plugin(&["sh", "-c", script], &[])
+            .process(&upload("application/pdf"), workdir.path())
+            .await
+            .unwrap();
+        assert_eq!(output.tags, ["scanned"]);
+        assert_eq!(output.text.as_deref(), Some("Total due"));
+        assert_eq!(std::fs::read_to_string(workdir.path().join("name.txt")).unwrap(), "scan.pdf\n");
+
+        let error = plugin(&["sh", "-c", "echo broken >&2; exit 3"], &[])
+            .process(&upload("application/pdf"), workdir.path())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().ends_with(": broken"), "{}", error);
+    }
+}
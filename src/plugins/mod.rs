@@ -0,0 +1,217 @@
+//! Plugins processing uploads once they arrived, like extracting text from scans, transcoding
+//! videos or feeding a search index.
+//!
+//! A plugin is anything implementing [`Plugin`]: the programs configured under `[[plugins]]`,
+//! or code registered with [`Plugins::register`] by a program embedding RustDrop. Each gets an
+//! empty working directory of its own and a time limit; files it leaves in the directory are
+//! added to the share next to the upload, the tags it reports are added to the upload's, and
+//! the text it found makes the upload show up in searches.
+
+pub mod command;
+#[cfg(feature = "ocr")]
+pub mod ocr;
+
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::core::config::AppConfig;
+use crate::core::history::{HistoryAction, HistoryEntry};
+use crate::core::metadata::{normalize_tag, STATE_DIR};
+use crate::core::models::FileInfo;
+use crate::utils::file::get_share_file_info;
+use crate::utils::filename::{host_name, unused_path};
+use crate::web::state::AppState;
+use command::CommandPlugin;
+
+/// Directory below [`STATE_DIR`] holding the working directories of running plugins
+const WORK_DIR: &str = "plugins";
+
+/// Uploads processed at once, so a burst of uploads cannot start dozens of OCR runs
+const MAX_RUNNING: usize = 2;
+
+/// What a plugin found out about an upload
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PluginOutput {
+    /// Tags to add to the upload
+    pub tags: Vec<String>,
+    /// Text found in the upload, for search
+    pub text: Option<String>,
+}
+
+/// Processing applied to uploads
+pub trait Plugin: Send + Sync {
+    /// Name in the logs, and the sender of the files the plugin produces
+    fn name(&self) -> &str;
+
+    /// Whether the plugin wants to process `file`
+    fn accepts(&self, file: &FileInfo) -> bool;
+
+    /// How long processing may take before it is abandoned
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(60)
+    }
+
+    /// Process `file`, leaving any files to add to the share in `workdir`
+    fn process<'a>(&'a self, file: &'a FileInfo, workdir: &'a Path) -> BoxFuture<'a, Result<PluginOutput>>;
+}
+
+/// The plugins of a server
+pub struct Plugins {
+    plugins: Vec<Box<dyn Plugin>>,
+    running: Semaphore,
+}
+
+impl Default for Plugins {
+    fn default() -> Self {
+        Self {
+            plugins: Vec::new(),
+            running: Semaphore::new(MAX_RUNNING),
+        }
+    }
+}
+
+impl Plugins {
+    /// The programs configured under `[[plugins]]`, and the text recognition of the `ocr` feature
+    pub fn from_config(config: &AppConfig) -> Self {
+        let mut plugins = Self::default();
+        #[cfg(feature = "ocr")]
+        if config.ocr.enabled {
+            plugins.register(Box::new(ocr::TextRecognition::new(&config.ocr)));
+        }
+        for plugin in &config.plugins {
+            plugins.register(Box::new(CommandPlugin::new(plugin.clone())));
+        }
+        plugins
+    }
+
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// Run the plugins accepting an upload of the share of `state`, one after the other, and
+    /// return the files they added to the share
+    pub async fn process(&self, state: &AppState, file: &FileInfo) -> Vec<FileInfo> {
+        let plugins: Vec<&dyn Plugin> = self
+            .plugins
+            .iter()
+            .map(|plugin| plugin.as_ref())
+            .filter(|plugin| plugin.accepts(file))
+            .collect();
+        if plugins.is_empty() {
+            return Vec::new();
+        }
+        let Ok(_permit) = self.running.acquire().await else {
+            return Vec::new();
+        };
+
+        let mut produced = Vec::new();
+        for plugin in plugins {
+            let workdir = state.directory.join(STATE_DIR).join(WORK_DIR).join(Uuid::new_v4().to_string());
+            if let Err(e) = tokio::fs::create_dir_all(&workdir).await {
+                warn!("Failed to create a working directory for plugin {}: {}", plugin.name(), e);
+                continue;
+            }
+
+            // Dropping the future on timeout kills a program it started
+            match tokio::time::timeout(plugin.timeout(), plugin.process(file, &workdir)).await {
+                Ok(Ok(output)) => {
+                    debug!("Plugin {} processed {}", plugin.name(), file.name);
+                    add_tags(state, file, &output.tags);
+                    if let Some(text) = &output.text {
+                        if let Err(e) = state.text.set(&file.name, text) {
+                            warn!("Failed to index the text of {}: {}", file.name, e);
+                        }
+                    }
+                    produced.extend(adopt_files(state, plugin.name(), file, &workdir).await);
+                }
+                Ok(Err(e)) => warn!("Plugin {} failed on {}: {:#}", plugin.name(), file.name, e),
+                Err(_) => warn!(
+                    "Plugin {} did not finish {} within {:?}",
+                    plugin.name(),
+                    file.name,
+                    plugin.timeout()
+                ),
+            }
+
+            if let Err(e) = tokio::fs::remove_dir_all(&workdir).await {
+                warn!("Failed to remove plugin working directory {:?}: {}", workdir, e);
+            }
+        }
+        if !produced.is_empty() {
+            state.storage.clear();
+        }
+        produced
+    }
+}
+
+fn add_tags(state: &AppState, file: &FileInfo, tags: &[String]) {
+    let mut merged = state.metadata.tags(&file.name);
+    let before = merged.len();
+    merged.extend(tags.iter().filter_map(|tag| normalize_tag(tag)));
+    merged.sort();
+    merged.dedup();
+    if merged.len() != before {
+        if let Err(e) = state.metadata.set_tags(&file.name, &merged) {
+            warn!("Failed to tag {}: {}", file.name, e);
+        }
+    }
+}
+
+/// Move the files a plugin left in `workdir` next to the upload it processed
+async fn adopt_files(state: &AppState, plugin: &str, file: &FileInfo, workdir: &Path) -> Vec<FileInfo> {
+    let directory = file.path.parent().map(Path::to_path_buf).unwrap_or_else(|| state.directory.clone());
+    let mut adopted = Vec::new();
+    for source in files_in(workdir).await {
+        let Some(name) = source.file_name() else {
+            continue;
+        };
+        let target = unused_path(directory.join(host_name(&name.to_string_lossy())));
+        if let Err(e) = tokio::fs::rename(&source, &target).await {
+            warn!("Failed to add {:?} from plugin {} to the share: {}", source, plugin, e);
+            continue;
+        }
+
+        let Ok(mut produced) = get_share_file_info(&state.directory, &target) else {
+            continue;
+        };
+        info!("Plugin {} added {}", plugin, produced.name);
+        if let Err(e) = state.metadata.set_sender(&produced.name, Some(plugin)) {
+            warn!("Failed to record sender for {}: {}", produced.name, e);
+        }
+        let mut entry = HistoryEntry::new(HistoryAction::Upload, &produced.name);
+        entry.size = produced.size;
+        entry.sender = Some(plugin.to_string());
+        if let Err(e) = state.record_history(&entry) {
+            warn!("Failed to record upload history for {}: {}", produced.name, e);
+        }
+        state.metadata.annotate(&mut produced);
+        adopted.push(produced);
+    }
+    adopted
+}
+
+/// Regular files directly inside `directory`, in name order, except hidden ones
+async fn files_in(directory: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(directory).await else {
+        return files;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        // Hidden files are left behind by tools using the directory as their home
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && entry.file_type().await.is_ok_and(|kind| kind.is_file()) {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    files
+}
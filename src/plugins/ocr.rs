@@ -0,0 +1,135 @@
+//! Text recognition for uploaded documents, the built-in plugin of the `ocr` feature.
+//!
+//! PDFs with a text layer give it up through poppler's `pdftotext`; scanned ones, and images,
+//! are rendered and read by `tesseract`. Both tools have to be installed.
+
+use anyhow::{bail, Context, Result};
+use futures_util::future::BoxFuture;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{Plugin, PluginOutput};
+use crate::core::config::OcrConfig;
+use crate::core::models::FileInfo;
+
+/// Characters of text a PDF needs to count as having a text layer, below that it is a scan
+const MIN_TEXT_LAYER: usize = 32;
+
+/// Resolution scanned PDF pages are rendered at for recognition
+const RENDER_DPI: &str = "300";
+
+/// Images tesseract can read
+const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/tiff", "image/bmp", "image/gif", "image/webp"];
+
+/// Finds the text in PDFs and images
+pub struct TextRecognition {
+    languages: String,
+    timeout: Duration,
+}
+
+impl TextRecognition {
+    pub fn new(config: &OcrConfig) -> Self {
+        Self {
+            languages: config.languages.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+        }
+    }
+
+    async fn recognize(&self, file: &FileInfo, workdir: &Path) -> Result<PluginOutput> {
+        let text = if file.mime_type == "application/pdf" {
+            let text = run("pdftotext", &["-layout".as_ref(), file.path.as_os_str(), "-".as_ref()]).await?;
+            if has_text_layer(&text) {
+                text
+            } else {
+                self.recognize_pages(&file.path, workdir).await?
+            }
+        } else {
+            self.recognize_image(&file.path).await?
+        };
+        Ok(PluginOutput { text: Some(text), ..Default::default() })
+    }
+
+    async fn recognize_image(&self, path: &Path) -> Result<String> {
+        run(
+            "tesseract",
+            &[path.as_os_str(), "stdout".as_ref(), "-l".as_ref(), self.languages.as_ref()],
+        )
+        .await
+    }
+
+    /// Render every page of a scanned PDF and read them in order
+    async fn recognize_pages(&self, path: &Path, workdir: &Path) -> Result<String> {
+        // A subdirectory, as files left in the working directory would be added to the share
+        let pages = workdir.join("pages");
+        tokio::fs::create_dir_all(&pages).await?;
+        let root = pages.join("page");
+        run(
+            "pdftoppm",
+            &["-png".as_ref(), "-r".as_ref(), RENDER_DPI.as_ref(), path.as_os_str(), root.as_os_str()],
+        )
+        .await?;
+
+        let mut images: Vec<PathBuf> = Vec::new();
+        let mut entries = tokio::fs::read_dir(&pages).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            images.push(entry.path());
+        }
+        // pdftoppm pads the page numbers, so names sort in page order
+        images.sort();
+
+        let mut text = String::new();
+        for image in images {
+            text.push_str(&self.recognize_image(&image).await?);
+            text.push('\n');
+        }
+        Ok(text)
+    }
+}
+
+impl Plugin for TextRecognition {
+    fn name(&self) -> &str {
+        "ocr"
+    }
+
+    fn accepts(&self, file: &FileInfo) -> bool {
+        file.mime_type == "application/pdf" || IMAGE_TYPES.contains(&file.mime_type.as_str())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn process<'a>(&'a self, file: &'a FileInfo, workdir: &'a Path) -> BoxFuture<'a, Result<PluginOutput>> {
+        Box::pin(self.recognize(file, workdir))
+    }
+}
+
+fn has_text_layer(text: &str) -> bool {
+    text.chars().filter(|c| c.is_alphanumeric()).count() >= MIN_TEXT_LAYER
+}
+
+/// Standard output of a tool, which is killed when recognition is given up
+async fn run(program: &str, arguments: &[&std::ffi::OsStr]) -> Result<String> {
+    let output = tokio::process::Command::new(program)
+        .args(arguments)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} (is it installed?)", program))?;
+    if !output.status.success() {
+        bail!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scans_have_no_text_layer() {
+        assert!(!has_text_layer("\u{c}\n  \u{c}\n 3 \u{c}"));
+        assert!(has_text_layer("Invoice 2024-117\nACME Corp, Total due: 1,200.00 EUR"));
+    }
+}
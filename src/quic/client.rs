@@ -0,0 +1,181 @@
+use anyhow::{bail, Context, Result};
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{Connection, Endpoint};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+
+use crate::client::Download;
+use crate::core::models::{FileAttributes, FileInfo};
+use crate::core::certificate::certificate_fingerprint;
+use crate::utils::sparse::{data_ranges, zero_runs};
+use crate::quic::{read_frame, write_frame, QuicEnvelope, QuicRequest, QuicResponse, ALPN, SERVER_NAME};
+
+/// Connection to another instance's QUIC transport
+pub struct QuicClient {
+    endpoint: Endpoint,
+    connection: Connection,
+    token: String,
+}
+
+impl QuicClient {
+    /// Connect to `addr`, accepting only a server whose certificate has the given fingerprint.
+    /// Requests carry `token` so the server can tell approved clients apart.
+    pub async fn connect(addr: SocketAddr, fingerprint: &str, token: &str) -> Result<Self> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut crypto = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertificate {
+                fingerprint: fingerprint.to_lowercase(),
+                provider,
+            }))
+            .with_no_client_auth();
+        crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+        let bind_addr: SocketAddr = if addr.is_ipv6() {
+            "[::]:0".parse()?
+        } else {
+            "0.0.0.0:0".parse()?
+        };
+        let mut endpoint = Endpoint::client(bind_addr)?;
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(
+            QuicClientConfig::try_from(crypto)?,
+        )));
+
+        let connection = endpoint
+            .connect(addr, SERVER_NAME)?
+            .await
+            .with_context(|| format!("Failed to open QUIC connection to {}", addr))?;
+
+        Ok(Self {
+            endpoint,
+            connection,
+            token: token.to_string(),
+        })
+    }
+
+    pub async fn list_files(&self) -> Result<Vec<FileInfo>> {
+        let (mut send, mut recv) = self.connection.open_bi().await?;
+        write_frame(&mut send, &self.envelope(QuicRequest::List)).await?;
+        send.finish()?;
+
+        match read_frame(&mut recv).await? {
+            QuicResponse::Files { files } => Ok(files),
+            other => unexpected(other),
+        }
+    }
+
+    pub async fn download(&self, id: &str) -> Result<Download> {
+        let (mut send, mut recv) = self.connection.open_bi().await?;
+        write_frame(&mut send, &self.envelope(QuicRequest::Download { id: id.to_string() })).await?;
+        send.finish()?;
+
+        let (size, modified) = match read_frame(&mut recv).await? {
+            QuicResponse::Content { size, modified } => (size, modified),
+            other => return unexpected(other),
+        };
+
+        let mut data = Vec::with_capacity(size as usize);
+        (&mut recv).take(size).read_to_end(&mut data).await?;
+        if data.len() as u64 != size {
+            bail!("Download ended after {} of {} bytes", data.len(), size);
+        }
+        Ok(Download { data, modified })
+    }
+
+    /// Upload a file to `path` (relative to the peer's share), replacing any existing file
+    pub async fn upload(&self, path: &str, data: Vec<u8>, attributes: FileAttributes) -> Result<FileInfo> {
+        let (mut send, mut recv) = self.connection.open_bi().await?;
+        let zeroes = zero_runs(&data);
+        let ranges = data_ranges(data.len() as u64, &zeroes).context("Invalid zero runs")?;
+        let request = QuicRequest::Upload {
+            path: path.to_string(),
+            size: data.len() as u64,
+            attributes,
+            zeroes,
+        };
+        write_frame(&mut send, &self.envelope(request)).await?;
+        for (start, end) in ranges {
+            send.write_all(&data[start as usize..end as usize]).await?;
+        }
+        send.finish()?;
+
+        match read_frame(&mut recv).await? {
+            QuicResponse::Stored { file } => Ok(file),
+            other => unexpected(other),
+        }
+    }
+
+    fn envelope(&self, request: QuicRequest) -> QuicEnvelope {
+        QuicEnvelope {
+            client: Some(self.token.clone()),
+            request,
+        }
+    }
+}
+
+impl Drop for QuicClient {
+    fn drop(&mut self) {
+        self.endpoint.close(0u32.into(), b"done");
+    }
+}
+
+fn unexpected<T>(response: QuicResponse) -> Result<T> {
+    match response {
+        QuicResponse::Error { message } => bail!("Peer refused the request: {}", message),
+        other => bail!("Unexpected reply from peer: {:?}", other),
+    }
+}
+
+/// Trusts exactly one self-signed certificate, identified by its fingerprint
+#[derive(Debug)]
+struct PinnedCertificate {
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if certificate_fingerprint(end_entity) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "Certificate does not match the peer's device identity".to_string(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
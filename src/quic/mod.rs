@@ -0,0 +1,171 @@
+//! QUIC transport for transfers between RustDrop instances.
+//!
+//! Every stream carries one request: a length-prefixed JSON header followed, for uploads, by
+//! the file contents. The reply is a header in the same framing followed, for downloads, by
+//! the file contents. Connections are encrypted with the server's device certificate, which the
+//! client pins to the fingerprint the server advertises at `/api/quic`.
+
+pub mod client;
+pub mod server;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::core::certificate::CertificateFiles;
+use crate::core::models::{FileAttributes, FileInfo};
+
+pub use client::QuicClient;
+pub use server::QuicServer;
+
+/// Where the certificate naming this device, which encrypts QUIC connections, is kept
+pub const DEVICE_CERTIFICATE: CertificateFiles = CertificateFiles {
+    cert: "device-cert.der",
+    key: "device-key.der",
+    names: "device-cert-names.json",
+};
+
+/// ALPN protocol id, so unrelated QUIC clients are turned away during the handshake
+pub const ALPN: &[u8] = b"rustdrop/1";
+
+/// Name the client asks for; certificates are checked by fingerprint, not by name
+const SERVER_NAME: &str = "rustdrop";
+
+const MAX_HEADER_SIZE: u32 = 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum QuicRequest {
+    List,
+    Download {
+        id: String,
+    },
+    /// Followed by the `size` bytes of file contents, minus the runs of `zeroes`
+    Upload {
+        path: String,
+        size: u64,
+        /// Modification time and permissions to give the stored file
+        #[serde(flatten)]
+        attributes: FileAttributes,
+        /// `(start, length)` runs of zeroes that are left out of the contents
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        zeroes: Vec<(u64, u64)>,
+    },
+}
+
+/// A request with the sender's client token, which must be approved when access codes are required
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuicEnvelope {
+    #[serde(default)]
+    pub client: Option<String>,
+    #[serde(flatten)]
+    pub request: QuicRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum QuicResponse {
+    Files { files: Vec<FileInfo> },
+    /// Followed by exactly `size` bytes of file contents
+    Content {
+        size: u64,
+        #[serde(default)]
+        modified: Option<DateTime<Utc>>,
+    },
+    Stored { file: FileInfo },
+    Error { message: String },
+}
+
+async fn write_frame<W, T>(writer: &mut W, value: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let data = serde_json::to_vec(value)?;
+    writer.write_u32(data.len() as u32).await?;
+    writer.write_all(&data).await?;
+    Ok(())
+}
+
+async fn read_frame<R, T>(reader: &mut R) -> Result<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let length = reader.read_u32().await?;
+    if length > MAX_HEADER_SIZE {
+        bail!("Frame of {} bytes is too large", length);
+    }
+
+    let mut data = vec![0u8; length as usize];
+    reader.read_exact(&mut data).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::certificate::SelfSignedCertificate;
+    use crate::core::models::DeviceInfo;
+    use crate::web::state::AppState;
+    use std::net::SocketAddr;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_frame_round_trip() {
+        let mut buffer = Vec::new();
+        let envelope = QuicEnvelope {
+            client: Some("token".to_string()),
+            request: QuicRequest::Download { id: "abc".to_string() },
+        };
+        write_frame(&mut buffer, &envelope).await.unwrap();
+
+        let envelope: QuicEnvelope = read_frame(&mut buffer.as_slice()).await.unwrap();
+        assert_eq!(envelope.client.as_deref(), Some("token"));
+        assert!(matches!(envelope.request, QuicRequest::Download { id } if id == "abc"));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_over_quic() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("hello.txt"), "hello").unwrap();
+
+        let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+        let certificate = SelfSignedCertificate::generate(vec!["server".to_string()]).unwrap();
+        let fingerprint = certificate.fingerprint();
+        let server = QuicServer::bind(SocketAddr::from(([127, 0, 0, 1], 0)), state, &certificate).unwrap();
+        let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().unwrap().port()));
+        tokio::spawn(server.run());
+
+        let client = QuicClient::connect(addr, &fingerprint, "token").await.unwrap();
+        let modified = "2020-02-02T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let attributes = FileAttributes { modified: Some(modified), ..Default::default() };
+        let stored = client.upload("docs/notes.txt", b"over quic".to_vec(), attributes).await.unwrap();
+        assert_eq!(stored.name, "docs/notes.txt");
+        assert_eq!(stored.modified, modified);
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("docs/notes.txt")).unwrap(),
+            b"over quic"
+        );
+
+        let files = client.list_files().await.unwrap();
+        let hello = files.iter().find(|f| f.name == "hello.txt").unwrap();
+        let download = client.download(&hello.id.to_string()).await.unwrap();
+        assert_eq!(download.data, b"hello");
+        assert_eq!(download.modified, Some(hello.modified));
+
+        // Runs of zeroes are left out of the stream and come back as zeroes
+        let mut image = vec![0u8; 3 * crate::utils::sparse::SPARSE_BLOCK];
+        image[5] = 7;
+        client.upload("disk.img", image.clone(), FileAttributes::default()).await.unwrap();
+        assert_eq!(std::fs::read(temp_dir.path().join("disk.img")).unwrap(), image);
+
+        // Paths outside the share are refused
+        assert!(client.upload("../escape.txt", b"x".to_vec(), FileAttributes::default()).await.is_err());
+
+        // A server presenting a different certificate is rejected
+        let impostor = SelfSignedCertificate::generate(vec!["server".to_string()]).unwrap().fingerprint();
+        assert!(QuicClient::connect(addr, &impostor, "token").await.is_err());
+    }
+}
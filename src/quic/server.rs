@@ -0,0 +1,206 @@
+use anyhow::{anyhow, bail, Context, Result};
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::{Endpoint, RecvStream, SendStream};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::core::certificate::SelfSignedCertificate;
+use crate::core::metadata::STATE_DIR;
+use crate::core::models::{FileAttributes, FileInfo};
+use crate::quic::{
+    read_frame, write_frame, QuicEnvelope, QuicRequest, QuicResponse, ALPN,
+};
+use crate::utils::file::{apply_attributes, get_share_file_info, resolve_relative_path, RestoreOptions};
+use crate::utils::sparse::data_ranges;
+use crate::web::error::ApiError;
+use crate::web::handlers::api::record_upload;
+use crate::web::state::AppState;
+
+/// QUIC listener serving the share of an `AppState` to other instances
+pub struct QuicServer {
+    endpoint: Endpoint,
+    state: AppState,
+}
+
+impl QuicServer {
+    pub fn bind(addr: SocketAddr, state: AppState, certificate: &SelfSignedCertificate) -> Result<Self> {
+        let mut crypto = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate.cert.clone()], certificate.private_key())
+        .context("Invalid device certificate")?;
+        crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+        let config = quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+        let endpoint = Endpoint::server(config, addr)
+            .with_context(|| format!("Failed to bind QUIC listener on {}", addr))?;
+
+        Ok(Self { endpoint, state })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// Accept connections until the endpoint is closed
+    pub async fn run(self) {
+        info!("QUIC transport listening on {:?}", self.endpoint.local_addr());
+
+        while let Some(incoming) = self.endpoint.accept().await {
+            let state = self.state.clone();
+            tokio::spawn(async move {
+                let connection = match incoming.await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        warn!("QUIC handshake failed: {}", e);
+                        return;
+                    }
+                };
+                let remote = connection.remote_address();
+
+                while let Ok((send, recv)) = connection.accept_bi().await {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_stream(&state, send, recv).await {
+                            warn!("QUIC request from {} failed: {}", remote, e);
+                        }
+                    });
+                }
+            });
+        }
+    }
+}
+
+async fn handle_stream(state: &AppState, mut send: SendStream, mut recv: RecvStream) -> Result<()> {
+    let envelope: QuicEnvelope = read_frame(&mut recv).await?;
+
+    let approved = envelope
+        .client
+        .as_deref()
+        .is_some_and(|client| !state.clients.is_revoked(client) && state.access.is_approved(client));
+    if !approved {
+        let message = "This device requires an access code, pair with `rustdrop pair` first".to_string();
+        write_frame(&mut send, &QuicResponse::Error { message }).await?;
+        send.finish()?;
+        return Ok(());
+    }
+
+    let result = match envelope.request {
+        QuicRequest::List => list(state).await.map(|files| QuicResponse::Files { files }),
+        QuicRequest::Download { id } => return download(state, &id, &mut send).await,
+        QuicRequest::Upload { path, size, attributes, zeroes } => {
+            upload(state, &path, size, attributes, &zeroes, &mut recv)
+        }
+            .await
+            .map(|file| QuicResponse::Stored { file }),
+    };
+
+    let response = result.unwrap_or_else(|e| QuicResponse::Error { message: e.to_string() });
+    write_frame(&mut send, &response).await?;
+    send.finish()?;
+    Ok(())
+}
+
+async fn list(state: &AppState) -> Result<Vec<FileInfo>> {
+    let mut files = state.list_share()?;
+    for file in files.iter_mut() {
+        state.metadata.annotate(file);
+    }
+    Ok(files)
+}
+
+async fn download(state: &AppState, id: &str, send: &mut SendStream) -> Result<()> {
+    let file = match state.find_file(id)? {
+        Some(file) => file,
+        None => {
+            write_frame(send, &QuicResponse::Error { message: format!("File not found: {}", id) }).await?;
+            send.finish()?;
+            return Ok(());
+        }
+    };
+
+    let mut content = tokio::fs::File::open(&file.path).await?;
+    let size = content.metadata().await?.len();
+    let modified = Some(file.modified);
+    write_frame(send, &QuicResponse::Content { size, modified }).await?;
+    tokio::io::copy(&mut content, send).await?;
+    send.finish()?;
+
+    info!("Sent {} over QUIC ({} bytes)", file.name, size);
+    Ok(())
+}
+
+async fn upload(
+    state: &AppState,
+    path: &str,
+    size: u64,
+    attributes: FileAttributes,
+    zeroes: &[(u64, u64)],
+    recv: &mut RecvStream,
+) -> Result<FileInfo> {
+    if state.snapshot.is_some() {
+        bail!("The share is a read-only snapshot");
+    }
+    if size > state.config.server.max_file_size {
+        bail!("File is larger than the {} byte limit", state.config.server.max_file_size);
+    }
+    let ranges = data_ranges(size, zeroes).ok_or_else(|| anyhow!("Invalid zero runs"))?;
+    let target = resolve_relative_path(&state.directory, path)
+        .ok_or_else(|| anyhow!("Invalid path: {}", path))?;
+    let parent = target.parent().ok_or_else(|| anyhow!("Invalid path: {}", path))?;
+    tokio::fs::create_dir_all(parent).await?;
+
+    // Receive into the state directory so a broken transfer never leaves a partial file in the share
+    let staging_dir = state.directory.join(STATE_DIR).join("uploads");
+    tokio::fs::create_dir_all(&staging_dir).await?;
+    let staging = staging_dir.join(format!("quic-{}.part", Uuid::new_v4()));
+
+    let received = async {
+        let mut file = tokio::fs::File::create(&staging).await?;
+        // Only the data between the zero runs arrives; skipping the runs leaves holes
+        for (start, end) in ranges {
+            file.seek(SeekFrom::Start(start)).await?;
+            let copied = tokio::io::copy(&mut (&mut *recv).take(end - start), &mut file).await?;
+            if copied != end - start {
+                bail!("Transfer ended after {} of {} bytes", start + copied, size);
+            }
+        }
+        file.set_len(size).await?;
+        file.sync_all().await?;
+        let replacing = tokio::fs::try_exists(&target).await.unwrap_or(false).then_some(target.as_path());
+        state.make_room(size, replacing.as_slice())?;
+        tokio::fs::rename(&staging, &target).await?;
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = received {
+        if let Err(cleanup) = tokio::fs::remove_file(&staging).await {
+            error!("Failed to remove {:?}: {}", staging, cleanup);
+        }
+        if let Some(io_error) = e.downcast_ref::<std::io::Error>() {
+            // Report disk and permission problems like HTTP uploads do
+            if let Some(message) = ApiError::storage(state, "receive QUIC upload", io_error).message {
+                bail!(message);
+            }
+        }
+        return Err(e);
+    }
+    state.storage.clear();
+
+    if let Err(e) = apply_attributes(&target, &attributes, RestoreOptions::from_config(&state.config.files)) {
+        warn!("Failed to restore attributes of {:?}: {}", target, e);
+    }
+
+    let mut file_info = get_share_file_info(&state.directory, &target)?;
+    record_upload(state, &mut file_info, None, None);
+
+    info!("Received {} over QUIC ({} bytes)", file_info.name, size);
+    Ok(file_info)
+}
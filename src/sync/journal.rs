@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::core::metadata::STATE_DIR;
+use crate::core::models::FileInfo;
+use crate::utils::file::sanitize_path_component;
+
+const SYNC_DIR: &str = "sync";
+
+/// Cheap identity of a file version: a file with the same size and modification time is assumed unchanged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+impl From<&FileInfo> for Fingerprint {
+    fn from(file: &FileInfo) -> Self {
+        Self {
+            size: file.size,
+            modified: file.modified,
+        }
+    }
+}
+
+/// State of a path on both sides right after it was last synced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub local: Fingerprint,
+    pub remote: Fingerprint,
+}
+
+/// Last synced state of one local directory / peer pair
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncJournal {
+    #[serde(skip)]
+    path: PathBuf,
+    pub peer: String,
+    pub last_sync: Option<DateTime<Utc>>,
+    /// Entries keyed by `/` separated path relative to the share
+    pub entries: BTreeMap<String, JournalEntry>,
+}
+
+impl SyncJournal {
+    /// Load the journal for syncing `directory` with `peer`, starting empty on the first sync
+    pub fn load(directory: &Path, peer: &str) -> Result<Self> {
+        let path = directory
+            .join(STATE_DIR)
+            .join(SYNC_DIR)
+            .join(format!("{}.json", sanitize_path_component(peer)));
+
+        let mut journal = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Corrupt sync journal {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self {
+                peer: peer.to_string(),
+                ..Default::default()
+            },
+            Err(e) => return Err(e).with_context(|| format!("Failed to read sync journal {:?}", path)),
+        };
+
+        journal.path = path;
+        Ok(journal)
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        self.last_sync = Some(Utc::now());
+        let content = serde_json::to_string_pretty(self)?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let fingerprint = Fingerprint { size: 3, modified: Utc::now() };
+
+        let mut journal = SyncJournal::load(temp_dir.path(), "http://laptop:8080").unwrap();
+        assert!(journal.entries.is_empty());
+        journal.entries.insert(
+            "docs/a.txt".to_string(),
+            JournalEntry { local: fingerprint, remote: fingerprint },
+        );
+        journal.save().unwrap();
+
+        let reloaded = SyncJournal::load(temp_dir.path(), "http://laptop:8080").unwrap();
+        assert_eq!(reloaded.entries["docs/a.txt"].local, fingerprint);
+        assert!(reloaded.last_sync.is_some());
+
+        // Each peer gets its own journal
+        let other = SyncJournal::load(temp_dir.path(), "http://phone:8080").unwrap();
+        assert!(other.entries.is_empty());
+    }
+}
@@ -0,0 +1,558 @@
+//! Two-way synchronization of a local directory with a peer's share
+
+pub mod journal;
+pub mod watch;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{info, instrument, warn};
+
+use crate::client::{Download, PeerClient};
+use crate::core::config::ConflictPolicy;
+use crate::core::models::{FileAttributes, FileInfo};
+use crate::utils::file::{
+    apply_attributes, attributes_of, get_share_file_info, list_share, resolve_relative_path, RestoreOptions,
+};
+use journal::{Fingerprint, JournalEntry, SyncJournal};
+
+/// One step needed to bring both sides in sync, paths are relative to the share
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncAction {
+    Upload(String),
+    Download(String),
+    DeleteLocal(String),
+    DeleteRemote(String),
+    /// The peer renamed the file, rename the local copy too
+    RenameLocal { from: String, to: String },
+    /// The file was renamed locally, rename it on the peer too
+    RenameRemote { from: String, to: String },
+    /// Both sides changed the file since the last sync
+    Conflict(String),
+    /// The file appeared on both sides since the last sync, contents must be compared
+    Compare(String),
+    /// The file is gone on both sides, only its journal entry is left
+    Forget(String),
+}
+
+impl SyncAction {
+    /// Whether the action only changes the peer (or the journal), as allowed when pushing
+    pub fn is_push(&self) -> bool {
+        matches!(
+            self,
+            Self::Upload(_) | Self::DeleteRemote(_) | Self::RenameRemote { .. } | Self::Forget(_)
+        )
+    }
+}
+
+impl std::fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Upload(path) => write!(f, "upload    {}", path),
+            Self::Download(path) => write!(f, "download  {}", path),
+            Self::DeleteLocal(path) => write!(f, "delete    {} (local)", path),
+            Self::DeleteRemote(path) => write!(f, "delete    {} (remote)", path),
+            Self::RenameLocal { from, to } => write!(f, "rename    {} -> {} (local)", from, to),
+            Self::RenameRemote { from, to } => write!(f, "rename    {} -> {} (remote)", from, to),
+            Self::Conflict(path) => write!(f, "conflict  {}", path),
+            Self::Compare(path) => write!(f, "compare   {}", path),
+            Self::Forget(path) => write!(f, "forget    {}", path),
+        }
+    }
+}
+
+/// Work out what to do from the last synced state and the current state of both sides
+pub fn plan_sync(
+    journal: &BTreeMap<String, JournalEntry>,
+    local: &BTreeMap<String, Fingerprint>,
+    remote: &BTreeMap<String, Fingerprint>,
+) -> Vec<SyncAction> {
+    let mut actions = Vec::new();
+    let mut handled: HashSet<&str> = HashSet::new();
+
+    // A journaled file missing on one side while an unknown file with the same fingerprint
+    // appeared there is a rename, provided the other side still has the old version
+    for (path, entry) in journal {
+        let new_path = |side: &BTreeMap<String, Fingerprint>, other: &BTreeMap<String, Fingerprint>, old: &Fingerprint| {
+            side.iter()
+                .find(|(candidate, fingerprint)| {
+                    *fingerprint == old
+                        && !journal.contains_key(*candidate)
+                        && !other.contains_key(*candidate)
+                        && !handled.contains(candidate.as_str())
+                })
+                .map(|(candidate, _)| candidate.clone())
+        };
+
+        if !local.contains_key(path) && remote.get(path) == Some(&entry.remote) {
+            if let Some(to) = new_path(local, remote, &entry.local) {
+                handled.insert(path);
+                handled.insert(local.get_key_value(&to).unwrap().0);
+                actions.push(SyncAction::RenameRemote { from: path.clone(), to });
+            }
+        } else if !remote.contains_key(path) && local.get(path) == Some(&entry.local) {
+            if let Some(to) = new_path(remote, local, &entry.remote) {
+                handled.insert(path);
+                handled.insert(remote.get_key_value(&to).unwrap().0);
+                actions.push(SyncAction::RenameLocal { from: path.clone(), to });
+            }
+        }
+    }
+
+    let paths: BTreeSet<&String> = journal.keys().chain(local.keys()).chain(remote.keys()).collect();
+    for path in paths {
+        if handled.contains(path.as_str()) {
+            continue;
+        }
+
+        let action = match (journal.get(path), local.get(path), remote.get(path)) {
+            (Some(entry), Some(l), Some(r)) => {
+                match (*l != entry.local, *r != entry.remote) {
+                    (false, false) => None,
+                    (true, false) => Some(SyncAction::Upload(path.clone())),
+                    (false, true) => Some(SyncAction::Download(path.clone())),
+                    (true, true) => Some(SyncAction::Conflict(path.clone())),
+                }
+            }
+            // Deleted on one side: propagate, unless the other side modified it meanwhile
+            (Some(entry), Some(l), None) => Some(if *l == entry.local {
+                SyncAction::DeleteLocal(path.clone())
+            } else {
+                SyncAction::Upload(path.clone())
+            }),
+            (Some(entry), None, Some(r)) => Some(if *r == entry.remote {
+                SyncAction::DeleteRemote(path.clone())
+            } else {
+                SyncAction::Download(path.clone())
+            }),
+            (Some(_), None, None) => Some(SyncAction::Forget(path.clone())),
+            (None, Some(_), Some(_)) => Some(SyncAction::Compare(path.clone())),
+            (None, Some(_), None) => Some(SyncAction::Upload(path.clone())),
+            (None, None, Some(_)) => Some(SyncAction::Download(path.clone())),
+            (None, None, None) => None,
+        };
+
+        actions.extend(action);
+    }
+
+    actions
+}
+
+/// How a single conflict gets settled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    KeepLocal,
+    KeepRemote,
+    KeepBoth,
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncOptions {
+    pub conflict_policy: ConflictPolicy,
+    /// Only push local changes, leaving changes made on the peer for a later full sync
+    pub push_only: bool,
+    /// Plan without changing anything
+    pub dry_run: bool,
+    /// Which attributes downloaded files take over from the peer. Extended attributes also
+    /// travel along with uploads when enabled.
+    pub restore: RestoreOptions,
+}
+
+/// Sync `directory` with the peer, returning the actions that were applied (or would be, in a dry run)
+#[instrument(name = "sync", skip_all, fields(peer = %client.base_url()))]
+pub async fn sync_directory(
+    client: &PeerClient,
+    directory: &Path,
+    options: SyncOptions,
+) -> Result<Vec<SyncAction>> {
+    let mut syncer = Syncer::new(client, directory, &options).await?;
+    let mut actions = plan_sync(&syncer.journal.entries, &syncer.local_fingerprints(), &syncer.remote_fingerprints());
+
+    if options.push_only {
+        actions.retain(SyncAction::is_push);
+    }
+    if options.dry_run {
+        return Ok(actions);
+    }
+
+    for action in &actions {
+        info!("Sync: {}", action);
+        if let Err(e) = syncer.apply(action).await {
+            // Keep what was already synced so the next run does not redo it
+            syncer.journal.save()?;
+            return Err(e.context(format!("Sync step failed: {}", action)));
+        }
+    }
+
+    syncer.journal.save()?;
+    Ok(actions)
+}
+
+struct Syncer<'a> {
+    client: &'a PeerClient,
+    directory: PathBuf,
+    policy: ConflictPolicy,
+    restore: RestoreOptions,
+    journal: SyncJournal,
+    local: BTreeMap<String, FileInfo>,
+    remote: BTreeMap<String, FileInfo>,
+}
+
+impl<'a> Syncer<'a> {
+    async fn new(client: &'a PeerClient, directory: &Path, options: &SyncOptions) -> Result<Self> {
+        let journal = SyncJournal::load(directory, client.base_url())?;
+        let local = list_share(directory)?
+            .into_iter()
+            .map(|file| (file.name.clone(), file))
+            .collect();
+        let remote = client
+            .list_files()
+            .await?
+            .into_iter()
+            .map(|file| (file.name.clone(), file))
+            .collect();
+
+        Ok(Self {
+            client,
+            directory: directory.to_path_buf(),
+            policy: options.conflict_policy,
+            restore: options.restore,
+            journal,
+            local,
+            remote,
+        })
+    }
+
+    fn local_fingerprints(&self) -> BTreeMap<String, Fingerprint> {
+        self.local.iter().map(|(path, file)| (path.clone(), file.into())).collect()
+    }
+
+    fn remote_fingerprints(&self) -> BTreeMap<String, Fingerprint> {
+        self.remote.iter().map(|(path, file)| (path.clone(), file.into())).collect()
+    }
+
+    /// Local path for a share-relative path, refusing anything a peer could use to escape the directory
+    fn local_path(&self, path: &str) -> Result<PathBuf> {
+        resolve_relative_path(&self.directory, path).ok_or_else(|| anyhow!("Refusing unsafe path from peer: {}", path))
+    }
+
+    fn remote_file(&self, path: &str) -> Result<&FileInfo> {
+        self.remote.get(path).ok_or_else(|| anyhow!("{} is missing on the peer", path))
+    }
+
+    fn local_file(&self, path: &str) -> Result<&FileInfo> {
+        self.local.get(path).ok_or_else(|| anyhow!("{} is missing locally", path))
+    }
+
+    async fn apply(&mut self, action: &SyncAction) -> Result<()> {
+        match action {
+            SyncAction::Upload(path) => self.upload(path, path).await,
+            SyncAction::Download(path) => {
+                let download = self.client.download(&self.remote_file(path)?.id.to_string()).await?;
+                self.store_download(path, &download).await
+            }
+            SyncAction::DeleteLocal(path) => {
+                std::fs::remove_file(self.local_path(path)?)?;
+                self.journal.entries.remove(path);
+                Ok(())
+            }
+            SyncAction::DeleteRemote(path) => {
+                self.client.delete(&self.remote_file(path)?.id.to_string()).await?;
+                self.journal.entries.remove(path);
+                Ok(())
+            }
+            SyncAction::RenameLocal { from, to } => {
+                let target = self.local_path(to)?;
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(self.local_path(from)?, &target)?;
+
+                let local = get_share_file_info(&self.directory, &target)?;
+                let entry = JournalEntry { local: (&local).into(), remote: self.remote_file(to)?.into() };
+                self.journal.entries.remove(from);
+                self.journal.entries.insert(to.clone(), entry);
+                Ok(())
+            }
+            SyncAction::RenameRemote { from, to } => {
+                self.client.rename(&self.remote_file(from)?.id.to_string(), to).await?;
+
+                // Renaming keeps size and modification time, so the peer's fingerprint carries over
+                let local = self.local_file(to)?;
+                let remote = self.remote_file(from)?;
+                let entry = JournalEntry { local: local.into(), remote: remote.into() };
+                self.journal.entries.remove(from);
+                self.journal.entries.insert(to.clone(), entry);
+                Ok(())
+            }
+            SyncAction::Conflict(path) => {
+                let download = self.client.download(&self.remote_file(path)?.id.to_string()).await?;
+                self.resolve_conflict(path, download).await
+            }
+            SyncAction::Compare(path) => {
+                let download = self.client.download(&self.remote_file(path)?.id.to_string()).await?;
+                let local_data = std::fs::read(self.local_path(path)?)?;
+
+                if download.data == local_data {
+                    let entry = JournalEntry {
+                        local: self.local_file(path)?.into(),
+                        remote: self.remote_file(path)?.into(),
+                    };
+                    self.journal.entries.insert(path.clone(), entry);
+                    Ok(())
+                } else {
+                    self.resolve_conflict(path, download).await
+                }
+            }
+            SyncAction::Forget(path) => {
+                self.journal.entries.remove(path);
+                Ok(())
+            }
+        }
+    }
+
+    async fn resolve_conflict(&mut self, path: &str, download: Download) -> Result<()> {
+        let local = self.local_file(path)?;
+        let remote = self.remote_file(path)?;
+
+        let resolution = match self.policy {
+            ConflictPolicy::Newest if remote.modified > local.modified => Resolution::KeepRemote,
+            ConflictPolicy::Newest => Resolution::KeepLocal,
+            ConflictPolicy::KeepBoth => Resolution::KeepBoth,
+            ConflictPolicy::Prompt => prompt_resolution(path, local, remote)?,
+        };
+        info!("Conflict on {} resolved: {:?}", path, resolution);
+
+        match resolution {
+            Resolution::KeepLocal => self.upload(path, path).await,
+            Resolution::KeepRemote => self.store_download(path, &download).await,
+            Resolution::KeepBoth => {
+                // The local version moves aside and travels to the peer under its new name
+                let copy = conflicted_copy_name(path, Utc::now().format("%Y-%m-%d").to_string());
+                let copy_path = self.local_path(&copy)?;
+                std::fs::rename(self.local_path(path)?, &copy_path)?;
+
+                let copy_info = get_share_file_info(&self.directory, &copy_path)?;
+                self.local.insert(copy.clone(), copy_info);
+                self.upload(&copy, &copy).await?;
+                self.store_download(path, &download).await
+            }
+            Resolution::Skip => {
+                warn!("Leaving conflict on {} unresolved", path);
+                Ok(())
+            }
+        }
+    }
+
+    /// Upload the local file at `path` to `remote_path` on the peer and record both versions
+    async fn upload(&mut self, path: &str, remote_path: &str) -> Result<()> {
+        let data = std::fs::read(self.local_path(path)?)?;
+        let attributes = attributes_of(self.local_file(path)?, self.restore.xattrs);
+        let uploaded = self.client.upload(remote_path, data, attributes).await?;
+        let entry = JournalEntry { local: self.local_file(path)?.into(), remote: (&uploaded).into() };
+        self.journal.entries.insert(remote_path.to_string(), entry);
+        self.remote.insert(remote_path.to_string(), uploaded);
+        Ok(())
+    }
+
+    async fn store_download(&mut self, path: &str, download: &Download) -> Result<()> {
+        let target = self.local_path(path)?;
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&target, &download.data).with_context(|| format!("Failed to write {:?}", target))?;
+        let remote = self.remote_file(path)?;
+        let xattrs = if self.restore.xattrs {
+            self.client.attributes(&remote.id.to_string()).await?.xattrs
+        } else {
+            BTreeMap::new()
+        };
+        let attributes = FileAttributes {
+            modified: download.modified,
+            mode: remote.mode,
+            xattrs,
+        };
+        if let Err(e) = apply_attributes(&target, &attributes, self.restore) {
+            warn!("Failed to restore attributes of {:?}: {}", target, e);
+        }
+
+        let local = get_share_file_info(&self.directory, &target)?;
+        let entry = JournalEntry { local: (&local).into(), remote: self.remote_file(path)?.into() };
+        self.journal.entries.insert(path.to_string(), entry);
+        self.local.insert(path.to_string(), local);
+        Ok(())
+    }
+}
+
+fn prompt_resolution(path: &str, local: &FileInfo, remote: &FileInfo) -> Result<Resolution> {
+    println!("Conflict: {} was changed on both sides", path);
+    println!("  local:  {} bytes, modified {}", local.size, local.modified);
+    println!("  remote: {} bytes, modified {}", remote.size, remote.modified);
+
+    loop {
+        print!("Keep [l]ocal, [r]emote, [b]oth or [s]kip? ");
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(Resolution::Skip);
+        }
+
+        match answer.trim().to_lowercase().as_str() {
+            "l" | "local" => return Ok(Resolution::KeepLocal),
+            "r" | "remote" => return Ok(Resolution::KeepRemote),
+            "b" | "both" => return Ok(Resolution::KeepBoth),
+            "s" | "skip" => return Ok(Resolution::Skip),
+            _ => continue,
+        }
+    }
+}
+
+/// `photos/a.jpg` becomes `photos/a (conflicted copy 2024-05-01).jpg`
+pub fn conflicted_copy_name(path: &str, label: String) -> String {
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, path),
+    };
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name, None),
+    };
+
+    let mut copy = format!("{} (conflicted copy {})", stem, label);
+    if let Some(extension) = extension {
+        copy.push('.');
+        copy.push_str(extension);
+    }
+
+    match dir {
+        Some(dir) => format!("{}/{}", dir, copy),
+        None => copy,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn fp(size: u64, minute: i64) -> Fingerprint {
+        Fingerprint {
+            size,
+            modified: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::minutes(minute),
+        }
+    }
+
+    fn map(entries: &[(&str, Fingerprint)]) -> BTreeMap<String, Fingerprint> {
+        entries.iter().map(|(path, fp)| (path.to_string(), *fp)).collect()
+    }
+
+    fn journal(entries: &[(&str, Fingerprint, Fingerprint)]) -> BTreeMap<String, JournalEntry> {
+        entries
+            .iter()
+            .map(|(path, local, remote)| (path.to_string(), JournalEntry { local: *local, remote: *remote }))
+            .collect()
+    }
+
+    #[test]
+    fn test_first_sync() {
+        let actions = plan_sync(
+            &BTreeMap::new(),
+            &map(&[("local.txt", fp(1, 0)), ("both.txt", fp(2, 0))]),
+            &map(&[("remote.txt", fp(3, 0)), ("both.txt", fp(2, 5))]),
+        );
+
+        assert_eq!(
+            actions,
+            vec![
+                SyncAction::Compare("both.txt".to_string()),
+                SyncAction::Upload("local.txt".to_string()),
+                SyncAction::Download("remote.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_changes_since_last_sync() {
+        let base = journal(&[
+            ("same.txt", fp(1, 0), fp(1, 1)),
+            ("edited-here.txt", fp(1, 0), fp(1, 1)),
+            ("edited-there.txt", fp(1, 0), fp(1, 1)),
+            ("edited-both.txt", fp(1, 0), fp(1, 1)),
+        ]);
+        let local = map(&[
+            ("same.txt", fp(1, 0)),
+            ("edited-here.txt", fp(2, 10)),
+            ("edited-there.txt", fp(1, 0)),
+            ("edited-both.txt", fp(2, 10)),
+        ]);
+        let remote = map(&[
+            ("same.txt", fp(1, 1)),
+            ("edited-here.txt", fp(1, 1)),
+            ("edited-there.txt", fp(3, 20)),
+            ("edited-both.txt", fp(3, 20)),
+        ]);
+
+        assert_eq!(
+            plan_sync(&base, &local, &remote),
+            vec![
+                SyncAction::Conflict("edited-both.txt".to_string()),
+                SyncAction::Upload("edited-here.txt".to_string()),
+                SyncAction::Download("edited-there.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deletions() {
+        let base = journal(&[
+            ("gone-here.txt", fp(1, 0), fp(1, 1)),
+            ("gone-there.txt", fp(1, 0), fp(1, 1)),
+            ("gone-both.txt", fp(1, 0), fp(1, 1)),
+            ("gone-there-edited-here.txt", fp(1, 0), fp(1, 1)),
+        ]);
+        let local = map(&[("gone-there.txt", fp(1, 0)), ("gone-there-edited-here.txt", fp(5, 30))]);
+        let remote = map(&[("gone-here.txt", fp(1, 1))]);
+
+        assert_eq!(
+            plan_sync(&base, &local, &remote),
+            vec![
+                SyncAction::Forget("gone-both.txt".to_string()),
+                SyncAction::DeleteRemote("gone-here.txt".to_string()),
+                SyncAction::Upload("gone-there-edited-here.txt".to_string()),
+                SyncAction::DeleteLocal("gone-there.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renames() {
+        let base = journal(&[
+            ("old-local.txt", fp(10, 0), fp(10, 1)),
+            ("old-remote.txt", fp(20, 0), fp(20, 1)),
+        ]);
+        let local = map(&[("new-local.txt", fp(10, 0)), ("old-remote.txt", fp(20, 0))]);
+        let remote = map(&[("old-local.txt", fp(10, 1)), ("archive/new-remote.txt", fp(20, 1))]);
+
+        assert_eq!(
+            plan_sync(&base, &local, &remote),
+            vec![
+                SyncAction::RenameRemote { from: "old-local.txt".to_string(), to: "new-local.txt".to_string() },
+                SyncAction::RenameLocal { from: "old-remote.txt".to_string(), to: "archive/new-remote.txt".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conflicted_copy_name() {
+        assert_eq!(conflicted_copy_name("a.jpg", "2024-05-01".to_string()), "a (conflicted copy 2024-05-01).jpg");
+        assert_eq!(
+            conflicted_copy_name("docs/notes", "2024-05-01".to_string()),
+            "docs/notes (conflicted copy 2024-05-01)"
+        );
+        assert_eq!(conflicted_copy_name(".env", "x".to_string()), ".env (conflicted copy x)");
+    }
+}
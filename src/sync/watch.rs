@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use notify::{Event, RecursiveMode, Watcher};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::client::PeerClient;
+use crate::core::metadata::STATE_DIR;
+use crate::sync::{sync_directory, SyncOptions};
+
+/// Quiet period after the last change before pushing, so a burst of writes becomes one sync
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Run a full sync, then keep pushing local changes to the peer until Ctrl+C
+pub async fn watch_and_push(client: &PeerClient, directory: &Path, options: SyncOptions) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let state_dir = directory.join(STATE_DIR);
+
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+        Ok(event) => {
+            // Our own journal writes must not trigger another sync
+            if event.paths.iter().any(|path| !path.starts_with(&state_dir)) {
+                let _ = tx.send(());
+            }
+        }
+        Err(e) => warn!("File watcher error: {}", e),
+    })
+    .context("Failed to create file watcher")?;
+    watcher
+        .watch(directory, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {:?}", directory))?;
+
+    report(sync_directory(client, directory, options).await);
+
+    let push = SyncOptions { push_only: true, ..options };
+    info!("Watching {:?} for changes", directory);
+
+    loop {
+        tokio::select! {
+            changed = rx.recv() => {
+                if changed.is_none() {
+                    break;
+                }
+                while let Ok(Some(())) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+                report(sync_directory(client, directory, push).await);
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Stopping watch mode");
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A failed pass (say the peer is briefly offline) is retried with the next change, not fatal
+fn report(result: Result<Vec<crate::sync::SyncAction>>) {
+    match result {
+        Ok(actions) => {
+            for action in actions {
+                println!("{}", action);
+            }
+        }
+        Err(e) => error!("Sync failed: {:#}", e),
+    }
+}
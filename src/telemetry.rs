@@ -0,0 +1,56 @@
+//! Export of traces to an OpenTelemetry collector over OTLP, configured with
+//! `telemetry.otlp_endpoint`. HTTP requests, discovery and transfers to peers run in spans, so
+//! they show up in the collector next to the log lines they produced.
+
+use anyhow::Result;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::core::config::TelemetryConfig;
+
+/// Tracing layer feeding the exporter
+pub type TelemetryLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Keeps the exporter running; dropping it sends the spans still buffered
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: opentelemetry_sdk::trace::TracerProvider,
+}
+
+/// Start exporting traces if an endpoint is configured. Must run inside the Tokio runtime.
+#[cfg(feature = "otlp")]
+pub fn init(config: &TelemetryConfig) -> Result<Option<(TelemetryLayer, Telemetry)>> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    let exporter = SpanExporter::builder().with_tonic().with_endpoint(endpoint).build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", config.service_name.clone())]))
+        .build();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("rustdrop"));
+    Ok(Some((Box::new(layer), Telemetry { provider })))
+}
+
+/// Without the `otlp` feature there is nothing to export with
+#[cfg(not(feature = "otlp"))]
+pub fn init(config: &TelemetryConfig) -> Result<Option<(TelemetryLayer, Telemetry)>> {
+    if config.otlp_endpoint.is_some() {
+        anyhow::bail!("telemetry.otlp_endpoint is set but this build lacks the `otlp` feature");
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "otlp")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+    }
+}
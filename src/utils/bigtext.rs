@@ -0,0 +1,66 @@
+//! Large block letters for the terminal, so an address can be read from across the room
+
+/// Rows of every glyph
+const HEIGHT: usize = 5;
+
+/// 3x5 glyphs of the characters addresses are made of. Hex letters are upper case, which
+/// reads better this small; short codes do not care about case.
+fn glyph(c: char) -> Option<[&'static str; HEIGHT]> {
+    let rows = match c.to_ascii_lowercase() {
+        '0' => ["###", "#.#", "#.#", "#.#", "###"],
+        '1' => [".#.", "##.", ".#.", ".#.", "###"],
+        '2' => ["###", "..#", "###", "#..", "###"],
+        '3' => ["###", "..#", ".##", "..#", "###"],
+        '4' => ["#.#", "#.#", "###", "..#", "..#"],
+        '5' => ["###", "#..", "###", "..#", "###"],
+        '6' => ["###", "#..", "###", "#.#", "###"],
+        '7' => ["###", "..#", "..#", ".#.", ".#."],
+        '8' => ["###", "#.#", "###", "#.#", "###"],
+        '9' => ["###", "#.#", "###", "..#", "###"],
+        'a' => [".#.", "#.#", "###", "#.#", "#.#"],
+        'b' => ["##.", "#.#", "##.", "#.#", "##."],
+        'c' => ["###", "#..", "#..", "#..", "###"],
+        'd' => ["##.", "#.#", "#.#", "#.#", "##."],
+        'e' => ["###", "#..", "##.", "#..", "###"],
+        'f' => ["###", "#..", "##.", "#..", "#.."],
+        'j' => ["..#", "...", "..#", "#.#", ".#."],
+        '.' => ["...", "...", "...", "...", ".#."],
+        ':' => ["...", ".#.", "...", ".#.", "..."],
+        '/' => ["..#", "..#", ".#.", "#..", "#.."],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// `text` in block letters, or None when it has a character without a glyph
+pub fn render_big(text: &str) -> Option<String> {
+    let glyphs = text.chars().map(glyph).collect::<Option<Vec<_>>>()?;
+
+    let mut output = String::new();
+    for row in 0..HEIGHT {
+        let line = glyphs
+            .iter()
+            .map(|glyph| glyph[row].replace('#', "█").replace('.', " "))
+            .collect::<Vec<_>>()
+            .join(" ");
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_big() {
+        let big = render_big("1.7").unwrap();
+        let lines: Vec<&str> = big.lines().collect();
+        assert_eq!(lines.len(), HEIGHT);
+        assert_eq!(lines[0], " █      ███");
+        assert_eq!(lines[4], "███  █   █");
+
+        assert!(render_big("[fd00::1]").is_none());
+    }
+}
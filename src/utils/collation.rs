@@ -0,0 +1,150 @@
+//! Orders for file listings besides the byte-wise one, so `File10` no longer comes before
+//! `file2` just because of its capital letter and its digits
+
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+use crate::core::models::FileInfo;
+
+/// How names are compared
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Collation {
+    /// Byte by byte, capitals before small letters
+    #[default]
+    Binary,
+    /// Ignoring case
+    Nocase,
+    /// Ignoring case, with numbers compared by value: `file2` before `file10`
+    Natural,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Compare two names. Names equal under the collation fall back to the byte-wise order, so
+/// the result never depends on the order files were found in.
+pub fn compare_names(a: &str, b: &str, collation: Collation) -> Ordering {
+    let ordering = match collation {
+        Collation::Binary => Ordering::Equal,
+        Collation::Nocase => a.chars().flat_map(char::to_lowercase).cmp(b.chars().flat_map(char::to_lowercase)),
+        Collation::Natural => natural(a, b),
+    };
+    ordering.then_with(|| a.cmp(b))
+}
+
+/// Sort `files` by `key`, files with equal sizes or times by name
+pub fn sort_files(files: &mut [FileInfo], key: SortKey, collation: Collation, order: SortOrder) {
+    files.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        }
+        .then_with(|| compare_names(&a.name, &b.name, collation));
+        match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    });
+}
+
+/// Case-insensitive comparison treating runs of digits as numbers
+fn natural(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                let ordering = compare_numbers(&x, &y);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits
+}
+
+/// Compare digit strings by value without parsing, so any length works; `007` comes after `7`
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a_value = a.trim_start_matches('0');
+    let b_value = b.trim_start_matches('0');
+    a_value
+        .len()
+        .cmp(&b_value.len())
+        .then_with(|| a_value.cmp(b_value))
+        .then_with(|| a.len().cmp(&b.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str], collation: Collation) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| compare_names(a, b, collation));
+        names
+    }
+
+    #[test]
+    fn test_collations() {
+        let names = ["file10.txt", "File2.txt", "file1.txt", "apple.txt", "Zebra.txt"];
+        assert_eq!(
+            sorted(&names, Collation::Binary),
+            ["File2.txt", "Zebra.txt", "apple.txt", "file1.txt", "file10.txt"]
+        );
+        assert_eq!(
+            sorted(&names, Collation::Nocase),
+            ["apple.txt", "file1.txt", "file10.txt", "File2.txt", "Zebra.txt"]
+        );
+        assert_eq!(
+            sorted(&names, Collation::Natural),
+            ["apple.txt", "file1.txt", "File2.txt", "file10.txt", "Zebra.txt"]
+        );
+    }
+
+    #[test]
+    fn test_natural_numbers() {
+        assert_eq!(
+            sorted(&["img007.jpg", "img7.jpg", "img70.jpg", "img99999999999999999999.jpg", "img8.jpg"], Collation::Natural),
+            ["img7.jpg", "img007.jpg", "img8.jpg", "img70.jpg", "img99999999999999999999.jpg"]
+        );
+        assert_eq!(compare_names("a.txt", "A.txt", Collation::Natural), Ordering::Greater);
+        assert_eq!(compare_names("Ärger", "ärger", Collation::Nocase), Ordering::Less);
+    }
+}
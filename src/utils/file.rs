@@ -3,19 +3,23 @@ use chrono::{DateTime, Utc};
 use humansize::{format_size, BINARY};
 use mime_guess::from_path;
 use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
 use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
 use uuid::Uuid;
 
-use crate::core::models::FileInfo;
+use crate::core::config::FilesConfig;
+use crate::core::metadata::STATE_DIR;
+use crate::core::models::{FileAttributes, FileInfo};
+use crate::utils::filename::{host_name, long_path, nfc, portable_name};
+use crate::utils::xattr::{read_xattrs, write_xattrs};
 
-pub fn get_file_info(path: &Path) -> Result<FileInfo> {
-    let metadata = std::fs::metadata(path)?;
-    let name = path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("unknown")
-        .to_string();
-    
+/// Directory inside the share that collects uploads sorted by sender
+pub const INBOX_DIR: &str = "inbox";
+
+/// Deterministic id of the file at `path`, derived from the path alone
+pub fn file_id(path: &Path) -> Uuid {
     // Generate deterministic UUID based on file path
     let mut hasher = DefaultHasher::new();
     path.to_string_lossy().hash(&mut hasher);
@@ -34,11 +38,25 @@ pub fn get_file_info(path: &Path) -> Result<FileInfo> {
         0, 0, 0, 0, 0, 0, 0, 0, // Pad to 16 bytes
     ];
     
-    let id = Uuid::from_bytes(uuid_bytes);
+    Uuid::from_bytes(uuid_bytes)
+}
+
+pub fn get_file_info(path: &Path) -> Result<FileInfo> {
+    let metadata = std::fs::metadata(path)?;
+    // Names that are not valid UTF-8 are shown with replacement characters; the id still finds them
+    let name = path.file_name()
+        .map(|n| nfc(&n.to_string_lossy()))
+        .unwrap_or_else(|| "unknown".to_string());
+    
+    let id = file_id(path);
     let size = metadata.len();
     let size_human = format_size(size, BINARY);
     let modified = DateTime::<Utc>::from(metadata.modified()?);
     let mime_type = from_path(path).first_or_octet_stream().to_string();
+    #[cfg(unix)]
+    let mode = Some(std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o777);
+    #[cfg(not(unix))]
+    let mode = None;
     
     Ok(FileInfo {
         id,
@@ -48,6 +66,11 @@ pub fn get_file_info(path: &Path) -> Result<FileInfo> {
         modified,
         mime_type,
         path: path.to_path_buf(),
+        tags: Vec::new(),
+        pinned: false,
+        sender: None,
+        published: false,
+        mode,
     })
 }
 
@@ -75,6 +98,297 @@ pub fn list_directory(dir: &Path) -> Result<Vec<FileInfo>> {
     Ok(files)
 }
 
+/// Like [`get_file_info`], but names the file by its `/` separated path relative to the share
+pub fn get_share_file_info(share: &Path, path: &Path) -> Result<FileInfo> {
+    let mut file_info = get_file_info(path)?;
+    if let Ok(relative) = path.strip_prefix(share) {
+        file_info.name = relative
+            .components()
+            .map(|c| nfc(&c.as_os_str().to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join("/");
+    }
+    Ok(file_info)
+}
+
+/// Ending of the hidden files uploads are received into, next to where they go
+pub const PARTIAL_UPLOAD_SUFFIX: &str = ".rustdrop-upload";
+
+/// A file being received. It is deleted when dropped before `keep` is called, so an upload cut
+/// short by an error or by the client going away leaves no half-written file in the share.
+pub struct PartialFile {
+    path: Option<PathBuf>,
+}
+
+impl PartialFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path: Some(path) }
+    }
+
+    /// The file is complete, leave it in place
+    pub fn keep(mut self) {
+        self.path = None;
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        let Some(path) = self.path.take() else {
+            return;
+        };
+        match std::fs::remove_file(&path) {
+            Ok(()) => debug!("Removed partial upload {:?}", path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove partial upload {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Give a received file the modification time it had on the sending device
+pub fn set_modified(path: &Path, modified: DateTime<Utc>) -> std::io::Result<()> {
+    let mtime = filetime::FileTime::from_unix_time(modified.timestamp(), modified.timestamp_subsec_nanos());
+    filetime::set_file_mtime(path, mtime)
+}
+
+/// Give a received file the attributes it had on the sending device. Permission bits are only
+/// restored with `restore_mode`, and never include setuid, setgid or sticky bits.
+pub fn apply_attributes(path: &Path, attributes: &FileAttributes, restore: RestoreOptions) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = attributes.mode.filter(|_| restore.permissions) {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    if restore.xattrs {
+        write_xattrs(path, &attributes.xattrs)?;
+    }
+
+    if let Some(modified) = attributes.modified {
+        set_modified(path, modified)?;
+    }
+    Ok(())
+}
+
+/// The attributes of `file` to send along with it, including its extended attributes if asked
+pub fn attributes_of(file: &FileInfo, with_xattrs: bool) -> FileAttributes {
+    let mut attributes = FileAttributes::of(file);
+    if with_xattrs {
+        match read_xattrs(&file.path) {
+            Ok(xattrs) => attributes.xattrs = xattrs,
+            Err(e) => warn!("Failed to read extended attributes of {:?}: {}", file.path, e),
+        }
+    }
+    attributes
+}
+
+/// Which optional attributes of incoming files to restore
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RestoreOptions {
+    /// Unix permission bits
+    pub permissions: bool,
+    /// Extended attributes
+    pub xattrs: bool,
+}
+
+impl RestoreOptions {
+    pub fn from_config(files: &FilesConfig) -> Self {
+        Self {
+            permissions: files.preserve_permissions,
+            xattrs: files.preserve_xattrs,
+        }
+    }
+}
+
+/// Which parts of the share a listing may reach
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    /// Follow every symlink, wherever it points
+    pub follow_symlinks: bool,
+    /// Directories outside the share that symlinks may point into
+    pub allowed_symlink_targets: Vec<PathBuf>,
+}
+
+impl ListOptions {
+    pub fn from_config(files: &FilesConfig) -> Self {
+        Self {
+            follow_symlinks: files.follow_symlinks,
+            allowed_symlink_targets: files.allowed_symlink_targets.clone(),
+        }
+    }
+}
+
+/// List every file in the share, including subdirectories but not RustDrop's state directory.
+///
+/// Symlinks are only followed when they stay inside the share; see [`list_share_with`].
+pub fn list_share(share: &Path) -> Result<Vec<FileInfo>> {
+    list_share_with(share, &ListOptions::default())
+}
+
+/// Like [`list_share`], following symlinks as `options` allow.
+///
+/// Links are judged by where they really point, so a link to `/etc` is skipped unless
+/// following all links or `/etc` is an allowed target. Dangling links are skipped.
+pub fn list_share_with(share: &Path, options: &ListOptions) -> Result<Vec<FileInfo>> {
+    let mut files = Vec::new();
+
+    if !share.exists() {
+        return Ok(files);
+    }
+
+    let real_share = share.canonicalize()?;
+    let allowed: Vec<PathBuf> = options
+        .allowed_symlink_targets
+        .iter()
+        .filter_map(|target| target.canonicalize().ok())
+        .collect();
+    let link_allowed = |target: &Path| {
+        options.follow_symlinks
+            || target.starts_with(&real_share)
+            || allowed.iter().any(|allowed| target.starts_with(allowed))
+    };
+
+    // Real paths of the directories already listed, so links cannot send us in circles
+    let mut visited = HashSet::from([real_share.clone()]);
+    let mut pending = vec![share.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let mut file_type = entry.file_type()?;
+
+            if file_type.is_symlink() {
+                let Ok(target) = path.canonicalize() else {
+                    continue;
+                };
+                if !link_allowed(&target) {
+                    debug!("Skipping symlink {:?} pointing outside the share to {:?}", path, target);
+                    continue;
+                }
+                file_type = std::fs::metadata(&target)?.file_type();
+            }
+
+            if file_type.is_dir() {
+                if dir == share && entry.file_name() == STATE_DIR {
+                    continue;
+                }
+                if visited.insert(path.canonicalize()?) {
+                    pending.push(path);
+                }
+            } else if file_type.is_file() {
+                if let Ok(file_info) = get_share_file_info(share, &path) {
+                    files.push(file_info);
+                }
+            }
+        }
+    }
+
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(files)
+}
+
+/// Find a file in the share by its (path derived) ID
+pub fn find_file_by_id(dir: &Path, id: &str) -> Result<Option<FileInfo>> {
+    find_file_by_id_with(dir, id, &ListOptions::default())
+}
+
+/// Like [`find_file_by_id`], reaching only the files [`list_share_with`] lists
+pub fn find_file_by_id_with(dir: &Path, id: &str, options: &ListOptions) -> Result<Option<FileInfo>> {
+    let files = list_share_with(dir, options)?;
+    Ok(files.into_iter().find(|f| f.id.to_string() == id))
+}
+
+/// Files operating systems leave behind that nobody means to share
+const SYSTEM_FILES: &[&str] = &["Thumbs.db", "desktop.ini", ".DS_Store"];
+
+/// Whether a share relative name is a dotfile, lies inside a dot directory, or is a known
+/// system file
+pub fn is_hidden(name: &str) -> bool {
+    name.split('/').any(|component| {
+        component.starts_with('.')
+            || SYSTEM_FILES.iter().any(|system| system.eq_ignore_ascii_case(component))
+    })
+}
+
+/// Resolve a client supplied relative path inside `base`.
+///
+/// Returns `None` for anything that could escape the share (absolute paths, `..`)
+/// or that points into RustDrop's own state directory.
+pub fn resolve_relative_path(base: &Path, relative: &str) -> Option<PathBuf> {
+    let relative = relative.replace('\\', "/");
+    if relative.starts_with('/') || relative.contains('\0') {
+        return None;
+    }
+
+    let components: Vec<&str> = relative
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+
+    if components.is_empty() || components[0] == STATE_DIR {
+        return None;
+    }
+
+    // Reject parent references and Windows drive prefixes like "C:"
+    if components.iter().any(|c| *c == ".." || c.contains(':')) {
+        return None;
+    }
+
+    // Store names the host file system would reject or mangle under a name it accepts
+    let components: Vec<String> = components.iter().map(|c| host_name(c)).collect();
+    if components.iter().any(|c| c.is_empty()) {
+        return None;
+    }
+
+    let path = components.iter().fold(base.to_path_buf(), |path, c| path.join(c));
+    if leaves_share(base, &components) {
+        return None;
+    }
+    Some(long_path(path))
+}
+
+/// Whether an existing part of `base/components...` is a symlink to somewhere outside `base`,
+/// which would let writes land outside the share
+fn leaves_share(base: &Path, components: &[String]) -> bool {
+    let Ok(real_base) = base.canonicalize() else {
+        return false;
+    };
+
+    let mut current = base.to_path_buf();
+    for component in components {
+        current.push(component);
+        match std::fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => match current.canonicalize() {
+                Ok(target) if target.starts_with(&real_base) => {}
+                // Pointing outside, or dangling and so could be created anywhere
+                _ => return true,
+            },
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    false
+}
+
+/// Turn arbitrary text (like a sender name) into a single safe path component
+pub fn sanitize_path_component(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Leading dots would hide the directory (or form "..")
+    let sanitized = sanitized.trim_matches(|c: char| c == '.' || c.is_whitespace());
+
+    if sanitized.is_empty() {
+        "unknown".to_string()
+    } else {
+        portable_name(sanitized)
+    }
+}
+
 pub fn format_file_size(size: u64) -> String {
     format_size(size, BINARY)
 }
@@ -86,6 +400,21 @@ mod tests {
     use std::io::Write;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_partial_file_removed_unless_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let dropped = temp_dir.path().join("dropped.bin");
+        let kept = temp_dir.path().join("kept.bin");
+        std::fs::write(&dropped, b"half").unwrap();
+        std::fs::write(&kept, b"whole").unwrap();
+
+        drop(PartialFile::new(dropped.clone()));
+        PartialFile::new(kept.clone()).keep();
+
+        assert!(!dropped.exists());
+        assert!(kept.exists());
+    }
+
     #[test]
     fn test_get_file_info_basic() {
         let temp_dir = TempDir::new().unwrap();
@@ -234,6 +563,182 @@ mod tests {
         assert_eq!(files[0].name, "file.txt");
     }
 
+    #[test]
+    fn test_list_share_includes_subdirectories() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("top.txt"), "top").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("inbox/alex")).unwrap();
+        std::fs::write(temp_dir.path().join("inbox/alex/photo.jpg"), "photo").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join(STATE_DIR)).unwrap();
+        std::fs::write(temp_dir.path().join(STATE_DIR).join("metadata.json"), "{}").unwrap();
+
+        let files = list_share(temp_dir.path()).unwrap();
+        let names: Vec<_> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["inbox/alex/photo.jpg", "top.txt"]);
+
+        // IDs match the ones derived from the full path
+        let nested = get_file_info(&temp_dir.path().join("inbox/alex/photo.jpg")).unwrap();
+        assert_eq!(files[0].id, nested.id);
+    }
+
+    #[test]
+    fn test_set_modified() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("old.txt");
+        std::fs::write(&path, "old").unwrap();
+
+        let modified = DateTime::parse_from_rfc3339("2019-06-01T12:30:00.250Z").unwrap().to_utc();
+        set_modified(&path, modified).unwrap();
+        assert_eq!(get_file_info(&path).unwrap().modified, modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_attributes_restores_mode_on_request() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("build.sh");
+        std::fs::write(&path, "#!/bin/sh").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let attributes = FileAttributes { mode: Some(0o4755), ..Default::default() };
+
+        apply_attributes(&path, &attributes, RestoreOptions::default()).unwrap();
+        assert_eq!(get_file_info(&path).unwrap().mode, Some(0o644));
+
+        let restore = RestoreOptions { permissions: true, ..Default::default() };
+        apply_attributes(&path, &attributes, restore).unwrap();
+        assert_eq!(get_file_info(&path).unwrap().mode, Some(0o755));
+    }
+
+    #[test]
+    fn test_is_hidden() {
+        assert!(is_hidden(".env"));
+        assert!(is_hidden(".git/config"));
+        assert!(is_hidden("photos/Thumbs.db"));
+        assert!(is_hidden("photos/.DS_Store"));
+        assert!(is_hidden("DESKTOP.INI"));
+        assert!(!is_hidden("photos/beach.jpg"));
+        assert!(!is_hidden("notes.txt"));
+    }
+
+    #[test]
+    fn test_sanitize_path_component() {
+        assert_eq!(sanitize_path_component("Alex's iPhone"), "Alex's iPhone");
+        assert_eq!(sanitize_path_component("../etc/passwd"), "_etc_passwd");
+        assert_eq!(sanitize_path_component("a:b*c?"), "a_b_c_");
+        assert_eq!(sanitize_path_component(".."), "unknown");
+        assert_eq!(sanitize_path_component("  "), "unknown");
+        assert_eq!(sanitize_path_component("con"), "con_");
+    }
+
+    #[test]
+    fn test_find_file_by_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("findme.txt");
+        std::fs::write(&file_path, "content").unwrap();
+
+        let info = get_file_info(&file_path).unwrap();
+        let found = find_file_by_id(temp_dir.path(), &info.id.to_string()).unwrap();
+        assert_eq!(found.unwrap().name, "findme.txt");
+
+        let missing = find_file_by_id(temp_dir.path(), &Uuid::nil().to_string()).unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_share_symlink_policy() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        let share = temp_dir.path().join("share");
+        let outside = temp_dir.path().join("outside");
+        std::fs::create_dir_all(share.join("docs")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(share.join("docs/a.txt"), "a").unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+
+        symlink(share.join("docs/a.txt"), share.join("inside-link.txt")).unwrap();
+        symlink(outside.join("secret.txt"), share.join("secret-link.txt")).unwrap();
+        symlink(&outside, share.join("outside-dir")).unwrap();
+        symlink(&share, share.join("docs/loop")).unwrap();
+        symlink(temp_dir.path().join("missing"), share.join("dangling")).unwrap();
+
+        let names = |options: &ListOptions| -> Vec<String> {
+            list_share_with(&share, options).unwrap().into_iter().map(|f| f.name).collect()
+        };
+
+        assert_eq!(names(&ListOptions::default()), vec!["docs/a.txt", "inside-link.txt"]);
+
+        let allowed = ListOptions {
+            allowed_symlink_targets: vec![outside.clone()],
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&allowed),
+            vec!["docs/a.txt", "inside-link.txt", "outside-dir/secret.txt", "secret-link.txt"]
+        );
+
+        let follow = ListOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        assert_eq!(names(&follow), names(&allowed));
+
+        // Nothing outside the share can be found by id either
+        let secret = get_file_info(&share.join("secret-link.txt")).unwrap();
+        assert!(find_file_by_id(&share, &secret.id.to_string()).unwrap().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_relative_path_rejects_escaping_links() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        let share = temp_dir.path().join("share");
+        std::fs::create_dir_all(share.join("docs")).unwrap();
+        symlink(temp_dir.path(), share.join("up")).unwrap();
+        symlink(share.join("docs"), share.join("docs-link")).unwrap();
+
+        assert!(resolve_relative_path(&share, "up/evil.txt").is_none());
+        assert!(resolve_relative_path(&share, "docs-link/fine.txt").is_some());
+        assert!(resolve_relative_path(&share, "new/dir/file.txt").is_some());
+    }
+
+    #[test]
+    fn test_resolve_relative_path() {
+        let base = Path::new("/share");
+
+        assert_eq!(resolve_relative_path(base, "a.txt"), Some(PathBuf::from("/share/a.txt")));
+        assert_eq!(
+            resolve_relative_path(base, "photos/2024/a.jpg"),
+            Some(PathBuf::from("/share/photos/2024/a.jpg"))
+        );
+        assert_eq!(
+            resolve_relative_path(base, "./docs//b.txt"),
+            Some(PathBuf::from("/share/docs/b.txt"))
+        );
+        assert_eq!(
+            resolve_relative_path(base, "docs\\c.txt"),
+            Some(PathBuf::from("/share/docs/c.txt"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_relative_path_rejects_escapes() {
+        let base = Path::new("/share");
+
+        assert_eq!(resolve_relative_path(base, ""), None);
+        assert_eq!(resolve_relative_path(base, "/etc/passwd"), None);
+        assert_eq!(resolve_relative_path(base, "../secret"), None);
+        assert_eq!(resolve_relative_path(base, "docs/../../secret"), None);
+        assert_eq!(resolve_relative_path(base, "C:/Windows"), None);
+        assert_eq!(resolve_relative_path(base, ".rustdrop/metadata.json"), None);
+        assert_eq!(resolve_relative_path(base, "./"), None);
+    }
+
     #[test]
     fn test_large_file_size_formatting() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,163 @@
+use icu_normalizer::ComposingNormalizerBorrowed;
+use std::path::{Path, PathBuf};
+
+/// Device names Windows reserves in every directory, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest path the classic Windows APIs accept without the `\\?\` prefix
+const MAX_PATH: usize = 260;
+
+/// A file name must be a single path component
+pub fn valid_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', ':'])
+        && !name.chars().any(|c| c.is_control())
+}
+
+/// Rewrite a single path component so Windows can store it: characters it forbids become `_`,
+/// trailing dots and spaces (which Windows silently drops) are removed, and reserved device
+/// names like `CON.txt` get a `_` appended to their stem.
+pub fn portable_name(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let mut name = replaced.trim_end_matches(['.', ' ']).to_string();
+
+    let stem_len = name.find('.').unwrap_or(name.len());
+    let stem = name[..stem_len].trim_end();
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+        name.insert(stem_len, '_');
+    }
+    name
+}
+
+/// `name` in Unicode normalization form C. macOS hands out decomposed names (`e` followed by a
+/// combining accent), so without this the same name could end up as two different files.
+pub fn nfc(name: &str) -> String {
+    ComposingNormalizerBorrowed::new_nfc().normalize(name).into_owned()
+}
+
+/// How this host stores an incoming name: always in NFC, and made [`portable_name`] on Windows
+pub fn host_name(name: &str) -> String {
+    let name = nfc(name);
+    if cfg!(windows) {
+        portable_name(&name)
+    } else {
+        name
+    }
+}
+
+/// `path`, or when a file already exists there the first free `name (2).ext`, `name (3).ext`, ...
+pub fn unused_path(path: PathBuf) -> PathBuf {
+    unused_path_by(path, |candidate| candidate.exists())
+}
+
+/// Like [`unused_path`], with `taken` deciding which paths are in use
+pub fn unused_path_by(path: PathBuf, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    if !taken(&path) {
+        return path;
+    }
+
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy()));
+    (2..)
+        .map(|copy| path.with_file_name(format!("{} ({}){}", stem, copy, extension.as_deref().unwrap_or(""))))
+        .find(|candidate| !taken(candidate))
+        .expect("some copy number is free")
+}
+
+/// On Windows, give paths too long for the classic APIs the `\\?\` prefix lifting the limit.
+/// Other platforms get the path back unchanged.
+pub fn long_path(path: PathBuf) -> PathBuf {
+    if !cfg!(windows) {
+        return path;
+    }
+
+    let absolute = std::path::absolute(&path).unwrap_or(path);
+    match absolute.to_str() {
+        Some(text) => PathBuf::from(with_long_path_prefix(text)),
+        None => absolute,
+    }
+}
+
+fn with_long_path_prefix(path: &str) -> String {
+    if path.len() < MAX_PATH || path.starts_with(r"\\?\") {
+        path.to_string()
+    } else if let Some(unc) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", unc)
+    } else {
+        format!(r"\\?\{}", path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unused_path() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("scan.pdf");
+        assert_eq!(unused_path(path.clone()), path);
+
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(unused_path(path.clone()), temp_dir.path().join("scan (2).pdf"));
+        std::fs::write(temp_dir.path().join("scan (2).pdf"), "").unwrap();
+        assert_eq!(unused_path(path), temp_dir.path().join("scan (3).pdf"));
+
+        std::fs::write(temp_dir.path().join("README"), "").unwrap();
+        assert_eq!(unused_path(temp_dir.path().join("README")), temp_dir.path().join("README (2)"));
+    }
+
+    #[test]
+    fn test_valid_file_name() {
+        assert!(valid_file_name("holiday photo.jpg"));
+        assert!(!valid_file_name(""));
+        assert!(!valid_file_name(".."));
+        assert!(!valid_file_name("../etc/passwd"));
+        assert!(!valid_file_name("a\\b.txt"));
+        assert!(!valid_file_name("line\nbreak"));
+    }
+
+    #[test]
+    fn test_portable_name() {
+        assert_eq!(portable_name("report.pdf"), "report.pdf");
+        assert_eq!(portable_name("CON"), "CON_");
+        assert_eq!(portable_name("nul.txt"), "nul_.txt");
+        assert_eq!(portable_name("com1.tar.gz"), "com1_.tar.gz");
+        assert_eq!(portable_name("console.txt"), "console.txt");
+        assert_eq!(portable_name("notes. . "), "notes");
+        assert_eq!(portable_name("what?<now>.txt"), "what__now_.txt");
+    }
+
+    #[test]
+    fn test_names_normalized_to_nfc() {
+        let decomposed = "Cafe\u{301}.txt";
+        assert_eq!(nfc(decomposed), "Caf\u{e9}.txt");
+        assert_eq!(host_name(decomposed), "Caf\u{e9}.txt");
+        assert_eq!(nfc("写真.jpg"), "写真.jpg");
+    }
+
+    #[test]
+    fn test_long_path_prefix() {
+        let short = r"C:\share\photo.jpg";
+        assert_eq!(with_long_path_prefix(short), short);
+
+        let long = format!(r"C:\share\{}", "a".repeat(300));
+        assert_eq!(with_long_path_prefix(&long), format!(r"\\?\{}", long));
+        assert_eq!(with_long_path_prefix(&format!(r"\\?\{}", long)), format!(r"\\?\{}", long));
+
+        let unc = format!(r"\\nas\share\{}", "a".repeat(300));
+        assert_eq!(with_long_path_prefix(&unc), format!(r"\\?\UNC\nas\share\{}", "a".repeat(300)));
+    }
+}
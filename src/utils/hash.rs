@@ -0,0 +1,176 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use tracing::warn;
+
+use crate::core::models::FileInfo;
+
+/// Compute the SHA-256 of a file's contents as a lowercase hex string
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hex(&hasher.finalize()))
+}
+
+/// The SHA-256 of `data` as a lowercase hex string
+pub fn hash_bytes(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A `SHA256SUMS` manifest of `files`, sorted by name, that `sha256sum -c` can check from the
+/// directory the names are relative to
+pub fn sha256_manifest(files: &[FileInfo]) -> std::io::Result<String> {
+    let mut files: Vec<&FileInfo> = files.iter().collect();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut manifest = String::new();
+    for file in files {
+        let hash = hash_file(&file.path)?;
+        // Like coreutils, names with a backslash or newline are escaped and the line marked with `\`
+        if file.name.contains(['\\', '\n']) {
+            let name = file.name.replace('\\', "\\\\").replace('\n', "\\n");
+            manifest.push_str(&format!("\\{}  {}\n", hash, name));
+        } else {
+            manifest.push_str(&format!("{}  {}\n", hash, file.name));
+        }
+    }
+    Ok(manifest)
+}
+
+/// Group files with identical contents, returning `(hash, files)` pairs with at least two files each.
+///
+/// Only files sharing a size are hashed, so a folder without duplicates costs one `stat` per file.
+/// Files that cannot be read, e.g. because they were removed during the scan, are left out.
+pub fn find_duplicates(files: Vec<FileInfo>) -> Vec<(String, Vec<FileInfo>)> {
+    let mut by_size: HashMap<u64, Vec<FileInfo>> = HashMap::new();
+    for file in files {
+        by_size.entry(file.size).or_default().push(file);
+    }
+
+    let mut groups = Vec::new();
+    for (size, candidates) in by_size {
+        // Empty files are trivially identical and not worth reporting
+        if size == 0 || candidates.len() < 2 {
+            continue;
+        }
+
+        let mut by_hash: HashMap<String, Vec<FileInfo>> = HashMap::new();
+        for file in candidates {
+            let hash = match hash_file(&file.path) {
+                Ok(hash) => hash,
+                Err(e) => {
+                    warn!("Skipping {} in the duplicate scan: {}", file.name, e);
+                    continue;
+                }
+            };
+            by_hash.entry(hash).or_default().push(file);
+        }
+
+        groups.extend(by_hash.into_iter().filter(|(_, files)| files.len() > 1));
+    }
+
+    // Oldest copy first within a group, largest waste first overall
+    for (_, files) in &mut groups {
+        files.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.name.cmp(&b.name)));
+    }
+    groups.sort_by(|(_, a), (_, b)| {
+        let wasted = |files: &Vec<FileInfo>| files[0].size * (files.len() as u64 - 1);
+        wasted(b).cmp(&wasted(a)).then_with(|| a[0].name.cmp(&b[0].name))
+    });
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::file::{get_file_info, list_directory};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_hash_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("hello.txt");
+        std::fs::write(&path, "hello").unwrap();
+
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[test]
+    fn test_sha256_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("hello.txt"), "hello").unwrap();
+        std::fs::write(temp_dir.path().join("empty.txt"), "").unwrap();
+
+        let files = list_directory(temp_dir.path()).unwrap();
+        assert_eq!(
+            sha256_manifest(&files).unwrap(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  empty.txt\n\
+             2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  hello.txt\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sha256_manifest_escapes_names() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a\\b\nc.txt"), "").unwrap();
+
+        let files = list_directory(temp_dir.path()).unwrap();
+        assert_eq!(
+            sha256_manifest(&files).unwrap(),
+            "\\e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  a\\\\b\\nc.txt\n"
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("IMG_001.jpg"), "photo").unwrap();
+        std::fs::write(temp_dir.path().join("IMG_001 (1).jpg"), "photo").unwrap();
+        // Same size, different contents
+        std::fs::write(temp_dir.path().join("other.jpg"), "image").unwrap();
+        std::fs::write(temp_dir.path().join("empty-a.txt"), "").unwrap();
+        std::fs::write(temp_dir.path().join("empty-b.txt"), "").unwrap();
+
+        // Gone by the time it is hashed
+        std::fs::write(temp_dir.path().join("removed.jpg"), "photo").unwrap();
+        let files = list_directory(temp_dir.path()).unwrap();
+        std::fs::remove_file(temp_dir.path().join("removed.jpg")).unwrap();
+        let groups = find_duplicates(files);
+
+        assert_eq!(groups.len(), 1);
+        let names: Vec<_> = groups[0].1.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"IMG_001.jpg"));
+        assert!(names.contains(&"IMG_001 (1).jpg"));
+        assert_eq!(groups[0].0, hash_file(&temp_dir.path().join("IMG_001.jpg")).unwrap());
+    }
+
+    #[test]
+    fn test_no_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "a").unwrap();
+        let file = get_file_info(&temp_dir.path().join("a.txt")).unwrap();
+
+        assert!(find_duplicates(vec![file]).is_empty());
+    }
+}
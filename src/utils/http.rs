@@ -0,0 +1,288 @@
+use axum::http::{header, HeaderMap, HeaderValue};
+use chrono::{DateTime, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+
+/// Characters RFC 5987 allows unencoded in an extended header parameter
+const ATTR_CHAR: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+/// A single satisfiable byte range, with inclusive bounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end < self.start
+    }
+
+    /// Value for the `Content-Range` header of a partial response
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// Outcome of interpreting a `Range` request header against a resource of known length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable range, serve the whole resource
+    Full,
+    /// Serve only the given part of the resource
+    Partial(ByteRange),
+    /// The range cannot be satisfied (416)
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header value (`bytes=0-499`, `bytes=500-`, `bytes=-500`).
+///
+/// Only single ranges are supported; multi-range and malformed headers fall back to
+/// serving the full resource, which RFC 9110 allows.
+pub fn parse_range(header: Option<&str>, total: u64) -> RangeRequest {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        ("", "") => return RangeRequest::Full,
+        // Suffix range: the last N bytes
+        ("", suffix) => {
+            let Ok(suffix) = suffix.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            if suffix == 0 || total == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange {
+                start: total.saturating_sub(suffix),
+                end: total - 1,
+            }
+        }
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if end.is_empty() {
+                total.saturating_sub(1)
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => end.min(total.saturating_sub(1)),
+                    _ => return RangeRequest::Full,
+                }
+            };
+            if start >= total {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange { start, end }
+        }
+    };
+
+    RangeRequest::Partial(range)
+}
+
+/// Whether a file of `mime_type` may be displayed on the app's own origin: media, PDFs and plain
+/// text. HTML, SVG and anything else that can carry script is always saved instead.
+pub fn is_inline_safe(mime_type: &str) -> bool {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    match essence.split_once('/') {
+        Some(("image", subtype)) => subtype != "svg+xml",
+        Some(("video" | "audio", _)) => true,
+        _ => matches!(essence.as_str(), "application/pdf" | "text/plain"),
+    }
+}
+
+/// Headers for every response carrying the contents of a file: browsers keep to the type sent
+/// and run nothing the file contains, even when it is opened directly
+pub fn insert_file_headers(headers: &mut HeaderMap) {
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    headers.insert(header::CONTENT_SECURITY_POLICY, HeaderValue::from_static("sandbox"));
+}
+
+/// `Content-Disposition` for serving the file at a share relative `name`.
+///
+/// Carries the last path component both as a plain ASCII `filename` for old clients and as an
+/// RFC 5987 `filename*`, which browsers prefer and which keeps emoji and CJK names intact.
+pub fn content_disposition(disposition: &str, name: &str) -> HeaderValue {
+    let name = name.rsplit('/').next().unwrap_or(name);
+    let fallback: String = name
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect();
+
+    let value = format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        disposition,
+        fallback,
+        utf8_percent_encode(name, ATTR_CHAR)
+    );
+    // Everything outside visible ASCII was replaced or percent-encoded above
+    HeaderValue::from_str(&value).expect("content disposition is visible ASCII")
+}
+
+/// The `filename` parameter of a multipart part's `Content-Disposition`, decoding bytes that are
+/// not UTF-8 lossily instead of losing the name altogether
+pub fn lossy_file_name(disposition: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(disposition);
+    let start = text.find("filename=\"")? + "filename=\"".len();
+    let end = start + text[start..].find('"')?;
+    Some(text[start..end].to_string())
+}
+
+/// Strong entity tag of a stored file, changing whenever its size or modification time does
+pub fn file_etag(size: u64, modified: DateTime<Utc>) -> String {
+    format!("\"{:x}-{:x}\"", size, modified.timestamp_nanos_opt().unwrap_or_default())
+}
+
+/// Weak entity tag of a generated response body
+pub fn body_etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..8].iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("W/\"{}\"", hex)
+}
+
+/// Whether an `If-None-Match` header lists `etag`, compared weakly as RFC 9110 asks
+pub fn etag_matches(if_none_match: Option<&str>, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    if_none_match.is_some_and(|header| header.split(',').any(|tag| tag.trim() == "*" || opaque(tag) == etag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_matches() {
+        let etag = body_etag(b"[]");
+        assert!(etag.starts_with("W/\""));
+        assert_ne!(etag, body_etag(b"[1]"));
+
+        assert!(etag_matches(Some(&etag), &etag));
+        assert!(etag_matches(Some(&format!("\"other\", {}", etag.trim_start_matches("W/"))), &etag));
+        assert!(etag_matches(Some("*"), &etag));
+        assert!(!etag_matches(Some("\"other\""), &etag));
+        assert!(!etag_matches(None, &etag));
+    }
+
+    #[test]
+    fn test_no_range_header() {
+        assert_eq!(parse_range(None, 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("items=0-1"), 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_bounded_range() {
+        assert_eq!(
+            parse_range(Some("bytes=0-9"), 100),
+            RangeRequest::Partial(ByteRange { start: 0, end: 9 })
+        );
+        // End past the resource is clamped
+        assert_eq!(
+            parse_range(Some("bytes=90-200"), 100),
+            RangeRequest::Partial(ByteRange { start: 90, end: 99 })
+        );
+    }
+
+    #[test]
+    fn test_open_ended_range() {
+        assert_eq!(
+            parse_range(Some("bytes=50-"), 100),
+            RangeRequest::Partial(ByteRange { start: 50, end: 99 })
+        );
+    }
+
+    #[test]
+    fn test_suffix_range() {
+        assert_eq!(
+            parse_range(Some("bytes=-10"), 100),
+            RangeRequest::Partial(ByteRange { start: 90, end: 99 })
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-500"), 100),
+            RangeRequest::Partial(ByteRange { start: 0, end: 99 })
+        );
+    }
+
+    #[test]
+    fn test_unsatisfiable_range() {
+        assert_eq!(parse_range(Some("bytes=100-"), 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=-0"), 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=0-"), 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_malformed_and_multi_ranges_serve_full() {
+        assert_eq!(parse_range(Some("bytes=abc-"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=9-1"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_content_range_header() {
+        let range = ByteRange { start: 0, end: 9 };
+        assert_eq!(range.len(), 10);
+        assert_eq!(range.content_range(100), "bytes 0-9/100");
+    }
+
+    #[test]
+    fn test_content_disposition_encodes_unicode_names() {
+        assert_eq!(
+            content_disposition("attachment", "docs/report.pdf"),
+            "attachment; filename=\"report.pdf\"; filename*=UTF-8''report.pdf"
+        );
+        assert_eq!(
+            content_disposition("inline", "写真 🎉.jpg"),
+            "inline; filename=\"__ _.jpg\"; filename*=UTF-8''%E5%86%99%E7%9C%9F%20%F0%9F%8E%89.jpg"
+        );
+        assert_eq!(
+            content_disposition("attachment", "say \"hi\".txt"),
+            "attachment; filename=\"say _hi_.txt\"; filename*=UTF-8''say%20%22hi%22.txt"
+        );
+    }
+
+    #[test]
+    fn test_inline_safe_types() {
+        for mime_type in ["image/png", "video/mp4", "audio/mpeg", "application/pdf", "text/plain; charset=utf-8"] {
+            assert!(is_inline_safe(mime_type), "{}", mime_type);
+        }
+        for mime_type in ["text/html", "image/svg+xml", "IMAGE/SVG+XML", "application/xhtml+xml", "text/xml"] {
+            assert!(!is_inline_safe(mime_type), "{}", mime_type);
+        }
+    }
+
+    #[test]
+    fn test_lossy_file_name() {
+        let disposition = b"form-data; name=\"file\"; filename=\"caf\xe9.txt\"";
+        assert_eq!(lossy_file_name(disposition).unwrap(), "caf\u{FFFD}.txt");
+        assert!(lossy_file_name(b"form-data; name=\"file\"").is_none());
+    }
+}
@@ -1,3 +1,16 @@
+pub mod bigtext;
+pub mod collation;
 pub mod file;
+pub mod filename;
+pub mod hash;
+pub mod http;
 pub mod qrcode;
 pub mod network;
+pub mod organize;
+pub mod render;
+pub mod sparse;
+pub mod tail;
+pub mod throughput;
+pub mod thumbnail;
+pub mod usage;
+pub mod xattr;
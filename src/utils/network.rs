@@ -1,24 +1,32 @@
-use std::net::{TcpListener, SocketAddr};
+use std::net::{IpAddr, TcpListener, SocketAddr};
 use tracing::warn;
 
 /// Find an available port starting from the given port number
 pub fn find_available_port(start_port: u16, end_port: u16) -> Option<u16> {
-    for port in start_port..=end_port {
-        if is_port_available(port) {
-            return Some(port);
-        }
-    }
-    None
+    (start_port..=end_port).find(|&port| is_port_available(port))
 }
 
 /// Check if a specific port is available
 pub fn is_port_available(port: u16) -> bool {
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     
-    match TcpListener::bind(addr) {
-        Ok(_) => true,
-        Err(_) => false,
-    }
+    TcpListener::bind(addr).is_ok()
+}
+
+/// Addresses of this host that other devices on a local network could reach, by interface
+pub fn interface_addresses() -> Vec<(String, IpAddr)> {
+    let mut addresses: Vec<(String, IpAddr)> = local_ip_address::list_afinet_netifas()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, ip)| match ip {
+            IpAddr::V4(ip) => !ip.is_loopback() && !ip.is_link_local(),
+            // Link-local IPv6 addresses need a zone id browsers do not accept in URLs
+            IpAddr::V6(ip) => !ip.is_loopback() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+        })
+        .collect();
+    // IPv4 first, it is what people type
+    addresses.sort_by_key(|(_, ip)| ip.is_ipv6());
+    addresses
 }
 
 /// Get the next available port starting from a given port
@@ -75,6 +83,11 @@ mod tests {
         // so we don't test immediate availability after drop
     }
 
+    #[test]
+    fn test_interface_addresses_skip_loopback() {
+        assert!(interface_addresses().iter().all(|(_, ip)| !ip.is_loopback()));
+    }
+
     #[test]
     fn test_find_available_port_success() {
         // Should find at least one available port in high range
@@ -82,7 +95,7 @@ mod tests {
         assert!(result.is_some());
         
         let port = result.unwrap();
-        assert!(port >= 60000 && port <= 60010);
+        assert!((60000..=60010).contains(&port));
         assert!(is_port_available(port));
     }
 
@@ -207,6 +220,6 @@ mod tests {
         assert!(result.is_some());
         
         let port = result.unwrap();
-        assert!(port >= 50000 && port <= 50100);
+        assert!((50000..=50100).contains(&port));
     }
 } 
\ No newline at end of file
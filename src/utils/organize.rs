@@ -0,0 +1,130 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use exif::{In, Reader, Tag, Value};
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use crate::core::config::{DateOrganization, FilesConfig};
+use crate::utils::file::{sanitize_path_component, INBOX_DIR};
+
+/// Enough of the start of a file to find its EXIF date
+pub const EXIF_SCAN_BYTES: usize = 256 * 1024;
+
+/// Directory (relative to the share) an upload should be stored in, following the `[files]` options
+pub fn upload_subdirectory(
+    config: &FilesConfig,
+    sender: Option<&str>,
+    data: &[u8],
+    now: DateTime<Utc>,
+) -> PathBuf {
+    let mut directory = PathBuf::new();
+
+    if let Some(sender) = sender.filter(|_| config.per_sender_subdirs) {
+        directory.push(INBOX_DIR);
+        directory.push(sanitize_path_component(sender));
+    }
+
+    let date = match config.organize_by_date {
+        DateOrganization::Off => None,
+        DateOrganization::Upload => Some(now.date_naive()),
+        DateOrganization::Exif => Some(exif_date(data).unwrap_or_else(|| now.date_naive())),
+    };
+    if let Some(date) = date {
+        directory.push(format!("{:04}", date.year()));
+        directory.push(format!("{:02}", date.month()));
+    }
+
+    directory
+}
+
+/// Date a photo was taken according to its EXIF data, if it has any
+pub fn exif_date(data: &[u8]) -> Option<NaiveDate> {
+    let exif = Reader::new().read_from_container(&mut Cursor::new(data)).ok()?;
+
+    [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+        .iter()
+        .filter_map(|tag| exif.get_field(*tag, In::PRIMARY))
+        .find_map(|field| match &field.value {
+            Value::Ascii(values) => values.first().and_then(|value| {
+                let date = exif::DateTime::from_ascii(value).ok()?;
+                NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())
+            }),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Minimal JPEG carrying only an EXIF `DateTime` tag
+    fn jpeg_with_date(date: &str) -> Vec<u8> {
+        let mut tiff = Vec::new();
+        tiff.extend_from_slice(b"II*\0");
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        // IFD0 with a single ASCII entry whose value follows the IFD
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&0x0132u16.to_le_bytes());
+        tiff.extend_from_slice(&2u16.to_le_bytes());
+        tiff.extend_from_slice(&20u32.to_le_bytes());
+        tiff.extend_from_slice(&26u32.to_le_bytes());
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(date.as_bytes());
+        tiff.push(0);
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 9, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_exif_date() {
+        let jpeg = jpeg_with_date("2019:12:25 08:30:00");
+        assert_eq!(exif_date(&jpeg), NaiveDate::from_ymd_opt(2019, 12, 25));
+        assert_eq!(exif_date(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_default_keeps_uploads_at_top() {
+        let config = FilesConfig::default();
+        assert_eq!(upload_subdirectory(&config, Some("Alex"), b"", now()), PathBuf::new());
+    }
+
+    #[test]
+    fn test_upload_date_folders() {
+        let config = FilesConfig {
+            organize_by_date: DateOrganization::Upload,
+            ..Default::default()
+        };
+        let jpeg = jpeg_with_date("2019:12:25 08:30:00");
+        assert_eq!(upload_subdirectory(&config, None, &jpeg, now()), PathBuf::from("2024/03"));
+    }
+
+    #[test]
+    fn test_exif_date_folders_with_sender() {
+        let config = FilesConfig {
+            organize_by_date: DateOrganization::Exif,
+            per_sender_subdirs: true,
+            ..Default::default()
+        };
+
+        let jpeg = jpeg_with_date("2019:12:25 08:30:00");
+        assert_eq!(
+            upload_subdirectory(&config, Some("Alex"), &jpeg, now()),
+            PathBuf::from("inbox/Alex/2019/12")
+        );
+
+        // Files without EXIF data fall back to the upload date
+        assert_eq!(
+            upload_subdirectory(&config, None, b"plain text", now()),
+            PathBuf::from("2024/03")
+        );
+    }
+}
@@ -10,10 +10,10 @@ pub fn generate_qr_code(url: &str) -> Result<String> {
         .build();
     
     let mut output = String::new();
-    output.push_str("\n");
+    output.push('\n');
     output.push_str("Scan this QR code to access RustDrop:\n");
     output.push_str(&qr);
-    output.push_str("\n");
+    output.push('\n');
     output.push_str(&format!("Or open: {}\n", url));
     
     Ok(output)
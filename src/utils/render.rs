@@ -0,0 +1,246 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::path::Path;
+use std::sync::OnceLock;
+use syntect::{
+    highlighting::ThemeSet,
+    html::highlighted_html_for_string,
+    parsing::{SyntaxReference, SyntaxSet},
+};
+
+/// Largest file (in bytes) that will be rendered as a preview
+pub const MAX_RENDER_SIZE: u64 = 2 * 1024 * 1024;
+
+/// Policy for rendered pages: the highlighting's inline styles and images, but no scripts,
+/// frames or forms, whatever gets past the renderer
+pub const RENDER_CSP: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; img-src 'self' http: https: data:; base-uri 'none'; form-action 'none'";
+
+/// Schemes links and images in rendered Markdown may use
+const SAFE_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+const CODE_THEME: &str = "InspiredGitHub";
+
+/// How a file can be previewed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderKind {
+    Markdown,
+    Code,
+}
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme_set() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+fn extension(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+}
+
+fn find_syntax(name: &str) -> Option<&'static SyntaxReference> {
+    let syntaxes = syntax_set();
+    extension(name)
+        .and_then(|ext| syntaxes.find_syntax_by_extension(&ext))
+        .or_else(|| syntaxes.find_syntax_by_extension(name))
+}
+
+/// Decide whether (and how) a file can be rendered as a preview
+pub fn render_kind(name: &str, mime_type: &str) -> Option<RenderKind> {
+    if matches!(extension(name).as_deref(), Some("md" | "markdown" | "mdown" | "mkd")) {
+        return Some(RenderKind::Markdown);
+    }
+
+    let textual_mime = mime_type.starts_with("text/")
+        || matches!(
+            mime_type,
+            "application/json" | "application/xml" | "application/javascript" | "application/toml"
+        );
+
+    if textual_mime || find_syntax(name).is_some() {
+        Some(RenderKind::Code)
+    } else {
+        None
+    }
+}
+
+/// Escape text for safe inclusion in HTML
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether `url` is relative or uses one of [`SAFE_SCHEMES`], so a link cannot run script
+fn is_safe_url(url: &str) -> bool {
+    match url.find([':', '/', '?', '#']) {
+        Some(end) if url[end..].starts_with(':') => {
+            SAFE_SCHEMES.iter().any(|scheme| url[..end].eq_ignore_ascii_case(scheme))
+        }
+        _ => true,
+    }
+}
+
+/// Render Markdown to HTML. Raw HTML in the source is escaped, never passed through, and links
+/// and images with other than web or mail addresses (`javascript:`, `data:`, ...) lead nowhere.
+pub fn render_markdown(source: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+
+    let parser = Parser::new_ext(source, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) if !is_safe_url(&dest_url) => {
+            Event::Start(Tag::Link { link_type, dest_url: CowStr::Borrowed(""), title, id })
+        }
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) if !is_safe_url(&dest_url) => {
+            Event::Start(Tag::Image { link_type, dest_url: CowStr::Borrowed(""), title, id })
+        }
+        other => other,
+    });
+
+    let mut output = String::new();
+    html::push_html(&mut output, parser);
+    output
+}
+
+/// Render source code as syntax highlighted HTML, falling back to plain text
+pub fn render_code(source: &str, name: &str) -> String {
+    let syntaxes = syntax_set();
+    let syntax = find_syntax(name)
+        .or_else(|| source.lines().next().and_then(|line| syntaxes.find_syntax_by_first_line(line)))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+
+    highlighted_html_for_string(source, syntaxes, syntax, &theme_set().themes[CODE_THEME])
+        .unwrap_or_else(|_| format!("<pre>{}</pre>", escape_html(source)))
+}
+
+/// Wrap a rendered preview in a standalone HTML page
+pub fn render_page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{title} - RustDrop</title>
+    <style>
+        body {{
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
+            max-width: 900px;
+            margin: 0 auto;
+            padding: 20px;
+            color: #333;
+            line-height: 1.5;
+        }}
+        pre {{
+            padding: 12px;
+            overflow-x: auto;
+            border-radius: 4px;
+            font-size: 14px;
+        }}
+        code {{
+            font-family: SFMono-Regular, Menlo, Consolas, monospace;
+        }}
+        table {{
+            border-collapse: collapse;
+        }}
+        th, td {{
+            border: 1px solid #ddd;
+            padding: 4px 8px;
+        }}
+        img {{
+            max-width: 100%;
+        }}
+    </style>
+</head>
+<body>
+    <h1>{title}</h1>
+    {body}
+</body>
+</html>"#,
+        title = escape_html(title),
+        body = body,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_kind() {
+        assert_eq!(render_kind("README.md", "text/markdown"), Some(RenderKind::Markdown));
+        assert_eq!(render_kind("main.rs", "text/x-rust"), Some(RenderKind::Code));
+        assert_eq!(render_kind("build.log", "text/plain"), Some(RenderKind::Code));
+        assert_eq!(render_kind("data.json", "application/json"), Some(RenderKind::Code));
+        assert_eq!(render_kind("photo.jpg", "image/jpeg"), None);
+        assert_eq!(render_kind("archive.zip", "application/zip"), None);
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let html = render_markdown("# Title\n\nSome *emphasis* and a | table |\n");
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<em>emphasis</em>"));
+    }
+
+    #[test]
+    fn test_render_markdown_escapes_raw_html() {
+        let html = render_markdown("hello <script>alert(1)</script>\n\n<div onclick=\"x\">block</div>\n");
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<div onclick"));
+        assert!(html.contains("&lt;script&gt;"));
+    }
+
+    #[test]
+    fn test_render_markdown_drops_script_urls() {
+        let html = render_markdown(
+            "[a](javascript:alert(1)) [b](JavaScript:alert(1)) <javascript:alert(1)> ![c](data:text/html,x) [d](vbscript:x)\n",
+        );
+        assert_eq!(html.matches("href=\"\"").count(), 4, "{}", html);
+        assert!(html.contains("src=\"\""), "{}", html);
+
+        let html = render_markdown("[a](https://example.com) [b](mailto:me@example.com) [c](notes/todo.md) [d](#top)\n");
+        for url in ["https://example.com", "mailto:me@example.com", "notes/todo.md", "#top"] {
+            assert!(html.contains(&format!("href=\"{}\"", url)), "{}", html);
+        }
+    }
+
+    #[test]
+    fn test_render_code_highlights() {
+        let html = render_code("fn main() {}\n", "main.rs");
+        assert!(html.starts_with("<pre"));
+        assert!(html.contains("main"));
+        assert!(html.contains("style="));
+    }
+
+    #[test]
+    fn test_render_code_escapes_plain_text() {
+        let html = render_code("<b>not bold</b>\n", "notes.unknownext");
+        assert!(!html.contains("<b>"));
+    }
+
+    #[test]
+    fn test_render_page_escapes_title() {
+        let page = render_page("<evil>.md", "<p>ok</p>");
+        assert!(page.contains("&lt;evil&gt;.md"));
+        assert!(page.contains("<p>ok</p>"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Spotting long runs of zeroes (the holes of sparse files such as disk images) so transfers can
+//! skip them and the receiver can leave holes instead of writing zeroes
+
+/// Granularity of hole detection; shorter runs of zeroes are sent as data
+pub const SPARSE_BLOCK: usize = 64 * 1024;
+
+/// The run of `data` starting at `offset`, returning where it ends and whether it is all zeroes.
+///
+/// A data run stops before the next block of zeroes or after `max_data` bytes, a zero run takes
+/// every zero block in a row.
+pub fn next_run(data: &[u8], offset: usize, max_data: usize) -> (usize, bool) {
+    let is_zero_block = |start: usize| {
+        let end = (start + SPARSE_BLOCK).min(data.len());
+        data[start..end].iter().all(|byte| *byte == 0)
+    };
+
+    let zeroes = is_zero_block(offset);
+    let mut end = offset;
+    while end < data.len() && is_zero_block(end) == zeroes && (zeroes || end - offset < max_data) {
+        end = (end + SPARSE_BLOCK).min(data.len());
+    }
+    (end, zeroes)
+}
+
+/// Every zero run of `data` as `(start, length)`
+pub fn zero_runs(data: &[u8]) -> Vec<(u64, u64)> {
+    let mut runs = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let (end, zeroes) = next_run(data, offset, usize::MAX);
+        if zeroes {
+            runs.push((offset as u64, (end - offset) as u64));
+        }
+        offset = end;
+    }
+    runs
+}
+
+/// The `(start, end)` ranges of a `size` byte file that are not covered by `zeroes`, or `None`
+/// if the zero runs are out of order, overlap or reach past the end
+pub fn data_ranges(size: u64, zeroes: &[(u64, u64)]) -> Option<Vec<(u64, u64)>> {
+    let mut ranges = Vec::new();
+    let mut position = 0;
+    for &(start, length) in zeroes {
+        let end = start.checked_add(length)?;
+        if start < position || end > size {
+            return None;
+        }
+        if start > position {
+            ranges.push((position, start));
+        }
+        position = end;
+    }
+    if position < size {
+        ranges.push((position, size));
+    }
+    Some(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_of_sparse_data() {
+        let mut data = vec![0u8; 5 * SPARSE_BLOCK];
+        data[10] = 1;
+        data[3 * SPARSE_BLOCK + 7] = 1;
+
+        assert_eq!(next_run(&data, 0, usize::MAX), (SPARSE_BLOCK, false));
+        assert_eq!(next_run(&data, SPARSE_BLOCK, usize::MAX), (3 * SPARSE_BLOCK, true));
+        assert_eq!(
+            zero_runs(&data),
+            vec![
+                (SPARSE_BLOCK as u64, 2 * SPARSE_BLOCK as u64),
+                (4 * SPARSE_BLOCK as u64, SPARSE_BLOCK as u64)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_data_ranges_between_zero_runs() {
+        assert_eq!(data_ranges(10, &[]), Some(vec![(0, 10)]));
+        assert_eq!(data_ranges(10, &[(2, 3), (7, 3)]), Some(vec![(0, 2), (5, 7)]));
+        assert_eq!(data_ranges(10, &[(0, 10)]), Some(vec![]));
+        assert_eq!(data_ranges(10, &[(5, 2), (4, 1)]), None);
+        assert_eq!(data_ranges(10, &[(8, 5)]), None);
+    }
+
+    #[test]
+    fn test_data_runs_are_capped() {
+        let data = vec![1u8; 4 * SPARSE_BLOCK + 5];
+        assert_eq!(next_run(&data, 0, 2 * SPARSE_BLOCK), (2 * SPARSE_BLOCK, false));
+        assert_eq!(next_run(&data, 4 * SPARSE_BLOCK, 2 * SPARSE_BLOCK), (data.len(), false));
+        assert!(zero_runs(&data).is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+//! Reading the end of growing text files, like `tail -f`
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How far back from the end to look for the last lines
+const TAIL_WINDOW: u64 = 256 * 1024;
+
+/// Most bytes read per poll, so a file that suddenly grew a lot is sent in steps
+const MAX_READ: u64 = 1024 * 1024;
+
+/// Where following a file has got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TailPosition {
+    /// Offset just past the last complete line sent
+    pub offset: u64,
+}
+
+/// What changed in a followed file since the last poll
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TailUpdate {
+    /// The file got shorter, so it was truncated or replaced and is read from the start again
+    pub truncated: bool,
+    pub lines: Vec<String>,
+}
+
+/// The last `count` complete lines of `path`, and the position to follow it from.
+/// A final line without its newline yet is left for [`read_new_lines`].
+pub fn last_lines(path: &Path, count: usize) -> io::Result<(Vec<String>, TailPosition)> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(TAIL_WINDOW);
+
+    file.seek(SeekFrom::Start(start))?;
+    let mut data = Vec::new();
+    file.take(len - start).read_to_end(&mut data)?;
+
+    // Starting inside the file most likely means starting inside a line
+    let skip = if start > 0 {
+        data.iter().position(|byte| *byte == b'\n').map_or(data.len(), |newline| newline + 1)
+    } else {
+        0
+    };
+    let (lines, consumed) = complete_lines(&data[skip..]);
+    let offset = start + (skip + consumed) as u64;
+    let lines = lines[lines.len().saturating_sub(count)..].to_vec();
+    Ok((lines, TailPosition { offset }))
+}
+
+/// Complete lines appended to `path` since `position`, moving it along
+pub fn read_new_lines(path: &Path, position: &mut TailPosition) -> io::Result<TailUpdate> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut update = TailUpdate::default();
+    if len < position.offset {
+        update.truncated = true;
+        position.offset = 0;
+    }
+    if len == position.offset {
+        return Ok(update);
+    }
+
+    file.seek(SeekFrom::Start(position.offset))?;
+    let mut data = Vec::new();
+    file.take((len - position.offset).min(MAX_READ)).read_to_end(&mut data)?;
+
+    let (lines, mut consumed) = complete_lines(&data);
+    // A single line longer than a whole read is sent in pieces rather than never
+    if consumed == 0 && data.len() as u64 == MAX_READ {
+        update.lines.push(String::from_utf8_lossy(&data).into_owned());
+        consumed = data.len();
+    }
+    update.lines.extend(lines);
+    position.offset += consumed as u64;
+    Ok(update)
+}
+
+/// The complete lines of `data` without their line endings, and how many bytes they cover
+fn complete_lines(data: &[u8]) -> (Vec<String>, usize) {
+    let end = data.iter().rposition(|byte| *byte == b'\n').map_or(0, |newline| newline + 1);
+    let mut lines: Vec<String> = data[..end]
+        .split(|byte| *byte == b'\n')
+        .map(|line| String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into_owned())
+        .collect();
+    // Splitting leaves an empty piece after the final newline
+    lines.pop();
+    (lines, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_follow_appended_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("build.log");
+        std::fs::write(&path, "one\ntwo\r\nthree\npart").unwrap();
+
+        let (lines, mut position) = last_lines(&path, 2).unwrap();
+        assert_eq!(lines, vec!["two", "three"]);
+        assert_eq!(read_new_lines(&path, &mut position).unwrap(), TailUpdate::default());
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"ial\nfour\n").unwrap();
+        let update = read_new_lines(&path, &mut position).unwrap();
+        assert_eq!(update.lines, vec!["partial", "four"]);
+        assert!(!update.truncated);
+
+        std::fs::write(&path, "fresh\n").unwrap();
+        let update = read_new_lines(&path, &mut position).unwrap();
+        assert!(update.truncated);
+        assert_eq!(update.lines, vec!["fresh"]);
+    }
+
+    #[test]
+    fn test_last_lines_of_large_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("big.log");
+        let content: String = (0..50_000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let (lines, position) = last_lines(&path, 3).unwrap();
+        assert_eq!(lines, vec!["line 49997", "line 49998", "line 49999"]);
+        assert_eq!(position.offset, content.len() as u64);
+
+        let (lines, _) = last_lines(&path, 100_000).unwrap();
+        assert!(lines.len() < 50_000);
+        assert!(lines[0].starts_with("line "));
+    }
+}
@@ -0,0 +1,124 @@
+//! Rolling transfer speed, to show how fast a transfer goes and when it should be done
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Speed is averaged over this much of the recent past, which smooths out the pauses between
+/// chunks without hiding a link that just slowed down
+const WINDOW: Duration = Duration::from_secs(10);
+
+/// Speeds kept for drawing a graph, one per second at most
+pub const HISTORY_LEN: usize = 60;
+const HISTORY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Speed of one transfer, fed with the bytes transferred so far
+#[derive(Debug, Clone, Default)]
+pub struct Throughput {
+    /// When how many bytes had been transferred, oldest first
+    samples: VecDeque<(Instant, u64)>,
+    history: VecDeque<u64>,
+    last_history: Option<Instant>,
+}
+
+impl Throughput {
+    /// Note that `transferred` bytes are done by now
+    pub fn record(&mut self, transferred: u64) {
+        self.record_at(Instant::now(), transferred);
+    }
+
+    fn record_at(&mut self, now: Instant, transferred: u64) {
+        // A transfer that started over counts from its new position
+        if self.samples.back().is_some_and(|&(_, last)| transferred < last) {
+            self.samples.clear();
+        }
+        self.samples.push_back((now, transferred));
+        // The oldest sample stays until the next one also covers the whole window
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= WINDOW {
+            self.samples.pop_front();
+        }
+
+        if self.last_history.is_none_or(|last| now.duration_since(last) >= HISTORY_INTERVAL) {
+            if let Some(speed) = self.speed() {
+                if self.history.len() == HISTORY_LEN {
+                    self.history.pop_front();
+                }
+                self.history.push_back(speed);
+                self.last_history = Some(now);
+            }
+        }
+    }
+
+    /// Bytes per second over the recent past, once there are two samples
+    pub fn speed(&self) -> Option<u64> {
+        let &(start, first) = self.samples.front()?;
+        let &(end, last) = self.samples.back()?;
+        let elapsed = end.duration_since(start).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        Some(((last - first) as f64 / elapsed) as u64)
+    }
+
+    /// Time left until `size` bytes are done at the current speed
+    pub fn eta(&self, size: u64) -> Option<Duration> {
+        let &(_, transferred) = self.samples.back()?;
+        let speed = self.speed().filter(|&speed| speed > 0)?;
+        Some(Duration::from_secs(size.saturating_sub(transferred).div_ceil(speed)))
+    }
+
+    /// Recent speeds, oldest first
+    pub fn history(&self) -> Vec<u64> {
+        self.history.iter().copied().collect()
+    }
+}
+
+/// A time left the way people say it: `45 s`, `3 min`, `1 h 05 min`
+pub fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    match secs {
+        0..60 => format!("{} s", secs),
+        60..3600 => format!("{} min", secs.div_ceil(60)),
+        _ => format!("{} h {:02} min", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed_and_eta() {
+        let start = Instant::now();
+        let mut throughput = Throughput::default();
+        throughput.record_at(start, 0);
+        assert_eq!(throughput.speed(), None);
+        assert_eq!(throughput.eta(1000), None);
+
+        // 100 bytes a second for 30 seconds, then 10 bytes a second
+        for second in 1..=30 {
+            throughput.record_at(start + Duration::from_secs(second), second * 100);
+        }
+        assert_eq!(throughput.speed(), Some(100));
+        assert_eq!(throughput.eta(4000), Some(Duration::from_secs(10)));
+        for second in 31..=45 {
+            throughput.record_at(start + Duration::from_secs(second), 3000 + (second - 30) * 10);
+        }
+        assert_eq!(throughput.speed(), Some(10));
+
+        let history = throughput.history();
+        assert_eq!(history.len(), 45);
+        assert_eq!(history[0], 100);
+        assert_eq!(*history.last().unwrap(), 10);
+
+        // Starting over forgets the old position instead of computing a negative speed
+        throughput.record_at(start + Duration::from_secs(46), 0);
+        assert_eq!(throughput.speed(), None);
+    }
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta(Duration::from_secs(45)), "45 s");
+        assert_eq!(format_eta(Duration::from_secs(150)), "3 min");
+        assert_eq!(format_eta(Duration::from_secs(3900)), "1 h 05 min");
+    }
+}
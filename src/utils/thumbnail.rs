@@ -0,0 +1,180 @@
+use image::ImageOutputFormat;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::core::error::{AppError, AppResult};
+use crate::core::metadata::STATE_DIR;
+use crate::core::models::FileInfo;
+
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+pub const MIN_THUMBNAIL_SIZE: u32 = 32;
+pub const MAX_THUMBNAIL_SIZE: u32 = 1024;
+
+const THUMBNAIL_DIR: &str = "thumbnails";
+
+/// How long `pdftoppm` may take for one page before it is killed, so a crafted PDF cannot keep
+/// a worker busy
+#[cfg(feature = "pdf-thumbnails")]
+const PDF_THUMBNAIL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Whether a thumbnail can be produced for this MIME type with the enabled features
+pub fn supports_thumbnail(mime_type: &str) -> bool {
+    if mime_type.starts_with("image/") {
+        return true;
+    }
+    cfg!(feature = "pdf-thumbnails") && mime_type == "application/pdf"
+}
+
+/// Location of the cached thumbnail; the modification time is part of the key so edits invalidate it
+pub fn thumbnail_cache_path(directory: &Path, file: &FileInfo, size: u32) -> PathBuf {
+    directory.join(STATE_DIR).join(THUMBNAIL_DIR).join(format!(
+        "{}-{}-{}.png",
+        file.id,
+        size,
+        file.modified.timestamp()
+    ))
+}
+
+/// Return a PNG thumbnail for the file, generating and caching it if needed.
+///
+/// Returns `Ok(None)` when the file type has no thumbnail support.
+pub fn get_or_create_thumbnail(directory: &Path, file: &FileInfo, size: u32) -> AppResult<Option<Vec<u8>>> {
+    if !supports_thumbnail(&file.mime_type) {
+        return Ok(None);
+    }
+
+    let cache_path = thumbnail_cache_path(directory, file, size);
+    if let Ok(data) = std::fs::read(&cache_path) {
+        return Ok(Some(data));
+    }
+
+    let cache_dir = cache_path.parent().expect("thumbnail path has a parent");
+    std::fs::create_dir_all(cache_dir)?;
+    remove_stale_thumbnails(cache_dir, file, size);
+
+    let data = if file.mime_type == "application/pdf" {
+        pdf_thumbnail(&file.path, size, &cache_path)?
+    } else {
+        image_thumbnail(&file.path, size)?
+    };
+
+    std::fs::write(&cache_path, &data)?;
+    Ok(Some(data))
+}
+
+/// Drop thumbnails generated for older versions of the same file
+fn remove_stale_thumbnails(cache_dir: &Path, file: &FileInfo, size: u32) {
+    let prefix = format!("{}-{}-", file.id, size);
+    if let Ok(entries) = std::fs::read_dir(cache_dir) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+fn image_thumbnail(path: &Path, size: u32) -> AppResult<Vec<u8>> {
+    let image = image::open(path)
+        .map_err(|e| AppError::File(format!("Failed to decode image: {}", e)))?;
+
+    let mut output = Cursor::new(Vec::new());
+    image
+        .thumbnail(size, size)
+        .write_to(&mut output, ImageOutputFormat::Png)
+        .map_err(|e| AppError::File(format!("Failed to encode thumbnail: {}", e)))?;
+
+    Ok(output.into_inner())
+}
+
+/// Render the first PDF page with poppler's `pdftoppm`, which is killed when it takes longer
+/// than [`PDF_THUMBNAIL_TIMEOUT`]. Must run on a blocking thread of the runtime.
+#[cfg(feature = "pdf-thumbnails")]
+fn pdf_thumbnail(path: &Path, size: u32, cache_path: &Path) -> AppResult<Vec<u8>> {
+    // pdftoppm appends ".png" to the output root
+    let output_root = cache_path.with_extension("");
+
+    let run = tokio::process::Command::new("pdftoppm")
+        .arg("-png")
+        .args(["-f", "1", "-l", "1"])
+        .arg("-singlefile")
+        .args(["-scale-to", &size.to_string()])
+        .arg(path)
+        .arg(&output_root)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::runtime::Handle::current()
+        .block_on(async { tokio::time::timeout(PDF_THUMBNAIL_TIMEOUT, run).await })
+        .map_err(|_| AppError::File(format!("pdftoppm took longer than {:?}", PDF_THUMBNAIL_TIMEOUT)))?
+        .map_err(|e| AppError::File(format!("Failed to run pdftoppm (is poppler installed?): {}", e)))?;
+
+    if !output.status.success() {
+        return Err(AppError::File(format!(
+            "pdftoppm failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(std::fs::read(output_root.with_extension("png"))?)
+}
+
+#[cfg(not(feature = "pdf-thumbnails"))]
+fn pdf_thumbnail(_path: &Path, _size: u32, _cache_path: &Path) -> AppResult<Vec<u8>> {
+    Err(AppError::File("PDF thumbnails are not enabled in this build".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::file::get_file_info;
+    use image::{ImageBuffer, Rgb};
+    use tempfile::TempDir;
+
+    fn create_test_image(path: &Path, width: u32, height: u32) {
+        let image = ImageBuffer::from_pixel(width, height, Rgb([200u8, 100, 50]));
+        image.save(path).unwrap();
+    }
+
+    #[test]
+    fn test_supports_thumbnail() {
+        assert!(supports_thumbnail("image/png"));
+        assert!(supports_thumbnail("image/jpeg"));
+        assert!(!supports_thumbnail("text/plain"));
+        assert_eq!(supports_thumbnail("application/pdf"), cfg!(feature = "pdf-thumbnails"));
+    }
+
+    #[test]
+    fn test_image_thumbnail_is_scaled_and_cached() {
+        let temp_dir = TempDir::new().unwrap();
+        let image_path = temp_dir.path().join("big.png");
+        create_test_image(&image_path, 400, 200);
+        let file = get_file_info(&image_path).unwrap();
+
+        let data = get_or_create_thumbnail(temp_dir.path(), &file, 100).unwrap().unwrap();
+        let thumbnail = image::load_from_memory(&data).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (100, 50));
+
+        assert!(thumbnail_cache_path(temp_dir.path(), &file, 100).exists());
+    }
+
+    #[test]
+    fn test_unsupported_type_has_no_thumbnail() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("notes.txt");
+        std::fs::write(&path, "hello").unwrap();
+        let file = get_file_info(&path).unwrap();
+
+        assert!(get_or_create_thumbnail(temp_dir.path(), &file, 100).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_corrupt_image_is_an_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("broken.png");
+        std::fs::write(&path, "not a png").unwrap();
+        let file = get_file_info(&path).unwrap();
+
+        assert!(get_or_create_thumbnail(temp_dir.path(), &file, 100).is_err());
+    }
+}
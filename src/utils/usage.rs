@@ -0,0 +1,189 @@
+use chrono::{DateTime, Duration, Utc};
+use mime_guess::from_path;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::core::metadata::STATE_DIR;
+
+/// Size and file count of one slice of the share
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageEntry {
+    pub name: String,
+    pub bytes: u64,
+    pub files: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub total_bytes: u64,
+    pub total_files: u64,
+    /// By top-level directory, `.` being files directly in the share
+    pub by_directory: Vec<UsageEntry>,
+    /// By MIME top-level type (`image`, `video`, ...)
+    pub by_category: Vec<UsageEntry>,
+    /// By time since last modification
+    pub by_age: Vec<UsageEntry>,
+}
+
+const AGE_BUCKETS: [(&str, i64); 4] = [("day", 1), ("week", 7), ("month", 30), ("year", 365)];
+const OLDER_BUCKET: &str = "older";
+
+/// Walk the whole share (excluding RustDrop's state directory) and summarize disk usage
+pub fn compute_usage(directory: &Path, now: DateTime<Utc>) -> std::io::Result<UsageReport> {
+    let mut report = UsageReport::default();
+    let mut by_directory: BTreeMap<String, UsageEntry> = BTreeMap::new();
+    let mut by_category: BTreeMap<String, UsageEntry> = BTreeMap::new();
+    let mut by_age: Vec<UsageEntry> = AGE_BUCKETS
+        .iter()
+        .map(|(name, _)| name)
+        .chain(std::iter::once(&OLDER_BUCKET))
+        .map(|name| UsageEntry { name: name.to_string(), ..Default::default() })
+        .collect();
+
+    let mut pending = vec![(directory.to_path_buf(), None::<String>)];
+    while let Some((dir, top_level)) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if file_type.is_dir() {
+                if top_level.is_none() && name == STATE_DIR {
+                    continue;
+                }
+                pending.push((entry.path(), Some(top_level.clone().unwrap_or(name))));
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            let size = metadata.len();
+            let modified: DateTime<Utc> = metadata.modified().map(Into::into).unwrap_or(now);
+
+            report.total_bytes += size;
+            report.total_files += 1;
+
+            let directory_name = top_level.clone().unwrap_or_else(|| ".".to_string());
+            add(by_directory.entry(directory_name.clone()).or_insert_with(|| entry_named(directory_name)), size);
+
+            let category = mime_category(&entry.path());
+            add(by_category.entry(category.clone()).or_insert_with(|| entry_named(category)), size);
+
+            let age = now.signed_duration_since(modified);
+            let bucket = AGE_BUCKETS
+                .iter()
+                .position(|(_, days)| age < Duration::days(*days))
+                .unwrap_or(AGE_BUCKETS.len());
+            add(&mut by_age[bucket], size);
+        }
+    }
+
+    report.by_directory = sorted_by_size(by_directory);
+    report.by_category = sorted_by_size(by_category);
+    report.by_age = by_age;
+    Ok(report)
+}
+
+/// Bytes and files under one folder, however deep; symlinks are not followed
+pub fn folder_size(directory: &Path) -> std::io::Result<(u64, u64)> {
+    let (mut bytes, mut files) = (0, 0);
+    let mut pending = vec![directory.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                bytes += entry.metadata()?.len();
+                files += 1;
+            }
+        }
+    }
+    Ok((bytes, files))
+}
+
+fn mime_category(path: &Path) -> String {
+    from_path(path)
+        .first()
+        .map(|mime| mime.type_().to_string())
+        .unwrap_or_else(|| "other".to_string())
+}
+
+fn entry_named(name: String) -> UsageEntry {
+    UsageEntry { name, ..Default::default() }
+}
+
+fn add(entry: &mut UsageEntry, size: u64) {
+    entry.bytes += size;
+    entry.files += 1;
+}
+
+fn sorted_by_size(entries: BTreeMap<String, UsageEntry>) -> Vec<UsageEntry> {
+    let mut entries: Vec<_> = entries.into_values().collect();
+    entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn find<'a>(entries: &'a [UsageEntry], name: &str) -> &'a UsageEntry {
+        entries.iter().find(|e| e.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_compute_usage() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "12345").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("photos/2024")).unwrap();
+        std::fs::write(temp_dir.path().join("photos/a.jpg"), "1234567890").unwrap();
+        std::fs::write(temp_dir.path().join("photos/2024/b.jpg"), "1234567890").unwrap();
+        // RustDrop's own state is not part of the share
+        std::fs::create_dir_all(temp_dir.path().join(STATE_DIR)).unwrap();
+        std::fs::write(temp_dir.path().join(STATE_DIR).join("metadata.json"), "{}").unwrap();
+
+        let report = compute_usage(temp_dir.path(), Utc::now()).unwrap();
+
+        assert_eq!(report.total_bytes, 25);
+        assert_eq!(report.total_files, 3);
+
+        assert_eq!(report.by_directory[0], UsageEntry { name: "photos".to_string(), bytes: 20, files: 2 });
+        assert_eq!(find(&report.by_directory, ".").bytes, 5);
+
+        assert_eq!(find(&report.by_category, "image").bytes, 20);
+        assert_eq!(find(&report.by_category, "text").bytes, 5);
+
+        assert_eq!(report.by_age.len(), 5);
+        assert_eq!(find(&report.by_age, "day").files, 3);
+    }
+
+    #[test]
+    fn test_folder_size() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("DCIM/100APPLE")).unwrap();
+        std::fs::write(temp_dir.path().join("DCIM/a.jpg"), "12345").unwrap();
+        std::fs::write(temp_dir.path().join("DCIM/100APPLE/b.jpg"), "1234567890").unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "12345").unwrap();
+
+        assert_eq!(folder_size(&temp_dir.path().join("DCIM")).unwrap(), (15, 2));
+        assert!(folder_size(&temp_dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_age_buckets() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("file.bin"), "x").unwrap();
+
+        let later = Utc::now() + Duration::days(400);
+        let report = compute_usage(temp_dir.path(), later).unwrap();
+
+        assert_eq!(find(&report.by_age, OLDER_BUCKET).files, 1);
+        assert_eq!(find(&report.by_age, "day").files, 0);
+    }
+}
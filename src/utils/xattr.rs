@@ -0,0 +1,81 @@
+//! Extended attributes, which carry macOS metadata such as Finder tags, color labels and
+//! quarantine flags
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use tracing::debug;
+
+/// Attributes larger than this (big resource forks, mostly) are not carried along
+const MAX_VALUE_SIZE: usize = 256 * 1024;
+
+/// Every extended attribute of `path` small enough to carry along
+#[cfg(unix)]
+pub fn read_xattrs(path: &Path) -> io::Result<BTreeMap<String, Vec<u8>>> {
+    let mut names = vec![0u8; rustix::fs::listxattr(path, &mut [0u8; 0][..])?];
+    let length = rustix::fs::listxattr(path, &mut names[..])?;
+    names.truncate(length);
+
+    let mut xattrs = BTreeMap::new();
+    for name in names.split(|byte| *byte == 0).filter(|name| !name.is_empty()) {
+        let Ok(name) = std::str::from_utf8(name) else {
+            continue;
+        };
+        let size = rustix::fs::getxattr(path, name, &mut [0u8; 0][..])?;
+        if size > MAX_VALUE_SIZE {
+            debug!("Leaving out extended attribute {} of {:?} ({} bytes)", name, path, size);
+            continue;
+        }
+        let mut value = vec![0u8; size];
+        let length = rustix::fs::getxattr(path, name, &mut value[..])?;
+        value.truncate(length);
+        xattrs.insert(name.to_string(), value);
+    }
+    Ok(xattrs)
+}
+
+#[cfg(not(unix))]
+pub fn read_xattrs(_path: &Path) -> io::Result<BTreeMap<String, Vec<u8>>> {
+    Ok(BTreeMap::new())
+}
+
+/// Set the given extended attributes on `path`, keeping any others it already has
+#[cfg(unix)]
+pub fn write_xattrs(path: &Path, xattrs: &BTreeMap<String, Vec<u8>>) -> io::Result<()> {
+    for (name, value) in xattrs {
+        rustix::fs::setxattr(path, name.as_str(), value, rustix::fs::XattrFlags::empty())?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn write_xattrs(_path: &Path, _xattrs: &BTreeMap<String, Vec<u8>>) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_xattrs_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source.txt");
+        let target = temp_dir.path().join("target.txt");
+        std::fs::write(&source, "tagged").unwrap();
+        std::fs::write(&target, "tagged").unwrap();
+
+        let xattrs = BTreeMap::from([("user.rustdrop.label".to_string(), b"red".to_vec())]);
+        if write_xattrs(&source, &xattrs).is_err() {
+            // The temporary directory's file system has no extended attributes
+            return;
+        }
+
+        let read = read_xattrs(&source).unwrap();
+        assert_eq!(read.get("user.rustdrop.label").map(Vec::as_slice), Some(&b"red"[..]));
+
+        write_xattrs(&target, &read).unwrap();
+        assert_eq!(read_xattrs(&target).unwrap(), read);
+    }
+}
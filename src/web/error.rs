@@ -0,0 +1,121 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use tracing::error;
+
+use crate::core::events::ServerEvent;
+use crate::core::quota::QuotaError;
+use crate::core::storage::StorageProblem;
+use crate::web::request_id::current_request_id;
+use crate::web::state::AppState;
+
+/// Handler error carrying a status and, where it helps the user, a readable message.
+///
+/// Plain status codes convert into it, so handlers can keep using `?` on them.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: Option<String>,
+    /// Fields added to the body next to the message, for clients that act on the error
+    pub details: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: Some(message.into()),
+            details: None,
+        }
+    }
+
+    pub fn with_detail(mut self, name: &str, value: impl serde::Serialize) -> Self {
+        let value = serde_json::to_value(value).unwrap_or_default();
+        self.details.get_or_insert_with(Default::default).insert(name.to_string(), value);
+        self
+    }
+
+    /// Turn an IO error while storing an upload into 507 (disk full), 403 (not writable) or 500.
+    ///
+    /// Disk and permission problems are recorded for `/api/health` and announced in the
+    /// terminal the first time they occur.
+    pub fn storage(state: &AppState, what: &str, e: &std::io::Error) -> Self {
+        error!("Failed to {}: {}", what, e);
+
+        let Some(problem) = StorageProblem::of(e) else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into();
+        };
+        if state.storage.report(problem, e.to_string()) {
+            if let Some(report) = state.storage.current() {
+                state.events.publish(ServerEvent::StorageProblem(report));
+            }
+            eprintln!();
+            eprintln!("⚠️  {}: uploads will fail until this is fixed ({})", problem.message(), e);
+            eprintln!();
+        }
+
+        let status = match problem {
+            StorageProblem::DiskFull => StatusCode::INSUFFICIENT_STORAGE,
+            StorageProblem::NotWritable => StatusCode::FORBIDDEN,
+        };
+        Self::new(status, problem.message())
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self { status, message: None, details: None }
+    }
+}
+
+impl From<QuotaError> for ApiError {
+    fn from(e: QuotaError) -> Self {
+        let QuotaError::Full { max_size, .. } = e;
+        Self::new(StatusCode::INSUFFICIENT_STORAGE, e.to_string()).with_detail("max_size", max_size)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self.message {
+            Some(message) => {
+                let mut body = self.details.unwrap_or_default();
+                body.insert("error".to_string(), json!(message));
+                if let Some(request_id) = current_request_id() {
+                    body.insert("request_id".to_string(), json!(request_id));
+                }
+                (self.status, Json(body)).into_response()
+            }
+            None => self.status.into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::DeviceInfo;
+    use std::io::{Error, ErrorKind};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_storage_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+
+        let error = ApiError::storage(&state, "write", &Error::from(ErrorKind::NotFound));
+        assert_eq!(error.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(state.storage.current().is_none());
+
+        let error = ApiError::storage(&state, "write", &Error::from(ErrorKind::PermissionDenied));
+        assert_eq!(error.status, StatusCode::FORBIDDEN);
+
+        let error = ApiError::storage(&state, "write", &Error::from(ErrorKind::StorageFull));
+        assert_eq!(error.status, StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(error.message.as_deref(), Some(StorageProblem::DiskFull.message()));
+        assert_eq!(state.storage.current().unwrap().problem, StorageProblem::DiskFull);
+    }
+}
@@ -0,0 +1,234 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, Extensions, Method, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::core::alerts::Alert;
+use crate::web::session::{client_ip, ClientId};
+use crate::web::state::AppState;
+
+/// Paths a client may use before it entered a code. Shutting down checks its own token; the
+/// welcome page helps people who cannot connect yet; the gallery only lists published files.
+const OPEN_PATHS: &[&str] = &[
+    "/api/access",
+    "/api/health",
+    "/api/instance/shutdown",
+    "/api/network",
+    "/api/public",
+    "/api/version",
+    "/icon.svg",
+    "/manifest.webmanifest",
+    "/public",
+    "/sw.js",
+    "/welcome",
+];
+
+/// Encrypted share links are meant for people without an access code; the passphrase protects them.
+/// Short codes only redirect, the page they lead to is checked on its own. Gallery files check
+/// that they were published.
+const LINK_PREFIXES: &[&str] = &["/s/", "/api/links/", "/j/", "/public/"];
+
+/// Where the virtual printer takes jobs. Printing systems cannot enter codes, so it is open
+/// whenever the host enabled it.
+pub const PRINTER_PATH: &str = "/ipp/print";
+
+#[derive(Debug, Deserialize)]
+pub struct AccessCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccessStatus {
+    /// Whether this server asks new clients for a code
+    pub required: bool,
+    /// Whether the requesting client may use the server
+    pub approved: bool,
+    /// Whether the code is the fixed PIN shown next to the QR code
+    #[serde(default)]
+    pub pin: bool,
+}
+
+/// Middleware turning away clients that have not entered an access code yet.
+///
+/// Must run inside `client_session`, which identifies the client. Whenever a new code is
+/// needed it is printed to the host terminal.
+pub async fn access_guard(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let client = request.extensions().get::<ClientId>().cloned();
+    let approved = client.is_some_and(|client| state.access.is_approved(client.as_str()));
+    let link = request.method() == Method::GET && LINK_PREFIXES.iter().any(|prefix| path.starts_with(prefix));
+    let printer = state.config.printer.enabled && path == PRINTER_PATH;
+
+    if approved || link || printer || OPEN_PATHS.contains(&path) {
+        return next.run(request).await;
+    }
+
+    announce_code(&state);
+
+    if path.starts_with("/api/") {
+        let body = serde_json::json!({ "error": "access code required" });
+        (StatusCode::UNAUTHORIZED, Json(body)).into_response()
+    } else {
+        (StatusCode::UNAUTHORIZED, Html(CODE_PAGE)).into_response()
+    }
+}
+
+/// Middleware alerting the host when an unexpected client uses the API, whether or not it
+/// is let in. Must run inside `client_session`.
+pub async fn alert_unexpected_access(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if request.uri().path().starts_with("/api/") {
+        let client = request.extensions().get::<ClientId>().cloned();
+        let paired = client.as_ref().is_some_and(|client| state.access.is_paired(client.as_str()));
+        let ip = client_ip(request.extensions());
+        if let Some(reason) = state.alerts.reason(ip, paired) {
+            state.alerts.raise(Alert {
+                reason,
+                client: client.map_or_else(|| ip.map(|ip| ip.to_string()).unwrap_or_default(), |client| client.0),
+                ip,
+                path: request.uri().path().to_string(),
+                user_agent: request
+                    .headers()
+                    .get(header::USER_AGENT)
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| value.to_string()),
+                time: chrono::Utc::now(),
+            });
+        }
+    }
+    next.run(request).await
+}
+
+/// Whether the requesting client still has to enter a code
+pub async fn get_access_status(State(state): State<AppState>, client: ClientId) -> Json<AccessStatus> {
+    Json(AccessStatus {
+        required: state.access.is_enabled(),
+        approved: state.access.is_approved(client.as_str()),
+        pin: state.access.uses_pin(),
+    })
+}
+
+/// Approve the requesting client if it knows the code shown on the host
+pub async fn submit_access_code(
+    State(state): State<AppState>,
+    client: ClientId,
+    extensions: Extensions,
+    Json(request): Json<AccessCodeRequest>,
+) -> StatusCode {
+    if !state.access.is_enabled() {
+        return StatusCode::NO_CONTENT;
+    }
+
+    match state.access.verify(client.as_str(), client_ip(&extensions), &request.code, chrono::Utc::now()) {
+        Ok(true) => {
+            info!("Approved a new client with {}", if state.access.uses_pin() { "the PIN" } else { "an access code" });
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => {
+            warn!("Rejected a wrong or expired {}", if state.access.uses_pin() { "PIN" } else { "access code" });
+            StatusCode::FORBIDDEN
+        }
+        Err(e) => {
+            error!("Failed to save approved client: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+fn announce_code(state: &AppState) {
+    if let Some((code, true)) = state.access.current_code(chrono::Utc::now()) {
+        println!();
+        println!("🔑 A new device wants to connect. Access code: {}", code);
+        println!(
+            "   Valid for {} seconds, enter it in the browser or run `rustdrop pair <this device> {}`",
+            state.config.access.code_lifetime_secs, code
+        );
+        println!();
+    }
+}
+
+const CODE_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>RustDrop - Access Code</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
+            max-width: 400px;
+            margin: 80px auto;
+            padding: 20px;
+            color: #333;
+            text-align: center;
+        }
+        h1 {
+            color: #2c3e50;
+        }
+        input {
+            font-size: 2em;
+            letter-spacing: 0.3em;
+            width: 8em;
+            text-align: center;
+            padding: 8px;
+            border: 1px solid #ddd;
+            border-radius: 8px;
+        }
+        button {
+            margin-top: 16px;
+            background-color: #3498db;
+            color: white;
+            border: none;
+            padding: 10px 20px;
+            border-radius: 4px;
+            cursor: pointer;
+            font-size: 1em;
+        }
+        #message {
+            margin-top: 16px;
+            color: #e74c3c;
+            min-height: 1.2em;
+        }
+    </style>
+</head>
+<body>
+    <h1>RustDrop</h1>
+    <p id="prompt">Enter the access code shown in the terminal of the device you are connecting to.</p>
+    <form id="code-form">
+        <input id="code" inputmode="numeric" autocomplete="one-time-code" maxlength="32" autofocus>
+        <br>
+        <button type="submit">Connect</button>
+    </form>
+    <div id="message"></div>
+    <script>
+        let usesPin = false;
+        fetch('/api/access').then(response => response.json()).then(status => {
+            usesPin = status.pin;
+            if (usesPin) {
+                document.getElementById('prompt').textContent = 'Enter the PIN shown next to the QR code on the device you are connecting to.';
+            }
+        }).catch(() => {});
+
+        document.getElementById('code-form').addEventListener('submit', async (event) => {
+            event.preventDefault();
+            const message = document.getElementById('message');
+            const response = await fetch('/api/access', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ code: document.getElementById('code').value })
+            });
+            if (response.ok) {
+                window.location.reload();
+            } else {
+                message.textContent = usesPin
+                    ? 'Wrong PIN, or too many tries. Wait a minute and try again.'
+                    : 'Wrong or expired code, or too many tries. Check the terminal and try again.';
+            }
+        });
+    </script>
+</body>
+</html>"#;
@@ -1,129 +1,524 @@
 use axum::{
-    extract::{Path, State, Multipart},
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
+    body::Body,
+    extract::{Path, Query, State, Multipart},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use serde_json::json;
-use std::path::PathBuf;
-use tokio::io::AsyncWriteExt;
-use tracing::{info, error};
-
-use crate::core::models::{DeviceInfo, FileInfo};
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tracing::{info, error, warn};
+
+use crate::core::capabilities::{Capabilities, Encryption, HashAlgorithm, UploadMethod};
+use crate::core::clients::client_handle;
+use crate::core::history::{HistoryAction, HistoryEntry};
+use crate::core::metadata::normalize_tag;
+use crate::core::models::{ConflictResolution, ConflictingFile, DeviceInfo, FileInfo, FileType, QuicInfo, UploadConflict};
+use crate::core::transcode;
 use crate::discovery::ServiceDiscovery;
-use crate::utils::file::{get_file_info, list_directory};
+use crate::utils::collation::{sort_files, Collation, SortKey, SortOrder};
+use crate::utils::file::{get_share_file_info, is_hidden, resolve_relative_path, set_modified, PartialFile, PARTIAL_UPLOAD_SUFFIX};
+use crate::utils::filename::{host_name, long_path, unused_path, valid_file_name};
+use crate::utils::hash::hash_file;
+use crate::utils::http::{content_disposition, insert_file_headers, is_inline_safe, lossy_file_name, parse_range, RangeRequest};
+use crate::utils::organize::{upload_subdirectory, EXIF_SCAN_BYTES};
+use crate::web::error::ApiError;
+use crate::web::session::{sender_name, ClientId};
+use crate::web::state::AppState;
+use crate::web::version::ApiVersions;
+
+/// Header carrying a file's modification time as RFC 3339, on uploads and downloads
+pub const MODIFIED_HEADER: &str = "x-rustdrop-modified";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListFilesQuery {
+    /// Only include files carrying this tag
+    pub tag: Option<String>,
+    /// Include hidden and system files, defaults to `files.show_hidden`
+    pub hidden: Option<bool>,
+    /// Order files by name (the default), size or modification time
+    pub sort: Option<SortKey>,
+    /// How names compare, byte-wise unless given
+    pub collation: Option<Collation>,
+    pub order: Option<SortOrder>,
+    /// Only include files of this kind, judged by MIME type
+    #[serde(rename = "type")]
+    pub file_type: Option<FileType>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TypeCount {
+    #[serde(rename = "type")]
+    pub file_type: FileType,
+    pub count: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DownloadQuery {
+    /// Ask the browser to display the file instead of saving it. Only honored for types that
+    /// cannot run script, see [`is_inline_safe`].
+    #[serde(default)]
+    pub inline: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UploadQuery {
+    /// Store the upload in this directory (relative to the share) instead of the automatic location
+    pub dir: Option<String>,
+    /// What to do when a file of the same name exists, ask (answer 409) unless given
+    #[serde(default)]
+    pub conflict: ConflictResolution,
+}
 
-pub async fn health_check() -> Json<serde_json::Value> {
+pub async fn health_check(State(state): State<AppState>) -> Json<serde_json::Value> {
+    // Uploads failing for lack of space or permissions leave the server degraded until one succeeds
+    let storage = state.storage.current();
     Json(json!({
-        "status": "healthy",
+        "status": if storage.is_some() { "degraded" } else { "healthy" },
         "timestamp": chrono::Utc::now().to_rfc3339(),
         "version": env!("CARGO_PKG_VERSION"),
-        "service": "rustdrop"
+        "service": "rustdrop",
+        "storage": storage.map(|report| json!({
+            "problem": report.problem,
+            "message": report.problem.message(),
+            "since": report.since,
+            "detail": report.detail,
+        }))
     }))
 }
 
 pub async fn get_device_info(
-    State((_, device_info)): State<(PathBuf, DeviceInfo)>,
+    State(state): State<AppState>,
 ) -> Json<DeviceInfo> {
-    Json(device_info)
+    Json(state.current_device_info())
+}
+
+/// The range of API versions this server speaks
+pub async fn get_api_version() -> Json<ApiVersions> {
+    Json(ApiVersions::supported())
+}
+
+/// What this device accepts, for senders to pick how to push files before they do
+pub async fn get_capabilities(State(state): State<AppState>) -> Json<Capabilities> {
+    let mut uploads = vec![UploadMethod::Resumable, UploadMethod::Sparse, UploadMethod::Tus, UploadMethod::Multipart];
+    // A snapshot is read-only
+    if state.snapshot.is_some() {
+        uploads.clear();
+    }
+    let mut encryption = Vec::new();
+    if state.current_device_info().tls {
+        encryption.push(Encryption::Tls);
+    }
+    if state.quic.is_some() {
+        encryption.push(Encryption::Quic);
+    }
+    Json(Capabilities {
+        max_file_size: state.config.server.max_file_size,
+        hash_algorithms: vec![HashAlgorithm::Sha256],
+        uploads,
+        encryption,
+    })
+}
+
+/// How to reach this device over QUIC, 404 when the transport is off
+pub async fn get_quic_info(
+    State(state): State<AppState>,
+) -> Result<Json<QuicInfo>, StatusCode> {
+    state.quic.map(Json).ok_or(StatusCode::NOT_FOUND)
 }
 
 pub async fn list_files(
-    State((directory, _)): State<(PathBuf, DeviceInfo)>,
+    State(state): State<AppState>,
+    client: Option<ClientId>,
+    Query(query): Query<ListFilesQuery>,
 ) -> Result<Json<Vec<FileInfo>>, StatusCode> {
-    match list_directory(&directory) {
-        Ok(files) => Ok(Json(files)),
-        Err(e) => {
-            error!("Failed to list directory: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    let mut files = state.list_share().map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if !query.hidden.unwrap_or(state.config.files.show_hidden) {
+        files.retain(|f| !is_hidden(&f.name));
+    }
+
+    for file in files.iter_mut() {
+        state.metadata.annotate(file);
+    }
+
+    if let Some(tag) = query.tag.as_deref() {
+        let tag = normalize_tag(tag).ok_or(StatusCode::BAD_REQUEST)?;
+        files.retain(|f| f.tags.contains(&tag));
+    }
+
+    if let Some(file_type) = query.file_type {
+        files.retain(|f| f.file_type() == file_type);
+    }
+
+    if query.sort.is_some() || query.collation.is_some() || query.order.is_some() {
+        sort_files(
+            &mut files,
+            query.sort.unwrap_or_default(),
+            query.collation.unwrap_or_default(),
+            query.order.unwrap_or_default(),
+        );
+    }
+
+    if let Some(client) = client {
+        let pinned = state.metadata.pinned(client.as_str());
+        for file in files.iter_mut() {
+            file.pinned = pinned.contains(&file.name);
         }
+        // Stable sort keeps the order within pinned and unpinned files
+        files.sort_by_key(|f| !f.pinned);
+    }
+
+    Ok(Json(files))
+}
+
+/// Count the listed files of each kind, for the type filter chips. Takes the same filters as
+/// `list_files` except the type itself, and leaves out kinds with no files.
+pub async fn list_file_types(
+    State(state): State<AppState>,
+    Query(query): Query<ListFilesQuery>,
+) -> Result<Json<Vec<TypeCount>>, StatusCode> {
+    let filter = ListFilesQuery { file_type: None, ..query };
+    let Json(files) = list_files(State(state), None, Query(filter)).await?;
+
+    let mut counts: BTreeMap<FileType, usize> = BTreeMap::new();
+    for file in &files {
+        *counts.entry(file.file_type()).or_default() += 1;
     }
+    Ok(Json(
+        counts
+            .into_iter()
+            .map(|(file_type, count)| TypeCount { file_type, count })
+            .collect(),
+    ))
 }
 
 pub async fn upload_file(
-    State((directory, _)): State<(PathBuf, DeviceInfo)>,
+    State(state): State<AppState>,
+    client_id: Option<ClientId>,
+    Query(query): Query<UploadQuery>,
+    request_headers: HeaderMap,
     mut multipart: Multipart,
-) -> Result<Json<FileInfo>, StatusCode> {
+) -> Result<Json<FileInfo>, ApiError> {
     info!("Upload request received");
     
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
+    // The request's length is a close enough guess at the size of the files it carries, to refuse
+    // early what cannot fit; the limit itself is applied to what arrives
+    let incoming = request_headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok())
+        .unwrap_or(0);
+    state.check_room(incoming)?;
+    
+    // The original modification time comes as a header, or as a `modified` field before the file
+    let mut modified = request_headers
+        .get(MODIFIED_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_modified);
+    
+    while let Some(mut field) = multipart.next_field().await.map_err(|e| {
         error!("Failed to read multipart field: {}", e);
-        StatusCode::BAD_REQUEST
+        e.status()
     })? {
-        let file_name = field.file_name().ok_or_else(|| {
-            error!("File name is missing from multipart field");
-            StatusCode::BAD_REQUEST
-        })?.to_string();
+        if field.name() == Some("modified") {
+            let text = field.text().await.map_err(|e| {
+                error!("Failed to read modified field: {}", e);
+                StatusCode::BAD_REQUEST
+            })?;
+            modified = parse_modified(&text).or(modified);
+            continue;
+        }
+        
+        let file_name = field
+            .file_name()
+            .map(str::to_string)
+            .or_else(|| {
+                let disposition = field.headers().get(header::CONTENT_DISPOSITION)?;
+                lossy_file_name(disposition.as_bytes())
+            })
+            .ok_or_else(|| {
+                error!("File name is missing from multipart field");
+                StatusCode::BAD_REQUEST
+            })?;
         
         info!("Processing file upload: {}", file_name);
         
         // Validate filename
-        if file_name.is_empty() {
-            error!("Empty filename provided");
-            return Err(StatusCode::BAD_REQUEST);
+        if !valid_file_name(&file_name) {
+            error!("Invalid filename provided: {:?}", file_name);
+            return Err(StatusCode::BAD_REQUEST.into());
         }
         
-        let file_path = directory.join(&file_name);
-        info!("File will be saved to: {:?}", file_path);
-        
-        // Create the file
-        let mut file = tokio::fs::File::create(&file_path).await.map_err(|e| {
-            error!("Failed to create file {:?}: {}", file_path, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-        
-        // Write the file data
-        let data = field.bytes().await.map_err(|e| {
+        // Only the start is held in memory, to sort photos by date; the rest goes straight to
+        // disk, so files larger than the host's memory can be uploaded. Going over the body limit
+        // shows up here as 413.
+        let read_error = |e: axum::extract::multipart::MultipartError| {
             error!("Failed to read file data for {}: {}", file_name, e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::from(e.status())
+        };
+        let max_file_size = state.config.server.max_file_size;
+        let too_large = || {
+            error!("Upload of {} is larger than {} bytes", file_name, max_file_size);
+            ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, format!("Files may be at most {} bytes", max_file_size))
+        };
+        let mut head = Vec::new();
+        while head.len() < EXIF_SCAN_BYTES {
+            match field.chunk().await.map_err(read_error)? {
+                Some(chunk) => head.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        if head.len() as u64 > max_file_size {
+            return Err(too_large());
+        }
+        
+        // Sort into sender and date folders when configured to, unless the client picked a directory
+        let sender = sender_name(&request_headers);
+        let target_dir = match &query.dir {
+            Some(dir) => resolve_relative_path(&state.directory, dir).ok_or_else(|| {
+                error!("Invalid upload directory: {}", dir);
+                StatusCode::BAD_REQUEST
+            })?,
+            None => state.directory.join(upload_subdirectory(
+                &state.config.files,
+                sender.as_deref(),
+                &head,
+                chrono::Utc::now(),
+            )),
+        };
+        tokio::fs::create_dir_all(&target_dir).await.map_err(|e| {
+            ApiError::storage(&state, &format!("create directory {:?}", target_dir), &e)
         })?;
         
-        info!("Received {} bytes for file {}", data.len(), file_name);
+        let mut file_path = long_path(target_dir.join(host_name(&file_name)));
+        let taken = tokio::fs::try_exists(&file_path).await.unwrap_or(false);
+        if taken {
+            match query.conflict {
+                ConflictResolution::Replace | ConflictResolution::Ask => {}
+                ConflictResolution::KeepBoth => file_path = unused_path(file_path),
+                ConflictResolution::Skip => {
+                    info!("Skipped upload of {}, which exists already", file_name);
+                    return existing_file(&state, &file_path).map(Json);
+                }
+            }
+        }
+        // The upload waits next to its place until it is complete, so a file it may replace stays
+        // intact, an abandoned upload never shows up in the share, and the share's limit applies to
+        // what actually arrived. When asking, it is compared with the existing file first.
+        let ask = taken && query.conflict == ConflictResolution::Ask;
+        let write_path = unused_path(target_dir.join(format!(".{}{}", host_name(&file_name), PARTIAL_UPLOAD_SUFFIX)));
+        info!("File will be saved to: {:?}", file_path);
         
-        file.write_all(&data).await.map_err(|e| {
-            error!("Failed to write file data for {}: {}", file_name, e);
-            StatusCode::INTERNAL_SERVER_ERROR
+        // Create the file
+        let mut file = tokio::fs::File::create(&write_path).await.map_err(|e| {
+            ApiError::storage(&state, &format!("create file {:?}", write_path), &e)
         })?;
+        // Removed again if writing fails or the client disconnects before the file is complete
+        let partial = PartialFile::new(write_path.clone());
         
-        // Ensure data is flushed to disk
-        file.flush().await.map_err(|e| {
-            error!("Failed to flush file {}: {}", file_name, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        // Write the file data as it arrives and make sure it reached the disk
+        let mut received = head.len() as u64;
+        if let Err(e) = file.write_all(&head).await {
+            return Err(ApiError::storage(&state, &format!("write file data for {}", file_name), &e));
+        }
+        drop(head);
+        while let Some(chunk) = field.chunk().await.map_err(read_error)? {
+            received += chunk.len() as u64;
+            if received > max_file_size {
+                return Err(too_large());
+            }
+            if let Err(e) = file.write_all(&chunk).await {
+                return Err(ApiError::storage(&state, &format!("write file data for {}", file_name), &e));
+            }
+        }
+        let written = async {
+            file.flush().await?;
+            file.sync_all().await
+        }
+        .await;
+        drop(file);
         
-        file.sync_all().await.map_err(|e| {
-            error!("Failed to sync file {}: {}", file_name, e);
-            StatusCode::INTERNAL_SERVER_ERROR
+        if let Err(e) = written {
+            return Err(ApiError::storage(&state, &format!("write file data for {}", file_name), &e));
+        }
+        if ask {
+            match upload_conflict(&state, &file_path, &write_path, &file_name, modified).await? {
+                Some(conflict) => return Err(conflict_error(conflict)),
+                None => {
+                    info!("Upload of {} is identical to the existing file", file_name);
+                    return existing_file(&state, &file_path).map(Json);
+                }
+            }
+        }
+        let replacing = (taken && query.conflict == ConflictResolution::Replace).then_some(file_path.as_path());
+        state.make_room(received, replacing.as_slice())?;
+        tokio::fs::rename(&write_path, &file_path).await.map_err(|e| {
+            ApiError::storage(&state, &format!("store {:?}", file_path), &e)
         })?;
+        partial.keep();
+        info!("Received {} bytes for file {}", received, file_name);
+        state.storage.clear();
         
-        // Drop the file handle to ensure it's closed
-        drop(file);
+        if let Some(modified) = modified {
+            if let Err(e) = set_modified(&file_path, modified) {
+                warn!("Failed to set modification time of {:?}: {}", file_path, e);
+            }
+        }
         
         // Get file info
-        let file_info = get_file_info(&file_path).map_err(|e| {
+        let mut file_info = get_share_file_info(&state.directory, &file_path).map_err(|e| {
             error!("Failed to get file info for {}: {}", file_name, e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
         
+        record_upload(&state, &mut file_info, sender, client_id.map(|id| id.0));
+        
         info!("File uploaded successfully: {} ({}  bytes)", file_name, file_info.size);
         return Ok(Json(file_info));
     }
     
     error!("No file found in multipart request");
-    Err(StatusCode::BAD_REQUEST)
+    Err(StatusCode::BAD_REQUEST.into())
+}
+
+/// The file of the share at `path`, for uploads that left it as it was
+pub(crate) fn existing_file(state: &AppState, path: &std::path::Path) -> Result<FileInfo, ApiError> {
+    let mut file_info = get_share_file_info(&state.directory, path).map_err(|e| {
+        error!("Failed to get file info for {:?}: {}", path, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    state.metadata.annotate(&mut file_info);
+    Ok(file_info)
+}
+
+/// The file at `existing` and the upload received at `received`, for the client to choose
+/// between. None when both have the same content, which leaves nothing to choose.
+pub(crate) async fn upload_conflict(
+    state: &AppState,
+    existing: &std::path::Path,
+    received: &std::path::Path,
+    name: &str,
+    modified: Option<DateTime<Utc>>,
+) -> Result<Option<UploadConflict>, ApiError> {
+    let share = state.directory.clone();
+    let (existing, received, name) = (existing.to_path_buf(), received.to_path_buf(), name.to_string());
+    // Hashing reads both files in full, keep it off the async workers
+    let compared = tokio::task::spawn_blocking(move || -> anyhow::Result<Option<UploadConflict>> {
+        let existing_hash = hash_file(&existing)?;
+        let incoming_hash = hash_file(&received)?;
+        if existing_hash == incoming_hash {
+            return Ok(None);
+        }
+        let file_info = get_share_file_info(&share, &existing)?;
+        Ok(Some(UploadConflict {
+            existing: ConflictingFile {
+                name: file_info.name,
+                size: file_info.size,
+                modified: Some(file_info.modified),
+                sha256: existing_hash,
+            },
+            incoming: ConflictingFile {
+                name,
+                size: std::fs::metadata(&received)?.len(),
+                modified,
+                sha256: incoming_hash,
+            },
+        }))
+    })
+    .await;
+
+    match compared {
+        Ok(Ok(conflict)) => Ok(conflict),
+        Ok(Err(e)) => {
+            error!("Failed to compare upload with the existing file: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+        Err(e) => {
+            error!("Upload comparison task failed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+/// 409 describing both files, answered until the client picks a resolution
+pub(crate) fn conflict_error(conflict: UploadConflict) -> ApiError {
+    let message = format!("A different file named {} exists already", conflict.existing.name);
+    ApiError::new(StatusCode::CONFLICT, message).with_detail("conflict", conflict)
+}
+
+/// Read a modification time sent along with an upload, ignoring malformed values
+pub(crate) fn parse_modified(value: &str) -> Option<DateTime<Utc>> {
+    match DateTime::parse_from_rfc3339(value.trim()) {
+        Ok(modified) => Some(modified.to_utc()),
+        Err(e) => {
+            warn!("Ignoring invalid modification time {:?}: {}", value, e);
+            None
+        }
+    }
+}
+
+/// Attribute a freshly stored upload to its sender and log it in the history
+pub(crate) fn record_upload(
+    state: &AppState,
+    file_info: &mut FileInfo,
+    sender: Option<String>,
+    client_id: Option<String>,
+) {
+    // The file is already stored, so bookkeeping failures are logged rather than failing the upload
+    if let Err(e) = state.metadata.set_sender(&file_info.name, sender.as_deref()) {
+        warn!("Failed to record sender for {}: {}", file_info.name, e);
+    }
+
+    let mut entry = HistoryEntry::new(HistoryAction::Upload, &file_info.name);
+    entry.size = file_info.size;
+    entry.sender = sender;
+    entry.client_id = client_id.as_deref().map(client_handle);
+    if let Err(e) = state.record_history(&entry) {
+        warn!("Failed to record upload history for {}: {}", file_info.name, e);
+    }
+
+    state.metadata.annotate(file_info);
+
+    if state.backend.is_some() || !state.plugins.is_empty() || state.config.transcode.background {
+        let state = state.clone();
+        let file = file_info.clone();
+        // Plugins and conversions go first, as the backend may take the file out of the share
+        tokio::spawn(async move {
+            let produced = state.plugins.process(&state, &file).await;
+            if state.config.transcode.background {
+                transcode::prepare(&state.transcoder, &file).await;
+            }
+            if let Some(backend) = &state.backend {
+                for file in std::iter::once(file).chain(produced) {
+                    if let Err(e) = backend.store(&file).await {
+                        warn!("Failed to store {} in {}: {:#}", file.name, backend.describe(), e);
+                    }
+                }
+            }
+        });
+    }
 }
 
 pub async fn download_file(
-    State((directory, _)): State<(PathBuf, DeviceInfo)>,
+    State(state): State<AppState>,
+    client: Option<ClientId>,
     Path(id): Path<String>,
-) -> Result<impl IntoResponse, StatusCode> {
+    Query(query): Query<DownloadQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     // Find the file with the given ID
-    let files = list_directory(&directory).map_err(|e| {
+    let file = state.find_file(&id).map_err(|e| {
         error!("Failed to list directory: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    
-    let file = files.iter().find(|f| f.id.to_string() == id).ok_or_else(|| {
+    })?.ok_or_else(|| {
         error!("File not found: {}", id);
         StatusCode::NOT_FOUND
     })?;
@@ -131,22 +526,104 @@ pub async fn download_file(
     // Prepare headers
     let mut headers = HeaderMap::new();
     headers.insert(
-        axum::http::header::CONTENT_TYPE,
+        header::CONTENT_TYPE,
         file.mime_type.parse().unwrap(),
     );
+    let disposition = if query.inline && is_inline_safe(&file.mime_type) { "inline" } else { "attachment" };
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        content_disposition(disposition, &file.name),
+    );
+    insert_file_headers(&mut headers);
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    // Last-Modified only has whole seconds, the RustDrop header keeps the full precision
     headers.insert(
-        axum::http::header::CONTENT_DISPOSITION,
-        format!("attachment; filename=\"{}\"", file.name).parse().unwrap(),
+        header::LAST_MODIFIED,
+        file.modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string().parse().unwrap(),
     );
+    headers.insert(
+        MODIFIED_HEADER,
+        file.modified.to_rfc3339_opts(SecondsFormat::AutoSi, true).parse().unwrap(),
+    );
+    
+    let range_header = request_headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    let range = match parse_range(range_header, file.size) {
+        RangeRequest::Full => None,
+        RangeRequest::Partial(range) => Some(range),
+        RangeRequest::Unsatisfiable => {
+            headers.insert(
+                header::CONTENT_RANGE,
+                format!("bytes */{}", file.size).parse().unwrap(),
+            );
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+    };
+    
+    // Previews and the later parts of a resumed or streamed download are not new transfers
+    if !query.inline && range.as_ref().is_none_or(|range| range.start == 0) {
+        record_download(&state, &file, sender_name(&request_headers), client);
+    }
+    
+    // Files only the storage backend still has
+    if let Some(backend) = state.backend.as_ref().filter(|_| !file.path.exists()) {
+        let mut reader = backend.open_file(&file.name).await.map_err(|e| {
+            error!("Failed to read {} from {}: {:#}", file.name, backend.describe(), e);
+            StatusCode::BAD_GATEWAY
+        })?;
+        info!("File downloaded from {}: {}", backend.describe(), file.name);
+        let Some(range) = range else {
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(file.size));
+            return Ok((headers, Body::from_stream(ReaderStream::new(reader.take(file.size)))).into_response());
+        };
+        // Backends are read from the start, the part before the range is passed over
+        tokio::io::copy(&mut (&mut reader).take(range.start), &mut tokio::io::sink()).await.map_err(|e| {
+            error!("Failed to read {} from {}: {}", file.name, backend.describe(), e);
+            StatusCode::BAD_GATEWAY
+        })?;
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(range.len()));
+        headers.insert(header::CONTENT_RANGE, range.content_range(file.size).parse().unwrap());
+        let part = Body::from_stream(ReaderStream::new(reader.take(range.len())));
+        return Ok((StatusCode::PARTIAL_CONTENT, headers, part).into_response());
+    }
+    
+    let mut handle = tokio::fs::File::open(&file.path).await.map_err(|e| {
+        error!("Failed to open file: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    
+    // The file is streamed from disk as it is sent, so large files neither wait to be read
+    // whole nor sit in memory
+    let Some(range) = range else {
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(file.size));
+        info!("File downloaded: {}", file.name);
+        return Ok((headers, Body::from_stream(ReaderStream::new(handle.take(file.size)))).into_response());
+    };
     
-    // Read the file
-    let file_data = tokio::fs::read(&file.path).await.map_err(|e| {
-        error!("Failed to read file: {}", e);
+    // Send only the requested part of the file
+    handle.seek(SeekFrom::Start(range.start)).await.map_err(|e| {
+        error!("Failed to seek in file: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
+    let file_data = Body::from_stream(ReaderStream::new(handle.take(range.len())));
     
-    info!("File downloaded: {}", file.name);
-    Ok((headers, file_data))
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(range.len()));
+    headers.insert(
+        header::CONTENT_RANGE,
+        range.content_range(file.size).parse().unwrap(),
+    );
+    
+    info!("File range downloaded: {} ({})", file.name, range.content_range(file.size));
+    Ok((StatusCode::PARTIAL_CONTENT, headers, file_data).into_response())
+}
+
+fn record_download(state: &AppState, file: &FileInfo, sender: Option<String>, client: Option<ClientId>) {
+    let mut entry = HistoryEntry::new(HistoryAction::Download, &file.name);
+    entry.size = file.size;
+    entry.sender = sender;
+    entry.client_id = client.map(|client| client_handle(client.as_str()));
+    if let Err(e) = state.record_history(&entry) {
+        warn!("Failed to record download history for {}: {}", file.name, e);
+    }
 }
 
 pub async fn discover_devices() -> Result<Json<Vec<DeviceInfo>>, StatusCode> {
@@ -169,8 +646,10 @@ pub async fn api_not_found() -> impl IntoResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::storage::StorageProblem;
     use std::fs::File;
     use std::io::Write;
+    use std::path::PathBuf;
     use tempfile::TempDir;
     use uuid::Uuid;
 
@@ -181,25 +660,34 @@ mod tests {
             ip: "127.0.0.1".to_string(),
             port: 8080,
             os: "linux".to_string(),
+            tls: false,
         }
     }
 
     #[tokio::test]
     async fn test_health_check() {
-        let response = health_check().await;
+        let temp_dir = TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_path_buf(), create_test_device_info());
+        let response = health_check(State(state.clone())).await;
         let Json(health_data) = response;
 
         assert_eq!(health_data["status"], "healthy");
         assert_eq!(health_data["service"], "rustdrop");
         assert_eq!(health_data["version"], env!("CARGO_PKG_VERSION"));
         assert!(health_data["timestamp"].is_string());
+        assert!(health_data["storage"].is_null());
+
+        state.storage.report(StorageProblem::DiskFull, "No space left on device".to_string());
+        let Json(health_data) = health_check(State(state)).await;
+        assert_eq!(health_data["status"], "degraded");
+        assert_eq!(health_data["storage"]["problem"], "disk_full");
     }
 
     #[tokio::test]
     async fn test_get_device_info() {
         let temp_dir = TempDir::new().unwrap();
         let device_info = create_test_device_info();
-        let state = (temp_dir.path().to_path_buf(), device_info.clone());
+        let state = AppState::new(temp_dir.path().to_path_buf(), device_info.clone());
 
         let response = get_device_info(State(state)).await;
         let Json(returned_device) = response;
@@ -215,9 +703,9 @@ mod tests {
     async fn test_list_files_empty_directory() {
         let temp_dir = TempDir::new().unwrap();
         let device_info = create_test_device_info();
-        let state = (temp_dir.path().to_path_buf(), device_info);
+        let state = AppState::new(temp_dir.path().to_path_buf(), device_info);
 
-        let response = list_files(State(state)).await;
+        let response = list_files(State(state), None, Query(ListFilesQuery::default())).await;
         assert!(response.is_ok());
 
         let Json(files) = response.unwrap();
@@ -237,8 +725,8 @@ mod tests {
             writeln!(file, "Test content for {}", name).unwrap();
         }
 
-        let state = (temp_dir.path().to_path_buf(), device_info);
-        let response = list_files(State(state)).await;
+        let state = AppState::new(temp_dir.path().to_path_buf(), device_info);
+        let response = list_files(State(state), None, Query(ListFilesQuery::default())).await;
         assert!(response.is_ok());
 
         let Json(files) = response.unwrap();
@@ -261,9 +749,9 @@ mod tests {
     async fn test_list_files_nonexistent_directory() {
         let device_info = create_test_device_info();
         let nonexistent_path = PathBuf::from("/nonexistent/directory");
-        let state = (nonexistent_path, device_info);
+        let state = AppState::new(nonexistent_path, device_info);
 
-        let response = list_files(State(state)).await;
+        let response = list_files(State(state), None, Query(ListFilesQuery::default())).await;
         assert!(response.is_ok());
 
         let Json(files) = response.unwrap();
@@ -273,8 +761,10 @@ mod tests {
     #[test]
     fn test_health_check_response_format() {
         // Test that health check returns expected JSON structure
+        let temp_dir = TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_path_buf(), create_test_device_info());
         tokio_test::block_on(async {
-            let response = health_check().await;
+            let response = health_check(State(state)).await;
             let Json(data) = response;
 
             // Check required fields exist
@@ -305,9 +795,10 @@ mod tests {
                 ip: "192.168.1.100".to_string(),
                 port: 9999,
                 os: "test-os".to_string(),
+                tls: false,
             };
 
-            let state = (temp_dir.path().to_path_buf(), original_device.clone());
+            let state = AppState::new(temp_dir.path().to_path_buf(), original_device.clone());
             let response = get_device_info(State(state)).await;
             let Json(extracted_device) = response;
 
@@ -334,13 +825,13 @@ mod tests {
             std::fs::write(&file_path2, r#"{"key": "value"}"#).unwrap();
 
             let file_path3 = temp_dir.path().join("binary.bin");
-            std::fs::write(&file_path3, &[0u8, 1, 2, 3, 255]).unwrap();
+            std::fs::write(&file_path3, [0u8, 1, 2, 3, 255]).unwrap();
 
             // Create a subdirectory (should be ignored)
             std::fs::create_dir(temp_dir.path().join("subdir")).unwrap();
 
-            let state = (temp_dir.path().to_path_buf(), device_info);
-            let response = list_files(State(state)).await;
+            let state = AppState::new(temp_dir.path().to_path_buf(), device_info);
+            let response = list_files(State(state), None, Query(ListFilesQuery::default())).await;
             assert!(response.is_ok());
 
             let Json(files) = response.unwrap();
@@ -0,0 +1,62 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use tracing::error;
+
+use crate::core::models::FileAttributes;
+use crate::utils::file::attributes_of;
+use crate::web::state::AppState;
+
+/// Attributes of a file for other instances restoring it after a download. Extended attributes
+/// are only included when the host preserves them.
+pub async fn get_file_attributes(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<FileAttributes>, StatusCode> {
+    let file = state.find_file(&id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    Ok(Json(attributes_of(&file, state.config.files.preserve_xattrs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::AppConfig;
+    use crate::core::models::DeviceInfo;
+    use crate::utils::file::get_file_info;
+    use crate::utils::xattr::write_xattrs;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_xattrs_only_when_preserved() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("tagged.txt");
+        std::fs::write(&file_path, "content").unwrap();
+        let xattrs = BTreeMap::from([("user.rustdrop.label".to_string(), b"red".to_vec())]);
+        if write_xattrs(&file_path, &xattrs).is_err() {
+            // The temporary directory's file system has no extended attributes
+            return;
+        }
+        let id = get_file_info(&file_path).unwrap().id.to_string();
+
+        let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+        let Json(attributes) = get_file_attributes(State(state), Path(id.clone())).await.unwrap();
+        assert!(attributes.xattrs.is_empty());
+        assert!(attributes.modified.is_some());
+
+        let mut config = AppConfig::default();
+        config.files.preserve_xattrs = true;
+        let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config);
+        let Json(attributes) = get_file_attributes(State(state), Path(id)).await.unwrap();
+        assert_eq!(attributes.xattrs, xattrs);
+    }
+}
@@ -0,0 +1,463 @@
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Html,
+    Json,
+};
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, warn};
+
+use crate::core::backup::{BackupItem, BackupSummary, MAX_KEY_LENGTH};
+use crate::core::clients::client_handle;
+use crate::core::models::FileInfo;
+use crate::utils::file::{
+    get_share_file_info, resolve_relative_path, sanitize_path_component, set_modified, PartialFile, PARTIAL_UPLOAD_SUFFIX,
+};
+use crate::utils::filename::{host_name, long_path, unused_path, valid_file_name};
+use crate::web::error::ApiError;
+use crate::web::handlers::api::{parse_modified, record_upload, MODIFIED_HEADER};
+use crate::web::session::{sender_name, ClientId};
+use crate::web::state::AppState;
+
+/// Items a device may ask about at once
+const MAX_CHECK_ITEMS: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct BackupCheck {
+    pub items: Vec<BackupItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupCheckResult {
+    /// Keys of the items still to send
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackupItemQuery {
+    /// The device's key for the item, as sent to `/api/backup/check`
+    pub key: String,
+    /// File name on the device
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BackupResult {
+    /// The item was new and is now in the share
+    Stored { file: FileInfo },
+    /// The same contents were backed up before, as `name`
+    Duplicate { name: String },
+}
+
+/// What the calling device has backed up so far
+pub async fn get_backup_summary(State(state): State<AppState>, client: ClientId) -> Json<BackupSummary> {
+    Json(state.backups.summary(client.as_str()))
+}
+
+/// Which of a device's items it still has to send
+pub async fn check_backup(
+    State(state): State<AppState>,
+    client: ClientId,
+    Json(check): Json<BackupCheck>,
+) -> Result<Json<BackupCheckResult>, StatusCode> {
+    if check.items.len() > MAX_CHECK_ITEMS || check.items.iter().any(|item| item.key.len() > MAX_KEY_LENGTH) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let missing = state.backups.missing(client.as_str(), &check.items).map_err(|e| {
+        error!("Failed to save backup state: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(BackupCheckResult { missing }))
+}
+
+/// Back up one item, storing it under the backup folder unless its contents are there already.
+///
+/// The item is written to disk as it arrives, as phone videos can be large, and only moved into
+/// place once its hash shows it is new.
+pub async fn upload_backup_item(
+    State(state): State<AppState>,
+    client: ClientId,
+    Query(query): Query<BackupItemQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<BackupResult>, ApiError> {
+    if query.key.is_empty() || query.key.len() > MAX_KEY_LENGTH || !valid_file_name(&query.name) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    let record = |hash: &str, name: &str, size: u64| {
+        state.backups.record(client.as_str(), &query.key, hash, name, size).map_err(|e| {
+            error!("Failed to save backup state: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    };
+
+    let backup_dir = state.config.backup.directory.to_string_lossy();
+    let backup_root = resolve_relative_path(&state.directory, &backup_dir).ok_or_else(|| {
+        error!("Invalid backup directory: {}", backup_dir);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    tokio::fs::create_dir_all(&backup_root)
+        .await
+        .map_err(|e| ApiError::storage(&state, &format!("create directory {:?}", backup_root), &e))?;
+
+    // Received next to the backups, so duplicates never show up and are simply removed again
+    let write_path = unused_path(backup_root.join(format!(".{}{}", host_name(&query.name), PARTIAL_UPLOAD_SUFFIX)));
+    let mut file = tokio::fs::File::create(&write_path)
+        .await
+        .map_err(|e| ApiError::storage(&state, &format!("create file {:?}", write_path), &e))?;
+    let partial = PartialFile::new(write_path.clone());
+
+    let max_file_size = state.config.server.max_file_size;
+    let mut stream = body.into_data_stream();
+    let mut hasher = Sha256::new();
+    let mut received = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| {
+            error!("Failed to read backup of {}: {}", query.name, e);
+            StatusCode::BAD_REQUEST
+        })?;
+        received += chunk.len() as u64;
+        if received > max_file_size {
+            error!("Backup of {} is larger than {} bytes", query.name, max_file_size);
+            return Err(ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Files may be at most {} bytes", max_file_size),
+            ));
+        }
+        hasher.update(&chunk);
+        if let Err(e) = file.write_all(&chunk).await {
+            return Err(ApiError::storage(&state, &format!("write backup of {}", query.name), &e));
+        }
+    }
+    let written = async {
+        file.flush().await?;
+        file.sync_all().await
+    }
+    .await;
+    drop(file);
+    if let Err(e) = written {
+        return Err(ApiError::storage(&state, &format!("write backup of {}", query.name), &e));
+    }
+
+    let hash: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    if let Some(name) = state.backups.stored(&hash) {
+        record(&hash, &name, 0)?;
+        return Ok(Json(BackupResult::Duplicate { name }));
+    }
+
+    // Photos are sorted by device and by the month they were taken, as far as the device knows
+    let sender = sender_name(&headers);
+    let modified = headers
+        .get(MODIFIED_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_modified);
+    let device = match &sender {
+        Some(name) => sanitize_path_component(name),
+        None => format!("Device {}", client_handle(client.as_str())),
+    };
+    let month = modified.unwrap_or_else(Utc::now).format("%Y/%m").to_string();
+    let target_dir = backup_root.join(device).join(month);
+    tokio::fs::create_dir_all(&target_dir)
+        .await
+        .map_err(|e| ApiError::storage(&state, &format!("create directory {:?}", target_dir), &e))?;
+
+    let file_path = long_path(unused_path(target_dir.join(host_name(&query.name))));
+    state.make_room(received, &[])?;
+    tokio::fs::rename(&write_path, &file_path)
+        .await
+        .map_err(|e| ApiError::storage(&state, &format!("store {:?}", file_path), &e))?;
+    partial.keep();
+    state.storage.clear();
+
+    if let Some(modified) = modified {
+        if let Err(e) = set_modified(&file_path, modified) {
+            warn!("Failed to set modification time of {:?}: {}", file_path, e);
+        }
+    }
+
+    let mut file_info = get_share_file_info(&state.directory, &file_path).map_err(|e| {
+        error!("Failed to get file info for {:?}: {}", file_path, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    record(&hash, &file_info.name, file_info.size)?;
+    record_upload(&state, &mut file_info, sender, Some(client.0));
+
+    info!("Backed up {} ({} bytes)", file_info.name, file_info.size);
+    Ok(Json(BackupResult::Stored { file: file_info }))
+}
+
+/// Page backing up a phone's photos, installable as an app
+pub async fn serve_backup_page() -> Html<&'static str> {
+    Html(BACKUP_PAGE)
+}
+
+const BACKUP_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta name="theme-color" content="#3498db">
+    <link rel="manifest" href="/manifest.webmanifest" crossorigin="use-credentials">
+    <link rel="icon" href="/icon.svg">
+    <title>RustDrop - Photo backup</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
+            max-width: 600px;
+            margin: 0 auto;
+            padding: 24px;
+            color: #2c3e50;
+            background: #f5f7fa;
+        }
+        input, .button {
+            font-size: 18px;
+            padding: 12px;
+            border-radius: 8px;
+            box-sizing: border-box;
+            width: 100%;
+            margin-bottom: 12px;
+        }
+        input {
+            border: 1px solid #ccc;
+        }
+        .button {
+            border: none;
+            background: #3498db;
+            color: white;
+            cursor: pointer;
+        }
+        .button:disabled {
+            background: #95a5a6;
+        }
+        #status {
+            min-height: 48px;
+            font-size: 18px;
+        }
+        progress {
+            width: 100%;
+            height: 16px;
+        }
+        .summary {
+            color: #7f8c8d;
+        }
+    </style>
+</head>
+<body>
+    <h1>📷 Photo backup</h1>
+    <input id="name" placeholder="Name of this device" autocomplete="name">
+    <button class="button" id="choose-photos">Back up photos and videos</button>
+    <button class="button" id="choose-folder" hidden>Back up a folder</button>
+    <button class="button" id="rescan" hidden>Back up new photos from the folder</button>
+    <input type="file" id="photo-picker" accept="image/*,video/*" multiple hidden>
+    <input type="file" id="folder-picker" webkitdirectory multiple hidden>
+    <p id="status"></p>
+    <progress id="progress" value="0" max="1" hidden></progress>
+    <p class="summary" id="summary"></p>
+
+    <script>
+        // Hashes let the server skip photos it has from another device before they are sent;
+        // browsers only hash on HTTPS, elsewhere the server finds duplicates after the upload
+        const MAX_HASHED_SIZE = 256 * 1024 * 1024;
+        const CHECK_BATCH = 500;
+        const MEDIA = /\.(jpe?g|png|gif|webp|heic|heif|avif|dng|tiff?|mp4|mov|m4v|3gp|webm|mkv)$/i;
+
+        const nameInput = document.getElementById('name');
+        const statusText = document.getElementById('status');
+        const progress = document.getElementById('progress');
+        const buttons = [...document.querySelectorAll('.button')];
+
+        const match = document.cookie.match(/(?:^|; )rustdrop_sender=([^;]*)/);
+        if (match) nameInput.value = decodeURIComponent(match[1]);
+        nameInput.addEventListener('change', () => {
+            const name = nameInput.value.trim();
+            const maxAge = name ? 365 * 24 * 60 * 60 : 0;
+            document.cookie = `rustdrop_sender=${encodeURIComponent(name)}; path=/; max-age=${maxAge}; SameSite=Lax`;
+        });
+
+        function formatSize(bytes) {
+            const units = ['B', 'KB', 'MB', 'GB', 'TB'];
+            let index = 0;
+            while (bytes >= 1024 && index < units.length - 1) {
+                bytes /= 1024;
+                index++;
+            }
+            return `${bytes.toFixed(index ? 1 : 0)} ${units[index]}`;
+        }
+
+        async function loadSummary() {
+            const response = await fetch('/api/backup');
+            if (!response.ok) return;
+            const summary = await response.json();
+            document.getElementById('summary').textContent = summary.last_backup
+                ? `${summary.items} items backed up from this device, ${formatSize(summary.bytes)} sent, last on ${new Date(summary.last_backup).toLocaleString()}`
+                : 'Nothing backed up from this device yet';
+        }
+
+        function itemKey(file, path) {
+            return `${path || file.webkitRelativePath || file.name}:${file.size}:${file.lastModified}`;
+        }
+
+        async function hashFile(file) {
+            if (!window.isSecureContext || !crypto.subtle || file.size > MAX_HASHED_SIZE) return undefined;
+            const digest = await crypto.subtle.digest('SHA-256', await file.arrayBuffer());
+            return [...new Uint8Array(digest)].map(byte => byte.toString(16).padStart(2, '0')).join('');
+        }
+
+        async function check(items) {
+            const response = await fetch('/api/backup/check', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ items }),
+            });
+            if (!response.ok) throw new Error(`HTTP ${response.status}`);
+            return new Set((await response.json()).missing);
+        }
+
+        function upload(item, index, count) {
+            return new Promise((resolve, reject) => {
+                const query = new URLSearchParams({ key: item.key, name: item.file.name });
+                const request = new XMLHttpRequest();
+                request.open('POST', `/api/backup/items?${query}`);
+                request.setRequestHeader('X-RustDrop-Modified', new Date(item.file.lastModified).toISOString());
+                request.upload.onprogress = event => {
+                    const percent = event.total ? Math.floor(event.loaded * 100 / event.total) : 0;
+                    statusText.textContent = `Sending ${index + 1} of ${count}… ${percent}%`;
+                };
+                request.onload = () => request.status < 300 ? resolve() : reject(new Error(`HTTP ${request.status}`));
+                request.onerror = () => reject(new Error('connection lost'));
+                request.send(item.file);
+            });
+        }
+
+        async function backUp(items) {
+            nameInput.dispatchEvent(new Event('change'));
+            buttons.forEach(button => button.disabled = true);
+            progress.hidden = false;
+            try {
+                statusText.textContent = `Looking for new items among ${items.length}…`;
+                const missing = [];
+                for (let start = 0; start < items.length; start += CHECK_BATCH) {
+                    const batch = items.slice(start, start + CHECK_BATCH);
+                    const keys = await check(batch.map(item => ({ key: item.key })));
+                    missing.push(...batch.filter(item => keys.has(item.key)));
+                }
+
+                let sent = 0;
+                for (const [index, item] of missing.entries()) {
+                    progress.value = index / missing.length;
+                    statusText.textContent = `Checking ${index + 1} of ${missing.length}…`;
+                    const hash = await hashFile(item.file);
+                    if (hash && !(await check([{ key: item.key, hash }])).has(item.key)) continue;
+                    await upload(item, index, missing.length);
+                    sent++;
+                }
+                progress.value = 1;
+                statusText.textContent = missing.length
+                    ? `✅ Backed up ${missing.length} new item${missing.length === 1 ? '' : 's'}, ${sent} sent`
+                    : '✅ Everything is backed up already';
+            } catch (error) {
+                statusText.textContent = `❌ Backup stopped (${error.message}), try again to continue where it stopped`;
+            }
+            buttons.forEach(button => button.disabled = false);
+            progress.hidden = true;
+            loadSummary();
+        }
+
+        function backUpFiles(files) {
+            const items = [...files]
+                .filter(file => MEDIA.test(file.name) || /^(image|video)\//.test(file.type))
+                .map(file => ({ key: itemKey(file), file }));
+            backUp(items);
+        }
+
+        const photoPicker = document.getElementById('photo-picker');
+        document.getElementById('choose-photos').addEventListener('click', () => photoPicker.click());
+        photoPicker.addEventListener('change', () => {
+            backUpFiles(photoPicker.files);
+            photoPicker.value = '';
+        });
+
+        // Browsers able to keep access to a folder back up what is new in it on every visit;
+        // others pick the folder again each time
+        const folderPicker = document.getElementById('folder-picker');
+        const chooseFolder = document.getElementById('choose-folder');
+        const rescan = document.getElementById('rescan');
+        chooseFolder.hidden = !('showDirectoryPicker' in window) && !('webkitdirectory' in folderPicker);
+        folderPicker.addEventListener('change', () => {
+            backUpFiles(folderPicker.files);
+            folderPicker.value = '';
+        });
+
+        function folderStore(mode, action) {
+            return new Promise((resolve, reject) => {
+                const open = indexedDB.open('rustdrop-backup', 1);
+                open.onupgradeneeded = () => open.result.createObjectStore('folders');
+                open.onerror = () => reject(open.error);
+                open.onsuccess = () => {
+                    const request = action(open.result.transaction('folders', mode).objectStore('folders'));
+                    request.onsuccess = () => resolve(request.result);
+                    request.onerror = () => reject(request.error);
+                };
+            });
+        }
+
+        async function listFolder(handle, path, items) {
+            for await (const entry of handle.values()) {
+                const entryPath = `${path}/${entry.name}`;
+                if (entry.kind === 'directory') {
+                    if (!entry.name.startsWith('.')) await listFolder(entry, entryPath, items);
+                } else if (MEDIA.test(entry.name)) {
+                    const file = await entry.getFile();
+                    items.push({ key: itemKey(file, entryPath), file });
+                }
+            }
+            return items;
+        }
+
+        async function backUpFolder(handle) {
+            statusText.textContent = `Reading ${handle.name}…`;
+            backUp(await listFolder(handle, handle.name, []));
+        }
+
+        chooseFolder.addEventListener('click', async () => {
+            if (!('showDirectoryPicker' in window)) {
+                folderPicker.click();
+                return;
+            }
+            try {
+                const handle = await window.showDirectoryPicker({ id: 'rustdrop-backup', mode: 'read' });
+                await folderStore('readwrite', store => store.put(handle, 'folder'));
+                rescan.hidden = false;
+                backUpFolder(handle);
+            } catch (error) {
+                if (error.name !== 'AbortError') statusText.textContent = `❌ ${error.message}`;
+            }
+        });
+
+        rescan.addEventListener('click', async () => {
+            const handle = await folderStore('readonly', store => store.get('folder'));
+            if (handle && (await handle.requestPermission({ mode: 'read' })) === 'granted') backUpFolder(handle);
+        });
+
+        (async () => {
+            if (!('showDirectoryPicker' in window)) return;
+            const handle = await folderStore('readonly', store => store.get('folder')).catch(() => undefined);
+            if (!handle) return;
+            rescan.hidden = false;
+            if ((await handle.queryPermission({ mode: 'read' })) === 'granted') backUpFolder(handle);
+        })();
+
+        if ('serviceWorker' in navigator) {
+            navigator.serviceWorker.register('/sw.js').catch(() => {});
+        }
+        loadSummary();
+    </script>
+</body>
+</html>
+"##;
@@ -0,0 +1,467 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::core::history::{HistoryAction, HistoryEntry};
+use crate::core::metadata::{normalize_tag, STATE_DIR};
+use crate::core::models::FileInfo;
+use crate::utils::file::resolve_relative_path;
+use crate::web::state::AppState;
+
+const MAX_BULK_OPERATIONS: usize = 1000;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BulkOperation {
+    Delete {
+        id: String,
+    },
+    /// Move (or rename) a file to a path relative to the share
+    Move {
+        id: String,
+        to: String,
+    },
+    Tag {
+        id: String,
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+}
+
+impl BulkOperation {
+    fn id(&self) -> &str {
+        match self {
+            Self::Delete { id } | Self::Move { id, .. } | Self::Tag { id, .. } => id,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Delete { .. } => "delete",
+            Self::Move { .. } => "move",
+            Self::Tag { .. } => "tag",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest {
+    pub operations: Vec<BulkOperation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkItemStatus {
+    /// The operation was applied
+    Ok,
+    /// The operation is the reason the batch was not applied
+    Failed,
+    /// The operation was not attempted because another one failed
+    Skipped,
+    /// The operation was applied and then undone because a later one failed
+    RolledBack,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkItemResult {
+    pub index: usize,
+    pub id: String,
+    pub op: String,
+    pub status: BulkItemStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkResponse {
+    pub success: bool,
+    pub results: Vec<BulkItemResult>,
+}
+
+/// A validated operation, ready to be executed
+enum PlannedOperation {
+    Delete { file: FileInfo },
+    Move { file: FileInfo, target: PathBuf, target_name: String },
+    Tag { file: FileInfo, tags: Vec<String> },
+}
+
+/// Execute a batch of delete/move/tag operations all-or-nothing
+pub async fn bulk_operations(
+    State(state): State<AppState>,
+    Json(request): Json<BulkRequest>,
+) -> (StatusCode, Json<BulkResponse>) {
+    let operations = request.operations;
+
+    if operations.is_empty() || operations.len() > MAX_BULK_OPERATIONS {
+        error!("Bulk request with {} operations rejected", operations.len());
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(BulkResponse { success: false, results: Vec::new() }),
+        );
+    }
+
+    let files = match state.list_share() {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to list directory: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(BulkResponse { success: false, results: Vec::new() }),
+            );
+        }
+    };
+
+    // Validate everything before touching the disk
+    let plan = match plan_operations(&state, &operations, files) {
+        Ok(plan) => plan,
+        Err(errors) => {
+            let results = operations
+                .iter()
+                .enumerate()
+                .map(|(index, op)| match errors.get(&index) {
+                    Some(message) => item_result(index, op, BulkItemStatus::Failed, Some(message.clone())),
+                    None => item_result(index, op, BulkItemStatus::Skipped, None),
+                })
+                .collect();
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(BulkResponse { success: false, results }),
+            );
+        }
+    };
+
+    if let Err((failed_index, message)) = execute_plan(&state.directory, &plan) {
+        let results = operations
+            .iter()
+            .enumerate()
+            .map(|(index, op)| {
+                if index < failed_index {
+                    item_result(index, op, BulkItemStatus::RolledBack, None)
+                } else if index == failed_index {
+                    item_result(index, op, BulkItemStatus::Failed, Some(message.clone()))
+                } else {
+                    item_result(index, op, BulkItemStatus::Skipped, None)
+                }
+            })
+            .collect();
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(BulkResponse { success: false, results }),
+        );
+    }
+
+    // The files are in place, now bring the metadata in line
+    let mut results = Vec::with_capacity(operations.len());
+    for (index, (op, planned)) in operations.iter().zip(&plan).enumerate() {
+        let outcome = match planned {
+            PlannedOperation::Delete { file } => {
+                let mut entry = HistoryEntry::new(HistoryAction::Delete, &file.name);
+                entry.size = file.size;
+                state
+                    .metadata
+                    .forget(&file.name)
+                    .and_then(|_| state.text.forget(&file.name))
+                    .and_then(|_| state.record_history(&entry))
+            }
+            PlannedOperation::Move { file, target_name, .. } => {
+                let mut entry = HistoryEntry::new(HistoryAction::Move, target_name);
+                entry.size = file.size;
+                entry.previous_name = Some(file.name.clone());
+                state
+                    .metadata
+                    .rename(&file.name, target_name)
+                    .and_then(|_| state.text.rename(&file.name, target_name))
+                    .and_then(|_| state.record_history(&entry))
+            }
+            PlannedOperation::Tag { file, tags } => state.metadata.set_tags(&file.name, tags).map(|_| ()),
+        };
+
+        results.push(match outcome {
+            Ok(()) => item_result(index, op, BulkItemStatus::Ok, None),
+            Err(e) => {
+                error!("Failed to update metadata for bulk operation {}: {}", index, e);
+                item_result(index, op, BulkItemStatus::Failed, Some(format!("Failed to update metadata: {}", e)))
+            }
+        });
+    }
+
+    let success = results.iter().all(|r| r.status == BulkItemStatus::Ok);
+    info!("Bulk request with {} operations completed (success: {})", results.len(), success);
+
+    let status = if success { StatusCode::OK } else { StatusCode::INTERNAL_SERVER_ERROR };
+    (status, Json(BulkResponse { success, results }))
+}
+
+fn item_result(index: usize, op: &BulkOperation, status: BulkItemStatus, error: Option<String>) -> BulkItemResult {
+    BulkItemResult {
+        index,
+        id: op.id().to_string(),
+        op: op.name().to_string(),
+        status,
+        error,
+    }
+}
+
+/// Resolve and validate every operation, returning the errors keyed by operation index
+fn plan_operations(
+    state: &AppState,
+    operations: &[BulkOperation],
+    files: Vec<FileInfo>,
+) -> Result<Vec<PlannedOperation>, HashMap<usize, String>> {
+    let files: HashMap<String, FileInfo> = files.into_iter().map(|f| (f.id.to_string(), f)).collect();
+
+    let mut errors = HashMap::new();
+    let mut plan = Vec::with_capacity(operations.len());
+    let mut seen_ids = HashSet::new();
+    let mut targets = HashSet::new();
+
+    for (index, op) in operations.iter().enumerate() {
+        let result = (|| {
+            let file = files.get(op.id()).cloned().ok_or("File not found")?;
+
+            if !seen_ids.insert(op.id().to_string()) {
+                return Err("File appears in more than one operation");
+            }
+
+            match op {
+                BulkOperation::Delete { .. } => Ok(PlannedOperation::Delete { file }),
+                BulkOperation::Move { to, .. } => {
+                    let target = resolve_relative_path(&state.directory, to).ok_or("Invalid target path")?;
+                    if target.exists() {
+                        return Err("Target already exists");
+                    }
+                    if !targets.insert(target.clone()) {
+                        return Err("Target used by another operation");
+                    }
+                    let target_name = target
+                        .strip_prefix(&state.directory)
+                        .map(|p| p.to_string_lossy().replace('\\', "/"))
+                        .map_err(|_| "Invalid target path")?;
+                    Ok(PlannedOperation::Move { file, target, target_name })
+                }
+                BulkOperation::Tag { add, remove, .. } => {
+                    let add = add.iter().map(|t| normalize_tag(t)).collect::<Option<Vec<_>>>().ok_or("Invalid tag")?;
+                    let remove = remove.iter().map(|t| normalize_tag(t)).collect::<Option<Vec<_>>>().ok_or("Invalid tag")?;
+
+                    let mut tags: BTreeSet<String> = state.metadata.tags(&file.name).into_iter().collect();
+                    tags.extend(add);
+                    for tag in &remove {
+                        tags.remove(tag);
+                    }
+                    Ok(PlannedOperation::Tag { file, tags: tags.into_iter().collect() })
+                }
+            }
+        })();
+
+        match result {
+            Ok(planned) => plan.push(planned),
+            Err(message) => {
+                errors.insert(index, message.to_string());
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(plan)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Apply the file system part of the plan, undoing everything if one step fails.
+///
+/// Deleted files are first moved into a staging directory so they can be restored.
+fn execute_plan(directory: &Path, plan: &[PlannedOperation]) -> Result<(), (usize, String)> {
+    let staging = directory.join(STATE_DIR).join("trash").join(Uuid::new_v4().to_string());
+    let mut undo: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    for (index, planned) in plan.iter().enumerate() {
+        let step = match planned {
+            PlannedOperation::Delete { file } => {
+                let staged = staging.join(index.to_string());
+                std::fs::create_dir_all(&staging)
+                    .and_then(|_| std::fs::rename(&file.path, &staged))
+                    .map(|_| undo.push((staged, file.path.clone())))
+            }
+            PlannedOperation::Move { file, target, .. } => target
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::rename(&file.path, target))
+                .map(|_| undo.push((target.clone(), file.path.clone()))),
+            PlannedOperation::Tag { .. } => Ok(()),
+        };
+
+        if let Err(e) = step {
+            error!("Bulk operation {} failed, rolling back: {}", index, e);
+            for (current, original) in undo.iter().rev() {
+                if let Err(e) = std::fs::rename(current, original) {
+                    error!("Failed to restore {:?} to {:?}: {}", current, original, e);
+                }
+            }
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err((index, e.to_string()));
+        }
+    }
+
+    if staging.exists() {
+        if let Err(e) = std::fs::remove_dir_all(&staging) {
+            warn!("Failed to clean up deleted files in {:?}: {}", staging, e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::DeviceInfo;
+    use crate::utils::file::get_file_info;
+    use tempfile::TempDir;
+
+    fn create_file(temp_dir: &TempDir, name: &str) -> String {
+        let path = temp_dir.path().join(name);
+        std::fs::write(&path, name).unwrap();
+        get_file_info(&path).unwrap().id.to_string()
+    }
+
+    fn create_test_state(temp_dir: &TempDir) -> AppState {
+        AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080))
+    }
+
+    #[tokio::test]
+    async fn test_bulk_delete_move_and_tag() {
+        let temp_dir = TempDir::new().unwrap();
+        let delete_id = create_file(&temp_dir, "old.txt");
+        let move_id = create_file(&temp_dir, "draft.txt");
+        let tag_id = create_file(&temp_dir, "receipt.pdf");
+        let state = create_test_state(&temp_dir);
+
+        let request = BulkRequest {
+            operations: vec![
+                BulkOperation::Delete { id: delete_id },
+                BulkOperation::Move { id: move_id, to: "archive/final.txt".to_string() },
+                BulkOperation::Tag { id: tag_id, add: vec!["Receipts".to_string()], remove: vec![] },
+            ],
+        };
+
+        let (status, Json(response)) = bulk_operations(State(state.clone()), Json(request)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(response.success);
+        assert!(response.results.iter().all(|r| r.status == BulkItemStatus::Ok));
+
+        assert!(!temp_dir.path().join("old.txt").exists());
+        assert!(!temp_dir.path().join("draft.txt").exists());
+        assert!(temp_dir.path().join("archive/final.txt").exists());
+        assert_eq!(state.metadata.tags("receipt.pdf"), vec!["receipts"]);
+
+        let history = state.history.recent(10);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].action, HistoryAction::Move);
+        assert_eq!(history[0].previous_name.as_deref(), Some("draft.txt"));
+        assert_eq!(history[1].action, HistoryAction::Delete);
+
+        // Staged deletions are cleaned up
+        let trash = temp_dir.path().join(STATE_DIR).join("trash");
+        assert!(!trash.exists() || std::fs::read_dir(trash).unwrap().next().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_validation_failure_changes_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let delete_id = create_file(&temp_dir, "keep.txt");
+        let state = create_test_state(&temp_dir);
+
+        let request = BulkRequest {
+            operations: vec![
+                BulkOperation::Delete { id: delete_id },
+                BulkOperation::Delete { id: "missing".to_string() },
+            ],
+        };
+
+        let (status, Json(response)) = bulk_operations(State(state), Json(request)).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(!response.success);
+        assert_eq!(response.results[0].status, BulkItemStatus::Skipped);
+        assert_eq!(response.results[1].status, BulkItemStatus::Failed);
+        assert_eq!(response.results[1].error.as_deref(), Some("File not found"));
+        assert!(temp_dir.path().join("keep.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_rejects_escaping_move() {
+        let temp_dir = TempDir::new().unwrap();
+        let id = create_file(&temp_dir, "secret.txt");
+        let state = create_test_state(&temp_dir);
+
+        let request = BulkRequest {
+            operations: vec![BulkOperation::Move { id, to: "../outside.txt".to_string() }],
+        };
+
+        let (status, Json(response)) = bulk_operations(State(state), Json(request)).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.results[0].error.as_deref(), Some("Invalid target path"));
+        assert!(temp_dir.path().join("secret.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_bulk_rejects_conflicting_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = create_file(&temp_dir, "a.txt");
+        let second = create_file(&temp_dir, "b.txt");
+        create_file(&temp_dir, "existing.txt");
+        let state = create_test_state(&temp_dir);
+
+        let request = BulkRequest {
+            operations: vec![
+                BulkOperation::Move { id: first.clone(), to: "same.txt".to_string() },
+                BulkOperation::Move { id: second, to: "same.txt".to_string() },
+            ],
+        };
+        let (status, Json(response)) = bulk_operations(State(state.clone()), Json(request)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.results[1].error.as_deref(), Some("Target used by another operation"));
+
+        let request = BulkRequest {
+            operations: vec![BulkOperation::Move { id: first, to: "existing.txt".to_string() }],
+        };
+        let (status, Json(response)) = bulk_operations(State(state), Json(request)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.results[0].error.as_deref(), Some("Target already exists"));
+    }
+
+    #[test]
+    fn test_execute_plan_rolls_back_on_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        create_file(&temp_dir, "first.txt");
+        let first = get_file_info(&temp_dir.path().join("first.txt")).unwrap();
+
+        // The second file vanished after validation, so its move fails
+        let mut vanished = first.clone();
+        vanished.name = "vanished.txt".to_string();
+        vanished.path = temp_dir.path().join("vanished.txt");
+
+        let plan = vec![
+            PlannedOperation::Move {
+                file: first,
+                target: temp_dir.path().join("moved.txt"),
+                target_name: "moved.txt".to_string(),
+            },
+            PlannedOperation::Delete { file: vanished },
+        ];
+
+        let result = execute_plan(temp_dir.path(), &plan);
+
+        assert_eq!(result.unwrap_err().0, 1);
+        assert!(temp_dir.path().join("first.txt").exists());
+        assert!(!temp_dir.path().join("moved.txt").exists());
+    }
+}
@@ -0,0 +1,88 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::core::clients::{client_handle, ClientSession};
+use crate::core::events::ServerEvent;
+use crate::core::fingerprint::Fingerprint;
+use crate::web::proxy::ClientIp;
+use crate::web::session::ClientId;
+use crate::web::state::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct ClientEntry {
+    #[serde(flatten)]
+    pub session: ClientSession,
+    /// Whether this is the client asking
+    pub current: bool,
+}
+
+/// Clients seen since the server started, most recently active first
+pub async fn list_clients(State(state): State<AppState>, client: ClientId) -> Json<Vec<ClientEntry>> {
+    let handle = client_handle(client.as_str());
+    let entries = state
+        .clients
+        .list()
+        .into_iter()
+        .map(|session| ClientEntry {
+            current: session.id == handle,
+            session,
+        })
+        .collect();
+    Json(entries)
+}
+
+/// Fingerprint of the asking client's session with this host. The first time a client asks,
+/// the host terminal shows it too, with a bell, so both sides can be compared.
+pub async fn get_fingerprint(
+    State(state): State<AppState>,
+    client: ClientId,
+    client_ip: Option<Extension<ClientIp>>,
+) -> Json<Fingerprint> {
+    let fingerprint = Fingerprint::of_session(&state.device_info.id, client.as_str());
+    if state.clients.first_fingerprint(client.as_str()) {
+        let from = client_ip.map_or_else(|| "an unknown address".to_string(), |Extension(ClientIp(ip))| ip.to_string());
+        println!();
+        println!("\x07🔐 A device at {} connected. Its page should show:", from);
+        println!("   {}", fingerprint);
+        println!();
+    }
+    Json(fingerprint)
+}
+
+/// Revoke a client's cookie or token, by the handle `/api/clients` lists. Only the host itself
+/// may do this.
+pub async fn revoke_client(
+    State(state): State<AppState>,
+    client_ip: Option<Extension<ClientIp>>,
+    Path(id): Path<String>,
+) -> StatusCode {
+    let from_host = client_ip.is_some_and(|Extension(ClientIp(ip))| ip.is_loopback());
+    if !from_host {
+        return StatusCode::FORBIDDEN;
+    }
+
+    let result = state
+        .clients
+        .revoke(&id)
+        .and_then(|token| match token {
+            Some(token) => state.access.forget(&token).map(|_| true),
+            None => Ok(false),
+        });
+    match result {
+        Ok(true) => {
+            info!("Revoked client {}", id);
+            state.events.publish(ServerEvent::ClientRevoked { id });
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to revoke client {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
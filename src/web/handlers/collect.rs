@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::core::collect::{matching_files, parse_pattern, CollectRequest};
+use crate::core::events::ServerEvent;
+use crate::web::session::{normalize_sender, ClientId};
+use crate::web::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct NewCollectRequest {
+    /// Name of the device asking, shown to the owner
+    pub requester: String,
+    /// Glob selecting the files, e.g. `*.jpg`
+    pub pattern: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CollectDecision {
+    pub approve: bool,
+}
+
+/// Ask the owner of this device to hand over the files matching a pattern
+pub async fn create_collect_request(
+    State(state): State<AppState>,
+    client: ClientId,
+    Json(request): Json<NewCollectRequest>,
+) -> Result<(StatusCode, Json<CollectRequest>), StatusCode> {
+    let requester = normalize_sender(&request.requester).ok_or(StatusCode::BAD_REQUEST)?;
+    if parse_pattern(&request.pattern).is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let request = state
+        .collect
+        .create(&requester, request.pattern.trim(), client.as_str());
+    info!(
+        "{} asks to collect files matching {:?}, approve in the web interface",
+        request.requester, request.pattern
+    );
+    state.events.publish(ServerEvent::CollectRequested(request.clone()));
+    Ok((StatusCode::CREATED, Json(request)))
+}
+
+/// Requests waiting for the owner's answer
+pub async fn list_collect_requests(State(state): State<AppState>) -> Json<Vec<CollectRequest>> {
+    Json(state.collect.pending())
+}
+
+/// Status of a request, including the approved files once the owner agreed
+pub async fn get_collect_request(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<CollectRequest>, StatusCode> {
+    state.collect.get(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Approve or decline a request; the device that asked cannot answer its own request
+pub async fn decide_collect_request(
+    State(state): State<AppState>,
+    client: ClientId,
+    Path(id): Path<String>,
+    Json(decision): Json<CollectDecision>,
+) -> Result<Json<CollectRequest>, StatusCode> {
+    let request = state.collect.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    if request.client_id == client.as_str() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let files = if decision.approve {
+        let pattern = parse_pattern(&request.pattern).ok_or(StatusCode::BAD_REQUEST)?;
+        let mut files = state.list_share().map_err(|e| {
+            error!("Failed to list directory: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        files.iter_mut().for_each(|file| state.metadata.annotate(file));
+        matching_files(files, &pattern)
+    } else {
+        Vec::new()
+    };
+
+    let request = state
+        .collect
+        .decide(&id, decision.approve, files)
+        .ok_or(StatusCode::CONFLICT)?;
+
+    info!(
+        "{} collection request from {} ({} files)",
+        if decision.approve { "Approved" } else { "Declined" },
+        request.requester,
+        request.files.len()
+    );
+    Ok(Json(request))
+}
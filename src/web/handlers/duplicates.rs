@@ -0,0 +1,86 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::core::models::FileInfo;
+use crate::utils::hash::find_duplicates;
+use crate::web::state::AppState;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    /// SHA-256 of the shared contents
+    pub hash: String,
+    /// Size of a single copy in bytes
+    pub size: u64,
+    /// Identical files, oldest first
+    pub files: Vec<FileInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateReport {
+    pub groups: Vec<DuplicateGroup>,
+    /// Bytes that would be freed by keeping a single copy of every group
+    pub wasted_bytes: u64,
+}
+
+/// Report groups of files in the share that have identical contents
+pub async fn list_duplicates(
+    State(state): State<AppState>,
+) -> Result<Json<DuplicateReport>, StatusCode> {
+    let files = state.list_share().map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Hashing reads every candidate file, keep it off the async workers
+    let groups = tokio::task::spawn_blocking(move || find_duplicates(files))
+        .await
+        .map_err(|e| {
+            error!("Duplicate scan task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let groups: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .map(|(hash, mut files)| {
+            files.iter_mut().for_each(|file| state.metadata.annotate(file));
+            DuplicateGroup {
+                hash,
+                size: files[0].size,
+                files,
+            }
+        })
+        .collect();
+
+    let wasted_bytes = groups
+        .iter()
+        .map(|group| group.size * (group.files.len() as u64 - 1))
+        .sum();
+
+    info!("Found {} duplicate groups ({} bytes wasted)", groups.len(), wasted_bytes);
+    Ok(Json(DuplicateReport { groups, wasted_bytes }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::DeviceInfo;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_list_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a.jpg"), "same bytes").unwrap();
+        std::fs::write(temp_dir.path().join("b.jpg"), "same bytes").unwrap();
+        std::fs::write(temp_dir.path().join("c.jpg"), "same bytes").unwrap();
+        std::fs::write(temp_dir.path().join("unique.jpg"), "different").unwrap();
+
+        let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+        let Json(report) = list_duplicates(State(state)).await.unwrap();
+
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].files.len(), 3);
+        assert_eq!(report.groups[0].size, 10);
+        assert_eq!(report.wasted_bytes, 20);
+    }
+}
@@ -0,0 +1,42 @@
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+
+use crate::web::state::AppState;
+
+type ServerEvents = BoxStream<'static, Result<Event, Infallible>>;
+
+/// Live events as server-sent events, for clients and proxies that handle them better than
+/// polling: uploads, deletes and moves (`activity`), `collect_requested`, `relay_offered`,
+/// `storage_problem` and `client_revoked`, each with its JSON as data.
+///
+/// A client that falls too far behind gets a `lagged` event and should reload what it shows.
+pub async fn stream_events(State(state): State<AppState>) -> Sse<ServerEvents> {
+    let receiver = state.events.subscribe();
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => match Event::default().event(event.name()).json_data(&event) {
+                    Ok(sse) => sse,
+                    Err(e) => {
+                        error!("Failed to encode {} event: {}", event.name(), e);
+                        continue;
+                    }
+                },
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Event subscriber missed {} events", missed);
+                    Event::default().event("lagged").data(missed.to_string())
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), receiver));
+        }
+    });
+
+    Sse::new(events.boxed()).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,37 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+
+use crate::core::folders::FolderSizeStatus;
+use crate::utils::file::resolve_relative_path;
+use crate::web::state::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct FolderSize {
+    pub path: String,
+    #[serde(flatten)]
+    pub status: FolderSizeStatus,
+}
+
+/// How much a folder holds, however deep. The first request starts counting and answers
+/// `202 Accepted`; ask again until the size is there.
+pub async fn get_folder_size(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+) -> Result<(StatusCode, Json<FolderSize>), StatusCode> {
+    let directory = resolve_relative_path(&state.directory, &path).ok_or(StatusCode::BAD_REQUEST)?;
+    if !tokio::fs::metadata(&directory).await.is_ok_and(|metadata| metadata.is_dir()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let status = state.folder_sizes.get_or_start(directory);
+    let code = match status {
+        FolderSizeStatus::Computing => StatusCode::ACCEPTED,
+        FolderSizeStatus::Done { .. } => StatusCode::OK,
+        FolderSizeStatus::Failed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    Ok((code, Json(FolderSize { path, status })))
+}
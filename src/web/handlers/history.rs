@@ -0,0 +1,35 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::core::history::HistoryEntry;
+use crate::web::state::AppState;
+
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<usize>,
+    /// Only uploads and downloads
+    #[serde(default)]
+    pub transfers: bool,
+}
+
+/// Recent activity in the share (uploads, downloads, moves, deletions), newest first
+pub async fn list_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Json<Vec<HistoryEntry>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .min(MAX_HISTORY_LIMIT);
+
+    if query.transfers {
+        return Json(state.history.recent_matching(limit, |entry| entry.action.is_transfer()));
+    }
+    Json(state.history.recent(limit))
+}
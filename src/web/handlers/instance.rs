@@ -0,0 +1,30 @@
+use axum::{
+    extract::{Extension, State},
+    http::{HeaderMap, StatusCode},
+};
+use tracing::{info, warn};
+
+use crate::web::proxy::ClientIp;
+use crate::web::state::AppState;
+
+/// Header carrying the control token from the instance registry
+pub const CONTROL_HEADER: &str = "x-rustdrop-control";
+
+/// Shut this instance down so another one started on the host can take over its port.
+/// Only allowed from the host itself, with the token this instance published in the registry.
+pub async fn shutdown_instance(
+    State(state): State<AppState>,
+    client_ip: Option<Extension<ClientIp>>,
+    headers: HeaderMap,
+) -> StatusCode {
+    let from_host = client_ip.is_some_and(|Extension(ClientIp(ip))| ip.is_loopback());
+    let token = headers.get(CONTROL_HEADER).and_then(|value| value.to_str().ok());
+    if !from_host || token != Some(state.control.token()) {
+        warn!("Refused a shutdown request without the control token");
+        return StatusCode::FORBIDDEN;
+    }
+
+    info!("Another instance is taking over the port, shutting down");
+    state.control.request_shutdown();
+    StatusCode::ACCEPTED
+}
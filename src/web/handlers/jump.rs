@@ -0,0 +1,54 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Redirect,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::core::jump::is_local_path;
+use crate::web::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateJumpRequest {
+    /// Path on this server, e.g. `/api/files/<id>`
+    pub target: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JumpCode {
+    pub code: String,
+    /// The short path, `/j/<code>`
+    pub url: String,
+    pub target: String,
+}
+
+/// A short code for a path on this server, easy to type on another device
+pub async fn create_jump_code(
+    State(state): State<AppState>,
+    Json(request): Json<CreateJumpRequest>,
+) -> Result<Json<JumpCode>, StatusCode> {
+    if !is_local_path(&request.target) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let code = state.jump.code_for(&request.target).map_err(|e| {
+        error!("Failed to save jump code for {}: {}", request.target, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(JumpCode {
+        url: format!("/j/{}", code),
+        code,
+        target: request.target,
+    }))
+}
+
+/// Follow a short code to where it stands for
+pub async fn follow_jump_code(State(state): State<AppState>, Path(code): Path<String>) -> Result<Redirect, StatusCode> {
+    state
+        .jump
+        .target(&code)
+        .map(|target| Redirect::temporary(&target))
+        .ok_or(StatusCode::NOT_FOUND)
+}
@@ -0,0 +1,229 @@
+use axum::{extract::State, response::Html, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::core::history::HistoryAction;
+use crate::web::state::AppState;
+
+/// Uploads looked at for the list of senders
+const SENDER_HISTORY_LIMIT: usize = 500;
+
+/// Senders shown on the kiosk
+const MAX_RECENT_SENDERS: usize = 12;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecentSender {
+    pub name: String,
+    /// Files among the recent uploads
+    pub files: usize,
+    pub last_upload: DateTime<Utc>,
+}
+
+/// Who sent files lately, newest first, without saying what they sent
+pub async fn list_recent_senders(State(state): State<AppState>) -> Json<Vec<RecentSender>> {
+    let mut senders: Vec<RecentSender> = Vec::new();
+    for entry in state.history.recent(SENDER_HISTORY_LIMIT) {
+        let (HistoryAction::Upload, Some(name)) = (entry.action, entry.sender) else {
+            continue;
+        };
+        match senders.iter_mut().find(|sender| sender.name == name) {
+            Some(sender) => sender.files += 1,
+            None => senders.push(RecentSender {
+                name,
+                files: 1,
+                last_upload: entry.timestamp,
+            }),
+        }
+    }
+    senders.truncate(MAX_RECENT_SENDERS);
+    Json(senders)
+}
+
+/// Upload-only page for a tablet collecting files at an event
+pub async fn serve_kiosk_page() -> Html<&'static str> {
+    Html(KIOSK_PAGE)
+}
+
+const KIOSK_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>RustDrop - Share your photos</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
+            margin: 0;
+            padding: 24px;
+            min-height: 100vh;
+            box-sizing: border-box;
+            display: flex;
+            flex-direction: column;
+            gap: 20px;
+            color: #2c3e50;
+            background: #f5f7fa;
+        }
+        #name {
+            font-size: 24px;
+            padding: 12px;
+            border: 1px solid #ccc;
+            border-radius: 8px;
+        }
+        #target {
+            flex: 1;
+            min-height: 50vh;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+            text-align: center;
+            border: 6px dashed #3498db;
+            border-radius: 24px;
+            background: white;
+            font-size: 40px;
+            cursor: pointer;
+            user-select: none;
+        }
+        #target.dragover {
+            background: #eaf4fc;
+        }
+        #senders {
+            display: flex;
+            flex-wrap: wrap;
+            gap: 8px;
+            min-height: 40px;
+        }
+        .sender {
+            padding: 8px 14px;
+            border-radius: 20px;
+            background: #d6eaf8;
+            font-size: 18px;
+        }
+    </style>
+</head>
+<body>
+    <input id="name" placeholder="Your name (optional)" autocomplete="name">
+    <div id="target">📷<br>Tap or drop photos here</div>
+    <input type="file" id="picker" multiple hidden>
+    <div id="senders"></div>
+
+    <script>
+        const target = document.getElementById('target');
+        const picker = document.getElementById('picker');
+        const nameInput = document.getElementById('name');
+        const idleText = target.innerHTML;
+
+        const match = document.cookie.match(/(?:^|; )rustdrop_sender=([^;]*)/);
+        if (match) nameInput.value = decodeURIComponent(match[1]);
+        nameInput.addEventListener('change', () => {
+            const name = nameInput.value.trim();
+            const maxAge = name ? 365 * 24 * 60 * 60 : 0;
+            document.cookie = `rustdrop_sender=${encodeURIComponent(name)}; path=/; max-age=${maxAge}; SameSite=Lax`;
+        });
+
+        function show(text) {
+            target.textContent = text;
+        }
+
+        // Files go up in slices read from disk one at a time, so a phone can send videos
+        // larger than its memory, and a dropped connection only repeats the slice in flight
+        const CHUNK_SIZE = 4 * 1024 * 1024;
+        const RETRIES = 5;
+
+        async function upload(file, index, count) {
+            const created = await fetch('/api/uploads', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({
+                    name: file.name,
+                    size: file.size,
+                    modified: new Date(file.lastModified).toISOString(),
+                }),
+            });
+            if (!created.ok) throw new Error(`HTTP ${created.status}`);
+            const session = await created.json();
+
+            let offset = session.offset;
+            let failures = 0;
+            while (true) {
+                const percent = file.size ? Math.floor(offset * 100 / file.size) : 100;
+                show(`Sending ${index + 1} of ${count}… ${percent}%`);
+                let response;
+                try {
+                    // Guests never overwrite what is on the host, a name taken gets a number
+                    response = await fetch(`/api/uploads/${session.id}?conflict=keep_both`, {
+                        method: 'PATCH',
+                        headers: { 'Upload-Offset': String(offset) },
+                        body: file.slice(offset, offset + CHUNK_SIZE),
+                    });
+                } catch (error) {
+                    if (++failures > RETRIES) throw new Error('connection lost');
+                    await new Promise(resolve => setTimeout(resolve, 1000 * failures));
+                    continue;
+                }
+                if (response.status === 503) {
+                    // Transfers are paused on the host
+                    await new Promise(resolve => setTimeout(resolve, 5000));
+                    continue;
+                }
+                // 409 carries the offset the server actually reached
+                if (!response.ok && response.status !== 409) throw new Error(`HTTP ${response.status}`);
+                const progress = await response.json();
+                if (progress.file) return;
+                offset = progress.offset;
+                failures = 0;
+            }
+        }
+
+        async function uploadAll(files) {
+            // Remember a name typed just before tapping
+            nameInput.dispatchEvent(new Event('change'));
+            try {
+                for (const [index, file] of files.entries()) {
+                    await upload(file, index, files.length);
+                }
+                show(`✅ Thank you! ${files.length} file${files.length === 1 ? '' : 's'} sent`);
+            } catch (error) {
+                show(`❌ Sending failed (${error.message}), please try again`);
+            }
+            loadSenders();
+            setTimeout(() => { target.innerHTML = idleText; }, 4000);
+        }
+
+        target.addEventListener('click', () => picker.click());
+        picker.addEventListener('change', () => {
+            uploadAll([...picker.files]);
+            picker.value = '';
+        });
+        target.addEventListener('dragover', event => {
+            event.preventDefault();
+            target.classList.add('dragover');
+        });
+        target.addEventListener('dragleave', () => target.classList.remove('dragover'));
+        target.addEventListener('drop', event => {
+            event.preventDefault();
+            target.classList.remove('dragover');
+            uploadAll([...event.dataTransfer.files]);
+        });
+
+        async function loadSenders() {
+            const response = await fetch('/api/kiosk/senders');
+            if (!response.ok) return;
+            const senders = await response.json();
+            document.getElementById('senders').replaceChildren(...senders.map(sender => {
+                const chip = document.createElement('span');
+                chip.className = 'sender';
+                chip.textContent = `${sender.name} · ${sender.files}`;
+                return chip;
+            }));
+        }
+
+        loadSenders();
+        if (window.EventSource) {
+            new EventSource('/api/events').addEventListener('activity', loadSenders);
+        } else {
+            setInterval(loadSenders, 10000);
+        }
+    </script>
+</body>
+</html>
+"#;
@@ -0,0 +1,387 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tracing::{error, info};
+
+use crate::core::links::{record_count, sealed_len, ShareLink, KDF_ITERATIONS, RECORD_SIZE};
+use crate::core::models::FileInfo;
+use crate::utils::http::insert_file_headers;
+use crate::web::state::AppState;
+
+/// Links expire after a week unless asked otherwise
+const DEFAULT_LIFETIME_HOURS: i64 = 24 * 7;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateLinkRequest {
+    pub file_id: String,
+    /// Never stored; tell it to the recipient through another channel than the link
+    pub passphrase: String,
+    /// Hours until the link stops working, 0 for never
+    pub expires_in_hours: Option<i64>,
+}
+
+/// What the recipient's browser needs to derive the key and decrypt the download
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareLinkInfo {
+    pub token: String,
+    /// Page decrypting the file in the browser
+    pub url: String,
+    pub name: String,
+    pub size: u64,
+    pub salt: String,
+    pub iterations: u32,
+    pub record_size: usize,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl ShareLinkInfo {
+    fn new(link: &ShareLink, file: &FileInfo) -> Self {
+        Self {
+            token: link.token.clone(),
+            url: format!("/s/{}", link.token),
+            name: file.name.rsplit('/').next().unwrap_or(&file.name).to_string(),
+            size: file.size,
+            salt: link.salt.clone(),
+            iterations: link.iterations,
+            record_size: RECORD_SIZE,
+            expires: link.expires,
+        }
+    }
+}
+
+/// Create an encrypted share link to a file
+pub async fn create_share_link(
+    State(state): State<AppState>,
+    Json(request): Json<CreateLinkRequest>,
+) -> Result<Json<ShareLinkInfo>, StatusCode> {
+    if request.passphrase.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let file = state.find_file(&request.file_id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", request.file_id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    let lifetime = match request.expires_in_hours.unwrap_or(DEFAULT_LIFETIME_HOURS) {
+        0 => None,
+        hours if hours > 0 => Some(Duration::hours(hours)),
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    let passphrase = request.passphrase;
+    let name = file.name.clone();
+    // Key derivation is deliberately slow, keep it off the async workers
+    let link = {
+        let links = state.links.clone();
+        tokio::task::spawn_blocking(move || links.create(&name, &passphrase, lifetime, KDF_ITERATIONS))
+    }
+    .await
+    .map_err(|e| {
+        error!("Share link creation panicked: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map_err(|e| {
+        error!("Failed to save share link for {}: {}", file.name, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("Created encrypted share link for {}", file.name);
+    Ok(Json(ShareLinkInfo::new(&link, &file)))
+}
+
+/// Links that have not expired yet
+pub async fn list_share_links(State(state): State<AppState>) -> Result<Json<Vec<ShareLinkInfo>>, StatusCode> {
+    let files = state.list_share().map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let links = state
+        .links
+        .list()
+        .iter()
+        .filter_map(|link| {
+            let file = files.iter().find(|file| file.name == link.file)?;
+            Some(ShareLinkInfo::new(link, file))
+        })
+        .collect();
+    Ok(Json(links))
+}
+
+pub async fn get_share_link(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<ShareLinkInfo>, StatusCode> {
+    let (link, file) = linked_file(&state, &token)?;
+    Ok(Json(ShareLinkInfo::new(&link, &file)))
+}
+
+pub async fn revoke_share_link(State(state): State<AppState>, Path(token): Path<String>) -> StatusCode {
+    match state.links.revoke(&token) {
+        Ok(true) => {
+            info!("Revoked share link {}", token);
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to save share links: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Stream the linked file encrypted, record by record
+pub async fn download_share_link(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Response, StatusCode> {
+    let (link, file) = linked_file(&state, &token)?;
+
+    let sealer = link.sealer().map_err(|e| {
+        error!("Failed to prepare encryption: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let handle = tokio::fs::File::open(&file.path).await.map_err(|e| {
+        error!("Failed to open file: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let size = file.size;
+    let records = record_count(size);
+    let prefix = Bytes::copy_from_slice(&sealer.prefix());
+    let sealed = stream::try_unfold((handle, sealer, 0u64), move |(mut handle, mut sealer, index)| async move {
+        if index == records {
+            return Ok(None);
+        }
+        let length = (size - index * RECORD_SIZE as u64).min(RECORD_SIZE as u64) as usize;
+        let mut record = vec![0u8; length];
+        // A file that shrank while being sent fails here, and the browser notices the missing records
+        handle.read_exact(&mut record).await?;
+        let record = sealer.seal(record, index + 1 == records).map_err(std::io::Error::other)?;
+        Ok::<_, std::io::Error>(Some((Bytes::from(record), (handle, sealer, index + 1))))
+    });
+    let body = stream::once(async move { Ok(prefix) }).chain(sealed);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(sealed_len(size)));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    insert_file_headers(&mut headers);
+
+    info!("Sending {} through an encrypted share link", file.name);
+    Ok((headers, Body::from_stream(body)).into_response())
+}
+
+/// The page asking for the passphrase and decrypting the download
+pub async fn serve_link_page() -> Html<&'static str> {
+    Html(LINK_PAGE)
+}
+
+fn linked_file(state: &AppState, token: &str) -> Result<(ShareLink, FileInfo), StatusCode> {
+    let link = state.links.get(token).ok_or(StatusCode::NOT_FOUND)?;
+    let file = state
+        .list_share()
+        .map_err(|e| {
+            error!("Failed to list directory: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .into_iter()
+        .find(|file| file.name == link.file)
+        .ok_or_else(|| {
+            error!("File of share link {} is gone: {}", token, link.file);
+            StatusCode::NOT_FOUND
+        })?;
+    Ok((link, file))
+}
+
+const LINK_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>RustDrop - Encrypted File</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
+            max-width: 400px;
+            margin: 80px auto;
+            padding: 20px;
+            color: #333;
+            text-align: center;
+        }
+        h1 {
+            color: #2c3e50;
+        }
+        input {
+            font-size: 1.2em;
+            width: 100%;
+            box-sizing: border-box;
+            padding: 8px;
+        }
+        button {
+            margin-top: 16px;
+            background-color: #3498db;
+            color: white;
+            border: none;
+            padding: 10px 20px;
+            border-radius: 4px;
+            cursor: pointer;
+            font-size: 1em;
+        }
+        button:disabled {
+            background-color: #95a5a6;
+        }
+        .error {
+            color: #e74c3c;
+        }
+    </style>
+</head>
+<body>
+    <h1>🔒 Encrypted File</h1>
+    <p id="file-name">Loading...</p>
+    <form id="unlock-form" hidden>
+        <input id="passphrase" type="password" autocomplete="off" placeholder="Passphrase" autofocus>
+        <button id="unlock-button" type="submit">Decrypt and download</button>
+    </form>
+    <p id="message"></p>
+    <script>
+        const token = location.pathname.split('/').pop();
+        const message = document.getElementById('message');
+        const TAG_LENGTH = 16;
+        const PREFIX_LENGTH = 7;
+        let link = null;
+
+        function showError(text) {
+            message.textContent = text;
+            message.className = 'error';
+        }
+
+        function base64Bytes(text) {
+            return Uint8Array.from(atob(text), c => c.charCodeAt(0));
+        }
+
+        function recordNonce(prefix, counter, last) {
+            const nonce = new Uint8Array(12);
+            nonce.set(prefix);
+            new DataView(nonce.buffer).setUint32(PREFIX_LENGTH, counter);
+            nonce[11] = last ? 1 : 0;
+            return nonce;
+        }
+
+        async function deriveKey(passphrase) {
+            const material = await crypto.subtle.importKey(
+                'raw', new TextEncoder().encode(passphrase), 'PBKDF2', false, ['deriveKey']);
+            return crypto.subtle.deriveKey(
+                { name: 'PBKDF2', hash: 'SHA-256', salt: base64Bytes(link.salt), iterations: link.iterations },
+                material,
+                { name: 'AES-GCM', length: 256 },
+                false,
+                ['decrypt']);
+        }
+
+        async function decryptDownload(key) {
+            const response = await fetch(`/api/links/${token}/content`);
+            if (!response.ok) {
+                throw new Error(`Download failed (HTTP ${response.status})`);
+            }
+
+            const records = Math.max(1, Math.ceil(link.size / link.record_size));
+            const reader = response.body.getReader();
+            const parts = [];
+            let buffer = new Uint8Array(0);
+            let prefix = null;
+            let counter = 0;
+            let received = 0;
+
+            const recordLength = index => Math.min(link.record_size, link.size - index * link.record_size) + TAG_LENGTH;
+            for (;;) {
+                const { done, value } = await reader.read();
+                if (value) {
+                    const joined = new Uint8Array(buffer.length + value.length);
+                    joined.set(buffer);
+                    joined.set(value, buffer.length);
+                    buffer = joined;
+                }
+                if (!prefix && buffer.length >= PREFIX_LENGTH) {
+                    prefix = buffer.slice(0, PREFIX_LENGTH);
+                    buffer = buffer.slice(PREFIX_LENGTH);
+                }
+                while (prefix && counter < records && buffer.length >= recordLength(counter)) {
+                    const length = recordLength(counter);
+                    const last = counter === records - 1;
+                    try {
+                        parts.push(await crypto.subtle.decrypt(
+                            { name: 'AES-GCM', iv: recordNonce(prefix, counter, last) },
+                            key,
+                            buffer.slice(0, length)));
+                    } catch (error) {
+                        throw new Error(counter === 0 ? 'Wrong passphrase' : 'The download was tampered with');
+                    }
+                    buffer = buffer.slice(length);
+                    received += length - TAG_LENGTH;
+                    counter += 1;
+                    message.textContent = `Decrypted ${Math.round(received / Math.max(link.size, 1) * 100)}%`;
+                }
+                if (done) {
+                    break;
+                }
+            }
+            if (counter !== records) {
+                throw new Error('The download was cut off, please try again');
+            }
+            return new Blob(parts, { type: 'application/octet-stream' });
+        }
+
+        document.getElementById('unlock-form').addEventListener('submit', async event => {
+            event.preventDefault();
+            const button = document.getElementById('unlock-button');
+            button.disabled = true;
+            message.className = '';
+            message.textContent = 'Deriving key...';
+            try {
+                const key = await deriveKey(document.getElementById('passphrase').value);
+                const blob = await decryptDownload(key);
+                const anchor = document.createElement('a');
+                anchor.href = URL.createObjectURL(blob);
+                anchor.download = link.name;
+                anchor.click();
+                message.textContent = 'Done';
+            } catch (error) {
+                showError(error.message);
+            } finally {
+                button.disabled = false;
+            }
+        });
+
+        (async () => {
+            const fileName = document.getElementById('file-name');
+            if (!window.crypto || !crypto.subtle) {
+                fileName.textContent = '';
+                showError('This browser cannot decrypt files here. Open the link over HTTPS or on localhost.');
+                return;
+            }
+            const response = await fetch(`/api/links/${token}`);
+            if (!response.ok) {
+                fileName.textContent = '';
+                showError('This link does not exist or has expired');
+                return;
+            }
+            link = await response.json();
+            fileName.textContent = `${link.name} (${link.size} bytes)`;
+            document.getElementById('unlock-form').hidden = false;
+        })();
+    </script>
+</body>
+</html>
+"#;
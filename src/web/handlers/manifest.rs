@@ -0,0 +1,91 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::utils::hash::{hash_bytes, hash_file, sha256_manifest};
+use crate::web::state::AppState;
+
+/// A file's contents at the time it was hashed, for checking a download
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub name: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    pub sha256: String,
+}
+
+/// `SHA256SUMS` of every file in the share, for checking a downloaded batch with `sha256sum -c`
+pub async fn get_manifest(State(state): State<AppState>) -> Result<impl IntoResponse, StatusCode> {
+    let files = state.list_share().map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Hashing reads every file, keep it off the async workers
+    let manifest = tokio::task::spawn_blocking(move || sha256_manifest(&files))
+        .await
+        .map_err(|e| {
+            error!("Manifest task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map_err(|e| {
+            error!("Failed to hash files: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"SHA256SUMS\""),
+        ],
+        manifest,
+    ))
+}
+
+/// SHA-256 of one file, which `rustdrop get` checks its download against
+pub async fn get_file_checksum(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<FileChecksum>, StatusCode> {
+    let file = state.find_file(&id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    // Files only the storage backend still has
+    let sha256 = if let Some(backend) = state.backend.as_ref().filter(|_| !file.path.exists()) {
+        let data = backend.read(&file.name).await.map_err(|e| {
+            error!("Failed to read {} from {}: {:#}", file.name, backend.describe(), e);
+            StatusCode::BAD_GATEWAY
+        })?;
+        hash_bytes(&data)
+    } else {
+        let path = file.path.clone();
+        tokio::task::spawn_blocking(move || hash_file(&path))
+            .await
+            .map_err(|e| {
+                error!("Checksum task failed: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .map_err(|e| {
+                error!("Failed to hash {}: {}", file.name, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+    };
+
+    Ok(Json(FileChecksum {
+        name: file.name,
+        size: file.size,
+        modified: file.modified,
+        sha256,
+    }))
+}
@@ -1,2 +1,38 @@
+pub mod access;
 pub mod api;
+pub mod attributes;
+pub mod backup;
+pub mod bulk;
+pub mod clients;
+pub mod collect;
+pub mod duplicates;
+pub mod events;
+pub mod folders;
+pub mod history;
+pub mod instance;
+pub mod jump;
+pub mod kiosk;
+pub mod links;
+pub mod manifest;
+pub mod outbox;
+pub mod pins;
+pub mod public;
+pub mod pwa;
+pub mod queue;
+pub mod relay;
+pub mod render;
+pub mod schedule;
+pub mod search;
+pub mod slideshow;
+pub mod snapshot;
 pub mod static_files;
+pub mod streams;
+pub mod tags;
+pub mod tail;
+pub mod thumbnails;
+pub mod transcode;
+pub mod tus;
+pub mod uploads;
+pub mod usage;
+pub mod v2;
+pub mod welcome;
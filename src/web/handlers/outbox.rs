@@ -0,0 +1,52 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::core::courier::OutboxStatus;
+use crate::core::models::FileInfo;
+use crate::utils::file::get_share_file_info;
+use crate::web::error::ApiError;
+use crate::web::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct OutboxRequest {
+    pub file_id: String,
+}
+
+/// Every configured peer with the files waiting for it and how delivering them goes
+pub async fn list_outboxes(State(state): State<AppState>) -> Json<Vec<OutboxStatus>> {
+    Json(state.courier.statuses())
+}
+
+/// Put a file of the share into a peer's outbox, to be delivered once the peer is reachable
+pub async fn enqueue_outbox(
+    State(state): State<AppState>,
+    Path(peer): Path<String>,
+    Json(request): Json<OutboxRequest>,
+) -> Result<(StatusCode, Json<FileInfo>), ApiError> {
+    let peer = state.courier.peer(&peer).ok_or(StatusCode::NOT_FOUND)?;
+    let file = state
+        .find_file(&request.file_id)
+        .map_err(|e| {
+            error!("Failed to look up file {}: {}", request.file_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let target = state
+        .courier
+        .enqueue(peer, &file)
+        .await
+        .map_err(|e| ApiError::storage(&state, &format!("add {} to the outbox of {}", file.name, peer.name), &e))?;
+    let queued = get_share_file_info(&state.directory, &target).map_err(|e| {
+        error!("Failed to get file info for {:?}: {}", target, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("Queued {} for {} in {}", file.name, peer.name, queued.name);
+    Ok((StatusCode::CREATED, Json(queued)))
+}
@@ -0,0 +1,85 @@
+use axum::{
+    extract::{Path, State},
+    http::{Method, StatusCode},
+    Json,
+};
+use tracing::{error, info};
+
+use crate::core::models::FileInfo;
+use crate::web::session::ClientId;
+use crate::web::state::AppState;
+
+/// Pin (`PUT`) or unpin (`DELETE`) a file for the requesting client
+pub async fn set_file_pin(
+    State(state): State<AppState>,
+    client: ClientId,
+    method: Method,
+    Path(id): Path<String>,
+) -> Result<Json<FileInfo>, StatusCode> {
+    let pinned = method == Method::PUT;
+
+    let mut file = state.find_file(&id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    state.metadata.set_pinned(client.as_str(), &file.name, pinned).map_err(|e| {
+        error!("Failed to save pin for {}: {}", file.name, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    state.metadata.annotate(&mut file);
+    file.pinned = pinned;
+
+    info!("{} {} for client {}", if pinned { "Pinned" } else { "Unpinned" }, file.name, client.as_str());
+    Ok(Json(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::DeviceInfo;
+    use crate::utils::file::get_file_info;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_pin_and_unpin() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("often.txt");
+        std::fs::write(&file_path, "content").unwrap();
+        let id = get_file_info(&file_path).unwrap().id.to_string();
+
+        let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+        let client = ClientId("client-a".to_string());
+
+        let Json(file) = set_file_pin(State(state.clone()), client.clone(), Method::PUT, Path(id.clone()))
+            .await
+            .unwrap();
+        assert!(file.pinned);
+        assert!(state.metadata.pinned("client-a").contains("often.txt"));
+
+        let Json(file) = set_file_pin(State(state.clone()), client, Method::DELETE, Path(id))
+            .await
+            .unwrap();
+        assert!(!file.pinned);
+        assert!(state.metadata.pinned("client-a").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pin_unknown_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+
+        let result = set_file_pin(
+            State(state),
+            ClientId("client-a".to_string()),
+            Method::PUT,
+            Path("missing".to_string()),
+        )
+        .await;
+        assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,224 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, Method, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::core::models::FileInfo;
+use crate::web::handlers::api::{download_file, DownloadQuery};
+use crate::web::handlers::thumbnails::{get_thumbnail, ThumbnailQuery};
+use crate::web::session::ClientId;
+use crate::web::state::AppState;
+
+/// What guests see of a published file; never its place on the host's disk
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicFile {
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+    pub size_human: String,
+    pub modified: DateTime<Utc>,
+    pub mime_type: String,
+}
+
+impl From<FileInfo> for PublicFile {
+    fn from(file: FileInfo) -> Self {
+        Self {
+            id: file.id.to_string(),
+            name: file.name,
+            size: file.size,
+            size_human: file.size_human,
+            modified: file.modified,
+            mime_type: file.mime_type,
+        }
+    }
+}
+
+/// Publish (`PUT`) a file to the public gallery or take it out again (`DELETE`)
+pub async fn set_file_published(
+    State(state): State<AppState>,
+    method: Method,
+    Path(id): Path<String>,
+) -> Result<Json<FileInfo>, StatusCode> {
+    let published = method == Method::PUT;
+
+    let mut file = state.find_file(&id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    state.metadata.set_published(&file.name, published).map_err(|e| {
+        error!("Failed to save publication of {}: {}", file.name, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    state.metadata.annotate(&mut file);
+
+    info!("{} {}", if published { "Published" } else { "Unpublished" }, file.name);
+    Ok(Json(file))
+}
+
+/// The files of the public gallery, newest first
+pub async fn list_public_files(State(state): State<AppState>) -> Result<Json<Vec<PublicFile>>, StatusCode> {
+    let published = state.metadata.published();
+    if published.is_empty() {
+        return Ok(Json(Vec::new()));
+    }
+
+    let mut files: Vec<FileInfo> = state
+        .list_share()
+        .map_err(|e| {
+            error!("Failed to list directory: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .into_iter()
+        .filter(|file| published.contains(&file.name))
+        .collect();
+    files.sort_by_key(|file| std::cmp::Reverse(file.modified));
+
+    Ok(Json(files.into_iter().map(PublicFile::from).collect()))
+}
+
+/// Content of a published file; anything else in the share stays out of reach
+pub async fn download_public_file(
+    State(state): State<AppState>,
+    client: Option<ClientId>,
+    Path(id): Path<String>,
+    query: Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    published_file(&state, &id)?;
+    download_file(State(state), client, Path(id), query, headers).await
+}
+
+/// Thumbnail of a published file
+pub async fn get_public_thumbnail(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    query: Query<ThumbnailQuery>,
+) -> Result<Response, StatusCode> {
+    published_file(&state, &id)?;
+    get_thumbnail(State(state), Path(id), query).await.map(IntoResponse::into_response)
+}
+
+/// The gallery page guests open, with no access code needed
+pub async fn serve_public_page() -> Html<&'static str> {
+    Html(PUBLIC_PAGE)
+}
+
+/// The file with `id`, if it is published. Unpublished and missing files look the same from outside.
+fn published_file(state: &AppState, id: &str) -> Result<FileInfo, StatusCode> {
+    let file = state.find_file(id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    file.filter(|file| state.metadata.published().contains(&file.name))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+const PUBLIC_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>RustDrop - Gallery</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
+            max-width: 1000px;
+            margin: 30px auto;
+            padding: 0 16px;
+            color: #2c3e50;
+        }
+        .gallery {
+            display: grid;
+            grid-template-columns: repeat(auto-fill, minmax(180px, 1fr));
+            gap: 12px;
+        }
+        .item {
+            display: block;
+            border-radius: 6px;
+            overflow: hidden;
+            background: #ecf0f1;
+            color: inherit;
+            text-decoration: none;
+        }
+        .item img {
+            width: 100%;
+            height: 180px;
+            object-fit: cover;
+            display: block;
+        }
+        .item .icon {
+            height: 180px;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+            font-size: 48px;
+        }
+        .item .name {
+            padding: 8px;
+            font-size: 14px;
+            overflow: hidden;
+            text-overflow: ellipsis;
+            white-space: nowrap;
+        }
+        .hint { color: #7f8c8d; }
+    </style>
+</head>
+<body>
+    <h1>Gallery</h1>
+    <div class="gallery" id="gallery"></div>
+    <p class="hint" id="empty" hidden>Nothing has been published yet.</p>
+
+    <script>
+        function hasThumbnail(file) {
+            return file.mime_type.startsWith('image/') || file.mime_type === 'application/pdf';
+        }
+
+        async function loadGallery() {
+            const response = await fetch('/api/public');
+            const files = response.ok ? await response.json() : [];
+            const gallery = document.getElementById('gallery');
+            document.getElementById('empty').hidden = files.length > 0;
+
+            gallery.replaceChildren(...files.map(file => {
+                const item = document.createElement('a');
+                item.className = 'item';
+                item.href = `/public/${file.id}?inline=true`;
+                item.target = '_blank';
+
+                if (hasThumbnail(file)) {
+                    const image = document.createElement('img');
+                    image.src = `/public/${file.id}/thumbnail?size=360`;
+                    image.loading = 'lazy';
+                    image.alt = file.name;
+                    item.append(image);
+                } else {
+                    const icon = document.createElement('div');
+                    icon.className = 'icon';
+                    icon.textContent = '📄';
+                    item.append(icon);
+                }
+
+                const name = document.createElement('div');
+                name.className = 'name';
+                name.textContent = `${file.name} · ${file.size_human}`;
+                item.append(name);
+                return item;
+            }));
+        }
+
+        loadGallery();
+    </script>
+</body>
+</html>
+"#;
@@ -0,0 +1,61 @@
+use axum::{
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+/// Web app manifest, so phones can add RustDrop to their home screen
+pub async fn serve_web_manifest() -> impl IntoResponse {
+    let manifest = json!({
+        "name": "RustDrop",
+        "short_name": "RustDrop",
+        "start_url": "/",
+        "scope": "/",
+        "display": "standalone",
+        "background_color": "#f5f7fa",
+        "theme_color": "#3498db",
+        "icons": [
+            { "src": "/icon.svg", "sizes": "any", "type": "image/svg+xml", "purpose": "any" }
+        ],
+        "shortcuts": [
+            { "name": "Photo backup", "url": "/backup" }
+        ],
+    });
+    ([(header::CONTENT_TYPE, "application/manifest+json")], Json(manifest))
+}
+
+/// Service worker of the installed app. It leaves requests to the network, as everything
+/// RustDrop shows lives on the host, and only explains why pages fail to load away from it.
+pub async fn serve_service_worker() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/javascript; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        SERVICE_WORKER,
+    )
+}
+
+pub async fn serve_icon() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "image/svg+xml")], ICON)
+}
+
+const SERVICE_WORKER: &str = r#"self.addEventListener('install', () => self.skipWaiting());
+self.addEventListener('activate', event => event.waitUntil(self.clients.claim()));
+self.addEventListener('fetch', event => {
+    if (event.request.mode !== 'navigate') return;
+    event.respondWith(fetch(event.request).catch(() => new Response(
+        '<!DOCTYPE html><meta name="viewport" content="width=device-width, initial-scale=1.0">' +
+        '<p style="font-family: sans-serif; padding: 24px">RustDrop cannot be reached. ' +
+        'Join the same network as the host and try again.</p>',
+        { headers: { 'Content-Type': 'text/html; charset=utf-8' } }
+    )));
+});
+"#;
+
+const ICON: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 512 512">
+<rect width="512" height="512" rx="96" fill="#3498db"/>
+<path d="M256 96c-64 88-112 150-112 214a112 112 0 0 0 224 0c0-64-48-126-112-214z" fill="#fff"/>
+</svg>
+"##;
@@ -0,0 +1,146 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::client::queue::run_queue;
+use crate::client::PeerClient;
+use crate::core::events::ServerEvent;
+use crate::core::queue::{QueueError, QueuedTransfer, TransferPriority};
+use crate::web::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct EnqueueRequest {
+    pub file_id: String,
+    /// Address of the device to send to, `host:port` or a URL
+    pub peer: String,
+    #[serde(default)]
+    pub priority: TransferPriority,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferUpdate {
+    pub priority: TransferPriority,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelResponse {
+    pub cancelled: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClearResponse {
+    pub cleared: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransfersState {
+    pub paused: bool,
+}
+
+/// The transfer in progress, the queue in sending order and recently finished transfers
+pub async fn list_queue(State(state): State<AppState>) -> Json<Vec<QueuedTransfer>> {
+    Json(state.queue.list())
+}
+
+/// Queue a file of the share for sending to another device
+pub async fn enqueue_transfer(
+    State(state): State<AppState>,
+    Json(request): Json<EnqueueRequest>,
+) -> Result<(StatusCode, Json<QueuedTransfer>), StatusCode> {
+    let file = state
+        .find_file(&request.file_id)
+        .map_err(|e| {
+            error!("Failed to look up file {}: {}", request.file_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    PeerClient::new(&request.peer).map_err(|e| {
+        error!("Invalid peer {:?}: {}", request.peer, e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    // Stored in the peer's usual upload location, like `rustdrop send`
+    let name = file.name.rsplit('/').next().unwrap_or(&file.name);
+    let transfer = state.queue.push(QueuedTransfer::new(
+        &request.file_id,
+        name,
+        file.size,
+        file.path,
+        request.peer.trim(),
+        request.priority,
+    ));
+    if state.queue.claim_worker() {
+        tokio::spawn(run_queue(
+            state.queue.clone(),
+            state.pause.clone(),
+            state.events.clone(),
+            state.network.clone(),
+        ));
+    }
+
+    info!("Queued {} for {} ({:?} priority)", transfer.name, transfer.peer, transfer.priority);
+    Ok((StatusCode::CREATED, Json(transfer)))
+}
+
+pub async fn get_transfer(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<QueuedTransfer>, StatusCode> {
+    state.queue.get(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Change the priority of a transfer that has not started yet
+pub async fn update_transfer(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(update): Json<TransferUpdate>,
+) -> Result<Json<QueuedTransfer>, StatusCode> {
+    state
+        .queue
+        .set_priority(&id, update.priority)
+        .map(Json)
+        .map_err(|e| match e {
+            QueueError::NotFound => StatusCode::NOT_FOUND,
+            QueueError::NotQueued => StatusCode::CONFLICT,
+        })
+}
+
+/// Cancel the transfer in progress and everything still queued
+pub async fn cancel_queue(State(state): State<AppState>) -> Json<CancelResponse> {
+    let cancelled = state.queue.cancel_all();
+    if cancelled > 0 {
+        info!("Cancelled {} queued transfers", cancelled);
+    }
+    Json(CancelResponse { cancelled })
+}
+
+/// Forget sent, failed and cancelled transfers
+pub async fn clear_queue(State(state): State<AppState>) -> Json<ClearResponse> {
+    Json(ClearResponse { cleared: state.queue.clear_finished() })
+}
+
+/// Whether transfers are paused
+pub async fn get_transfers_state(State(state): State<AppState>) -> Json<TransfersState> {
+    Json(TransfersState { paused: state.pause.is_paused() })
+}
+
+/// Hold the send queue and chunked uploads from other devices until resumed
+pub async fn pause_transfers(State(state): State<AppState>) -> Json<TransfersState> {
+    set_paused(&state, true)
+}
+
+pub async fn resume_transfers(State(state): State<AppState>) -> Json<TransfersState> {
+    set_paused(&state, false)
+}
+
+fn set_paused(state: &AppState, paused: bool) -> Json<TransfersState> {
+    if state.pause.set(paused) {
+        info!("Transfers {}", if paused { "paused" } else { "resumed" });
+        state.events.publish(ServerEvent::TransfersPaused { paused });
+    }
+    Json(TransfersState { paused })
+}
@@ -0,0 +1,131 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{stream, StreamExt};
+use mime_guess::from_path;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::core::events::ServerEvent;
+use crate::core::relay::{RelayChunk, RelayOffer};
+use crate::utils::filename::valid_file_name;
+use crate::utils::http::{content_disposition, insert_file_headers};
+use crate::web::session::sender_name;
+use crate::web::state::AppState;
+
+/// An upload gives up when the downloader has not taken any data for this long
+const STALL_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRelayRequest {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Offer a file to the next device that downloads it; the data follows with `PUT`
+pub async fn create_relay(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<CreateRelayRequest>,
+) -> Result<(StatusCode, Json<RelayOffer>), StatusCode> {
+    if !valid_file_name(&request.name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let offer = state.relays.offer(&request.name, request.size, sender_name(&headers));
+    info!("Relay {} offers {} ({} bytes)", offer.id, offer.name, offer.size);
+    state.events.publish(ServerEvent::RelayOffered(offer.clone()));
+    Ok((StatusCode::CREATED, Json(offer)))
+}
+
+/// Offers waiting for a device to download them
+pub async fn list_relays(State(state): State<AppState>) -> Json<Vec<RelayOffer>> {
+    Json(state.relays.waiting())
+}
+
+/// Download a relayed file as the uploader sends it
+pub async fn download_relay(State(state): State<AppState>, Path(id): Path<String>) -> Result<Response, StatusCode> {
+    let (offer, download) = match state.relays.claim(&id) {
+        Some(claimed) => claimed,
+        None if state.relays.get(&id).is_some() => return Err(StatusCode::CONFLICT),
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let mut headers = HeaderMap::new();
+    let mime_type = from_path(&offer.name).first_or_octet_stream();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(mime_type.as_ref()).unwrap());
+    headers.insert(header::CONTENT_DISPOSITION, content_disposition("attachment", &offer.name));
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(offer.size));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    insert_file_headers(&mut headers);
+
+    info!("Relay {} of {} claimed", offer.id, offer.name);
+    Ok((headers, relayed_body(download)).into_response())
+}
+
+/// Feed the data of an offer through to its downloader, waiting for one if nobody claimed it yet
+pub async fn upload_relay(State(state): State<AppState>, Path(id): Path<String>, body: Body) -> StatusCode {
+    let (offer, upload) = match state.relays.start_upload(&id) {
+        Some(started) => started,
+        None if state.relays.get(&id).is_some() => return StatusCode::CONFLICT,
+        None => return StatusCode::NOT_FOUND,
+    };
+
+    let status = forward_body(body, &upload, Some(offer.size)).await;
+    state.relays.finish(&id);
+    if status == StatusCode::NO_CONTENT {
+        info!("Relayed {} ({} bytes)", offer.name, offer.size);
+    } else {
+        warn!("Relay of {} failed: {}", offer.name, status);
+    }
+    status
+}
+
+/// A response body passing on the chunks of a relay or pipe as they arrive
+pub(crate) fn relayed_body(download: mpsc::Receiver<RelayChunk>) -> Body {
+    Body::from_stream(stream::unfold(download, |mut download| async move {
+        download.recv().await.map(|chunk| (chunk, download))
+    }))
+}
+
+/// Pass a request body on to the downloading side, checking its length if one was announced.
+/// Returns the status for the uploader.
+pub(crate) async fn forward_body(body: Body, upload: &mpsc::Sender<RelayChunk>, expected: Option<u64>) -> StatusCode {
+    let mut stream = body.into_data_stream();
+    let mut received = 0u64;
+
+    // The downloader also sees errors (or at least fewer bytes than announced), so a broken
+    // upload never looks like a complete file
+    let fail = |status: StatusCode, reason: &str| {
+        let _ = upload.try_send(Err(std::io::Error::other(reason.to_string())));
+        status
+    };
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(_) => return fail(StatusCode::BAD_REQUEST, "Upload interrupted"),
+        };
+        received += chunk.len() as u64;
+        if expected.is_some_and(|expected| received > expected) {
+            return fail(StatusCode::PAYLOAD_TOO_LARGE, "Upload larger than announced");
+        }
+
+        match tokio::time::timeout(STALL_TIMEOUT, upload.send(Ok(chunk))).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => return StatusCode::GONE,
+            Err(_) => return StatusCode::REQUEST_TIMEOUT,
+        }
+    }
+
+    if expected.is_some_and(|expected| received < expected) {
+        return fail(StatusCode::BAD_REQUEST, "Upload shorter than announced");
+    }
+    StatusCode::NO_CONTENT
+}
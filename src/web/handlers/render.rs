@@ -0,0 +1,94 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::Html,
+};
+use tracing::{error, info};
+
+use crate::utils::render::{
+    render_code, render_kind, render_markdown, render_page, RenderKind, MAX_RENDER_SIZE, RENDER_CSP,
+};
+use crate::web::state::AppState;
+
+/// Render a Markdown or source file as an HTML page for quick reading
+pub async fn render_file(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<([(header::HeaderName, &'static str); 1], Html<String>), StatusCode> {
+    let file = state.find_file(&id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    let kind = render_kind(&file.name, &file.mime_type).ok_or_else(|| {
+        info!("File cannot be rendered: {} ({})", file.name, file.mime_type);
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    })?;
+
+    if file.size > MAX_RENDER_SIZE {
+        info!("File too large to render: {} ({} bytes)", file.name, file.size);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let data = tokio::fs::read(&file.path).await.map_err(|e| {
+        error!("Failed to read file: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Highlighting is CPU bound, keep it off the async workers
+    let name = file.name.clone();
+    let body = tokio::task::spawn_blocking(move || {
+        let source = String::from_utf8_lossy(&data);
+        match kind {
+            RenderKind::Markdown => render_markdown(&source),
+            RenderKind::Code => render_code(&source, &name),
+        }
+    })
+    .await
+    .map_err(|e| {
+        error!("Rendering task failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("File rendered: {}", file.name);
+    Ok(([(header::CONTENT_SECURITY_POLICY, RENDER_CSP)], Html(render_page(&file.name, &body))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::DeviceInfo;
+    use crate::utils::file::get_file_info;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_render_markdown_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("README.md");
+        std::fs::write(&file_path, "# Hello\n\nWorld").unwrap();
+        let id = get_file_info(&file_path).unwrap().id.to_string();
+
+        let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+        let ([(_, policy)], Html(page)) = render_file(State(state), Path(id)).await.unwrap();
+
+        assert!(page.contains("<h1>Hello</h1>"));
+        assert!(policy.contains("default-src 'none'"));
+        assert!(page.contains("<title>README.md - RustDrop</title>"));
+    }
+
+    #[tokio::test]
+    async fn test_render_binary_file_unsupported() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("photo.jpg");
+        std::fs::write(&file_path, [0xffu8, 0xd8, 0xff]).unwrap();
+        let id = get_file_info(&file_path).unwrap().id.to_string();
+
+        let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+        let result = render_file(State(state), Path(id)).await;
+
+        assert_eq!(result.unwrap_err(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -0,0 +1,65 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use chrono::Local;
+
+use crate::web::session::client_ip;
+use crate::web::state::AppState;
+
+/// Middleware turning clients away outside the scheduled hours. The host itself is always
+/// served, so it can still look at the share. Must run inside `forwarded_headers`.
+pub async fn schedule_guard(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let from_host = client_ip(request.extensions()).is_some_and(|ip| ip.is_loopback());
+    let closed = state.schedule.until_open(Local::now().time()).filter(|_| !from_host);
+    let Some(until_open) = closed else {
+        return next.run(request).await;
+    };
+
+    let hours = state.schedule.to_string();
+    let mut response = if request.uri().path().starts_with("/api/") {
+        let body = serde_json::json!({ "error": "outside the scheduled hours", "hours": hours });
+        (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Html(CLOSED_PAGE.replace("{hours}", &hours))).into_response()
+    };
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(until_open.as_secs()));
+    response
+}
+
+const CLOSED_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>RustDrop - Closed</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
+            max-width: 400px;
+            margin: 80px auto;
+            padding: 20px;
+            color: #333;
+            text-align: center;
+        }
+        h1 {
+            color: #2c3e50;
+        }
+        .hours {
+            font-size: 1.5em;
+            margin: 24px 0;
+        }
+    </style>
+</head>
+<body>
+    <h1>Closed for now</h1>
+    <p>This device takes files only at certain times. Please come back during:</p>
+    <p class="hours">{hours}</p>
+</body>
+</html>
+"#;
@@ -0,0 +1,55 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::core::models::FileInfo;
+use crate::core::search;
+use crate::utils::file::is_hidden;
+use crate::web::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    /// Words that all have to appear in a file's name or text
+    pub q: String,
+    /// Include hidden and system files, defaults to `files.show_hidden`
+    pub hidden: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub file: FileInfo,
+    /// The part of the file's text that matched, when its name did not
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+}
+
+/// Find files by their name or, for documents whose text was extracted, by what they say
+pub async fn search_files(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>, StatusCode> {
+    let terms = search::Query::parse(&query.q).ok_or(StatusCode::BAD_REQUEST)?;
+    let mut files = state.list_share().map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if !query.hidden.unwrap_or(state.config.files.show_hidden) {
+        files.retain(|f| !is_hidden(&f.name));
+    }
+
+    let hits = files
+        .into_iter()
+        .filter_map(|mut file| {
+            let snippet = state.text.search(&terms, &file.name)?;
+            state.metadata.annotate(&mut file);
+            Some(SearchHit { file, snippet })
+        })
+        .collect();
+    Ok(Json(hits))
+}
@@ -0,0 +1,108 @@
+use axum::response::Html;
+
+/// Full-screen slideshow of the share's images for a TV browser, showing new photos as they arrive
+pub async fn serve_slideshow_page() -> Html<&'static str> {
+    Html(SLIDESHOW_PAGE)
+}
+
+const SLIDESHOW_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>RustDrop - Slideshow</title>
+    <style>
+        html, body {
+            margin: 0;
+            height: 100%;
+            background: black;
+            overflow: hidden;
+            cursor: none;
+        }
+        img {
+            position: absolute;
+            inset: 0;
+            width: 100%;
+            height: 100%;
+            object-fit: contain;
+            opacity: 0;
+            transition: opacity 1s;
+        }
+        img.shown {
+            opacity: 1;
+        }
+        #caption {
+            position: absolute;
+            left: 0;
+            right: 0;
+            bottom: 20px;
+            text-align: center;
+            color: white;
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
+            font-size: 24px;
+            text-shadow: 0 0 6px black;
+        }
+    </style>
+</head>
+<body>
+    <img id="front" alt="">
+    <img id="back" alt="">
+    <div id="caption">Waiting for photos…</div>
+
+    <script>
+        // Seconds per photo, e.g. /slideshow?interval=5
+        const interval = Math.max(2, parseInt(new URLSearchParams(location.search).get('interval')) || 8) * 1000;
+        let images = [];
+        let position = -1;
+        let timer = null;
+        let [front, back] = [document.getElementById('front'), document.getElementById('back')];
+
+        async function loadImages() {
+            const response = await fetch('/api/files');
+            if (!response.ok) return [];
+            const files = await response.json();
+            return files
+                .filter(file => file.mime_type.startsWith('image/'))
+                .sort((a, b) => new Date(a.modified) - new Date(b.modified));
+        }
+
+        function show(index) {
+            if (images.length === 0) return;
+            position = (index + images.length) % images.length;
+            const file = images[position];
+            back.onload = () => {
+                back.classList.add('shown');
+                front.classList.remove('shown');
+                [front, back] = [back, front];
+            };
+            back.src = `/api/files/${file.id}?inline=true`;
+            document.getElementById('caption').textContent = file.sender ? `from ${file.sender}` : '';
+            clearTimeout(timer);
+            timer = setTimeout(() => show(position + 1), interval);
+        }
+
+        // New photos are shown right away, then the slideshow carries on from there
+        async function refresh() {
+            const known = new Set(images.map(file => file.id));
+            const current = images[position];
+            images = await loadImages();
+            const fresh = images.findIndex(file => !known.has(file.id));
+            if (fresh >= 0 && known.size > 0) {
+                show(fresh);
+            } else if (position < 0 || !current) {
+                show(0);
+            } else {
+                position = Math.max(0, images.findIndex(file => file.id === current.id));
+            }
+        }
+
+        refresh();
+        if (window.EventSource) {
+            new EventSource('/api/events').addEventListener('activity', refresh);
+        }
+        // Catch up on anything the events missed
+        setInterval(refresh, 60000);
+    </script>
+</body>
+</html>
+"#;
@@ -0,0 +1,43 @@
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::core::snapshot::SnapshotInfo;
+use crate::web::handlers::access::PRINTER_PATH;
+use crate::web::state::AppState;
+
+/// Requests under these paths change the share or what is kept about its files
+const SHARE_WRITES: &[&str] = &[
+    "/api/backup/items",
+    "/api/files",
+    "/api/outbox",
+    "/api/uploads",
+    "/api/v2/files",
+    PRINTER_PATH,
+];
+
+/// Middleware turning away everything that would change a share served as a snapshot.
+/// Reads, and writes that leave the share alone like entering an access code, go through.
+pub async fn snapshot_guard(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let reads = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let path = request.uri().path();
+    if state.snapshot.is_none() || reads || !SHARE_WRITES.iter().any(|prefix| path.starts_with(prefix)) {
+        return next.run(request).await;
+    }
+
+    let body = serde_json::json!({ "error": "the share is a read-only snapshot" });
+    (StatusCode::FORBIDDEN, Json(body)).into_response()
+}
+
+/// When the snapshot being served was taken, 404 when the live share is served
+pub async fn get_snapshot(State(state): State<AppState>) -> Result<Json<SnapshotInfo>, StatusCode> {
+    state
+        .snapshot
+        .as_ref()
+        .map(|snapshot| Json(snapshot.info()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
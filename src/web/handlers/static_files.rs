@@ -11,6 +11,8 @@ pub async fn serve_index() -> impl IntoResponse {
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <link rel="manifest" href="/manifest.webmanifest" crossorigin="use-credentials">
+    <link rel="icon" href="/icon.svg">
     <title>RustDrop - File Transfer</title>
     <style>
         body {
@@ -96,6 +98,297 @@ pub async fn serve_index() -> impl IntoResponse {
             text-align: center;
             padding: 20px;
         }
+        .tag-filter {
+            display: flex;
+            flex-wrap: wrap;
+            gap: 6px;
+            margin-bottom: 10px;
+        }
+        .tag-chip {
+            display: inline-block;
+            background-color: #ecf0f1;
+            color: #2c3e50;
+            border: none;
+            border-radius: 12px;
+            padding: 2px 10px;
+            margin: 2px 4px 0 0;
+            font-size: 13px;
+            cursor: pointer;
+        }
+        .tag-chip.active {
+            background-color: #3498db;
+            color: white;
+        }
+        .file-actions {
+            display: flex;
+            gap: 6px;
+            align-items: center;
+        }
+        .file-item.pinned {
+            background-color: #fffbe6;
+        }
+        .file-select {
+            margin-right: 10px;
+        }
+        .bulk-bar {
+            display: flex;
+            gap: 6px;
+            align-items: center;
+            padding: 8px 0;
+        }
+        .bulk-bar[hidden] {
+            display: none;
+        }
+        .usage-row {
+            display: flex;
+            align-items: center;
+            gap: 8px;
+            font-size: 13px;
+            margin: 2px 0;
+        }
+        .usage-label {
+            width: 120px;
+            overflow: hidden;
+            text-overflow: ellipsis;
+            white-space: nowrap;
+        }
+        .usage-bar {
+            flex: 1;
+            background-color: #ecf0f1;
+            border-radius: 3px;
+            height: 12px;
+        }
+        .usage-bar div {
+            background-color: #3498db;
+            border-radius: 3px;
+            height: 100%;
+        }
+        .sender {
+            color: #666;
+            font-size: 13px;
+        }
+        .history-list {
+            list-style: none;
+            padding: 0;
+            font-size: 14px;
+        }
+        .history-list li {
+            padding: 4px 0;
+            border-bottom: 1px solid #eee;
+        }
+        .queue-list progress {
+            width: 100%;
+        }
+        .sparkline {
+            vertical-align: middle;
+        }
+        .sparkline polyline {
+            fill: none;
+            stroke: #3498db;
+            stroke-width: 1.5;
+        }
+        .recent-list li {
+            display: flex;
+            gap: 8px;
+            align-items: baseline;
+        }
+        .recent-list .recent-file {
+            flex: 1;
+            overflow: hidden;
+            text-overflow: ellipsis;
+            white-space: nowrap;
+        }
+        .duplicate-group {
+            border-top: 1px solid #eee;
+            padding: 8px 0;
+        }
+        .duplicate-group label {
+            display: block;
+            padding: 2px 0;
+        }
+        .relay-mode {
+            display: block;
+            margin-top: 10px;
+            font-size: 14px;
+            color: #666;
+        }
+        .download-status {
+            margin: 10px 0;
+            font-size: 14px;
+        }
+        /* Read out by screen readers, invisible otherwise */
+        .sr-only {
+            position: absolute;
+            width: 1px;
+            height: 1px;
+            overflow: hidden;
+            clip: rect(0 0 0 0);
+            white-space: nowrap;
+        }
+        :focus-visible {
+            outline: 3px solid #f39c12;
+            outline-offset: 2px;
+        }
+        .file-item:focus-visible {
+            background-color: #fef5e7;
+        }
+        .shortcut-hint {
+            text-align: center;
+            font-size: 13px;
+            color: #666;
+        }
+        .shortcut-hint kbd {
+            border: 1px solid #ccc;
+            border-radius: 3px;
+            padding: 0 4px;
+            background-color: #f8f8f8;
+        }
+        #search-input {
+            width: 100%;
+            box-sizing: border-box;
+            padding: 6px 10px;
+            margin-bottom: 10px;
+            font-size: 14px;
+        }
+        .card-header {
+            display: flex;
+            justify-content: space-between;
+            align-items: center;
+        }
+        .gallery {
+            display: grid;
+            grid-template-columns: repeat(auto-fill, minmax(120px, 1fr));
+            gap: 8px;
+        }
+        .gallery-item {
+            position: relative;
+            aspect-ratio: 1;
+            padding: 0;
+            border: none;
+            border-radius: 4px;
+            overflow: hidden;
+            background-color: #ecf0f1;
+            cursor: pointer;
+        }
+        .gallery-item img,
+        .gallery-item video {
+            width: 100%;
+            height: 100%;
+            object-fit: cover;
+        }
+        .list-thumbnail {
+            width: 40px;
+            height: 40px;
+            object-fit: cover;
+            border-radius: 4px;
+            margin-right: 10px;
+            flex-shrink: 0;
+        }
+        .file-summary {
+            display: flex;
+            align-items: center;
+        }
+        .doc-icon {
+            font-size: 48px;
+            line-height: 120px;
+        }
+        .play-badge {
+            position: absolute;
+            bottom: 6px;
+            right: 6px;
+            color: white;
+            background-color: rgba(0, 0, 0, 0.6);
+            border-radius: 50%;
+            width: 24px;
+            height: 24px;
+            line-height: 24px;
+            font-size: 12px;
+        }
+        .lightbox {
+            position: fixed;
+            inset: 0;
+            background-color: rgba(0, 0, 0, 0.92);
+            display: flex;
+            align-items: center;
+            justify-content: center;
+            z-index: 1000;
+            touch-action: pan-y;
+        }
+        .lightbox[hidden] {
+            display: none;
+        }
+        .lightbox-content img,
+        .lightbox-content video {
+            max-width: 100vw;
+            max-height: 85vh;
+        }
+        .lightbox-content p {
+            color: #fff;
+            padding: 0 20px;
+        }
+        .lightbox-content p a {
+            color: #8ab4f8;
+        }
+        .lightbox-button {
+            position: absolute;
+            background: none;
+            border: none;
+            color: white;
+            font-size: 40px;
+            cursor: pointer;
+            padding: 10px 16px;
+        }
+        .lightbox-close {
+            top: 10px;
+            right: 10px;
+        }
+        .lightbox-prev {
+            left: 0;
+        }
+        .lightbox-next {
+            right: 0;
+        }
+        .lightbox-caption {
+            position: absolute;
+            bottom: 15px;
+            left: 0;
+            right: 0;
+            text-align: center;
+            color: white;
+        }
+        .pin-button {
+            background: none;
+            border: none;
+            font-size: 20px;
+            cursor: pointer;
+            color: #f1c40f;
+        }
+        #conflict-dialog {
+            max-width: 520px;
+            border: none;
+            border-radius: 8px;
+            box-shadow: 0 2px 12px rgba(0,0,0,0.3);
+        }
+        #conflict-dialog table {
+            width: 100%;
+            border-collapse: collapse;
+            font-size: 14px;
+            margin: 10px 0;
+        }
+        #conflict-dialog th, #conflict-dialog td {
+            text-align: left;
+            padding: 4px;
+            border-bottom: 1px solid #eee;
+        }
+        #conflict-dialog code {
+            font-size: 12px;
+        }
+        .conflict-actions {
+            display: flex;
+            gap: 8px;
+            justify-content: flex-end;
+            margin-top: 10px;
+        }
     </style>
 </head>
 <body>
@@ -103,33 +396,125 @@ pub async fn serve_index() -> impl IntoResponse {
     <div class="device-info" id="device-info">
         <p>Loading device information...</p>
     </div>
+    <p class="shortcut-hint" id="shortcut-hint">
+        Keys: <kbd>U</kbd> upload, <kbd>/</kbd> search, <kbd>J</kbd>/<kbd>K</kbd> or arrows to move through files, <kbd>Enter</kbd> download, <kbd>Esc</kbd> close
+    </p>
+    <div class="sr-only" id="announcer" role="status" aria-live="polite"></div>
     
-    <div class="container">
+    <main class="container">
+        <div class="card" id="recent-card" hidden>
+            <h2>Recent</h2>
+            <div id="recent-container"></div>
+        </div>
+        
+        <div class="card" id="queue-card" hidden>
+            <div class="card-header">
+                <h2>Sending To Devices</h2>
+                <div>
+                    <button class="button" id="queue-cancel" title="Stop the transfer in progress and everything still waiting">Cancel all</button>
+                    <button class="button" id="queue-clear" title="Remove sent, failed and cancelled transfers from the list">Clear finished</button>
+                </div>
+            </div>
+            <div id="queue-container"></div>
+        </div>
+        
+        <div class="card" id="collect-card" hidden>
+            <h2>Requests For Your Files</h2>
+            <div id="collect-container"></div>
+        </div>
+        
+        <div class="card" id="relay-card" hidden>
+            <h2>Direct Transfers Waiting For You</h2>
+            <div id="relay-container"></div>
+        </div>
+        
         <div class="card">
-            <h2>Upload Files</h2>
-            <div class="upload-area" id="upload-area">
+            <div class="card-header">
+                <h2>Upload Files</h2>
+                <label>Your name: <input type="text" id="sender-name" maxlength="64" placeholder="Anonymous"></label>
+                <button type="button" class="tag-chip" id="pause-toggle" title="Hold all transfers, e.g. while the network is needed for a call">Pause transfers</button>
+            </div>
+            <div class="upload-area" id="upload-area" aria-label="Upload files" role="region">
                 <div>
                     <p>Select files to upload:</p>
                     <div style="display: flex; flex-wrap: wrap; gap: 10px; justify-content: center;">
                         <button type="button" class="button" id="camera-btn">📷 Camera</button>
                         <button type="button" class="button" id="photos-btn">🖼️ Photos</button>
                         <button type="button" class="button" id="files-btn">📁 All Files</button>
+                        <button type="button" class="button" id="screenshot-btn" hidden>🖥️ Screenshot</button>
                     </div>
-                    <p style="margin-top: 15px; font-size: 14px; color: #666;">Or drag and drop files here</p>
+                    <p style="margin-top: 15px; font-size: 14px; color: #666;">Or drag and drop files here, or paste an image</p>
                 </div>
                 <input type="file" id="file-input-camera" accept="image/*" capture="environment">
                 <input type="file" id="file-input-photos" multiple accept="image/*,video/*">
                 <input type="file" id="file-input-files" multiple accept="image/*,video/*,audio/*,application/*,text/*,*/*">
             </div>
+            <label class="relay-mode" title="The file passes through to a device that downloads it from this page and is not stored here">
+                <input type="checkbox" id="relay-mode"> Send directly to another device (not saved here)
+            </label>
+            <label class="relay-mode" title="The files appear on the host together once every one of them arrived, or not at all">
+                <input type="checkbox" id="batch-mode"> All or nothing when sending several files
+            </label>
         </div>
         
         <div class="card">
-            <h2>Available Files</h2>
-            <div id="file-list-container">
+            <div class="card-header">
+                <h2>Available Files</h2>
+                <div id="view-toggle" role="group" aria-label="View">
+                    <button class="tag-chip" data-view="list" aria-pressed="false">List</button>
+                    <button class="tag-chip" data-view="gallery" aria-pressed="false">Gallery</button>
+                    <button class="tag-chip" id="hidden-toggle" title="Show dotfiles and system files" aria-pressed="false">Hidden</button>
+                    <select id="sort-select" title="Order of the files" aria-label="Order of the files">
+                        <option value="name:natural:asc">Name</option>
+                        <option value="name:nocase:asc">Name, digits as text</option>
+                        <option value="name:binary:asc">Name, capitals first</option>
+                        <option value="modified:natural:desc">Newest first</option>
+                        <option value="size:natural:desc">Largest first</option>
+                    </select>
+                </div>
+            </div>
+            <input type="search" id="search-input" placeholder="Search names and text (press /)" aria-label="Search files" autocomplete="off">
+            <div class="tag-filter" id="type-filter" role="group" aria-label="Filter by type"></div>
+            <div class="tag-filter" id="tag-filter" role="group" aria-label="Filter by tag"></div>
+            <div class="download-status" id="download-status" hidden></div>
+            <div class="bulk-bar" id="bulk-bar" hidden>
+                <label><input type="checkbox" id="select-all"> <span id="selection-count"></span></label>
+                <button class="button" id="bulk-delete">Delete</button>
+                <button class="button" id="bulk-move">Move to folder…</button>
+                <button class="button" id="bulk-tag">Add tag…</button>
+                <button class="button" id="bulk-publish" title="Show in the public gallery at /public">Publish</button>
+            </div>
+            <div id="file-list-container" aria-busy="true">
                 <p class="loading">Loading files...</p>
             </div>
         </div>
         
+        <div class="card">
+            <div class="card-header">
+                <h2>Recent Activity</h2>
+                <button class="button" id="refresh-history">Refresh</button>
+            </div>
+            <div id="history-container"></div>
+        </div>
+        
+        <div class="card">
+            <div class="card-header">
+                <h2>Storage Usage</h2>
+                <button class="button" id="refresh-usage">Refresh</button>
+            </div>
+            <div id="usage-container">
+                <p class="loading">Loading usage...</p>
+            </div>
+        </div>
+        
+        <div class="card">
+            <div class="card-header">
+                <h2>Duplicates</h2>
+                <button class="button" id="scan-duplicates">Find Duplicates</button>
+            </div>
+            <div id="duplicates-container"></div>
+        </div>
+        
         <div class="card">
             <h2>Nearby Devices</h2>
             <div id="device-list-container">
@@ -137,55 +522,1074 @@ pub async fn serve_index() -> impl IntoResponse {
             </div>
             <button class="button" id="refresh-devices">Refresh Devices</button>
         </div>
+        
+        <div class="card">
+            <div class="card-header">
+                <h2>Connected Clients</h2>
+                <button class="button" id="refresh-clients">Refresh</button>
+            </div>
+            <div id="clients-container"></div>
+        </div>
+    </main>
+    
+    <div class="lightbox" id="lightbox" role="dialog" aria-modal="true" aria-labelledby="lightbox-caption" hidden>
+        <div class="lightbox-content" id="lightbox-content"></div>
+        <button class="lightbox-button lightbox-prev" id="lightbox-prev" aria-label="Previous">‹</button>
+        <button class="lightbox-button lightbox-next" id="lightbox-next" aria-label="Next">›</button>
+        <button class="lightbox-button lightbox-close" id="lightbox-close" aria-label="Close">×</button>
+        <div class="lightbox-caption" id="lightbox-caption"></div>
     </div>
+    
+    <dialog id="conflict-dialog" aria-labelledby="conflict-title">
+        <form method="dialog">
+            <h2 id="conflict-title">File already exists</h2>
+            <div id="conflict-details"></div>
+            <label><input type="checkbox" id="conflict-all"> Do the same for the other files of this upload</label>
+            <div class="conflict-actions">
+                <button class="button" value="keep_both">Keep both</button>
+                <button class="button" value="replace">Replace</button>
+                <button class="button" value="skip">Skip</button>
+            </div>
+        </form>
+    </dialog>
 
     <script>
         // Device info
         async function loadDeviceInfo() {
             try {
-                const response = await fetch('/api/device');
-                const device = await response.json();
+                const response = await fetch('/api/device');
+                const device = await response.json();
+                
+                const fingerprint = await (await fetch('/api/fingerprint')).json();
+                
+                const deviceInfoEl = document.getElementById('device-info');
+                deviceInfoEl.innerHTML = `
+                    <p><strong>${device.name}</strong> (${device.os})</p>
+                    <p>IP: ${device.ip}:${device.port}</p>
+                    <p title="The host's terminal shows the same when you connect; if not, this is not the device you meant">
+                        Check the host shows: ${fingerprint.emoji.join(' ')} (${fingerprint.words.join(' ')})
+                    </p>
+                `;
+            } catch (error) {
+                console.error('Error loading device info:', error);
+            }
+        }
+        
+        // Tags
+        let activeTag = null;
+        
+        async function loadTags() {
+            try {
+                const response = await fetch('/api/tags');
+                const tags = await response.json();
+                
+                const tagFilter = document.getElementById('tag-filter');
+                
+                if (tags.length === 0) {
+                    tagFilter.innerHTML = '';
+                    return;
+                }
+                
+                let html = `<button class="tag-chip ${activeTag === null ? 'active' : ''}" data-tag="" aria-pressed="${activeTag === null}">All</button>`;
+                tags.forEach(tag => {
+                    const active = tag.name === activeTag;
+                    html += `<button class="tag-chip ${active ? 'active' : ''}" data-tag="${tag.name}" aria-pressed="${active}">${tag.name} (${tag.count})</button>`;
+                });
+                tagFilter.innerHTML = html;
+                
+                tagFilter.querySelectorAll('.tag-chip').forEach(chip => {
+                    chip.addEventListener('click', () => {
+                        activeTag = chip.dataset.tag || null;
+                        loadTags();
+                        loadFiles();
+                    });
+                });
+            } catch (error) {
+                console.error('Error loading tags:', error);
+            }
+        }
+        
+        // File types
+        let activeType = null;
+        const TYPE_LABELS = {
+            image: 'Images',
+            video: 'Videos',
+            audio: 'Audio',
+            document: 'Documents',
+            archive: 'Archives',
+            other: 'Other',
+        };
+        
+        async function loadTypes() {
+            try {
+                const params = new URLSearchParams();
+                if (activeTag) params.set('tag', activeTag);
+                if (showHidden !== null) params.set('hidden', showHidden);
+                const response = await fetch(`/api/types?${params}`);
+                const types = await response.json();
+                
+                const typeFilter = document.getElementById('type-filter');
+                
+                // A chip for a single kind would filter nothing out
+                if (types.length < 2 && activeType === null) {
+                    typeFilter.innerHTML = '';
+                    return;
+                }
+                
+                let html = `<button class="tag-chip ${activeType === null ? 'active' : ''}" data-type="" aria-pressed="${activeType === null}">All types</button>`;
+                types.forEach(type => {
+                    const active = type.type === activeType;
+                    html += `<button class="tag-chip ${active ? 'active' : ''}" data-type="${type.type}" aria-pressed="${active}">${TYPE_LABELS[type.type] || type.type} (${type.count})</button>`;
+                });
+                typeFilter.innerHTML = html;
+                
+                typeFilter.querySelectorAll('.tag-chip').forEach(chip => {
+                    chip.addEventListener('click', () => {
+                        activeType = chip.dataset.type || null;
+                        loadFiles();
+                    });
+                });
+            } catch (error) {
+                console.error('Error loading file types:', error);
+            }
+        }
+        
+        async function editTags(fileId, currentTags) {
+            const input = prompt('Tags (comma separated):', currentTags.join(', '));
+            if (input === null) return;
+            
+            const tags = input.split(',').map(t => t.trim()).filter(t => t.length > 0);
+            const response = await fetch(`/api/files/${fileId}/tags`, {
+                method: 'PUT',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ tags }),
+            });
+            
+            if (!response.ok) {
+                alert('Invalid tags: use letters, numbers, "-" or "_" only');
+                return;
+            }
+            
+            loadTags();
+            loadFiles();
+        }
+        
+        // Pins
+        async function togglePin(fileId, pinned) {
+            const response = await fetch(`/api/files/${fileId}/pin`, {
+                method: pinned ? 'DELETE' : 'PUT',
+            });
+            
+            if (!response.ok) {
+                console.error('Failed to update pin:', response.status);
+                return;
+            }
+            
+            loadFiles();
+        }
+        
+        // Public gallery
+        async function setPublished(fileId, published) {
+            const response = await fetch(`/api/files/${fileId}/publish`, {
+                method: published ? 'PUT' : 'DELETE',
+            });
+            
+            if (!response.ok) {
+                console.error('Failed to update the gallery:', response.status);
+            }
+        }
+        
+        // Encrypted share links
+        async function createShareLink(fileId) {
+            const passphrase = prompt('Passphrase to encrypt the file with (tell it to the recipient separately):');
+            if (!passphrase) {
+                return;
+            }
+            
+            const response = await fetch('/api/links', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ file_id: fileId, passphrase }),
+            });
+            
+            if (!response.ok) {
+                alert('Failed to create the share link');
+                return;
+            }
+            
+            const link = await response.json();
+            prompt('Encrypted share link:', new URL(link.url, location.href).href);
+        }
+        
+        // Short addresses for typing by hand
+        async function createShortLink(target) {
+            const response = await fetch('/api/jump', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ target }),
+            });
+            
+            if (!response.ok) {
+                alert('Failed to create the short address');
+                return;
+            }
+            
+            const jump = await response.json();
+            prompt('Type this on the other device:', new URL(jump.url, location.href).href);
+        }
+        
+        // Bulk operations
+        let selectedIds = new Set();
+        let listedFiles = [];
+        
+        function updateBulkBar() {
+            const bar = document.getElementById('bulk-bar');
+            bar.hidden = currentView !== 'list' || listedFiles.length === 0;
+            document.getElementById('selection-count').textContent =
+                selectedIds.size > 0 ? `${selectedIds.size} selected` : 'Select all';
+            document.getElementById('select-all').checked =
+                listedFiles.length > 0 && selectedIds.size === listedFiles.length;
+            ['bulk-delete', 'bulk-move', 'bulk-tag', 'bulk-publish'].forEach(id => {
+                document.getElementById(id).disabled = selectedIds.size === 0;
+            });
+        }
+        
+        async function runBulk(operations) {
+            const response = await fetch('/api/files/bulk', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ operations }),
+            });
+            const result = await response.json().catch(() => null);
+            
+            if (!response.ok) {
+                const failed = result ? result.results.filter(r => r.status === 'failed') : [];
+                const names = failed.map(r => {
+                    const file = listedFiles.find(f => f.id === r.id);
+                    return `${file ? file.name : r.id}: ${r.error}`;
+                });
+                alert('Nothing was changed.\n' + names.join('\n'));
+                return;
+            }
+            
+            selectedIds.clear();
+            loadTags();
+            loadFiles();
+            loadHistory();
+        }
+        
+        function setupBulkActions() {
+            document.getElementById('select-all').addEventListener('change', (event) => {
+                selectedIds = new Set(event.target.checked ? listedFiles.map(f => f.id) : []);
+                document.querySelectorAll('.file-select').forEach(box => {
+                    box.checked = event.target.checked;
+                });
+                updateBulkBar();
+            });
+            
+            document.getElementById('bulk-delete').addEventListener('click', () => {
+                if (!confirm(`Delete ${selectedIds.size} file(s)?`)) return;
+                runBulk([...selectedIds].map(id => ({ op: 'delete', id })));
+            });
+            
+            document.getElementById('bulk-move').addEventListener('click', () => {
+                const folder = prompt('Move to folder:');
+                if (!folder) return;
+                const prefix = folder.replace(/\/+$/, '');
+                runBulk([...selectedIds].map(id => {
+                    const file = listedFiles.find(f => f.id === id);
+                    return { op: 'move', id, to: `${prefix}/${file.name.split('/').pop()}` };
+                }));
+            });
+            
+            document.getElementById('bulk-tag').addEventListener('click', () => {
+                const tag = prompt('Tag to add:');
+                if (!tag) return;
+                runBulk([...selectedIds].map(id => ({ op: 'tag', id, add: [tag.trim()] })));
+            });
+            
+            document.getElementById('bulk-publish').addEventListener('click', async () => {
+                for (const id of selectedIds) {
+                    await setPublished(id, true);
+                }
+                selectedIds.clear();
+                loadFiles();
+            });
+        }
+        
+        function escapeHtml(text) {
+            const div = document.createElement('div');
+            div.textContent = text;
+            return div.innerHTML;
+        }
+        
+        // Sender name
+        function setupSenderName() {
+            const input = document.getElementById('sender-name');
+            const match = document.cookie.match(/(?:^|; )rustdrop_sender=([^;]*)/);
+            if (match) input.value = decodeURIComponent(match[1]);
+            
+            input.addEventListener('change', () => {
+                const name = input.value.trim();
+                const maxAge = name ? 365 * 24 * 60 * 60 : 0;
+                document.cookie = `rustdrop_sender=${encodeURIComponent(name)}; path=/; max-age=${maxAge}; SameSite=Lax`;
+            });
+        }
+        
+        // History
+        async function loadHistory() {
+            const container = document.getElementById('history-container');
+            
+            try {
+                const response = await fetch('/api/history?limit=20');
+                const entries = await response.json();
+                
+                if (entries.length === 0) {
+                    container.innerHTML = '<p>No activity yet</p>';
+                    return;
+                }
+                
+                const verbs = { upload: 'uploaded', download: 'downloaded', delete: 'deleted', move: 'moved' };
+                let html = '<ul class="history-list">';
+                entries.forEach(entry => {
+                    const who = entry.sender ? escapeHtml(entry.sender) : 'Someone';
+                    const target = escapeHtml(entry.action === 'move' ? `${entry.previous_name} → ${entry.file}` : entry.file);
+                    html += `<li>${who} ${verbs[entry.action] || entry.action} <strong>${target}</strong> <small>${new Date(entry.timestamp).toLocaleString()}</small></li>`;
+                });
+                html += '</ul>';
+                container.innerHTML = html;
+            } catch (error) {
+                console.error('Error loading history:', error);
+                container.innerHTML = '<p>Error loading activity</p>';
+            }
+        }
+        
+        // What went in and out lately, the first thing people look for in a drop folder
+        async function loadRecent() {
+            const card = document.getElementById('recent-card');
+            
+            try {
+                const response = await fetch('/api/history?transfers=true&limit=8');
+                const entries = await response.json();
+                
+                card.hidden = entries.length === 0;
+                let html = '<ul class="history-list recent-list">';
+                entries.forEach(entry => {
+                    const incoming = entry.action === 'upload';
+                    const arrow = incoming ? '⬇️' : '⬆️';
+                    const who = entry.sender ? `${incoming ? 'from' : 'to'} ${escapeHtml(entry.sender)}` : '';
+                    const time = new Date(entry.timestamp);
+                    html += `<li><span title="${incoming ? 'Received' : 'Sent'}">${arrow}</span>`;
+                    html += `<span class="recent-file"><strong>${escapeHtml(entry.file)}</strong> <span class="sender">${formatFileSize(entry.size)} ${who}</span></span>`;
+                    html += `<small title="${time.toLocaleString()}">${timeAgo(time)}</small></li>`;
+                });
+                html += '</ul>';
+                document.getElementById('recent-container').innerHTML = html;
+            } catch (error) {
+                console.error('Error loading recent transfers:', error);
+            }
+        }
+        
+        // The host's send queue, refreshed while something is on its way
+        let queueTimer = null;
+        
+        async function loadQueue() {
+            clearTimeout(queueTimer);
+            const card = document.getElementById('queue-card');
+            
+            try {
+                const response = await fetch('/api/queue');
+                const transfers = await response.json();
+                
+                card.hidden = transfers.length === 0;
+                let html = '<ul class="history-list queue-list">';
+                transfers.forEach(transfer => {
+                    const percent = transfer.size ? Math.floor(transfer.sent * 100 / transfer.size) : 100;
+                    let state = {
+                        queued: 'waiting',
+                        sent: '✅ sent',
+                        failed: `❌ ${escapeHtml(transfer.error || 'failed')}`,
+                        cancelled: '⏹️ cancelled',
+                    }[transfer.status] || `${percent}%`;
+                    if (transfer.speed) state += ` · ${formatFileSize(transfer.speed)}/s`;
+                    if (transfer.eta_secs != null) state += ` · ${formatEta(transfer.eta_secs)} left`;
+                    html += `<li><strong>${escapeHtml(transfer.name)}</strong> → ${escapeHtml(transfer.peer)} <small>${state}</small> ${sparkline(transfer.speed_history || [])}`;
+                    if (transfer.status === 'sending') {
+                        html += `<progress max="100" value="${percent}" aria-label="${escapeHtml(transfer.name)} sent">${percent}%</progress>`;
+                    }
+                    html += '</li>';
+                });
+                html += '</ul>';
+                document.getElementById('queue-container').innerHTML = html;
+                
+                const active = transfers.some(transfer => transfer.status === 'sending' || transfer.status === 'queued');
+                document.getElementById('queue-cancel').disabled = !active;
+                document.getElementById('queue-clear').disabled = transfers.every(transfer => transfer.status === 'sending' || transfer.status === 'queued');
+                if (active) {
+                    queueTimer = setTimeout(loadQueue, 2000);
+                }
+            } catch (error) {
+                console.error('Error loading the send queue:', error);
+            }
+        }
+        
+        function setupQueueActions() {
+            document.getElementById('queue-cancel').addEventListener('click', async () => {
+                if (!confirm('Cancel every transfer that is waiting or being sent?')) return;
+                try {
+                    const response = await fetch('/api/queue/cancel', { method: 'POST' });
+                    if (response.ok) {
+                        const { cancelled } = await response.json();
+                        announce(`Cancelled ${cancelled} transfer${cancelled === 1 ? '' : 's'}`);
+                    }
+                } catch (error) {
+                    console.error('Error cancelling transfers:', error);
+                }
+                loadQueue();
+            });
+            document.getElementById('queue-clear').addEventListener('click', async () => {
+                try {
+                    await fetch('/api/queue/clear', { method: 'POST' });
+                } catch (error) {
+                    console.error('Error clearing finished transfers:', error);
+                }
+                loadQueue();
+            });
+        }
+        
+        // Speeds as a small line graph, scaled to the fastest of them
+        function sparkline(speeds) {
+            if (speeds.length < 2) return '';
+            const width = 120;
+            const height = 24;
+            const max = Math.max(...speeds, 1);
+            const points = speeds
+                .map((speed, i) => `${(i * width / (speeds.length - 1)).toFixed(1)},${(height - 1 - speed * (height - 2) / max).toFixed(1)}`)
+                .join(' ');
+            return `<svg class="sparkline" width="${width}" height="${height}" viewBox="0 0 ${width} ${height}" role="img" aria-label="Speed over the last minute, up to ${formatFileSize(max)}/s">`
+                + `<polyline points="${points}"></polyline></svg>`;
+        }
+        
+        function formatEta(seconds) {
+            if (seconds < 60) return `${seconds} s`;
+            if (seconds < 3600) return `${Math.ceil(seconds / 60)} min`;
+            return `${Math.floor(seconds / 3600)} h ${String(Math.floor(seconds % 3600 / 60)).padStart(2, '0')} min`;
+        }
+        
+        // Rolling speed of a transfer in the browser, over the last ten seconds
+        function createSpeedMeter() {
+            const samples = [];
+            return {
+                record(bytes) {
+                    const now = performance.now();
+                    if (samples.length > 0 && bytes < samples[samples.length - 1].bytes) samples.length = 0;
+                    samples.push({ time: now, bytes });
+                    while (samples.length > 2 && now - samples[1].time >= 10000) samples.shift();
+                },
+                describe(size) {
+                    if (samples.length < 2) return '';
+                    const first = samples[0];
+                    const last = samples[samples.length - 1];
+                    const speed = (last.bytes - first.bytes) * 1000 / (last.time - first.time);
+                    if (!(speed > 0)) return '';
+                    return ` · ${formatFileSize(Math.round(speed))}/s · ${formatEta(Math.ceil((size - last.bytes) / speed))} left`;
+                },
+            };
+        }
+        
+        function timeAgo(time) {
+            const seconds = Math.round((Date.now() - time.getTime()) / 1000);
+            if (seconds < 60) return 'just now';
+            if (seconds < 3600) return `${Math.floor(seconds / 60)} min ago`;
+            if (seconds < 86400) return `${Math.floor(seconds / 3600)} h ago`;
+            return time.toLocaleDateString();
+        }
+        
+        // Collection requests from other devices
+        async function loadCollectRequests() {
+            try {
+                const response = await fetch('/api/collect');
+                const requests = await response.json();
+                const card = document.getElementById('collect-card');
+                card.hidden = requests.length === 0;
+                
+                let html = '';
+                requests.forEach(request => {
+                    html += `<div class="collect-request"><p><strong>${escapeHtml(request.requester)}</strong> wants to download your files matching <code>${escapeHtml(request.pattern)}</code></p>`;
+                    html += `<button class="button" onclick="decideCollectRequest('${request.id}', true)">Allow</button> `;
+                    html += `<button class="button" onclick="decideCollectRequest('${request.id}', false)">Decline</button></div>`;
+                });
+                document.getElementById('collect-container').innerHTML = html;
+            } catch (error) {
+                console.error('Error loading collection requests:', error);
+            }
+        }
+        
+        async function decideCollectRequest(id, approve) {
+            const response = await fetch(`/api/collect/${id}/decision`, {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ approve }),
+            });
+            if (!response.ok) {
+                alert(`Could not answer the request (status ${response.status})`);
+            }
+            loadCollectRequests();
+        }
+        
+        // Direct transfers relayed through the host
+        async function loadRelays() {
+            try {
+                const response = await fetch('/api/relays');
+                const offers = await response.json();
+                document.getElementById('relay-card').hidden = offers.length === 0;
+                
+                let html = '';
+                offers.forEach(offer => {
+                    const from = offer.sender ? ` from ${escapeHtml(offer.sender)}` : '';
+                    html += `<div class="collect-request"><p><strong>${escapeHtml(offer.name)}</strong> (${formatFileSize(offer.size)})${from}</p>`;
+                    html += `<a class="button" href="/api/relays/${offer.id}" download="${escapeHtml(offer.name)}">Download</a></div>`;
+                });
+                document.getElementById('relay-container').innerHTML = html;
+            } catch (error) {
+                console.error('Error loading direct transfers:', error);
+            }
+        }
+        
+        async function relayFile(file, uploadContent) {
+            const response = await fetch('/api/relays', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ name: file.name, size: file.size }),
+            });
+            if (!response.ok) {
+                throw new Error(`Could not offer the file (status ${response.status})`);
+            }
+            const offer = await response.json();
+            
+            uploadContent.innerHTML = `<p>Waiting for another device to download ${escapeHtml(file.name)}...</p><p>Keep this page open until it finishes</p>`;
+            const upload = await fetch(`/api/relays/${offer.id}`, { method: 'PUT', body: file });
+            if (!upload.ok) {
+                throw new Error(upload.status === 410 ? 'The other device stopped downloading' : `Transfer failed (status ${upload.status})`);
+            }
+        }
+        
+        // Connected clients
+        async function loadClients() {
+            const container = document.getElementById('clients-container');
+            
+            try {
+                const response = await fetch('/api/clients');
+                const clients = await response.json();
+                
+                const kinds = { browser: 'Browser', peer: 'RustDrop device', other: 'Other client' };
+                let html = '<ul class="history-list">';
+                clients.forEach(client => {
+                    const who = client.peer
+                        ? `RustDrop ${escapeHtml(client.peer.version)} on ${escapeHtml(client.peer.os)}`
+                        : escapeHtml(client.user_agent || 'Unknown client');
+                    const kind = kinds[client.kind] || 'Other client';
+                    const where = client.ip ? ` from ${escapeHtml(client.ip)}` : '';
+                    const traffic = `↑ ${formatFileSize(client.bytes_received)} ↓ ${formatFileSize(client.bytes_sent)}`;
+                    html += `<li><strong>${who}</strong> <em>${kind}</em>${where} <small>since ${new Date(client.first_seen).toLocaleString()}, ${traffic}</small> `;
+                    html += client.current ? '<em>(this browser)</em>' : `<button class="button" onclick="revokeClient('${client.id}')">Revoke</button>`;
+                    html += '</li>';
+                });
+                html += '</ul>';
+                container.innerHTML = html;
+            } catch (error) {
+                console.error('Error loading clients:', error);
+                container.innerHTML = '<p>Error loading clients</p>';
+            }
+        }
+        
+        async function revokeClient(id) {
+            const response = await fetch(`/api/clients/${id}`, { method: 'DELETE' });
+            if (response.status === 403) {
+                alert('Only the host running RustDrop can revoke clients');
+            } else if (!response.ok) {
+                alert(`Could not revoke the client (status ${response.status})`);
+            }
+            loadClients();
+        }
+        
+        // Storage usage
+        function usageChart(title, entries, total) {
+            let html = `<h3>${title}</h3>`;
+            entries.filter(entry => entry.files > 0).forEach(entry => {
+                const percent = total > 0 ? (entry.bytes / total) * 100 : 0;
+                html += `
+                    <div class="usage-row">
+                        <span class="usage-label" title="${entry.name}">${entry.name}</span>
+                        <div class="usage-bar"><div style="width: ${percent.toFixed(1)}%"></div></div>
+                        <span>${formatFileSize(entry.bytes)}</span>
+                    </div>
+                `;
+            });
+            return html;
+        }
+        
+        async function loadUsage() {
+            const container = document.getElementById('usage-container');
+            
+            try {
+                const response = await fetch('/api/usage');
+                const usage = await response.json();
+                
+                if (usage.total_files === 0) {
+                    container.innerHTML = '<p>The shared folder is empty</p>';
+                    return;
+                }
+                
+                const ageNames = { day: 'Last day', week: 'Last week', month: 'Last month', year: 'Last year', older: 'Older' };
+                const byAge = usage.by_age.map(entry => ({ ...entry, name: ageNames[entry.name] || entry.name }));
+                
+                container.innerHTML = `<p>${usage.total_files} files, ${formatFileSize(usage.total_bytes)}</p>`
+                    + usageChart('By folder', usage.by_directory, usage.total_bytes)
+                    + usageChart('By type', usage.by_category, usage.total_bytes)
+                    + usageChart('By age', byAge, usage.total_bytes);
+            } catch (error) {
+                console.error('Error loading usage:', error);
+                container.innerHTML = '<p>Error loading usage</p>';
+            }
+        }
+        
+        // Duplicates
+        async function loadDuplicates() {
+            const container = document.getElementById('duplicates-container');
+            container.innerHTML = '<p class="loading">Scanning files...</p>';
+            
+            try {
+                const response = await fetch('/api/duplicates');
+                const report = await response.json();
                 
-                const deviceInfoEl = document.getElementById('device-info');
-                deviceInfoEl.innerHTML = `
-                    <p><strong>${device.name}</strong> (${device.os})</p>
-                    <p>IP: ${device.ip}:${device.port}</p>
-                `;
+                if (report.groups.length === 0) {
+                    container.innerHTML = '<p>No duplicate files found</p>';
+                    return;
+                }
+                
+                let html = `<p>${report.groups.length} group(s), ${formatFileSize(report.wasted_bytes)} can be freed. The oldest copy is kept by default.</p>`;
+                report.groups.forEach(group => {
+                    html += `<div class="duplicate-group"><strong>${formatFileSize(group.size)} × ${group.files.length}</strong>`;
+                    group.files.forEach((file, index) => {
+                        html += `
+                            <label>
+                                <input type="checkbox" class="duplicate-select" data-id="${file.id}" ${index > 0 ? 'checked' : ''}>
+                                ${file.name} <small>${new Date(file.modified).toLocaleString()}</small>
+                            </label>
+                        `;
+                    });
+                    html += '</div>';
+                });
+                html += '<button class="button" id="delete-duplicates">Delete Selected</button>';
+                container.innerHTML = html;
+                
+                document.getElementById('delete-duplicates').addEventListener('click', deleteDuplicates);
             } catch (error) {
-                console.error('Error loading device info:', error);
+                console.error('Error finding duplicates:', error);
+                container.innerHTML = '<p>Error finding duplicates</p>';
+            }
+        }
+        
+        async function deleteDuplicates() {
+            const ids = [...document.querySelectorAll('.duplicate-select:checked')].map(box => box.dataset.id);
+            if (ids.length === 0) return;
+            
+            // Refuse to delete every copy of a file
+            const groups = [...document.querySelectorAll('.duplicate-group')];
+            if (groups.some(group => group.querySelectorAll('.duplicate-select:not(:checked)').length === 0)) {
+                alert('Keep at least one copy of each file');
+                return;
+            }
+            
+            if (!confirm(`Delete ${ids.length} duplicate file(s)?`)) return;
+            await runBulk(ids.map(id => ({ op: 'delete', id })));
+            loadDuplicates();
+        }
+        
+        // Gallery view
+        let currentView = localStorage.getItem('rustdrop-view') || 'list';
+        // null until the user picks, leaving the choice to the server's files.show_hidden
+        let showHidden = localStorage.getItem('rustdrop-hidden');
+        // Natural order unless the user picks another: file2 before file10, case ignored
+        let sortOrder = localStorage.getItem('rustdrop-sort') || 'name:natural:asc';
+        // Words from the search box, matched against names and extracted text by /api/search
+        let searchQuery = '';
+        let galleryItems = [];
+        let lightboxIndex = 0;
+        
+        function isMedia(file) {
+            return file.mime_type.startsWith('image/')
+                || file.mime_type.startsWith('video/')
+                || file.mime_type === 'application/pdf';
+        }
+        
+        function hasThumbnail(file) {
+            return file.mime_type.startsWith('image/') || file.mime_type === 'application/pdf';
+        }
+        
+        function thumbnailUrl(file, size) {
+            return `/api/files/${file.id}/thumbnail?size=${size}`;
+        }
+        
+        function isRenderable(file) {
+            return file.mime_type.startsWith('text/')
+                || file.mime_type === 'application/json'
+                || /\.(md|markdown|rs|py|js|ts|go|java|c|h|cpp|sh|toml|yaml|yml|log)$/i.test(file.name);
+        }
+        
+        function isFollowable(file) {
+            return /\.(log|out|txt)$/i.test(file.name);
+        }
+        
+        function isPlayable(file) {
+            return file.mime_type.startsWith('video/') || file.mime_type.startsWith('audio/');
+        }
+        
+        // Open a single audio/video file in the player without downloading it first
+        function openPlayer(file) {
+            galleryItems = [file];
+            openLightbox(0);
+        }
+        
+        function setupViewToggle() {
+            const toggle = document.getElementById('view-toggle');
+            const showView = () => {
+                toggle.querySelectorAll('.tag-chip[data-view]').forEach(b => {
+                    b.classList.toggle('active', b.dataset.view === currentView);
+                    b.setAttribute('aria-pressed', b.dataset.view === currentView);
+                });
+            };
+            showView();
+            toggle.querySelectorAll('.tag-chip[data-view]').forEach(button => {
+                button.addEventListener('click', () => {
+                    currentView = button.dataset.view;
+                    localStorage.setItem('rustdrop-view', currentView);
+                    showView();
+                    loadFiles();
+                });
+            });
+            
+            const hiddenToggle = document.getElementById('hidden-toggle');
+            const showHiddenState = () => {
+                hiddenToggle.classList.toggle('active', showHidden === 'true');
+                hiddenToggle.setAttribute('aria-pressed', showHidden === 'true');
+            };
+            showHiddenState();
+            hiddenToggle.addEventListener('click', () => {
+                showHidden = showHidden === 'true' ? 'false' : 'true';
+                localStorage.setItem('rustdrop-hidden', showHidden);
+                showHiddenState();
+                loadFiles();
+            });
+            
+            const searchInput = document.getElementById('search-input');
+            let searchTimer = null;
+            searchInput.addEventListener('input', () => {
+                clearTimeout(searchTimer);
+                searchTimer = setTimeout(() => {
+                    searchQuery = searchInput.value.trim();
+                    loadFiles();
+                }, 250);
+            });
+
+            const sortSelect = document.getElementById('sort-select');
+            sortSelect.value = sortOrder;
+            sortSelect.addEventListener('change', () => {
+                sortOrder = sortSelect.value;
+                localStorage.setItem('rustdrop-sort', sortOrder);
+                loadFiles();
+            });
+        }
+        
+        function renderGallery(files) {
+            const fileListContainer = document.getElementById('file-list-container');
+            galleryItems = files.filter(isMedia);
+            
+            if (galleryItems.length === 0) {
+                fileListContainer.innerHTML = '<p>No images or videos</p>';
+                return;
+            }
+            
+            let html = '<div class="gallery">';
+            galleryItems.forEach((file, index) => {
+                const src = `/api/files/${file.id}?inline=true`;
+                let thumbnail;
+                if (file.mime_type.startsWith('video/')) {
+                    thumbnail = `<video src="${src}#t=0.1" preload="metadata" muted></video><span class="play-badge">▶</span>`;
+                } else if (file.mime_type === 'application/pdf') {
+                    // PDF thumbnails need the pdf-thumbnails server feature, fall back to an icon
+                    thumbnail = `<img src="${thumbnailUrl(file, 256)}" loading="lazy" alt="" onerror="this.outerHTML='<span class=&quot;doc-icon&quot;>📄</span>'"><span class="play-badge">PDF</span>`;
+                } else {
+                    thumbnail = `<img src="${thumbnailUrl(file, 256)}" loading="lazy" alt="">`;
+                }
+                html += `<button class="gallery-item" data-index="${index}" title="${file.name}" aria-label="Open ${file.name}">${thumbnail}</button>`;
+            });
+            html += '</div>';
+            fileListContainer.innerHTML = html;
+            
+            fileListContainer.querySelectorAll('.gallery-item').forEach(item => {
+                item.addEventListener('click', () => openLightbox(parseInt(item.dataset.index)));
+            });
+        }
+        
+        // Ask the server for a copy in H.264 when this browser cannot decode a video
+        async function playTranscoded(file, content) {
+            const stillShown = () => !document.getElementById('lightbox').hidden && galleryItems[lightboxIndex] === file;
+            const url = `/api/files/${file.id}/transcode`;
+            let response = await fetch(url);
+            if (response.status === 404) {
+                response = await fetch(url, { method: 'POST' });
+            }
+            while (response.ok && stillShown()) {
+                const status = await response.json();
+                if (status.state === 'done') {
+                    content.innerHTML = `<video src="/api/files/${file.id}/transcoded" controls autoplay playsinline></video>`;
+                    return;
+                }
+                if (status.state === 'failed') break;
+                const percent = status.progress == null ? '' : ` ${Math.round(status.progress * 100)}%`;
+                content.innerHTML = `<p>Converting the video for this browser…${percent}</p>`;
+                await new Promise(resolve => setTimeout(resolve, 1000));
+                response = await fetch(url);
+            }
+            if (stillShown()) {
+                content.innerHTML = `<p>This browser cannot play ${file.name}. <a href="/api/files/${file.id}" download>Download it</a> instead.</p>`;
             }
         }
         
+        // Gets the focus back when the lightbox closes
+        let lightboxOpener = null;
+        
+        function openLightbox(index) {
+            lightboxIndex = index;
+            lightboxOpener = document.activeElement;
+            document.getElementById('lightbox').hidden = false;
+            showLightboxItem();
+            document.getElementById('lightbox-close').focus();
+        }
+        
+        function closeLightbox() {
+            document.getElementById('lightbox').hidden = true;
+            // Stop any playing video
+            document.getElementById('lightbox-content').innerHTML = '';
+            if (lightboxOpener && document.contains(lightboxOpener)) lightboxOpener.focus();
+            lightboxOpener = null;
+        }
+        
+        function stepLightbox(delta) {
+            if (galleryItems.length === 0) return;
+            lightboxIndex = (lightboxIndex + delta + galleryItems.length) % galleryItems.length;
+            showLightboxItem();
+        }
+        
+        function showLightboxItem() {
+            const file = galleryItems[lightboxIndex];
+            const src = `/api/files/${file.id}?inline=true`;
+            const content = document.getElementById('lightbox-content');
+            
+            if (file.mime_type.startsWith('video/')) {
+                content.innerHTML = `<video src="${src}" controls autoplay playsinline></video>`;
+                const video = content.querySelector('video');
+                video.addEventListener('error', () => playTranscoded(file, content));
+                // Some browsers play the sound of a video whose picture they cannot decode
+                video.addEventListener('loadedmetadata', () => {
+                    if (video.videoWidth === 0) playTranscoded(file, content);
+                });
+            } else if (file.mime_type.startsWith('audio/')) {
+                content.innerHTML = `<audio src="${src}" controls autoplay></audio>`;
+            } else if (file.mime_type === 'application/pdf') {
+                content.innerHTML = `<a href="${src}" target="_blank"><img src="${thumbnailUrl(file, 1024)}" alt="Open ${file.name}" onerror="this.outerHTML='<span class=&quot;doc-icon&quot;>📄 Open PDF</span>'"></a>`;
+            } else {
+                content.innerHTML = `<img src="${src}" alt="${file.name}">`;
+            }
+            
+            const single = galleryItems.length < 2;
+            document.getElementById('lightbox-prev').hidden = single;
+            document.getElementById('lightbox-next').hidden = single;
+            document.getElementById('lightbox-caption').innerHTML =
+                `${file.name} (${lightboxIndex + 1}/${galleryItems.length}) · <a href="/api/files/${file.id}" download="${file.name}" style="color: white;">Download</a>`;
+        }
+        
+        function setupLightbox() {
+            const lightbox = document.getElementById('lightbox');
+            document.getElementById('lightbox-close').addEventListener('click', closeLightbox);
+            document.getElementById('lightbox-prev').addEventListener('click', () => stepLightbox(-1));
+            document.getElementById('lightbox-next').addEventListener('click', () => stepLightbox(1));
+            
+            document.addEventListener('keydown', (e) => {
+                if (lightbox.hidden) return;
+                if (e.key === 'Escape') closeLightbox();
+                if (e.key === 'ArrowLeft') stepLightbox(-1);
+                if (e.key === 'ArrowRight') stepLightbox(1);
+                // Keep the focus inside the dialog
+                if (e.key === 'Tab') {
+                    const focusable = [...lightbox.querySelectorAll('button, a, video, audio')]
+                        .filter(element => !element.hidden);
+                    const first = focusable[0];
+                    const last = focusable[focusable.length - 1];
+                    if (e.shiftKey && document.activeElement === first) {
+                        e.preventDefault();
+                        last.focus();
+                    } else if (!e.shiftKey && document.activeElement === last) {
+                        e.preventDefault();
+                        first.focus();
+                    } else if (!lightbox.contains(document.activeElement)) {
+                        e.preventDefault();
+                        first.focus();
+                    }
+                }
+            });
+            
+            // Swipe left/right on touch devices
+            let touchStartX = null;
+            lightbox.addEventListener('touchstart', (e) => {
+                touchStartX = e.changedTouches[0].clientX;
+            }, { passive: true });
+            lightbox.addEventListener('touchend', (e) => {
+                if (touchStartX === null) return;
+                const deltaX = e.changedTouches[0].clientX - touchStartX;
+                touchStartX = null;
+                if (Math.abs(deltaX) > 50) {
+                    stepLightbox(deltaX < 0 ? 1 : -1);
+                }
+            });
+        }
+        
         // File list
         async function loadFiles() {
+            loadTypes();
             try {
-                const response = await fetch('/api/files');
-                const files = await response.json();
+                const params = new URLSearchParams();
+                if (activeTag) params.set('tag', activeTag);
+                if (activeType) params.set('type', activeType);
+                if (showHidden !== null) params.set('hidden', showHidden);
+                const [sort, collation, order] = sortOrder.split(':');
+                params.set('sort', sort);
+                params.set('collation', collation);
+                params.set('order', order);
+                const response = await fetch(`/api/files?${params}`);
+                let files = await response.json();
+                if (searchQuery) {
+                    const search = new URLSearchParams({ q: searchQuery });
+                    if (showHidden !== null) search.set('hidden', showHidden);
+                    const hits = await (await fetch(`/api/search?${search}`)).json();
+                    const found = new Set(hits.map(hit => hit.id));
+                    files = files.filter(file => found.has(file.id));
+                }
                 
                 const fileListContainer = document.getElementById('file-list-container');
+                fileListContainer.setAttribute('aria-busy', 'false');
+                // The list is drawn anew, so remember which file had the keyboard focus
+                const focusedItem = document.activeElement && document.activeElement.closest('.file-item');
+                const focusedId = focusedItem ? focusedItem.dataset.id : null;
+                
+                listedFiles = files;
+                selectedIds = new Set([...selectedIds].filter(id => files.some(f => f.id === id)));
+                updateBulkBar();
                 
                 if (files.length === 0) {
-                    fileListContainer.innerHTML = '<p>No files available</p>';
+                    fileListContainer.innerHTML = searchQuery
+                        ? `<p>No files match "${escapeHtml(searchQuery)}"</p>`
+                        : activeTag
+                        ? `<p>No files tagged "${activeTag}"</p>`
+                        : activeType
+                            ? `<p>No ${(TYPE_LABELS[activeType] || activeType).toLowerCase()} available</p>`
+                            : '<p>No files available</p>';
                     return;
                 }
                 
-                let html = '<ul class="file-list">';
+                if (currentView === 'gallery') {
+                    renderGallery(files);
+                    return;
+                }
+                
+                let html = '<ul class="file-list" aria-label="Files">';
+                const rovingId = files.some(file => file.id === focusedId) ? focusedId : files[0].id;
                 
-                files.forEach(file => {
+                files.forEach((file, index) => {
                     const fileSize = formatFileSize(file.size);
+                    const tags = (file.tags || []).map(tag => `<span class="tag-chip">${tag}</span>`).join('');
+                    // One file in the tab order, the arrow keys reach the others
+                    const tabIndex = file.id === rovingId ? 0 : -1;
                     html += `
-                        <li class="file-item">
-                            <div>
-                                <strong>${file.name}</strong>
-                                <div>${fileSize}</div>
+                        <li class="file-item ${file.pinned ? 'pinned' : ''}" data-id="${file.id}" tabindex="${tabIndex}" aria-label="${file.name}, ${fileSize}">
+                            <div class="file-summary">
+                                <input type="checkbox" class="file-select" data-id="${file.id}" aria-label="Select ${file.name}" ${selectedIds.has(file.id) ? 'checked' : ''}>
+                                ${hasThumbnail(file) ? `<img class="list-thumbnail" src="${thumbnailUrl(file, 80)}" loading="lazy" alt="" onerror="this.remove()">` : ''}
+                                <div>
+                                    <strong>${file.name}</strong>
+                                    <div>${fileSize}${file.sender ? ` <span class="sender">from ${escapeHtml(file.sender)}</span>` : ''}</div>
+                                    <div>${tags}</div>
+                                </div>
+                            </div>
+                            <div class="file-actions">
+                                <button class="pin-button" data-id="${file.id}" data-pinned="${file.pinned}" title="${file.pinned ? 'Unpin' : 'Pin to top'}" aria-label="${file.pinned ? 'Unpin' : 'Pin'} ${file.name}" aria-pressed="${file.pinned}">${file.pinned ? '★' : '☆'}</button>
+                                ${isPlayable(file) ? `<button class="button play-file" data-index="${index}">Play</button>` : ''}
+                                ${isRenderable(file) ? `<a href="/api/files/${file.id}/render" target="_blank" class="button">View</a>` : ''}
+                                ${isFollowable(file) ? `<a href="/tail/${file.id}" target="_blank" class="button" title="Watch new lines live">Follow</a>` : ''}
+                                <button class="button edit-tags" data-id="${file.id}" data-tags="${(file.tags || []).join(',')}">Tags</button>
+                                <button class="button share-link" data-id="${file.id}" title="Encrypted link with a passphrase">Link</button>
+                                <button class="button short-link" data-id="${file.id}" title="Short address to type on another device">Short</button>
+                                <button class="button toggle-publish" data-id="${file.id}" data-published="${file.published}" title="${file.published ? 'Remove from the public gallery' : 'Show in the public gallery at /public'}">${file.published ? 'Unpublish' : 'Publish'}</button>
+                                <a href="/api/files/${file.id}" download="${file.name}" class="button download-file" data-index="${index}" aria-label="Download ${file.name}">Download</a>
                             </div>
-                            <a href="/api/files/${file.id}" download="${file.name}" class="button">Download</a>
                         </li>
                     `;
                 });
                 
                 html += '</ul>';
                 fileListContainer.innerHTML = html;
+                if (focusedId) {
+                    fileListContainer.querySelector(`.file-item[data-id="${rovingId}"]`).focus();
+                }
+                
+                fileListContainer.querySelectorAll('.play-file').forEach(button => {
+                    button.addEventListener('click', () => openPlayer(files[parseInt(button.dataset.index)]));
+                });
+                
+                fileListContainer.querySelectorAll('.file-select').forEach(box => {
+                    box.addEventListener('change', () => {
+                        if (box.checked) {
+                            selectedIds.add(box.dataset.id);
+                        } else {
+                            selectedIds.delete(box.dataset.id);
+                        }
+                        updateBulkBar();
+                    });
+                });
+                
+                fileListContainer.querySelectorAll('.pin-button').forEach(button => {
+                    button.addEventListener('click', () => {
+                        togglePin(button.dataset.id, button.dataset.pinned === 'true');
+                    });
+                });
+                
+                fileListContainer.querySelectorAll('.download-file').forEach(link => {
+                    link.addEventListener('click', event => downloadFile(event, files[parseInt(link.dataset.index)]));
+                });
+                
+                fileListContainer.querySelectorAll('.share-link').forEach(button => {
+                    button.addEventListener('click', () => createShareLink(button.dataset.id));
+                });
+                
+                fileListContainer.querySelectorAll('.short-link').forEach(button => {
+                    button.addEventListener('click', () => createShortLink(`/api/files/${button.dataset.id}`));
+                });
+                
+                fileListContainer.querySelectorAll('.toggle-publish').forEach(button => {
+                    button.addEventListener('click', async () => {
+                        await setPublished(button.dataset.id, button.dataset.published !== 'true');
+                        loadFiles();
+                    });
+                });
+                
+                fileListContainer.querySelectorAll('.edit-tags').forEach(button => {
+                    button.addEventListener('click', () => {
+                        const currentTags = button.dataset.tags ? button.dataset.tags.split(',') : [];
+                        editTags(button.dataset.id, currentTags);
+                    });
+                });
             } catch (error) {
                 console.error('Error loading files:', error);
                 const fileListContainer = document.getElementById('file-list-container');
@@ -193,6 +1597,132 @@ pub async fn serve_index() -> impl IntoResponse {
             }
         }
         
+        // Chunks follow the measured throughput and should take about two seconds each: fast
+        // networks get few large requests, congested Wi-Fi small ones that are cheap to retry
+        const MIN_CHUNK_SIZE = 256 * 1024;
+        const MAX_CHUNK_SIZE = 64 * 1024 * 1024;
+        const TARGET_CHUNK_MS = 2000;
+
+        function createPacer(initialSize) {
+            return {
+                size: initialSize,
+                // Bytes per millisecond, averaged over the recent chunks
+                throughput: null,
+                sent(bytes, ms) {
+                    if (bytes < MIN_CHUNK_SIZE || ms <= 0) return;
+                    const sample = bytes / ms;
+                    this.throughput = this.throughput === null ? sample : this.throughput + 0.3 * (sample - this.throughput);
+                    // At most doubling per chunk, one lucky sample should not make the next one huge
+                    const target = Math.min(this.throughput * TARGET_CHUNK_MS, this.size * 2);
+                    this.size = Math.round(Math.min(Math.max(target, MIN_CHUNK_SIZE), MAX_CHUNK_SIZE));
+                },
+                failed() {
+                    this.size = Math.max(MIN_CHUNK_SIZE, Math.floor(this.size / 2));
+                },
+            };
+        }
+
+        const RESUMABLE_DOWNLOAD_MIN = 64 * 1024 * 1024;
+        const downloadPacer = createPacer(8 * 1024 * 1024);
+        const DOWNLOAD_RETRIES = 8;
+        // Downloads that gave up after repeated failures, continued with their Resume button
+        const stalledDownloads = new Map();
+
+        function showDownloadStatus(html, progress = null) {
+            const status = document.getElementById('download-status');
+            status.innerHTML = html;
+            status.hidden = false;
+            if (progress === null) {
+                announce(status.textContent);
+            }
+        }
+
+        // Large files are fetched in ranges and written through the File System Access API, so a
+        // dropped connection continues from the last byte received instead of starting over.
+        // Other browsers get the plain download.
+        function downloadFile(event, file) {
+            if (file.size < RESUMABLE_DOWNLOAD_MIN) return;
+            if (!window.showSaveFilePicker) {
+                showDownloadStatus(`Downloading ${escapeHtml(file.name)} with the browser. If the connection drops it has to start over; Chrome and Edge can resume large downloads.`);
+                return;
+            }
+            event.preventDefault();
+            saveResumable(file);
+        }
+
+        async function saveResumable(file) {
+            let handle;
+            try {
+                handle = await window.showSaveFilePicker({ suggestedName: file.name });
+            } catch (_) {
+                // The picker was dismissed
+                return;
+            }
+            const writable = await handle.createWritable();
+            continueDownload({ file, writable, offset: 0, version: null });
+        }
+
+        function resumeDownload(id) {
+            const download = stalledDownloads.get(id);
+            if (download) continueDownload(download);
+        }
+
+        async function continueDownload(download) {
+            const { file, writable } = download;
+            const name = escapeHtml(file.name);
+            stalledDownloads.delete(file.id);
+
+            let failures = 0;
+            try {
+                while (download.offset < file.size) {
+                    const percent = Math.floor(download.offset * 100 / file.size);
+                    showDownloadStatus(`Downloading ${name}... ${percent}%`, percent);
+                    announceProgress(`Downloading ${file.name}`, percent);
+
+                    const end = Math.min(download.offset + downloadPacer.size, file.size) - 1;
+                    let response, data;
+                    try {
+                        const started = performance.now();
+                        response = await fetch(`/api/files/${file.id}`, {
+                            headers: { Range: `bytes=${download.offset}-${end}` },
+                        });
+                        if (response.status === 206) {
+                            data = await response.arrayBuffer();
+                            downloadPacer.sent(data.byteLength, performance.now() - started);
+                        }
+                    } catch (error) {
+                        downloadPacer.failed();
+                        if (++failures > DOWNLOAD_RETRIES) {
+                            stalledDownloads.set(file.id, download);
+                            showDownloadStatus(`❌ Download of ${name} interrupted at ${percent}% <button class="button" onclick="resumeDownload('${file.id}')">Resume</button>`);
+                            return;
+                        }
+                        console.warn('Download interrupted, retrying:', error);
+                        await new Promise(resolve => setTimeout(resolve, 1000 * failures));
+                        continue;
+                    }
+
+                    // Parts of a file that was replaced in the meantime would not fit together
+                    const version = response.headers.get('Last-Modified');
+                    if (response.status !== 206 || (download.version && version !== download.version)) {
+                        await writable.abort();
+                        showDownloadStatus(`❌ ${name} changed or disappeared while downloading, please download it again`);
+                        return;
+                    }
+                    download.version = version;
+
+                    await writable.write({ type: 'write', position: download.offset, data });
+                    download.offset += data.byteLength;
+                    failures = 0;
+                }
+                await writable.close();
+                showDownloadStatus(`✅ Saved ${name}`);
+            } catch (error) {
+                console.error('Error saving download:', error);
+                showDownloadStatus(`❌ Could not save ${name}: ${escapeHtml(error.message)}`);
+            }
+        }
+        
         // Device discovery
         async function discoverDevices() {
             try {
@@ -245,8 +1775,9 @@ pub async fn serve_index() -> impl IntoResponse {
                         <button type="button" class="button" id="camera-btn">📷 Camera</button>
                         <button type="button" class="button" id="photos-btn">🖼️ Photos</button>
                         <button type="button" class="button" id="files-btn">📁 All Files</button>
+                        <button type="button" class="button" id="screenshot-btn" hidden>🖥️ Screenshot</button>
                     </div>
-                    <p style="margin-top: 15px; font-size: 14px; color: #666;">Or drag and drop files here</p>
+                    <p style="margin-top: 15px; font-size: 14px; color: #666;">Or drag and drop files here, or paste an image</p>
                 `;
                 
                 // Re-attach button events
@@ -273,6 +1804,17 @@ pub async fn serve_index() -> impl IntoResponse {
                     e.stopPropagation();
                     filesInput.click();
                 });
+                
+                // Phones and tablets have no screen capture
+                const screenshotBtn = document.getElementById('screenshot-btn');
+                if (screenshotBtn && navigator.mediaDevices && navigator.mediaDevices.getDisplayMedia) {
+                    screenshotBtn.hidden = false;
+                    screenshotBtn.addEventListener('click', async (e) => {
+                        e.stopPropagation();
+                        const screenshot = await captureScreenshot();
+                        if (screenshot) uploadFiles([screenshot], resetUploadArea);
+                    });
+                }
             }
             
             // Initial setup for buttons
@@ -311,58 +1853,437 @@ pub async fn serve_index() -> impl IntoResponse {
                     uploadFiles(e.dataTransfer.files, resetUploadArea);
                 }
             });
+            
+            // Pasting anywhere but a text field uploads the copied files or image
+            document.addEventListener('paste', (e) => {
+                if (e.target.matches && e.target.matches('input, textarea, [contenteditable]')) return;
+                const pasted = [...e.clipboardData.items]
+                    .filter(item => item.kind === 'file')
+                    .map(item => item.getAsFile())
+                    .filter(file => file !== null)
+                    .map(namePastedFile);
+                if (pasted.length > 0) {
+                    e.preventDefault();
+                    uploadFiles(pasted, resetUploadArea);
+                }
+            });
+        }
+        
+        // Browsers call every pasted image "image.png", so it gets the time it was pasted instead
+        function namePastedFile(file) {
+            if (!file.type.startsWith('image/') || !/^image\.\w+$/.test(file.name)) return file;
+            const extension = file.name.split('.').pop();
+            return new File([file], timestampedName('pasted', extension), { type: file.type, lastModified: Date.now() });
+        }
+        
+        function timestampedName(prefix, extension) {
+            const now = new Date();
+            const pad = n => String(n).padStart(2, '0');
+            const date = `${now.getFullYear()}-${pad(now.getMonth() + 1)}-${pad(now.getDate())}`;
+            const time = `${pad(now.getHours())}-${pad(now.getMinutes())}-${pad(now.getSeconds())}`;
+            return `${prefix}-${date}-${time}.${extension}`;
+        }
+        
+        // Ask for a screen, window or tab, grab one frame of it as PNG and stop sharing right away
+        async function captureScreenshot() {
+            let stream;
+            try {
+                stream = await navigator.mediaDevices.getDisplayMedia({ video: true, audio: false });
+            } catch (error) {
+                // Sharing was refused or cancelled
+                console.warn('Screen capture not started:', error);
+                return null;
+            }
+            try {
+                const video = document.createElement('video');
+                video.muted = true;
+                video.srcObject = stream;
+                await video.play();
+                // The first frame can still be black while the capture starts
+                await new Promise(resolve => setTimeout(resolve, 300));
+                const canvas = document.createElement('canvas');
+                canvas.width = video.videoWidth;
+                canvas.height = video.videoHeight;
+                canvas.getContext('2d').drawImage(video, 0, 0);
+                const blob = await new Promise(resolve => canvas.toBlob(resolve, 'image/png'));
+                if (!blob) throw new Error('the frame could not be encoded');
+                return new File([blob], timestampedName('screenshot', 'png'), { type: 'image/png', lastModified: Date.now() });
+            } catch (error) {
+                console.error('Error capturing screenshot:', error);
+                announce(`Could not capture the screen: ${error.message}`);
+                return null;
+            } finally {
+                stream.getTracks().forEach(track => track.stop());
+            }
+        }
+        
+        const uploadPacer = createPacer(1024 * 1024);
+        const UPLOAD_RETRIES = 8;
+
+        async function uploadError(response) {
+            const text = await response.text();
+            // Lets the host find the failure in the server log
+            const requestId = response.headers.get('X-Request-Id');
+            const reference = requestId ? ` (request ${requestId})` : '';
+            try {
+                // Disk-full and permission problems come with an explanation
+                const body = JSON.parse(text);
+                if (body.error) return new Error(body.error + reference);
+            } catch (_) {
+                // Not JSON, show the raw response
+            }
+            return new Error(`Status ${response.status}: ${text}${reference}`);
+        }
+
+        function showTransfersPaused(paused) {
+            const toggle = document.getElementById('pause-toggle');
+            toggle.textContent = paused ? 'Resume transfers' : 'Pause transfers';
+            toggle.dataset.paused = paused;
+        }
+
+        async function loadTransfersState() {
+            try {
+                const response = await fetch('/api/transfers');
+                if (response.ok) showTransfersPaused((await response.json()).paused);
+            } catch (error) {
+                console.error('Error loading transfer state:', error);
+            }
+        }
+
+        function setupPauseToggle() {
+            const toggle = document.getElementById('pause-toggle');
+            toggle.addEventListener('click', async () => {
+                const action = toggle.dataset.paused === 'true' ? 'resume' : 'pause';
+                try {
+                    const response = await fetch(`/api/transfers/${action}`, { method: 'POST' });
+                    if (response.ok) showTransfersPaused((await response.json()).paused);
+                } catch (error) {
+                    console.error('Error switching transfers:', error);
+                }
+            });
+            loadTransfersState();
+        }
+
+        // Aborts the upload in progress, set while one runs
+        let currentUpload = null;
+
+        function cancelUpload(event) {
+            event.stopPropagation();
+            if (currentUpload) currentUpload.abort();
+        }
+
+        // Upload in chunks through a resumable session. The session belongs to this browser,
+        // not its address, so switching networks only retries the chunk in flight. Picking the
+        // same file again after a reload continues an unfinished upload.
+        // Uploads of a `batch` stay on the host until the batch is committed.
+        async function uploadResumable(file, onProgress, signal, batch = null) {
+            const pending = await fetch('/api/uploads', { signal });
+            const sessions = pending.ok ? await pending.json() : [];
+            let session = batch ? null : sessions.find(s => s.name === file.name && s.size === file.size && !s.dir && !s.batch);
+
+            if (!session) {
+                const response = await fetch('/api/uploads', {
+                    method: 'POST',
+                    headers: { 'Content-Type': 'application/json' },
+                    body: JSON.stringify({
+                        name: file.name,
+                        size: file.size,
+                        modified: new Date(file.lastModified).toISOString(),
+                        batch,
+                    }),
+                    signal,
+                });
+                if (!response.ok) throw await uploadError(response);
+                session = await response.json();
+            }
+
+            let offset = session.offset;
+            let failures = 0;
+            // Set once the host reported a file of the same name and the user chose what to do
+            let resolution = null;
+            try {
+                while (true) {
+                    onProgress(offset);
+                    let response;
+                    const chunk = file.slice(offset, offset + uploadPacer.size);
+                    const started = performance.now();
+                    const query = resolution ? `?conflict=${resolution}` : '';
+                    try {
+                        response = await fetch(`/api/uploads/${session.id}${query}`, {
+                            method: 'PATCH',
+                            headers: { 'Upload-Offset': String(offset) },
+                            body: chunk,
+                            signal,
+                        });
+                    } catch (error) {
+                        if (signal.aborted || ++failures > UPLOAD_RETRIES) throw error;
+                        uploadPacer.failed();
+                        console.warn('Upload interrupted, retrying:', error);
+                        await new Promise(resolve => setTimeout(resolve, 1000 * failures));
+                        try {
+                            const current = await fetch(`/api/uploads/${session.id}`, { signal });
+                            if (current.ok) offset = (await current.json()).offset;
+                        } catch (_) {
+                            // Still offline, the next attempt will tell
+                        }
+                        continue;
+                    }
+
+                    // The host paused transfers; wait as long as asked without giving up
+                    if (response.status === 503 && response.headers.get('Retry-After')) {
+                        onProgress(offset, true);
+                        const seconds = Number(response.headers.get('Retry-After')) || 5;
+                        await new Promise(resolve => setTimeout(resolve, 1000 * seconds));
+                        continue;
+                    }
+
+                    // 409 carries the offset the server actually reached, or a file in the way
+                    if (!response.ok && response.status !== 409) throw await uploadError(response);
+                    const progress = await response.json();
+                    if (progress.conflict) {
+                        // The data is all there, an empty chunk at the end settles the conflict
+                        resolution = await askConflict(progress.conflict);
+                        offset = file.size;
+                        continue;
+                    }
+                    if (progress.file) return resolution === 'skip' ? { ...progress.file, skipped: true } : progress.file;
+                    if (batch && progress.complete) return null;
+                    if (response.ok) uploadPacer.sent(chunk.size, performance.now() - started);
+                    offset = progress.offset;
+                    failures = 0;
+                }
+            } catch (error) {
+                if (signal.aborted) {
+                    // Free the space the partial upload takes on the server
+                    await fetch(`/api/uploads/${session.id}`, { method: 'DELETE' }).catch(() => {});
+                }
+                throw error;
+            }
+        }
+
+        // What to do with every further file of the name of an existing one, when the user said so
+        let conflictChoice = null;
+        
+        function askConflict(conflict) {
+            if (conflictChoice) return Promise.resolve(conflictChoice);
+            const dialog = document.getElementById('conflict-dialog');
+            const row = (label, existing, incoming) => `<tr><th>${label}</th><td>${existing}</td><td>${incoming}</td></tr>`;
+            const when = file => file.modified ? new Date(file.modified).toLocaleString() : 'unknown';
+            const hash = file => `<code title="${file.sha256}">${file.sha256.slice(0, 12)}…</code>`;
+            const { existing, incoming } = conflict;
+            document.getElementById('conflict-details').innerHTML = `
+                <p>${escapeHtml(existing.name)} is already shared, with different content.</p>
+                <table>
+                    ${row('', 'On this device', 'Uploading')}
+                    ${row('Size', formatFileSize(existing.size), formatFileSize(incoming.size))}
+                    ${row('Modified', when(existing), when(incoming))}
+                    ${row('SHA-256', hash(existing), hash(incoming))}
+                </table>`;
+            document.getElementById('conflict-all').checked = false;
+            announce(`${existing.name} already exists`);
+            return new Promise(resolve => {
+                dialog.addEventListener('close', () => {
+                    // Escape leaves the existing file alone
+                    const choice = dialog.returnValue || 'skip';
+                    if (document.getElementById('conflict-all').checked) conflictChoice = choice;
+                    resolve(choice);
+                }, { once: true });
+                dialog.returnValue = '';
+                dialog.showModal();
+            });
+        }
+        
+        // A random UUID, which crypto.randomUUID only makes on HTTPS pages
+        function newBatchId() {
+            const bytes = crypto.getRandomValues(new Uint8Array(16));
+            bytes[6] = (bytes[6] & 0x0f) | 0x40;
+            bytes[8] = (bytes[8] & 0x3f) | 0x80;
+            const hex = [...bytes].map(byte => byte.toString(16).padStart(2, '0')).join('');
+            return `${hex.slice(0, 8)}-${hex.slice(8, 12)}-${hex.slice(12, 16)}-${hex.slice(16, 20)}-${hex.slice(20)}`;
+        }
+        
+        // Move a batch whose files all arrived into the share, or drop it after a failure
+        async function finishBatch(batch, count, uploadContent, failed) {
+            if (failed) {
+                await fetch(`/api/uploads/batches/${batch}`, { method: 'DELETE' }).catch(() => {});
+                uploadContent.innerHTML += '<p>None of the files were added</p>';
+                announce('None of the files were added');
+                return;
+            }
+            uploadContent.innerHTML = `<p>Adding ${count} files...</p>`;
+            let query = '';
+            while (true) {
+                const response = await fetch(`/api/uploads/batches/${batch}/commit${query}`, { method: 'POST' });
+                if (response.status === 409) {
+                    const body = await response.json();
+                    if (body.conflicts) {
+                        // One choice covers the whole batch
+                        query = `?conflict=${await askConflict(body.conflicts[0])}`;
+                        continue;
+                    }
+                }
+                if (!response.ok) {
+                    const error = await uploadError(response);
+                    await fetch(`/api/uploads/batches/${batch}`, { method: 'DELETE' }).catch(() => {});
+                    uploadContent.innerHTML = `<p>❌ None of the files were added</p><p>${escapeHtml(error.message)}</p>`;
+                    announce(`None of the files were added: ${error.message}`);
+                    return;
+                }
+                const { files } = await response.json();
+                uploadContent.innerHTML = `<p>✅ Added ${files.length} files together</p>`;
+                announce(`Added ${files.length} files`);
+                loadFiles();
+                loadHistory();
+                return;
+            }
         }
         
         async function uploadFiles(files, resetCallback) {
+            conflictChoice = null;
             const uploadArea = document.getElementById('upload-area');
             const uploadContent = uploadArea.querySelector('div');
+            const batch = document.getElementById('batch-mode').checked && files.length > 1
+                && !document.getElementById('relay-mode').checked ? newBatchId() : null;
+            let batchFailed = false;
             
             console.log('uploadFiles called with', files.length, 'files');
             
-            for (const file of files) {
+            for (const [index, file] of Array.from(files).entries()) {
                 try {
                     console.log('Uploading file:', file.name, 'Size:', file.size, 'Type:', file.type);
                     
-                    const formData = new FormData();
-                    formData.append('file', file);
-                    
-                    uploadContent.innerHTML = `<p>Uploading ${file.name}...</p><p>Size: ${formatFileSize(file.size)}</p>`;
-                    
-                    console.log('Sending POST request to /api/files');
-                    const response = await fetch('/api/files', {
-                        method: 'POST',
-                        body: formData,
-                    });
+                    if (document.getElementById('relay-mode').checked) {
+                        await relayFile(file, uploadContent);
+                        uploadContent.innerHTML = `<p>✅ Sent ${escapeHtml(file.name)} to the other device</p>`;
+                        announce(`Sent ${file.name} to the other device`);
+                        setTimeout(resetCallback, 3000);
+                        continue;
+                    }
                     
-                    console.log('Response status:', response.status);
-                    console.log('Response headers:', response.headers);
+                    currentUpload = new AbortController();
+                    const meter = createSpeedMeter();
+                    const result = await uploadResumable(file, (offset, paused) => {
+                        const percent = file.size ? Math.floor(offset * 100 / file.size) : 100;
+                        meter.record(offset);
+                        const state = paused ? `paused by the host at ${percent}%` : `${percent}%${meter.describe(file.size)}`;
+                        announceProgress(`Uploading ${file.name}`, percent);
+                        const position = batch ? ` (${index + 1} of ${files.length})` : '';
+                        uploadContent.innerHTML = `<p>Uploading ${escapeHtml(file.name)}${position}... ${state}</p><p>Size: ${formatFileSize(file.size)}</p>`
+                            + '<button class="button" onclick="cancelUpload(event)">Cancel</button>';
+                    }, currentUpload.signal, batch);
                     
-                    if (response.ok) {
-                        const result = await response.json();
-                        console.log('Upload successful:', result);
-                        uploadContent.innerHTML = `<p>✅ Uploaded ${file.name} successfully!</p><p>Size: ${formatFileSize(result.size)}</p>`;
-                        setTimeout(() => {
-                            resetCallback();
-                        }, 3000);
-                        
-                        // Reload file list
-                        loadFiles();
+                    console.log('Upload successful:', result);
+                    if (batch) continue;
+                    if (result.skipped) {
+                        uploadContent.innerHTML = `<p>⏭️ Skipped ${escapeHtml(file.name)}, the existing file stays</p>`;
+                        announce(`Skipped ${file.name}`);
                     } else {
-                        const errorText = await response.text();
-                        console.error('Upload error:', response.status, errorText);
-                        uploadContent.innerHTML = `<p>❌ Error uploading ${file.name}</p><p>Status: ${response.status}</p><p>Error: ${errorText}</p>`;
-                        setTimeout(() => {
-                            resetCallback();
-                        }, 5000);
+                        uploadContent.innerHTML = `<p>✅ Uploaded ${escapeHtml(file.name)} successfully!</p><p>Size: ${formatFileSize(result.size)}</p>`;
+                        announce(`Uploaded ${file.name}`);
                     }
+                    setTimeout(() => {
+                        resetCallback();
+                    }, 3000);
+                    
+                    // Reload file list
+                    loadFiles();
+                    loadHistory();
                 } catch (error) {
+                    if (currentUpload && currentUpload.signal.aborted) {
+                        // Cancelling stops the files still waiting as well
+                        uploadContent.innerHTML = `<p>Cancelled upload of ${escapeHtml(file.name)}</p>`;
+                        announce(`Cancelled upload of ${file.name}`);
+                        setTimeout(resetCallback, 3000);
+                        batchFailed = true;
+                        break;
+                    }
                     console.error('Error uploading file:', error);
-                    uploadContent.innerHTML = `<p>❌ Network error uploading ${file.name}</p><p>Error: ${error.message}</p><p>Check console for details</p>`;
+                    uploadContent.innerHTML = `<p>❌ Error uploading ${escapeHtml(file.name)}</p><p>${escapeHtml(error.message)}</p>${batch ? '' : '<p>Select the file again to resume</p>'}`;
+                    announce(`Error uploading ${file.name}: ${error.message}`);
                     setTimeout(() => {
                         resetCallback();
                     }, 5000);
+                    // The rest of an all-or-nothing batch would be thrown away anyway
+                    if (batch) {
+                        batchFailed = true;
+                        break;
+                    }
                 }
             }
+            currentUpload = null;
+            if (batch) {
+                await finishBatch(batch, files.length, uploadContent, batchFailed);
+                setTimeout(resetCallback, 5000);
+            }
+        }
+        
+        // Screen readers hear progress in quarters rather than every percent
+        const announcedProgress = new Map();
+        
+        function announce(message) {
+            const announcer = document.getElementById('announcer');
+            // Emptied first, so the same message twice is read twice
+            announcer.textContent = '';
+            setTimeout(() => { announcer.textContent = message; }, 50);
+        }
+        
+        function announceProgress(label, percent) {
+            const step = Math.floor(percent / 25) * 25;
+            if (announcedProgress.get(label) === step) return;
+            if (step >= 100) {
+                announcedProgress.delete(label);
+            } else {
+                announcedProgress.set(label, step);
+            }
+            announce(`${label} ${step}%`);
+        }
+        
+        // Keyboard shortcuts, left alone while typing or with modifier keys held
+        function setupShortcuts() {
+            document.addEventListener('keydown', event => {
+                if (event.ctrlKey || event.metaKey || event.altKey) return;
+                if (!document.getElementById('lightbox').hidden) return;
+                if (document.getElementById('conflict-dialog').open) return;
+                const target = event.target;
+                
+                if (target.matches('input, textarea, select, [contenteditable]')) {
+                    if (event.key === 'Escape' && target.id === 'search-input') {
+                        target.value = '';
+                        searchQuery = '';
+                        loadFiles();
+                        target.blur();
+                    }
+                    return;
+                }
+                
+                const item = target.closest('.file-item');
+                const inList = item && item.closest('#file-list-container');
+                if (event.key === '/') {
+                    event.preventDefault();
+                    document.getElementById('search-input').focus();
+                } else if (event.key === 'u' || event.key === 'U') {
+                    const filesButton = document.getElementById('files-btn');
+                    if (filesButton) {
+                        event.preventDefault();
+                        filesButton.click();
+                    }
+                } else if (event.key === 'j' || event.key === 'k' || (inList && (event.key === 'ArrowDown' || event.key === 'ArrowUp'))) {
+                    event.preventDefault();
+                    moveFileFocus(inList ? item : null, event.key === 'j' || event.key === 'ArrowDown' ? 1 : -1);
+                } else if (event.key === 'Enter' && inList && target === item) {
+                    event.preventDefault();
+                    item.querySelector('.download-file').click();
+                }
+            });
+        }
+        
+        function moveFileFocus(current, delta) {
+            const items = [...document.querySelectorAll('#file-list-container .file-item')];
+            if (items.length === 0) return;
+            const index = current ? items.indexOf(current) + delta : 0;
+            const next = items[Math.max(0, Math.min(items.length - 1, index))];
+            items.forEach(item => item.setAttribute('tabindex', item === next ? '0' : '-1'));
+            next.focus();
         }
         
         // Utility functions
@@ -376,12 +2297,79 @@ pub async fn serve_index() -> impl IntoResponse {
             return parseFloat((bytes / Math.pow(k, i)).toFixed(2)) + ' ' + sizes[i];
         }
         
+        // Refresh what changed as the server reports it, falling back to polling
+        function listenForEvents() {
+            if (!window.EventSource) {
+                setInterval(loadCollectRequests, 5000);
+                return;
+            }
+
+            const refreshAll = () => {
+                loadFiles();
+                loadHistory();
+                loadRecent();
+                loadQueue();
+                loadCollectRequests();
+                loadRelays();
+                loadTransfersState();
+            };
+            let connected = false;
+            const events = new EventSource('/api/events');
+            // Catch up on whatever happened while disconnected
+            events.onopen = () => {
+                if (connected) {
+                    refreshAll();
+                }
+                connected = true;
+            };
+            events.addEventListener('lagged', refreshAll);
+            events.addEventListener('activity', event => {
+                // Downloads leave the share as it was
+                if (JSON.parse(event.data).action !== 'download') {
+                    loadFiles();
+                }
+                loadHistory();
+                loadRecent();
+            });
+            events.addEventListener('collect_requested', loadCollectRequests);
+            events.addEventListener('transfer_failed', loadQueue);
+            events.addEventListener('relay_offered', loadRelays);
+            events.addEventListener('client_revoked', loadClients);
+            events.addEventListener('transfers_paused', event => showTransfersPaused(JSON.parse(event.data).paused));
+            events.addEventListener('address_changed', loadDeviceInfo);
+        }
+
         // Initialize
         document.addEventListener('DOMContentLoaded', () => {
             loadDeviceInfo();
+            setupViewToggle();
+            setupLightbox();
+            setupBulkActions();
+            setupShortcuts();
+            document.getElementById('scan-duplicates').addEventListener('click', loadDuplicates);
+            document.getElementById('refresh-usage').addEventListener('click', loadUsage);
+            document.getElementById('refresh-history').addEventListener('click', loadHistory);
+            document.getElementById('refresh-clients').addEventListener('click', loadClients);
+            setupSenderName();
+            setupPauseToggle();
+            setupQueueActions();
+            loadCollectRequests();
+            loadRelays();
+            // Claimed offers are not announced, so they drop off the list on the next poll
+            setInterval(loadRelays, 5000);
+            listenForEvents();
+            loadRecent();
+            loadQueue();
+            loadHistory();
+            loadClients();
+            loadUsage();
+            loadTags();
             loadFiles();
             discoverDevices();
             setupFileUpload();
+            if ('serviceWorker' in navigator) {
+                navigator.serviceWorker.register('/sw.js').catch(() => {});
+            }
             
             // Refresh devices button
             document.getElementById('refresh-devices').addEventListener('click', () => {
@@ -0,0 +1,48 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tracing::{info, warn};
+
+use crate::core::relay::valid_pipe_name;
+use crate::utils::http::insert_file_headers;
+use crate::web::handlers::relay::{forward_body, relayed_body};
+use crate::web::state::AppState;
+
+/// Feed the stream `name`: the request body goes to the client reading it, as it arrives.
+/// If no reader is connected yet the producer waits for one.
+pub async fn produce_stream(State(state): State<AppState>, Path(name): Path<String>, body: Body) -> StatusCode {
+    if !valid_pipe_name(&name) {
+        return StatusCode::BAD_REQUEST;
+    }
+    let Some(producer) = state.relays.pipe_producer(&name) else {
+        return StatusCode::CONFLICT;
+    };
+
+    info!("Stream {} has a producer", name);
+    let status = forward_body(body, &producer, None).await;
+    if status.is_success() {
+        info!("Stream {} finished", name);
+    } else {
+        warn!("Stream {} broke off: {}", name, status);
+    }
+    status
+}
+
+/// Read the stream `name` until its producer finishes, waiting for one if needed
+pub async fn consume_stream(State(state): State<AppState>, Path(name): Path<String>) -> Result<Response, StatusCode> {
+    if !valid_pipe_name(&name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let consumer = state.relays.pipe_consumer(&name).ok_or(StatusCode::CONFLICT)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    insert_file_headers(&mut headers);
+
+    info!("Stream {} has a consumer", name);
+    Ok((headers, relayed_body(consumer)).into_response())
+}
@@ -0,0 +1,146 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::{error, info};
+
+use crate::core::metadata::normalize_tag;
+use crate::core::models::FileInfo;
+use crate::web::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct SetTagsRequest {
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TagCount {
+    pub name: String,
+    pub count: usize,
+}
+
+/// Replace the tags of a single file
+pub async fn set_file_tags(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<SetTagsRequest>,
+) -> Result<Json<FileInfo>, StatusCode> {
+    let tags = request
+        .tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            error!("Invalid tag in request: {:?}", request.tags);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    let mut file = state.find_file(&id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    file.tags = state.metadata.set_tags(&file.name, &tags).map_err(|e| {
+        error!("Failed to save tags for {}: {}", file.name, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("Updated tags for {}: {:?}", file.name, file.tags);
+    Ok(Json(file))
+}
+
+/// List every tag in use together with the number of files carrying it
+pub async fn list_tags(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TagCount>>, StatusCode> {
+    let files = state.list_share().map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Count only files that still exist so tags of deleted files disappear
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for file in &files {
+        for tag in state.metadata.tags(&file.name) {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+
+    Ok(Json(
+        counts
+            .into_iter()
+            .map(|(name, count)| TagCount { name, count })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::DeviceInfo;
+    use crate::utils::file::get_file_info;
+    use tempfile::TempDir;
+
+    fn create_test_state(temp_dir: &TempDir) -> AppState {
+        AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080))
+    }
+
+    #[tokio::test]
+    async fn test_set_and_list_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("invoice.pdf");
+        std::fs::write(&file_path, "pdf").unwrap();
+        std::fs::write(temp_dir.path().join("other.txt"), "txt").unwrap();
+        let id = get_file_info(&file_path).unwrap().id.to_string();
+
+        let state = create_test_state(&temp_dir);
+        let request = SetTagsRequest {
+            tags: vec!["Receipts".to_string(), "tax".to_string()],
+        };
+        let Json(file) = set_file_tags(State(state.clone()), Path(id), Json(request))
+            .await
+            .unwrap();
+        assert_eq!(file.tags, vec!["receipts", "tax"]);
+
+        let Json(tags) = list_tags(State(state)).await.unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                TagCount { name: "receipts".to_string(), count: 1 },
+                TagCount { name: "tax".to_string(), count: 1 },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_tags_rejects_invalid_tag() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("note.txt");
+        std::fs::write(&file_path, "note").unwrap();
+        let id = get_file_info(&file_path).unwrap().id.to_string();
+
+        let state = create_test_state(&temp_dir);
+        let request = SetTagsRequest {
+            tags: vec!["has space".to_string()],
+        };
+        let result = set_file_tags(State(state), Path(id), Json(request)).await;
+        assert_eq!(result.unwrap_err(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_set_tags_unknown_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = create_test_state(&temp_dir);
+        let request = SetTagsRequest {
+            tags: vec!["x".to_string()],
+        };
+        let result = set_file_tags(State(state), Path("missing".to_string()), Json(request)).await;
+        assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+}
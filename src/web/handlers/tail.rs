@@ -0,0 +1,205 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html,
+    },
+};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{error, info};
+
+use crate::utils::tail::{last_lines, read_new_lines, TailPosition, TailUpdate};
+use crate::web::state::AppState;
+
+/// Lines sent before following, unless asked otherwise
+const DEFAULT_LINES: usize = 50;
+
+const MAX_LINES: usize = 1000;
+
+/// How often a followed file is checked for new lines
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Deserialize)]
+pub struct TailQuery {
+    /// `1` or `true` to keep sending lines as they are appended
+    pub follow: Option<String>,
+    /// How many of the last lines to start with
+    pub lines: Option<usize>,
+}
+
+type TailEvents = BoxStream<'static, Result<Event, Infallible>>;
+
+/// Stream the last lines of a text file as server-sent events, one line per message, and with
+/// `follow=1` keep streaming lines appended to it.
+///
+/// The id of the last event of each batch is the file offset reached, so an `EventSource` that
+/// reconnects with `Last-Event-ID` picks up where it left off. A truncated file sends a
+/// `truncated` event before its new lines; a removed one ends the stream with a `gone` event.
+pub async fn tail_file(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<TailQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<TailEvents>, StatusCode> {
+    let file = state.find_file(&id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    let resume = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(|offset| TailPosition { offset });
+    let (lines, position) = match resume {
+        Some(position) => (Vec::new(), position),
+        None => {
+            let count = query.lines.unwrap_or(DEFAULT_LINES).min(MAX_LINES);
+            let path = file.path.clone();
+            tokio::task::spawn_blocking(move || last_lines(&path, count))
+                .await
+                .map_err(|e| {
+                    error!("Tail task failed: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?
+                .map_err(|e| {
+                    error!("Failed to read {}: {}", file.name, e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?
+        }
+    };
+
+    let initial = stream::iter(line_events(TailUpdate { truncated: false, lines }, position));
+    let follow = matches!(query.follow.as_deref(), Some("1" | "true"));
+    if !follow {
+        return Ok(Sse::new(initial.boxed()));
+    }
+
+    info!("Following {}", file.name);
+    let updates = stream::unfold(Some((file.path, position)), |followed| async move {
+        let (path, position) = followed?;
+        let (events, next) = poll_file(path, position).await;
+        Some((stream::iter(events), next))
+    })
+    .flatten();
+
+    Ok(Sse::new(initial.chain(updates).boxed()).keep_alive(KeepAlive::default()))
+}
+
+/// Wait for new lines in `path`, returning their events and what to follow next (nothing once
+/// the file is gone)
+async fn poll_file(path: PathBuf, mut position: TailPosition) -> (Vec<Result<Event, Infallible>>, Option<(PathBuf, TailPosition)>) {
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let read = {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || read_new_lines(&path, &mut position).map(|update| (update, position)))
+                .await
+        };
+        match read {
+            Ok(Ok((update, moved))) => {
+                position = moved;
+                if update.truncated || !update.lines.is_empty() {
+                    return (line_events(update, position), Some((path, position)));
+                }
+            }
+            _ => {
+                info!("Stopped following {:?}, it is gone", path);
+                return (vec![Ok(Event::default().event("gone").data(""))], None);
+            }
+        }
+    }
+}
+
+fn line_events(update: TailUpdate, position: TailPosition) -> Vec<Result<Event, Infallible>> {
+    let mut events = Vec::new();
+    if update.truncated {
+        events.push(Event::default().event("truncated").data(""));
+    }
+    events.extend(update.lines.into_iter().map(|line| Event::default().data(line)));
+    if let Some(last) = events.pop() {
+        events.push(last.id(position.offset.to_string()));
+    }
+    events.into_iter().map(Ok).collect()
+}
+
+/// A page following a file live, for phones and tablets
+pub async fn serve_tail_page() -> Html<&'static str> {
+    Html(TAIL_PAGE)
+}
+
+const TAIL_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>RustDrop - Follow</title>
+    <style>
+        body {
+            margin: 0;
+            background: #1e1e1e;
+            color: #d4d4d4;
+            font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace;
+            font-size: 13px;
+        }
+        #status {
+            position: sticky;
+            top: 0;
+            padding: 6px 10px;
+            background: #2c3e50;
+            color: white;
+        }
+        #log {
+            margin: 0;
+            padding: 10px;
+            white-space: pre-wrap;
+            word-break: break-all;
+        }
+    </style>
+</head>
+<body>
+    <div id="status">Connecting...</div>
+    <pre id="log"></pre>
+    <script>
+        const id = location.pathname.split('/').pop();
+        const log = document.getElementById('log');
+        const status = document.getElementById('status');
+        const MAX_LINES = 5000;
+
+        function atBottom() {
+            return window.innerHeight + window.scrollY >= document.body.scrollHeight - 20;
+        }
+
+        function append(text) {
+            const follow = atBottom();
+            log.append(text + '\n');
+            while (log.childNodes.length > MAX_LINES) {
+                log.removeChild(log.firstChild);
+            }
+            if (follow) {
+                window.scrollTo(0, document.body.scrollHeight);
+            }
+        }
+
+        const source = new EventSource(`/api/files/${id}/tail?follow=1&lines=200`);
+        source.onopen = () => { status.textContent = 'Following live'; };
+        source.onerror = () => { status.textContent = 'Connection lost, reconnecting...'; };
+        source.onmessage = event => append(event.data);
+        source.addEventListener('truncated', () => append('--- file truncated ---'));
+        source.addEventListener('gone', () => {
+            status.textContent = 'The file was removed';
+            source.close();
+        });
+    </script>
+</body>
+</html>
+"#;
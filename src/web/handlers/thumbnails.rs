@@ -0,0 +1,61 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::utils::thumbnail::{
+    get_or_create_thumbnail, DEFAULT_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE, MIN_THUMBNAIL_SIZE,
+};
+use crate::web::state::AppState;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ThumbnailQuery {
+    /// Longest edge of the thumbnail in pixels
+    pub size: Option<u32>,
+}
+
+/// Serve a PNG thumbnail for an image (or PDF, when enabled)
+pub async fn get_thumbnail(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let size = query
+        .size
+        .unwrap_or(DEFAULT_THUMBNAIL_SIZE)
+        .clamp(MIN_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE);
+
+    let file = state.find_file(&id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or_else(|| {
+        error!("File not found: {}", id);
+        StatusCode::NOT_FOUND
+    })?;
+
+    // Decoding and scaling images is CPU bound
+    let directory = state.directory.clone();
+    let name = file.name.clone();
+    let thumbnail = tokio::task::spawn_blocking(move || get_or_create_thumbnail(&directory, &file, size))
+        .await
+        .map_err(|e| {
+            error!("Thumbnail task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map_err(|e| {
+            error!("Failed to create thumbnail for {}: {}", name, e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?
+        .ok_or(StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "private, max-age=3600"),
+        ],
+        thumbnail,
+    ))
+}
@@ -0,0 +1,76 @@
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+use tracing::error;
+
+use crate::core::models::FileInfo;
+use crate::core::transcode::{TranscodeStatus, AVAILABLE};
+use crate::utils::http::{content_disposition, insert_file_headers};
+use crate::web::state::AppState;
+
+fn find_video(state: &AppState, id: &str) -> Result<FileInfo, StatusCode> {
+    let file = state.find_file(id).map_err(|e| {
+        error!("Failed to list directory: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?.ok_or(StatusCode::NOT_FOUND)?;
+    if !file.mime_type.starts_with("video/") {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+    Ok(file)
+}
+
+/// How the browser-friendly copy of a video is coming along
+pub async fn get_transcode_status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TranscodeStatus>, StatusCode> {
+    let file = find_video(&state, &id)?;
+    state.transcoder.status(&file).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Start making a copy of a video every browser can play
+pub async fn start_transcode(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<(StatusCode, Json<TranscodeStatus>), StatusCode> {
+    if !AVAILABLE {
+        return Err(StatusCode::NOT_IMPLEMENTED);
+    }
+    let file = find_video(&state, &id)?;
+    Ok((StatusCode::ACCEPTED, Json(state.transcoder.start(&file))))
+}
+
+/// Serve the finished copy of a video, with ranges for seeking
+pub async fn download_transcoded(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    request: Request,
+) -> Result<Response, StatusCode> {
+    let file = find_video(&state, &id)?;
+    let Some(TranscodeStatus::Done { .. }) = state.transcoder.status(&file) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let response = ServeFile::new(state.transcoder.output_path(&file))
+        .oneshot(request)
+        .await
+        .map_err(|e| {
+            error!("Failed to serve the copy of {}: {}", file.name, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let name = std::path::Path::new(&file.name).with_extension("mp4");
+    let mut response = response.map(Body::new).into_response();
+    response.headers_mut().insert(
+        header::CONTENT_DISPOSITION,
+        content_disposition("inline", &name.to_string_lossy()),
+    );
+    insert_file_headers(response.headers_mut());
+    Ok(response)
+}
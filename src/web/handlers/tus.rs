@@ -0,0 +1,168 @@
+//! The tus 1.0.0 resumable upload protocol (core, creation and termination) on top of the
+//! resumable uploads of `/api/uploads`, so off-the-shelf tus clients can resume large uploads.
+//! Requests carrying `Tus-Resumable` get the headers tus expects instead of JSON bodies.
+//!
+//! Sessions still belong to the client that started them, so tus clients need to keep the
+//! `rustdrop_client` cookie or send `X-RustDrop-Device`.
+
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::collections::HashMap;
+
+use crate::core::models::FileAttributes;
+use crate::core::uploads::UploadError;
+use crate::web::error::ApiError;
+use crate::web::handlers::api::parse_modified;
+use crate::web::handlers::uploads::{CreateUploadRequest, UPLOAD_OFFSET_HEADER};
+use crate::web::session::ClientId;
+use crate::web::state::AppState;
+
+pub const TUS_RESUMABLE_HEADER: &str = "tus-resumable";
+
+/// The only protocol version spoken
+pub const TUS_VERSION: &str = "1.0.0";
+
+const UPLOAD_LENGTH_HEADER: &str = "upload-length";
+const UPLOAD_METADATA_HEADER: &str = "upload-metadata";
+
+/// Whether a request speaks tus
+pub fn is_tus(headers: &HeaderMap) -> bool {
+    headers.contains_key(TUS_RESUMABLE_HEADER)
+}
+
+/// The 412 the protocol asks for when a client speaks a tus version other than 1.0.0
+pub fn version_mismatch(headers: &HeaderMap) -> Option<Response> {
+    let version = headers.get(TUS_RESUMABLE_HEADER)?;
+    (version != TUS_VERSION)
+        .then(|| (StatusCode::PRECONDITION_FAILED, [("tus-version", TUS_VERSION)]).into_response())
+}
+
+/// Add the headers every tus response carries to `response`
+pub fn tus_response(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(TUS_RESUMABLE_HEADER, HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+/// The upload a tus creation request describes with `Upload-Length` and the `filename` (or
+/// `name`), `dir` and `modified` entries of `Upload-Metadata`
+pub fn create_request(headers: &HeaderMap) -> Result<CreateUploadRequest, ApiError> {
+    let size = headers
+        .get(UPLOAD_LENGTH_HEADER)
+        .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "Upload-Length is required"))?;
+    let mut metadata = headers
+        .get(UPLOAD_METADATA_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(parse_metadata)
+        .unwrap_or_default();
+    let name = metadata
+        .remove("filename")
+        .or_else(|| metadata.remove("name"))
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "Upload-Metadata needs a filename"))?;
+
+    Ok(CreateUploadRequest {
+        name,
+        size,
+        dir: metadata.remove("dir"),
+        attributes: FileAttributes {
+            modified: metadata.get("modified").and_then(|value| parse_modified(value)),
+            ..Default::default()
+        },
+        batch: None,
+    })
+}
+
+/// `Upload-Metadata`: comma separated keys, each followed by its base64 encoded value if it has
+/// one. Values that are not valid UTF-8 are dropped.
+fn parse_metadata(value: &str) -> HashMap<String, String> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let mut parts = pair.split_whitespace();
+            let key = parts.next()?.to_string();
+            let value = match parts.next() {
+                Some(encoded) => String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?,
+                None => String::new(),
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Middleware answering `OPTIONS /api/uploads` with what this server supports. It goes outside
+/// the CORS layer, which takes every `OPTIONS` request for a preflight, and leaves real
+/// preflights (with `Access-Control-Request-Method`) to it.
+pub async fn describe_uploads(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let discovery = request.method() == Method::OPTIONS
+        && request.uri().path() == "/api/uploads"
+        && !request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if !discovery {
+        return next.run(request).await;
+    }
+    let headers = [
+        ("tus-version", TUS_VERSION.to_string()),
+        ("tus-extension", "creation,termination".to_string()),
+        ("tus-max-size", state.config.server.max_file_size.to_string()),
+    ];
+    tus_response((StatusCode::NO_CONTENT, headers).into_response())
+}
+
+/// `HEAD /api/uploads/:id`: how far an upload got, for a client about to resume it
+pub async fn head_upload(
+    State(state): State<AppState>,
+    client: ClientId,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(response) = version_mismatch(&headers) {
+        return response;
+    }
+    let response = match state.uploads.get(&id, client.as_str()) {
+        Ok(session) => {
+            let headers = [
+                (UPLOAD_OFFSET_HEADER, session.offset.to_string()),
+                ("upload-length", session.size.to_string()),
+                (header::CACHE_CONTROL.as_str(), "no-store".to_string()),
+            ];
+            (StatusCode::OK, headers).into_response()
+        }
+        Err(UploadError::NotFound) => StatusCode::NOT_FOUND.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    tus_response(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_creation_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TUS_RESUMABLE_HEADER, HeaderValue::from_static("1.0.0"));
+        headers.insert(UPLOAD_LENGTH_HEADER, HeaderValue::from_static("10"));
+        // filename "clip.mp4", dir "videos", and a key without a value
+        headers.insert(
+            UPLOAD_METADATA_HEADER,
+            HeaderValue::from_static("filename Y2xpcC5tcDQ=,dir dmlkZW9z,is_confidential"),
+        );
+        let request = create_request(&headers).unwrap();
+        assert_eq!(request.name, "clip.mp4");
+        assert_eq!(request.size, 10);
+        assert_eq!(request.dir.as_deref(), Some("videos"));
+
+        headers.remove(UPLOAD_METADATA_HEADER);
+        assert_eq!(create_request(&headers).unwrap_err().status, StatusCode::BAD_REQUEST);
+
+        assert!(version_mismatch(&headers).is_none());
+        headers.insert(TUS_RESUMABLE_HEADER, HeaderValue::from_static("0.2.2"));
+        assert_eq!(version_mismatch(&headers).unwrap().status(), StatusCode::PRECONDITION_FAILED);
+    }
+}
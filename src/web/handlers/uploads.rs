@@ -0,0 +1,585 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path as FsPath, PathBuf};
+use tracing::{error, info, warn};
+
+use crate::core::models::{ConflictResolution, FileAttributes, FileInfo, UploadConflict};
+use crate::core::uploads::{UploadError, UploadSession};
+use crate::utils::file::{apply_attributes, get_share_file_info, resolve_relative_path, RestoreOptions};
+use crate::utils::filename::{host_name, long_path, unused_path, unused_path_by, valid_file_name};
+use crate::utils::organize::{upload_subdirectory, EXIF_SCAN_BYTES};
+use crate::web::error::ApiError;
+use crate::web::handlers::api::{conflict_error, existing_file, record_upload, upload_conflict};
+use crate::web::handlers::tus::{create_request, is_tus, tus_response, version_mismatch};
+use crate::web::session::{sender_name, ClientId};
+use crate::web::state::AppState;
+
+/// Header carrying the position a chunk starts at
+pub const UPLOAD_OFFSET_HEADER: &str = "upload-offset";
+
+/// Header of a bodiless chunk standing for this many zero bytes, sent for the holes of sparse files
+pub const UPLOAD_ZEROES_HEADER: &str = "upload-zeroes";
+
+/// Seconds a client is asked to wait before sending a chunk again while transfers are paused
+const PAUSED_RETRY_SECS: u64 = 5;
+
+/// Unfinished uploads are dropped after this long without new data
+const SESSION_MAX_AGE_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadRequest {
+    pub name: String,
+    pub size: u64,
+    /// Store the upload in this directory (relative to the share) instead of the automatic location
+    pub dir: Option<String>,
+    /// Modification time and permissions of the original file, given to the stored copy
+    #[serde(flatten)]
+    pub attributes: FileAttributes,
+    /// UUID of a batch chosen by the client. The upload stays out of the share once complete,
+    /// until the whole batch is committed.
+    pub batch: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AppendQuery {
+    /// What to do when the completed upload has the name of an existing file. Unless given,
+    /// tus clients, which cannot be asked, keep both and others are asked.
+    pub conflict: Option<ConflictResolution>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadProgress {
+    pub offset: u64,
+    pub size: u64,
+    pub complete: bool,
+    /// The stored file once the last chunk has arrived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<FileInfo>,
+}
+
+impl UploadProgress {
+    fn of(session: &UploadSession) -> Self {
+        Self {
+            offset: session.offset,
+            size: session.size,
+            complete: session.is_complete(),
+            file: None,
+        }
+    }
+}
+
+fn upload_error_status(e: UploadError) -> StatusCode {
+    match e {
+        UploadError::NotFound => StatusCode::NOT_FOUND,
+        UploadError::OffsetMismatch { .. } => StatusCode::CONFLICT,
+        UploadError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        UploadError::Io(_) | UploadError::Corrupt(_) => {
+            error!("Upload session error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Like `upload_error_status`, but explains disk and permission problems while storing data.
+/// The partial data is kept, so the upload can resume once the problem is fixed.
+fn upload_write_error(state: &AppState, e: UploadError) -> ApiError {
+    match e {
+        UploadError::Io(e) => ApiError::storage(state, "store upload data", &e),
+        e => upload_error_status(e).into(),
+    }
+}
+
+/// Start a resumable upload owned by the calling client, described by a JSON body or, for tus
+/// clients, by the `Upload-Length` and `Upload-Metadata` headers
+pub async fn create_upload(
+    State(state): State<AppState>,
+    client: ClientId,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let tus = is_tus(&headers);
+    if let Some(response) = version_mismatch(&headers) {
+        return Ok(response);
+    }
+    let request = if tus {
+        create_request(&headers)?
+    } else {
+        Json::<CreateUploadRequest>::from_bytes(&body)
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?
+            .0
+    };
+    if !valid_file_name(&request.name) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    if request.size > state.config.server.max_file_size {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+    state.check_room(request.size)?;
+    if let Some(dir) = &request.dir {
+        if resolve_relative_path(&state.directory, dir).is_none() {
+            error!("Invalid upload directory: {}", dir);
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+    }
+
+    match state.uploads.expire(Duration::days(SESSION_MAX_AGE_DAYS), chrono::Utc::now()) {
+        Ok(0) => {}
+        Ok(count) => info!("Dropped {} abandoned uploads", count),
+        Err(e) => warn!("Failed to clean up abandoned uploads: {}", e),
+    }
+
+    let session = state
+        .uploads
+        .create(
+            client.as_str(),
+            &request.name,
+            request.size,
+            request.dir,
+            sender_name(&headers),
+            request.attributes,
+        )
+        .map_err(|e| upload_write_error(&state, e))?;
+    let session = match &request.batch {
+        Some(batch) => state.uploads.join_batch(&session.id, client.as_str(), batch).map_err(|e| {
+            if let Err(e) = state.uploads.remove(&session.id) {
+                warn!("Failed to remove upload session {}: {}", session.id, e);
+            }
+            match e {
+                UploadError::NotFound => ApiError::new(StatusCode::BAD_REQUEST, "The batch must be a UUID"),
+                e => upload_write_error(&state, e),
+            }
+        })?,
+        None => session,
+    };
+
+    info!("Started upload {} for {} ({} bytes)", session.id, session.name, session.size);
+    if tus {
+        let location = [(header::LOCATION, format!("/api/uploads/{}", session.id))];
+        return Ok(tus_response((StatusCode::CREATED, location).into_response()));
+    }
+    Ok((StatusCode::CREATED, Json(session)).into_response())
+}
+
+/// Unfinished uploads of the calling client, so it can offer to resume them
+pub async fn list_uploads(
+    State(state): State<AppState>,
+    client: ClientId,
+) -> Result<Json<Vec<UploadSession>>, StatusCode> {
+    state
+        .uploads
+        .list(client.as_str())
+        .map(Json)
+        .map_err(upload_error_status)
+}
+
+pub async fn get_upload(
+    State(state): State<AppState>,
+    client: ClientId,
+    Path(id): Path<String>,
+) -> Result<Json<UploadSession>, StatusCode> {
+    state
+        .uploads
+        .get(&id, client.as_str())
+        .map(Json)
+        .map_err(upload_error_status)
+}
+
+/// Abandon an unfinished upload, deleting the data received so far
+pub async fn cancel_upload(
+    State(state): State<AppState>,
+    client: ClientId,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let response = match state.uploads.cancel(&id, client.as_str()) {
+        Ok(session) => {
+            info!("Cancelled upload {} of {} at {} of {} bytes", session.id, session.name, session.offset, session.size);
+            StatusCode::NO_CONTENT
+        }
+        Err(e) => upload_error_status(e),
+    };
+    if is_tus(&headers) {
+        return tus_response(response.into_response());
+    }
+    response.into_response()
+}
+
+/// Append a chunk at the offset given by the `Upload-Offset` header, storing the file once complete.
+/// With an `Upload-Zeroes` header the chunk is that many zero bytes, which are not written.
+///
+/// A chunk that does not start where the upload stands is answered with 409 and the current
+/// progress, so the client can continue from there. While transfers are paused chunks are
+/// refused with 503 and a `Retry-After`.
+///
+/// When the last chunk arrives and a different file of the same name exists, the upload is
+/// kept and answered with 409 and a `conflict` describing both files, unless `?conflict=` says
+/// what to do. An empty chunk at the end with a resolution settles it.
+///
+/// tus clients get 204 with the new `Upload-Offset` instead of the progress.
+pub async fn append_upload(
+    State(state): State<AppState>,
+    client: ClientId,
+    Path(id): Path<String>,
+    Query(query): Query<AppendQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if !is_tus(&headers) {
+        return append_chunk(&state, &client, &id, query, &headers, body).await.into_response();
+    }
+    if let Some(response) = version_mismatch(&headers) {
+        return response;
+    }
+    tus_response(append_chunk(&state, &client, &id, query, &headers, body).await.into_response())
+}
+
+async fn append_chunk(
+    state: &AppState,
+    client: &ClientId,
+    id: &str,
+    query: AppendQuery,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let tus = is_tus(headers);
+    if state.pause.is_paused() {
+        let session = state
+            .uploads
+            .get(id, client.as_str())
+            .map_err(upload_error_status)?;
+        let retry = [(header::RETRY_AFTER, PAUSED_RETRY_SECS.to_string())];
+        return Ok((StatusCode::SERVICE_UNAVAILABLE, retry, Json(UploadProgress::of(&session))).into_response());
+    }
+
+    let offset: u64 = headers
+        .get(UPLOAD_OFFSET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let zeroes = match headers.get(UPLOAD_ZEROES_HEADER) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .filter(|_| body.is_empty())
+                .ok_or(StatusCode::BAD_REQUEST)?,
+        ),
+        None => None,
+    };
+
+    let appended = match zeroes {
+        Some(length) => state.uploads.append_zeroes(id, client.as_str(), offset, length),
+        None => state.uploads.append(id, client.as_str(), offset, &body),
+    };
+    let session = match appended {
+        Ok(session) => session,
+        Err(UploadError::OffsetMismatch { given, expected }) => {
+            warn!("Upload {} resumed at {} but is at {}", id, given, expected);
+            let session = state
+                .uploads
+                .get(id, client.as_str())
+                .map_err(upload_error_status)?;
+            return Ok((StatusCode::CONFLICT, Json(UploadProgress::of(&session))).into_response());
+        }
+        Err(e) => return Err(upload_write_error(state, e)),
+    };
+    state.storage.clear();
+
+    let mut progress = UploadProgress::of(&session);
+    // Uploads of a batch wait for the batch to be committed
+    if session.is_complete() && session.batch.is_none() {
+        let default = if tus { ConflictResolution::KeepBoth } else { ConflictResolution::Ask };
+        let conflict = query.conflict.unwrap_or(default);
+        progress.file = Some(finish_upload(state, client, &session, conflict).await?);
+    }
+
+    if tus {
+        return Ok((StatusCode::NO_CONTENT, [(UPLOAD_OFFSET_HEADER, session.offset.to_string())]).into_response());
+    }
+    Ok(Json(progress).into_response())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchCommit {
+    /// The stored files, and the existing ones left in place of skipped uploads
+    pub files: Vec<FileInfo>,
+}
+
+/// The uploads of a batch, oldest first
+pub async fn get_batch(
+    State(state): State<AppState>,
+    client: ClientId,
+    Path(batch): Path<String>,
+) -> Result<Json<Vec<UploadSession>>, StatusCode> {
+    let sessions = state.uploads.batch(client.as_str(), &batch).map_err(upload_error_status)?;
+    if sessions.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(sessions))
+}
+
+/// Abandon a batch, deleting the data of all its uploads
+pub async fn cancel_batch(
+    State(state): State<AppState>,
+    client: ClientId,
+    Path(batch): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let sessions = state.uploads.batch(client.as_str(), &batch).map_err(upload_error_status)?;
+    if sessions.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    for session in &sessions {
+        state.uploads.cancel(&session.id, client.as_str()).map_err(upload_error_status)?;
+    }
+    info!("Cancelled batch {} of {} uploads", batch, sessions.len());
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Move every file of a batch into the share together, so that nothing watching the share sees
+/// part of it. Nothing moves while an upload of the batch is incomplete (409 with the
+/// `incomplete` names) or would replace a different file without a `?conflict=` choice (409
+/// with the `conflicts`), and a move that fails puts back the files moved before it.
+pub async fn commit_batch(
+    State(state): State<AppState>,
+    client: ClientId,
+    Path(batch): Path<String>,
+    Query(query): Query<AppendQuery>,
+) -> Result<Json<BatchCommit>, ApiError> {
+    let sessions = state.uploads.batch(client.as_str(), &batch).map_err(upload_error_status)?;
+    if sessions.is_empty() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+    let incomplete: Vec<&str> = sessions
+        .iter()
+        .filter(|session| !session.is_complete())
+        .map(|session| session.name.as_str())
+        .collect();
+    if !incomplete.is_empty() {
+        let message = format!("{} of {} uploads of the batch are incomplete", incomplete.len(), sessions.len());
+        return Err(ApiError::new(StatusCode::CONFLICT, message).with_detail("incomplete", incomplete));
+    }
+
+    let mut destinations = Vec::new();
+    let mut conflicts = Vec::new();
+    // Files of the batch with the same name each keep their own copy
+    let mut claimed = HashSet::new();
+    for session in &sessions {
+        match destination(&state, session, query.conflict.unwrap_or_default()).await? {
+            Destination::Store(path) => {
+                // A file being replaced keeps its path, copies avoid files already on disk
+                let path = if claimed.contains(&path) {
+                    unused_path_by(path, |path| path.exists() || claimed.contains(path))
+                } else {
+                    path
+                };
+                claimed.insert(path.clone());
+                destinations.push(Destination::Store(path));
+            }
+            Destination::Conflict(found) => conflicts.push(found),
+            other => destinations.push(other),
+        }
+    }
+    if !conflicts.is_empty() {
+        let message = format!("{} uploads of the batch have the name of a different file", conflicts.len());
+        return Err(ApiError::new(StatusCode::CONFLICT, message).with_detail("conflicts", conflicts));
+    }
+
+    // Room for the whole batch is made before any of it moves
+    let mut incoming = 0;
+    let mut replacing = Vec::new();
+    for (session, destination) in sessions.iter().zip(&destinations) {
+        let Destination::Store(path) = destination else { continue };
+        incoming += session.size;
+        if tokio::fs::try_exists(path).await.unwrap_or(false) {
+            replacing.push(path.as_path());
+        }
+    }
+    state.make_room(incoming, &replacing)?;
+
+    let mut moved: Vec<(&UploadSession, &PathBuf, bool)> = Vec::new();
+    for (session, destination) in sessions.iter().zip(&destinations) {
+        let Destination::Store(path) = destination else { continue };
+        // A file being replaced is put aside, to be restored if the batch fails
+        let replaced = tokio::fs::try_exists(path).await.unwrap_or(false);
+        let mut result = Ok(());
+        if replaced {
+            result = tokio::fs::rename(path, state.uploads.replaced_path(&session.id)).await;
+        }
+        if result.is_ok() {
+            result = tokio::fs::rename(state.uploads.part_path(&session.id), path).await;
+            if result.is_err() && replaced {
+                restore_replaced(&state, session, path).await;
+            }
+        }
+        if let Err(e) = result {
+            roll_back(&state, &moved).await;
+            return Err(ApiError::storage(&state, &format!("move upload {} to {:?}", session.id, path), &e));
+        }
+        moved.push((session, path, replaced));
+    }
+    for (session, _, replaced) in &moved {
+        if *replaced {
+            if let Err(e) = tokio::fs::remove_file(state.uploads.replaced_path(&session.id)).await {
+                warn!("Failed to remove the replaced copy of {}: {}", session.name, e);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    for (session, destination) in sessions.iter().zip(&destinations) {
+        files.push(match destination {
+            Destination::Store(path) => store_upload(&state, &client, session, path)?,
+            Destination::KeepExisting(path) => drop_upload(&state, session, path)?,
+            Destination::Conflict(_) => unreachable!("conflicts were refused above"),
+        });
+    }
+    info!("Committed batch {} of {} uploads", batch, files.len());
+    Ok(Json(BatchCommit { files }))
+}
+
+/// Put the uploads of a failed batch back where they waited, and the files they replaced back
+/// in the share
+async fn roll_back(state: &AppState, moved: &[(&UploadSession, &PathBuf, bool)]) {
+    for (session, path, replaced) in moved.iter().rev() {
+        if let Err(e) = tokio::fs::rename(path, state.uploads.part_path(&session.id)).await {
+            error!("Failed to take {:?} back out of the share: {}", path, e);
+        }
+        if *replaced {
+            restore_replaced(state, session, path).await;
+        }
+    }
+}
+
+async fn restore_replaced(state: &AppState, session: &UploadSession, path: &FsPath) {
+    if let Err(e) = tokio::fs::rename(state.uploads.replaced_path(&session.id), path).await {
+        error!("Failed to restore {:?}: {}", path, e);
+    }
+}
+
+/// Move a completed upload into the share and record it like a regular upload
+async fn finish_upload(
+    state: &AppState,
+    client: &ClientId,
+    session: &UploadSession,
+    conflict: ConflictResolution,
+) -> Result<FileInfo, ApiError> {
+    let file_path = match destination(state, session, conflict).await? {
+        Destination::Store(path) => path,
+        Destination::KeepExisting(path) => return drop_upload(state, session, &path),
+        // The data stays, so the client can settle the conflict without sending it again
+        Destination::Conflict(found) => return Err(conflict_error(found)),
+    };
+    let replacing = tokio::fs::try_exists(&file_path).await.unwrap_or(false).then_some(file_path.as_path());
+    state.make_room(session.size, replacing.as_slice())?;
+    tokio::fs::rename(state.uploads.part_path(&session.id), &file_path).await.map_err(|e| {
+        ApiError::storage(state, &format!("move upload {} to {:?}", session.id, file_path), &e)
+    })?;
+    store_upload(state, client, session, &file_path)
+}
+
+/// Where a completed upload goes
+enum Destination {
+    Store(PathBuf),
+    /// The file already there stays and the upload is dropped
+    KeepExisting(PathBuf),
+    Conflict(UploadConflict),
+}
+
+/// Pick the place of a completed upload, following `conflict` when its name is taken
+async fn destination(
+    state: &AppState,
+    session: &UploadSession,
+    conflict: ConflictResolution,
+) -> Result<Destination, ApiError> {
+    let part_path = state.uploads.part_path(&session.id);
+
+    let target_dir = match &session.dir {
+        Some(dir) => resolve_relative_path(&state.directory, dir).ok_or(StatusCode::BAD_REQUEST)?,
+        None => {
+            let head = read_head(&part_path).map_err(|e| {
+                error!("Failed to read upload {}: {}", session.id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            state.directory.join(upload_subdirectory(
+                &state.config.files,
+                session.sender.as_deref(),
+                &head,
+                chrono::Utc::now(),
+            ))
+        }
+    };
+    tokio::fs::create_dir_all(&target_dir).await.map_err(|e| {
+        ApiError::storage(state, &format!("create directory {:?}", target_dir), &e)
+    })?;
+
+    let file_path = long_path(target_dir.join(host_name(&session.name)));
+    if !tokio::fs::try_exists(&file_path).await.unwrap_or(false) {
+        return Ok(Destination::Store(file_path));
+    }
+    Ok(match conflict {
+        ConflictResolution::Replace => Destination::Store(file_path),
+        ConflictResolution::KeepBoth => Destination::Store(unused_path(file_path)),
+        ConflictResolution::Skip => Destination::KeepExisting(file_path),
+        ConflictResolution::Ask => {
+            let modified = session.attributes.modified;
+            match upload_conflict(state, &file_path, &part_path, &session.name, modified).await? {
+                Some(found) => Destination::Conflict(found),
+                None => Destination::KeepExisting(file_path),
+            }
+        }
+    })
+}
+
+/// Finish the bookkeeping of an upload moved to `file_path`
+fn store_upload(
+    state: &AppState,
+    client: &ClientId,
+    session: &UploadSession,
+    file_path: &FsPath,
+) -> Result<FileInfo, ApiError> {
+    let restore = RestoreOptions::from_config(&state.config.files);
+    if let Err(e) = apply_attributes(file_path, &session.attributes, restore) {
+        warn!("Failed to restore attributes of {:?}: {}", file_path, e);
+    }
+
+    if let Err(e) = state.uploads.remove(&session.id) {
+        warn!("Failed to remove finished upload session {}: {}", session.id, e);
+    }
+
+    let mut file_info = get_share_file_info(&state.directory, file_path).map_err(|e| {
+        error!("Failed to get file info for {}: {}", session.name, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    record_upload(state, &mut file_info, session.sender.clone(), Some(client.0.clone()));
+
+    info!("Resumable upload finished: {} ({} bytes)", file_info.name, file_info.size);
+    Ok(file_info)
+}
+
+/// Forget an upload the existing file at `existing` stands in for
+fn drop_upload(state: &AppState, session: &UploadSession, existing: &FsPath) -> Result<FileInfo, ApiError> {
+    info!("Dropped upload {} of {}, the existing file stays", session.id, session.name);
+    if let Err(e) = state.uploads.remove(&session.id) {
+        warn!("Failed to remove upload session {}: {}", session.id, e);
+    }
+    existing_file(state, existing)
+}
+
+fn read_head(path: &FsPath) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)?
+        .take(EXIF_SCAN_BYTES as u64)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
@@ -0,0 +1,47 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::core::history::NetworkReport;
+use crate::utils::usage::{compute_usage, UsageReport};
+use crate::web::state::AppState;
+
+/// Break down the space used by the share by directory, file type and age
+pub async fn get_usage(State(state): State<AppState>) -> Result<Json<UsageReport>, StatusCode> {
+    let directory = state.directory.clone();
+
+    // Walking a large share touches every file, keep it off the async workers
+    let report = tokio::task::spawn_blocking(move || compute_usage(&directory, Utc::now()))
+        .await
+        .map_err(|e| {
+            error!("Usage scan task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map_err(|e| {
+            error!("Failed to compute usage: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(report))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NetworkQuery {
+    /// Days to cover, up to today
+    #[serde(default = "default_network_days")]
+    pub days: u32,
+}
+
+fn default_network_days() -> u32 {
+    30
+}
+
+/// Bytes received from and sent to each peer per day, for keeping an eye on metered connections
+pub async fn get_network_usage(State(state): State<AppState>, Query(query): Query<NetworkQuery>) -> Json<NetworkReport> {
+    Json(state.network.report(query.days))
+}
@@ -0,0 +1,211 @@
+//! `/api/v2`, for native clients: cursor pagination, entity tags with conditional requests, and
+//! idempotent uploads. The v1 endpoints these wrap keep their behavior unchanged.
+
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::core::idempotency::{valid_key, KeyState};
+use crate::core::models::{FileInfo, FileType};
+use crate::utils::http::{body_etag, etag_matches, file_etag};
+use crate::web::error::ApiError;
+use crate::web::handlers::api::{download_file, list_files, upload_file, DownloadQuery, ListFilesQuery, UploadQuery};
+use crate::web::session::ClientId;
+use crate::web::state::AppState;
+
+/// Header naming an upload so that retrying it does not store the file twice
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Set on responses replayed for a repeated idempotency key
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListFilesPageQuery {
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    pub tag: Option<String>,
+    pub hidden: Option<bool>,
+    #[serde(rename = "type")]
+    pub file_type: Option<FileType>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor for the following page, absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// One page of the share's files ordered by name.
+///
+/// The cursor encodes the last name of the page, so files added or removed meanwhile never make
+/// a client skip or repeat others.
+pub async fn list_files_page(
+    State(state): State<AppState>,
+    client: Option<ClientId>,
+    Query(query): Query<ListFilesPageQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let after = query.cursor.as_deref().map(decode_cursor).transpose()?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let filter = ListFilesQuery {
+        tag: query.tag,
+        hidden: query.hidden,
+        file_type: query.file_type,
+        ..ListFilesQuery::default()
+    };
+    let Json(mut files) = list_files(State(state), client, Query(filter)).await?;
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    if let Some(after) = after {
+        files.drain(..files.partition_point(|file| file.name <= after));
+    }
+
+    let next_cursor = (files.len() > limit).then(|| encode_cursor(&files[limit - 1].name));
+    files.truncate(limit);
+    conditional_json(&headers, &Page { items: files, next_cursor })
+}
+
+/// A file's details, answering `If-None-Match` with 304 when they did not change
+pub async fn get_file(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let mut file = find(&state, &id)?;
+    state.metadata.annotate(&mut file);
+    conditional_json(&headers, &file)
+}
+
+/// Download a file like v1, with an entity tag so unchanged files need not be fetched again
+pub async fn download_file_content(
+    State(state): State<AppState>,
+    client: Option<ClientId>,
+    Path(id): Path<String>,
+    query: Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let file = find(&state, &id)?;
+    let etag = HeaderValue::from_str(&file_etag(file.size, file.modified)).unwrap();
+    if etag_matches(if_none_match(&headers), etag.to_str().unwrap()) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    let mut response = download_file(State(state), client, Path(id), query, headers).await?;
+    response.headers_mut().insert(header::ETAG, etag);
+    Ok(response)
+}
+
+/// Upload a file like v1, answering 201 with its location.
+///
+/// With an `Idempotency-Key` header, repeating a successful upload returns the original answer
+/// marked `Idempotent-Replayed` instead of storing the file again, and repeating one still in
+/// progress is refused with 409.
+pub async fn upload_file_once(
+    State(state): State<AppState>,
+    client: Option<ClientId>,
+    query: Query<UploadQuery>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Result<Response, ApiError> {
+    let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        let Json(file) = upload_file(State(state), client, query, headers, multipart).await?;
+        return Ok(created(serde_json::to_value(file).unwrap_or_default(), false));
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| valid_key(key))
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "Invalid idempotency key"))?
+        .to_string();
+
+    let keys = state.idempotency.clone();
+    let scope = client.as_ref().map(|client| client.as_str().to_string()).unwrap_or_default();
+    let pending = match keys.begin(&scope, &key) {
+        KeyState::New(pending) => pending,
+        KeyState::Running => {
+            return Err(ApiError::new(StatusCode::CONFLICT, "An upload with this idempotency key is in progress"));
+        }
+        KeyState::Completed(response) => {
+            info!("Replaying upload for idempotency key {}", key);
+            return Ok(created(response, true));
+        }
+    };
+
+    let Json(file) = upload_file(State(state), client, query, headers, multipart).await?;
+    let response = serde_json::to_value(&file).map_err(|e| {
+        error!("Failed to encode file info: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    pending.complete(response.clone());
+    Ok(created(response, false))
+}
+
+fn created(file: serde_json::Value, replayed: bool) -> Response {
+    let mut headers = HeaderMap::new();
+    if let Some(location) = file["id"].as_str().and_then(|id| HeaderValue::from_str(&format!("/api/v2/files/{}", id)).ok()) {
+        headers.insert(header::LOCATION, location);
+    }
+    if replayed {
+        headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    }
+    (StatusCode::CREATED, headers, Json(file)).into_response()
+}
+
+fn find(state: &AppState, id: &str) -> Result<FileInfo, StatusCode> {
+    state
+        .find_file(id)
+        .map_err(|e| {
+            error!("Failed to list directory: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Serve `value` as JSON with an entity tag, or 304 if the client has it already
+fn conditional_json<T: Serialize>(headers: &HeaderMap, value: &T) -> Result<Response, StatusCode> {
+    let body = serde_json::to_vec(value).map_err(|e| {
+        error!("Failed to encode response: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let etag = HeaderValue::from_str(&body_etag(&body)).unwrap();
+    if etag_matches(if_none_match(headers), etag.to_str().unwrap()) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    let headers = [
+        (header::CONTENT_TYPE, HeaderValue::from_static("application/json")),
+        (header::ETAG, etag),
+        // Clients may keep the response but must check it is current before using it
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+    ];
+    Ok((headers, body).into_response())
+}
+
+fn if_none_match(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok())
+}
+
+fn encode_cursor(name: &str) -> String {
+    URL_SAFE_NO_PAD.encode(name)
+}
+
+fn decode_cursor(cursor: &str) -> Result<String, StatusCode> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|name| String::from_utf8(name).ok())
+        .ok_or(StatusCode::BAD_REQUEST)
+}
@@ -0,0 +1,181 @@
+use axum::{extract::State, response::Html, Json};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::utils::network::interface_addresses;
+use crate::web::state::AppState;
+
+/// How to reach this host, for people whose device cannot load the page behind the QR code
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkInfo {
+    pub host: String,
+    /// Wi-Fi network to join, when the host named it in its configuration
+    pub network_name: Option<String>,
+    /// The address announced in the QR code and over mDNS
+    pub url: String,
+    /// Every other address the host has, one per interface
+    pub addresses: Vec<InterfaceAddress>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InterfaceAddress {
+    pub interface: String,
+    pub ip: String,
+    pub url: String,
+}
+
+pub async fn get_network_info(State(state): State<AppState>) -> Json<NetworkInfo> {
+    let device_info = state.current_device_info();
+    let addresses = interface_addresses()
+        .into_iter()
+        .map(|(interface, ip)| InterfaceAddress {
+            interface,
+            ip: ip.to_string(),
+            url: address_url(ip, device_info.port, device_info.tls),
+        })
+        .collect();
+
+    Json(NetworkInfo {
+        host: device_info.name.clone(),
+        network_name: state.config.ui.network_name.clone(),
+        url: device_info.url(),
+        addresses,
+    })
+}
+
+/// Landing page of the QR code: connection instructions and a self-test, then on to the share
+pub async fn serve_welcome_page() -> Html<&'static str> {
+    Html(WELCOME_PAGE)
+}
+
+fn address_url(ip: IpAddr, port: u16, tls: bool) -> String {
+    let scheme = if tls { "https" } else { "http" };
+    // Brackets IPv6 addresses as URLs need
+    format!("{}://{}", scheme, SocketAddr::new(ip, port))
+}
+
+const WELCOME_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>RustDrop - Welcome</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, 'Open Sans', 'Helvetica Neue', sans-serif;
+            max-width: 480px;
+            margin: 30px auto;
+            padding: 0 16px;
+            color: #2c3e50;
+        }
+        .open {
+            display: block;
+            margin: 20px 0;
+            padding: 14px;
+            border-radius: 6px;
+            background: #3498db;
+            color: white;
+            text-align: center;
+            text-decoration: none;
+            font-size: 18px;
+        }
+        ul { padding-left: 20px; }
+        li { margin: 6px 0; }
+        .check { font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; }
+        .ok { color: #27ae60; }
+        .failed { color: #c0392b; }
+        .hint { color: #7f8c8d; font-size: 14px; }
+    </style>
+</head>
+<body>
+    <h1>Welcome to RustDrop</h1>
+    <p>You are connected to <strong id="host">this device</strong>.</p>
+    <a class="open" href="/">Open the shared files</a>
+
+    <h2>Connection check</h2>
+    <ul id="checks"></ul>
+
+    <h2>Trouble connecting?</h2>
+    <p id="network">Your phone or tablet must be on the same network as the host.</p>
+    <ul class="hint">
+        <li>Turn off mobile data, so the device does not leave the Wi-Fi when a page is slow.</li>
+        <li>Guest networks and some office networks keep devices apart; join the main network instead.</li>
+        <li>If one of the addresses below fails, try another one, or check the host's firewall allows the port.</li>
+    </ul>
+
+    <h2>Other addresses</h2>
+    <ul id="addresses"></ul>
+
+    <script>
+        function addCheck(list, label) {
+            const item = document.createElement('li');
+            item.className = 'check';
+            item.textContent = `… ${label}`;
+            list.append(item);
+            return (ok, detail) => {
+                item.className = `check ${ok ? 'ok' : 'failed'}`;
+                item.textContent = `${ok ? '✓' : '✗'} ${label}${detail ? ` (${detail})` : ''}`;
+            };
+        }
+
+        async function probe(url) {
+            const controller = new AbortController();
+            const timer = setTimeout(() => controller.abort(), 3000);
+            const started = performance.now();
+            try {
+                const response = await fetch(`${url}/api/health`, { signal: controller.signal, cache: 'no-store' });
+                const elapsed = Math.round(performance.now() - started);
+                return response.ok ? [true, `${elapsed} ms`] : [false, `HTTP ${response.status}`];
+            } catch (error) {
+                return [false, controller.signal.aborted ? 'timed out' : 'unreachable'];
+            } finally {
+                clearTimeout(timer);
+            }
+        }
+
+        async function selfTest() {
+            const checks = document.getElementById('checks');
+            addCheck(checks, 'This page loaded')(true);
+            const server = addCheck(checks, 'The server answers');
+            server(...await probe(''));
+
+            let network;
+            try {
+                network = await (await fetch('/api/network')).json();
+            } catch (error) {
+                return;
+            }
+            document.getElementById('host').textContent = network.host;
+            if (network.network_name) {
+                document.getElementById('network').innerHTML =
+                    'Your phone or tablet must be on the Wi-Fi network <strong></strong>.';
+                document.querySelector('#network strong').textContent = network.network_name;
+            }
+
+            const addresses = document.getElementById('addresses');
+            const urls = [network.url, ...network.addresses.map(address => address.url)];
+            const labels = new Map(network.addresses.map(address => [address.url, address.interface]));
+            for (const url of new Set(urls)) {
+                const label = labels.has(url) ? `${url} (${labels.get(url)})` : url;
+                const check = addCheck(addresses, label);
+                probe(url).then(([ok, detail]) => check(ok, detail));
+            }
+        }
+
+        selfTest();
+    </script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_url() {
+        assert_eq!(address_url("192.168.1.7".parse().unwrap(), 8080, false), "http://192.168.1.7:8080");
+        assert_eq!(address_url("fd00::7".parse().unwrap(), 8080, false), "http://[fd00::7]:8080");
+        assert_eq!(address_url("192.168.1.7".parse().unwrap(), 8080, true), "https://192.168.1.7:8080");
+    }
+}
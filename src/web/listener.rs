@@ -0,0 +1,277 @@
+//! Accept loop of the web server.
+//!
+//! On a small host a handful of clients that open connections and then trickle bytes (or
+//! nothing at all) would otherwise hold every connection the server can afford. Connections
+//! are therefore capped at `server.max_connections`, must send their request headers within
+//! `server.header_timeout_secs`, and are closed once they have been silent in both directions
+//! for `server.idle_timeout_secs`. With `server.tls`, the TLS handshake counts against the
+//! header timeout too.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::{extract::ConnectInfo, Router};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::{Instant, Sleep};
+use tower::ServiceExt;
+use tracing::{debug, error};
+
+use crate::core::config::ServerConfig;
+
+/// Limits applied to every connection
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionLimits {
+    pub header_timeout: Duration,
+    /// `None` leaves silent connections open
+    pub idle_timeout: Option<Duration>,
+    pub max_connections: usize,
+}
+
+impl From<&ServerConfig> for ConnectionLimits {
+    fn from(config: &ServerConfig) -> Self {
+        Self {
+            header_timeout: Duration::from_secs(config.header_timeout_secs.max(1)),
+            idle_timeout: (config.idle_timeout_secs > 0).then(|| Duration::from_secs(config.idle_timeout_secs)),
+            max_connections: config.max_connections.max(1),
+        }
+    }
+}
+
+/// Serve `app` on `listener`, giving handlers the client address as `ConnectInfo<SocketAddr>`
+pub async fn serve(listener: TcpListener, app: Router, limits: ConnectionLimits) -> io::Result<()> {
+    accept_loop(listener, app, limits, |stream| std::future::ready(Ok(stream))).await
+}
+
+/// Serve `app` over HTTPS on `listener`, like [`serve`], with whichever acceptor is current when
+/// a connection comes in
+#[cfg(feature = "tls")]
+pub async fn serve_tls(
+    listener: TcpListener,
+    app: Router,
+    limits: ConnectionLimits,
+    acceptor: tokio::sync::watch::Receiver<tokio_rustls::TlsAcceptor>,
+) -> io::Result<()> {
+    accept_loop(listener, app, limits, move |stream| acceptor.borrow().accept(stream)).await
+}
+
+/// Accept connections, running `handshake` on each before serving HTTP over what it returns
+async fn accept_loop<H, F, S>(listener: TcpListener, app: Router, limits: ConnectionLimits, handshake: H) -> io::Result<()>
+where
+    H: Fn(IdleTimeout<TcpStream>) -> F,
+    F: Future<Output = io::Result<S>> + Send + 'static,
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(limits.header_timeout);
+    let builder = Arc::new(builder);
+    let slots = Arc::new(Semaphore::new(limits.max_connections));
+
+    loop {
+        // Waiting for a free slot before accepting leaves further clients in the listen backlog
+        let slot = slots.clone().acquire_owned().await.expect("the semaphore is never closed");
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Typically out of file descriptors; give connections time to close
+                error!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+
+        let service = app.clone().map_request(move |mut request: axum::http::Request<Incoming>| {
+            request.extensions_mut().insert(ConnectInfo(remote));
+            request
+        });
+        let handshake = handshake(IdleTimeout::new(stream, limits.idle_timeout));
+        let builder = builder.clone();
+        tokio::spawn(async move {
+            let io = match tokio::time::timeout(limits.header_timeout, handshake).await {
+                Ok(Ok(stream)) => TokioIo::new(stream),
+                Ok(Err(e)) => {
+                    debug!("Handshake with {} failed: {}", remote, e);
+                    return;
+                }
+                Err(_) => {
+                    debug!("Handshake with {} timed out", remote);
+                    return;
+                }
+            };
+            if let Err(e) = builder
+                .serve_connection_with_upgrades(io, TowerToHyperService::new(service))
+                .await
+            {
+                debug!("Connection from {} closed: {}", remote, e);
+            }
+            drop(slot);
+        });
+    }
+}
+
+/// A stream failing with `TimedOut` once it has neither read nor written anything for the
+/// timeout, while either is waiting
+struct IdleTimeout<S> {
+    inner: S,
+    timeout: Option<Duration>,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> IdleTimeout<S> {
+    fn new(inner: S, timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            deadline: timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+            timeout,
+        }
+    }
+
+    fn check<T>(&mut self, cx: &mut Context<'_>, poll: Poll<io::Result<T>>) -> Poll<io::Result<T>> {
+        let (Some(deadline), Some(timeout)) = (self.deadline.as_mut(), self.timeout) else {
+            return poll;
+        };
+        match poll {
+            Poll::Ready(result) => {
+                deadline.as_mut().reset(Instant::now() + timeout);
+                Poll::Ready(result)
+            }
+            Poll::Pending => match deadline.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, "connection idle"))),
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleTimeout<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.check(cx, poll)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        self.check(cx, poll)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        self.check(cx, poll)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_flush(cx);
+        self.check(cx, poll)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn spawn(limits: ConnectionLimits) -> SocketAddr {
+        let app = Router::new().route(
+            "/",
+            get(|ConnectInfo(remote): ConnectInfo<SocketAddr>| async move { remote.ip().to_string() }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, app, limits));
+        addr
+    }
+
+    fn limits() -> ConnectionLimits {
+        ConnectionLimits {
+            header_timeout: Duration::from_millis(300),
+            idle_timeout: Some(Duration::from_millis(600)),
+            max_connections: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serves_connect_info() {
+        let addr = spawn(limits()).await;
+        let body = reqwest::get(format!("http://{}/", addr)).await.unwrap().text().await.unwrap();
+        assert_eq!(body, "127.0.0.1");
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_serves_https() {
+        use crate::core::certificate::SelfSignedCertificate;
+        use crate::web::tls::acceptor;
+
+        let certificate = SelfSignedCertificate::generate(vec!["localhost".to_string()]).unwrap();
+        let acceptor = acceptor(&certificate).unwrap();
+        let app = Router::new().route("/", get(|| async { "secure" }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_tls(listener, app, limits(), tokio::sync::watch::channel(acceptor).1));
+
+        let client = reqwest::Client::builder().danger_accept_invalid_certs(true).build().unwrap();
+        let body = client.get(format!("https://{}/", addr)).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "secure");
+
+        // Plain HTTP gets no answer
+        assert!(reqwest::get(format!("http://{}/", addr)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_headers_are_cut_off() {
+        let addr = spawn(limits()).await;
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: x\r\n").await.unwrap();
+
+        let mut response = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+            .await
+            .expect("the server should close the connection")
+            .ok();
+        assert!(!String::from_utf8_lossy(&response).contains("127.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_idle_connection_frees_its_slot() {
+        let addr = spawn(limits()).await;
+        // Holds the only slot without sending anything
+        let _silent = tokio::net::TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let body = tokio::time::timeout(Duration::from_secs(5), async {
+            reqwest::get(format!("http://{}/", addr)).await.unwrap().text().await.unwrap()
+        })
+        .await
+        .expect("the silent connection should be closed");
+        assert_eq!(body, "127.0.0.1");
+    }
+}
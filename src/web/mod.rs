@@ -1,3 +1,12 @@
 pub mod server;
+pub mod error;
 pub mod handlers;
+pub mod listener;
+pub mod proxy;
+pub mod request_id;
 pub mod routes;
+pub mod session;
+pub mod state;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod version;
@@ -0,0 +1,141 @@
+//! Client addresses behind reverse proxies.
+//!
+//! `X-Forwarded-For` and friends are only believed when the connection comes from one of the
+//! configured `server.trusted_proxies`; from anyone else they are stripped, so a client cannot
+//! pose as another address.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName},
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use tracing::warn;
+
+use crate::web::state::AppState;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Headers a proxy uses to describe the original request
+const FORWARDED_HEADERS: &[&str] = &[
+    "forwarded",
+    X_FORWARDED_FOR,
+    "x-forwarded-host",
+    "x-forwarded-proto",
+    "x-forwarded-port",
+    "x-real-ip",
+];
+
+/// Address of the client that made a request, after looking through trusted proxies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Networks whose forwarded headers are trusted
+#[derive(Debug, Default)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    /// Parse addresses (`10.0.0.1`) and ranges (`10.0.0.0/8`), skipping invalid entries
+    pub fn parse(entries: &[String]) -> Self {
+        let networks = entries
+            .iter()
+            .filter_map(|entry| {
+                let entry = entry.trim();
+                let network = entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from));
+                if network.is_err() {
+                    warn!("Ignoring invalid trusted proxy {:?}", entry);
+                }
+                network.ok()
+            })
+            .collect();
+        Self(networks)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(&ip))
+    }
+
+    /// The client behind `peer`: the rightmost `X-Forwarded-For` address that is not itself a
+    /// trusted proxy. Only call this when `peer` is trusted.
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let forwarded: Vec<IpAddr> = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|address| address.trim().parse().ok())
+            .collect();
+
+        forwarded
+            .iter()
+            .rev()
+            .find(|ip| !self.contains(**ip))
+            .or(forwarded.first())
+            .copied()
+            .unwrap_or(peer)
+    }
+}
+
+/// Middleware attaching a `ClientIp` to every request and dropping forwarded headers from
+/// untrusted connections
+pub async fn forwarded_headers(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    if let Some(peer) = peer {
+        let client = if state.proxies.contains(peer) {
+            state.proxies.resolve(peer, request.headers())
+        } else {
+            for name in FORWARDED_HEADERS {
+                request.headers_mut().remove(HeaderName::from_static(name));
+            }
+            peer
+        };
+        request.extensions_mut().insert(ClientIp(client));
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_contains() {
+        let proxies = TrustedProxies::parse(&[
+            "10.0.0.0/8".to_string(),
+            "192.168.1.1".to_string(),
+            "not an address".to_string(),
+        ]);
+
+        assert!(proxies.contains(ip("10.20.30.40")));
+        assert!(proxies.contains(ip("192.168.1.1")));
+        assert!(!proxies.contains(ip("192.168.1.2")));
+    }
+
+    #[test]
+    fn test_resolve_skips_trusted_hops() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8".to_string()]);
+        let mut headers = HeaderMap::new();
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &headers), ip("10.0.0.1"));
+
+        // The client may prepend whatever it likes; only the hop added by our proxy counts
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("1.2.3.4, 192.168.1.50, 10.0.0.2"));
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &headers), ip("192.168.1.50"));
+
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("10.0.0.3"));
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &headers), ip("10.0.0.3"));
+    }
+}
@@ -0,0 +1,78 @@
+//! Request ids for correlating a failure a user reports with the server's logs.
+//!
+//! Every request gets an id, taken from an inbound `X-Request-Id` (set by a proxy or the
+//! client) or generated. It is sent back in the same header, attached to every log line of the
+//! request, included in JSON error bodies and recorded with history entries.
+
+use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request the current task is handling, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Middleware assigning the request id and running the rest of the request in its span
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let value = HeaderValue::from_str(&id).expect("request ids are visible ASCII");
+    request.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+    // `client` is filled in for RustDrop peers once the client session is known
+    let span = tracing::info_span!(
+        "request",
+        id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        client = tracing::field::Empty
+    );
+
+    let mut response = REQUEST_ID.scope(id, next.run(request).instrument(span)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
+
+/// Inbound ids are kept when they are short and visible ASCII, so they are safe to log
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.chars().all(|c| c.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_request_id() {
+        assert!(valid_request_id("4bf92f3577b34da6a3ce929d0e0e4736"));
+        assert!(!valid_request_id(""));
+        assert!(!valid_request_id("two words"));
+        assert!(!valid_request_id(&"x".repeat(129)));
+    }
+
+    #[tokio::test]
+    async fn test_current_request_id() {
+        assert_eq!(current_request_id(), None);
+        let id = REQUEST_ID.scope("abc".to_string(), async { current_request_id() }).await;
+        assert_eq!(id.as_deref(), Some("abc"));
+    }
+}
@@ -1,44 +1,210 @@
 use axum::{
     Router,
-    routing::get,
+    routing::{delete, get, post, put},
     extract::DefaultBodyLimit,
+    middleware,
 };
 use std::path::PathBuf;
 use tower_http::services::ServeDir;
 
+use crate::core::config::AppConfig;
 use crate::core::models::DeviceInfo;
 use crate::web::handlers::{
+    access::{access_guard, alert_unexpected_access, get_access_status, submit_access_code},
     api::{
         health_check,
+        get_api_version,
+        get_capabilities,
         get_device_info,
+        get_quic_info,
         list_files,
+        list_file_types,
         upload_file,
         download_file,
         discover_devices,
         api_not_found,
     },
+    attributes::get_file_attributes,
+    backup::{check_backup, get_backup_summary, serve_backup_page, upload_backup_item},
+    bulk::bulk_operations,
+    clients::{get_fingerprint, list_clients, revoke_client},
+    collect::{
+        create_collect_request,
+        decide_collect_request,
+        get_collect_request,
+        list_collect_requests,
+    },
+    duplicates::list_duplicates,
+    events::stream_events,
+    folders::get_folder_size,
+    history::list_history,
+    instance::shutdown_instance,
+    jump::{create_jump_code, follow_jump_code},
+    kiosk::{list_recent_senders, serve_kiosk_page},
+    links::{
+        create_share_link,
+        download_share_link,
+        get_share_link,
+        list_share_links,
+        revoke_share_link,
+        serve_link_page,
+    },
+    manifest::{get_file_checksum, get_manifest},
+    outbox::{enqueue_outbox, list_outboxes},
+    pins::set_file_pin,
+    public::{download_public_file, get_public_thumbnail, list_public_files, serve_public_page, set_file_published},
+    pwa::{serve_icon, serve_service_worker, serve_web_manifest},
+    queue::{
+        cancel_queue, clear_queue, enqueue_transfer, get_transfer, get_transfers_state, list_queue, pause_transfers,
+        resume_transfers, update_transfer,
+    },
+    relay::{create_relay, download_relay, list_relays, upload_relay},
+    render::render_file,
+    schedule::schedule_guard,
+    search::search_files,
+    slideshow::serve_slideshow_page,
+    snapshot::{get_snapshot, snapshot_guard},
     static_files::serve_index,
+    streams::{consume_stream, produce_stream},
+    tags::{list_tags, set_file_tags},
+    tail::{serve_tail_page, tail_file},
+    thumbnails::get_thumbnail,
+    transcode::{download_transcoded, get_transcode_status, start_transcode},
+    tus::head_upload,
+    uploads::{
+        append_upload, cancel_batch, cancel_upload, commit_batch, create_upload, get_batch, get_upload, list_uploads,
+    },
+    usage::{get_network_usage, get_usage},
+    v2,
+    welcome::{get_network_info, serve_welcome_page},
 };
+use crate::web::proxy::forwarded_headers;
+use crate::web::request_id::request_id;
+use crate::web::session::client_session;
+use crate::web::state::AppState;
+use crate::web::version::api_version;
 
 pub fn create_routes(directory: PathBuf, device_info: DeviceInfo, max_file_size: u64) -> Router {
+    let mut config = AppConfig::default();
+    config.server.max_file_size = max_file_size;
+    create_router(AppState::with_config(directory, device_info, config))
+}
+
+/// Build the router around an existing state, taking limits from its configuration
+pub fn create_router(state: AppState) -> Router {
+    let max_file_size = state.config.server.max_file_size;
+    
     // API routes
     let api_routes = Router::new()
         .route("/health", get(health_check))
+        .route("/access", get(get_access_status).post(submit_access_code))
+        .route("/version", get(get_api_version))
+        .route("/capabilities", get(get_capabilities))
         .route("/device", get(get_device_info))
+        .route("/network", get(get_network_info))
+        .route("/quic", get(get_quic_info))
         .route("/files", get(list_files).post(upload_file))
+        .route("/files/bulk", post(bulk_operations))
         .route("/files/:id", get(download_file))
+        .route("/files/:id/attributes", get(get_file_attributes))
+        .route("/files/:id/checksum", get(get_file_checksum))
+        .route("/files/:id/tags", put(set_file_tags))
+        .route("/files/:id/pin", put(set_file_pin).delete(set_file_pin))
+        .route("/files/:id/publish", put(set_file_published).delete(set_file_published))
+        .route("/files/:id/render", get(render_file))
+        .route("/files/:id/thumbnail", get(get_thumbnail))
+        .route("/files/:id/tail", get(tail_file))
+        .route("/files/:id/transcode", get(get_transcode_status).post(start_transcode))
+        .route("/files/:id/transcoded", get(download_transcoded))
+        .route("/uploads", get(list_uploads).post(create_upload))
+        .route("/uploads/:id", get(get_upload).head(head_upload).patch(append_upload).delete(cancel_upload))
+        .route("/uploads/batches/:batch", get(get_batch).delete(cancel_batch))
+        .route("/uploads/batches/:batch/commit", post(commit_batch))
+        .route("/tags", get(list_tags))
+        .route("/types", get(list_file_types))
+        .route("/search", get(search_files))
+        .route("/backup", get(get_backup_summary))
+        .route("/backup/check", post(check_backup))
+        .route("/backup/items", post(upload_backup_item))
+        .route("/duplicates", get(list_duplicates))
+        .route("/manifest", get(get_manifest))
+        .route("/usage", get(get_usage))
+        .route("/folders/:path/size", get(get_folder_size))
+        .route("/usage/network", get(get_network_usage))
+        .route("/history", get(list_history))
+        .route("/snapshot", get(get_snapshot))
+        .route("/events", get(stream_events))
+        .route("/clients", get(list_clients))
+        .route("/clients/:id", delete(revoke_client))
+        .route("/fingerprint", get(get_fingerprint))
+        .route("/instance/shutdown", post(shutdown_instance))
+        .route("/collect", get(list_collect_requests).post(create_collect_request))
+        .route("/collect/:id", get(get_collect_request))
+        .route("/collect/:id/decision", post(decide_collect_request))
+        .route("/jump", post(create_jump_code))
+        .route("/kiosk/senders", get(list_recent_senders))
+        .route("/public", get(list_public_files))
+        .route("/links", get(list_share_links).post(create_share_link))
+        .route("/links/:token", get(get_share_link).delete(revoke_share_link))
+        .route("/links/:token/content", get(download_share_link))
+        .route("/queue", get(list_queue).post(enqueue_transfer))
+        .route("/queue/cancel", post(cancel_queue))
+        .route("/queue/clear", post(clear_queue))
+        .route("/queue/:id", get(get_transfer).patch(update_transfer))
+        .route("/outbox", get(list_outboxes))
+        .route("/outbox/:peer", post(enqueue_outbox))
+        .route("/transfers", get(get_transfers_state))
+        .route("/transfers/pause", post(pause_transfers))
+        .route("/transfers/resume", post(resume_transfers))
+        .route("/relays", get(list_relays).post(create_relay))
+        .route("/relays/:id", get(download_relay).put(upload_relay))
+        .route("/streams/:name", get(consume_stream).post(produce_stream))
         .route("/discover", get(discover_devices))
+        // v1 above stays as it is for existing clients, changes go into v2
+        .route("/v2/files", get(v2::list_files_page).post(v2::upload_file_once))
+        .route("/v2/files/:id", get(v2::get_file))
+        .route("/v2/files/:id/content", get(v2::download_file_content))
         .fallback(api_not_found)
-        .with_state((directory.clone(), device_info));
+        .with_state(state.clone());
     
     // Static file serving for the web UI
     let static_routes = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
-        .fallback(serve_index);
+        .route("/s/:token", get(serve_link_page))
+        .route("/j/:code", get(follow_jump_code))
+        .route("/kiosk", get(serve_kiosk_page))
+        .route("/backup", get(serve_backup_page))
+        .route("/manifest.webmanifest", get(serve_web_manifest))
+        .route("/sw.js", get(serve_service_worker))
+        .route("/icon.svg", get(serve_icon))
+        .route("/slideshow", get(serve_slideshow_page))
+        .route("/public", get(serve_public_page))
+        .route("/public/:id", get(download_public_file))
+        .route("/public/:id/thumbnail", get(get_public_thumbnail))
+        .route("/tail/:id", get(serve_tail_page))
+        .route("/welcome", get(serve_welcome_page))
+        .fallback(serve_index)
+        .with_state(state.clone());
+    #[cfg(feature = "grpc")]
+    let static_routes = static_routes.merge(crate::grpc::router(state.clone()));
+    #[cfg(feature = "ipp")]
+    let static_routes = if state.config.printer.enabled {
+        static_routes.merge(crate::ipp::router(state.clone()))
+    } else {
+        static_routes
+    };
     
     // Combine routes
     Router::new()
         .nest("/api", api_routes)
         .merge(static_routes)
+        .layer(middleware::from_fn_with_state(state.clone(), snapshot_guard))
+        .layer(middleware::from_fn_with_state(state.clone(), access_guard))
+        .layer(middleware::from_fn_with_state(state.clone(), alert_unexpected_access))
+        .layer(middleware::from_fn_with_state(state.clone(), client_session))
+        .layer(middleware::from_fn_with_state(state.clone(), schedule_guard))
+        .layer(middleware::from_fn_with_state(state, forwarded_headers))
+        .layer(middleware::from_fn(api_version))
+        .layer(middleware::from_fn(request_id))
         .layer(DefaultBodyLimit::max(max_file_size as usize))
 }
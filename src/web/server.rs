@@ -1,32 +1,78 @@
 use anyhow::Result;
+use axum::middleware;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tracing::info;
 
+use crate::backend::{keep_refreshed, Backend};
+use crate::client::courier::run_courier;
+use crate::core::address::LocalAddress;
+use crate::core::config::{AppConfig, SnapshotMode};
+use crate::core::events::ServerEvent;
+use crate::core::instances::InstanceControl;
 use crate::core::models::DeviceInfo;
-use crate::web::routes::create_routes;
+use crate::core::notifications::Notifications;
+use crate::core::snapshot::Snapshot;
+use crate::web::handlers::tus::describe_uploads;
+use crate::web::listener::{self, ConnectionLimits};
+use crate::web::routes::create_router;
+use crate::web::state::AppState;
 
 pub struct WebServer {
     addr: SocketAddr,
     directory: PathBuf,
     device_info: DeviceInfo,
-    max_file_size: u64,
+    config: AppConfig,
+    control: Arc<InstanceControl>,
+    address: Arc<LocalAddress>,
 }
 
 impl WebServer {
     pub fn new(addr: SocketAddr, directory: PathBuf, device_info: DeviceInfo, max_file_size: u64) -> Self {
+        let mut config = AppConfig::default();
+        config.server.max_file_size = max_file_size;
+        
+        let address = Arc::new(LocalAddress::new(device_info.ip.clone()));
+        
         Self {
             addr,
             directory,
             device_info,
-            max_file_size,
+            config,
+            control: Arc::default(),
+            address,
         }
     }
     
+    /// Use the full application configuration instead of the defaults
+    pub fn with_config(mut self, config: AppConfig) -> Self {
+        self.config = config;
+        self
+    }
+    
+    /// Share shutdown requests with whoever runs the server
+    pub fn with_control(mut self, control: Arc<InstanceControl>) -> Self {
+        self.control = control;
+        self
+    }
+    
+    /// Follow the host's address as it changes networks, telling live clients when it does
+    pub fn with_address(mut self, address: Arc<LocalAddress>) -> Self {
+        self.address = address;
+        self
+    }
+    
     pub async fn run(&self) -> Result<()> {
+        let listener = TcpListener::bind(self.addr).await?;
+        self.serve(listener).await
+    }
+    
+    /// Serve on a listener the caller already bound
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
         // Create CORS layer
         let cors = CorsLayer::new()
             .allow_origin(Any)
@@ -34,15 +80,196 @@ impl WebServer {
             .allow_headers(Any);
         
         // Create the application router
-        let app = create_routes(self.directory.clone(), self.device_info.clone(), self.max_file_size)
+        #[cfg(feature = "tls")]
+        let acceptor = self.tls_acceptor()?;
+        #[cfg(not(feature = "tls"))]
+        if self.config.server.tls.enabled {
+            tracing::warn!("HTTPS requested but this build lacks the `tls` feature, serving HTTP");
+        }
+        
+        let state = AppState::with_config(self.directory.clone(), self.device_info.clone(), self.config.clone());
+        let state = AppState { control: self.control.clone(), address: self.address.clone(), ..state };
+        let backend = match Backend::open(&self.config.storage, &self.directory).await {
+            Ok(backend) => backend.map(Arc::new),
+            Err(e) => {
+                tracing::error!("Failed to open the storage backend, keeping files in the share only: {:#}", e);
+                None
+            }
+        };
+        let refresher = backend.clone().map(|backend| tokio::spawn(keep_refreshed(backend)));
+        let state = AppState { backend, ..state };
+        let state = AppState { snapshot: self.take_snapshot(&state).await?, ..state };
+        #[cfg(feature = "quic")]
+        let state = AppState { quic: self.start_quic(&state), ..state };
+        #[cfg(not(feature = "quic"))]
+        if self.config.quic.enabled {
+            tracing::warn!("QUIC transport requested but this build lacks the `quic` feature");
+        }
+        #[cfg(not(feature = "ipp"))]
+        if self.config.printer.enabled {
+            tracing::warn!("Printer requested but this build lacks the `ipp` feature");
+        }
+        #[cfg(feature = "email")]
+        let gateway = self.start_email(&state).await;
+        #[cfg(not(feature = "email"))]
+        if self.config.email.enabled {
+            tracing::warn!("Email gateway requested but this build lacks the `email` feature");
+        }
+        #[cfg(not(feature = "transcode"))]
+        if self.config.transcode.background {
+            tracing::warn!("Video transcoding requested but this build lacks the `transcode` feature");
+        }
+        
+        let announcer = tokio::spawn(announce_address_changes(state.clone()));
+        let notifications = Notifications::new(&self.config.notifications, &self.device_info.name);
+        let notifier = (!notifications.is_empty()).then(|| tokio::spawn(notifications.run(state.events.subscribe())));
+        let courier = (!state.courier.peers().is_empty()).then(|| {
+            tokio::spawn(run_courier(
+                state.courier.clone(),
+                state.pause.clone(),
+                state.network.clone(),
+                self.device_info.name.clone(),
+            ))
+        });
+        let network = state.network.clone();
+        let app = create_router(state.clone())
             .layer(TraceLayer::new_for_http())
-            .layer(cors);
+            .layer(cors)
+            .layer(middleware::from_fn_with_state(state, describe_uploads));
         
         // Start the server
-        info!("Starting web server on {}", self.addr);
-        let listener = TcpListener::bind(self.addr).await?;
-        axum::serve(listener, app).await?;
+        info!("Starting web server on {}", listener.local_addr()?);
+        let limits = ConnectionLimits::from(&self.config.server);
+        #[cfg(feature = "tls")]
+        let result = match acceptor {
+            Some(acceptor) => {
+                let (sender, acceptor) = tokio::sync::watch::channel(acceptor);
+                let address = self.address.subscribe();
+                let renewer = tokio::spawn(async move {
+                    let directory = crate::core::identity::identity_dir();
+                    crate::web::tls::follow_address(address, directory.as_deref(), sender).await
+                });
+                let result = listener::serve_tls(listener, app, limits, acceptor).await;
+                renewer.abort();
+                result
+            }
+            None => listener::serve(listener, app, limits).await,
+        };
+        #[cfg(not(feature = "tls"))]
+        let result = listener::serve(listener, app, limits).await;
+        announcer.abort();
+        if let Some(refresher) = refresher {
+            refresher.abort();
+        }
+        if let Some(notifier) = notifier {
+            notifier.abort();
+        }
+        if let Some(courier) = courier {
+            courier.abort();
+        }
+        if let Err(e) = network.flush() {
+            tracing::warn!("Failed to save network usage: {}", e);
+        }
+        #[cfg(feature = "email")]
+        if let Some(gateway) = gateway {
+            gateway.abort();
+        }
+        result?;
         
         Ok(())
     }
+    
+    /// Freeze the share if `files.snapshot` asks for it
+    async fn take_snapshot(&self, state: &AppState) -> Result<Option<Arc<Snapshot>>> {
+        let mode = self.config.files.snapshot;
+        if mode == SnapshotMode::Off {
+            return Ok(None);
+        }
+        let files = state.list_share()?;
+        // Copying can take a while for a large share, keep it off the async workers
+        let snapshot = tokio::task::spawn_blocking(move || Snapshot::take(files, mode)).await??;
+        let info = snapshot.info();
+        info!("Serving a read-only snapshot of {} files taken at {}", info.files, info.taken.to_rfc3339());
+        Ok(Some(Arc::new(snapshot)))
+    }
+    
+    /// The HTTPS certificate if `server.tls` is enabled, kept with the device identity so
+    /// browsers that were told to trust it keep doing so
+    #[cfg(feature = "tls")]
+    fn tls_acceptor(&self) -> Result<Option<tokio_rustls::TlsAcceptor>> {
+        use crate::core::identity::identity_dir;
+        use crate::web::tls::load_acceptor;
+        
+        if !self.config.server.tls.enabled {
+            return Ok(None);
+        }
+        
+        let acceptor = load_acceptor(identity_dir().as_deref(), &self.device_info.ip)?;
+        info!("Serving HTTPS with a self-signed certificate; browsers will ask to trust it once");
+        Ok(Some(acceptor))
+    }
+    
+    /// Start the QUIC listener on the server port if enabled, returning how peers can reach it
+    #[cfg(feature = "quic")]
+    fn start_quic(&self, state: &AppState) -> Option<crate::core::models::QuicInfo> {
+        use crate::core::identity::identity_dir;
+        use crate::core::certificate::SelfSignedCertificate;
+        use crate::quic::{QuicServer, DEVICE_CERTIFICATE};
+        
+        if !self.config.quic.enabled {
+            return None;
+        }
+        
+        let certificate = match identity_dir() {
+            Some(dir) => SelfSignedCertificate::load_or_create(&dir, &DEVICE_CERTIFICATE, vec![self.device_info.id.clone()]),
+            None => SelfSignedCertificate::generate(vec![self.device_info.id.clone()]),
+        };
+        let result = certificate.and_then(|certificate| {
+            let server = QuicServer::bind(self.addr, state.clone(), &certificate)?;
+            Ok((server, certificate.fingerprint()))
+        });
+        
+        match result {
+            Ok((server, fingerprint)) => {
+                let port = server.local_addr().map(|addr| addr.port()).unwrap_or(self.addr.port());
+                tokio::spawn(server.run());
+                Some(crate::core::models::QuicInfo { port, fingerprint })
+            }
+            Err(e) => {
+                tracing::error!("Failed to start QUIC transport: {}", e);
+                None
+            }
+        }
+    }
+    
+    /// Start the SMTP listener if enabled
+    #[cfg(feature = "email")]
+    async fn start_email(&self, state: &AppState) -> Option<tokio::task::JoinHandle<()>> {
+        use crate::email::EmailGateway;
+        
+        if !self.config.email.enabled {
+            return None;
+        }
+        
+        let addr = std::net::SocketAddr::new(self.addr.ip(), self.config.email.port);
+        match EmailGateway::bind(addr, state.clone()).await {
+            Ok(gateway) => Some(tokio::spawn(gateway.run())),
+            Err(e) => {
+                tracing::error!("Failed to start email gateway: {:#}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Tell live clients the address the host is reachable at now
+async fn announce_address_changes(state: AppState) {
+    let mut address = state.address.subscribe();
+    while address.changed().await.is_ok() {
+        let device_info = state.current_device_info();
+        state.events.publish(ServerEvent::AddressChanged {
+            url: device_info.url(),
+            ip: device_info.ip,
+        });
+    }
 }
@@ -0,0 +1,266 @@
+use axum::{
+    async_trait,
+    body::HttpBody,
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts, Extensions, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use percent_encoding::percent_decode_str;
+use std::net::IpAddr;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::core::clients::{client_handle, PeerAgent};
+use crate::core::history::OTHER_PEERS;
+use crate::web::proxy::ClientIp;
+use crate::web::state::AppState;
+
+/// Cookie identifying a browser (or other client) across visits
+pub const CLIENT_COOKIE: &str = "rustdrop_client";
+
+/// Cookie holding the display name a client attaches to its uploads, set by the web UI
+pub const SENDER_COOKIE: &str = "rustdrop_sender";
+
+/// Header through which non-browser clients present their persistent client token instead of a cookie
+pub const DEVICE_HEADER: &str = "x-rustdrop-device";
+
+/// Header in which RustDrop peers name their version and device, see [`PeerAgent`]
+pub const CLIENT_HEADER: &str = "x-rustdrop-client";
+
+const CLIENT_COOKIE_MAX_AGE: u64 = 365 * 24 * 60 * 60;
+const MAX_SENDER_LENGTH: usize = 64;
+
+/// Stable identifier of the client making a request
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientId(pub String);
+
+impl ClientId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Read a cookie value from the request headers
+pub fn cookie_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Display name of the sender from the (percent-encoded) sender cookie
+pub fn sender_name(headers: &HeaderMap) -> Option<String> {
+    let raw = cookie_value(headers, SENDER_COOKIE)?;
+    let decoded = percent_decode_str(&raw).decode_utf8().ok()?;
+    normalize_sender(&decoded)
+}
+
+/// Trim a display name and strip control characters, returning `None` if nothing usable is left
+pub fn normalize_sender(name: &str) -> Option<String> {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_SENDER_LENGTH)
+        .collect();
+    let name = name.trim();
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Persistent client token sent by a RustDrop peer, if it is a valid UUID
+pub fn device_header(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(DEVICE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| Uuid::parse_str(value.trim()).ok())
+        .map(|id| id.to_string())
+}
+
+/// Version and device of a RustDrop peer, from `X-RustDrop-Client`
+pub fn peer_agent(headers: &HeaderMap) -> Option<PeerAgent> {
+    headers
+        .get(CLIENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(PeerAgent::parse)
+}
+
+/// Address of the client as resolved by `forwarded_headers`, when the server was started
+/// with connection info
+pub fn client_ip(extensions: &Extensions) -> Option<IpAddr> {
+    extensions.get::<ClientIp>().map(|ClientIp(ip)| *ip)
+}
+
+/// Middleware attaching a `ClientId` to every request, issuing a new cookie to unknown clients.
+///
+/// The id never depends on the client's address, so a device keeps its identity when it
+/// switches networks. Requests are recorded in the client registry once the client sent its
+/// cookie or token, so requests that never return one add nothing; browsers whose cookie was
+/// revoked get a fresh one, while peers presenting a revoked token are turned away. Peers
+/// are named in the log lines of their requests.
+pub async fn client_session(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let token = device_header(request.headers());
+    if token.as_deref().is_some_and(|token| state.clients.is_revoked(token)) {
+        let body = serde_json::json!({ "error": "client revoked" });
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+
+    let existing = token.or_else(|| {
+        cookie_value(request.headers(), CLIENT_COOKIE)
+            .filter(|value| Uuid::parse_str(value).is_ok() && !state.clients.is_revoked(value))
+    });
+
+    let (client_id, is_new) = match existing {
+        Some(id) => (ClientId(id), false),
+        None => (ClientId(Uuid::new_v4().to_string()), true),
+    };
+
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let ip = client_ip(request.extensions()).map(|ip| ip.to_string());
+    let peer = peer_agent(request.headers());
+    if let Some(peer) = &peer {
+        tracing::Span::current().record("client", tracing::field::display(peer));
+    }
+    let first_request = !is_new
+        && state.clients.touch(client_id.as_str(), ip.clone(), user_agent, peer.clone(), chrono::Utc::now());
+    if let Some(peer) = peer.as_ref().filter(|_| first_request) {
+        info!(
+            "RustDrop {} on device {} ({}) connected from {}",
+            peer.version,
+            peer.device,
+            peer.os,
+            ip.as_deref().unwrap_or("an unknown address")
+        );
+    }
+    let received = body_size(request.headers(), request.body().size_hint().exact());
+
+    request.extensions_mut().insert(client_id.clone());
+    let mut response = next.run(request).await;
+
+    let sent = body_size(response.headers(), response.body().size_hint().exact());
+    state.clients.add_traffic(client_id.as_str(), received, sent);
+    // Requests that haven't sent their cookie back yet would add a new peer each time
+    let peer = if is_new { OTHER_PEERS.to_string() } else { client_handle(client_id.as_str()) };
+    state.network.record(&peer, ip.as_deref(), received, sent);
+
+    if is_new {
+        let cookie = format!(
+            "{}={}; Path=/; Max-Age={}; SameSite=Lax; HttpOnly",
+            CLIENT_COOKIE, client_id.0, CLIENT_COOKIE_MAX_AGE
+        );
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+
+    response
+}
+
+/// Size of a body from its exact size hint, falling back to the `Content-Length` header
+fn body_size(headers: &HeaderMap, exact: Option<u64>) -> u64 {
+    exact
+        .or_else(|| {
+            headers
+                .get(header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientId
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<ClientId>()
+            .cloned()
+            .ok_or(StatusCode::BAD_REQUEST)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookie_value() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; rustdrop_client=abc; other=1"),
+        );
+
+        assert_eq!(cookie_value(&headers, CLIENT_COOKIE), Some("abc".to_string()));
+        assert_eq!(cookie_value(&headers, "theme"), Some("dark".to_string()));
+        assert_eq!(cookie_value(&headers, "missing"), None);
+    }
+
+    #[test]
+    fn test_sender_name() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("rustdrop_sender=Grandma%E2%80%99s%20iPhone"),
+        );
+        assert_eq!(sender_name(&headers), Some("Grandma\u{2019}s iPhone".to_string()));
+
+        headers.insert(header::COOKIE, HeaderValue::from_static("rustdrop_sender=%20%0A%20"));
+        assert_eq!(sender_name(&headers), None);
+    }
+
+    #[test]
+    fn test_normalize_sender() {
+        assert_eq!(normalize_sender("  Sam "), Some("Sam".to_string()));
+        assert_eq!(normalize_sender("a\nb"), Some("ab".to_string()));
+        assert_eq!(normalize_sender(""), None);
+        assert_eq!(normalize_sender(&"x".repeat(100)).unwrap().len(), MAX_SENDER_LENGTH);
+    }
+
+    #[test]
+    fn test_device_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(device_header(&headers), None);
+
+        headers.insert(DEVICE_HEADER, HeaderValue::from_static("not-a-uuid"));
+        assert_eq!(device_header(&headers), None);
+
+        let id = Uuid::new_v4().to_string();
+        headers.insert(DEVICE_HEADER, HeaderValue::from_str(&id).unwrap());
+        assert_eq!(device_header(&headers), Some(id));
+    }
+
+    #[test]
+    fn test_peer_agent_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(peer_agent(&headers), None);
+
+        headers.insert(CLIENT_HEADER, HeaderValue::from_static("rustdrop/0.1.0; device=abc; os=macos"));
+        let peer = peer_agent(&headers).unwrap();
+        assert_eq!((peer.device.as_str(), peer.os.as_str()), ("abc", "macos"));
+    }
+
+    #[test]
+    fn test_cookie_value_without_cookies() {
+        let headers = HeaderMap::new();
+        assert_eq!(cookie_value(&headers, CLIENT_COOKIE), None);
+    }
+}
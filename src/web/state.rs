@@ -0,0 +1,247 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::backend::Backend;
+use crate::core::access::AccessControl;
+use crate::core::address::LocalAddress;
+use crate::core::alerts::Alerts;
+use crate::core::backup::BackupStore;
+use crate::core::courier::Courier;
+use crate::core::clients::ClientRegistry;
+use crate::core::collect::CollectRequests;
+use crate::core::config::AppConfig;
+use crate::core::error::AppResult;
+use crate::core::events::{EventBus, ServerEvent};
+use crate::core::folders::FolderSizes;
+use crate::core::history::{HistoryAction, HistoryEntry, HistoryLog, NetworkUsage};
+use crate::core::identity::identity_dir;
+use crate::core::idempotency::IdempotencyKeys;
+use crate::core::instances::InstanceControl;
+use crate::core::jump::JumpCodes;
+use crate::core::links::ShareLinks;
+use crate::core::metadata::{MetadataStore, STATE_DIR};
+use crate::core::models::{DeviceInfo, FileInfo, QuicInfo};
+use crate::core::pause::TransferPause;
+use crate::core::queue::TransferQueue;
+use crate::core::quota::{Quota, QuotaError};
+use crate::core::relay::RelayHub;
+use crate::core::schedule::Schedule;
+use crate::core::search::TextIndex;
+use crate::core::snapshot::Snapshot;
+use crate::core::storage::StorageHealth;
+use crate::core::transcode::Transcoder;
+use crate::core::uploads::UploadStore;
+use crate::plugins::Plugins;
+use crate::utils::file::{list_share_with, ListOptions, PARTIAL_UPLOAD_SUFFIX};
+use crate::web::proxy::TrustedProxies;
+use crate::web::request_id::current_request_id;
+use tracing::{info, warn};
+
+/// Shared state handed to every API handler
+#[derive(Clone)]
+pub struct AppState {
+    pub directory: PathBuf,
+    pub device_info: DeviceInfo,
+    /// Follows the device as it changes networks; its IP overrides the one in `device_info`
+    pub address: Arc<LocalAddress>,
+    pub metadata: Arc<MetadataStore>,
+    /// Text extracted from documents, for `/api/search`
+    pub text: Arc<TextIndex>,
+    pub history: Arc<HistoryLog>,
+    /// Bytes moved per peer and day, for `/api/usage/network`
+    pub network: Arc<NetworkUsage>,
+    pub uploads: Arc<UploadStore>,
+    /// What each phone backed up to `/backup`
+    pub backups: Arc<BackupStore>,
+    pub collect: Arc<CollectRequests>,
+    /// Idempotency keys of recent `/api/v2` uploads
+    pub idempotency: Arc<IdempotencyKeys>,
+    pub links: Arc<ShareLinks>,
+    /// Short codes under `/j/` for typing addresses by hand
+    pub jump: Arc<JumpCodes>,
+    pub relays: Arc<RelayHub>,
+    /// Files waiting to be sent to other devices
+    pub queue: Arc<TransferQueue>,
+    /// Outboxes carried to their peers whenever they can be reached
+    pub courier: Arc<Courier>,
+    /// Set while the host paused all transfers
+    pub pause: Arc<TransferPause>,
+    pub access: Arc<AccessControl>,
+    /// Tells the host about clients it did not expect
+    pub alerts: Arc<Alerts>,
+    /// Hours during which clients are served
+    pub schedule: Arc<Schedule>,
+    pub clients: Arc<ClientRegistry>,
+    pub proxies: Arc<TrustedProxies>,
+    pub storage: Arc<StorageHealth>,
+    /// Live events for `/api/events`
+    pub events: Arc<EventBus>,
+    pub config: Arc<AppConfig>,
+    /// Shutdown requests from another instance taking over the port
+    pub control: Arc<InstanceControl>,
+    /// Set when the QUIC transport is running
+    pub quic: Option<QuicInfo>,
+    /// Network storage that receives a copy of every upload
+    pub backend: Option<Arc<Backend>>,
+    /// Set when the share is served as it was at startup, read-only
+    pub snapshot: Option<Arc<Snapshot>>,
+    /// Processing applied to uploads
+    pub plugins: Arc<Plugins>,
+    /// Copies of videos in codecs every browser plays
+    pub transcoder: Arc<Transcoder>,
+    /// Sizes of folders for `/api/folders/:path/size`
+    pub folder_sizes: Arc<FolderSizes>,
+}
+
+impl AppState {
+    pub fn new(directory: PathBuf, device_info: DeviceInfo) -> Self {
+        Self::with_config(directory, device_info, AppConfig::default())
+    }
+
+    pub fn with_config(directory: PathBuf, device_info: DeviceInfo, config: AppConfig) -> Self {
+        let metadata = Arc::new(MetadataStore::load(&directory));
+        let text = Arc::new(TextIndex::load(&directory));
+        let transcoder = Arc::new(Transcoder::new(&directory));
+        let history = Arc::new(HistoryLog::open(&directory));
+        let network = Arc::new(NetworkUsage::load(&directory));
+        let uploads = Arc::new(UploadStore::open(&directory));
+        let backups = Arc::new(BackupStore::load(&directory));
+        let courier = Arc::new(Courier::new(&directory, &config.courier));
+        let pin_keys = identity_dir().unwrap_or_else(|| directory.join(STATE_DIR));
+        let access = Arc::new(AccessControl::load(&directory, &config.access).with_pin(config.security.pin(), &pin_keys));
+        let alerts = Arc::new(Alerts::new(&config.alerts));
+        let schedule = Arc::new(Schedule::parse(&config.server.schedule));
+        let clients = Arc::new(ClientRegistry::load(&directory));
+        let links = Arc::new(ShareLinks::load(&directory));
+        let jump = Arc::new(JumpCodes::load(&directory));
+        let proxies = Arc::new(TrustedProxies::parse(&config.server.trusted_proxies));
+        let plugins = Arc::new(Plugins::from_config(&config));
+
+        let address = Arc::new(LocalAddress::new(device_info.ip.clone()));
+
+        Self {
+            directory,
+            device_info,
+            address,
+            metadata,
+            text,
+            history,
+            network,
+            uploads,
+            backups,
+            collect: Arc::default(),
+            idempotency: Arc::default(),
+            links,
+            jump,
+            relays: Arc::default(),
+            queue: Arc::default(),
+            courier,
+            pause: Arc::default(),
+            access,
+            alerts,
+            schedule,
+            clients,
+            proxies,
+            storage: Arc::default(),
+            events: Arc::default(),
+            config: Arc::new(config),
+            control: Arc::default(),
+            quic: None,
+            backend: None,
+            snapshot: None,
+            plugins,
+            transcoder,
+            folder_sizes: Arc::default(),
+        }
+    }
+
+    /// This device as reachable right now
+    pub fn current_device_info(&self) -> DeviceInfo {
+        DeviceInfo {
+            ip: self.address.current(),
+            ..self.device_info.clone()
+        }
+    }
+
+    /// Every file in the share that the symlink settings allow serving, and the files only the
+    /// storage backend has
+    pub fn list_share(&self) -> anyhow::Result<Vec<FileInfo>> {
+        if let Some(snapshot) = &self.snapshot {
+            return Ok(snapshot.files().to_vec());
+        }
+        let mut files = list_share_with(&self.directory, &ListOptions::from_config(&self.config.files))?;
+        if let Some(backend) = &self.backend {
+            backend.merge(&self.directory, &mut files);
+        }
+        Ok(files)
+    }
+
+    /// Add an upload, delete or move to the history and tell live clients about it. When called
+    /// while a request is handled, the entry carries its id.
+    pub fn record_history(&self, entry: &HistoryEntry) -> AppResult<()> {
+        let mut entry = entry.clone();
+        entry.request_id = entry.request_id.or_else(current_request_id);
+        self.history.record(&entry)?;
+        if entry.action != HistoryAction::Download {
+            self.folder_sizes.invalidate();
+        }
+        self.events.publish(ServerEvent::Activity(entry));
+        Ok(())
+    }
+
+    /// Refuse up front an upload of `incoming` bytes that could not fit under the share's
+    /// `max_size` even by evicting files. Nothing is removed yet, see [`AppState::make_room`].
+    pub fn check_room(&self, incoming: u64) -> Result<(), QuotaError> {
+        let Some((quota, files)) = self.quota() else {
+            return Ok(());
+        };
+        quota.make_room(&files, incoming, &[]).map(|_| ())
+    }
+
+    /// Make room for `incoming` more bytes under the share's `max_size`, deleting the files
+    /// received longest ago if `evict_oldest` allows it. Only called with an upload received in
+    /// full, just before it is stored; files at `replacing` are about to be overwritten by it.
+    pub fn make_room(&self, incoming: u64, replacing: &[&std::path::Path]) -> Result<(), QuotaError> {
+        let Some((quota, files)) = self.quota() else {
+            return Ok(());
+        };
+        for file in quota.make_room(&files, incoming, replacing)? {
+            if let Err(e) = std::fs::remove_file(&file.path) {
+                warn!("Failed to remove {} to make room: {}", file.name, e);
+                continue;
+            }
+            info!("Removed {} to stay within the share size limit", file.name);
+            let mut entry = HistoryEntry::new(HistoryAction::Delete, &file.name);
+            entry.size = file.size;
+            let forgotten = self
+                .metadata
+                .forget(&file.name)
+                .and_then(|_| self.text.forget(&file.name))
+                .and_then(|_| self.record_history(&entry));
+            if let Err(e) = forgotten {
+                warn!("Failed to update metadata for evicted {}: {}", file.name, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// The share's limit with the files it counts against, if it has one
+    fn quota(&self) -> Option<(Quota, Vec<FileInfo>)> {
+        let quota = Quota::for_share(&self.config.shares, &self.directory)?;
+        match list_share_with(&self.directory, &ListOptions::from_config(&self.config.files)) {
+            // Uploads still arriving only count once they are stored
+            Ok(files) => Some((quota, files.into_iter().filter(|file| !file.name.ends_with(PARTIAL_UPLOAD_SUFFIX)).collect())),
+            Err(e) => {
+                warn!("Failed to list the share to check its size: {}", e);
+                None
+            }
+        }
+    }
+
+    /// A file of [`AppState::list_share`] by its id. A snapshot's files that changed since it
+    /// was taken are not found.
+    pub fn find_file(&self, id: &str) -> anyhow::Result<Option<FileInfo>> {
+        let file = self.list_share()?.into_iter().find(|file| file.id.to_string() == id);
+        Ok(file.filter(|file| self.snapshot.as_ref().is_none_or(|snapshot| snapshot.is_current(file))))
+    }
+}
@@ -0,0 +1,118 @@
+//! HTTPS for the web interface. Mobile browsers keep features such as clipboard access to
+//! secure contexts, which a LAN address over plain HTTP is not. Without a certificate of its
+//! own, the server generates a self-signed one on first run and keeps it, so browsers only ask
+//! to trust it once, or again after the host's address changed. The server follows such
+//! changes while running, presenting a certificate for the new address to new connections.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
+
+use crate::core::certificate::{CertificateFiles, SelfSignedCertificate};
+
+/// Where the certificate the web server presents is kept
+pub const HTTPS_CERTIFICATE: CertificateFiles = CertificateFiles {
+    cert: "https-cert.der",
+    key: "https-key.der",
+    names: "https-cert-names.json",
+};
+
+/// Acceptor for HTTP/1.1 over TLS with `certificate`
+pub fn acceptor(certificate: &SelfSignedCertificate) -> Result<TlsAcceptor> {
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate.cert.clone()], certificate.private_key())
+        .context("Invalid HTTPS certificate")?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Acceptor presenting the certificate for `ip`, kept in `directory` when there is one
+pub fn load_acceptor(directory: Option<&Path>, ip: &str) -> Result<TlsAcceptor> {
+    let names = certificate_names(ip);
+    let certificate = match directory {
+        Some(dir) => SelfSignedCertificate::load_or_create(dir, &HTTPS_CERTIFICATE, names)?,
+        None => SelfSignedCertificate::generate(names)?,
+    };
+    acceptor(&certificate)
+}
+
+/// Swap in a certificate for each new address the host gets, forever. Connections already open
+/// keep the one they were accepted with.
+pub async fn follow_address(
+    mut address: watch::Receiver<String>,
+    directory: Option<&Path>,
+    acceptor: watch::Sender<TlsAcceptor>,
+) {
+    while address.changed().await.is_ok() {
+        let ip = address.borrow_and_update().clone();
+        match load_acceptor(directory, &ip) {
+            Ok(renewed) => {
+                info!("Presenting a new HTTPS certificate for {}", ip);
+                acceptor.send_replace(renewed);
+            }
+            Err(e) => warn!("Failed to renew the HTTPS certificate for {}: {:#}", ip, e),
+        }
+    }
+}
+
+/// Names browsers may reach this host by: `localhost`, the host name with and without `.local`,
+/// and its current address
+pub fn certificate_names(ip: &str) -> Vec<String> {
+    let mut names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+    if let Some(host) = hostname::get().ok().and_then(|host| host.into_string().ok()) {
+        names.push(format!("{}.local", host));
+        names.push(host);
+    }
+    if !names.iter().any(|name| name == ip) {
+        names.push(ip.to_string());
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_certificate_is_reused() {
+        let temp_dir = TempDir::new().unwrap();
+        let names = certificate_names("192.168.1.7");
+        assert!(names.contains(&"192.168.1.7".to_string()));
+
+        let first = SelfSignedCertificate::load_or_create(temp_dir.path(), &HTTPS_CERTIFICATE, names.clone()).unwrap();
+        let second = SelfSignedCertificate::load_or_create(temp_dir.path(), &HTTPS_CERTIFICATE, names).unwrap();
+        assert_eq!(first.cert, second.cert);
+        assert!(acceptor(&second).is_ok());
+
+        // A new address gets a certificate naming it
+        let moved = SelfSignedCertificate::load_or_create(temp_dir.path(), &HTTPS_CERTIFICATE, certificate_names("192.168.1.8")).unwrap();
+        assert_ne!(first.cert, moved.cert);
+    }
+
+    #[tokio::test]
+    async fn test_follows_address_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let address = crate::core::address::LocalAddress::new("192.168.1.7".to_string());
+        let (sender, mut acceptor) = watch::channel(load_acceptor(Some(temp_dir.path()), "192.168.1.7").unwrap());
+        let directory = temp_dir.path().to_path_buf();
+        let follower = tokio::spawn({
+            let address = address.subscribe();
+            async move { follow_address(address, Some(&directory), sender).await }
+        });
+
+        address.set("10.0.0.12".to_string());
+        tokio::time::timeout(std::time::Duration::from_secs(5), acceptor.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        let names = std::fs::read_to_string(temp_dir.path().join(HTTPS_CERTIFICATE.names)).unwrap();
+        assert!(names.contains("10.0.0.12"));
+        follower.abort();
+    }
+}
@@ -0,0 +1,104 @@
+//! Versions of the REST API.
+//!
+//! Every `/api` response names the version that served it in `X-RustDrop-Api-Version`.
+//! Clients may send the same header with the version they were written for: older versions
+//! get a `Warning` header, versions the server no longer (or not yet) speaks are refused.
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+pub const API_VERSION_HEADER: &str = "x-rustdrop-api-version";
+
+/// Newest version of the REST API
+pub const API_VERSION: u32 = 2;
+
+/// Oldest version still served
+pub const MIN_API_VERSION: u32 = 1;
+
+/// Versions this server speaks, as served at `/api/version`
+#[derive(Debug, Serialize)]
+pub struct ApiVersions {
+    pub current: u32,
+    pub min: u32,
+    /// Version of the RustDrop server itself
+    pub server: &'static str,
+}
+
+impl ApiVersions {
+    pub fn supported() -> Self {
+        Self {
+            current: API_VERSION,
+            min: MIN_API_VERSION,
+            server: crate::VERSION,
+        }
+    }
+}
+
+/// The API version a path belongs to, `None` outside the API
+pub fn version_of(path: &str) -> Option<u32> {
+    if path.starts_with("/api/v2/") || path == "/api/v2" {
+        Some(2)
+    } else if path.starts_with("/api/") {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// Middleware labelling API responses with their version and checking the client's
+pub async fn api_version(request: Request, next: Next) -> Response {
+    let Some(served) = version_of(request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    let announced = request.headers().get(API_VERSION_HEADER).map(|value| {
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .filter(|version| (MIN_API_VERSION..=API_VERSION).contains(version))
+    });
+    let mut response = match announced {
+        Some(None) => {
+            let body = serde_json::json!({
+                "error": "unsupported API version",
+                "min": MIN_API_VERSION,
+                "current": API_VERSION,
+            });
+            (StatusCode::BAD_REQUEST, Json(body)).into_response()
+        }
+        _ => next.run(request).await,
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(API_VERSION_HEADER, HeaderValue::from(served));
+    if let Some(version) = announced.flatten().filter(|version| *version < API_VERSION) {
+        let warning = format!(
+            "299 rustdrop \"API version {} is older than the current {}, see /api/version\"",
+            version, API_VERSION
+        );
+        if let Ok(value) = HeaderValue::from_str(&warning) {
+            headers.insert(header::WARNING, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_of() {
+        assert_eq!(version_of("/api/files"), Some(1));
+        assert_eq!(version_of("/api/v2/files"), Some(2));
+        assert_eq!(version_of("/api/v2files"), Some(1));
+        assert_eq!(version_of("/s/token"), None);
+    }
+}
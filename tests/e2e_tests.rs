@@ -13,7 +13,7 @@ fn find_available_port() -> u16 {
     
     // Try ports starting from 8080
     for port in 8080..9080 {
-        if let Ok(_) = TcpListener::bind(("127.0.0.1", port)) {
+        if TcpListener::bind(("127.0.0.1", port)).is_ok() {
             return port;
         }
     }
@@ -33,7 +33,7 @@ impl TestServer {
         
         // Build the binary first if needed
         let output = Command::new("cargo")
-            .args(&["build", "--bin", "rustdrop"])
+            .args(["build", "--bin", "rustdrop"])
             .output()?;
         
         if !output.status.success() {
@@ -42,7 +42,7 @@ impl TestServer {
         
         // Start the server
         let child = Command::new("./target/debug/rustdrop")
-            .args(&[
+            .args([
                 "--port", &port.to_string(),
                 "--directory", temp_dir.path().to_str().unwrap(),
                 "--no-mdns",
@@ -75,7 +75,7 @@ impl TestServer {
             .build()?;
             
         for attempt in 0..20 {
-            match client.get(&format!("{}/api/health", self.url())).send().await {
+            match client.get(format!("{}/api/health", self.url())).send().await {
                 Ok(response) => {
                     if response.status().is_success() {
                         return Ok(());
@@ -99,7 +99,7 @@ impl TestServer {
         
         // Try fallback endpoint
         for _ in 0..5 {
-            match client.get(&format!("{}/", self.url())).send().await {
+            match client.get(format!("{}/", self.url())).send().await {
                 Ok(response) => {
                     if response.status().is_success() || response.status() == 404 {
                         return Ok(());
@@ -138,21 +138,21 @@ async fn test_api_endpoints() -> Result<()> {
     // Test health endpoint
     let response = timeout(
         SERVER_RESPONSE_TIMEOUT,
-        client.get(&format!("{}/api/health", server.url())).send()
+        client.get(format!("{}/api/health", server.url())).send()
     ).await??;
     assert!(response.status().is_success());
     
     // Test device info endpoint
     let response = timeout(
         SERVER_RESPONSE_TIMEOUT,
-        client.get(&format!("{}/api/device", server.url())).send()
+        client.get(format!("{}/api/device", server.url())).send()
     ).await??;
     assert!(response.status().is_success());
     
     // Test files endpoint
     let response = timeout(
         SERVER_RESPONSE_TIMEOUT,
-        client.get(&format!("{}/api/files", server.url())).send()
+        client.get(format!("{}/api/files", server.url())).send()
     ).await??;
     assert!(response.status().is_success());
     
@@ -175,14 +175,14 @@ async fn test_file_upload_and_download() -> Result<()> {
     // Test file listing first
     let response = timeout(
         SERVER_RESPONSE_TIMEOUT,
-        client.get(&format!("{}/api/files", server.url())).send()
+        client.get(format!("{}/api/files", server.url())).send()
     ).await??;
     assert!(response.status().is_success());
     
     let files: serde_json::Value = response.json().await?;
     println!("Files response: {}", files);
     let files_array = files.as_array().unwrap();
-    assert!(files_array.len() > 0);
+    assert!(!files_array.is_empty());
     
     // Get the file ID from the response
     let file_id = files_array[0]["id"].as_str().unwrap();
@@ -190,7 +190,7 @@ async fn test_file_upload_and_download() -> Result<()> {
     // Test file download using the file ID
     let response = timeout(
         SERVER_RESPONSE_TIMEOUT,
-        client.get(&format!("{}/api/files/{}", server.url(), file_id)).send()
+        client.get(format!("{}/api/files/{}", server.url(), file_id)).send()
     ).await??;
     
     let status = response.status();
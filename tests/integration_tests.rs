@@ -3,12 +3,21 @@ use axum::{
     http::{Request, StatusCode},
     Router,
 };
-use rustdrop::web::routes::create_routes;
-use rustdrop::core::models::DeviceInfo;
+use rustdrop::web::routes::{create_router, create_routes};
+use rustdrop::web::state::AppState;
+use rustdrop::web::handlers::tus::describe_uploads;
+use rustdrop::client::PeerClient;
+use rustdrop::client::fanout::{collect_from_peers, send_to_peers, OutgoingFile};
+use rustdrop::core::clients::client_handle;
+use rustdrop::core::config::{ConflictPolicy, ShareConfig};
+use rustdrop::core::instances::instance_label;
+use rustdrop::sync::{sync_directory, SyncAction, SyncOptions};
+use rustdrop::core::models::{DeviceInfo, FileAttributes};
 use rustdrop::{AppConfig, get_file_info, list_directory};
 use serde_json::Value;
 use std::fs::File;
 use std::io::Write;
+use std::time::Duration;
 use tempfile::TempDir;
 use tower::util::ServiceExt;
 use tower_http::cors::{Any, CorsLayer};
@@ -162,7 +171,7 @@ async fn test_download_nonexistent_file() {
 
     let fake_uuid = "123e4567-e89b-12d3-a456-426614174000";
     let request = Request::builder()
-        .uri(&format!("/api/files/{}", fake_uuid))
+        .uri(format!("/api/files/{}", fake_uuid))
         .body(Body::empty())
         .unwrap();
 
@@ -187,7 +196,7 @@ async fn test_download_existing_file() {
     let app = create_test_app(&temp_dir);
 
     let request = Request::builder()
-        .uri(&format!("/api/files/{}", file_id))
+        .uri(format!("/api/files/{}", file_id))
         .body(Body::empty())
         .unwrap();
 
@@ -205,13 +214,2357 @@ async fn test_download_existing_file() {
         .to_str()
         .unwrap()
         .contains("download_test.txt"));
+    assert_eq!(
+        headers.get("content-length").unwrap(),
+        &file_content.len().to_string()
+    );
+
+    // Check content
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let downloaded_content = String::from_utf8(body.to_vec()).unwrap();
+    assert_eq!(downloaded_content, file_content);
+}
+
+#[tokio::test]
+async fn test_tag_and_filter_files() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("receipt.pdf"), "receipt").unwrap();
+    std::fs::write(temp_dir.path().join("photo.jpg"), "photo").unwrap();
+
+    let file_id = get_file_info(&temp_dir.path().join("receipt.pdf"))
+        .unwrap()
+        .id
+        .to_string();
+
+    let app = create_test_app(&temp_dir);
+
+    // Tag one of the files
+    let request = Request::builder()
+        .method("PUT")
+        .uri(format!("/api/files/{}/tags", file_id))
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"tags": ["receipts"]}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Filtering by the tag should only return the tagged file
+    let request = Request::builder()
+        .uri("/api/files?tag=receipts")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let files: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["name"], "receipt.pdf");
+    assert_eq!(files[0]["tags"][0], "receipts");
+
+    // The tag should show up in the tag list
+    let request = Request::builder()
+        .uri("/api/tags")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let tags: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0]["name"], "receipts");
+    assert_eq!(tags[0]["count"], 1);
+}
+
+#[tokio::test]
+async fn test_pinned_files_listed_first_per_client() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("alpha.txt"), "a").unwrap();
+    std::fs::write(temp_dir.path().join("zulu.txt"), "z").unwrap();
+
+    let file_id = get_file_info(&temp_dir.path().join("zulu.txt"))
+        .unwrap()
+        .id
+        .to_string();
+
+    let app = create_test_app(&temp_dir);
+
+    // First visit hands out a client cookie
+    let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let set_cookie = response
+        .headers()
+        .get("set-cookie")
+        .expect("client cookie should be issued")
+        .to_str()
+        .unwrap()
+        .to_string();
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+
+    let request = Request::builder()
+        .method("PUT")
+        .uri(format!("/api/files/{}/pin", file_id))
+        .header("cookie", &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The pinning client sees the pinned file first
+    let request = Request::builder()
+        .uri("/api/files")
+        .header("cookie", &cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let files: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(files[0]["name"], "zulu.txt");
+    assert_eq!(files[0]["pinned"], true);
+
+    // Other clients keep the plain name order
+    let request = Request::builder().uri("/api/files").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let files: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(files[0]["name"], "alpha.txt");
+    assert_eq!(files[1]["pinned"], false);
+}
+
+#[tokio::test]
+async fn test_download_inline() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("photo.png");
+    std::fs::write(&file_path, "not really a png").unwrap();
+    let file_id = get_file_info(&file_path).unwrap().id.to_string();
+
+    let app = create_test_app(&temp_dir);
+
+    let request = Request::builder()
+        .uri(format!("/api/files/{}?inline=true", file_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers.get("content-type").unwrap(), "image/png");
+    assert!(headers
+        .get("content-disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("inline;"));
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+    assert_eq!(headers["content-security-policy"], "sandbox");
+
+    // Pages and images that can run script are always saved
+    std::fs::write(temp_dir.path().join("page.html"), "<script>alert(1)</script>").unwrap();
+    std::fs::write(temp_dir.path().join("logo.svg"), "<svg onload=\"alert(1)\"/>").unwrap();
+    for name in ["page.html", "logo.svg"] {
+        let file_id = get_file_info(&temp_dir.path().join(name)).unwrap().id.to_string();
+        let request = Request::builder()
+            .uri(format!("/api/files/{}?inline=true", file_id))
+            .body(Body::empty())
+            .unwrap();
+        let response = create_test_app(&temp_dir).oneshot(request).await.unwrap();
+        let disposition = response.headers()["content-disposition"].to_str().unwrap();
+        assert!(disposition.starts_with("attachment;"), "{}", name);
+        assert_eq!(response.headers()["content-security-policy"], "sandbox");
+    }
+}
+
+#[tokio::test]
+async fn test_aborted_replace_keeps_the_original() {
+    use futures_util::StreamExt;
+
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("report.txt"), "original").unwrap();
+
+    let boundary = "rustdrop-boundary";
+    let upload = |body: Body| {
+        Request::builder()
+            .method("POST")
+            .uri("/api/files?conflict=replace")
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+            .body(body)
+            .unwrap()
+    };
+    let head = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"report.txt\"\r\nContent-Type: text/plain\r\n\r\n{data}",
+        b = boundary,
+        data = "x".repeat(1_000_000)
+    );
+
+    // The client goes away after more than the head kept in memory, so the new file was started
+    let parts = vec![Ok(head.clone()), Err(std::io::Error::other("connection reset"))];
+    let parts = futures_util::stream::iter(parts).then(|part| async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        part
+    });
+    let response = create_test_app(&temp_dir)
+        .oneshot(upload(Body::from_stream(parts)))
+        .await
+        .unwrap();
+    assert!(!response.status().is_success());
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("report.txt")).unwrap(), "original");
+    let leftovers = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".rustdrop-upload"))
+        .count();
+    assert_eq!(leftovers, 0);
+
+    // A complete upload still replaces it
+    let body = format!("{}\r\n--{}--\r\n", head, boundary);
+    let response = create_test_app(&temp_dir).oneshot(upload(Body::from(body))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(std::fs::metadata(temp_dir.path().join("report.txt")).unwrap().len(), 1_000_000);
+}
+
+#[tokio::test]
+async fn test_download_range_requests() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("clip.mp4");
+    std::fs::write(&file_path, "0123456789").unwrap();
+    let file_id = get_file_info(&file_path).unwrap().id.to_string();
+
+    let app = create_test_app(&temp_dir);
+
+    // Partial content
+    let request = Request::builder()
+        .uri(format!("/api/files/{}", file_id))
+        .header("range", "bytes=2-5")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers().get("content-range").unwrap(), "bytes 2-5/10");
+    assert_eq!(response.headers().get("accept-ranges").unwrap(), "bytes");
+    assert_eq!(response.headers().get("content-length").unwrap(), "4");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"2345");
+
+    // Suffix range
+    let request = Request::builder()
+        .uri(format!("/api/files/{}", file_id))
+        .header("range", "bytes=-3")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"789");
+
+    // Past the end of the file
+    let request = Request::builder()
+        .uri(format!("/api/files/{}", file_id))
+        .header("range", "bytes=20-")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers().get("content-range").unwrap(), "bytes */10");
+}
+
+#[tokio::test]
+async fn test_thumbnail_endpoint() {
+    let temp_dir = TempDir::new().unwrap();
+    let image_path = temp_dir.path().join("photo.png");
+    image::ImageBuffer::from_pixel(300, 300, image::Rgb([10u8, 20, 30]))
+        .save(&image_path)
+        .unwrap();
+    std::fs::write(temp_dir.path().join("notes.txt"), "text").unwrap();
+
+    let image_id = get_file_info(&image_path).unwrap().id.to_string();
+    let text_id = get_file_info(&temp_dir.path().join("notes.txt"))
+        .unwrap()
+        .id
+        .to_string();
+
+    let app = create_test_app(&temp_dir);
+
+    let request = Request::builder()
+        .uri(format!("/api/files/{}/thumbnail?size=64", image_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let thumbnail = image::load_from_memory(&body).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (64, 64));
+
+    // Thumbnails are cached in the state directory, which must not show up in listings
+    let files = list_directory(temp_dir.path()).unwrap();
+    assert_eq!(files.len(), 2);
+
+    let request = Request::builder()
+        .uri(format!("/api/files/{}/thumbnail", text_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn test_bulk_operations() {
+    let temp_dir = TempDir::new().unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(temp_dir.path().join(name), name).unwrap();
+    }
+    let id = |name: &str| {
+        get_file_info(&temp_dir.path().join(name))
+            .unwrap()
+            .id
+            .to_string()
+    };
+
+    let app = create_test_app(&temp_dir);
+
+    // One invalid operation rejects the whole batch
+    let body = serde_json::json!({
+        "operations": [
+            { "op": "delete", "id": id("a.txt") },
+            { "op": "move", "id": id("b.txt"), "to": "../escape.txt" },
+        ]
+    });
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files/bulk")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let result: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(result["results"][0]["status"], "skipped");
+    assert_eq!(result["results"][1]["status"], "failed");
+    assert!(temp_dir.path().join("a.txt").exists());
+
+    let body = serde_json::json!({
+        "operations": [
+            { "op": "delete", "id": id("a.txt") },
+            { "op": "move", "id": id("b.txt"), "to": "renamed.txt" },
+            { "op": "tag", "id": id("c.txt"), "add": ["Work"] },
+        ]
+    });
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files/bulk")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = Request::builder()
+        .uri("/api/files")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let files: Value = serde_json::from_slice(&body).unwrap();
+    let files = files.as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["name"], "c.txt");
+    assert_eq!(files[0]["tags"], serde_json::json!(["work"]));
+    assert_eq!(files[1]["name"], "renamed.txt");
+}
+
+#[tokio::test]
+async fn test_duplicates_endpoint() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("IMG_0001.jpg"), "photo bytes").unwrap();
+    std::fs::write(temp_dir.path().join("IMG_0001 copy.jpg"), "photo bytes").unwrap();
+    std::fs::write(temp_dir.path().join("IMG_0002.jpg"), "other photo").unwrap();
+
+    let app = create_test_app(&temp_dir);
+
+    let request = Request::builder()
+        .uri("/api/duplicates")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let report: Value = serde_json::from_slice(&body).unwrap();
+    let groups = report["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0]["files"].as_array().unwrap().len(), 2);
+    assert_eq!(report["wasted_bytes"], 11);
+}
+
+#[tokio::test]
+async fn test_usage_endpoint() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("notes.txt"), "1234").unwrap();
+    std::fs::create_dir(temp_dir.path().join("photos")).unwrap();
+    std::fs::write(temp_dir.path().join("photos/beach.jpg"), "12345678").unwrap();
+
+    let app = create_test_app(&temp_dir);
+
+    let request = Request::builder()
+        .uri("/api/usage")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let usage: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(usage["total_bytes"], 12);
+    assert_eq!(usage["total_files"], 2);
+    assert_eq!(usage["by_directory"][0]["name"], "photos");
+    assert_eq!(usage["by_category"][0]["name"], "image");
+}
+
+#[tokio::test]
+async fn test_upload_records_sender() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_test_app(&temp_dir);
+
+    let boundary = "rustdrop-boundary";
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"hello.txt\"\r\nContent-Type: text/plain\r\n\r\nhello\r\n--{b}--\r\n",
+        b = boundary
+    );
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .header("cookie", "rustdrop_sender=Dad%27s%20phone")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let file: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file["sender"], "Dad's phone");
+
+    let request = Request::builder()
+        .uri("/api/history")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let history: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(history[0]["action"], "upload");
+    assert_eq!(history[0]["file"], "hello.txt");
+    assert_eq!(history[0]["sender"], "Dad's phone");
+}
+
+#[tokio::test]
+async fn test_kiosk_lists_recent_senders() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_test_app(&temp_dir);
+
+    let upload = |name: &str, sender: Option<&str>| {
+        let boundary = "rustdrop-boundary";
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\n\r\nphoto\r\n--{b}--\r\n",
+            b = boundary
+        );
+        let mut request = Request::builder()
+            .method("POST")
+            .uri("/api/files")
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary));
+        if let Some(sender) = sender {
+            request = request.header("cookie", format!("rustdrop_sender={}", sender));
+        }
+        request.body(Body::from(body)).unwrap()
+    };
+    for (name, sender) in [("a.jpg", Some("Alex")), ("b.jpg", None), ("c.jpg", Some("Sam")), ("d.jpg", Some("Alex"))] {
+        let response = app.clone().oneshot(upload(name, sender)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/kiosk").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(Request::builder().uri("/api/kiosk/senders").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let senders: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(senders.as_array().unwrap().len(), 2);
+    assert_eq!(senders[0]["name"], "Alex");
+    assert_eq!(senders[0]["files"], 2);
+    assert_eq!(senders[1]["name"], "Sam");
+    assert!(senders[0].get("file").is_none());
+}
+
+#[tokio::test]
+async fn test_per_sender_upload_directories() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.files.per_sender_subdirs = true;
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config);
+    let app = create_router(state);
+
+    let boundary = "rustdrop-boundary";
+    let upload = |cookie: Option<&str>| {
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"photo.jpg\"\r\nContent-Type: image/jpeg\r\n\r\njpeg\r\n--{b}--\r\n",
+            b = boundary
+        );
+        let mut builder = Request::builder()
+            .method("POST")
+            .uri("/api/files")
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary));
+        if let Some(cookie) = cookie {
+            builder = builder.header("cookie", cookie);
+        }
+        builder.body(Body::from(body)).unwrap()
+    };
+
+    let response = app.clone().oneshot(upload(Some("rustdrop_sender=Alex"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let file: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file["name"], "inbox/Alex/photo.jpg");
+    assert!(temp_dir.path().join("inbox/Alex/photo.jpg").exists());
+
+    // Anonymous uploads stay at the top of the share
+    let response = app.clone().oneshot(upload(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(temp_dir.path().join("photo.jpg").exists());
+
+    // Both copies are listed, nested files by their relative path
+    let request = Request::builder()
+        .uri("/api/files")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let files: Value = serde_json::from_slice(&body).unwrap();
+    let names: Vec<_> = files.as_array().unwrap().iter().map(|f| f["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["inbox/Alex/photo.jpg", "photo.jpg"]);
+}
+
+#[tokio::test]
+async fn test_share_size_limit() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("old.txt"), "0123456789").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    std::fs::write(temp_dir.path().join("new.txt"), "0123456789").unwrap();
+    let app_with = |evict_oldest: bool| {
+        let mut config = AppConfig::default();
+        let share = ShareConfig { max_size: Some(25), evict_oldest };
+        config.shares.insert(instance_label(temp_dir.path()), share);
+        create_router(AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config))
+    };
+    let start_upload = || {
+        Request::builder()
+            .method("POST")
+            .uri("/api/uploads")
+            .header("content-type", "application/json")
+            .header("x-rustdrop-device", "9d8c7b6a-5f4e-4d3c-8b2a-1f0e9d8c7b6a")
+            .body(Body::from(serde_json::json!({ "name": "photo.jpg", "size": 10 }).to_string()))
+            .unwrap()
+    };
+
+    // Without eviction, uploads that do not fit are refused up front
+    let response = app_with(false).oneshot(start_upload()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let error: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["max_size"], 25);
+
+    // With eviction, an upload that is started but never sent removes nothing
+    let response = app_with(true).oneshot(start_upload()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(temp_dir.path().join("old.txt").exists());
+
+    // With eviction, the file received longest ago makes way
+    let boundary = "rustdrop-boundary";
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"photo.jpg\"\r\nContent-Type: image/jpeg\r\n\r\n0123456789\r\n--{b}--\r\n",
+        b = boundary
+    );
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .body(Body::from(body))
+        .unwrap();
+    let response = app_with(true).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!temp_dir.path().join("old.txt").exists());
+    assert!(temp_dir.path().join("new.txt").exists());
+    assert!(temp_dir.path().join("photo.jpg").exists());
+}
+
+#[tokio::test]
+async fn test_upload_larger_than_limit() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.server.max_file_size = 1000;
+    let app = create_router(AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config));
+
+    let boundary = "rustdrop-boundary";
+    let upload = |size: usize| {
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"big.bin\"\r\nContent-Type: application/octet-stream\r\n\r\n{data}\r\n--{b}--\r\n",
+            b = boundary,
+            data = "x".repeat(size)
+        );
+        Request::builder()
+            .method("POST")
+            .uri("/api/files")
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(upload(5000)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(!temp_dir.path().join("big.bin").exists());
+
+    let response = app.oneshot(upload(500)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(std::fs::metadata(temp_dir.path().join("big.bin")).unwrap().len(), 500);
+}
+
+async fn spawn_server(directory: &std::path::Path) -> String {
+    spawn_app(create_routes(directory.to_path_buf(), DeviceInfo::new(0), 1024 * 1024)).await
+}
+
+async fn spawn_app(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+            .await
+            .unwrap();
+    });
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn test_two_way_sync() {
+    let local = TempDir::new().unwrap();
+    let remote = TempDir::new().unwrap();
+    std::fs::write(local.path().join("local.txt"), "from local").unwrap();
+    std::fs::create_dir(remote.path().join("docs")).unwrap();
+    std::fs::write(remote.path().join("docs/remote.txt"), "from remote").unwrap();
+
+    let client = PeerClient::new(&spawn_server(remote.path()).await).unwrap();
+
+    // First sync copies everything both ways
+    sync_directory(&client, local.path(), SyncOptions::default()).await.unwrap();
+    assert_eq!(std::fs::read_to_string(local.path().join("docs/remote.txt")).unwrap(), "from remote");
+    assert_eq!(std::fs::read_to_string(remote.path().join("local.txt")).unwrap(), "from local");
+
+    // Nothing left to do afterwards
+    let actions = sync_directory(&client, local.path(), SyncOptions { dry_run: true, ..Default::default() }).await.unwrap();
+    assert!(actions.is_empty(), "unexpected actions: {:?}", actions);
+
+    // A local rename and a remote deletion are propagated
+    std::fs::rename(local.path().join("local.txt"), local.path().join("renamed.txt")).unwrap();
+    std::fs::remove_file(remote.path().join("docs/remote.txt")).unwrap();
+    let actions = sync_directory(&client, local.path(), SyncOptions::default()).await.unwrap();
+    assert_eq!(
+        actions,
+        vec![
+            SyncAction::RenameRemote { from: "local.txt".to_string(), to: "renamed.txt".to_string() },
+            SyncAction::DeleteLocal("docs/remote.txt".to_string()),
+        ]
+    );
+    assert!(remote.path().join("renamed.txt").exists());
+    assert!(!remote.path().join("local.txt").exists());
+    assert!(!local.path().join("docs/remote.txt").exists());
+
+    // Changes on both sides keep both versions when asked to
+    std::fs::write(local.path().join("renamed.txt"), "edited locally").unwrap();
+    std::fs::write(remote.path().join("renamed.txt"), "edited remotely").unwrap();
+    sync_directory(&client, local.path(), SyncOptions { conflict_policy: ConflictPolicy::KeepBoth, ..Default::default() }).await.unwrap();
+    assert_eq!(std::fs::read_to_string(local.path().join("renamed.txt")).unwrap(), "edited remotely");
+
+    let copies: Vec<_> = list_directory(remote.path())
+        .unwrap()
+        .into_iter()
+        .filter(|f| f.name.contains("conflicted copy"))
+        .collect();
+    assert_eq!(copies.len(), 1);
+    assert_eq!(std::fs::read_to_string(&copies[0].path).unwrap(), "edited locally");
+}
+
+#[tokio::test]
+async fn test_push_only_sync_leaves_remote_changes() {
+    let local = TempDir::new().unwrap();
+    let remote = TempDir::new().unwrap();
+    std::fs::write(local.path().join("mine.txt"), "local").unwrap();
+    std::fs::write(remote.path().join("theirs.txt"), "remote").unwrap();
+
+    let client = PeerClient::new(&spawn_server(remote.path()).await).unwrap();
+    let options = SyncOptions { push_only: true, ..Default::default() };
+
+    let actions = sync_directory(&client, local.path(), options).await.unwrap();
+    assert_eq!(actions, vec![SyncAction::Upload("mine.txt".to_string())]);
+    assert!(remote.path().join("mine.txt").exists());
+    assert!(!local.path().join("theirs.txt").exists());
+}
+
+#[tokio::test]
+async fn test_resumable_upload_follows_device_id() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_test_app(&temp_dir);
+    let phone = "0b7f6c1e-8d4a-4e8a-9a57-3f1f2d6b9c01";
+    let laptop = "5a2c7d9e-1f3b-4c6d-8e0a-7b9c1d2e3f40";
+
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+    let chunk = |device: &str, id: &str, offset: u64, data: &'static str| {
+        Request::builder()
+            .method("PATCH")
+            .uri(format!("/api/uploads/{}", id))
+            .header("x-rustdrop-device", device)
+            .header("upload-offset", offset)
+            .body(Body::from(data))
+            .unwrap()
+    };
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/uploads")
+        .header("x-rustdrop-device", phone)
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"name": "clip.mp4", "size": 10}"#))
+        .unwrap();
+    let (status, session) = send(&app, request).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = session["id"].as_str().unwrap().to_string();
+
+    let (status, progress) = send(&app, chunk(phone, &id, 0, "hello")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(progress["offset"], 5);
+
+    // Another device cannot see or continue the session
+    let (status, _) = send(&app, chunk(laptop, &id, 5, "world")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // The phone reconnects (new address, no cookie) and retries a chunk it already sent
+    let (status, progress) = send(&app, chunk(phone, &id, 0, "hello")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(progress["offset"], 5);
+
+    let request = Request::builder()
+        .uri("/api/uploads")
+        .header("x-rustdrop-device", phone)
+        .body(Body::empty())
+        .unwrap();
+    let (_, sessions) = send(&app, request).await;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+
+    let (status, progress) = send(&app, chunk(phone, &id, 5, "world")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(progress["complete"], true);
+    assert_eq!(progress["file"]["name"], "clip.mp4");
+    assert_eq!(std::fs::read(temp_dir.path().join("clip.mp4")).unwrap(), b"helloworld");
+
+    // Finished sessions are gone
+    let (status, _) = send(&app, chunk(phone, &id, 10, "")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_cancel_resumable_upload() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_test_app(&temp_dir);
+    let phone = "0b7f6c1e-8d4a-4e8a-9a57-3f1f2d6b9c01";
+    let laptop = "5a2c7d9e-1f3b-4c6d-8e0a-7b9c1d2e3f40";
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/uploads")
+        .header("x-rustdrop-device", phone)
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"name": "clip.mp4", "size": 10}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let id = serde_json::from_slice::<Value>(&body).unwrap()["id"].as_str().unwrap().to_string();
+
+    let request = Request::builder()
+        .method("PATCH")
+        .uri(format!("/api/uploads/{}", id))
+        .header("x-rustdrop-device", phone)
+        .header("upload-offset", 0)
+        .body(Body::from("hello"))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    let part = temp_dir.path().join(".rustdrop/uploads").join(format!("{}.part", id));
+    assert!(part.exists());
+
+    let cancel = |device: &str| {
+        Request::builder()
+            .method("DELETE")
+            .uri(format!("/api/uploads/{}", id))
+            .header("x-rustdrop-device", device)
+            .body(Body::empty())
+            .unwrap()
+    };
+    // Only the owner can cancel
+    assert_eq!(app.clone().oneshot(cancel(laptop)).await.unwrap().status(), StatusCode::NOT_FOUND);
+    assert!(part.exists());
+
+    assert_eq!(app.clone().oneshot(cancel(phone)).await.unwrap().status(), StatusCode::NO_CONTENT);
+    assert!(!part.exists());
+    assert_eq!(app.clone().oneshot(cancel(phone)).await.unwrap().status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_tus_uploads_resume() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("clip.mp4"), "an older clip").unwrap();
+    // Wrapped like in the actual server, so tus discovery gets past the CORS layer
+    let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+    let cors = CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any);
+    let app = create_router(state.clone())
+        .layer(cors)
+        .layer(axum::middleware::from_fn_with_state(state, describe_uploads));
+    let phone = "0b7f6c1e-8d4a-4e8a-9a57-3f1f2d6b9c01";
+    let tus = |method: &str, uri: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("tus-resumable", "1.0.0")
+            .header("x-rustdrop-device", phone)
+    };
+
+    let response = app.clone().oneshot(tus("OPTIONS", "/api/uploads").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()["tus-version"], "1.0.0");
+    assert!(response.headers()["tus-extension"].to_str().unwrap().contains("creation"));
+
+    // filename "clip.mp4"
+    let request = tus("POST", "/api/uploads")
+        .header("upload-length", "10")
+        .header("upload-metadata", "filename Y2xpcC5tcDQ=")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["tus-resumable"], "1.0.0");
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+    assert!(location.starts_with("/api/uploads/"));
+
+    let chunk = |offset: u64, data: &'static str| {
+        tus("PATCH", &location)
+            .header("content-type", "application/offset+octet-stream")
+            .header("upload-offset", offset)
+            .body(Body::from(data))
+            .unwrap()
+    };
+    let response = app.clone().oneshot(chunk(0, "hello")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()["upload-offset"], "5");
+
+    // The connection dropped, the client asks where to continue
+    let response = app.clone().oneshot(tus("HEAD", &location).body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["upload-offset"], "5");
+    assert_eq!(response.headers()["upload-length"], "10");
+    assert_eq!(response.headers()["cache-control"], "no-store");
+
+    let response = app.clone().oneshot(chunk(0, "hello")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = app.clone().oneshot(chunk(5, "world")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()["upload-offset"], "10");
+
+    // tus clients cannot answer a conflict, so both files are kept
+    assert_eq!(std::fs::read(temp_dir.path().join("clip.mp4")).unwrap(), b"an older clip");
+    assert_eq!(std::fs::read(temp_dir.path().join("clip (2).mp4")).unwrap(), b"helloworld");
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/uploads")
+        .header("tus-resumable", "0.2.2")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(response.headers()["tus-version"], "1.0.0");
+}
+
+#[tokio::test]
+async fn test_quic_info_absent_when_disabled() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_test_app(&temp_dir);
+
+    let request = Request::builder()
+        .uri("/api/quic")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_send_to_several_peers() {
+    let first = TempDir::new().unwrap();
+    let second = TempDir::new().unwrap();
+
+    let clients = vec![
+        PeerClient::new(&spawn_server(first.path()).await).unwrap(),
+        PeerClient::new(&spawn_server(second.path()).await).unwrap(),
+        // Nothing listens here, the other peers must still get the files
+        PeerClient::new("127.0.0.1:9").unwrap(),
+    ];
+    let modified = "2021-05-04T09:15:00Z".parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+    let files = vec![
+        OutgoingFile {
+            path: "agenda.txt".to_string(),
+            data: b"1. welcome".to_vec(),
+            attributes: FileAttributes { modified: Some(modified), ..Default::default() },
+        },
+        OutgoingFile {
+            path: "slides/deck.txt".to_string(),
+            data: b"slide one".to_vec(),
+            attributes: FileAttributes::default(),
+        },
+    ];
+
+    let (progress, transfer) = send_to_peers(clients, files);
+    let outcomes = transfer.await;
+
+    assert_eq!(outcomes.len(), 3);
+    assert_eq!(outcomes[0].result.as_ref().unwrap(), &2);
+    assert_eq!(outcomes[1].result.as_ref().unwrap(), &2);
+    assert!(outcomes[2].result.is_err());
+    assert_eq!(progress.finished(), 3);
+    assert_eq!(progress.sent(), 2 * 19);
+
+    for share in [&first, &second] {
+        assert_eq!(std::fs::read(share.path().join("agenda.txt")).unwrap(), b"1. welcome");
+        assert_eq!(std::fs::read(share.path().join("slides/deck.txt")).unwrap(), b"slide one");
+        let agenda = std::fs::metadata(share.path().join("agenda.txt")).unwrap();
+        assert_eq!(chrono::DateTime::<chrono::Utc>::from(agenda.modified().unwrap()), modified);
+    }
+}
+
+#[tokio::test]
+async fn test_collect_with_owner_approval() {
+    let phone = TempDir::new().unwrap();
+    std::fs::create_dir(phone.path().join("trip")).unwrap();
+    std::fs::write(phone.path().join("trip/beach.jpg"), "beach").unwrap();
+    std::fs::write(phone.path().join("notes.txt"), "private").unwrap();
+    let into = TempDir::new().unwrap();
+
+    let url = spawn_server(phone.path()).await;
+    let client = PeerClient::new(&url).unwrap();
+
+    // The collecting device cannot approve its own request
+    let request = client.request_collection("Laptop", "*.txt").await.unwrap();
+    let status = reqwest::Client::new()
+        .post(format!("{}/api/collect/{}/decision", url, request.id))
+        .header("x-rustdrop-device", rustdrop::core::identity::client_token())
+        .json(&serde_json::json!({ "approve": true }))
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let collect = tokio::spawn({
+        let into = into.path().to_path_buf();
+        async move {
+            collect_from_peers(vec![client], "Laptop", "*.jpg", &into, Duration::from_secs(10)).await
+        }
+    });
+
+    // The owner answers from the web interface, a different client
+    let owner = reqwest::Client::new();
+    let pending = loop {
+        let pending: Vec<Value> = owner
+            .get(format!("{}/api/collect", url))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if let Some(request) = pending.into_iter().find(|r| r["pattern"] == "*.jpg") {
+            break request;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    assert_eq!(pending["requester"], "Laptop");
+
+    let response = owner
+        .post(format!("{}/api/collect/{}/decision", url, pending["id"].as_str().unwrap()))
+        .json(&serde_json::json!({ "approve": true }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let outcomes = collect.await.unwrap();
+    let saved = outcomes[0].result.as_ref().unwrap();
+    assert_eq!(saved.len(), 1);
+
+    let device_dir = std::fs::read_dir(into.path()).unwrap().next().unwrap().unwrap().path();
+    assert_eq!(std::fs::read(device_dir.join("trip/beach.jpg")).unwrap(), b"beach");
+    assert!(!device_dir.join("notes.txt").exists());
+}
+
+#[tokio::test]
+async fn test_access_code_required_for_new_clients() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.access.require_code = true;
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(0), config);
+    let app = create_router(state.clone());
+    let phone = "5f0c6c1e-8d0a-4a53-9a77-2f0a4c3b9e11";
+
+    let get = |uri: &str| {
+        Request::builder()
+            .uri(uri)
+            .header("x-rustdrop-device", phone)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // Unknown clients see neither the API nor the web UI, but can still check health
+    let response = app.clone().oneshot(get("/api/files")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app.clone().oneshot(get("/")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app.clone().oneshot(get("/api/health")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let submit = |code: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/api/access")
+            .header("x-rustdrop-device", phone)
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({ "code": code }).to_string()))
+            .unwrap()
+    };
+
+    let (code, _) = state.access.current_code(chrono::Utc::now()).unwrap();
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    let response = app.clone().oneshot(submit(wrong)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app.clone().oneshot(submit(&code)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(get("/api/files")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.oneshot(get("/api/access")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let status: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(status["required"], true);
+    assert_eq!(status["approved"], true);
+}
+
+#[tokio::test]
+async fn test_pin_sets_up_a_session_cookie() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.security.pin = Some("1234".to_string());
+    let app = create_router(AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(0), config));
+
+    // A browser gets its session cookie along with the login page
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let cookie = response.headers()["set-cookie"].to_str().unwrap().split(';').next().unwrap().to_string();
+
+    let get = |uri: &str| {
+        Request::builder()
+            .uri(uri)
+            .header("cookie", &cookie)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let submit = |pin: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/api/access")
+            .header("cookie", &cookie)
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({ "code": pin }).to_string()))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(get("/api/files")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app.clone().oneshot(get("/api/access")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let status: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(status["pin"], true);
+
+    let response = app.clone().oneshot(submit("4321")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app.clone().oneshot(submit("1234")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app.clone().oneshot(get("/api/files")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.oneshot(get("/")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_welcome_page_open_without_access_code() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.access.require_code = true;
+    config.ui.network_name = Some("Home".to_string());
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config);
+    let app = create_router(state);
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/welcome").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(Request::builder().uri("/api/network").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let network: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(network["network_name"], "Home");
+    assert!(network["url"].as_str().unwrap().ends_with(":8080"));
+    assert!(network["addresses"].is_array());
+}
+
+#[tokio::test]
+async fn test_jump_codes_redirect_to_local_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_test_app(&temp_dir);
+
+    let create = |target: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/api/jump")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({ "target": target }).to_string()))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(create("https://example.com/")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app.clone().oneshot(create("/welcome")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let jump: Value = serde_json::from_slice(&body).unwrap();
+    let url = jump["url"].as_str().unwrap();
+    assert!(url.starts_with("/j/"));
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(url).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(response.headers()["location"], "/welcome");
+
+    let response = app
+        .oneshot(Request::builder().uri("/j/nope").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_public_gallery_shows_only_published_files() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("beach.jpg"), "photo").unwrap();
+    std::fs::write(temp_dir.path().join("taxes.txt"), "secret").unwrap();
+    let beach_id = get_file_info(&temp_dir.path().join("beach.jpg")).unwrap().id.to_string();
+    let taxes_id = get_file_info(&temp_dir.path().join("taxes.txt")).unwrap().id.to_string();
+
+    let mut config = AppConfig::default();
+    config.access.require_code = true;
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config);
+    state.metadata.set_published("beach.jpg", true).unwrap();
+    let app = create_router(state);
+
+    let get = |uri: String| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/public".to_string())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(get("/api/public".to_string())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let files: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(files.as_array().unwrap().len(), 1);
+    assert_eq!(files[0]["name"], "beach.jpg");
+    assert!(files[0].get("path").is_none());
+
+    let response = app.clone().oneshot(get(format!("/public/{}", beach_id))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"photo");
+
+    // The rest of the share stays behind the access code
+    let response = app.clone().oneshot(get(format!("/public/{}", taxes_id))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = app.clone().oneshot(get(format!("/api/files/{}", taxes_id))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri(format!("/api/files/{}/publish", taxes_id))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_closed_outside_scheduled_hours() {
+    let temp_dir = TempDir::new().unwrap();
+    // A window that starts an hour from now is closed now, whatever time the test runs
+    let now = chrono::Local::now();
+    let window = format!(
+        "{}-{}",
+        (now + chrono::Duration::hours(1)).format("%H:%M"),
+        (now + chrono::Duration::hours(2)).format("%H:%M")
+    );
+    let mut config = AppConfig::default();
+    config.server.schedule = vec![window];
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config);
+    let app = create_router(state);
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("come back"));
+
+    let response = app
+        .oneshot(Request::builder().uri("/api/files").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+}
+
+#[tokio::test]
+async fn test_list_and_revoke_clients() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let peer = PeerClient::with_token(&url, "0b7a4a9e-3f7e-4f57-9d3c-6d1b8c2f5a10").unwrap();
+    peer.list_files().await.unwrap();
+
+    let host = reqwest::Client::new();
+    let clients: Value = host
+        .get(format!("{}/api/clients", url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let peer_entry = clients
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|client| client["id"] == client_handle("0b7a4a9e-3f7e-4f57-9d3c-6d1b8c2f5a10"))
+        .unwrap();
+    assert!(!clients.to_string().contains("0b7a4a9e-3f7e-4f57-9d3c-6d1b8c2f5a10"));
+    assert_eq!(peer_entry["ip"], "127.0.0.1");
+    assert_eq!(peer_entry["current"], false);
+    assert!(peer_entry["bytes_sent"].as_u64().unwrap() > 0);
+    // Peers identify themselves, and the host's own request is from another kind of client
+    assert_eq!(peer_entry["kind"], "peer");
+    assert_eq!(peer_entry["peer"]["version"], rustdrop::VERSION);
+    assert!(peer_entry["user_agent"].as_str().unwrap().starts_with("RustDrop/"));
+    // Requests without a cookie, like the host's own, leave no session behind
+    assert_eq!(clients.as_array().unwrap().len(), 1);
+
+    let response = host
+        .delete(format!("{}/api/clients/{}", url, client_handle("0b7a4a9e-3f7e-4f57-9d3c-6d1b8c2f5a10")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+    // The revoked token no longer works
+    assert!(peer.list_files().await.is_err());
+}
+
+#[tokio::test]
+async fn test_forwarded_for_only_from_trusted_proxies() {
+    let seen_ip = |trusted: Vec<String>| async move {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = AppConfig::default();
+        config.server.trusted_proxies = trusted;
+        let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(0), config);
+        let url = spawn_app(create_router(state.clone())).await;
+
+        reqwest::Client::new()
+            .get(format!("{}/api/health", url))
+            .header("x-forwarded-for", "192.168.7.7")
+            .header("x-rustdrop-device", "9a1d2c3b-4e5f-4a6b-8c7d-0e1f2a3b4c5d")
+            .send()
+            .await
+            .unwrap();
+        state.clients.list()[0].ip.clone().unwrap()
+    };
+
+    assert_eq!(seen_ip(Vec::new()).await, "127.0.0.1");
+    assert_eq!(seen_ip(vec!["127.0.0.0/8".to_string()]).await, "192.168.7.7");
+}
+
+#[tokio::test]
+async fn test_unexpected_access_posts_alert_to_webhook() {
+    let (sender, mut received) = tokio::sync::mpsc::unbounded_channel::<Value>();
+    let webhook = Router::new().route(
+        "/alerts",
+        axum::routing::post(move |axum::Json(alert): axum::Json<Value>| {
+            let sender = sender.clone();
+            async move {
+                sender.send(alert).unwrap();
+            }
+        }),
+    );
+    let webhook_url = spawn_app(webhook).await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.server.trusted_proxies = vec!["127.0.0.0/8".to_string()];
+    config.alerts.expected_networks = vec!["192.168.1.0/24".to_string()];
+    config.alerts.bell = false;
+    config.alerts.webhook = Some(format!("{}/alerts", webhook_url));
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(0), config);
+    let url = spawn_app(create_router(state)).await;
+
+    let client = reqwest::Client::new();
+    for from in ["192.168.1.20", "10.0.0.7", "10.0.0.7"] {
+        client
+            .get(format!("{}/api/files", url))
+            .header("x-forwarded-for", from)
+            .header("x-rustdrop-device", "9a1d2c3b-4e5f-4a6b-8c7d-0e1f2a3b4c5d")
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let alert = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+    assert_eq!(alert["reason"], "unexpected_network");
+    assert_eq!(alert["ip"], "10.0.0.7");
+    assert_eq!(alert["path"], "/api/files");
+    // The repeated request does not raise a second alert
+    assert!(tokio::time::timeout(Duration::from_millis(300), received.recv()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_hidden_files_only_listed_on_request() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("photo.jpg"), "photo").unwrap();
+    std::fs::write(temp_dir.path().join(".DS_Store"), "finder").unwrap();
+    std::fs::write(temp_dir.path().join("Thumbs.db"), "thumbs").unwrap();
+    std::fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    std::fs::write(temp_dir.path().join(".git/HEAD"), "ref").unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+
+    let names = |query: &'static str| {
+        let url = url.clone();
+        async move {
+            let files: Vec<Value> = reqwest::get(format!("{}/api/files{}", url, query))
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            files.iter().map(|f| f["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(names("").await, vec!["photo.jpg"]);
+    assert_eq!(
+        names("?hidden=true").await,
+        vec![".DS_Store", ".git/HEAD", "Thumbs.db", "photo.jpg"]
+    );
+}
+
+#[tokio::test]
+async fn test_unicode_names_normalized_and_downloadable() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_test_app(&temp_dir);
+
+    // "Café 🎉.txt" with a decomposed é, as macOS sends it
+    let boundary = "rustdrop-boundary";
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"Cafe\u{301} \u{1F389}.txt\"\r\nContent-Type: text/plain\r\n\r\nhello\r\n--{b}--\r\n",
+        b = boundary
+    );
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let file: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file["name"], "Caf\u{e9} \u{1F389}.txt");
+    assert!(temp_dir.path().join("Caf\u{e9} \u{1F389}.txt").exists());
+
+    let request = Request::builder()
+        .uri(format!("/api/files/{}", file["id"].as_str().unwrap()))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"Caf_ _.txt\"; filename*=UTF-8''Caf%C3%A9%20%F0%9F%8E%89.txt"
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_permissions_restored_only_when_enabled() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode_after_send = |preserve: bool| async move {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = AppConfig::default();
+        config.files.preserve_permissions = preserve;
+        let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(0), config);
+        let client = PeerClient::new(&spawn_app(create_router(state)).await).unwrap();
+
+        let attributes = FileAttributes { mode: Some(0o755), ..Default::default() };
+        client.upload("build.sh", b"#!/bin/sh".to_vec(), attributes).await.unwrap();
+        let metadata = std::fs::metadata(temp_dir.path().join("build.sh")).unwrap();
+        metadata.permissions().mode() & 0o777
+    };
+
+    assert_eq!(mode_after_send(true).await, 0o755);
+    assert_ne!(mode_after_send(false).await & 0o111, 0o111);
+}
+
+#[tokio::test]
+async fn test_sparse_upload_skips_zeroes() {
+    let temp_dir = TempDir::new().unwrap();
+    let client = PeerClient::new(&spawn_server(temp_dir.path()).await).unwrap();
+
+    // A disk image with a little data at the start and end and a large hole between
+    let mut image = vec![0u8; 900 * 1024];
+    image[..4].copy_from_slice(b"boot");
+    let tail = image.len() - 3;
+    image[tail..].copy_from_slice(b"end");
+
+    let file = client.upload("disk.img", image.clone(), FileAttributes::default()).await.unwrap();
+    assert_eq!(file.size, image.len() as u64);
+    assert_eq!(std::fs::read(temp_dir.path().join("disk.img")).unwrap(), image);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_xattrs_travel_when_preserved() {
+    use rustdrop::utils::xattr::{read_xattrs, write_xattrs};
+    use std::collections::BTreeMap;
+
+    let xattrs = BTreeMap::from([("user.rustdrop.label".to_string(), b"red".to_vec())]);
+    let probe = TempDir::new().unwrap();
+    let probe_file = probe.path().join("probe");
+    std::fs::write(&probe_file, "").unwrap();
+    if write_xattrs(&probe_file, &xattrs).is_err() {
+        // The temporary directory's file system has no extended attributes
+        return;
+    }
+
+    let xattrs_after_send = |preserve: bool| {
+        let xattrs = xattrs.clone();
+        async move {
+            let temp_dir = TempDir::new().unwrap();
+            let mut config = AppConfig::default();
+            config.files.preserve_xattrs = preserve;
+            let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(0), config);
+            let client = PeerClient::new(&spawn_app(create_router(state)).await).unwrap();
+
+            let attributes = FileAttributes { xattrs, ..Default::default() };
+            let file = client.upload("tagged.txt", b"tagged".to_vec(), attributes).await.unwrap();
+            let stored = read_xattrs(&temp_dir.path().join("tagged.txt")).unwrap();
+            let served = client.attributes(&file.id.to_string()).await.unwrap().xattrs;
+            assert_eq!(stored, served);
+            stored
+        }
+    };
+
+    assert_eq!(xattrs_after_send(true).await, xattrs);
+    assert!(xattrs_after_send(false).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_encrypted_share_link_without_access_code() {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+    use rustdrop::core::links::{derive_key, record_nonce, NONCE_PREFIX_LEN, RECORD_SIZE, TAG_LEN};
+
+    let temp_dir = TempDir::new().unwrap();
+    let data: Vec<u8> = (0..RECORD_SIZE + 100).map(|i| (i % 251) as u8).collect();
+    std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+    std::fs::write(temp_dir.path().join("docs/secret.bin"), &data).unwrap();
+
+    let mut config = AppConfig::default();
+    config.access.require_code = true;
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(0), config);
+    let link = state.links.create("docs/secret.bin", "correct horse", None, 1000).unwrap();
+    let app = create_router(state);
+
+    let request = |method: &str, uri: String| Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request("GET", format!("/s/{}", link.token))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(request("GET", format!("/api/links/{}", link.token))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let info: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(info["name"], "secret.bin");
+    assert_eq!(info["size"], data.len());
+
+    // Managing links still needs an approved client
+    let response = app.clone().oneshot(request("DELETE", format!("/api/links/{}", link.token))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app.clone().oneshot(request("GET", "/api/links".to_string())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.oneshot(request("GET", format!("/api/links/{}/content", link.token))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let stream = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(!stream.windows(16).any(|window| window == &data[..16]));
+
+    let salt = STANDARD.decode(info["salt"].as_str().unwrap()).unwrap();
+    let key = derive_key("correct horse", &salt, link.iterations);
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).unwrap());
+    let prefix: [u8; NONCE_PREFIX_LEN] = stream[..NONCE_PREFIX_LEN].try_into().unwrap();
+    let records: Vec<&[u8]> = stream[NONCE_PREFIX_LEN..].chunks(RECORD_SIZE + TAG_LEN).collect();
+    assert_eq!(records.len(), 2);
+
+    let mut plain = Vec::new();
+    for (counter, record) in records.iter().enumerate() {
+        let nonce = Nonce::assume_unique_for_key(record_nonce(&prefix, counter as u32, counter == 1));
+        let mut record = record.to_vec();
+        plain.extend_from_slice(key.open_in_place(nonce, Aad::empty(), &mut record).unwrap());
+    }
+    assert_eq!(plain, data);
+}
+
+#[tokio::test]
+async fn test_relay_streams_past_size_limit_without_storing() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
+
+    // Larger than the server's 1 MiB upload limit, which only applies to stored files
+    let data: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 253) as u8).collect();
+    let offer: Value = http
+        .post(format!("{}/api/relays", url))
+        .json(&serde_json::json!({ "name": "big.iso", "size": data.len() }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let relay_url = format!("{}/api/relays/{}", url, offer["id"].as_str().unwrap());
+
+    let waiting: Vec<Value> = http.get(format!("{}/api/relays", url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(waiting.len(), 1);
+    assert_eq!(waiting[0]["name"], "big.iso");
+
+    // The download starts before any data exists and only one device gets it
+    let response = http.get(&relay_url).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(http.get(&relay_url).send().await.unwrap().status(), reqwest::StatusCode::CONFLICT);
+
+    let upload = tokio::spawn({
+        let (http, relay_url, data) = (http.clone(), relay_url.clone(), data.clone());
+        async move { http.put(relay_url).body(data).send().await.unwrap().status() }
+    });
+    assert_eq!(response.bytes().await.unwrap().to_vec(), data);
+    assert_eq!(upload.await.unwrap(), reqwest::StatusCode::NO_CONTENT);
+
+    assert!(!temp_dir.path().join("big.iso").exists());
+    assert_eq!(http.get(&relay_url).send().await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_stream_pipes_producer_to_consumer() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
+    let stream_url = format!("{}/api/streams/backup.tar", url);
+
+    // The consumer may connect first and waits for data
+    let consumer = http.get(&stream_url).send().await.unwrap();
+    assert_eq!(consumer.status(), reqwest::StatusCode::OK);
+    assert_eq!(http.get(&stream_url).send().await.unwrap().status(), reqwest::StatusCode::CONFLICT);
+
+    let data: Vec<u8> = (0..2 * 1024 * 1024).map(|i| (i % 241) as u8).collect();
+    let producer = tokio::spawn({
+        let (http, stream_url, data) = (http.clone(), stream_url.clone(), data.clone());
+        async move { http.post(stream_url).body(data).send().await.unwrap().status() }
+    });
+
+    assert_eq!(consumer.bytes().await.unwrap().to_vec(), data);
+    assert!(producer.await.unwrap().is_success());
+
+    let response = http.get(format!("{}/api/streams/no%20spaces", url)).send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_tail_follows_appended_lines() {
+    let temp_dir = TempDir::new().unwrap();
+    let log_path = temp_dir.path().join("build.log");
+    std::fs::write(&log_path, "compiling a\ncompiling b\ncompiling c\n").unwrap();
+    let id = get_file_info(&log_path).unwrap().id;
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
+
+    let body = http
+        .get(format!("{}/api/files/{}/tail?lines=2", url, id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(body, "data: compiling b\n\ndata: compiling c\nid: 36\n\n");
+
+    let mut response = http
+        .get(format!("{}/api/files/{}/tail?follow=1&lines=1", url, id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    let mut received = String::new();
+    let mut next_events = async |received: &mut String, until: &str| {
+        while !received.contains(until) {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk()).await.unwrap();
+            received.push_str(&String::from_utf8_lossy(&chunk.unwrap().unwrap()));
+        }
+    };
+    next_events(&mut received, "compiling c").await;
+
+    let mut log = std::fs::OpenOptions::new().append(true).open(&log_path).unwrap();
+    log.write_all(b"finished\n").unwrap();
+    next_events(&mut received, "data: finished").await;
+    assert!(!received.contains("compiling b"));
+
+    // Reconnecting with the last event id only sends what came after it
+    let body = http
+        .get(format!("{}/api/files/{}/tail", url, id))
+        .header("last-event-id", "36")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(body, "");
+}
+
+#[tokio::test]
+async fn test_events_stream_announces_offers_and_requests() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
 
-    // Check content
-    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+    let mut events = http.get(format!("{}/api/events", url)).send().await.unwrap();
+    assert_eq!(events.headers()["content-type"], "text/event-stream");
+
+    http.post(format!("{}/api/relays", url))
+        .json(&serde_json::json!({ "name": "movie.mkv", "size": 10 }))
+        .send()
         .await
         .unwrap();
-    let downloaded_content = String::from_utf8(body.to_vec()).unwrap();
-    assert_eq!(downloaded_content, file_content);
+    http.post(format!("{}/api/collect", url))
+        .json(&serde_json::json!({ "requester": "Laptop", "pattern": "*.jpg" }))
+        .send()
+        .await
+        .unwrap();
+
+    let mut received = String::new();
+    while !received.contains("event: collect_requested") {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), events.chunk()).await.unwrap();
+        received.push_str(&String::from_utf8_lossy(&chunk.unwrap().unwrap()));
+    }
+    assert!(received.contains("event: relay_offered\ndata: {\"type\":\"relay_offered\""));
+    assert!(received.contains("\"name\":\"movie.mkv\""));
+    assert!(received.contains("\"pattern\":\"*.jpg\""));
+}
+
+#[cfg(feature = "grpc")]
+#[tokio::test]
+async fn test_grpc_shares_the_web_server_port() {
+    use prost::Message;
+    use rustdrop::grpc::proto::{Device, GetDeviceRequest, ListFilesRequest, ListFilesResponse};
+
+    async fn call<Req: Message, Res: Message + Default>(http: &reqwest::Client, url: &str, method: &str, request: Req) -> Res {
+        let message = request.encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&message);
+
+        let response = http
+            .post(format!("{}/rustdrop.v1.RustDrop/{}", url, method))
+            .header("content-type", "application/grpc")
+            .header("te", "trailers")
+            .body(frame)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body = response.bytes().await.unwrap();
+        Res::decode(&body[5..]).unwrap()
+    }
+
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("notes.txt"), "hello").unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::builder().http2_prior_knowledge().build().unwrap();
+
+    let device: Device = call(&http, &url, "GetDevice", GetDeviceRequest {}).await;
+    assert_eq!(device.version, rustdrop::VERSION);
+
+    let listing: ListFilesResponse = call(&http, &url, "ListFiles", ListFilesRequest::default()).await;
+    assert_eq!(listing.files.len(), 1);
+    assert_eq!(listing.files[0].name, "notes.txt");
+    assert_eq!(listing.files[0].size, 5);
+}
+
+#[cfg(feature = "ipp")]
+#[tokio::test]
+async fn test_printing_without_access_code() {
+    use rustdrop::ipp::message::{group, Attribute, Group, Request as IppRequest, Value};
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.access.require_code = true;
+    config.printer.enabled = true;
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config);
+    let app = create_router(state);
+
+    let job = IppRequest {
+        version: (2, 0),
+        operation: 0x0002,
+        request_id: 42,
+        groups: vec![Group {
+            tag: group::OPERATION,
+            attributes: vec![
+                Attribute::new("attributes-charset", Value::Charset("utf-8".to_string())),
+                Attribute::new("attributes-natural-language", Value::Language("en".to_string())),
+                Attribute::new("job-name", Value::Name("Invoice".to_string())),
+            ],
+        }],
+        document: b"%PDF-1.4 invoice".to_vec(),
+    };
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/ipp/print")
+                .header("content-type", "application/ipp")
+                .body(Body::from(job.encode()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/ipp");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    // Successful status and the request id echoed back
+    assert_eq!(&body[..8], &[2, 0, 0, 0, 0, 0, 0, 42]);
+    assert!(temp_dir.path().join("Printed").join("Invoice.pdf").exists());
+}
+
+#[cfg(feature = "email")]
+#[tokio::test]
+async fn test_email_attachments_are_saved() {
+    use rustdrop::email::EmailGateway;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.email.enabled = true;
+    config.email.address = Some("drop@example.com".to_string());
+    let state = AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config);
+    let gateway = EmailGateway::bind("127.0.0.1:0".parse().unwrap(), state.clone()).await.unwrap();
+    let addr = gateway.local_addr().unwrap();
+    tokio::spawn(gateway.run());
+
+    /// Send a command and read the last line of the reply, which has a space after the code
+    async fn exchange(stream: &mut BufReader<tokio::net::TcpStream>, command: &str) -> String {
+        stream.get_mut().write_all(command.as_bytes()).await.unwrap();
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return line;
+            }
+        }
+    }
+
+    let stream = &mut BufReader::new(tokio::net::TcpStream::connect(addr).await.unwrap());
+    assert!(exchange(stream, "").await.starts_with("220 "));
+    assert!(exchange(stream, "EHLO scanner\r\n").await.starts_with("250 "));
+    assert!(exchange(stream, "MAIL FROM:<scanner@example.com>\r\n").await.starts_with("250 "));
+    assert!(exchange(stream, "RCPT TO:<someone@example.com>\r\n").await.starts_with("550 "));
+    assert!(exchange(stream, "RCPT TO:<Drop@example.com>\r\n").await.starts_with("250 "));
+    assert!(exchange(stream, "DATA\r\n").await.starts_with("354 "));
+    let reply = exchange(
+        stream,
+        "From: Office Scanner <scanner@example.com>\r\n\
+Content-Type: multipart/mixed; boundary=b\r\n\
+\r\n\
+--b\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+..Scanned document attached.\r\n\
+--b\r\n\
+Content-Type: application/pdf; name=scan.pdf\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0xLjQgc2Nhbg==\r\n\
+--b--\r\n\
+.\r\n",
+    )
+    .await;
+    assert!(reply.starts_with("250 "), "{}", reply);
+    assert!(exchange(stream, "QUIT\r\n").await.starts_with("221 "));
+
+    assert_eq!(std::fs::read(temp_dir.path().join("scan.pdf")).unwrap(), b"%PDF-1.4 scan");
+    let history = state.history.recent(10);
+    assert_eq!(history[0].sender.as_deref(), Some("Office Scanner"));
+}
+
+#[tokio::test]
+async fn test_v2_pages_etags_and_idempotent_uploads() {
+    let temp_dir = TempDir::new().unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(temp_dir.path().join(name), name).unwrap();
+    }
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
+
+    let first = http.get(format!("{}/api/v2/files?limit=2", url)).send().await.unwrap();
+    let etag = first.headers()["etag"].to_str().unwrap().to_string();
+    let page: Value = first.json().await.unwrap();
+    assert_eq!(page["items"].as_array().unwrap().len(), 2);
+    assert_eq!(page["items"][1]["name"], "b.txt");
+    let unchanged = http.get(format!("{}/api/v2/files?limit=2", url)).header("if-none-match", &etag).send().await.unwrap();
+    assert_eq!(unchanged.status(), reqwest::StatusCode::NOT_MODIFIED);
+
+    // Files added before the cursor do not shift the next page
+    std::fs::write(temp_dir.path().join("0.txt"), "new").unwrap();
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let page: Value = http
+        .get(format!("{}/api/v2/files?limit=2&cursor={}", url, cursor))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["items"][0]["name"], "c.txt");
+    assert!(page.get("next_cursor").is_none());
+
+    let id = get_file_info(&temp_dir.path().join("c.txt")).unwrap().id;
+    let content = http.get(format!("{}/api/v2/files/{}/content", url, id)).send().await.unwrap();
+    let etag = content.headers()["etag"].to_str().unwrap().to_string();
+    assert_eq!(content.text().await.unwrap(), "c.txt");
+    let cached = http
+        .get(format!("{}/api/v2/files/{}/content", url, id))
+        .header("if-none-match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cached.status(), reqwest::StatusCode::NOT_MODIFIED);
+
+    let device = uuid::Uuid::new_v4().to_string();
+    let upload = || {
+        let form = reqwest::multipart::Form::new()
+            .part("file", reqwest::multipart::Part::bytes(b"retry".to_vec()).file_name("retry.txt"));
+        http.post(format!("{}/api/v2/files", url))
+            .header("x-rustdrop-device", &device)
+            .header("idempotency-key", "upload-1")
+            .multipart(form)
+            .send()
+    };
+    let stored = upload().await.unwrap();
+    assert_eq!(stored.status(), reqwest::StatusCode::CREATED);
+    assert!(stored.headers().get("idempotent-replayed").is_none());
+    let stored: Value = stored.json().await.unwrap();
+
+    let replayed = upload().await.unwrap();
+    assert_eq!(replayed.status(), reqwest::StatusCode::CREATED);
+    assert_eq!(replayed.headers()["idempotent-replayed"], "true");
+    assert_eq!(replayed.headers()["location"], format!("/api/v2/files/{}", stored["id"].as_str().unwrap()));
+    assert_eq!(replayed.json::<Value>().await.unwrap()["name"], stored["name"]);
+
+    let uploaded = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with("retry"))
+        .count();
+    assert_eq!(uploaded, 1);
+
+    // v1 is unchanged: a plain array
+    let v1: Value = http.get(format!("{}/api/files", url)).send().await.unwrap().json().await.unwrap();
+    assert!(v1.is_array());
+}
+
+#[tokio::test]
+async fn test_upload_name_conflicts() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("report.pdf"), "first draft").unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
+    let upload = |content: &'static str, conflict: &str| {
+        let form = reqwest::multipart::Form::new()
+            .part("file", reqwest::multipart::Part::bytes(content.as_bytes().to_vec()).file_name("report.pdf"));
+        http.post(format!("{}/api/files?conflict={}", url, conflict)).multipart(form).send()
+    };
+
+    let response = upload("final version", "ask").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["conflict"]["existing"]["size"], 11);
+    assert_eq!(body["conflict"]["incoming"]["size"], 13);
+    assert_ne!(body["conflict"]["existing"]["sha256"], body["conflict"]["incoming"]["sha256"]);
+    assert_eq!(std::fs::read(temp_dir.path().join("report.pdf")).unwrap(), b"first draft");
+    // The compared upload is gone again
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+
+    // Same content is no conflict
+    assert_eq!(upload("first draft", "ask").await.unwrap().status(), reqwest::StatusCode::OK);
+    assert_eq!(upload("final version", "skip").await.unwrap().status(), reqwest::StatusCode::OK);
+    assert_eq!(std::fs::read(temp_dir.path().join("report.pdf")).unwrap(), b"first draft");
+
+    let kept: Value = upload("final version", "keep_both").await.unwrap().json().await.unwrap();
+    assert_eq!(kept["name"], "report (2).pdf");
+    upload("final version", "replace").await.unwrap();
+    assert_eq!(std::fs::read(temp_dir.path().join("report.pdf")).unwrap(), b"final version");
+
+    // A resumable upload keeps its data until the conflict is settled
+    let device = uuid::Uuid::new_v4().to_string();
+    let session: Value = http
+        .post(format!("{}/api/uploads", url))
+        .header("x-rustdrop-device", &device)
+        .json(&serde_json::json!({ "name": "report.pdf", "size": 6 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let chunk = |offset: u64, body: &'static str, query: &str| {
+        http.patch(format!("{}/api/uploads/{}{}", url, session["id"].as_str().unwrap(), query))
+            .header("x-rustdrop-device", &device)
+            .header("upload-offset", offset)
+            .body(body)
+            .send()
+    };
+    let response = chunk(0, "signed", "").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["conflict"]["incoming"]["size"], 6);
+
+    let response = chunk(6, "", "?conflict=replace").await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let progress: Value = response.json().await.unwrap();
+    assert_eq!(progress["file"]["name"], "report.pdf");
+    assert_eq!(std::fs::read(temp_dir.path().join("report.pdf")).unwrap(), b"signed");
+}
+
+#[tokio::test]
+async fn test_batch_uploads_appear_together() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
+    let device = uuid::Uuid::new_v4().to_string();
+    let batch = uuid::Uuid::new_v4().to_string();
+
+    let mut sessions = Vec::new();
+    for name in ["IMG_0001.jpg", "IMG_0002.jpg"] {
+        let session: Value = http
+            .post(format!("{}/api/uploads", url))
+            .header("x-rustdrop-device", &device)
+            .json(&serde_json::json!({ "name": name, "size": 5, "dir": "Album", "batch": batch }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        sessions.push(session["id"].as_str().unwrap().to_string());
+    }
+    let send = |id: &str| {
+        http.patch(format!("{}/api/uploads/{}", url, id))
+            .header("x-rustdrop-device", &device)
+            .header("upload-offset", 0)
+            .body("photo")
+            .send()
+    };
+    let commit = || {
+        http.post(format!("{}/api/uploads/batches/{}/commit", url, batch))
+            .header("x-rustdrop-device", &device)
+            .send()
+    };
+
+    // A complete upload of the batch stays out of the share
+    let progress: Value = send(&sessions[0]).await.unwrap().json().await.unwrap();
+    assert_eq!(progress["complete"], true);
+    assert!(progress.get("file").is_none());
+    assert!(!temp_dir.path().join("Album/IMG_0001.jpg").exists());
+
+    let response = commit().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["incomplete"], serde_json::json!(["IMG_0002.jpg"]));
+    assert!(!temp_dir.path().join("Album/IMG_0001.jpg").exists());
+
+    send(&sessions[1]).await.unwrap();
+    let response = commit().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let committed: Value = response.json().await.unwrap();
+    assert_eq!(committed["files"].as_array().unwrap().len(), 2);
+    assert_eq!(std::fs::read(temp_dir.path().join("Album/IMG_0001.jpg")).unwrap(), b"photo");
+    assert_eq!(std::fs::read(temp_dir.path().join("Album/IMG_0002.jpg")).unwrap(), b"photo");
+
+    // The batch is gone once committed
+    assert_eq!(commit().await.unwrap().status(), reqwest::StatusCode::NOT_FOUND);
+
+    // Files of a batch with the same name never take the name of a file on disk
+    std::fs::write(temp_dir.path().join("Album/IMG_0003 (2).jpg"), b"older").unwrap();
+    let twins = uuid::Uuid::new_v4().to_string();
+    for _ in 0..2 {
+        let session: Value = http
+            .post(format!("{}/api/uploads", url))
+            .header("x-rustdrop-device", &device)
+            .json(&serde_json::json!({ "name": "IMG_0003.jpg", "size": 5, "dir": "Album", "batch": twins }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        send(session["id"].as_str().unwrap()).await.unwrap();
+    }
+    let response = http
+        .post(format!("{}/api/uploads/batches/{}/commit", url, twins))
+        .header("x-rustdrop-device", &device)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(std::fs::read(temp_dir.path().join("Album/IMG_0003.jpg")).unwrap(), b"photo");
+    assert_eq!(std::fs::read(temp_dir.path().join("Album/IMG_0003 (2).jpg")).unwrap(), b"older");
+    assert_eq!(std::fs::read(temp_dir.path().join("Album/IMG_0003 (3).jpg")).unwrap(), b"photo");
+
+    let response = http
+        .post(format!("{}/api/uploads", url))
+        .header("x-rustdrop-device", &device)
+        .json(&serde_json::json!({ "name": "a.jpg", "size": 1, "batch": "album" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_api_version_headers() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
+
+    let response = http.get(format!("{}/api/version", url)).send().await.unwrap();
+    assert_eq!(response.headers()["x-rustdrop-api-version"], "1");
+    let versions: Value = response.json().await.unwrap();
+    assert_eq!(versions["current"], 2);
+    assert_eq!(versions["min"], 1);
+
+    let response = http.get(format!("{}/api/v2/files", url)).header("x-rustdrop-api-version", "2").send().await.unwrap();
+    assert_eq!(response.headers()["x-rustdrop-api-version"], "2");
+    assert!(response.headers().get("warning").is_none());
+
+    let response = http.get(format!("{}/api/files", url)).header("x-rustdrop-api-version", "1").send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(response.headers()["warning"].to_str().unwrap().starts_with("299 rustdrop"));
+
+    let response = http.get(format!("{}/api/files", url)).header("x-rustdrop-api-version", "7").send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // Pages are not part of the API
+    let response = http.get(&url).send().await.unwrap();
+    assert!(response.headers().get("x-rustdrop-api-version").is_none());
+}
+
+#[tokio::test]
+async fn test_request_id_is_echoed_and_recorded() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let http = reqwest::Client::new();
+
+    let response = http.get(format!("{}/api/health", url)).send().await.unwrap();
+    let generated = response.headers()["x-request-id"].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(generated).is_ok());
+
+    let form = reqwest::multipart::Form::new()
+        .part("file", reqwest::multipart::Part::bytes(b"hello".to_vec()).file_name("traced.txt"));
+    let response = http
+        .post(format!("{}/api/files", url))
+        .header("x-request-id", "upload-from-phone-42")
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(response.headers()["x-request-id"], "upload-from-phone-42");
+
+    let history: Vec<Value> = http.get(format!("{}/api/history", url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(history[0]["file"], "traced.txt");
+    assert_eq!(history[0]["request_id"], "upload-from-phone-42");
+}
+
+#[tokio::test]
+async fn test_send_queue() {
+    let sender = TempDir::new().unwrap();
+    let receiver = TempDir::new().unwrap();
+    std::fs::write(sender.path().join("contract.pdf"), "signed").unwrap();
+    let url = spawn_server(sender.path()).await;
+    let peer = spawn_server(receiver.path()).await;
+    let client = reqwest::Client::new();
+
+    let files: Value = client.get(format!("{}/api/files", url)).send().await.unwrap().json().await.unwrap();
+    let file_id = files[0]["id"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(format!("{}/api/queue", url))
+        .json(&serde_json::json!({ "file_id": "missing", "peer": peer }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = client
+        .post(format!("{}/api/queue", url))
+        .json(&serde_json::json!({ "file_id": file_id, "peer": peer, "priority": "high" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let transfer: Value = response.json().await.unwrap();
+    assert_eq!(transfer["priority"], "high");
+    let id = transfer["id"].as_str().unwrap().to_string();
+
+    let mut status = Value::Null;
+    for _ in 0..50 {
+        let transfer: Value = client.get(format!("{}/api/queue/{}", url, id)).send().await.unwrap().json().await.unwrap();
+        status = transfer["status"].clone();
+        if status == "sent" || status == "failed" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(status, "sent");
+    assert_eq!(std::fs::read(receiver.path().join("contract.pdf")).unwrap(), b"signed");
+
+    // Only waiting transfers can be reordered
+    let response = client
+        .patch(format!("{}/api/queue/{}", url, id))
+        .json(&serde_json::json!({ "priority": "low" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 409);
+}
+
+#[tokio::test]
+async fn test_cancel_and_clear_send_queue() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("IMG_0001.jpg"), "photo").unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let client = reqwest::Client::new();
+
+    let files: Value = client.get(format!("{}/api/files", url)).send().await.unwrap().json().await.unwrap();
+    let file_id = files[0]["id"].as_str().unwrap().to_string();
+
+    // Paused, so the transfers stay in the queue
+    client.post(format!("{}/api/transfers/pause", url)).send().await.unwrap();
+    for _ in 0..2 {
+        let response = client
+            .post(format!("{}/api/queue", url))
+            .json(&serde_json::json!({ "file_id": file_id, "peer": "127.0.0.1:9" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+    }
+
+    let cancelled: Value = client.post(format!("{}/api/queue/cancel", url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(cancelled["cancelled"], 2);
+    let transfers: Value = client.get(format!("{}/api/queue", url)).send().await.unwrap().json().await.unwrap();
+    assert!(transfers.as_array().unwrap().iter().all(|transfer| transfer["status"] == "cancelled"));
+
+    let cleared: Value = client.post(format!("{}/api/queue/clear", url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(cleared["cleared"], 2);
+    let transfers: Value = client.get(format!("{}/api/queue", url)).send().await.unwrap().json().await.unwrap();
+    assert!(transfers.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_folder_size() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::create_dir_all(temp_dir.path().join("DCIM/100APPLE")).unwrap();
+    std::fs::write(temp_dir.path().join("DCIM/IMG_0001.jpg"), "12345").unwrap();
+    std::fs::write(temp_dir.path().join("DCIM/100APPLE/IMG_0002.jpg"), "1234567890").unwrap();
+    std::fs::write(temp_dir.path().join("notes.txt"), "12345").unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let client = reqwest::Client::new();
+
+    let mut size = Value::Null;
+    for _ in 0..50 {
+        let response = client.get(format!("{}/api/folders/DCIM/size", url)).send().await.unwrap();
+        size = response.json().await.unwrap();
+        if size["state"] != "computing" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(size["state"], "done");
+    assert_eq!(size["path"], "DCIM");
+    assert_eq!(size["bytes"], 15);
+    assert_eq!(size["files"], 2);
+
+    let response = client.get(format!("{}/api/folders/notes.txt/size", url)).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+    let response = client.get(format!("{}/api/folders/..%2Fetc/size", url)).send().await.unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_pause_transfers() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let client = reqwest::Client::new();
+    let phone = "0b7f6c1e-8d4a-4e8a-9a57-3f1f2d6b9c01";
+
+    let session: Value = client
+        .post(format!("{}/api/uploads", url))
+        .header("x-rustdrop-device", phone)
+        .json(&serde_json::json!({ "name": "call-notes.txt", "size": 5 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let chunk = || {
+        client
+            .patch(format!("{}/api/uploads/{}", url, session["id"].as_str().unwrap()))
+            .header("x-rustdrop-device", phone)
+            .header("upload-offset", 0)
+            .body("hello")
+            .send()
+    };
+
+    let state: Value = client.post(format!("{}/api/transfers/pause", url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(state["paused"], true);
+    let response = chunk().await.unwrap();
+    assert_eq!(response.status(), 503);
+    assert!(response.headers().contains_key("retry-after"));
+
+    client.post(format!("{}/api/transfers/resume", url)).send().await.unwrap();
+    let state: Value = client.get(format!("{}/api/transfers", url)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(state["paused"], false);
+    let progress: Value = chunk().await.unwrap().json().await.unwrap();
+    assert_eq!(progress["complete"], true);
+}
+
+#[tokio::test]
+async fn test_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::create_dir(temp_dir.path().join("photos")).unwrap();
+    std::fs::write(temp_dir.path().join("photos/a.txt"), "hello").unwrap();
+    std::fs::write(temp_dir.path().join("b.txt"), "").unwrap();
+    let app = create_test_app(&temp_dir);
+
+    let request = Request::builder().uri("/api/manifest").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-disposition"].to_str().unwrap().contains("SHA256SUMS"));
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(
+        String::from_utf8(body.to_vec()).unwrap(),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  b.txt\n\
+         2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  photos/a.txt\n"
+    );
 }
 
 #[tokio::test]
@@ -390,7 +2743,7 @@ fn test_port_availability() {
     assert!(available_port.is_some());
 
     let port = available_port.unwrap();
-    assert!(port >= 60000 && port <= 60100);
+    assert!((60000..=60100).contains(&port));
     assert!(is_port_available(port));
 }
 
@@ -462,4 +2815,702 @@ fn test_concurrent_file_operations() {
     // List directory to ensure all files are visible
     let files = list_directory(temp_dir.path()).unwrap();
     assert_eq!(files.len(), 5);
-} 
\ No newline at end of file
+} 
+#[tokio::test]
+async fn test_uploads_move_to_the_storage_backend() {
+    use rustdrop::backend::{Backend, LocalStorage};
+    use std::sync::Arc;
+
+    let temp_dir = TempDir::new().unwrap();
+    let nas = TempDir::new().unwrap();
+    let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+    let backend = Backend::new(Box::new(LocalStorage::new(nas.path().to_path_buf())), false);
+    let app = create_router(AppState { backend: Some(Arc::new(backend)), ..state });
+
+    let boundary = "rustdrop-boundary";
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"report.txt\"\r\nContent-Type: text/plain\r\n\r\nquarterly numbers\r\n--{b}--\r\n",
+        b = boundary
+    );
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let uploaded: Value = serde_json::from_slice(&body).unwrap();
+
+    // Copied in the background, then removed from the share
+    for _ in 0..50 {
+        if !temp_dir.path().join("report.txt").exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(!temp_dir.path().join("report.txt").exists());
+    assert_eq!(std::fs::read(nas.path().join("report.txt")).unwrap(), b"quarterly numbers");
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/api/files").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let files: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(files[0]["id"], uploaded["id"]);
+
+    for (range, expected) in [("bytes=0-8", &b"quarterly"[..]), ("bytes=10-", &b"numbers"[..])] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/files/{}", uploaded["id"].as_str().unwrap()))
+                    .header("range", range)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], expected);
+    }
+
+    let response = app
+        .oneshot(Request::builder().uri(format!("/api/files/{}", uploaded["id"].as_str().unwrap())).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-length"], "17");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"quarterly numbers");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_plugins_process_uploads() {
+    use rustdrop::core::config::{AppConfig, PluginConfig};
+
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.plugins.push(PluginConfig {
+        name: "wordcount".to_string(),
+        command: vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            r#"wc -w < "$1" > "$RUSTDROP_FILE_NAME.words" && echo '{"tags": ["Counted"]}'"#.to_string(),
+            "wordcount".to_string(),
+            "{file}".to_string(),
+        ],
+        mime_types: vec!["text/*".to_string()],
+        timeout_secs: 10,
+    });
+    let app = create_router(AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(8080), config));
+
+    let boundary = "rustdrop-boundary";
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\none two three\r\n--{b}--\r\n",
+        b = boundary
+    );
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Processed in the background
+    let words = temp_dir.path().join("notes.txt.words");
+    for _ in 0..100 {
+        if words.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(std::fs::read_to_string(&words).unwrap().trim(), "3");
+
+    let response = app
+        .oneshot(Request::builder().uri("/api/files").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let files: Value = serde_json::from_slice(&body).unwrap();
+    let notes = files.as_array().unwrap().iter().find(|file| file["name"] == "notes.txt").unwrap();
+    assert_eq!(notes["tags"], serde_json::json!(["counted"]));
+    let counted = files.as_array().unwrap().iter().find(|file| file["name"] == "notes.txt.words").unwrap();
+    assert_eq!(counted["sender"], "wordcount");
+}
+
+#[tokio::test]
+async fn test_search_finds_documents_by_their_text() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("scan-0001.pdf"), b"%PDF").unwrap();
+    std::fs::write(temp_dir.path().join("invoice-template.odt"), b"odt").unwrap();
+    std::fs::write(temp_dir.path().join("holiday.jpg"), b"jpeg").unwrap();
+    let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+    state.text.set("scan-0001.pdf", "ACME Corp\nInvoice 2024-117\nTotal due: 1,200.00 EUR").unwrap();
+    let app = create_router(state);
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/api/search?q=Invoice").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let hits: Value = serde_json::from_slice(&body).unwrap();
+    let hits = hits.as_array().unwrap();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0]["name"], "invoice-template.odt");
+    assert!(hits[0].get("snippet").is_none());
+    assert_eq!(hits[1]["name"], "scan-0001.pdf");
+    assert!(hits[1]["snippet"].as_str().unwrap().contains("Invoice 2024-117"));
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri("/api/search?q=acme%20total").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let hits: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(hits.as_array().unwrap().len(), 1);
+
+    let response = app
+        .oneshot(Request::builder().uri("/api/search?q=%20").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_transcoded_copies_of_videos() {
+    use rustdrop::utils::file::get_share_file_info;
+
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("clip.mkv");
+    std::fs::write(&path, b"matroska").unwrap();
+    let file = get_share_file_info(temp_dir.path(), &path).unwrap();
+    let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080));
+    let transcoder = state.transcoder.clone();
+    let app = create_router(state);
+    let status_uri = format!("/api/files/{}/transcode", file.id);
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(&status_uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    #[cfg(not(feature = "transcode"))]
+    {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().method("POST").uri(&status_uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+
+    // A copy made earlier
+    let output = transcoder.output_path(&file);
+    std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+    std::fs::write(&output, b"mp4 of the clip").unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(&status_uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let status: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(status, serde_json::json!({"state": "done", "size": 15}));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(format!("/api/files/{}/transcoded", file.id))
+                .header("range", "bytes=0-2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()["content-type"], "video/mp4");
+    assert!(response.headers()["content-disposition"].to_str().unwrap().contains("clip.mp4"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"mp4");
+}
+
+#[tokio::test]
+async fn test_photo_backup_stores_each_photo_once() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_router(AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(8080)));
+    let phone = "rustdrop_client=0b5e7a52-3c1f-4d5e-9a51-5f4c2d7e8a10; rustdrop_sender=Anna%27s%20phone";
+    let tablet = "rustdrop_client=7d2c4b1e-8f3a-4e6b-b0c9-2a1d5e6f7a8b";
+
+    let request = |method: &str, uri: &str, cookie: &str, body: Body| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("cookie", cookie)
+            .header("content-type", "application/json")
+            .header("x-rustdrop-modified", "2024-07-14T10:30:00Z")
+            .body(body)
+            .unwrap()
+    };
+    let json = |response: axum::response::Response| async move {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<Value>(&body).unwrap()
+    };
+
+    let check = r#"{"items": [{"key": "IMG_0001.HEIC:4:1"}, {"key": "IMG_0002.HEIC:4:2"}]}"#;
+    let response = app.clone().oneshot(request("POST", "/api/backup/check", phone, Body::from(check))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await["missing"].as_array().unwrap().len(), 2);
+
+    let uri = "/api/backup/items?key=IMG_0001.HEIC:4:1&name=IMG_0001.HEIC";
+    let response = app.clone().oneshot(request("POST", uri, phone, Body::from("heic"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let stored = json(response).await;
+    assert_eq!(stored["status"], "stored");
+    assert_eq!(stored["file"]["name"], "Backup/Anna's phone/2024/07/IMG_0001.HEIC");
+    assert!(temp_dir.path().join("Backup/Anna's phone/2024/07/IMG_0001.HEIC").exists());
+
+    let response = app.clone().oneshot(request("POST", "/api/backup/check", phone, Body::from(check))).await.unwrap();
+    assert_eq!(json(response).await["missing"], serde_json::json!(["IMG_0002.HEIC:4:2"]));
+
+    // The tablet has the same photo and is not asked for it once it tells the hash
+    let hash = rustdrop::utils::hash::hash_bytes(b"heic");
+    let check = format!(r#"{{"items": [{{"key": "photo-17", "hash": "{}"}}]}}"#, hash);
+    let response = app.clone().oneshot(request("POST", "/api/backup/check", tablet, Body::from(check))).await.unwrap();
+    assert_eq!(json(response).await["missing"], serde_json::json!([]));
+
+    // Sent anyway, it is not stored twice
+    let uri = "/api/backup/items?key=photo-18&name=copy.heic";
+    let response = app.clone().oneshot(request("POST", uri, tablet, Body::from("heic"))).await.unwrap();
+    let duplicate = json(response).await;
+    assert_eq!(duplicate["status"], "duplicate");
+    assert_eq!(duplicate["name"], "Backup/Anna's phone/2024/07/IMG_0001.HEIC");
+
+    // Without a name, a device's folder is named by its handle, never by its cookie
+    let uri = "/api/backup/items?key=photo-19&name=IMG_0003.HEIC";
+    let response = app.clone().oneshot(request("POST", uri, tablet, Body::from("other"))).await.unwrap();
+    let stored = json(response).await;
+    let handle = client_handle("7d2c4b1e-8f3a-4e6b-b0c9-2a1d5e6f7a8b");
+    assert_eq!(stored["file"]["name"], format!("Backup/Device {}/2024/07/IMG_0003.HEIC", handle));
+    // Only the stored items are left in the backup folder
+    let entries = |dir: &str| std::fs::read_dir(temp_dir.path().join(dir)).unwrap().count();
+    assert_eq!(entries("Backup"), 2);
+
+    let response = app.clone().oneshot(request("GET", "/api/backup", tablet, Body::empty())).await.unwrap();
+    let summary = json(response).await;
+    assert_eq!(summary["items"], 3);
+    assert_eq!(summary["bytes"], 5);
+
+    let response = app.clone().oneshot(request("GET", "/manifest.webmanifest", tablet, Body::empty())).await.unwrap();
+    assert_eq!(json(response).await["start_url"], "/");
+
+    let uri = "/api/backup/items?key=x&name=../escape.jpg";
+    let response = app.oneshot(request("POST", uri, phone, Body::from("jpeg"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_outbox_is_delivered_once_the_peer_is_reachable() {
+    use rustdrop::client::courier::run_courier;
+    use rustdrop::core::config::CourierPeer;
+    use rustdrop::utils::file::set_modified;
+
+    let sender = TempDir::new().unwrap();
+    let receiver = TempDir::new().unwrap();
+    let path = sender.path().join("report.pdf");
+    std::fs::write(&path, "quarterly").unwrap();
+    set_modified(&path, chrono::Utc::now() - chrono::Duration::hours(1)).unwrap();
+    let peer = spawn_server(receiver.path()).await;
+
+    let mut config = AppConfig::default();
+    config.courier.peers = vec![CourierPeer { name: "Laptop".to_string(), address: peer }];
+    let state = AppState::with_config(sender.path().to_path_buf(), DeviceInfo::new(8080), config);
+    tokio::spawn(run_courier(state.courier.clone(), state.pause.clone(), state.network.clone(), "Desk".to_string()));
+    let app = create_router(state);
+
+    let files: Value = {
+        let response = app.clone().oneshot(Request::builder().uri("/api/files").body(Body::empty()).unwrap()).await.unwrap();
+        serde_json::from_slice(&axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+    };
+    let body = serde_json::json!({ "file_id": files[0]["id"] }).to_string();
+    let enqueue = |peer: &str| {
+        Request::builder()
+            .method("POST")
+            .uri(format!("/api/outbox/{}", peer))
+            .header("content-type", "application/json")
+            .body(Body::from(body.clone()))
+            .unwrap()
+    };
+    let response = app.clone().oneshot(enqueue("phone")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = app.clone().oneshot(enqueue("laptop")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let delivered = receiver.path().join("inbox/Desk/report.pdf");
+    for _ in 0..50 {
+        if delivered.exists() && !sender.path().join("outbox/Laptop/report.pdf").exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(std::fs::read(&delivered).unwrap(), b"quarterly");
+    assert!(!sender.path().join("outbox/Laptop/report.pdf").exists());
+    assert!(path.exists());
+
+    let response = app.oneshot(Request::builder().uri("/api/outbox").body(Body::empty()).unwrap()).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let outboxes: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(outboxes[0]["name"], "Laptop");
+    assert_eq!(outboxes[0]["pending"], 0);
+    assert_eq!(outboxes[0]["delivered"], 1);
+    assert_eq!(outboxes[0]["reachable"], true);
+}
+
+#[tokio::test]
+async fn test_queued_sends_are_delivered_later() {
+    use rustdrop::client::outgoing::OutgoingQueue;
+
+    let home = TempDir::new().unwrap();
+    let receiver = TempDir::new().unwrap();
+    let queue = OutgoingQueue::new(home.path().join("outgoing"));
+    let file = OutgoingFile {
+        path: "trip/itinerary.pdf".to_string(),
+        data: b"flights".to_vec(),
+        attributes: FileAttributes::default(),
+    };
+
+    // Nobody listens on the port yet
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let send = queue.add(&address, &[file]).unwrap();
+    assert!(queue.deliver(&send).await.is_err());
+
+    let listener = tokio::net::TcpListener::bind(&address).await.unwrap();
+    let app = create_routes(receiver.path().to_path_buf(), DeviceInfo::new(0), 1024 * 1024);
+    tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+            .await
+            .unwrap();
+    });
+    assert_eq!(queue.deliver(&send).await.unwrap(), 1);
+    assert_eq!(std::fs::read(receiver.path().join("trip/itinerary.pdf")).unwrap(), b"flights");
+}
+
+#[tokio::test]
+async fn test_network_usage_per_peer() {
+    let temp_dir = TempDir::new().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let peer = PeerClient::with_token(&url, "5d0c1f7e-8a3b-4c2d-9e6f-1a2b3c4d5e6f").unwrap();
+    peer.upload("notes.txt", vec![b'x'; 4096], FileAttributes::default()).await.unwrap();
+    peer.list_files().await.unwrap();
+
+    let usage: Value = reqwest::get(format!("{}/api/usage/network?days=7", url))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(usage["days"].as_array().unwrap().len(), 1);
+    let entry = usage["peers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["peer"] == client_handle("5d0c1f7e-8a3b-4c2d-9e6f-1a2b3c4d5e6f"))
+        .unwrap();
+    assert_eq!(entry["address"], "127.0.0.1");
+    assert!(entry["received"].as_u64().unwrap() >= 4096);
+    assert!(entry["sent"].as_u64().unwrap() > 0);
+    assert!(usage["received"].as_u64().unwrap() >= 4096);
+}
+
+#[tokio::test]
+async fn test_get_resumes_and_verifies_downloads() {
+    use rustdrop::client::download::{download_resumable, part_path, PartialDownload};
+
+    let share = TempDir::new().unwrap();
+    let local = TempDir::new().unwrap();
+    let content: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(share.path().join("archive.bin"), &content).unwrap();
+    let url = spawn_server(share.path()).await;
+    let client = PeerClient::new(&url).unwrap();
+    let file = client.list_files().await.unwrap().remove(0);
+    let id = file.id.to_string();
+    let checksum = client.checksum(&id).await.unwrap();
+
+    // An earlier attempt got a third of the way
+    let interrupted = |target: &std::path::Path, prefix: &[u8]| {
+        std::fs::write(part_path(target), prefix).unwrap();
+        let progress = PartialDownload {
+            peer: client.base_url().to_string(),
+            id: id.clone(),
+            name: checksum.name.clone(),
+            size: checksum.size,
+            modified: checksum.modified,
+            sha256: checksum.sha256.clone(),
+            received: prefix.len() as u64,
+        };
+        let mut json = target.as_os_str().to_owned();
+        json.push(".part.json");
+        std::fs::write(json, serde_json::to_string(&progress).unwrap()).unwrap();
+    };
+
+    let target = local.path().join("archive.bin");
+    interrupted(&target, &content[..100_000]);
+    let first = std::sync::Mutex::new(None);
+    download_resumable(&client, &id, &target, |received, _| {
+        first.lock().unwrap().get_or_insert(received);
+    })
+    .await
+    .unwrap();
+    assert_eq!(*first.lock().unwrap(), Some(100_000));
+    assert_eq!(std::fs::read(&target).unwrap(), content);
+    assert!(!part_path(&target).exists());
+    assert_eq!(std::fs::read_dir(local.path()).unwrap().count(), 1);
+
+    // Whatever got corrupted on the way is caught before the file takes its name
+    let target = local.path().join("corrupt.bin");
+    interrupted(&target, &vec![0xff; 100_000]);
+    let error = download_resumable(&client, &id, &target, |_, _| {}).await.unwrap_err();
+    assert!(error.to_string().contains("Checksum mismatch"));
+    assert!(!target.exists());
+    assert!(!part_path(&target).exists());
+}
+
+#[tokio::test]
+async fn test_snapshot_serves_the_share_as_it_was() {
+    use rustdrop::core::config::SnapshotMode;
+    use rustdrop::core::snapshot::Snapshot;
+    use std::sync::Arc;
+
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("build.log"), "step 1").unwrap();
+    let state = AppState::new(temp_dir.path().to_path_buf(), DeviceInfo::new(0));
+    let snapshot = Snapshot::take(state.list_share().unwrap(), SnapshotMode::Copy).unwrap();
+    let app = create_router(AppState { snapshot: Some(Arc::new(snapshot)), ..state });
+
+    // Another program keeps writing
+    std::fs::write(temp_dir.path().join("build.log"), "step 1\nstep 2").unwrap();
+    std::fs::write(temp_dir.path().join("output.bin"), "partial").unwrap();
+
+    let get = |uri: String| Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(get("/api/files".to_string())).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let files: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(files.as_array().unwrap().len(), 1);
+    assert_eq!(files[0]["name"], "build.log");
+
+    let id = files[0]["id"].as_str().unwrap();
+    let response = app.clone().oneshot(get(format!("/api/files/{}", id))).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"step 1");
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/uploads")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"name": "new.txt", "size": 3}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app.oneshot(get("/api/snapshot".to_string())).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let info: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(info["mode"], "copy");
+    assert_eq!(info["files"], 1);
+}
+
+#[tokio::test]
+async fn test_list_files_in_natural_order() {
+    let temp_dir = TempDir::new().unwrap();
+    for name in ["file10.txt", "File2.txt", "file1.txt", "apple.txt"] {
+        std::fs::write(temp_dir.path().join(name), name).unwrap();
+    }
+    let app = create_routes(temp_dir.path().to_path_buf(), DeviceInfo::new(0), 1024 * 1024);
+
+    let names = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let files: Value = serde_json::from_slice(&body).unwrap();
+            files.as_array().unwrap().iter().map(|file| file["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(names("/api/files").await, ["File2.txt", "apple.txt", "file1.txt", "file10.txt"]);
+    assert_eq!(
+        names("/api/files?sort=name&collation=natural").await,
+        ["apple.txt", "file1.txt", "File2.txt", "file10.txt"]
+    );
+    assert_eq!(
+        names("/api/files?collation=nocase&order=desc").await,
+        ["File2.txt", "file10.txt", "file1.txt", "apple.txt"]
+    );
+    assert_eq!(names("/api/files?sort=size&collation=natural").await[0], "apple.txt");
+}
+
+#[tokio::test]
+async fn test_filter_files_by_type() {
+    let temp_dir = TempDir::new().unwrap();
+    for name in ["beach.jpg", "sunset.png", "report.pdf", "notes.txt", "photos.zip", "song.mp3"] {
+        std::fs::write(temp_dir.path().join(name), name).unwrap();
+    }
+    let app = create_routes(temp_dir.path().to_path_buf(), DeviceInfo::new(0), 1024 * 1024);
+
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null))
+        }
+    };
+    let names = |files: Value| -> Vec<String> {
+        files.as_array().unwrap().iter().map(|file| file["name"].as_str().unwrap().to_string()).collect()
+    };
+
+    let (_, files) = get("/api/files?type=document").await;
+    assert_eq!(names(files), ["notes.txt", "report.pdf"]);
+    let (_, files) = get("/api/files?type=image").await;
+    assert_eq!(names(files), ["beach.jpg", "sunset.png"]);
+    let (_, files) = get("/api/v2/files?type=archive").await;
+    assert_eq!(files["items"].as_array().unwrap().len(), 1);
+
+    let (status, _) = get("/api/files?type=spreadsheet").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, types) = get("/api/types?type=image").await;
+    assert_eq!(
+        types,
+        serde_json::json!([
+            {"type": "image", "count": 2},
+            {"type": "audio", "count": 1},
+            {"type": "document", "count": 2},
+            {"type": "archive", "count": 1},
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_recent_transfers_include_downloads() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_test_app(&temp_dir);
+
+    let boundary = "rustdrop-boundary";
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"hello.txt\"\r\nContent-Type: text/plain\r\n\r\nhello\r\n--{b}--\r\n",
+        b = boundary
+    );
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .header("cookie", "rustdrop_sender=Alex")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let file: Value = serde_json::from_slice(&body).unwrap();
+    let uri = format!("/api/files/{}", file["id"].as_str().unwrap());
+
+    let download = |uri: String, cookie: Option<&'static str>, range: Option<&'static str>| {
+        let app = app.clone();
+        async move {
+            let mut request = Request::builder().uri(uri);
+            if let Some(cookie) = cookie {
+                request = request.header("cookie", cookie);
+            }
+            if let Some(range) = range {
+                request = request.header("range", range);
+            }
+            let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+            assert!(response.status().is_success());
+        }
+    };
+    download(uri.clone(), Some("rustdrop_sender=Mum"), None).await;
+    // A preview and the rest of a resumed download are not counted again
+    download(format!("{}?inline=true", uri), None, None).await;
+    download(uri.clone(), None, Some("bytes=2-")).await;
+    download(uri, None, Some("bytes=0-1")).await;
+
+    let request = Request::builder().uri("/api/history?transfers=true").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let history: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    let actions: Vec<&str> = history.iter().map(|entry| entry["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, ["download", "download", "upload"]);
+    assert_eq!(history[1]["sender"], "Mum");
+    assert_eq!(history[1]["size"], 5);
+    assert_eq!(history[2]["sender"], "Alex");
+}
+
+#[tokio::test]
+async fn test_multipart_upload_is_written_as_it_arrives() {
+    let temp_dir = TempDir::new().unwrap();
+    let app = create_routes(temp_dir.path().to_path_buf(), DeviceInfo::new(0), 16 * 1024 * 1024);
+
+    // Several megabytes in small pieces, more than the part held in memory for EXIF dates
+    let content: Vec<u8> = (0..3 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+    let boundary = "rustdrop-boundary";
+    let mut pieces = vec![bytes::Bytes::from(format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"video.mp4\"\r\nContent-Type: video/mp4\r\n\r\n",
+        b = boundary
+    ))];
+    pieces.extend(content.chunks(64 * 1024).map(bytes::Bytes::copy_from_slice));
+    pieces.push(bytes::Bytes::from(format!("\r\n--{}--\r\n", boundary)));
+    let body = Body::from_stream(futures_util::stream::iter(pieces.into_iter().map(Ok::<_, std::io::Error>)));
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/files")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .body(body)
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let file: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(file["size"], content.len() as u64);
+    assert_eq!(std::fs::read(temp_dir.path().join("video.mp4")).unwrap(), content);
+}
+
+#[tokio::test]
+async fn test_capabilities_checked_before_upload() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = AppConfig::default();
+    config.server.max_file_size = 1000;
+    let url = spawn_app(create_router(AppState::with_config(temp_dir.path().to_path_buf(), DeviceInfo::new(0), config))).await;
+
+    let capabilities: Value = reqwest::get(format!("{}/api/capabilities", url)).await.unwrap().json().await.unwrap();
+    assert_eq!(capabilities["max_file_size"], 1000);
+    assert_eq!(capabilities["hash_algorithms"], serde_json::json!(["sha256"]));
+    assert_eq!(capabilities["uploads"], serde_json::json!(["resumable", "sparse", "tus", "multipart"]));
+
+    let peer = PeerClient::with_token(&url, "7e1d2c3b-4a5f-4e6d-8c7b-9a0f1e2d3c4b").unwrap();
+    let error = peer.upload("big.bin", vec![b'x'; 5000], FileAttributes::default()).await.unwrap_err();
+    assert!(error.to_string().contains("the peer only accepts files up to 1000 B"), "{}", error);
+    assert!(!temp_dir.path().join("big.bin").exists());
+
+    peer.upload("small.bin", vec![b'x'; 500], FileAttributes::default()).await.unwrap();
+    assert_eq!(std::fs::metadata(temp_dir.path().join("small.bin")).unwrap().len(), 500);
+}
@@ -2,7 +2,6 @@ use proptest::prelude::*;
 use rustdrop::core::config::AppConfig;
 use rustdrop::core::models::DeviceInfo;
 use rustdrop::utils::file::get_file_info;
-use std::collections::HashSet;
 use std::fs::File;
 use std::io::Write;
 use tempfile::TempDir;
@@ -173,10 +172,12 @@ proptest! {
                 port,
                 host: "127.0.0.1".to_string(),
                 max_file_size,
+                ..Default::default()
             },
             files: FilesConfig {
                 directory: None,
                 expiry_hours: Some(24),
+                ..Default::default()
             },
             discovery: DiscoveryConfig {
                 enabled,
@@ -184,7 +185,9 @@ proptest! {
             ui: UiConfig {
                 qr_code,
                 open_browser,
+                ..Default::default()
             },
+            ..Default::default()
         };
         
         // Serialize to TOML and back
@@ -274,7 +277,7 @@ proptest! {
         // All UUIDs should be unique (different files with unique names)
         let mut ids = std::collections::HashSet::new();
         for info in &infos {
-            prop_assert!(ids.insert(info.id.clone()), "UUID should be unique for file: {}", info.name);
+            prop_assert!(ids.insert(info.id), "UUID should be unique for file: {}", info.name);
         }
         
         // Names should match the unique filenames we created
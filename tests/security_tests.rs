@@ -5,7 +5,6 @@ use axum::{
 };
 use rustdrop::web::routes::create_routes;
 use rustdrop::core::models::DeviceInfo;
-use serde_json::Value;
 use tempfile::TempDir;
 use tower::util::ServiceExt;
 use tower_http::cors::{Any, CorsLayer};
@@ -53,7 +52,7 @@ async fn test_path_traversal_protection() {
 
     for malicious_path in malicious_paths {
         let request = Request::builder()
-            .uri(&format!("/api/files/{}", malicious_path))
+            .uri(format!("/api/files/{}", malicious_path))
             .body(Body::empty())
             .unwrap();
 
@@ -204,7 +203,7 @@ async fn test_invalid_uuid_handling() {
 
     for invalid_uuid in invalid_uuids {
         let request = Request::builder()
-            .uri(&format!("/api/files/{}", invalid_uuid))
+            .uri(format!("/api/files/{}", invalid_uuid))
             .body(Body::empty())
             .unwrap();
 
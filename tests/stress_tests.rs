@@ -1,13 +1,11 @@
 use rustdrop::core::models::DeviceInfo;
 use std::fs;
-use std::path::PathBuf;
 use std::sync::{Arc, Barrier};
 use std::thread;
 use std::time::{Duration, Instant};
 use tempfile::TempDir;
 use rustdrop::utils::file::{get_file_info, list_directory};
 use rustdrop::utils::network::{find_available_port, is_port_available};
-use uuid::Uuid;
 
 #[test]
 fn test_concurrent_file_operations() {
@@ -383,7 +381,7 @@ fn test_file_name_edge_cases_stress() {
             let file_path = temp_dir.path().join(&filename);
             
             // Try to create the file (some names might not be valid on all filesystems)
-            if let Ok(_) = fs::write(&file_path, format!("Content {}-{}", i, j)) {
+            if fs::write(&file_path, format!("Content {}-{}", i, j)).is_ok() {
                 created_files += 1;
             }
         }